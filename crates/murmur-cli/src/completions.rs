@@ -0,0 +1,48 @@
+//! Tab completion scripts for the `murmur` CLI itself.
+
+use anyhow::Result;
+use clap::Command;
+use clap_complete::{generate, Shell};
+use clap_complete_nushell::Nushell;
+
+/// Shells `murmur completions` can generate for.
+pub const SUPPORTED: &str = "zsh, bash, fish, nushell";
+
+/// Write the completion script for `shell` to `out`.
+pub fn write(cmd: &mut Command, shell: &str, out: &mut dyn std::io::Write) -> Result<()> {
+    let name = cmd.get_name().to_string();
+    match shell {
+        "zsh" => generate(Shell::Zsh, cmd, name, out),
+        "bash" => generate(Shell::Bash, cmd, name, out),
+        "fish" => generate(Shell::Fish, cmd, name, out),
+        "nushell" | "nu" => generate(Nushell, cmd, name, out),
+        other => anyhow::bail!("Unsupported shell: {other}. Supported: {SUPPORTED}"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn script(shell: &str) -> String {
+        let mut cmd = Command::new("murmur").subcommand(Command::new("status"));
+        let mut out = Vec::new();
+        write(&mut cmd, shell, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn generates_for_each_shell() {
+        assert!(script("zsh").contains("compdef _murmur murmur"));
+        assert!(script("bash").contains("complete -F _murmur"));
+        assert!(script("fish").contains("complete -c murmur"));
+        assert!(script("nu").contains("export extern murmur"));
+    }
+
+    #[test]
+    fn unknown_shell_is_rejected() {
+        let mut cmd = Command::new("murmur");
+        assert!(write(&mut cmd, "tcsh", &mut Vec::new()).is_err());
+    }
+}
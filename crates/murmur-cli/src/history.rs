@@ -0,0 +1,63 @@
+//! Output for `murmur history ask`.
+
+use murmur_daemon::localtime;
+use murmur_protocol::HistoryAskResponse;
+
+/// Summary, then one line per matching entry: local time, cwd (with the
+/// home directory shortened to `~`) and command, marking failures.
+pub fn render_ask(response: &HistoryAskResponse, home: &str) -> String {
+    let mut out = response.summary.clone();
+    if !response.entries.is_empty() {
+        out.push('\n');
+    }
+    for entry in &response.entries {
+        let cwd = match entry.cwd.strip_prefix(home) {
+            Some(rest) if !home.is_empty() && (rest.is_empty() || rest.starts_with('/')) => {
+                format!("~{rest}")
+            }
+            _ => entry.cwd.clone(),
+        };
+        let status = if entry.exit_code == 0 {
+            String::new()
+        } else {
+            format!("  [exit {}]", entry.exit_code)
+        };
+        out.push_str(&format!(
+            "\n{}  {:<24}  {}{status}",
+            localtime::format_local(entry.timestamp),
+            cwd,
+            entry.command
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use murmur_protocol::{HistoryEntry, HistoryFilter};
+
+    #[test]
+    fn lists_entries_under_the_summary() {
+        let response = HistoryAskResponse {
+            filter: HistoryFilter::default(),
+            entries: vec![HistoryEntry {
+                command: "fly deploy".to_string(),
+                cwd: "/home/me/app".to_string(),
+                source: "terminal".to_string(),
+                exit_code: 1,
+                timestamp: 1_760_000_000,
+                session_id: None,
+                host: None,
+                suggested_by: None,
+            }],
+            summary: "You deployed the app once; it failed.".to_string(),
+            provider: "anthropic".to_string(),
+        };
+        let text = render_ask(&response, "/home/me");
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "You deployed the app once; it failed.");
+        assert!(lines[2].contains("~/app"));
+        assert!(lines[2].ends_with("fly deploy  [exit 1]"));
+    }
+}
@@ -1,11 +1,46 @@
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand};
+use murmur_daemon::clock::SystemClock;
 use murmur_daemon::config::Config;
+use murmur_daemon::import::{self, HistorySource};
 use murmur_daemon::server::{self, Server};
-use murmur_protocol::{methods, JsonRpcRequest, JsonRpcResponse, RequestId, VoiceMode};
-use std::path::Path;
+use murmur_daemon::store::Store;
+use murmur_daemon::{assets, compression, frame, localtime, repl, upload};
+use murmur_protocol::{
+    methods, AbbrListResponse, BranchSuggestRequest, BranchSuggestResponse, CommitSuggestRequest,
+    CommitSuggestResponse, CompletionRequest, CompletionResponse, ConfigReloadResponse,
+    ContextUpdateRequest, CronSuggestRequest, CronSuggestResponse, DebugBundle, DebugBundleRequest,
+    DiagnoseRequest, DiagnoseResponse, DraftKind, ExprSuggestRequest, ExprSuggestResponse,
+    HealthResponse, HistoryAskRequest, HistoryAskResponse, HistoryEntry, HistoryImportRequest,
+    HistoryImportResponse, HistoryListRequest, InitializeRequest, InitializeResponse,
+    JsonRpcRequest, JsonRpcResponse, QueryRequest, QueryResponse, RegexPreview,
+    RegexSuggestRequest, RegexSuggestResponse, RequestId, Scope, SummarizeCommitsRequest,
+    SummarizeCommitsResponse, VoiceMode, VoiceSelftestReport, VoiceSelftestRequest,
+    VoiceUploadBeginRequest, VoiceUploadBeginResponse, VoiceUploadChunkRequest, METHOD_NOT_FOUND,
+};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::net::UnixStream;
+
+mod bundle;
+mod completions;
+mod history;
+mod output;
+mod pidfile;
+mod query;
+mod remote;
+mod service;
+mod statusbar;
+mod terminal;
+mod transcribe;
+
+use bundle::Bundle;
+use output::{Output, OutputArgs, Records};
+use query::QueryFormat;
+use remote::Endpoint;
+use statusbar::StatusFormat;
+use terminal::TerminalKind;
+use transcribe::TranscribeFormat;
 
 #[derive(Parser)]
 #[command(
@@ -14,6 +49,10 @@ use tokio::net::UnixStream;
 )]
 #[command(version)]
 struct Cli {
+    /// Run against a separate daemon with its own config, history, socket
+    /// and PID file (same as setting MURMUR_PROFILE)
+    #[arg(long, global = true)]
+    profile: Option<String>,
     #[command(subcommand)]
     command: Commands,
 }
@@ -31,20 +70,431 @@ enum Commands {
     },
     /// Stop the running daemon
     Stop,
+    /// Re-read the config file without restarting the daemon (same as
+    /// sending it SIGHUP)
+    Reload,
     /// Show daemon status
-    Status,
+    Status {
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Show today's provider requests against the daily budget, and each
+    /// provider's latency and health
+    Usage {
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// The providers the daemon is using and what they support
+    Providers {
+        #[command(subcommand)]
+        action: ProvidersAction,
+    },
+    /// Ask the daemon for completions of a command line, as the shell
+    /// widgets do
+    Complete {
+        /// The command line so far
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        input: Vec<String>,
+        /// Shell to complete for (default: from $SHELL)
+        #[arg(long)]
+        shell: Option<String>,
+        /// Maximum number of suggestions
+        #[arg(long)]
+        max_items: Option<usize>,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Check the daemon is up and can serve completions; fails otherwise
+    Health,
+    /// Start the daemon if it isn't answering, killing a hung one first.
+    /// Prints a line only when it had to start it
+    EnsureRunning,
     /// Print shell integration script
     Setup {
-        /// Shell to generate setup for (zsh, bash, fish)
+        /// Shell to generate setup for (zsh, bash, fish), or "wezterm" for
+        /// the WezTerm Lua picker
+        shell: String,
+        /// Integration mode: "widget" (inline suggestions) or "keybind"
+        /// (synchronous menu on a bound key, bash only)
+        #[arg(long, default_value = "widget")]
+        mode: String,
+        /// Terminal rendering layer: auto (from $TERM_PROGRAM), kitty,
+        /// wezterm, osc or none
+        #[arg(long, default_value = "auto")]
+        terminal: String,
+        /// Also start a status bar component showing daemon state
+        #[arg(long)]
+        statusbar: bool,
+        /// Also install the post-command hook that explains failed commands
+        #[arg(long)]
+        diagnose: bool,
+        /// Also expand murmur abbreviations on space and Enter
+        #[arg(long)]
+        abbr: bool,
+        /// Also record each command in murmur's history, marking the ones
+        /// run from an accepted suggestion
+        #[arg(long)]
+        record: bool,
+        /// Don't include tab completion for the murmur command itself
+        #[arg(long)]
+        no_completions: bool,
+    },
+    /// Print a tab completion script for the murmur CLI (zsh, bash, fish, nushell)
+    Completions {
+        /// Shell to generate completions for
         shell: String,
     },
+    /// Print the daemon socket path from the config, for scripts and
+    /// integrations
+    SocketPath,
+    /// Forward a local port to a remote daemon over SSH (use with MURMUR_REMOTE)
+    Tunnel {
+        /// SSH destination, e.g. user@devbox
+        destination: String,
+        /// Local port to listen on (default: port of MURMUR_REMOTE, else 7878)
+        #[arg(long)]
+        local_port: Option<u16>,
+        /// Daemon socket path on the remote host (default: ask its murmur)
+        #[arg(long)]
+        remote_socket: Option<String>,
+        /// Start in the background unless the port is already reachable
+        #[arg(long)]
+        ensure: bool,
+    },
+    /// Stream daemon state (provider, budget, voice) to the terminal status bar
+    Statusbar {
+        /// Output format: auto, iterm2 (user variable), title (window title) or plain
+        #[arg(long, default_value = "auto")]
+        format: String,
+    },
+    /// Turn a plain-language request into a shell command (for Raycast/Alfred)
+    Query {
+        /// What you want to do, e.g. "find files larger than 100MB"
+        #[arg(required = true, trailing_var_arg = true)]
+        query: Vec<String>,
+        /// Print the full result as JSON (command + description per item)
+        #[arg(long, conflicts_with = "alfred")]
+        json: bool,
+        /// Print Alfred script filter items
+        #[arg(long)]
+        alfred: bool,
+        /// Shell the command should target
+        #[arg(long)]
+        shell: Option<String>,
+        /// Maximum number of alternatives
+        #[arg(long)]
+        max_items: Option<usize>,
+    },
+    /// Explain why a command failed, with a suggested fix (used by the
+    /// post-command hook from `murmur setup <shell> --diagnose`)
+    Diagnose {
+        /// Exit code the command returned
+        #[arg(long, allow_hyphen_values = true)]
+        exit_code: i32,
+        /// Shell the command ran in
+        #[arg(long)]
+        shell: Option<String>,
+        /// Host the command ran on (SSH sessions)
+        #[arg(long)]
+        host: Option<String>,
+        /// The command that failed
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    /// Write a commit message for the staged changes and commit with it
+    Commit {
+        /// Reword the last commit, including anything staged since
+        #[arg(long)]
+        amend: bool,
+        /// Commit without asking
+        #[arg(long, short, conflicts_with = "print")]
+        yes: bool,
+        /// Only print the message
+        #[arg(long)]
+        print: bool,
+    },
+    /// Draft a PR description (or changelog section) from a range of commits
+    PrDraft {
+        /// Commits to describe: base..head, base...head, or a base ref
+        /// meaning base..HEAD
+        range: String,
+        /// Write a changelog section instead
+        #[arg(long)]
+        changelog: bool,
+        /// Print the full result as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Suggest a branch name for a piece of work, e.g. "PROJ-42 fix login
+    /// timeout", and optionally create it
+    Branch {
+        /// What the work is; a ticket key, #number or issue URL in it is
+        /// put in the name
+        description: Vec<String>,
+        /// Also look for a ticket in the clipboard, or describe the work
+        /// with it if no description is given
+        #[arg(long)]
+        clipboard: bool,
+        /// Create the branch and switch to it
+        #[arg(long, short)]
+        create: bool,
+        /// Print the full result as JSON
+        #[arg(long, conflicts_with = "create")]
+        json: bool,
+    },
+    /// Write a jq, awk or sed one-liner for a transformation, e.g.
+    /// `curl ... | murmur expr jq "the name of each item"`
+    Expr {
+        /// Tool to write it for
+        #[arg(value_parser = ["jq", "awk", "sed"])]
+        tool: String,
+        /// What the one-liner should do
+        #[arg(required = true)]
+        description: Vec<String>,
+        /// Print the full result as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Write a regular expression, e.g. `murmur regex "ISO dates" < app.log`,
+    /// and show which piped lines it matches
+    Regex {
+        /// What the expression should match
+        #[arg(required = true)]
+        description: Vec<String>,
+        /// Print the full result as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Write a crontab line for a schedule, e.g. "every weekday at 6pm",
+    /// and show when it would run next
+    Cron {
+        /// When it should run
+        #[arg(required = true)]
+        description: Vec<String>,
+        /// Command to put after the schedule
+        #[arg(long, short)]
+        command: Option<String>,
+        /// Print the full result as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Abbreviations the shell widgets expand
+    Abbr {
+        #[command(subcommand)]
+        action: AbbrAction,
+    },
+    /// Command history commands
+    History {
+        #[command(subcommand)]
+        action: HistoryAction,
+    },
     /// Run diagnostic checks
     Doctor,
+    /// Interactively try inputs against each provider, showing prompts,
+    /// raw replies and the final ranked suggestions
+    Repl {
+        /// Path to config file
+        #[arg(long)]
+        config: Option<String>,
+        /// Shell to complete for
+        #[arg(long, default_value = "zsh")]
+        shell: String,
+    },
+    /// Transcribe audio files (16-bit PCM WAV) with the configured STT
+    /// engines; runs locally, without the daemon
+    Transcribe {
+        /// Files or glob patterns, e.g. "meetings/*.wav"
+        #[arg(required = true)]
+        files: Vec<String>,
+        /// Output format: text, json (text plus timed segments and words),
+        /// srt or vtt
+        #[arg(long, default_value = "text")]
+        format: String,
+        /// Rewrite each transcript as clean prose with the voice restructurer
+        #[arg(long)]
+        restructure: bool,
+        /// Label who is speaking (Deepgram, or diarize_command in [voice])
+        #[arg(long)]
+        speakers: bool,
+        /// Write <name>.txt, .json, .srt or .vtt for each file here instead of
+        /// printing to stdout
+        #[arg(long)]
+        out_dir: Option<String>,
+        /// Path to config file
+        #[arg(long)]
+        config: Option<String>,
+    },
     /// Voice input commands
     Voice {
         #[command(subcommand)]
         action: VoiceAction,
     },
+    /// Diagnostics for bug reports
+    Debug {
+        #[command(subcommand)]
+        action: DebugAction,
+    },
+    /// Run the daemon as a systemd user service or launchd agent
+    Service {
+        #[command(subcommand)]
+        action: ServiceAction,
+    },
+    /// API tokens for network clients of `listen_tcp` and `listen_ws`
+    Token {
+        #[command(subcommand)]
+        action: TokenAction,
+    },
+    /// Shell scripts, integrations and prompts built into murmur
+    Assets {
+        #[command(subcommand)]
+        action: AssetsAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum ProvidersAction {
+    /// List them with their latency and cost classes, context size and
+    /// the features their APIs support
+    List {
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+}
+
+#[derive(Subcommand)]
+enum TokenAction {
+    /// Add a token to the config and print it for the client's
+    /// MURMUR_TOKEN
+    Create {
+        /// What the token allows: complete, history, repo or admin (repeatable)
+        #[arg(long = "scope", required = true)]
+        scopes: Vec<Scope>,
+        /// Who it's for, shown in the daemon's logs
+        #[arg(long, default_value = "client")]
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum AssetsAction {
+    /// List the built-in assets, marking those overridden
+    List,
+    /// Write built-in assets out for editing; files in the overrides
+    /// directory are used instead of the built-in ones
+    Export {
+        /// Export only assets under these paths, e.g. `prompts` or
+        /// `shell/zsh`
+        paths: Vec<String>,
+        /// Directory to write to; default: the overrides directory
+        #[arg(long)]
+        dir: Option<PathBuf>,
+        /// Replace files that are already there
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ServiceAction {
+    /// Start the daemon at login and restart it if it crashes
+    Install {
+        /// Print the unit files or plist instead of installing them
+        #[arg(long)]
+        print: bool,
+    },
+    /// Stop the service and remove its files
+    Uninstall,
+    /// Show whether the service is installed and running
+    Status,
+}
+
+#[derive(Subcommand)]
+enum DebugAction {
+    /// Collect config (keys redacted), daemon status, recent logs and
+    /// request traces, provider health and environment info into a tarball
+    Bundle {
+        /// Where to write it; default: murmur-debug-<time>.tar.gz here
+        #[arg(long, short)]
+        output: Option<String>,
+        /// How many recent request traces to include
+        #[arg(long, default_value_t = 50)]
+        traces: usize,
+        /// Path to config file
+        #[arg(long)]
+        config: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum AbbrAction {
+    /// List defined and learned abbreviations
+    List {
+        /// Print `abbr` commands for fish to source
+        #[arg(long, conflicts_with = "names")]
+        fish: bool,
+        /// Print only the names, one per line
+        #[arg(long)]
+        names: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum HistoryAction {
+    /// List recorded commands, newest first
+    List {
+        /// Only commands run in the current directory
+        #[arg(long)]
+        here: bool,
+        /// Maximum number of entries
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Ask about past commands, e.g. "what did I deploy on friday"
+    Ask {
+        /// The question
+        #[arg(required = true, trailing_var_arg = true)]
+        question: Vec<String>,
+        /// Print the full result as JSON (filter, entries, summary)
+        #[arg(long)]
+        json: bool,
+        /// Maximum number of entries to list
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    /// Import existing shell history (zsh, bash, fish, Atuin) into murmur
+    ImportShell {
+        /// Only import this source (zsh, bash, fish, atuin); default: every
+        /// source whose history file exists
+        #[arg(long)]
+        source: Option<String>,
+        /// History file to read instead of the source's default location
+        #[arg(long, requires = "source")]
+        path: Option<String>,
+    },
+    /// Record a command that ran, for shell hooks (`murmur setup <shell>
+    /// --record`)
+    Record {
+        /// Exit code the command returned
+        #[arg(long, default_value_t = 0, allow_hyphen_values = true)]
+        exit_code: i32,
+        /// Shell the command ran in
+        #[arg(long, default_value = "terminal")]
+        shell: String,
+        /// Shell session, for learning command sequences
+        #[arg(long)]
+        session: Option<String>,
+        /// Host the command ran on (SSH sessions)
+        #[arg(long)]
+        host: Option<String>,
+        /// Provider of the murmur suggestion the command was run from
+        #[arg(long)]
+        suggested_by: Option<String>,
+        /// The command that ran
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
 }
 
 #[derive(Subcommand)]
@@ -58,141 +508,1569 @@ enum VoiceAction {
         #[arg(long, default_value = "command")]
         mode: String,
     },
+    /// Run the bundled utterance corpus through each STT engine and the
+    /// restructurer, comparing against expected outputs
+    Selftest {
+        /// Directory of recordings named <id>.wav; other utterances are
+        /// synthesized with the local TTS command
+        #[arg(long)]
+        corpus: Option<String>,
+        /// Only test this STT engine
+        #[arg(long)]
+        engine: Option<String>,
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
     /// Show voice engine status
     Status,
+    /// Answer a confirmation prompt for a sensitive voice command
+    Confirm {
+        /// Confirmation ID printed by `murmur voice test`
+        #[arg(long)]
+        id: u64,
+        /// WAV recording of the spoken reply
+        #[arg(long, conflicts_with = "text")]
+        file: Option<String>,
+        /// Typed reply instead of audio (e.g. "yes", or the challenge word)
+        #[arg(long)]
+        text: Option<String>,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
+    if let Some(profile) = &cli.profile {
+        // Read by the config paths, and inherited by a spawned daemon
+        std::env::set_var("MURMUR_PROFILE", profile);
+    }
+    if let Ok(profile) = std::env::var("MURMUR_PROFILE") {
+        if !profile.is_empty() && !Config::valid_profile(&profile) {
+            anyhow::bail!("Invalid profile name {profile:?}: use letters, digits, '-' and '_'");
+        }
+    }
 
     match cli.command {
         Commands::Start { foreground, config } => cmd_start(foreground, config).await,
         Commands::Stop => cmd_stop().await,
-        Commands::Status => cmd_status().await,
-        Commands::Setup { shell } => cmd_setup(&shell),
+        Commands::Reload => cmd_reload().await,
+        Commands::Status { output } => cmd_status(Output::from_args(&output)?).await,
+        Commands::Usage { output } => cmd_usage(Output::from_args(&output)?).await,
+        Commands::Providers { action } => match action {
+            ProvidersAction::List { output } => {
+                cmd_providers_list(Output::from_args(&output)?).await
+            }
+        },
+        Commands::Complete {
+            input,
+            shell,
+            max_items,
+            output,
+        } => {
+            cmd_complete(
+                input.join(" "),
+                shell,
+                max_items,
+                Output::from_args(&output)?,
+            )
+            .await
+        }
+        Commands::Health => cmd_health().await,
+        Commands::EnsureRunning => cmd_ensure_running().await,
+        Commands::Setup {
+            shell,
+            mode,
+            terminal,
+            statusbar,
+            diagnose,
+            abbr,
+            record,
+            no_completions,
+        } => {
+            let hooks = [("diagnose", diagnose), ("abbr", abbr), ("record", record)];
+            let hooks: Vec<&str> = hooks
+                .into_iter()
+                .filter_map(|(hook, on)| on.then_some(hook))
+                .collect();
+            cmd_setup(&shell, &mode, &terminal, statusbar, &hooks, !no_completions)
+        }
+        Commands::Completions { shell } => {
+            completions::write(&mut Cli::command(), &shell, &mut std::io::stdout())
+        }
+        Commands::SocketPath => {
+            println!("{}", Config::load().unwrap_or_default().daemon.socket_path);
+            Ok(())
+        }
+        Commands::Statusbar { format } => cmd_statusbar(&format).await,
+        Commands::Tunnel {
+            destination,
+            local_port,
+            remote_socket,
+            ensure,
+        } => cmd_tunnel(&destination, local_port, remote_socket, ensure).await,
+        Commands::Query {
+            query,
+            json,
+            alfred,
+            shell,
+            max_items,
+        } => {
+            let format = if json {
+                QueryFormat::Json
+            } else if alfred {
+                QueryFormat::Alfred
+            } else {
+                QueryFormat::Text
+            };
+            cmd_query(query.join(" "), format, shell, max_items).await
+        }
+        Commands::Diagnose {
+            exit_code,
+            shell,
+            host,
+            command,
+        } => cmd_diagnose(command.join(" "), exit_code, shell, host).await,
+        Commands::History { action } => match action {
+            HistoryAction::List {
+                here,
+                limit,
+                output,
+            } => cmd_history_list(here, limit, Output::from_args(&output)?).await,
+            HistoryAction::Ask {
+                question,
+                json,
+                limit,
+            } => cmd_history_ask(question.join(" "), json, limit).await,
+            HistoryAction::ImportShell { source, path } => {
+                cmd_history_import_shell(source, path).await
+            }
+            HistoryAction::Record {
+                exit_code,
+                shell,
+                session,
+                host,
+                suggested_by,
+                command,
+            } => {
+                let update = ContextUpdateRequest {
+                    source: shell,
+                    command: command.join(" "),
+                    cwd: std::env::current_dir()?.display().to_string(),
+                    exit_code,
+                    session_id: session.filter(|s| !s.is_empty()),
+                    host: host.filter(|h| !h.is_empty()),
+                    suggested_by: suggested_by.filter(|p| !p.is_empty()),
+                };
+                cmd_history_record(update).await
+            }
+        },
         Commands::Doctor => cmd_doctor().await,
+        Commands::Repl { config, shell } => cmd_repl(config, shell).await,
+        Commands::Transcribe {
+            files,
+            format,
+            restructure,
+            speakers,
+            out_dir,
+            config,
+        } => {
+            let format = TranscribeFormat::parse(&format)?;
+            cmd_transcribe(files, format, restructure, speakers, out_dir, config).await
+        }
         Commands::Voice { action } => match action {
             VoiceAction::Test { file, mode } => cmd_voice_test(file, mode).await,
+            VoiceAction::Selftest {
+                corpus,
+                engine,
+                json,
+            } => cmd_voice_selftest(corpus, engine, json).await,
             VoiceAction::Status => cmd_voice_status().await,
+            VoiceAction::Confirm { id, file, text } => cmd_voice_confirm(id, file, text).await,
+        },
+        Commands::Commit { amend, yes, print } => cmd_commit(amend, yes, print).await,
+        Commands::PrDraft {
+            range,
+            changelog,
+            json,
+        } => cmd_pr_draft(range, changelog, json).await,
+        Commands::Branch {
+            description,
+            clipboard,
+            create,
+            json,
+        } => cmd_branch(description.join(" "), clipboard, create, json).await,
+        Commands::Expr {
+            tool,
+            description,
+            json,
+        } => cmd_expr(tool, description.join(" "), json).await,
+        Commands::Regex { description, json } => cmd_regex(description.join(" "), json).await,
+        Commands::Cron {
+            description,
+            command,
+            json,
+        } => cmd_cron(description.join(" "), command, json).await,
+        Commands::Abbr { action } => match action {
+            AbbrAction::List { fish, names } => cmd_abbr_list(fish, names).await,
+        },
+        Commands::Debug { action } => match action {
+            DebugAction::Bundle {
+                output,
+                traces,
+                config,
+            } => cmd_debug_bundle(output, traces, config).await,
+        },
+        Commands::Service { action } => {
+            let manager = service::Manager::for_platform();
+            match action {
+                ServiceAction::Install { print } => {
+                    if !print && is_daemon_running() {
+                        anyhow::bail!(
+                            "A daemon started by `murmur start` is running; stop it with `murmur stop` first"
+                        );
+                    }
+                    service::install(manager, print)
+                }
+                ServiceAction::Uninstall => service::uninstall(manager),
+                ServiceAction::Status => service::status(manager),
+            }
+        }
+        Commands::Token { action } => match action {
+            TokenAction::Create { scopes, name } => cmd_token_create(&scopes, &name).await,
+        },
+        Commands::Assets { action } => match action {
+            AssetsAction::List => {
+                cmd_assets_list();
+                Ok(())
+            }
+            AssetsAction::Export { paths, dir, force } => cmd_assets_export(&paths, dir, force),
+        },
+    }
+}
+
+async fn cmd_token_create(scopes: &[Scope], name: &str) -> Result<()> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    {
+        anyhow::bail!("Token names use letters, digits, '-', '_' and '.'");
+    }
+    let mut bytes = [0u8; 24];
+    std::io::Read::read_exact(
+        &mut std::fs::File::open("/dev/urandom").context("Failed to open /dev/urandom")?,
+        &mut bytes,
+    )?;
+    let token: String = std::iter::once("mmr_".to_string())
+        .chain(bytes.iter().map(|b| format!("{b:02x}")))
+        .collect();
+    let mut scope_names: Vec<&str> = Vec::new();
+    for scope in scopes {
+        if !scope_names.contains(&scope.as_str()) {
+            scope_names.push(scope.as_str());
+        }
+    }
+
+    let path = Config::config_path();
+    let original = std::fs::read_to_string(&path).unwrap_or_default();
+    let mut text = original.clone();
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+    text.push_str(&format!(
+        "\n[[daemon.tokens]]\nname = \"{name}\"\ntoken = \"{token}\"\nscopes = [{}]\n",
+        scope_names
+            .iter()
+            .map(|scope| format!("\"{scope}\""))
+            .collect::<Vec<_>>()
+            .join(", ")
+    ));
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let created = !path.exists();
+    std::fs::write(&path, &text).with_context(|| format!("Failed to write {}", path.display()))?;
+    if created {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    if let Err(e) = Config::load_from(&path) {
+        std::fs::write(&path, &original)?;
+        anyhow::bail!("Adding the token would break {}: {e}", path.display());
+    }
+
+    println!("Token for {name} ({}):", scope_names.join(", "));
+    println!("  {token}");
+    println!("Clients send it with: export MURMUR_TOKEN={token}");
+    let config = Config::load().unwrap_or_default();
+    if config.daemon.listen_tcp.is_none() && config.daemon.listen_ws.is_none() {
+        println!("Tokens apply to listen_tcp and listen_ws in [daemon]; neither is set.");
+    }
+    if is_daemon_running() && !Endpoint::from_env("").is_remote() {
+        match send_request(&config.daemon.socket_path, methods::CONFIG_RELOAD, None).await {
+            Ok(response) if response.error.is_none() => println!("Daemon reloaded."),
+            _ => println!("Run `murmur reload` for the daemon to accept it."),
+        }
+    }
+    Ok(())
+}
+
+fn cmd_assets_list() {
+    for path in assets::list() {
+        if assets::is_overridden(&path) {
+            println!("{path} (overridden)");
+        } else {
+            println!("{path}");
+        }
+    }
+}
+
+fn cmd_assets_export(paths: &[String], dir: Option<PathBuf>, force: bool) -> Result<()> {
+    let dir = dir.unwrap_or_else(assets::overrides_dir);
+    let paths: Vec<String> = paths
+        .iter()
+        .map(|path| path.trim_end_matches('/').to_string())
+        .collect();
+    if let Some(path) = paths.iter().find(|path| {
+        !assets::list()
+            .iter()
+            .any(|asset| asset.starts_with(path.as_str()))
+    }) {
+        anyhow::bail!("No asset matches {path}; see `murmur assets list`");
+    }
+    let written = assets::export(&dir, &paths, force)
+        .with_context(|| format!("Failed to export to {}", dir.display()))?;
+    for path in &written {
+        println!("{}", dir.join(path).display());
+    }
+    if written.is_empty() {
+        println!(
+            "Nothing to export; the files are already in {} (--force replaces them)",
+            dir.display()
+        );
+    }
+    Ok(())
+}
+
+async fn cmd_start(foreground: bool, config_path: Option<String>) -> Result<()> {
+    if std::env::var("MURMUR_REMOTE").is_ok_and(|r| !r.is_empty()) {
+        anyhow::bail!("MURMUR_REMOTE is set; start the daemon on the remote host instead");
+    }
+
+    // Check if already running
+    if is_daemon_running() {
+        println!("Murmur daemon is already running.");
+        return Ok(());
+    }
+
+    let config = match &config_path {
+        Some(path) => Config::load_from(Path::new(path))?,
+        None => Config::load()?,
+    };
+
+    if foreground {
+        server::init_tracing(&config.daemon.log_level);
+        println!("Starting Murmur daemon (foreground)...");
+        let config_file = config_path
+            .map(std::path::PathBuf::from)
+            .unwrap_or_else(Config::config_path);
+        let server = Server::new(config).watch_config(config_file);
+        server.run().await?;
+    } else {
+        let pid = spawn_daemon(config_path)?;
+        println!("Murmur daemon started (PID: {pid})");
+    }
+
+    Ok(())
+}
+
+/// Start `murmur start --foreground` as a background process.
+fn spawn_daemon(config_path: Option<String>) -> Result<u32> {
+    let exe = std::env::current_exe()?;
+    let mut args = vec!["start".to_string(), "--foreground".to_string()];
+    if let Some(path) = config_path {
+        args.push("--config".to_string());
+        args.push(path);
+    }
+
+    let child = std::process::Command::new(exe)
+        .args(&args)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .context("Failed to spawn daemon process")?;
+    Ok(child.id())
+}
+
+/// How long a running daemon has to answer `ping` before it counts as hung.
+const PING_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long a started daemon has to begin answering.
+const START_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Least time between restarts, so a daemon that dies as it starts isn't
+/// restarted on every keypress.
+const RESTART_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Whether the daemon answers `ping` within `timeout`. Any answer counts,
+/// including an error from a daemon too old to know `ping`.
+async fn answers(socket_path: &str, timeout: Duration) -> bool {
+    matches!(
+        tokio::time::timeout(timeout, send_request(socket_path, methods::PING, None)).await,
+        Ok(Ok(_))
+    )
+}
+
+/// Called by the shell integration when a request goes unanswered: does
+/// nothing if the daemon answers, and otherwise starts it, through the
+/// installed service if there is one.
+async fn cmd_ensure_running() -> Result<()> {
+    let config = Config::load().unwrap_or_default();
+    let socket_path = &config.daemon.socket_path;
+    if answers(socket_path, PING_TIMEOUT).await {
+        return Ok(());
+    }
+    if Endpoint::from_env("").is_remote() {
+        anyhow::bail!("The remote daemon isn't answering; restart it on the remote host");
+    }
+
+    let marker = Config::pid_path().with_extension("restarted");
+    let recent = std::fs::metadata(&marker)
+        .and_then(|meta| meta.modified())
+        .is_ok_and(|at| at.elapsed().is_ok_and(|age| age < RESTART_INTERVAL));
+    if recent {
+        anyhow::bail!(
+            "Murmur daemon isn't answering and was restarted less than {}s ago; \
+             run `murmur start --foreground` to see why",
+            RESTART_INTERVAL.as_secs()
+        );
+    }
+    let _ = std::fs::write(&marker, "");
+
+    // Alive but not answering: hung. `daemon_pid` drops a PID file left by
+    // a crash whose PID now belongs to something else
+    if let Some(pid) = daemon_pid() {
+        eprintln!("Murmur daemon (PID: {pid}) isn't answering; restarting it");
+        // SAFETY: pid is positive, so this signals only that process
+        unsafe { libc::kill(pid, libc::SIGTERM) };
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(1);
+        while daemon_pid().is_some() && tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        if daemon_pid().is_some() {
+            unsafe { libc::kill(pid, libc::SIGKILL) };
+        }
+        let _ = std::fs::remove_file(Config::pid_path());
+    }
+
+    if !service::start(service::Manager::for_platform())? {
+        spawn_daemon(None)?;
+    }
+    let deadline = tokio::time::Instant::now() + START_TIMEOUT;
+    while tokio::time::Instant::now() < deadline {
+        if answers(socket_path, PING_TIMEOUT).await {
+            match daemon_pid() {
+                Some(pid) => println!("Murmur daemon started (PID: {pid})"),
+                None => println!("Murmur daemon started"),
+            }
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    anyhow::bail!("Murmur daemon didn't start; run `murmur start --foreground` to see why")
+}
+
+async fn cmd_stop() -> Result<()> {
+    if !is_daemon_running() {
+        println!("Murmur daemon is not running.");
+        return Ok(());
+    }
+
+    // Send shutdown via socket
+    let config = Config::load().unwrap_or_default();
+    match send_request(&config.daemon.socket_path, methods::SHUTDOWN, None).await {
+        Ok(_) => println!("Murmur daemon stopped."),
+        Err(_) => {
+            // Fallback: kill via PID
+            match daemon_pid() {
+                Some(pid) => {
+                    // SAFETY: pid is positive, so this signals only that process
+                    unsafe {
+                        libc::kill(pid, libc::SIGTERM);
+                    }
+                    let _ = std::fs::remove_file(Config::pid_path());
+                    let _ = std::fs::remove_file(&config.daemon.socket_path);
+                    println!("Murmur daemon stopped (via signal).");
+                }
+                None => {
+                    println!("PID file names no murmur process, cleaning up.");
+                    let _ = std::fs::remove_file(Config::pid_path());
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn cmd_status(output: Output) -> Result<()> {
+    if !is_daemon_running() {
+        match output.format {
+            output::OutputFormat::Plain => println!("Murmur daemon is not running."),
+            _ => {
+                let status = serde_json::json!({ "status": "stopped" });
+                output.print(&status, &Records::key_value(&status));
+            }
+        }
+        return Ok(());
+    }
+
+    let status = daemon_status().await?;
+    output.print(&status, &Records::key_value(&status));
+    Ok(())
+}
+
+/// The daemon's `status` result.
+async fn daemon_status() -> Result<serde_json::Value> {
+    let config = Config::load().unwrap_or_default();
+    let response = send_request(&config.daemon.socket_path, methods::STATUS, None).await?;
+    match (response.result, response.error) {
+        (Some(result), _) => Ok(result),
+        (None, Some(error)) => anyhow::bail!("Failed to get status: {}", error.message),
+        (None, None) => anyhow::bail!("Empty response from daemon"),
+    }
+}
+
+async fn cmd_usage(output: Output) -> Result<()> {
+    if !is_daemon_running() {
+        anyhow::bail!("Murmur daemon is not running");
+    }
+    let status = daemon_status().await?;
+    let active = status["providers_active"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+    let providers: serde_json::Map<String, serde_json::Value> = status["providers_configured"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|name| name.as_str())
+        .map(|name| {
+            let health = &status["provider_health"][name];
+            let usage = serde_json::json!({
+                "active": active.iter().any(|a| a == name),
+                "latency_ms": status["provider_latency_ms"][name],
+                "healthy": health["ok"],
+            });
+            (name.to_string(), usage)
+        })
+        .collect();
+    let usage = serde_json::json!({
+        "requests_today": status["budget_used"],
+        "daily_limit": status["budget_limit"],
+        "remaining": status["budget_remaining"],
+        "providers": providers,
+    });
+    output.print(&usage, &Records::key_value(&usage));
+    Ok(())
+}
+
+async fn cmd_providers_list(output: Output) -> Result<()> {
+    if !is_daemon_running() {
+        anyhow::bail!("Murmur daemon is not running");
+    }
+    let status = daemon_status().await?;
+    let capabilities = &status["provider_capabilities"];
+    let rows = status["providers_active"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|name| name.as_str())
+        .map(|name| {
+            let caps = &capabilities[name];
+            let supports: Vec<&str> = ["streaming", "fim", "json_mode"]
+                .into_iter()
+                .filter(|feature| caps[feature].as_bool() == Some(true))
+                .collect();
+            vec![
+                name.to_string(),
+                output::cell(&caps["latency"]),
+                output::cell(&caps["cost"]),
+                output::cell(&caps["max_context"]),
+                supports.join(","),
+            ]
+        })
+        .collect();
+    let records = Records {
+        columns: &["provider", "latency", "cost", "max_context", "supports"],
+        plain_columns: 5,
+        rows,
+    };
+    output.print(capabilities, &records);
+    Ok(())
+}
+
+async fn cmd_complete(
+    input: String,
+    shell: Option<String>,
+    max_items: Option<usize>,
+    output: Output,
+) -> Result<()> {
+    if !is_daemon_running() {
+        anyhow::bail!("Murmur daemon is not running");
+    }
+    let config = Config::load().unwrap_or_default();
+    let request = CompletionRequest {
+        cursor_pos: input.len(),
+        input,
+        cwd: std::env::current_dir()?.display().to_string(),
+        history: Vec::new(),
+        shell: shell.or_else(|| {
+            std::env::var("SHELL")
+                .ok()
+                .and_then(|s| s.rsplit('/').next().map(String::from))
+        }),
+        max_items,
+        min_score: None,
+        host: None,
+        provider: None,
+        model: None,
+        cancel_token: None,
+        session_id: None,
+    };
+    let params = serde_json::to_value(&request)?;
+    let response =
+        send_request(&config.daemon.socket_path, methods::COMPLETE, Some(params)).await?;
+    let result = match (response.result, response.error) {
+        (Some(result), _) => result,
+        (None, Some(error)) => anyhow::bail!("Completion failed: {}", error.message),
+        (None, None) => anyhow::bail!("Empty response from daemon"),
+    };
+    let completions: CompletionResponse = serde_json::from_value(result.clone())?;
+    let rows = completions
+        .items
+        .iter()
+        .map(|item| {
+            vec![
+                item.text.clone(),
+                item.description.clone().unwrap_or_default(),
+                output::cell(&serde_json::to_value(&item.kind).unwrap_or_default()),
+                item.score.to_string(),
+            ]
+        })
+        .collect();
+    let records = Records {
+        columns: &["text", "description", "kind", "score"],
+        plain_columns: 2,
+        rows,
+    };
+    output.print(&result, &records);
+    Ok(())
+}
+
+async fn cmd_history_list(here: bool, limit: usize, output: Output) -> Result<()> {
+    if !is_daemon_running() {
+        anyhow::bail!("Murmur daemon is not running");
+    }
+    let config = Config::load().unwrap_or_default();
+    let request = HistoryListRequest {
+        cwd: here
+            .then(|| std::env::current_dir().map(|p| p.display().to_string()))
+            .transpose()?,
+        host: None,
+        limit,
+    };
+    let params = serde_json::to_value(&request)?;
+    let response = send_request(
+        &config.daemon.socket_path,
+        methods::HISTORY_LIST,
+        Some(params),
+    )
+    .await?;
+    let result = match (response.result, response.error) {
+        (Some(result), _) => result,
+        (None, Some(error)) => anyhow::bail!("History list failed: {}", error.message),
+        (None, None) => anyhow::bail!("Empty response from daemon"),
+    };
+    let entries: Vec<HistoryEntry> = serde_json::from_value(result.clone())?;
+    let rows = entries
+        .into_iter()
+        .map(|entry| {
+            vec![
+                entry.command,
+                entry.cwd,
+                entry.exit_code.to_string(),
+                entry.timestamp.to_string(),
+                entry.source,
+                entry.host.unwrap_or_default(),
+                entry.session_id.unwrap_or_default(),
+            ]
+        })
+        .collect();
+    let records = Records {
+        columns: &[
+            "command",
+            "cwd",
+            "exit_code",
+            "timestamp",
+            "source",
+            "host",
+            "session_id",
+        ],
+        plain_columns: 2,
+        rows,
+    };
+    output.print(&result, &records);
+    Ok(())
+}
+
+/// For `HEALTHCHECK CMD murmur health` and exec probes: succeeds only if
+/// the daemon answers and is ready.
+async fn cmd_health() -> Result<()> {
+    if !is_daemon_running() {
+        anyhow::bail!("Murmur daemon is not running");
+    }
+    let config = Config::load().unwrap_or_default();
+    let response = send_request(&config.daemon.socket_path, methods::HEALTH, None).await?;
+    let health: HealthResponse = match (response.result, response.error) {
+        (Some(result), _) => serde_json::from_value(result)?,
+        (None, Some(error)) => anyhow::bail!("Health check failed: {}", error.message),
+        (None, None) => anyhow::bail!("Empty response from daemon"),
+    };
+    if !health.ready {
+        anyhow::bail!("Murmur daemon is not ready: {}", health.reasons.join("; "));
+    }
+    println!(
+        "Murmur daemon {} is ready (up {}s)",
+        health.version, health.uptime_secs
+    );
+    Ok(())
+}
+
+async fn cmd_reload() -> Result<()> {
+    if !is_daemon_running() {
+        anyhow::bail!("Murmur daemon is not running");
+    }
+    let config = Config::load().unwrap_or_default();
+    let response = send_request(&config.daemon.socket_path, methods::CONFIG_RELOAD, None).await?;
+    let reload: ConfigReloadResponse = match (response.result, response.error) {
+        (Some(result), _) => serde_json::from_value(result)?,
+        (None, Some(error)) => anyhow::bail!("Reload failed: {}", error.message),
+        (None, None) => anyhow::bail!("Empty response from daemon"),
+    };
+    println!("Reloaded {}", reload.path);
+    match reload.providers.is_empty() {
+        true => println!("  Providers: none active"),
+        false => println!("  Providers: {}", reload.providers.join(", ")),
+    }
+    println!(
+        "  Voice:     {}",
+        if reload.voice_enabled {
+            "enabled"
+        } else {
+            "disabled"
+        }
+    );
+    println!("  Cache:     {} entries", reload.cache_size);
+    if !reload.restart_needed.is_empty() {
+        println!(
+            "Restart the daemon to apply: {}",
+            reload.restart_needed.join(", ")
+        );
+    }
+    Ok(())
+}
+
+async fn cmd_commit(amend: bool, yes: bool, print: bool) -> Result<()> {
+    use std::io::{IsTerminal, Write};
+
+    if !is_daemon_running() {
+        anyhow::bail!("Murmur daemon is not running — start with: murmur start");
+    }
+    let config = Config::load().unwrap_or_default();
+    let request = CommitSuggestRequest {
+        cwd: std::env::current_dir()?.display().to_string(),
+        amend,
+    };
+    let params = serde_json::to_value(&request)?;
+    let response = send_request(
+        &config.daemon.socket_path,
+        methods::COMMIT_SUGGEST,
+        Some(params),
+    )
+    .await?;
+    let suggestion: CommitSuggestResponse = match (response.result, response.error) {
+        (Some(result), _) => serde_json::from_value(result)?,
+        (None, Some(error)) => anyhow::bail!("{}", error.message),
+        (None, None) => anyhow::bail!("Empty response from daemon"),
+    };
+    if print {
+        println!("{}", suggestion.message);
+        return Ok(());
+    }
+
+    println!("{}\n", suggestion.message);
+    if suggestion.truncated {
+        eprintln!("(The diff was too large to send whole; check the message covers it.)");
+    }
+    let edit = if yes {
+        false
+    } else if !std::io::stdin().is_terminal() {
+        anyhow::bail!("Not a terminal; pass --yes to commit or --print for the message alone");
+    } else {
+        print!("Commit with this message? [y]es, [e]dit, [N]o: ");
+        std::io::stdout().flush()?;
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        match answer.trim().to_ascii_lowercase().as_str() {
+            "y" | "yes" => false,
+            "e" | "edit" => true,
+            _ => {
+                println!("Not committed");
+                return Ok(());
+            }
+        }
+    };
+
+    // A file rather than -m, so git's editor gets the message to edit
+    let mut file = tempfile::Builder::new()
+        .prefix("murmur-commit-")
+        .suffix(".txt")
+        .tempfile()
+        .context("Failed to create a file for the commit message")?;
+    writeln!(file, "{}", suggestion.message).context("Failed to write the commit message")?;
+    let mut git = std::process::Command::new("git");
+    git.arg("commit").arg("-F").arg(file.path());
+    if amend {
+        git.arg("--amend");
+    }
+    if edit {
+        git.arg("--edit");
+    }
+    let status = git.status();
+    drop(file);
+    if !status.context("Failed to run git")?.success() {
+        anyhow::bail!("git commit failed");
+    }
+    Ok(())
+}
+
+async fn cmd_pr_draft(range: String, changelog: bool, json: bool) -> Result<()> {
+    if !is_daemon_running() {
+        anyhow::bail!("Murmur daemon is not running — start with: murmur start");
+    }
+    let config = Config::load().unwrap_or_default();
+    let request = SummarizeCommitsRequest {
+        cwd: std::env::current_dir()?.display().to_string(),
+        range,
+        kind: if changelog {
+            DraftKind::Changelog
+        } else {
+            DraftKind::Pr
         },
+    };
+    let params = serde_json::to_value(&request)?;
+    let response = send_request(
+        &config.daemon.socket_path,
+        methods::SUMMARIZE_COMMITS,
+        Some(params),
+    )
+    .await?;
+    let draft: SummarizeCommitsResponse = match (response.result, response.error) {
+        (Some(result), _) => serde_json::from_value(result)?,
+        (None, Some(error)) => anyhow::bail!("{}", error.message),
+        (None, None) => anyhow::bail!("Empty response from daemon"),
+    };
+    if json {
+        println!("{}", serde_json::to_string_pretty(&draft)?);
+        return Ok(());
+    }
+    println!("{}", draft.text);
+    if draft.truncated {
+        eprintln!(
+            "(Drafted from part of {} commits; the range was too long to send whole.)",
+            draft.commits
+        );
+    }
+    Ok(())
+}
+
+async fn cmd_branch(description: String, clipboard: bool, create: bool, json: bool) -> Result<()> {
+    if !is_daemon_running() {
+        anyhow::bail!("Murmur daemon is not running — start with: murmur start");
+    }
+    let clipboard = match clipboard {
+        true => Some(read_clipboard().context("Failed to read the clipboard")?),
+        false => None,
+    };
+    let (description, clipboard) = match (description.trim().is_empty(), clipboard) {
+        (true, Some(text)) => (text, None),
+        (true, None) => {
+            anyhow::bail!("Describe the work, e.g. murmur branch \"PROJ-42 fix login timeout\"")
+        }
+        (false, clipboard) => (description, clipboard),
+    };
+    let config = Config::load().unwrap_or_default();
+    let request = BranchSuggestRequest {
+        description,
+        clipboard,
+        cwd: Some(std::env::current_dir()?.display().to_string()),
+    };
+    let params = serde_json::to_value(&request)?;
+    let response = send_request(
+        &config.daemon.socket_path,
+        methods::BRANCH_SUGGEST,
+        Some(params),
+    )
+    .await?;
+    let branch: BranchSuggestResponse = match (response.result, response.error) {
+        (Some(result), _) => serde_json::from_value(result)?,
+        (None, Some(error)) => anyhow::bail!("{}", error.message),
+        (None, None) => anyhow::bail!("Empty response from daemon"),
+    };
+    if json {
+        println!("{}", serde_json::to_string_pretty(&branch)?);
+        return Ok(());
+    }
+    if !create {
+        println!("{}", branch.name);
+        return Ok(());
+    }
+    let status = std::process::Command::new("git")
+        .args(["switch", "-c", &branch.name])
+        .status()
+        .context("Failed to run git")?;
+    if !status.success() {
+        anyhow::bail!("git switch -c {} failed", branch.name);
+    }
+    Ok(())
+}
+
+/// Text on the clipboard, from the first clipboard tool that runs.
+fn read_clipboard() -> Result<String> {
+    let candidates: &[(&str, &[&str])] = if cfg!(target_os = "macos") {
+        &[("pbpaste", &[])]
+    } else {
+        &[
+            ("wl-paste", &["--no-newline"]),
+            ("xclip", &["-selection", "clipboard", "-o"]),
+            ("xsel", &["--clipboard", "--output"]),
+        ]
+    };
+    for (program, args) in candidates {
+        match std::process::Command::new(program).args(*args).output() {
+            Ok(output) if output.status.success() => {
+                return Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+            }
+            _ => continue,
+        }
+    }
+    anyhow::bail!("no clipboard tool found (pbpaste, wl-paste, xclip or xsel)")
+}
+
+async fn cmd_expr(tool: String, description: String, json: bool) -> Result<()> {
+    if !is_daemon_running() {
+        anyhow::bail!("Murmur daemon is not running — start with: murmur start");
+    }
+    let config = Config::load().unwrap_or_default();
+    let request = ExprSuggestRequest {
+        tool: serde_json::from_value(serde_json::Value::String(tool))?,
+        description,
+        sample: read_sample(),
+    };
+    let params = serde_json::to_value(&request)?;
+    let response = send_request(
+        &config.daemon.socket_path,
+        methods::EXPR_SUGGEST,
+        Some(params),
+    )
+    .await?;
+    let expr: ExprSuggestResponse = match (response.result, response.error) {
+        (Some(result), _) => serde_json::from_value(result)?,
+        (None, Some(error)) => anyhow::bail!("{}", error.message),
+        (None, None) => anyhow::bail!("Empty response from daemon"),
+    };
+    if json {
+        println!("{}", serde_json::to_string_pretty(&expr)?);
+    } else {
+        println!("{}", expr.command);
+    }
+    Ok(())
+}
+
+async fn cmd_regex(description: String, json: bool) -> Result<()> {
+    if !is_daemon_running() {
+        anyhow::bail!("Murmur daemon is not running — start with: murmur start");
+    }
+    let config = Config::load().unwrap_or_default();
+    let request = RegexSuggestRequest {
+        description,
+        sample: read_sample(),
+    };
+    let params = serde_json::to_value(&request)?;
+    let response = send_request(
+        &config.daemon.socket_path,
+        methods::REGEX_SUGGEST,
+        Some(params),
+    )
+    .await?;
+    let regex: RegexSuggestResponse = match (response.result, response.error) {
+        (Some(result), _) => serde_json::from_value(result)?,
+        (None, Some(error)) => anyhow::bail!("{}", error.message),
+        (None, None) => anyhow::bail!("Empty response from daemon"),
+    };
+    if json {
+        println!("{}", serde_json::to_string_pretty(&regex)?);
+        return Ok(());
+    }
+    // The expression alone on stdout, so it can be captured
+    println!("{}", regex.pattern);
+    if regex.sample_lines > 0 {
+        eprintln!(
+            "{} of {} lines match",
+            regex.matched_lines, regex.sample_lines
+        );
+        for preview in &regex.previews {
+            eprintln!("{:>6}  {}", preview.line_number, mark_matches(preview));
+        }
+    }
+    Ok(())
+}
+
+/// A previewed line with each match in brackets.
+fn mark_matches(preview: &RegexPreview) -> String {
+    let line = &preview.line;
+    let mut marked = String::with_capacity(line.len() + 2 * preview.spans.len());
+    let mut at = 0;
+    for &[start, end] in &preview.spans {
+        let (Some(before), Some(matched)) = (line.get(at..start), line.get(start..end)) else {
+            return line.clone();
+        };
+        marked.push_str(before);
+        marked.push('[');
+        marked.push_str(matched);
+        marked.push(']');
+        at = end;
+    }
+    marked.push_str(line.get(at..).unwrap_or(""));
+    marked
+}
+
+async fn cmd_cron(description: String, command: Option<String>, json: bool) -> Result<()> {
+    if !is_daemon_running() {
+        anyhow::bail!("Murmur daemon is not running — start with: murmur start");
+    }
+    let config = Config::load().unwrap_or_default();
+    let request = CronSuggestRequest {
+        description,
+        command,
+    };
+    let params = serde_json::to_value(&request)?;
+    let response = send_request(
+        &config.daemon.socket_path,
+        methods::CRON_SUGGEST,
+        Some(params),
+    )
+    .await?;
+    let cron: CronSuggestResponse = match (response.result, response.error) {
+        (Some(result), _) => serde_json::from_value(result)?,
+        (None, Some(error)) => anyhow::bail!("{}", error.message),
+        (None, None) => anyhow::bail!("Empty response from daemon"),
+    };
+    if json {
+        println!("{}", serde_json::to_string_pretty(&cron)?);
+        return Ok(());
+    }
+    // The line alone on stdout, e.g. for `(crontab -l; murmur cron ...) | crontab -`
+    println!("{}", cron.line);
+    if cron.next_runs.is_empty() {
+        eprintln!("Runs at startup only");
+    } else {
+        eprintln!("Next runs:");
+        for run in &cron.next_runs {
+            eprintln!("  {}", localtime::format_local_with_weekday(*run));
+        }
+    }
+    Ok(())
+}
+
+/// Most bytes of piped input `murmur expr` and `murmur regex` read as a
+/// sample.
+const MAX_SAMPLE_BYTES: usize = 16 * 1024;
+
+/// How long `murmur expr` and `murmur regex` wait for piped input.
+const SAMPLE_WAIT: Duration = Duration::from_secs(2);
+
+/// The start of what is piped into stdin, or None when it is a terminal.
+/// Reading stops at `MAX_SAMPLE_BYTES` or after `SAMPLE_WAIT`, so a
+/// stream that never ends, like `tail -f`, doesn't hang the command.
+fn read_sample() -> Option<String> {
+    use std::io::{IsTerminal, Read};
+
+    if std::io::stdin().is_terminal() {
+        return None;
+    }
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let mut stdin = std::io::stdin().lock();
+        let mut buf = [0u8; 8192];
+        while let Ok(n @ 1..) = stdin.read(&mut buf) {
+            if tx.send(buf[..n].to_vec()).is_err() {
+                break;
+            }
+        }
+    });
+    let deadline = std::time::Instant::now() + SAMPLE_WAIT;
+    let mut sample = Vec::new();
+    while sample.len() < MAX_SAMPLE_BYTES {
+        let wait = deadline.saturating_duration_since(std::time::Instant::now());
+        match rx.recv_timeout(wait) {
+            Ok(chunk) => sample.extend(chunk),
+            Err(_) => break,
+        }
+    }
+    sample.truncate(MAX_SAMPLE_BYTES);
+    let sample = String::from_utf8_lossy(&sample).into_owned();
+    (!sample.trim().is_empty()).then_some(sample)
+}
+
+async fn cmd_abbr_list(fish: bool, names: bool) -> Result<()> {
+    let config = Config::load().unwrap_or_default();
+    let response = send_request(&config.daemon.socket_path, methods::ABBR_LIST, None).await?;
+    let list: AbbrListResponse = match (response.result, response.error) {
+        (Some(result), _) => serde_json::from_value(result)?,
+        (None, Some(error)) => anyhow::bail!("Listing abbreviations failed: {}", error.message),
+        (None, None) => anyhow::bail!("Empty response from daemon"),
+    };
+    for abbr in &list.abbreviations {
+        if fish {
+            println!(
+                "abbr --add -- {} {}",
+                fish_quote(&abbr.name),
+                fish_quote(&abbr.expansion)
+            );
+        } else if names {
+            println!("{}", abbr.name);
+        } else {
+            let learned = if abbr.learned { "  (learned)" } else { "" };
+            println!("{}\t{}{learned}", abbr.name, abbr.expansion);
+        }
     }
+    Ok(())
 }
 
-async fn cmd_start(foreground: bool, config_path: Option<String>) -> Result<()> {
-    // Check if already running
-    if is_daemon_running() {
-        println!("Murmur daemon is already running.");
+/// The built-in asset at `path`, or the user's override of it.
+fn asset(path: &str) -> Result<std::borrow::Cow<'static, str>> {
+    assets::get(path).with_context(|| format!("No asset at {path}"))
+}
+
+/// `text` as a single-quoted fish string.
+fn fish_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+fn cmd_setup(
+    shell: &str,
+    mode: &str,
+    terminal: &str,
+    statusbar: bool,
+    hooks: &[&str],
+    with_completions: bool,
+) -> Result<()> {
+    if shell == "wezterm" {
+        println!("{}", asset(terminal::WEZTERM_LUA)?);
         return Ok(());
     }
 
-    let config = match &config_path {
-        Some(path) => Config::load_from(Path::new(path))?,
-        None => Config::load()?,
+    let script = match (shell, mode) {
+        ("zsh", "widget") => "shell/zsh/murmur.zsh",
+        ("bash", "widget") => "shell/bash/murmur.bash",
+        ("bash", "keybind") => "shell/bash/murmur-keybind.bash",
+        ("fish", "widget") => "shell/fish/murmur.fish",
+        ("zsh" | "fish", "keybind") => {
+            anyhow::bail!("Keybind mode is only available for bash");
+        }
+        ("zsh" | "bash" | "fish", other) => {
+            anyhow::bail!("Unknown setup mode: {other}. Supported: widget, keybind");
+        }
+        (other, _) => {
+            anyhow::bail!("Unsupported shell: {other}. Supported: zsh, bash, fish");
+        }
     };
+    // A shell set up under a profile keeps talking to that profile's daemon
+    if let Some(profile) = Config::profile() {
+        match shell {
+            "fish" => println!("set -gx MURMUR_PROFILE {profile}"),
+            _ => println!("export MURMUR_PROFILE={profile}"),
+        }
+    }
+    println!("{}", asset(script)?);
 
-    if foreground {
-        server::init_tracing(&config.daemon.log_level);
-        println!("Starting Murmur daemon (foreground)...");
-        let server = Server::new(config);
-        server.run().await?;
-    } else {
-        // Spawn as background process
-        let exe = std::env::current_exe()?;
-        let mut args = vec!["start".to_string(), "--foreground".to_string()];
-        if let Some(path) = config_path {
-            args.push("--config".to_string());
-            args.push(path);
+    // The fish script renders its own completions; keybind mode uses `select`
+    if mode == "widget" && shell != "fish" {
+        if let Some(layer) = TerminalKind::from_arg(terminal)?.render_script() {
+            println!("{}", asset(layer)?);
         }
+    }
 
-        let child = std::process::Command::new(exe)
-            .args(&args)
-            .stdin(std::process::Stdio::null())
-            .stdout(std::process::Stdio::null())
-            .stderr(std::process::Stdio::null())
-            .spawn()
-            .context("Failed to spawn daemon process")?;
+    if statusbar {
+        let component = match shell {
+            "fish" => "shell/statusbar/murmur-statusbar.fish",
+            _ => "shell/statusbar/murmur-statusbar.sh",
+        };
+        println!("{}", asset(component)?);
+    }
 
-        println!("Murmur daemon started (PID: {})", child.id());
+    // Optional hooks live at shell/<hook>/murmur-<hook>.<shell>
+    for hook in hooks {
+        let extension = match shell {
+            "zsh" | "fish" => shell,
+            _ => "bash",
+        };
+        println!(
+            "{}",
+            asset(&format!("shell/{hook}/murmur-{hook}.{extension}"))?
+        );
     }
 
+    if with_completions {
+        completions::write(&mut Cli::command(), shell, &mut std::io::stdout())?;
+    }
     Ok(())
 }
 
-async fn cmd_stop() -> Result<()> {
-    if !is_daemon_running() {
-        println!("Murmur daemon is not running.");
-        return Ok(());
-    }
+async fn cmd_statusbar(format: &str) -> Result<()> {
+    let format = StatusFormat::from_arg(format)?;
+    let config = Config::load().unwrap_or_default();
+    statusbar::run(&Endpoint::from_env(&config.daemon.socket_path), format).await
+}
 
-    // Send shutdown via socket
+async fn cmd_query(
+    query: String,
+    format: QueryFormat,
+    shell: Option<String>,
+    max_items: Option<usize>,
+) -> Result<()> {
     let config = Config::load().unwrap_or_default();
-    match send_request(&config.daemon.socket_path, methods::SHUTDOWN, None).await {
-        Ok(_) => println!("Murmur daemon stopped."),
-        Err(_) => {
-            // Fallback: kill via PID
-            if let Ok(pid_str) = std::fs::read_to_string(Config::pid_path()) {
-                if let Ok(pid) = pid_str.trim().parse::<i32>() {
-                    if pid > 0 {
-                        unsafe {
-                            libc::kill(pid, libc::SIGTERM);
-                        }
-                        let _ = std::fs::remove_file(Config::pid_path());
-                        let _ = std::fs::remove_file(&config.daemon.socket_path);
-                        println!("Murmur daemon stopped (via signal).");
-                    } else {
-                        println!("Invalid PID in PID file, cleaning up.");
-                        let _ = std::fs::remove_file(Config::pid_path());
-                    }
+    let request = QueryRequest {
+        query,
+        cwd: std::env::current_dir()
+            .ok()
+            .map(|p| p.display().to_string()),
+        shell: shell.or_else(|| {
+            std::env::var("SHELL")
+                .ok()
+                .and_then(|s| s.rsplit('/').next().map(String::from))
+        }),
+        max_items,
+    };
+
+    // Launchers may run before the daemon is up; ask the providers directly
+    let response: QueryResponse = if is_daemon_running() {
+        let params = serde_json::to_value(&request)?;
+        match send_request(&config.daemon.socket_path, methods::QUERY, Some(params)).await {
+            Ok(response) => {
+                if let Some(err) = response.error {
+                    anyhow::bail!("Query failed: {}", err.message);
                 }
+                serde_json::from_value(response.result.unwrap_or_default())?
             }
+            Err(_) => murmur_daemon::handler::query_direct(&config, &request)
+                .await
+                .map_err(anyhow::Error::msg)?,
         }
-    }
+    } else {
+        murmur_daemon::handler::query_direct(&config, &request)
+            .await
+            .map_err(anyhow::Error::msg)?
+    };
 
+    println!("{}", query::render(&response, format));
     Ok(())
 }
 
-async fn cmd_status() -> Result<()> {
+async fn cmd_diagnose(
+    command: String,
+    exit_code: i32,
+    shell: Option<String>,
+    host: Option<String>,
+) -> Result<()> {
+    let config = Config::load().unwrap_or_default();
+    // The daemon owns the cache and rate limit; without it, stay quiet
     if !is_daemon_running() {
-        println!("Murmur daemon is not running.");
-        return Ok(());
+        anyhow::bail!("Daemon not running");
     }
+    let request = DiagnoseRequest {
+        command,
+        exit_code,
+        cwd: std::env::current_dir()?.display().to_string(),
+        shell,
+        stderr: None,
+        host: host.filter(|h| !h.is_empty()),
+    };
 
-    let config = Config::load().unwrap_or_default();
-    match send_request(&config.daemon.socket_path, methods::STATUS, None).await {
-        Ok(response) => {
-            if let Some(result) = response.result {
-                println!("Murmur daemon status:");
-                println!("{}", serde_json::to_string_pretty(&result)?);
+    let params = serde_json::to_value(&request)?;
+    let response =
+        send_request(&config.daemon.socket_path, methods::DIAGNOSE, Some(params)).await?;
+    if let Some(err) = response.error {
+        anyhow::bail!("Diagnosis failed: {}", err.message);
+    }
+    let response: DiagnoseResponse = serde_json::from_value(response.result.unwrap_or_default())?;
+
+    match response.diagnosis {
+        Some(diagnosis) => {
+            println!("murmur: {diagnosis}");
+            if let Some(fix) = response.fix {
+                println!("  try: {fix}");
             }
         }
-        Err(e) => {
-            println!("Failed to get status: {e}");
+        None => {
+            if let Some(reason) = response.skipped {
+                eprintln!("Not diagnosed: {reason}");
+            }
         }
     }
+    Ok(())
+}
 
+async fn cmd_history_record(update: ContextUpdateRequest) -> Result<()> {
+    let config = Config::load().unwrap_or_default();
+    let params = serde_json::to_value(&update)?;
+    let response = send_request(
+        &config.daemon.socket_path,
+        methods::CONTEXT_UPDATE,
+        Some(params),
+    )
+    .await?;
+    if let Some(err) = response.error {
+        anyhow::bail!("Recording failed: {}", err.message);
+    }
     Ok(())
 }
 
-fn cmd_setup(shell: &str) -> Result<()> {
-    match shell {
-        "zsh" => {
-            // Output the zsh integration script
-            let script = include_str!("../../../shell-integration/zsh/murmur.zsh");
-            println!("{script}");
+async fn cmd_history_ask(question: String, json: bool, limit: Option<usize>) -> Result<()> {
+    let config = Config::load().unwrap_or_default();
+    let request = HistoryAskRequest {
+        question,
+        cwd: std::env::current_dir()
+            .ok()
+            .map(|p| p.display().to_string()),
+        limit,
+    };
+
+    // Without a daemon, read the state database directly
+    let response: HistoryAskResponse = if is_daemon_running() {
+        let params = serde_json::to_value(&request)?;
+        match send_request(
+            &config.daemon.socket_path,
+            methods::HISTORY_ASK,
+            Some(params),
+        )
+        .await
+        {
+            Ok(response) => {
+                if let Some(err) = response.error {
+                    anyhow::bail!("History question failed: {}", err.message);
+                }
+                serde_json::from_value(response.result.unwrap_or_default())?
+            }
+            Err(_) => murmur_daemon::handler::ask_direct(&config, &request)
+                .await
+                .map_err(anyhow::Error::msg)?,
         }
-        "bash" => {
-            let script = include_str!("../../../shell-integration/bash/murmur.bash");
-            println!("{script}");
+    } else {
+        murmur_daemon::handler::ask_direct(&config, &request)
+            .await
+            .map_err(anyhow::Error::msg)?
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&response)?);
+    } else {
+        let home = std::env::var("HOME").unwrap_or_default();
+        println!("{}", history::render_ask(&response, &home));
+    }
+    Ok(())
+}
+
+/// Entries sent per `history/import` request, keeping each message small.
+const IMPORT_BATCH: usize = 2000;
+
+async fn cmd_history_import_shell(source: Option<String>, path: Option<String>) -> Result<()> {
+    let sources: Vec<(HistorySource, std::path::PathBuf)> = match source {
+        Some(name) => {
+            let source = HistorySource::from_name(&name)?;
+            let path = path.map_or_else(|| source.default_path(), Into::into);
+            vec![(source, path)]
         }
-        "fish" => {
-            let script = include_str!("../../../shell-integration/fish/murmur.fish");
-            println!("{script}");
+        None => HistorySource::ALL
+            .into_iter()
+            .map(|s| (s, s.default_path()))
+            .filter(|(_, path)| path.exists())
+            .collect(),
+    };
+    if sources.is_empty() {
+        println!("No shell history found (looked for zsh, bash, fish and Atuin).");
+        return Ok(());
+    }
+
+    let config = Config::load().unwrap_or_default();
+    for (source, path) in sources {
+        let history = import::read_history(source, &path)?;
+        let read = history.len();
+        let imported = import_entries(&config, history.dated, false).await?
+            + import_entries(&config, history.undated, true).await?;
+        println!(
+            "{}: imported {imported} of {read} commands from {} ({} already present)",
+            source.name(),
+            path.display(),
+            read - imported
+        );
+    }
+    Ok(())
+}
+
+/// Import through the running daemon so its in-memory history sees the
+/// entries; otherwise write straight to the state database.
+async fn import_entries(
+    config: &Config,
+    entries: Vec<HistoryEntry>,
+    undated: bool,
+) -> Result<usize> {
+    if entries.is_empty() {
+        return Ok(0);
+    }
+    if is_daemon_running() {
+        let mut imported = 0;
+        // Undated imports keep the newest of repeated commands, so send the
+        // newest batch first
+        let batches: Vec<&[HistoryEntry]> = if undated {
+            entries.rchunks(IMPORT_BATCH).collect()
+        } else {
+            entries.chunks(IMPORT_BATCH).collect()
+        };
+        for batch in batches {
+            let params = serde_json::to_value(HistoryImportRequest {
+                entries: batch.to_vec(),
+                undated,
+            })?;
+            let response = send_request(
+                &config.daemon.socket_path,
+                methods::HISTORY_IMPORT,
+                Some(params),
+            )
+            .await
+            .context("Failed to reach the daemon")?;
+            if let Some(err) = response.error {
+                anyhow::bail!("Import failed: {}", err.message);
+            }
+            let result: HistoryImportResponse =
+                serde_json::from_value(response.result.unwrap_or_default())?;
+            imported += result.imported;
         }
-        other => {
-            anyhow::bail!("Unsupported shell: {other}. Supported: zsh, bash, fish");
+        return Ok(imported);
+    }
+
+    let path = server::state_path(config).context(
+        "History persistence is disabled (daemon.state_path is empty); nothing to import into",
+    )?;
+    let mut store = Store::open(Path::new(&path), &SystemClock)?;
+    Ok(store.import_history(&entries, undated)?)
+}
+
+async fn cmd_tunnel(
+    destination: &str,
+    local_port: Option<u16>,
+    remote_socket: Option<String>,
+    ensure: bool,
+) -> Result<()> {
+    let local_port = match local_port {
+        Some(port) => port,
+        None => match std::env::var("MURMUR_REMOTE") {
+            Ok(remote) if !remote.is_empty() => remote::port_of(&remote)?,
+            _ => remote::DEFAULT_TUNNEL_PORT,
+        },
+    };
+    if ensure {
+        remote::ensure_tunnel(destination, local_port, remote_socket.as_deref()).await
+    } else {
+        let remote_socket = match remote_socket {
+            Some(path) => path,
+            None => remote::remote_socket_path(destination).await?,
+        };
+        remote::run_tunnel(destination, local_port, &remote_socket).await
+    }
+}
+
+async fn cmd_debug_bundle(
+    output: Option<String>,
+    traces: usize,
+    config_path: Option<String>,
+) -> Result<()> {
+    let config_path = config_path.map_or_else(Config::config_path, PathBuf::from);
+    let config = if config_path.exists() {
+        Config::load_from(&config_path).unwrap_or_default()
+    } else {
+        Config::default()
+    };
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs();
+    let name = format!("murmur-debug-{now}");
+    let mut bundle = Bundle::new();
+
+    match std::fs::read_to_string(&config_path) {
+        Ok(text) => bundle.add("config.toml", bundle::redact_config(&text)),
+        Err(e) => bundle.add("config.toml", format!("# {}: {e}\n", config_path.display())),
+    }
+
+    let running = is_daemon_running();
+    let params = serde_json::to_value(DebugBundleRequest { traces })?;
+    let daemon = if running {
+        send_request(
+            &config.daemon.socket_path,
+            methods::DEBUG_BUNDLE,
+            Some(params),
+        )
+        .await
+        .and_then(|response| match (response.result, response.error) {
+            (Some(result), _) => Ok(serde_json::from_value::<DebugBundle>(result)?),
+            (None, Some(e)) => anyhow::bail!("{} ({})", e.message, e.code),
+            (None, None) => anyhow::bail!("empty response"),
+        })
+    } else {
+        Err(anyhow::anyhow!("not running"))
+    };
+    match daemon {
+        Ok(daemon) => {
+            bundle.add(
+                "status.json",
+                serde_json::to_string_pretty(&daemon.status)? + "\n",
+            );
+            bundle.add("logs.txt", daemon.logs.join("\n") + "\n");
+            let traces: Vec<String> = daemon
+                .traces
+                .iter()
+                .map(serde_json::to_string)
+                .collect::<Result<_, _>>()?;
+            bundle.add("traces.jsonl", traces.join("\n") + "\n");
         }
+        Err(e) => bundle.add("daemon.txt", format!("Daemon unavailable: {e:#}\n")),
+    }
+    bundle.add(
+        "environment.txt",
+        bundle::environment(&config_path, &config.daemon.socket_path, running),
+    );
+
+    let path = PathBuf::from(output.unwrap_or_else(|| format!("{name}.tar.gz")));
+    bundle.write(&path, &name, now)?;
+    println!("Wrote {}:", path.display());
+    for file in bundle.names() {
+        println!("  {file}");
     }
+    println!("Keys are redacted and nothing you typed or said is included, but look it over before sharing.");
     Ok(())
 }
 
@@ -220,12 +2098,28 @@ async fn cmd_doctor() -> Result<()> {
                             println!("[SKIP] Provider '{name}': disabled");
                             continue;
                         }
-                        if provider_cfg.api_key.is_none() && name != "ollama" {
+                        // Ollama, self-hosted OpenAI-compatible endpoints and
+                        // plugins need no key
+                        let keyless = name == "ollama"
+                            || (name == "openai" && provider_cfg.endpoint.is_some())
+                            || provider_cfg.command.is_some();
+                        if provider_cfg.api_key.is_none() && !keyless {
                             println!("[WARN] Provider '{name}': no api_key set");
                             all_ok = false;
                         } else {
                             println!("[OK] Provider '{name}': configured");
                         }
+                        if provider_cfg.proxy.is_some() {
+                            match murmur_providers::check_reachable(name, provider_cfg).await {
+                                Ok(status) => println!(
+                                    "[OK] Provider '{name}': reachable through proxy (HTTP {status})"
+                                ),
+                                Err(e) => {
+                                    println!("[FAIL] Provider '{name}': unreachable through proxy: {e}");
+                                    all_ok = false;
+                                }
+                            }
+                        }
                     }
                 }
             }
@@ -268,6 +2162,29 @@ async fn cmd_doctor() -> Result<()> {
                     println!("[OK] Active providers: {}", active.join(", "));
                 }
 
+                // Results of the daemon's periodic health checks
+                if let Some(health) = result["provider_health"].as_object() {
+                    let now = std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .map(|d| d.as_secs())
+                        .unwrap_or(0);
+                    for (name, check) in health {
+                        let ago = now.saturating_sub(check["checked_at"].as_u64().unwrap_or(0));
+                        let latency = check["latency_ms"].as_u64().unwrap_or(0);
+                        if check["ok"].as_bool().unwrap_or(false) {
+                            println!(
+                                "[OK] Provider '{name}': healthy ({latency} ms, checked {ago}s ago)"
+                            );
+                        } else {
+                            let error = check["error"].as_str().unwrap_or("unknown error");
+                            println!(
+                                "[FAIL] Provider '{name}': health check failed {ago}s ago: {error}"
+                            );
+                            all_ok = false;
+                        }
+                    }
+                }
+
                 let cache = result["cache_entries"].as_u64().unwrap_or(0);
                 println!("[INFO] Cache entries: {cache}");
             }
@@ -308,9 +2225,7 @@ async fn cmd_doctor() -> Result<()> {
     println!();
 
     // 4. Check socket connectivity
-    let socket_path = Config::load()
-        .map(|c| c.daemon.socket_path)
-        .unwrap_or_else(|_| "/tmp/murmur.sock".to_string());
+    let socket_path = Config::load().unwrap_or_default().daemon.socket_path;
     if std::path::Path::new(&socket_path).exists() {
         println!("[OK] Socket exists: {socket_path}");
     } else {
@@ -329,6 +2244,108 @@ async fn cmd_doctor() -> Result<()> {
     Ok(())
 }
 
+async fn cmd_repl(config_path: Option<String>, shell: String) -> Result<()> {
+    let cwd = std::env::current_dir()?.display().to_string();
+    let mut session = repl::Session::new(config_path.map(Into::into), cwd, shell).await?;
+    println!("{}\n", repl::HELP);
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut stdout = tokio::io::stdout();
+    loop {
+        stdout.write_all(b"murmur> ").await?;
+        stdout.flush().await?;
+        let Some(line) = lines.next_line().await? else {
+            break;
+        };
+        match session.handle(&line).await {
+            Some(output) if output.is_empty() => {}
+            Some(output) => println!("{}", output.trim_end()),
+            None => break,
+        }
+    }
+    Ok(())
+}
+
+async fn cmd_transcribe(
+    patterns: Vec<String>,
+    format: TranscribeFormat,
+    restructure: bool,
+    speakers: bool,
+    out_dir: Option<String>,
+    config_path: Option<String>,
+) -> Result<()> {
+    let config = match config_path {
+        Some(ref path) => Config::load_from(Path::new(path))?,
+        None => Config::load().unwrap_or_default(),
+    };
+    let files = transcribe::expand(&patterns)?;
+    let voice = murmur_daemon::handler::voice_engine(&config);
+    if restructure && voice.restructurer().is_none() {
+        anyhow::bail!(
+            "--restructure needs a voice restructurer; set restructurer in [voice] config"
+        );
+    }
+    if let Some(ref dir) = out_dir {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {dir}"))?;
+    }
+    let cwd = std::env::current_dir()?.display().to_string();
+
+    let mut failed = 0;
+    for file in &files {
+        let name = file.display().to_string();
+        let result = async {
+            let audio = std::fs::read(file)
+                .with_context(|| format!("Failed to read audio file: {name}"))?;
+            let segments = if speakers {
+                voice.transcribe_speakers(&audio).await?
+            } else {
+                voice.transcribe_recording(&audio).await?
+            };
+            let restructured = if restructure && !format.is_subtitles() {
+                let text = transcribe::text(&segments);
+                Some(
+                    voice
+                        .restructure(&text, &VoiceMode::Natural, &cwd, None)
+                        .await?,
+                )
+            } else {
+                None
+            };
+            anyhow::Ok(transcribe::render(
+                &name,
+                &segments,
+                restructured.as_deref(),
+                format,
+            ))
+        }
+        .await;
+
+        match (result, &out_dir) {
+            (Ok(output), Some(dir)) => {
+                let stem = file.file_stem().unwrap_or(file.as_os_str());
+                let path = Path::new(dir).join(stem).with_extension(format.extension());
+                std::fs::write(&path, output)
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+                eprintln!("{name} -> {}", path.display());
+            }
+            (Ok(output), None) => {
+                if files.len() > 1 && format != TranscribeFormat::Json {
+                    println!("==> {name} <==");
+                }
+                print!("{output}");
+            }
+            (Err(e), _) => {
+                eprintln!("{name}: {e:#}");
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        anyhow::bail!("{failed} of {} files failed", files.len());
+    }
+    Ok(())
+}
+
 async fn cmd_voice_test(file: Option<String>, mode: String) -> Result<()> {
     let voice_mode = match mode.as_str() {
         "command" => VoiceMode::Command,
@@ -356,16 +2373,11 @@ async fn cmd_voice_test(file: Option<String>, mode: String) -> Result<()> {
         }
     };
 
-    // Base64 encode the audio
-    use base64::Engine;
-    let encoded = base64::engine::general_purpose::STANDARD.encode(&audio_data);
-
     let cwd = std::env::current_dir()
         .map(|p| p.to_string_lossy().to_string())
         .unwrap_or_else(|_| ".".to_string());
 
     let params = serde_json::json!({
-        "audio_data": encoded,
         "mode": voice_mode,
         "cwd": cwd,
         "shell": std::env::var("SHELL").ok(),
@@ -374,13 +2386,7 @@ async fn cmd_voice_test(file: Option<String>, mode: String) -> Result<()> {
     println!("Sending audio to daemon for processing (mode: {mode})...");
 
     let config = Config::load().unwrap_or_default();
-    match send_request(
-        &config.daemon.socket_path,
-        methods::VOICE_PROCESS,
-        Some(params),
-    )
-    .await
-    {
+    match send_voice_process(&config.daemon.socket_path, params, &audio_data).await {
         Ok(response) => {
             if let Some(result) = response.result {
                 println!("\nVoice Processing Result:");
@@ -408,6 +2414,42 @@ async fn cmd_voice_test(file: Option<String>, mode: String) -> Result<()> {
                     "  Latency:    {}ms",
                     result["latency_ms"].as_u64().unwrap_or(0)
                 );
+                if let Some(candidates) = result["stt_candidates"].as_array() {
+                    println!("  Heard:");
+                    for candidate in candidates {
+                        let engine = candidate["engine"].as_str().unwrap_or("unknown");
+                        match candidate["error"].as_str() {
+                            Some(error) => println!("    {engine:<10} failed: {error}"),
+                            None => println!(
+                                "    {engine:<10} {:>5.1}% sure, {:>5.1}% agreed  {}",
+                                candidate["confidence"].as_f64().unwrap_or(0.0) * 100.0,
+                                candidate["agreement"].as_f64().unwrap_or(0.0) * 100.0,
+                                candidate["transcript"].as_str().unwrap_or("")
+                            ),
+                        }
+                    }
+                }
+                if let Some(risk) = result["risk"].as_str() {
+                    println!("  Risk:       {risk}");
+                }
+                if let Some(sensitive) = result["sensitive"].as_array() {
+                    let categories: Vec<&str> =
+                        sensitive.iter().filter_map(|c| c.as_str()).collect();
+                    println!("  Sensitive:  {}", categories.join(", "));
+                }
+                if result["execute"].as_bool() == Some(true) {
+                    println!("  Execute:    yes (auto-execute)");
+                }
+                if let Some(confirmation) = result.get("confirmation") {
+                    println!(
+                        "\n  {}",
+                        confirmation["prompt"].as_str().unwrap_or("Confirm?")
+                    );
+                    println!(
+                        "  Answer with: murmur voice confirm --id {} --file reply.wav",
+                        confirmation["id"].as_u64().unwrap_or(0)
+                    );
+                }
             } else if let Some(error) = response.error {
                 println!("Voice processing error: {}", error.message);
             }
@@ -465,48 +2507,279 @@ async fn cmd_voice_status() -> Result<()> {
     Ok(())
 }
 
-/// Send a JSON-RPC request to the daemon and return the response.
+async fn cmd_voice_selftest(
+    corpus: Option<String>,
+    engine: Option<String>,
+    json: bool,
+) -> Result<()> {
+    if !is_daemon_running() {
+        anyhow::bail!("Murmur daemon is not running. Start it with: murmur start");
+    }
+
+    // The daemon reads the recordings, so send it an absolute path
+    let corpus_dir = match corpus {
+        Some(dir) => Some(
+            std::fs::canonicalize(&dir)
+                .with_context(|| format!("Corpus directory not found: {dir}"))?
+                .display()
+                .to_string(),
+        ),
+        None => None,
+    };
+    let params = serde_json::to_value(VoiceSelftestRequest { corpus_dir, engine })?;
+
+    let config = Config::load().unwrap_or_default();
+    let response = send_request(
+        &config.daemon.socket_path,
+        methods::VOICE_SELFTEST,
+        Some(params),
+    )
+    .await?;
+    if let Some(err) = response.error {
+        anyhow::bail!("Voice selftest failed: {}", err.message);
+    }
+    let report: VoiceSelftestReport = serde_json::from_value(response.result.unwrap_or_default())?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        println!(
+            "Voice selftest (restructurer: {})",
+            report.restructurer.as_deref().unwrap_or("none")
+        );
+        for case in &report.cases {
+            let verdict = if case.passed() { "PASS" } else { "FAIL" };
+            println!(
+                "  {verdict}  {:<18} {:<10} {:<11} wer {:.2}  {:>5}ms",
+                case.utterance, case.engine, case.audio, case.word_error_rate, case.latency_ms
+            );
+            println!("        heard: {}", case.transcript);
+            if let Some(ref output) = case.output {
+                println!("        output: {output}");
+            }
+            if let Some(ref error) = case.error {
+                println!("        error: {error}");
+            }
+        }
+        for skipped in &report.skipped {
+            println!("  SKIP  {skipped}");
+        }
+    }
+
+    let failed = report.cases.iter().filter(|c| !c.passed()).count();
+    if report.cases.is_empty() {
+        anyhow::bail!(
+            "No utterances could be tested; pass --corpus with recordings or install say/espeak-ng"
+        );
+    }
+    if failed > 0 {
+        anyhow::bail!("{failed} of {} cases failed", report.cases.len());
+    }
+    if !json {
+        println!("All {} cases passed", report.cases.len());
+    }
+    Ok(())
+}
+
+async fn cmd_voice_confirm(id: u64, file: Option<String>, text: Option<String>) -> Result<()> {
+    if !is_daemon_running() {
+        println!("Murmur daemon is not running. Start it with: murmur start");
+        return Ok(());
+    }
+
+    let audio_data = match file {
+        Some(path) => {
+            let data = std::fs::read(&path)
+                .with_context(|| format!("Failed to read audio file: {path}"))?;
+            use base64::Engine;
+            Some(base64::engine::general_purpose::STANDARD.encode(&data))
+        }
+        None if text.is_none() => anyhow::bail!("Provide --file or --text with the reply"),
+        None => None,
+    };
+    let params = serde_json::json!({
+        "id": id,
+        "audio_data": audio_data,
+        "transcript": text,
+    });
+
+    let config = Config::load().unwrap_or_default();
+    let response = send_request(
+        &config.daemon.socket_path,
+        methods::VOICE_CONFIRM,
+        Some(params),
+    )
+    .await?;
+    if let Some(result) = response.result {
+        let command = result["command"].as_str().unwrap_or("");
+        println!("Heard: {}", result["transcript"].as_str().unwrap_or(""));
+        if result["confirmed"].as_bool() == Some(true) {
+            println!("Confirmed: {command}");
+        } else {
+            println!("Cancelled: {command}");
+        }
+    } else if let Some(error) = response.error {
+        println!("Confirmation error: {}", error.message);
+    }
+    Ok(())
+}
+
+/// Send a JSON-RPC request to the daemon (local socket or `MURMUR_REMOTE`)
+/// and return the response.
+/// Largest compressed response accepted from the daemon.
+const MAX_RESPONSE_BYTES: usize = 32 * 1024 * 1024;
+
 async fn send_request(
     socket_path: &str,
     method: &str,
     params: Option<serde_json::Value>,
 ) -> Result<JsonRpcResponse> {
-    let stream = UnixStream::connect(socket_path).await?;
-    let (reader, mut writer) = stream.into_split();
+    let endpoint = Endpoint::from_env(socket_path);
+    let (reader, mut writer) = endpoint.connect().await?;
+    let mut reader = BufReader::new(reader);
 
     let request = JsonRpcRequest::new(method, params, RequestId::Number(1));
     let json = serde_json::to_string(&request)?;
 
-    writer.write_all(json.as_bytes()).await?;
-    writer.write_all(b"\n").await?;
-    writer.flush().await?;
+    let codec = if endpoint.is_remote() && json.len() >= remote::COMPRESS_MIN_BYTES {
+        remote::negotiate_compression(&mut reader, &mut writer).await?
+    } else {
+        None
+    };
+    if let Some(algorithm) = codec {
+        compression::write_frame(&mut writer, algorithm, &json).await?;
+        let line = compression::read_frame(&mut reader, algorithm, MAX_RESPONSE_BYTES)
+            .await?
+            .context("Daemon closed the connection")?;
+        return Ok(serde_json::from_str(&line)?);
+    }
+
+    remote::exchange(&mut reader, &mut writer, &request).await
+}
 
-    let mut reader = BufReader::new(reader);
-    let mut line = String::new();
-    reader.read_line(&mut line).await?;
+/// Send `voice/process` with the audio as a raw binary frame, skipping
+/// base64. Remote daemons (which compress large requests instead) and
+/// daemons without `initialize` get base64 in the params.
+async fn send_voice_process(
+    socket_path: &str,
+    mut params: serde_json::Value,
+    audio: &[u8],
+) -> Result<JsonRpcResponse> {
+    let endpoint = Endpoint::from_env(socket_path);
+    if !endpoint.is_remote() {
+        let (reader, mut writer) = endpoint.connect().await?;
+        let mut reader = BufReader::new(reader);
+        let initialize = JsonRpcRequest::new(
+            methods::INITIALIZE,
+            Some(serde_json::to_value(InitializeRequest {
+                binary_audio: true,
+                ..Default::default()
+            })?),
+            RequestId::Number(0),
+        );
+        let response = remote::exchange(&mut reader, &mut writer, &initialize).await?;
+        let binary_audio = response
+            .result
+            .and_then(|r| serde_json::from_value::<InitializeResponse>(r).ok())
+            .is_some_and(|r| r.binary_audio);
+        if binary_audio {
+            params["audio_frame"] = true.into();
+            let request =
+                JsonRpcRequest::new(methods::VOICE_PROCESS, Some(params), RequestId::Number(1));
+            writer
+                .write_all(serde_json::to_string(&request)?.as_bytes())
+                .await?;
+            writer.write_all(b"\n").await?;
+            frame::write(&mut writer, audio).await?;
+
+            let mut line = String::new();
+            reader.read_line(&mut line).await?;
+            return Ok(serde_json::from_str(&line)?);
+        }
+    }
+
+    if audio.len() > upload::MAX_CHUNK_BYTES {
+        if let Some(response) = upload_voice(&endpoint, params.clone(), audio).await? {
+            return Ok(response);
+        }
+    }
 
-    let response: JsonRpcResponse = serde_json::from_str(&line)?;
-    Ok(response)
+    use base64::Engine;
+    params["audio_data"] = base64::engine::general_purpose::STANDARD
+        .encode(audio)
+        .into();
+    send_request(socket_path, methods::VOICE_PROCESS, Some(params)).await
 }
 
-fn is_daemon_running() -> bool {
-    let pid_path = Config::pid_path();
-    if !pid_path.exists() {
-        return false;
+/// Send a long recording in chunks over one connection, then process it.
+/// `None` if the daemon predates chunked uploads.
+async fn upload_voice(
+    endpoint: &Endpoint,
+    mut params: serde_json::Value,
+    audio: &[u8],
+) -> Result<Option<JsonRpcResponse>> {
+    use base64::Engine;
+
+    let (reader, mut writer) = endpoint.connect().await?;
+    let mut reader = BufReader::new(reader);
+    let begin = JsonRpcRequest::new(
+        methods::VOICE_UPLOAD_BEGIN,
+        Some(serde_json::to_value(VoiceUploadBeginRequest {
+            total_bytes: audio.len(),
+            crc32: Some(crc32fast::hash(audio)),
+        })?),
+        RequestId::Number(0),
+    );
+    let response = remote::exchange(&mut reader, &mut writer, &begin).await?;
+    if let Some(ref error) = response.error {
+        if error.code == METHOD_NOT_FOUND {
+            return Ok(None);
+        }
+        return Ok(Some(response));
     }
+    let begun: VoiceUploadBeginResponse =
+        serde_json::from_value(response.result.unwrap_or_default())?;
 
-    if let Ok(pid_str) = std::fs::read_to_string(&pid_path) {
-        if let Ok(pid) = pid_str.trim().parse::<i32>() {
-            // Reject non-positive PIDs to avoid signaling process groups
-            if pid <= 0 {
-                return false;
-            }
-            // Check if process is alive
-            unsafe { libc::kill(pid, 0) == 0 }
-        } else {
-            false
+    let mut id = 1;
+    for (i, chunk) in audio.chunks(begun.max_chunk_bytes.max(1)).enumerate() {
+        let request = JsonRpcRequest::new(
+            methods::VOICE_UPLOAD_CHUNK,
+            Some(serde_json::to_value(VoiceUploadChunkRequest {
+                upload_id: begun.upload_id,
+                offset: i * begun.max_chunk_bytes,
+                data: base64::engine::general_purpose::STANDARD.encode(chunk),
+                crc32: Some(crc32fast::hash(chunk)),
+            })?),
+            RequestId::Number(id),
+        );
+        let response = remote::exchange(&mut reader, &mut writer, &request).await?;
+        if response.error.is_some() {
+            return Ok(Some(response));
         }
-    } else {
-        false
+        id += 1;
+    }
+
+    params["upload_id"] = begun.upload_id.into();
+    let end = JsonRpcRequest::new(
+        methods::VOICE_UPLOAD_END,
+        Some(params),
+        RequestId::Number(id),
+    );
+    Ok(Some(
+        remote::exchange(&mut reader, &mut writer, &end).await?,
+    ))
+}
+
+fn is_daemon_running() -> bool {
+    // A remote daemon has no local PID file; let the request itself fail
+    if Endpoint::from_env("").is_remote() {
+        return true;
     }
+
+    daemon_pid().is_some()
+}
+
+/// The local daemon's PID, if its PID file names a live murmur process.
+fn daemon_pid() -> Option<i32> {
+    pidfile::daemon_pid(&Config::pid_path())
 }
@@ -0,0 +1,271 @@
+//! Output formats shared by `murmur complete`, `history list`, `status`
+//! and `usage`.
+//!
+//! Each command hands over its result twice: as the JSON value printed
+//! by `--format json` (the daemon's own response, so its schema is the
+//! protocol's), and as records with named columns for the other formats.
+//! `tsv` prints a header row then one row per record, with tabs, newlines
+//! and backslashes escaped; `plain` aligns the first columns for people;
+//! `zsh-widget` prints one `typeset -a murmur_<column>=(...)` array per
+//! column, ready to `eval` in a widget.
+
+use anyhow::Result;
+use serde_json::Value;
+use std::io::{IsTerminal, Write};
+
+/// `--format` and `--no-color`, for commands that print records.
+#[derive(clap::Args, Debug, Clone)]
+pub struct OutputArgs {
+    /// Output format: plain, json, tsv or zsh-widget
+    #[arg(long, default_value = "plain")]
+    pub format: String,
+    /// Don't color plain output (also off when NO_COLOR is set or stdout
+    /// isn't a terminal)
+    #[arg(long)]
+    pub no_color: bool,
+}
+
+/// How records are printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Plain,
+    Json,
+    Tsv,
+    ZshWidget,
+}
+
+impl OutputFormat {
+    pub fn from_arg(value: &str) -> Result<Self> {
+        match value {
+            "plain" => Ok(Self::Plain),
+            "json" => Ok(Self::Json),
+            "tsv" => Ok(Self::Tsv),
+            "zsh-widget" => Ok(Self::ZshWidget),
+            other => {
+                anyhow::bail!("Unknown format: {other}. Supported: plain, json, tsv, zsh-widget")
+            }
+        }
+    }
+}
+
+/// A command's result as rows of named columns.
+pub struct Records {
+    pub columns: &'static [&'static str],
+    /// How many leading columns `plain` shows.
+    pub plain_columns: usize,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Records {
+    /// `key`/`value` rows for a JSON object, nested objects flattened to
+    /// dotted keys and arrays joined with commas.
+    pub fn key_value(value: &Value) -> Self {
+        let mut rows = Vec::new();
+        flatten(value, "", &mut rows);
+        Self {
+            columns: &["key", "value"],
+            plain_columns: 2,
+            rows,
+        }
+    }
+}
+
+/// The chosen format, and whether plain output is colored.
+pub struct Output {
+    pub format: OutputFormat,
+    color: bool,
+}
+
+impl Output {
+    pub fn from_args(args: &OutputArgs) -> Result<Self> {
+        let color = !args.no_color
+            && std::env::var_os("NO_COLOR").is_none()
+            && std::io::stdout().is_terminal();
+        Ok(Self {
+            format: OutputFormat::from_arg(&args.format)?,
+            color,
+        })
+    }
+
+    /// Print `json` or `records`, whichever the format wants. A reader
+    /// that stops early (`| head`) isn't an error.
+    pub fn print(&self, json: &Value, records: &Records) {
+        let text = self.render(json, records);
+        if !text.is_empty() {
+            let _ = writeln!(std::io::stdout().lock(), "{text}");
+        }
+    }
+
+    pub fn render(&self, json: &Value, records: &Records) -> String {
+        match self.format {
+            OutputFormat::Json => json.to_string(),
+            OutputFormat::Tsv => std::iter::once(records.columns.join("\t"))
+                .chain(records.rows.iter().map(|row| {
+                    row.iter()
+                        .map(|cell| tsv_escape(cell))
+                        .collect::<Vec<_>>()
+                        .join("\t")
+                }))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            OutputFormat::Plain => self.plain(records),
+            OutputFormat::ZshWidget => records
+                .columns
+                .iter()
+                .enumerate()
+                .map(|(i, column)| {
+                    let cells: Vec<String> = records
+                        .rows
+                        .iter()
+                        .map(|row| zsh_quote(row.get(i).map_or("", String::as_str)))
+                        .collect();
+                    format!("typeset -a murmur_{column}=({})", cells.join(" "))
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+
+    /// Columns padded to line up, the first bold and the rest dimmed when
+    /// colored.
+    fn plain(&self, records: &Records) -> String {
+        let shown = records.plain_columns.min(records.columns.len());
+        let widths: Vec<usize> = (0..shown)
+            .map(|i| {
+                records
+                    .rows
+                    .iter()
+                    .map(|row| row.get(i).map_or(0, |c| c.chars().count()))
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        records
+            .rows
+            .iter()
+            .map(|row| {
+                let mut line = String::new();
+                for (i, cell) in row.iter().take(shown).enumerate() {
+                    let last = i + 1 == shown || row[i + 1..shown].iter().all(String::is_empty);
+                    let padded = match last {
+                        true => cell.clone(),
+                        false => format!("{cell:<width$}", width = widths[i]),
+                    };
+                    if i > 0 {
+                        line.push_str("  ");
+                    }
+                    line.push_str(&match (self.color, i) {
+                        (false, _) => padded,
+                        (true, 0) => format!("\x1b[1m{padded}\x1b[0m"),
+                        (true, _) => format!("\x1b[2m{padded}\x1b[0m"),
+                    });
+                    if last {
+                        break;
+                    }
+                }
+                line
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+fn flatten(value: &Value, prefix: &str, rows: &mut Vec<Vec<String>>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                let key = match prefix {
+                    "" => key.clone(),
+                    prefix => format!("{prefix}.{key}"),
+                };
+                flatten(value, &key, rows);
+            }
+        }
+        value => rows.push(vec![prefix.to_string(), cell(value)]),
+    }
+}
+
+/// A JSON value as one cell: strings unquoted, arrays comma-joined, null
+/// empty.
+pub fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        Value::Array(items) => items.iter().map(cell).collect::<Vec<_>>().join(","),
+        other => other.to_string(),
+    }
+}
+
+fn tsv_escape(cell: &str) -> String {
+    cell.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+/// `text` single-quoted for zsh.
+fn zsh_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn output(format: OutputFormat) -> Output {
+        Output {
+            format,
+            color: false,
+        }
+    }
+
+    fn records() -> Records {
+        Records {
+            columns: &["text", "description", "score"],
+            plain_columns: 2,
+            rows: vec![
+                vec!["git status".into(), "Show changes".into(), "0.9".into()],
+                vec!["git commit -m 'a\tb'".into(), String::new(), "0.5".into()],
+            ],
+        }
+    }
+
+    #[test]
+    fn formats_records() {
+        let json = json!({"items": []});
+        assert_eq!(
+            output(OutputFormat::Json).render(&json, &records()),
+            r#"{"items":[]}"#
+        );
+        assert_eq!(
+            output(OutputFormat::Tsv).render(&json, &records()),
+            "text\tdescription\tscore\ngit status\tShow changes\t0.9\ngit commit -m 'a\\tb'\t\t0.5"
+        );
+        assert_eq!(
+            output(OutputFormat::Plain).render(&json, &records()),
+            "git status           Show changes\ngit commit -m 'a\tb'"
+        );
+        let zsh = output(OutputFormat::ZshWidget).render(&json, &records());
+        assert_eq!(
+            zsh.lines().next().unwrap(),
+            r"typeset -a murmur_text=('git status' 'git commit -m '\''a	b'\''')"
+        );
+        assert!(zsh.ends_with("typeset -a murmur_score=('0.9' '0.5')"));
+    }
+
+    #[test]
+    fn flattens_objects_to_keys() {
+        let status = json!({
+            "status": "running",
+            "providers_active": ["ollama", "anthropic"],
+            "provider_latency_ms": {"ollama": 120},
+            "budget_remaining": null,
+        });
+        let rows = Records::key_value(&status).rows;
+        assert!(rows.contains(&vec!["providers_active".into(), "ollama,anthropic".into()]));
+        assert!(rows.contains(&vec!["provider_latency_ms.ollama".into(), "120".into()]));
+        assert!(rows.contains(&vec!["budget_remaining".into(), String::new()]));
+        assert!(OutputFormat::from_arg("yaml").is_err());
+    }
+}
@@ -0,0 +1,120 @@
+//! The daemon's PID file, and telling the daemon from a process that was
+//! given its PID after it crashed.
+
+use std::path::Path;
+
+/// Longest name Linux keeps for a process (`TASK_COMM_LEN` less the NUL).
+const COMM_LEN: usize = 15;
+
+/// The PID in `path` if it names a live murmur process. A file naming
+/// anything else was left by a daemon that died, and is removed so nothing
+/// signals the process that has since been given its PID.
+pub fn daemon_pid(path: &Path) -> Option<i32> {
+    let pid = std::fs::read_to_string(path)
+        .ok()?
+        .trim()
+        .parse::<i32>()
+        .ok()?;
+    // Reject non-positive PIDs to avoid signaling process groups
+    if pid <= 0 {
+        return None;
+    }
+    // SAFETY: signal 0 only checks that the process exists
+    if unsafe { libc::kill(pid, 0) } != 0 {
+        return None;
+    }
+    if !runs_this_program(pid) {
+        let _ = std::fs::remove_file(path);
+        return None;
+    }
+    Some(pid)
+}
+
+/// Whether `pid` runs the same program as this process: the daemon is
+/// `murmur start --foreground`, whether started by the CLI or a service.
+fn runs_this_program(pid: i32) -> bool {
+    let ours = std::env::current_exe()
+        .ok()
+        .and_then(|exe| Some(exe.file_name()?.to_string_lossy().into_owned()));
+    match (ours, process_name(pid)) {
+        (Some(ours), Some(theirs)) => same_name(&ours, &theirs),
+        _ => false,
+    }
+}
+
+/// The name of the program `pid` runs.
+#[cfg(target_os = "linux")]
+fn process_name(pid: i32) -> Option<String> {
+    let comm = std::fs::read_to_string(format!("/proc/{pid}/comm")).ok()?;
+    Some(comm.trim_end_matches('\n').to_string())
+}
+
+/// The name of the program `pid` runs.
+#[cfg(not(target_os = "linux"))]
+fn process_name(pid: i32) -> Option<String> {
+    let output = std::process::Command::new("ps")
+        .args(["-o", "comm=", "-p", &pid.to_string()])
+        .output()
+        .ok()?;
+    let comm = String::from_utf8_lossy(&output.stdout);
+    let comm = comm.trim();
+    // macOS prints the whole path
+    let name = comm.rsplit('/').next()?;
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// Whether `name` is `program`, allowing for Linux cutting names short.
+fn same_name(program: &str, name: &str) -> bool {
+    if program == name {
+        return true;
+    }
+    cfg!(target_os = "linux")
+        && name.len() == COMM_LEN
+        && program.len() > COMM_LEN
+        && program.as_bytes().starts_with(name.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pid_file(name: &str, pid: u32) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("murmur-pid-{name}-{}", std::process::id()));
+        std::fs::write(&path, pid.to_string()).unwrap();
+        path
+    }
+
+    #[test]
+    fn another_program_with_the_pid_is_not_the_daemon() {
+        let mut other = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        let path = pid_file("reused", other.id());
+
+        assert_eq!(daemon_pid(&path), None);
+        // Stale, so removed, and the process is left alone
+        assert!(!path.exists());
+        assert!(other.try_wait().unwrap().is_none());
+
+        other.kill().unwrap();
+        other.wait().unwrap();
+    }
+
+    #[test]
+    fn this_program_is_recognized() {
+        let path = pid_file("ours", std::process::id());
+        assert_eq!(daemon_pid(&path), Some(std::process::id() as i32));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn truncated_names_match() {
+        assert!(same_name("murmur", "murmur"));
+        assert!(!same_name("murmur", "sleep"));
+        assert_eq!(
+            same_name("murmur-0123456789abcdef", "murmur-01234567"),
+            cfg!(target_os = "linux")
+        );
+    }
+}
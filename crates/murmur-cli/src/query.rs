@@ -0,0 +1,78 @@
+//! Output formats for `murmur query`, the launcher (Raycast/Alfred) entry point.
+
+use murmur_protocol::QueryResponse;
+use serde_json::json;
+
+/// How `murmur query` prints its result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryFormat {
+    /// Best command only, ready to paste or pipe to the clipboard.
+    Text,
+    /// The full `QueryResponse`.
+    Json,
+    /// Alfred script filter items.
+    Alfred,
+}
+
+/// Render a response in `format`.
+pub fn render(response: &QueryResponse, format: QueryFormat) -> String {
+    match format {
+        QueryFormat::Text => response
+            .items
+            .first()
+            .map(|item| item.command.clone())
+            .unwrap_or_default(),
+        QueryFormat::Json => serde_json::to_string(response).unwrap_or_default(),
+        QueryFormat::Alfred => {
+            let items: Vec<_> = response
+                .items
+                .iter()
+                .map(|item| {
+                    json!({
+                        "title": item.command,
+                        "subtitle": item.description.as_deref().unwrap_or(""),
+                        "arg": item.command,
+                        "text": { "copy": item.command },
+                    })
+                })
+                .collect();
+            json!({ "items": items }).to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use murmur_protocol::QueryItem;
+
+    fn response() -> QueryResponse {
+        QueryResponse {
+            items: vec![
+                QueryItem {
+                    command: "lsof -i :8080".to_string(),
+                    description: Some("Process on port 8080".to_string()),
+                },
+                QueryItem {
+                    command: "ss -ltnp".to_string(),
+                    description: None,
+                },
+            ],
+            provider: "ollama".to_string(),
+            latency_ms: 5,
+        }
+    }
+
+    #[test]
+    fn text_prints_best_command() {
+        assert_eq!(render(&response(), QueryFormat::Text), "lsof -i :8080");
+    }
+
+    #[test]
+    fn alfred_items_carry_command_as_arg() {
+        let out: serde_json::Value =
+            serde_json::from_str(&render(&response(), QueryFormat::Alfred)).unwrap();
+        assert_eq!(out["items"][0]["arg"], "lsof -i :8080");
+        assert_eq!(out["items"][1]["subtitle"], "");
+    }
+}
@@ -0,0 +1,293 @@
+//! Remote daemon support: `MURMUR_REMOTE=host:port` and SSH tunnels.
+//!
+//! A thin client can talk to a daemon on a dev box by forwarding a local TCP
+//! port to the remote Unix socket over SSH (`murmur tunnel user@devbox`) and
+//! pointing `MURMUR_REMOTE` at it. With `MURMUR_REMOTE_SSH=user@devbox`, the
+//! tunnel is started on demand when the port isn't reachable.
+//!
+//! A daemon's own `listen_tcp` or `listen_ws` listener may require an API
+//! token (`murmur token create`); `MURMUR_TOKEN` is sent for it when set.
+
+use anyhow::{Context, Result};
+use murmur_daemon::compression;
+use murmur_protocol::{
+    methods, Compression, CompressionRequest, CompressionResponse, InitializeRequest,
+    JsonRpcRequest, JsonRpcResponse, RequestId,
+};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpStream, UnixStream};
+
+/// Local port used by `murmur tunnel` when none is given.
+pub const DEFAULT_TUNNEL_PORT: u16 = 7878;
+
+/// How long to wait for a freshly started tunnel to accept connections.
+const TUNNEL_READY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Requests to a remote daemon at least this large (voice audio, big
+/// contexts) are sent compressed. Smaller ones aren't worth the extra
+/// round trip.
+pub const COMPRESS_MIN_BYTES: usize = 16 * 1024;
+
+pub type Reader = Box<dyn AsyncRead + Unpin + Send>;
+pub type Writer = Box<dyn AsyncWrite + Unpin + Send>;
+
+/// Where the daemon is reachable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    /// Local Unix socket path.
+    Unix(String),
+    /// `host:port` of a remote daemon or SSH tunnel.
+    Tcp(String),
+}
+
+impl Endpoint {
+    /// `MURMUR_REMOTE` if set, otherwise the local socket.
+    pub fn from_env(socket_path: &str) -> Self {
+        match std::env::var("MURMUR_REMOTE") {
+            Ok(remote) if !remote.trim().is_empty() => Self::Tcp(remote.trim().to_string()),
+            _ => Self::Unix(socket_path.to_string()),
+        }
+    }
+
+    pub fn is_remote(&self) -> bool {
+        matches!(self, Self::Tcp(_))
+    }
+
+    /// Connect and split into reader/writer halves. For TCP endpoints, a
+    /// failed connection triggers the SSH tunnel from `MURMUR_REMOTE_SSH`.
+    pub async fn connect(&self) -> Result<(Reader, Writer)> {
+        match self {
+            Self::Unix(path) => {
+                let (r, w) = UnixStream::connect(path)
+                    .await
+                    .with_context(|| format!("Failed to connect to {path}"))?
+                    .into_split();
+                Ok((Box::new(r), Box::new(w)))
+            }
+            Self::Tcp(addr) => {
+                let stream = match TcpStream::connect(addr).await {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        let Some(destination) = ssh_destination() else {
+                            return Err(e).with_context(|| format!("Failed to connect to {addr}"));
+                        };
+                        let port = port_of(addr)?;
+                        ensure_tunnel(&destination, port, None).await?;
+                        TcpStream::connect(addr)
+                            .await
+                            .with_context(|| format!("Failed to connect to {addr} via tunnel"))?
+                    }
+                };
+                let (r, w) = stream.into_split();
+                match token() {
+                    Some(token) => authenticate(Box::new(r), Box::new(w), token).await,
+                    None => Ok((Box::new(r), Box::new(w))),
+                }
+            }
+        }
+    }
+}
+
+/// `MURMUR_TOKEN`, the API token for a remote daemon's listener.
+pub fn token() -> Option<String> {
+    std::env::var("MURMUR_TOKEN")
+        .ok()
+        .map(|token| token.trim().to_string())
+        .filter(|token| !token.is_empty())
+}
+
+/// Present `token` in `initialize` before anything else on the connection.
+async fn authenticate(
+    reader: Reader,
+    mut writer: Writer,
+    token: String,
+) -> Result<(Reader, Writer)> {
+    let mut reader = BufReader::new(reader);
+    let params = InitializeRequest {
+        token: Some(token),
+        ..Default::default()
+    };
+    let request = JsonRpcRequest::new(
+        methods::INITIALIZE,
+        Some(serde_json::to_value(&params)?),
+        RequestId::Number(0),
+    );
+    let response = exchange(&mut reader, &mut writer, &request).await?;
+    if let Some(error) = response.error {
+        anyhow::bail!("The daemon refused MURMUR_TOKEN: {}", error.message);
+    }
+    // Anything already buffered stays readable
+    Ok((Box::new(reader), writer))
+}
+
+/// Send one request as a JSON line on an open connection and read the
+/// response line.
+pub async fn exchange(
+    reader: &mut BufReader<Reader>,
+    writer: &mut Writer,
+    request: &JsonRpcRequest,
+) -> Result<JsonRpcResponse> {
+    writer
+        .write_all(serde_json::to_string(request)?.as_bytes())
+        .await?;
+    writer.write_all(b"\n").await?;
+    writer.flush().await?;
+
+    let mut line = String::new();
+    reader.read_line(&mut line).await?;
+    Ok(serde_json::from_str(&line)?)
+}
+
+/// Ask the daemon to compress the rest of this connection. `None` if it
+/// declined, including daemons too old to know the method.
+pub async fn negotiate_compression(
+    reader: &mut BufReader<Reader>,
+    writer: &mut Writer,
+) -> Result<Option<Compression>> {
+    let params = CompressionRequest {
+        algorithms: compression::SUPPORTED.to_vec(),
+    };
+    let request = JsonRpcRequest::new(
+        methods::COMPRESSION_NEGOTIATE,
+        Some(serde_json::to_value(&params)?),
+        RequestId::Number(0),
+    );
+    let response = exchange(reader, writer, &request).await?;
+    Ok(response
+        .result
+        .and_then(|r| serde_json::from_value::<CompressionResponse>(r).ok())
+        .and_then(|r| r.algorithm))
+}
+
+/// `MURMUR_REMOTE_SSH`, the SSH destination for automatic tunnels.
+pub fn ssh_destination() -> Option<String> {
+    std::env::var("MURMUR_REMOTE_SSH")
+        .ok()
+        .filter(|d| !d.trim().is_empty())
+}
+
+/// Port part of a `host:port` address.
+pub fn port_of(addr: &str) -> Result<u16> {
+    let (_, port) = addr
+        .rsplit_once(':')
+        .with_context(|| format!("Expected host:port, got '{addr}'"))?;
+    port.parse()
+        .with_context(|| format!("Invalid port in '{addr}'"))
+}
+
+/// Arguments for an `ssh` process forwarding `127.0.0.1:local_port` to the
+/// daemon socket on the remote host.
+pub fn tunnel_args(
+    destination: &str,
+    local_port: u16,
+    remote_socket: &str,
+    background: bool,
+) -> Vec<String> {
+    let mut args = vec![
+        "-N".to_string(),
+        "-o".to_string(),
+        "ExitOnForwardFailure=yes".to_string(),
+        "-o".to_string(),
+        "ServerAliveInterval=30".to_string(),
+        "-L".to_string(),
+        format!("127.0.0.1:{local_port}:{remote_socket}"),
+    ];
+    if background {
+        // -f backgrounds ssh only once the forward is established
+        args.insert(0, "-f".to_string());
+    }
+    args.push(destination.to_string());
+    args
+}
+
+/// The daemon socket path on `destination`, from `murmur socket-path`
+/// there, since it depends on the remote user's config and runtime dir.
+pub async fn remote_socket_path(destination: &str) -> Result<String> {
+    let output = tokio::process::Command::new("ssh")
+        .args(["-o", "BatchMode=yes", destination, "murmur", "socket-path"])
+        .stdin(std::process::Stdio::null())
+        .output()
+        .await
+        .context("Failed to run ssh")?;
+    let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() || path.is_empty() {
+        anyhow::bail!(
+            "Couldn't ask murmur on {destination} for its socket path; pass --remote-socket"
+        );
+    }
+    Ok(path)
+}
+
+/// Run a tunnel in the foreground until ssh exits.
+pub async fn run_tunnel(destination: &str, local_port: u16, remote_socket: &str) -> Result<()> {
+    println!("Forwarding 127.0.0.1:{local_port} → {destination}:{remote_socket}");
+    println!("Use it with: export MURMUR_REMOTE=127.0.0.1:{local_port}");
+    let status = tokio::process::Command::new("ssh")
+        .args(tunnel_args(destination, local_port, remote_socket, false))
+        .status()
+        .await
+        .context("Failed to run ssh")?;
+    if !status.success() {
+        anyhow::bail!("ssh exited with {status}");
+    }
+    Ok(())
+}
+
+/// Start a background tunnel unless `127.0.0.1:local_port` already accepts
+/// connections. Returns once the port is reachable.
+pub async fn ensure_tunnel(
+    destination: &str,
+    local_port: u16,
+    remote_socket: Option<&str>,
+) -> Result<()> {
+    let addr = format!("127.0.0.1:{local_port}");
+    if TcpStream::connect(&addr).await.is_ok() {
+        return Ok(());
+    }
+
+    let remote_socket = match remote_socket {
+        Some(path) => path.to_string(),
+        None => remote_socket_path(destination).await?,
+    };
+    let remote_socket = remote_socket.as_str();
+    let status = tokio::process::Command::new("ssh")
+        .args(tunnel_args(destination, local_port, remote_socket, true))
+        .stdin(std::process::Stdio::null())
+        .status()
+        .await
+        .context("Failed to run ssh")?;
+    if !status.success() {
+        anyhow::bail!("ssh tunnel to {destination} failed ({status})");
+    }
+
+    let deadline = tokio::time::Instant::now() + TUNNEL_READY_TIMEOUT;
+    while tokio::time::Instant::now() < deadline {
+        if TcpStream::connect(&addr).await.is_ok() {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    anyhow::bail!("Tunnel started but {addr} is not reachable")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tunnel_forwards_local_port_to_remote_socket() {
+        let args = tunnel_args("dev@box", 7878, "/tmp/murmur.sock", true);
+        assert_eq!(args[0], "-f");
+        assert!(args.contains(&"127.0.0.1:7878:/tmp/murmur.sock".to_string()));
+        assert_eq!(args.last().unwrap(), "dev@box");
+        assert!(!tunnel_args("dev@box", 1, "/s", false).contains(&"-f".to_string()));
+    }
+
+    #[test]
+    fn port_is_parsed_from_address() {
+        assert_eq!(port_of("127.0.0.1:7878").unwrap(), 7878);
+        assert_eq!(port_of("[::1]:9000").unwrap(), 9000);
+        assert!(port_of("devbox").is_err());
+    }
+}
@@ -0,0 +1,367 @@
+//! `murmur service`: keep the daemon running as a systemd user service on
+//! Linux or a launchd agent on macOS, started at login and restarted after
+//! a crash.
+//!
+//! systemd gets a socket unit too: it holds the daemon's socket, so a
+//! restarting daemon loses no connections and a stopped one starts again
+//! on the next one. launchd agents are started at login and kept alive.
+
+use anyhow::{Context, Result};
+use murmur_daemon::config::Config;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The service manager that runs the daemon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Manager {
+    Systemd,
+    Launchd,
+}
+
+impl Manager {
+    /// launchd on macOS, systemd elsewhere.
+    pub fn for_platform() -> Self {
+        if cfg!(target_os = "macos") {
+            Self::Launchd
+        } else {
+            Self::Systemd
+        }
+    }
+
+    /// Fail unless the manager runs this machine's services (containers
+    /// and some distributions boot without systemd).
+    fn check(self) -> Result<()> {
+        if self == Self::Systemd && !Path::new("/run/systemd/system").exists() {
+            anyhow::bail!("systemd isn't running here; start the daemon with `murmur start`");
+        }
+        Ok(())
+    }
+}
+
+/// A file the service is defined by and its contents.
+pub struct ServiceFile {
+    pub path: PathBuf,
+    pub contents: String,
+}
+
+/// `murmur`, or `murmur-<profile>`: systemd unit and log file names.
+fn name() -> String {
+    match Config::profile() {
+        Some(profile) => format!("murmur-{profile}"),
+        None => "murmur".to_string(),
+    }
+}
+
+/// launchd label.
+fn label() -> String {
+    match Config::profile() {
+        Some(profile) => format!("com.murmur.daemon.{profile}"),
+        None => "com.murmur.daemon".to_string(),
+    }
+}
+
+fn home() -> String {
+    std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string())
+}
+
+/// Arguments that start the daemon in the foreground, for the profile in
+/// use.
+fn start_args() -> Vec<String> {
+    let mut args = Vec::new();
+    if let Some(profile) = Config::profile() {
+        args.push("--profile".to_string());
+        args.push(profile);
+    }
+    args.push("start".to_string());
+    args.push("--foreground".to_string());
+    args
+}
+
+/// The files `install` writes for `manager`.
+pub fn files(manager: Manager, exe: &Path) -> Result<Vec<ServiceFile>> {
+    let name = name();
+    match manager {
+        Manager::Systemd => {
+            let dir = std::env::var("XDG_CONFIG_HOME")
+                .ok()
+                .filter(|dir| !dir.is_empty())
+                .unwrap_or_else(|| format!("{}/.config", home()));
+            let dir = PathBuf::from(dir).join("systemd/user");
+            let config = Config::load().context("Failed to load config")?;
+            Ok(vec![
+                ServiceFile {
+                    path: dir.join(format!("{name}.socket")),
+                    contents: systemd_socket(&config.daemon.socket_path),
+                },
+                ServiceFile {
+                    path: dir.join(format!("{name}.service")),
+                    contents: systemd_service(&name, exe),
+                },
+            ])
+        }
+        Manager::Launchd => {
+            let label = label();
+            let log = format!("{}/Library/Logs/{name}.log", home());
+            Ok(vec![ServiceFile {
+                path: PathBuf::from(home()).join(format!("Library/LaunchAgents/{label}.plist")),
+                contents: launchd_plist(&label, exe, &log),
+            }])
+        }
+    }
+}
+
+fn systemd_socket(socket_path: &str) -> String {
+    format!(
+        "[Unit]
+Description=Murmur daemon socket
+
+[Socket]
+ListenStream={socket_path}
+SocketMode=0600
+DirectoryMode=0700
+RemoveOnStop=true
+
+[Install]
+WantedBy=sockets.target
+"
+    )
+}
+
+fn systemd_service(name: &str, exe: &Path) -> String {
+    let mut command = vec![exe.display().to_string()];
+    command.extend(start_args());
+    let command: Vec<String> = command.iter().map(|arg| systemd_quote(arg)).collect();
+    format!(
+        "[Unit]
+Description=Murmur daemon
+Requires={name}.socket
+After={name}.socket
+
+[Service]
+ExecStart={command}
+Restart=on-failure
+RestartSec=2
+
+[Install]
+WantedBy=default.target
+",
+        command = command.join(" ")
+    )
+}
+
+/// Quote an ExecStart word when it needs it; `%` starts a specifier.
+fn systemd_quote(word: &str) -> String {
+    let word = word.replace('%', "%%");
+    if word.contains(|c: char| c.is_whitespace() || c == '"' || c == '\\') {
+        format!("\"{}\"", word.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        word
+    }
+}
+
+fn launchd_plist(label: &str, exe: &Path, log: &str) -> String {
+    let mut arguments = vec![exe.display().to_string()];
+    arguments.extend(start_args());
+    let arguments: String = arguments
+        .iter()
+        .map(|arg| format!("        <string>{}</string>\n", xml_escape(arg)))
+        .collect();
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{label}</string>
+    <key>ProgramArguments</key>
+    <array>
+{arguments}    </array>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <dict>
+        <key>SuccessfulExit</key>
+        <false/>
+    </dict>
+    <key>StandardErrorPath</key>
+    <string>{log}</string>
+</dict>
+</plist>
+"#,
+        log = xml_escape(log)
+    )
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Run a service manager command, failing with its exit status.
+fn run(program: &str, args: &[&str]) -> Result<()> {
+    let status = Command::new(program)
+        .args(args)
+        .status()
+        .with_context(|| format!("Failed to run {program}"))?;
+    if !status.success() {
+        anyhow::bail!("{program} {} exited with {status}", args.join(" "));
+    }
+    Ok(())
+}
+
+/// launchd's domain for this user's agents.
+fn gui_domain() -> String {
+    // SAFETY: getuid has no preconditions and can't fail
+    format!("gui/{}", unsafe { libc::getuid() })
+}
+
+/// Write the service files and start the service now and at every login.
+pub fn install(manager: Manager, print: bool) -> Result<()> {
+    let exe = std::env::current_exe().context("Failed to find the murmur binary")?;
+    let files = files(manager, &exe)?;
+    if print {
+        for file in &files {
+            println!("# {}\n{}", file.path.display(), file.contents);
+        }
+        return Ok(());
+    }
+    manager.check()?;
+    for file in &files {
+        if let Some(dir) = file.path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        std::fs::write(&file.path, &file.contents)
+            .with_context(|| format!("Failed to write {}", file.path.display()))?;
+        println!("Wrote {}", file.path.display());
+    }
+
+    let name = name();
+    match manager {
+        Manager::Systemd => {
+            run("systemctl", &["--user", "daemon-reload"])?;
+            run(
+                "systemctl",
+                &[
+                    "--user",
+                    "enable",
+                    "--now",
+                    &format!("{name}.socket"),
+                    &format!("{name}.service"),
+                ],
+            )?;
+        }
+        Manager::Launchd => {
+            let path = files[0].path.display().to_string();
+            // Replace an agent loaded by an earlier install
+            let _ = Command::new("launchctl")
+                .args(["bootout", &format!("{}/{}", gui_domain(), label())])
+                .stderr(std::process::Stdio::null())
+                .status();
+            run("launchctl", &["bootstrap", &gui_domain(), &path])?;
+        }
+    }
+    println!(
+        "Murmur daemon installed as a service; it starts at login and restarts if it crashes."
+    );
+    println!(
+        "Installed for {}; run `murmur service install` again if the binary moves.",
+        exe.display()
+    );
+    Ok(())
+}
+
+/// Stop the service and remove its files.
+pub fn uninstall(manager: Manager) -> Result<()> {
+    manager.check()?;
+    let exe = std::env::current_exe().unwrap_or_else(|_| PathBuf::from("murmur"));
+    let files = files(manager, &exe)?;
+    let name = name();
+    match manager {
+        Manager::Systemd => {
+            let _ = Command::new("systemctl")
+                .args([
+                    "--user",
+                    "disable",
+                    "--now",
+                    &format!("{name}.service"),
+                    &format!("{name}.socket"),
+                ])
+                .status();
+        }
+        Manager::Launchd => {
+            let _ = Command::new("launchctl")
+                .args(["bootout", &format!("{}/{}", gui_domain(), label())])
+                .status();
+        }
+    }
+    let mut removed = false;
+    for file in &files {
+        if file.path.exists() {
+            std::fs::remove_file(&file.path)
+                .with_context(|| format!("Failed to remove {}", file.path.display()))?;
+            println!("Removed {}", file.path.display());
+            removed = true;
+        }
+    }
+    if manager == Manager::Systemd && removed {
+        run("systemctl", &["--user", "daemon-reload"])?;
+    }
+    if !removed {
+        println!("Murmur service was not installed.");
+    }
+    Ok(())
+}
+
+/// Start the installed service, or restart it if it's already running.
+/// False when no service is installed.
+pub fn start(manager: Manager) -> Result<bool> {
+    let exe = std::env::current_exe().unwrap_or_else(|_| PathBuf::from("murmur"));
+    if !files(manager, &exe)?.iter().all(|file| file.path.exists()) {
+        return Ok(false);
+    }
+    manager.check()?;
+    match manager {
+        Manager::Systemd => run(
+            "systemctl",
+            &["--user", "restart", &format!("{}.service", name())],
+        )?,
+        Manager::Launchd => run(
+            "launchctl",
+            &["kickstart", "-k", &format!("{}/{}", gui_domain(), label())],
+        )?,
+    }
+    Ok(true)
+}
+
+/// Show whether the service is installed and what the manager reports.
+pub fn status(manager: Manager) -> Result<()> {
+    manager.check()?;
+    let exe = std::env::current_exe().unwrap_or_else(|_| PathBuf::from("murmur"));
+    let files = files(manager, &exe)?;
+    if !files.iter().all(|file| file.path.exists()) {
+        println!("Murmur service is not installed (murmur service install).");
+        return Ok(());
+    }
+    for file in &files {
+        println!("Installed: {}", file.path.display());
+    }
+    let name = name();
+    // Both report inactive services with a non-zero exit, which isn't an error here
+    let _ = match manager {
+        Manager::Systemd => Command::new("systemctl")
+            .args([
+                "--user",
+                "--no-pager",
+                "status",
+                &format!("{name}.socket"),
+                &format!("{name}.service"),
+            ])
+            .status(),
+        Manager::Launchd => Command::new("launchctl")
+            .args(["print", &format!("{}/{}", gui_domain(), label())])
+            .status(),
+    };
+    Ok(())
+}
@@ -0,0 +1,179 @@
+//! `murmur statusbar` — renders daemon state for terminal status bars.
+//!
+//! Subscribes to daemon events and re-renders on every change, either as an
+//! iTerm2 user variable (for an "Interpolated String" status bar component),
+//! as the window title (Ghostty and most other terminals), or as plain lines
+//! for tmux and external bars.
+
+use anyhow::Result;
+use base64::Engine;
+use murmur_protocol::{
+    methods, DaemonEvent, DaemonState, JsonRpcNotification, JsonRpcRequest, JsonRpcResponse,
+    RequestId,
+};
+use std::io::Write;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+use crate::remote::Endpoint;
+
+/// Delay before reconnecting after the daemon goes away.
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusFormat {
+    /// OSC 1337 SetUserVar=murmur_status (iTerm2 status bar).
+    Iterm2,
+    /// OSC 2 window title.
+    Title,
+    /// One plain line per update.
+    Plain,
+}
+
+impl StatusFormat {
+    /// Parse a `--format` value. `auto` picks iTerm2 inside iTerm2 and the
+    /// window title elsewhere.
+    pub fn from_arg(value: &str) -> Result<Self> {
+        match value {
+            "auto" => Ok(match std::env::var("TERM_PROGRAM").ok().as_deref() {
+                Some("iTerm.app") => Self::Iterm2,
+                _ => Self::Title,
+            }),
+            "iterm2" => Ok(Self::Iterm2),
+            "title" => Ok(Self::Title),
+            "plain" => Ok(Self::Plain),
+            other => {
+                anyhow::bail!("Unknown format: {other}. Supported: auto, iterm2, title, plain")
+            }
+        }
+    }
+}
+
+/// Short human-readable summary, e.g. `murmur: anthropic | 42 left | rec`.
+/// `None` means the daemon is not reachable.
+pub fn status_text(state: Option<&DaemonState>) -> String {
+    let Some(state) = state else {
+        return "murmur: off".to_string();
+    };
+    let mut parts = vec![state.provider.clone().unwrap_or_else(|| "idle".to_string())];
+    if let Some(remaining) = state.budget_remaining {
+        parts.push(format!("{remaining} left"));
+    }
+    if state.voice_active {
+        parts.push("rec".to_string());
+    }
+    format!("murmur: {}", parts.join(" | "))
+}
+
+/// Wrap the status text in the escape sequence for `format`.
+pub fn render(format: StatusFormat, text: &str) -> String {
+    match format {
+        StatusFormat::Iterm2 => {
+            let encoded = base64::engine::general_purpose::STANDARD.encode(text);
+            format!("\x1b]1337;SetUserVar=murmur_status={encoded}\x07")
+        }
+        StatusFormat::Title => format!("\x1b]2;{text}\x07"),
+        StatusFormat::Plain => format!("{text}\n"),
+    }
+}
+
+/// Follow daemon events forever, reconnecting when the daemon restarts.
+/// Returns once stdout is gone (e.g. the terminal was closed).
+pub async fn run(endpoint: &Endpoint, format: StatusFormat) -> Result<()> {
+    let mut last = String::new();
+    loop {
+        let result = follow(endpoint, format, &mut last).await;
+        if let Err(e) = &result {
+            if e.downcast_ref::<std::io::Error>()
+                .is_some_and(|io| io.kind() == std::io::ErrorKind::BrokenPipe)
+            {
+                return Ok(());
+            }
+        }
+        emit(format, &status_text(None), &mut last)?;
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+async fn follow(endpoint: &Endpoint, format: StatusFormat, last: &mut String) -> Result<()> {
+    let (reader, mut writer) = endpoint.connect().await?;
+
+    let params = serde_json::json!({ "methods": [methods::EVENT] });
+    let request = JsonRpcRequest::new(
+        methods::EVENTS_SUBSCRIBE,
+        Some(params),
+        RequestId::Number(1),
+    );
+    writer
+        .write_all(format!("{}\n", serde_json::to_string(&request)?).as_bytes())
+        .await?;
+    writer.flush().await?;
+
+    let mut lines = BufReader::new(reader).lines();
+    let first = lines
+        .next_line()
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("daemon closed the connection"))?;
+    let response: JsonRpcResponse = serde_json::from_str(&first)?;
+    let mut state: DaemonState = match response.result {
+        Some(result) => serde_json::from_value(result)?,
+        None => anyhow::bail!("events/subscribe not supported by daemon"),
+    };
+    emit(format, &status_text(Some(&state)), last)?;
+
+    while let Some(line) = lines.next_line().await? {
+        let Ok(notification) = serde_json::from_str::<JsonRpcNotification>(&line) else {
+            continue;
+        };
+        if notification.method != methods::EVENT {
+            continue;
+        }
+        if let Some(event) = notification
+            .params
+            .and_then(|p| serde_json::from_value::<DaemonEvent>(p).ok())
+        {
+            state.apply(&event);
+            emit(format, &status_text(Some(&state)), last)?;
+        }
+    }
+    Ok(())
+}
+
+/// Write the rendered status if it changed since the last write.
+fn emit(format: StatusFormat, text: &str, last: &mut String) -> Result<()> {
+    if text == last {
+        return Ok(());
+    }
+    let mut stdout = std::io::stdout().lock();
+    stdout.write_all(render(format, text).as_bytes())?;
+    stdout.flush()?;
+    *last = text.to_string();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_text_includes_budget_and_voice() {
+        let state = DaemonState {
+            provider: Some("anthropic".to_string()),
+            budget_remaining: Some(42),
+            voice_active: true,
+        };
+        assert_eq!(
+            status_text(Some(&state)),
+            "murmur: anthropic | 42 left | rec"
+        );
+        assert_eq!(status_text(Some(&DaemonState::default())), "murmur: idle");
+        assert_eq!(status_text(None), "murmur: off");
+    }
+
+    #[test]
+    fn iterm2_format_sets_user_var() {
+        let out = render(StatusFormat::Iterm2, "murmur: idle");
+        assert!(out.starts_with("\x1b]1337;SetUserVar=murmur_status="));
+        assert!(out.ends_with('\x07'));
+    }
+}
@@ -0,0 +1,113 @@
+//! Terminal-specific rendering layers for the shell integrations.
+//!
+//! The zsh and bash scripts call `_murmur_render` (when defined) to show
+//! multiple suggestions. `murmur setup` appends the implementation that best
+//! fits the terminal it is running in.
+
+use anyhow::Result;
+
+/// Terminal rendering layer appended to the shell integration script.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerminalKind {
+    /// Kitty: overlay window via remote control.
+    Kitty,
+    /// WezTerm: user variable consumed by a Lua picker.
+    WezTerm,
+    /// Any other terminal: styled list using ANSI/OSC sequences.
+    Osc,
+    /// No terminal layer; the shell's plain list is used.
+    Plain,
+}
+
+impl TerminalKind {
+    /// Parse a `--terminal` value. `auto` detects from the environment.
+    pub fn from_arg(value: &str) -> Result<Self> {
+        match value {
+            "auto" => Ok(detect(|key| std::env::var(key).ok())),
+            "kitty" => Ok(Self::Kitty),
+            "wezterm" => Ok(Self::WezTerm),
+            "osc" => Ok(Self::Osc),
+            "none" => Ok(Self::Plain),
+            other => anyhow::bail!(
+                "Unknown terminal: {other}. Supported: auto, kitty, wezterm, osc, none"
+            ),
+        }
+    }
+
+    /// Asset path of the shell snippet defining `_murmur_render` for this
+    /// terminal.
+    pub fn render_script(self) -> Option<&'static str> {
+        match self {
+            Self::Kitty => Some("shell/terminals/kitty.sh"),
+            Self::WezTerm => Some("shell/terminals/wezterm.sh"),
+            Self::Osc => Some("shell/terminals/osc.sh"),
+            Self::Plain => None,
+        }
+    }
+}
+
+/// Asset path of the Lua handler that WezTerm users add to their config.
+pub const WEZTERM_LUA: &str = "shell/terminals/wezterm.lua";
+
+/// Pick a terminal layer from environment variables, primarily `$TERM_PROGRAM`.
+pub fn detect(env: impl Fn(&str) -> Option<String>) -> TerminalKind {
+    // Kitty doesn't set TERM_PROGRAM, but always exports its window ID
+    if env("KITTY_WINDOW_ID").is_some() || env("TERM").as_deref() == Some("xterm-kitty") {
+        return TerminalKind::Kitty;
+    }
+
+    match env("TERM_PROGRAM").as_deref() {
+        Some("WezTerm") => TerminalKind::WezTerm,
+        Some("WarpTerminal" | "iTerm.app" | "ghostty" | "Apple_Terminal" | "vscode") => {
+            TerminalKind::Osc
+        }
+        _ => TerminalKind::Plain,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env_of(vars: &'static [(&'static str, &'static str)]) -> impl Fn(&str) -> Option<String> {
+        move |key| {
+            vars.iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v.to_string())
+        }
+    }
+
+    #[test]
+    fn detects_from_term_program() {
+        assert_eq!(
+            detect(env_of(&[("TERM_PROGRAM", "WezTerm")])),
+            TerminalKind::WezTerm
+        );
+        assert_eq!(
+            detect(env_of(&[("TERM_PROGRAM", "WarpTerminal")])),
+            TerminalKind::Osc
+        );
+        assert_eq!(
+            detect(env_of(&[("TERM_PROGRAM", "tmux")])),
+            TerminalKind::Plain
+        );
+    }
+
+    #[test]
+    fn detects_kitty_without_term_program() {
+        assert_eq!(
+            detect(env_of(&[("KITTY_WINDOW_ID", "1")])),
+            TerminalKind::Kitty
+        );
+        assert_eq!(
+            detect(env_of(&[("TERM", "xterm-kitty")])),
+            TerminalKind::Kitty
+        );
+    }
+
+    #[test]
+    fn unknown_terminal_arg_is_rejected() {
+        assert!(TerminalKind::from_arg("hyper").is_err());
+        assert_eq!(TerminalKind::from_arg("none").unwrap(), TerminalKind::Plain);
+    }
+}
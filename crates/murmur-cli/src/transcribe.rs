@@ -0,0 +1,206 @@
+//! Input and output for `murmur transcribe`, bulk transcription of audio files.
+
+use anyhow::{Context, Result};
+use murmur_voice::{speaker_label, Segment, SubtitleFormat};
+use serde_json::json;
+use std::path::PathBuf;
+
+/// How `murmur transcribe` prints each transcript.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TranscribeFormat {
+    /// Plain text, restructured if asked.
+    Text,
+    /// One JSON object per file: text plus timed segments.
+    Json,
+    /// SubRip subtitles.
+    Srt,
+    /// WebVTT subtitles.
+    Vtt,
+}
+
+impl TranscribeFormat {
+    pub fn parse(format: &str) -> Result<Self> {
+        match format {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            "srt" => Ok(Self::Srt),
+            "vtt" => Ok(Self::Vtt),
+            other => anyhow::bail!("Unknown format: {other}. Use 'text', 'json', 'srt' or 'vtt'."),
+        }
+    }
+
+    /// Extension for transcripts written to `--out-dir`.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Text => "txt",
+            Self::Json => "json",
+            Self::Srt => "srt",
+            Self::Vtt => "vtt",
+        }
+    }
+
+    /// Subtitles keep the words as spoken, so aren't restructured.
+    pub fn is_subtitles(self) -> bool {
+        matches!(self, Self::Srt | Self::Vtt)
+    }
+}
+
+/// Files named by `patterns`, in order. Patterns with glob characters are
+/// expanded (for shells that pass them through quoted); a pattern that
+/// matches nothing is an error rather than silently skipped.
+pub fn expand(patterns: &[String]) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for pattern in patterns {
+        if !pattern.contains(['*', '?', '[']) {
+            files.push(PathBuf::from(pattern));
+            continue;
+        }
+        let matches = glob::glob(pattern)
+            .with_context(|| format!("Invalid pattern: {pattern}"))?
+            .filter_map(Result::ok)
+            .filter(|path| path.is_file())
+            .collect::<Vec<_>>();
+        if matches.is_empty() {
+            anyhow::bail!("No files match {pattern}");
+        }
+        files.extend(matches);
+    }
+    Ok(files)
+}
+
+/// The segments as plain text: joined with spaces, or with speakers one
+/// line per turn, e.g. `Speaker 1: Ready to ship?`.
+pub fn text(segments: &[Segment]) -> String {
+    let mut lines: Vec<(Option<u32>, String)> = Vec::new();
+    for segment in segments {
+        match lines.last_mut() {
+            Some((speaker, line)) if *speaker == segment.speaker => {
+                line.push(' ');
+                line.push_str(&segment.text);
+            }
+            _ => lines.push((segment.speaker, segment.text.clone())),
+        }
+    }
+    lines
+        .into_iter()
+        .map(|(speaker, line)| match speaker {
+            Some(speaker) => format!("{}: {line}", speaker_label(speaker)),
+            None => line,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Render one file's transcript. `restructured` replaces the joined
+/// segments as the text, except in subtitles, which keep the words as
+/// spoken.
+pub fn render(
+    file: &str,
+    segments: &[Segment],
+    restructured: Option<&str>,
+    format: TranscribeFormat,
+) -> String {
+    let text = match restructured {
+        Some(text) => text.to_string(),
+        None => text(segments),
+    };
+    match format {
+        TranscribeFormat::Text => text + "\n",
+        TranscribeFormat::Json => {
+            json!({ "file": file, "text": text, "segments": segments }).to_string() + "\n"
+        }
+        TranscribeFormat::Srt => murmur_voice::subtitles(segments, SubtitleFormat::Srt),
+        TranscribeFormat::Vtt => murmur_voice::subtitles(segments, SubtitleFormat::Vtt),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segments() -> Vec<Segment> {
+        ["so um the deploy", "is done"]
+            .iter()
+            .enumerate()
+            .map(|(i, text)| Segment {
+                start: i as f64 * 5.0,
+                end: i as f64 * 5.0 + 5.0,
+                text: text.to_string(),
+                confidence: 0.9,
+                engine: "deepgram".to_string(),
+                words: Vec::new(),
+                speaker: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn transcripts_render_in_each_format() {
+        let segments = segments();
+        assert_eq!(
+            render("memo.wav", &segments, None, TranscribeFormat::Text),
+            "so um the deploy is done\n"
+        );
+        let restructured = Some("The deploy is done.");
+        assert_eq!(
+            render("memo.wav", &segments, restructured, TranscribeFormat::Text),
+            "The deploy is done.\n"
+        );
+        let json: serde_json::Value = serde_json::from_str(&render(
+            "memo.wav",
+            &segments,
+            restructured,
+            TranscribeFormat::Json,
+        ))
+        .unwrap();
+        assert_eq!(json["text"], "The deploy is done.");
+        assert_eq!(json["segments"][1]["start"], 5.0);
+        assert!(
+            render("memo.wav", &segments, restructured, TranscribeFormat::Srt)
+                .contains("00:00:05,000 --> 00:00:10,000\nis done\n")
+        );
+        assert!(render("memo.wav", &segments, None, TranscribeFormat::Vtt)
+            .starts_with("WEBVTT\n\n00:00:00.000 --> 00:00:05.000\nso um the deploy\n"));
+    }
+
+    #[test]
+    fn speakers_get_a_line_per_turn() {
+        let mut segments = segments();
+        segments.push(Segment {
+            text: "thanks".to_string(),
+            ..segments[1].clone()
+        });
+        for (segment, speaker) in segments.iter_mut().zip([0, 1, 1]) {
+            segment.speaker = Some(speaker);
+        }
+        assert_eq!(
+            render("memo.wav", &segments, None, TranscribeFormat::Text),
+            "Speaker 1: so um the deploy\nSpeaker 2: is done thanks\n"
+        );
+        let json: serde_json::Value =
+            serde_json::from_str(&render("memo.wav", &segments, None, TranscribeFormat::Json))
+                .unwrap();
+        assert_eq!(json["segments"][1]["speaker"], 1);
+    }
+
+    #[test]
+    fn patterns_must_match_something() {
+        let dir = std::env::temp_dir().join(format!("murmur-transcribe-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["b.wav", "a.wav", "notes.txt"] {
+            std::fs::write(dir.join(name), b"").unwrap();
+        }
+        let pattern = format!("{}/*.wav", dir.display());
+        let files = expand(&[pattern, "plain.wav".to_string()]).unwrap();
+        assert_eq!(
+            files,
+            [
+                dir.join("a.wav"),
+                dir.join("b.wav"),
+                PathBuf::from("plain.wav")
+            ]
+        );
+        assert!(expand(&[format!("{}/*.mp3", dir.display())]).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,291 @@
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+/// Container runtime the shell is running under.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ContainerRuntime {
+    Docker,
+    Podman,
+    Kubernetes,
+    Lxc,
+    Other,
+}
+
+/// What is known about the container the shell runs in.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ContainerInfo {
+    pub runtime: ContainerRuntime,
+    /// Whether this is a devcontainer / Codespace rather than a plain container.
+    pub devcontainer: bool,
+    /// Image name, when the runtime exposes it.
+    pub image: Option<String>,
+    /// Distribution `ID` from `/etc/os-release` (e.g. "debian", "alpine").
+    pub distro: Option<String>,
+    /// Mount point of the bind-mounted workspace containing the cwd.
+    pub workspace: Option<String>,
+}
+
+/// Package managers and the distributions that ship them.
+const PACKAGE_MANAGERS: &[(&str, &[&str])] = &[
+    ("apt-get", &["debian", "ubuntu", "linuxmint", "pop"]),
+    ("apt", &["debian", "ubuntu", "linuxmint", "pop"]),
+    ("apk", &["alpine"]),
+    ("dnf", &["fedora", "rhel", "centos", "rocky", "almalinux"]),
+    ("yum", &["rhel", "centos", "amzn"]),
+    ("pacman", &["arch", "manjaro"]),
+    (
+        "zypper",
+        &["opensuse", "opensuse-leap", "opensuse-tumbleweed", "sles"],
+    ),
+    ("brew", &[]),
+];
+
+/// Root filesystems that are not a workspace bind mount.
+const SYSTEM_MOUNTS: &[&str] = &["/", "/proc", "/sys", "/dev", "/etc", "/tmp", "/run"];
+
+impl ContainerInfo {
+    /// The distribution's package manager, if known.
+    pub fn package_manager(&self) -> Option<&'static str> {
+        let distro = self.distro.as_deref()?;
+        PACKAGE_MANAGERS
+            .iter()
+            .find(|(_, distros)| distros.contains(&distro))
+            .map(|(pm, _)| *pm)
+    }
+
+    /// One-line description for provider prompts, e.g. "docker devcontainer,
+    /// distro debian, workspace /workspaces/app; install packages with
+    /// apt-get, Homebrew is not available".
+    pub fn describe(&self) -> String {
+        let kind = if self.devcontainer {
+            "devcontainer"
+        } else {
+            "container"
+        };
+        let mut text = format!("{:?} {kind}", self.runtime).to_lowercase();
+        if let Some(ref image) = self.image {
+            text.push_str(&format!(", image {image}"));
+        }
+        if let Some(ref distro) = self.distro {
+            text.push_str(&format!(", distro {distro}"));
+        }
+        if let Some(ref workspace) = self.workspace {
+            text.push_str(&format!(", workspace {workspace}"));
+        }
+        match self.package_manager() {
+            Some(pm) => text.push_str(&format!(
+                "; install packages with {pm}, Homebrew is not available"
+            )),
+            None => text.push_str("; Homebrew is not available"),
+        }
+        text
+    }
+
+    /// Whether a suggested command uses a package manager that doesn't exist
+    /// in this container (e.g. `brew` or `apk` inside a Debian image).
+    pub fn rejects(&self, command: &str) -> bool {
+        let program = command
+            .split_whitespace()
+            .find(|w| !matches!(*w, "sudo" | "doas"))
+            .unwrap_or("");
+        let Some((_, distros)) = PACKAGE_MANAGERS.iter().find(|(pm, _)| *pm == program) else {
+            return false;
+        };
+        match self.distro.as_deref() {
+            Some(distro) => !distros.contains(&distro),
+            // Unknown distro: only Homebrew is certainly wrong in a container
+            None => program == "brew",
+        }
+    }
+}
+
+/// Detects whether the current process runs inside a container.
+pub struct ContainerDetector;
+
+impl ContainerDetector {
+    /// Detect from the real filesystem and environment.
+    pub fn detect(cwd: &str) -> Option<ContainerInfo> {
+        Self::detect_with(
+            |path| std::fs::read_to_string(path).ok(),
+            |key| std::env::var(key).ok(),
+            cwd,
+        )
+    }
+
+    /// Detect using `read` for files and `env` for variables, so the
+    /// markers can be supplied directly.
+    pub fn detect_with(
+        read: impl Fn(&str) -> Option<String>,
+        env: impl Fn(&str) -> Option<String>,
+        cwd: &str,
+    ) -> Option<ContainerInfo> {
+        let devcontainer = env("REMOTE_CONTAINERS").is_some()
+            || env("DEVCONTAINER").is_some()
+            || env("CODESPACES").as_deref() == Some("true");
+        let containerenv = read("/run/.containerenv");
+
+        let runtime = if containerenv.is_some() || env("container").as_deref() == Some("podman") {
+            ContainerRuntime::Podman
+        } else if read("/.dockerenv").is_some() {
+            ContainerRuntime::Docker
+        } else if env("KUBERNETES_SERVICE_HOST").is_some() {
+            ContainerRuntime::Kubernetes
+        } else if let Some(runtime) = read("/proc/1/cgroup").and_then(|c| runtime_from_cgroup(&c)) {
+            runtime
+        } else if env("container").is_some() || devcontainer {
+            ContainerRuntime::Other
+        } else {
+            return None;
+        };
+
+        let image = containerenv
+            .as_deref()
+            .and_then(|c| containerenv_value(c, "image"))
+            .or_else(|| env("DEVCONTAINER_IMAGE"));
+        let distro = read("/etc/os-release").and_then(|c| os_release_id(&c));
+        let workspace = read("/proc/self/mountinfo").and_then(|m| workspace_mount(&m, cwd));
+
+        debug!(
+            ?runtime,
+            devcontainer,
+            ?image,
+            ?distro,
+            "Running inside a container"
+        );
+        Some(ContainerInfo {
+            runtime,
+            devcontainer,
+            image,
+            distro,
+            workspace,
+        })
+    }
+}
+
+fn runtime_from_cgroup(cgroup: &str) -> Option<ContainerRuntime> {
+    if cgroup.contains("kubepods") {
+        Some(ContainerRuntime::Kubernetes)
+    } else if cgroup.contains("docker") || cgroup.contains("containerd") {
+        Some(ContainerRuntime::Docker)
+    } else if cgroup.contains("libpod") {
+        Some(ContainerRuntime::Podman)
+    } else if cgroup.contains("/lxc") {
+        Some(ContainerRuntime::Lxc)
+    } else {
+        None
+    }
+}
+
+/// `key="value"` from podman's `/run/.containerenv`.
+fn containerenv_value(contents: &str, key: &str) -> Option<String> {
+    contents.lines().find_map(|line| {
+        let (k, v) = line.split_once('=')?;
+        (k.trim() == key)
+            .then(|| v.trim().trim_matches('"').to_string())
+            .filter(|v| !v.is_empty())
+    })
+}
+
+fn os_release_id(contents: &str) -> Option<String> {
+    containerenv_value(contents, "ID").map(|id| id.to_lowercase())
+}
+
+/// The deepest non-system mount point in `/proc/self/mountinfo` that
+/// contains `cwd` — in a devcontainer, the bind-mounted project folder.
+fn workspace_mount(mountinfo: &str, cwd: &str) -> Option<String> {
+    mountinfo
+        .lines()
+        // Field 5 is the mount point
+        .filter_map(|line| line.split_whitespace().nth(4))
+        .filter(|mount| !SYSTEM_MOUNTS.contains(mount))
+        .filter(|mount| {
+            cwd == *mount
+                || cwd
+                    .strip_prefix(mount)
+                    .is_some_and(|rest| rest.starts_with('/'))
+        })
+        .max_by_key(|mount| mount.len())
+        .map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MOUNTINFO: &str = "\
+1 0 0:1 / / rw - overlay overlay rw
+2 1 8:1 /home/me/app /workspaces/app rw - ext4 /dev/sda1 rw
+3 1 0:5 / /proc rw - proc proc rw";
+
+    fn files(entries: &'static [(&'static str, &'static str)]) -> impl Fn(&str) -> Option<String> {
+        move |path| {
+            entries
+                .iter()
+                .find(|(p, _)| *p == path)
+                .map(|(_, c)| c.to_string())
+        }
+    }
+
+    #[test]
+    fn detects_docker_devcontainer() {
+        let info = ContainerDetector::detect_with(
+            files(&[
+                ("/.dockerenv", ""),
+                ("/etc/os-release", "NAME=\"Debian GNU/Linux\"\nID=debian\n"),
+                ("/proc/self/mountinfo", MOUNTINFO),
+            ]),
+            |key| (key == "REMOTE_CONTAINERS").then(|| "true".to_string()),
+            "/workspaces/app/src",
+        )
+        .unwrap();
+        assert_eq!(info.runtime, ContainerRuntime::Docker);
+        assert!(info.devcontainer);
+        assert_eq!(info.distro.as_deref(), Some("debian"));
+        assert_eq!(info.workspace.as_deref(), Some("/workspaces/app"));
+        assert_eq!(info.package_manager(), Some("apt-get"));
+        assert!(info
+            .describe()
+            .starts_with("docker devcontainer, distro debian, workspace /workspaces/app"));
+    }
+
+    #[test]
+    fn podman_exposes_image() {
+        let info = ContainerDetector::detect_with(
+            files(&[(
+                "/run/.containerenv",
+                "engine=\"podman-4.9\"\nimage=\"docker.io/library/alpine:3.19\"\n",
+            )]),
+            |_| None,
+            "/",
+        )
+        .unwrap();
+        assert_eq!(info.runtime, ContainerRuntime::Podman);
+        assert_eq!(info.image.as_deref(), Some("docker.io/library/alpine:3.19"));
+    }
+
+    #[test]
+    fn host_is_not_a_container() {
+        let detected = ContainerDetector::detect_with(
+            files(&[("/proc/1/cgroup", "0::/init.scope\n")]),
+            |_| None,
+            "/home/me",
+        );
+        assert!(detected.is_none());
+    }
+
+    #[test]
+    fn foreign_package_managers_are_rejected() {
+        let info = ContainerInfo {
+            runtime: ContainerRuntime::Docker,
+            devcontainer: false,
+            image: None,
+            distro: Some("debian".to_string()),
+            workspace: None,
+        };
+        assert!(info.rejects("brew install jq"));
+        assert!(info.rejects("sudo apk add jq"));
+        assert!(!info.rejects("sudo apt-get install jq"));
+        assert!(!info.rejects("cargo build"));
+    }
+}
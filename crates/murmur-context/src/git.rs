@@ -7,12 +7,40 @@ use tracing::debug;
 pub enum GitError {
     #[error("Not a git repository")]
     NotARepo,
+    #[error("Invalid revision range: {0}")]
+    InvalidRange(String),
     #[error("Git command failed: {0}")]
     CommandFailed(String),
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 }
 
+/// Git's empty tree, the base an amended root commit is compared against.
+const EMPTY_TREE: &str = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+
+/// What a commit would record, for writing its message.
+#[derive(Debug, Clone)]
+pub struct StagedChanges {
+    /// `git diff --stat` of the changes.
+    pub stat: String,
+    /// The full patch.
+    pub diff: String,
+    /// Message of the commit being amended.
+    pub amended_message: Option<String>,
+    /// Subjects of recent commits, newest first, to follow the repo's style.
+    pub recent_subjects: Vec<String>,
+}
+
+/// One commit from `GitContext::log`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogEntry {
+    /// Abbreviated hash.
+    pub hash: String,
+    pub subject: String,
+    /// The message after the subject, trimmed; empty if there is none.
+    pub body: String,
+}
+
 /// Collects git context for a directory.
 pub struct GitContext {
     cwd: String,
@@ -61,6 +89,109 @@ impl GitContext {
         })
     }
 
+    /// Commits in `range` (`base..head`, `base...head`, or a single ref
+    /// meaning `ref..HEAD`), newest first, at most `limit` of them.
+    pub async fn log(&self, range: &str, limit: usize) -> Result<Vec<LogEntry>, GitError> {
+        let range = checked_range(range)?;
+        let range = match range.contains("..") {
+            true => range.to_string(),
+            false => format!("{range}..HEAD"),
+        };
+        let limit = format!("-{limit}");
+        // Fields and records split by characters commit messages don't use
+        let output = self
+            .git_output(&[
+                "log",
+                &limit,
+                "--no-color",
+                "--format=%h%x1f%s%x1f%b%x1e",
+                &range,
+            ])
+            .await?;
+        Ok(output
+            .split('\x1e')
+            .filter_map(|record| {
+                let mut fields = record.trim_start_matches('\n').splitn(3, '\x1f');
+                let hash = fields.next()?.trim();
+                (!hash.is_empty()).then(|| LogEntry {
+                    hash: hash.to_string(),
+                    subject: fields.next().unwrap_or("").trim().to_string(),
+                    body: fields.next().unwrap_or("").trim().to_string(),
+                })
+            })
+            .collect())
+    }
+
+    /// `git diff --stat` between the ends of `range`, as `log` reads it.
+    pub async fn range_stat(&self, range: &str) -> Result<String, GitError> {
+        let range = checked_range(range)?;
+        // `diff a..b` compares the two ends; `a...b` from their merge base,
+        // which is what a pull request shows
+        let range = match range.contains("..") {
+            true => range.to_string(),
+            false => format!("{range}...HEAD"),
+        };
+        self.git_output(&["diff", "--no-color", "--stat", &range])
+            .await
+    }
+
+    /// The staged changes, or with `amend` the staged changes together with
+    /// those of the commit being amended.
+    pub async fn staged_changes(&self, amend: bool) -> Result<StagedChanges, GitError> {
+        if self
+            .git_output(&["rev-parse", "--show-toplevel"])
+            .await
+            .is_err()
+        {
+            return Err(GitError::NotARepo);
+        }
+        let base = if !amend {
+            None
+        } else if self
+            .git_output(&["rev-parse", "--verify", "-q", "HEAD^"])
+            .await
+            .is_ok()
+        {
+            Some("HEAD^")
+        } else {
+            Some(EMPTY_TREE)
+        };
+        let diff_args = |extra: &[&'static str]| {
+            let mut args = vec!["diff", "--cached", "--no-color", "--no-ext-diff"];
+            args.extend_from_slice(extra);
+            args.extend(base);
+            args
+        };
+        let stat = self.git_output(&diff_args(&["--stat"])).await?;
+        let diff = self.git_output(&diff_args(&[])).await?;
+        let amended_message = match amend {
+            true => Some(self.git_output(&["log", "-1", "--format=%B"]).await?),
+            false => None,
+        };
+        // An unborn branch has no log yet
+        let recent_subjects = self
+            .git_output(&["log", "--format=%s", "-10"])
+            .await
+            .unwrap_or_default()
+            .lines()
+            .map(String::from)
+            .collect();
+        Ok(StagedChanges {
+            stat,
+            diff,
+            amended_message,
+            recent_subjects,
+        })
+    }
+
+    /// Names of the local branches.
+    pub async fn branches(&self) -> Result<Vec<String>, GitError> {
+        let names = self
+            .git_output(&["for-each-ref", "--format=%(refname:short)", "refs/heads"])
+            .await?;
+        Ok(names.lines().map(String::from).collect())
+    }
+
     async fn git_output(&self, args: &[&str]) -> Result<String, GitError> {
         let output = Command::new("git")
             .args(args)
@@ -77,3 +208,13 @@ impl GitContext {
         Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
     }
 }
+
+/// `range` trimmed, if it can be passed to git as a revision range.
+fn checked_range(range: &str) -> Result<&str, GitError> {
+    let range = range.trim();
+    // One that looks like an option would be taken for one
+    if range.is_empty() || range.starts_with('-') || range.contains(char::is_whitespace) {
+        return Err(GitError::InvalidRange(range.to_string()));
+    }
+    Ok(range)
+}
@@ -0,0 +1,554 @@
+//! Splitting command lines into words the way a POSIX shell would.
+//!
+//! Quotes and escapes are resolved, so `echo "a; rm -rf x"` is one command
+//! with one argument, not two commands. Operators (`;`, `&&`, `||`, `|`,
+//! `&`, newlines), redirections and subshell parentheses become their own
+//! tokens. Command substitutions (`$(...)` and backticks) stay inside the
+//! word they appear in, and `commands` lexes their contents as commands
+//! of their own.
+//!
+//! Input is often a half-typed line, so nothing is an error: an unclosed
+//! quote or substitution runs to the end of the input.
+
+/// One token of a command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    /// A word, with quotes and escapes removed.
+    Word(String),
+    /// A redirection operator such as `>`, `2>>` or `&>`, possibly with
+    /// its file descriptor target attached (`2>&1`).
+    Redirect(String),
+    /// `;`, `&&`, `||`, `|`, `|&`, `&` or a newline.
+    Operator(String),
+    /// `(`, opening a subshell.
+    OpenParen,
+    /// `)`, closing a subshell.
+    CloseParen,
+}
+
+/// A simple command: its words and its redirections.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Command {
+    /// The program and its arguments, after leading keywords like `!`,
+    /// `{` and `then`. `VAR=value` assignments are kept.
+    pub words: Vec<String>,
+    pub redirects: Vec<Redirect>,
+}
+
+/// A redirection and the file it points at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redirect {
+    pub op: String,
+    /// `None` when the line ends before the target.
+    pub target: Option<String>,
+}
+
+impl Redirect {
+    /// Whether this redirection writes to its target.
+    pub fn writes(&self) -> bool {
+        self.op.contains('>')
+    }
+}
+
+impl Command {
+    /// The words after any `VAR=value` assignments.
+    pub fn without_assignments(&self) -> &[String] {
+        let start = self
+            .words
+            .iter()
+            .position(|w| !is_assignment(w))
+            .unwrap_or(self.words.len());
+        &self.words[start..]
+    }
+}
+
+/// Reserved words that can start a command without being its program.
+const LEADING_KEYWORDS: &[&str] = &[
+    "!", "{", "}", "if", "then", "else", "elif", "fi", "do", "done", "while", "until",
+];
+
+/// Whether `word` is a `VAR=value` assignment.
+pub fn is_assignment(word: &str) -> bool {
+    match word.split_once('=') {
+        Some((name, _)) => {
+            !name.is_empty()
+                && !name.starts_with(|c: char| c.is_ascii_digit())
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        None => false,
+    }
+}
+
+/// Split `input` into tokens.
+pub fn tokenize(input: &str) -> Vec<Token> {
+    Lexer::new(input).run().0
+}
+
+/// Every simple command in `input`, in order, including those inside
+/// subshells and command substitutions. Substituted commands follow the
+/// command they appear in.
+pub fn commands(input: &str) -> Vec<Command> {
+    let (tokens, substitutions) = Lexer::new(input).run();
+    let mut commands = Vec::new();
+    let mut current = Command::default();
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Word(word) => {
+                if !(current.words.is_empty() && LEADING_KEYWORDS.contains(&word.as_str())) {
+                    current.words.push(word);
+                }
+            }
+            Token::Redirect(op) => {
+                // `2>&1` already names its target; `>& file` doesn't
+                let duplicates = op.contains(">&") || op.contains("<&");
+                let has_target = !duplicates || op.ends_with('&');
+                let target = tokens
+                    .next_if(|t| has_target && matches!(t, Token::Word(_)))
+                    .and_then(|t| match t {
+                        Token::Word(word) => Some(word),
+                        _ => None,
+                    });
+                current.redirects.push(Redirect { op, target });
+            }
+            Token::Operator(_) | Token::OpenParen | Token::CloseParen => {
+                finish(&mut commands, &mut current);
+            }
+        }
+    }
+    finish(&mut commands, &mut current);
+    for substitution in substitutions {
+        commands.extend(self::commands(&substitution));
+    }
+    commands
+}
+
+/// Byte offset where the command at the end of `line` starts: after the
+/// last `;`, `&&`, `||`, `|`, `&`, newline or opening `(`, `$(` or
+/// backtick that is still open at the end, and past any whitespace. 0 for
+/// a line that is a single command.
+pub fn segment_start(line: &str) -> usize {
+    // One frame per open subshell, substitution or backtick: where its
+    // current command starts, the quote open inside it and what closes it
+    struct Frame {
+        start: usize,
+        quote: Option<char>,
+        closer: char,
+    }
+    let mut frames = vec![Frame {
+        start: 0,
+        quote: None,
+        closer: '\0',
+    }];
+    let mut prev = None;
+    let mut chars = line.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let next = chars.peek().map(|&(_, n)| n);
+        let frame = frames
+            .last_mut()
+            .expect("the outermost frame is never popped");
+        match (c, frame.quote) {
+            ('\\', q) if q != Some('\'') => {
+                chars.next();
+            }
+            ('`', q) if q != Some('\'') => {
+                if frame.closer == '`' && q.is_none() {
+                    frames.pop();
+                } else {
+                    frames.push(Frame {
+                        start: i + 1,
+                        quote: None,
+                        closer: '`',
+                    });
+                }
+            }
+            ('\'' | '"', None) => frame.quote = Some(c),
+            (c, Some(q)) if c == q => frame.quote = None,
+            ('$', q) if q != Some('\'') && next == Some('(') => {
+                chars.next();
+                frames.push(Frame {
+                    start: i + 2,
+                    quote: None,
+                    closer: ')',
+                });
+            }
+            ('(', None) => frames.push(Frame {
+                start: i + 1,
+                quote: None,
+                closer: ')',
+            }),
+            (')', None) if frame.closer == ')' => {
+                frames.pop();
+            }
+            (';' | '\n', None) => frame.start = i + 1,
+            // `&>`, `>&` and `>|` are redirections
+            ('&' | '|', None) if !matches!(prev, Some('>' | '<')) && next != Some('>') => {
+                frame.start = i + 1;
+            }
+            _ => {}
+        }
+        prev = Some(c);
+    }
+    let start = frames.last().map_or(0, |f| f.start);
+    start + (line[start..].len() - line[start..].trim_start().len())
+}
+
+fn finish(commands: &mut Vec<Command>, current: &mut Command) {
+    if !current.words.is_empty() || !current.redirects.is_empty() {
+        commands.push(std::mem::take(current));
+    }
+}
+
+struct Lexer<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    tokens: Vec<Token>,
+    substitutions: Vec<String>,
+    word: String,
+    /// Whether a word has started, so `""` still makes an empty word.
+    in_word: bool,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(input: &'a str) -> Self {
+        Self {
+            chars: input.chars().peekable(),
+            tokens: Vec::new(),
+            substitutions: Vec::new(),
+            word: String::new(),
+            in_word: false,
+        }
+    }
+
+    fn run(mut self) -> (Vec<Token>, Vec<String>) {
+        while let Some(c) = self.chars.next() {
+            match c {
+                ' ' | '\t' => self.end_word(),
+                '\n' | ';' => {
+                    self.end_word();
+                    self.tokens.push(Token::Operator(c.to_string()));
+                }
+                '&' if self.chars.peek() == Some(&'>') => {
+                    self.end_word();
+                    self.chars.next();
+                    let mut op = String::from("&>");
+                    if self.chars.next_if_eq(&'>').is_some() {
+                        op.push('>');
+                    }
+                    self.tokens.push(Token::Redirect(op));
+                }
+                '&' | '|' => {
+                    self.end_word();
+                    let mut op = c.to_string();
+                    if let Some(next) = self.chars.next_if(|&n| n == c || (c == '|' && n == '&')) {
+                        op.push(next);
+                    }
+                    self.tokens.push(Token::Operator(op));
+                }
+                '>' | '<' => {
+                    // A word of digits right before is the file descriptor
+                    let fd = if self.in_word && self.word.chars().all(|c| c.is_ascii_digit()) {
+                        self.in_word = false;
+                        std::mem::take(&mut self.word)
+                    } else {
+                        self.end_word();
+                        String::new()
+                    };
+                    if c == '<' && self.chars.peek() == Some(&'(') {
+                        // Process substitution
+                        self.chars.next();
+                        let inner = self.until_close_paren();
+                        self.substitutions.push(inner.clone());
+                        self.push_word(&format!("<({inner})"));
+                        self.end_word();
+                        continue;
+                    }
+                    let mut op = fd + &c.to_string();
+                    while let Some(next) = self.chars.next_if(|&n| n == c || n == '|') {
+                        op.push(next);
+                    }
+                    if let Some(amp) = self.chars.next_if_eq(&'&') {
+                        op.push(amp);
+                        // `2>&1`: the target descriptor belongs to the operator
+                        while let Some(digit) =
+                            self.chars.next_if(|n| n.is_ascii_digit() || *n == '-')
+                        {
+                            op.push(digit);
+                        }
+                    }
+                    self.tokens.push(Token::Redirect(op));
+                }
+                '(' if !self.in_word => self.tokens.push(Token::OpenParen),
+                ')' => {
+                    self.end_word();
+                    self.tokens.push(Token::CloseParen);
+                }
+                '#' if !self.in_word => {
+                    // Comment to the end of the line
+                    while self.chars.next_if(|&n| n != '\n').is_some() {}
+                }
+                '\\' => match self.chars.next() {
+                    Some('\n') => {}
+                    Some(escaped) => self.push_word(&escaped.to_string()),
+                    None => self.push_word("\\"),
+                },
+                '\'' => {
+                    self.in_word = true;
+                    for q in self.chars.by_ref() {
+                        if q == '\'' {
+                            break;
+                        }
+                        self.word.push(q);
+                    }
+                }
+                '"' => {
+                    self.in_word = true;
+                    self.double_quoted();
+                }
+                '$' if self.chars.peek() == Some(&'(') => {
+                    self.chars.next();
+                    let inner = self.until_close_paren();
+                    self.substitutions.push(inner.clone());
+                    self.push_word(&format!("$({inner})"));
+                }
+                '`' => {
+                    let inner = self.until_backtick();
+                    self.substitutions.push(inner.clone());
+                    self.push_word(&format!("`{inner}`"));
+                }
+                _ => self.push_word(&c.to_string()),
+            }
+        }
+        self.end_word();
+        (self.tokens, self.substitutions)
+    }
+
+    fn push_word(&mut self, text: &str) {
+        self.in_word = true;
+        self.word.push_str(text);
+    }
+
+    fn end_word(&mut self) {
+        if self.in_word {
+            self.tokens
+                .push(Token::Word(std::mem::take(&mut self.word)));
+            self.in_word = false;
+        }
+    }
+
+    /// The rest of a `"..."` string, with the escapes a shell honors there.
+    fn double_quoted(&mut self) {
+        while let Some(c) = self.chars.next() {
+            match c {
+                '"' => return,
+                '\\' => match self.chars.next() {
+                    Some(escaped @ ('"' | '\\' | '$' | '`')) => self.word.push(escaped),
+                    Some('\n') => {}
+                    Some(other) => {
+                        self.word.push('\\');
+                        self.word.push(other);
+                    }
+                    None => self.word.push('\\'),
+                },
+                '$' if self.chars.peek() == Some(&'(') => {
+                    self.chars.next();
+                    let inner = self.until_close_paren();
+                    self.substitutions.push(inner.clone());
+                    self.word.push_str(&format!("$({inner})"));
+                }
+                '`' => {
+                    let inner = self.until_backtick();
+                    self.substitutions.push(inner.clone());
+                    self.word.push_str(&format!("`{inner}`"));
+                }
+                _ => self.word.push(c),
+            }
+        }
+    }
+
+    /// Text up to the `)` matching an already consumed `(`, which is
+    /// consumed too. Quoted parentheses don't count.
+    fn until_close_paren(&mut self) -> String {
+        let mut inner = String::new();
+        let mut depth = 1;
+        let mut quote = None;
+        while let Some(c) = self.chars.next() {
+            match (c, quote) {
+                ('\\', q) if q != Some('\'') => {
+                    inner.push(c);
+                    if let Some(escaped) = self.chars.next() {
+                        inner.push(escaped);
+                    }
+                    continue;
+                }
+                ('\'' | '"', None) => quote = Some(c),
+                (c, Some(q)) if c == q => quote = None,
+                ('(', None) => depth += 1,
+                (')', None) => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+            inner.push(c);
+        }
+        inner
+    }
+
+    /// Text up to the next unescaped backtick, which is consumed.
+    fn until_backtick(&mut self) -> String {
+        let mut inner = String::new();
+        while let Some(c) = self.chars.next() {
+            match c {
+                '`' => break,
+                '\\' => {
+                    if let Some(escaped) = self.chars.next() {
+                        inner.push(escaped);
+                    }
+                }
+                _ => inner.push(c),
+            }
+        }
+        inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(text: &str) -> Token {
+        Token::Word(text.to_string())
+    }
+
+    fn words(input: &str) -> Vec<Vec<String>> {
+        commands(input).into_iter().map(|c| c.words).collect()
+    }
+
+    #[test]
+    fn quotes_and_escapes_are_resolved() {
+        assert_eq!(
+            tokenize(r#"git commit -m "fix: a; b" 'it''s' a\ b """#),
+            vec![
+                word("git"),
+                word("commit"),
+                word("-m"),
+                word("fix: a; b"),
+                word("its"),
+                word("a b"),
+                word(""),
+            ]
+        );
+        assert_eq!(
+            tokenize(r#"echo "say \"hi\" \n""#)[1],
+            word(r#"say "hi" \n"#)
+        );
+    }
+
+    #[test]
+    fn operators_split_commands() {
+        assert_eq!(
+            words("cd /tmp && rm -r old || echo failed; ls | wc -l & jobs"),
+            vec![
+                vec!["cd", "/tmp"],
+                vec!["rm", "-r", "old"],
+                vec!["echo", "failed"],
+                vec!["ls"],
+                vec!["wc", "-l"],
+                vec!["jobs"],
+            ]
+        );
+        assert_eq!(words("echo 'a && b'"), vec![vec!["echo", "a && b"]]);
+    }
+
+    #[test]
+    fn redirects_take_their_target() {
+        let parsed = commands("cat img > /dev/sda 2>&1 <in");
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].words, vec!["cat", "img"]);
+        assert_eq!(
+            parsed[0].redirects,
+            vec![
+                Redirect {
+                    op: ">".to_string(),
+                    target: Some("/dev/sda".to_string()),
+                },
+                Redirect {
+                    op: "2>&1".to_string(),
+                    target: None,
+                },
+                Redirect {
+                    op: "<".to_string(),
+                    target: Some("in".to_string()),
+                },
+            ]
+        );
+        assert!(parsed[0].redirects[0].writes());
+        assert_eq!(commands("make &>>log")[0].redirects[0].op, "&>>");
+        // Digits only count as a descriptor right before the operator
+        assert_eq!(commands("echo 2 >x")[0].words, vec!["echo", "2"]);
+    }
+
+    #[test]
+    fn subshells_and_substitutions_are_commands() {
+        assert_eq!(
+            words("(cd src && ls) ; echo \"$(rm -rf `pwd`)\""),
+            vec![
+                vec!["cd", "src"],
+                vec!["ls"],
+                vec!["echo", "$(rm -rf `pwd`)"],
+                vec!["rm", "-rf", "`pwd`"],
+                vec!["pwd"],
+            ]
+        );
+        assert_eq!(
+            words("diff <(ls a) b"),
+            vec![vec!["diff", "<(ls a)", "b"], vec!["ls", "a"]]
+        );
+    }
+
+    #[test]
+    fn keywords_and_assignments() {
+        assert_eq!(
+            words("if true; then ! make; fi"),
+            vec![vec!["true"], vec!["make"]]
+        );
+        let command = &commands("RUST_LOG=debug FOO= cargo run --x=1")[0];
+        assert_eq!(command.without_assignments(), ["cargo", "run", "--x=1"]);
+        assert!(!is_assignment("--x=1"));
+        assert!(!is_assignment("=x"));
+    }
+
+    #[test]
+    fn partial_input_is_forgiven() {
+        assert_eq!(
+            words("git commit -m \"wip"),
+            vec![vec!["git", "commit", "-m", "wip"]]
+        );
+        assert_eq!(words("echo $(ls"), vec![vec!["echo", "$(ls)"], vec!["ls"]]);
+        assert_eq!(words("ls # comment; rm x"), vec![vec!["ls"]]);
+        assert!(commands("   ").is_empty());
+    }
+
+    #[test]
+    fn segment_start_finds_the_command_being_typed() {
+        fn rest(line: &str) -> &str {
+            &line[segment_start(line)..]
+        }
+        assert_eq!(rest("git pu"), "git pu");
+        assert_eq!(rest("make build && git pu"), "git pu");
+        assert_eq!(rest("ls | grep -"), "grep -");
+        assert_eq!(rest("a; b ||  c"), "c");
+        assert_eq!(rest("(cd src && car"), "car");
+        assert_eq!(rest("(cd src) && car"), "car");
+        assert_eq!(rest("echo \"$(git rev"), "git rev");
+        assert_eq!(rest("echo `whoa"), "whoa");
+        assert_eq!(rest("echo `pwd` && l"), "l");
+        // Operators in quotes and redirections don't start a command
+        assert_eq!(rest("echo 'a && b"), "echo 'a && b");
+        assert_eq!(rest("make 2>&1 | tee lo"), "tee lo");
+        assert_eq!(rest("make &>log"), "make &>log");
+        assert_eq!(rest("make && "), "");
+    }
+}
@@ -1,14 +1,21 @@
 //! Murmur Context — Collects shell and project context for better completions.
 
+mod container;
 mod env;
 mod git;
 mod history;
+pub mod lexer;
+mod nix;
 mod project;
+mod remote;
 
+pub use container::{ContainerDetector, ContainerInfo, ContainerRuntime};
 pub use env::EnvContext;
-pub use git::GitContext;
+pub use git::{GitContext, LogEntry, StagedChanges};
 pub use history::HistoryCollector;
+pub use nix::{DevEnvInfo, DirenvStatus, NixDetector};
 pub use project::{ProjectDetector, ProjectType};
+pub use remote::{is_local_host, local_hostname, remote_context};
 
 use serde::{Deserialize, Serialize};
 
@@ -27,6 +34,24 @@ pub struct ShellContext {
     pub project: Option<ProjectType>,
     /// Relevant environment variables.
     pub env_vars: Vec<(String, String)>,
+    /// Condensed summary of what the user has been doing recently
+    /// (filled in by the daemon from cross-tool history).
+    #[serde(default)]
+    pub session_summary: Option<String>,
+    /// Host the shell is running on when it is an SSH session on another
+    /// machine. `None` for local shells.
+    #[serde(default)]
+    pub remote_host: Option<String>,
+    /// Container/devcontainer the shell runs in, if any.
+    #[serde(default)]
+    pub container: Option<ContainerInfo>,
+    /// Nix dev shell / direnv state, if the project uses either.
+    #[serde(default)]
+    pub dev_env: Option<DevEnvInfo>,
+    /// The line before the command being completed, when the cursor is in
+    /// a later part of a chain (`make build && `). Filled in by the daemon.
+    #[serde(default)]
+    pub line_prefix: Option<String>,
 }
 
 /// Git repository information.
@@ -52,6 +77,8 @@ pub async fn collect_context(cwd: &str, shell: &str, history_lines: usize) -> Sh
     let git = GitContext::new(cwd).collect().await.ok();
     let project = ProjectDetector::detect(cwd).await;
     let env_vars = EnvContext::collect_relevant();
+    let container = ContainerDetector::detect(cwd);
+    let dev_env = NixDetector::detect(cwd);
 
     ShellContext {
         history,
@@ -60,5 +87,10 @@ pub async fn collect_context(cwd: &str, shell: &str, history_lines: usize) -> Sh
         git,
         project,
         env_vars,
+        session_summary: None,
+        remote_host: None,
+        container,
+        dev_env,
+        line_prefix: None,
     }
 }
@@ -0,0 +1,247 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tracing::debug;
+
+/// Most tool names reported from a Nix dev shell.
+const MAX_COMMANDS: usize = 40;
+
+/// Nix and direnv state for the working directory.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DevEnvInfo {
+    /// Nix files found in the cwd or a parent (`flake.nix`, `shell.nix`, `default.nix`).
+    pub nix_files: Vec<String>,
+    /// Value of `IN_NIX_SHELL` ("pure" or "impure") when inside `nix develop`/`nix-shell`.
+    pub nix_shell: Option<String>,
+    /// direnv state for the nearest `.envrc`.
+    pub direnv: Option<DirenvStatus>,
+    /// Commands provided by the dev shell (executables on `PATH` under `/nix/store`).
+    pub commands: Vec<String>,
+}
+
+/// Whether direnv has loaded the nearest `.envrc`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DirenvStatus {
+    /// `.envrc` is loaded into the environment.
+    Loaded,
+    /// `.envrc` exists but isn't loaded (not allowed yet, or direnv not hooked).
+    NotLoaded,
+}
+
+impl DevEnvInfo {
+    /// Whether toolchains come from Nix, so global installs are the wrong fix.
+    pub fn is_nix_managed(&self) -> bool {
+        self.nix_shell.is_some()
+            || (self.direnv == Some(DirenvStatus::Loaded) && !self.nix_files.is_empty())
+    }
+
+    /// Whether `command` installs a tool globally, bypassing the Nix shell.
+    pub fn rejects(&self, command: &str) -> bool {
+        self.is_nix_managed() && is_global_install(command)
+    }
+
+    /// One-line description for provider prompts.
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(ref mode) = self.nix_shell {
+            parts.push(format!("inside a {mode} Nix dev shell"));
+        }
+        if !self.nix_files.is_empty() {
+            parts.push(format!("project has {}", self.nix_files.join(", ")));
+        }
+        match self.direnv {
+            Some(DirenvStatus::Loaded) => parts.push("direnv loaded".to_string()),
+            Some(DirenvStatus::NotLoaded) => {
+                parts.push(".envrc not loaded (run `direnv allow`)".to_string())
+            }
+            None => {}
+        }
+        if !self.commands.is_empty() {
+            parts.push(format!("dev shell provides: {}", self.commands.join(" ")));
+        }
+        let mut text = parts.join("; ");
+        if self.is_nix_managed() {
+            text.push_str(". Use the dev shell's tools; don't suggest global installs");
+        } else if self.nix_shell.is_none() && self.nix_files.iter().any(|f| f == "flake.nix") {
+            text.push_str(". Tools are available via `nix develop`");
+        }
+        text
+    }
+}
+
+/// Collects Nix and direnv context.
+pub struct NixDetector;
+
+impl NixDetector {
+    /// Detect from the filesystem and the daemon's environment. Returns
+    /// `None` when there's no Nix or direnv involvement at all.
+    pub fn detect(cwd: &str) -> Option<DevEnvInfo> {
+        Self::detect_with(
+            Path::new(cwd),
+            |key| std::env::var(key).ok(),
+            |dir| {
+                std::fs::read_dir(dir)
+                    .map(|entries| {
+                        entries
+                            .flatten()
+                            .map(|e| e.file_name().to_string_lossy().into_owned())
+                            .collect()
+                    })
+                    .unwrap_or_default()
+            },
+        )
+    }
+
+    /// Detect using `env` for variables and `list` for directory listings.
+    pub fn detect_with(
+        cwd: &Path,
+        env: impl Fn(&str) -> Option<String>,
+        list: impl Fn(&Path) -> Vec<String>,
+    ) -> Option<DevEnvInfo> {
+        let mut nix_files = Vec::new();
+        let mut envrc_dir = None;
+        // Nearest directory with Nix files wins, like `nix develop` itself
+        for dir in cwd.ancestors() {
+            let names = list(dir);
+            if envrc_dir.is_none() && names.iter().any(|n| n == ".envrc") {
+                envrc_dir = Some(dir.to_path_buf());
+            }
+            if nix_files.is_empty() {
+                nix_files = ["flake.nix", "shell.nix", "default.nix"]
+                    .into_iter()
+                    .filter(|f| names.iter().any(|n| n == f))
+                    .map(String::from)
+                    .collect();
+            }
+            if !nix_files.is_empty() && envrc_dir.is_some() {
+                break;
+            }
+        }
+
+        let nix_shell = env("IN_NIX_SHELL").filter(|v| !v.is_empty());
+        let direnv = envrc_dir.map(|dir| {
+            // direnv exports DIRENV_DIR as "-<dir of loaded .envrc>"
+            let loaded = env("DIRENV_DIR")
+                .is_some_and(|d| Path::new(d.trim_start_matches('-')) == dir.as_path());
+            if loaded {
+                DirenvStatus::Loaded
+            } else {
+                DirenvStatus::NotLoaded
+            }
+        });
+
+        if nix_files.is_empty() && nix_shell.is_none() && direnv.is_none() {
+            return None;
+        }
+
+        let commands = if nix_shell.is_some() || direnv == Some(DirenvStatus::Loaded) {
+            nix_commands(&env("PATH").unwrap_or_default(), &list)
+        } else {
+            Vec::new()
+        };
+
+        debug!(
+            ?nix_files,
+            ?nix_shell,
+            ?direnv,
+            commands = commands.len(),
+            "Detected dev environment"
+        );
+        Some(DevEnvInfo {
+            nix_files,
+            nix_shell,
+            direnv,
+            commands,
+        })
+    }
+}
+
+/// Executables from `/nix/store` entries on `PATH`, sorted and deduplicated.
+fn nix_commands(path: &str, list: &impl Fn(&Path) -> Vec<String>) -> Vec<String> {
+    let mut commands: Vec<String> = std::env::split_paths(path)
+        .filter(|dir| dir.starts_with("/nix/store"))
+        .flat_map(|dir| list(&dir))
+        .filter(|name| !name.starts_with('.'))
+        .collect();
+    commands.sort();
+    commands.dedup();
+    commands.truncate(MAX_COMMANDS);
+    commands
+}
+
+fn is_global_install(command: &str) -> bool {
+    let words: Vec<&str> = command
+        .split_whitespace()
+        .filter(|w| !matches!(*w, "sudo" | "doas"))
+        .collect();
+    let has = |flag: &str| words.contains(&flag);
+    match words.as_slice() {
+        ["brew" | "apt" | "apt-get" | "dnf" | "yum" | "pacman", sub, ..] => {
+            matches!(*sub, "install" | "-S")
+        }
+        ["npm" | "pnpm", "install" | "i" | "add", ..] => has("-g") || has("--global"),
+        ["yarn", "global", "add", ..] => true,
+        ["pip" | "pip3", "install", ..] => has("--user"),
+        ["cargo", "install", ..] | ["go", "install", ..] | ["gem", "install", ..] => true,
+        ["nix-env", "-i" | "-iA" | "--install", ..] | ["nix", "profile", "install", ..] => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listing(
+        dirs: &'static [(&'static str, &'static [&'static str])],
+    ) -> impl Fn(&Path) -> Vec<String> {
+        move |dir| {
+            dirs.iter()
+                .find(|(d, _)| Path::new(d) == dir)
+                .map(|(_, names)| names.iter().map(|n| n.to_string()).collect())
+                .unwrap_or_default()
+        }
+    }
+
+    #[test]
+    fn flake_with_loaded_direnv_lists_shell_commands() {
+        let info = NixDetector::detect_with(
+            Path::new("/repo/src"),
+            |key| match key {
+                "DIRENV_DIR" => Some("-/repo".to_string()),
+                "PATH" => Some("/nix/store/abc-rust/bin:/usr/bin".to_string()),
+                _ => None,
+            },
+            listing(&[
+                ("/repo", &["flake.nix", "flake.lock", ".envrc"]),
+                ("/nix/store/abc-rust/bin", &["cargo", "rustc"]),
+                ("/usr/bin", &["ls"]),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(info.nix_files, vec!["flake.nix"]);
+        assert_eq!(info.direnv, Some(DirenvStatus::Loaded));
+        assert_eq!(info.commands, vec!["cargo", "rustc"]);
+        assert!(info.rejects("cargo install ripgrep"));
+        assert!(info.rejects("npm i -g typescript"));
+        assert!(!info.rejects("cargo build"));
+    }
+
+    #[test]
+    fn unloaded_envrc_does_not_block_installs() {
+        let info = NixDetector::detect_with(
+            Path::new("/repo"),
+            |_| None,
+            listing(&[("/repo", &[".envrc", "shell.nix"])]),
+        )
+        .unwrap();
+        assert_eq!(info.direnv, Some(DirenvStatus::NotLoaded));
+        assert!(!info.is_nix_managed());
+        assert!(info.describe().contains("direnv allow"));
+    }
+
+    #[test]
+    fn plain_directory_has_no_dev_env() {
+        assert!(NixDetector::detect_with(Path::new("/tmp"), |_| None, |_| Vec::new()).is_none());
+    }
+}
@@ -3,7 +3,7 @@ use std::path::Path;
 use tracing::debug;
 
 /// Detected project type.
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "snake_case")]
 pub enum ProjectType {
     Rust,
@@ -17,6 +17,22 @@ pub enum ProjectType {
     Unknown,
 }
 
+/// Files that mark a project, in the order they're looked for. A leading
+/// `*` matches any file with that suffix.
+const MARKERS: &[(&str, ProjectType)] = &[
+    ("Cargo.toml", ProjectType::Rust),
+    ("package.json", ProjectType::Node),
+    ("pyproject.toml", ProjectType::Python),
+    ("requirements.txt", ProjectType::Python),
+    ("go.mod", ProjectType::Go),
+    ("Gemfile", ProjectType::Ruby),
+    ("pom.xml", ProjectType::Java),
+    ("build.gradle", ProjectType::Java),
+    ("*.csproj", ProjectType::CSharp),
+    ("CMakeLists.txt", ProjectType::Cpp),
+    ("Makefile", ProjectType::Cpp),
+];
+
 /// Detects the project type based on marker files in the working directory.
 pub struct ProjectDetector;
 
@@ -25,21 +41,7 @@ impl ProjectDetector {
     pub async fn detect(cwd: &str) -> Option<ProjectType> {
         let path = Path::new(cwd);
 
-        let markers = [
-            ("Cargo.toml", ProjectType::Rust),
-            ("package.json", ProjectType::Node),
-            ("pyproject.toml", ProjectType::Python),
-            ("requirements.txt", ProjectType::Python),
-            ("go.mod", ProjectType::Go),
-            ("Gemfile", ProjectType::Ruby),
-            ("pom.xml", ProjectType::Java),
-            ("build.gradle", ProjectType::Java),
-            ("*.csproj", ProjectType::CSharp),
-            ("CMakeLists.txt", ProjectType::Cpp),
-            ("Makefile", ProjectType::Cpp),
-        ];
-
-        for (marker, project_type) in &markers {
+        for (marker, project_type) in MARKERS {
             if let Some(ext) = marker.strip_prefix('*') {
                 // Glob pattern — check with readdir
                 if let Ok(mut entries) = tokio::fs::read_dir(path).await {
@@ -58,6 +60,16 @@ impl ProjectDetector {
 
         None
     }
+
+    /// Whether a file named `name` marks a project.
+    pub fn is_marker(name: &str) -> bool {
+        MARKERS
+            .iter()
+            .any(|(marker, _)| match marker.strip_prefix('*') {
+                Some(suffix) => name.ends_with(suffix),
+                None => name == *marker,
+            })
+    }
 }
 
 #[cfg(test)]
@@ -71,4 +83,11 @@ mod tests {
         // This is more of a smoke test
         assert!(result.is_none() || result.is_some());
     }
+
+    #[test]
+    fn recognizes_markers() {
+        assert!(ProjectDetector::is_marker("package.json"));
+        assert!(ProjectDetector::is_marker("App.csproj"));
+        assert!(!ProjectDetector::is_marker("README.md"));
+    }
 }
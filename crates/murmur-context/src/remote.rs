@@ -0,0 +1,68 @@
+use crate::ShellContext;
+
+/// Hostname of the machine the daemon runs on.
+///
+/// Reads the kernel hostname first and falls back to `/etc/hostname` and the
+/// `hostname` command, so it works on both Linux and macOS.
+pub fn local_hostname() -> Option<String> {
+    let from_file = |path: &str| std::fs::read_to_string(path).ok();
+    let from_command = || {
+        std::process::Command::new("hostname")
+            .output()
+            .ok()
+            .filter(|o| o.status.success())
+            .map(|o| String::from_utf8_lossy(&o.stdout).into_owned())
+    };
+
+    from_file("/proc/sys/kernel/hostname")
+        .or_else(|| from_file("/etc/hostname"))
+        .or_else(from_command)
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+}
+
+/// Whether `host` names the local machine. Compares the short hostname
+/// case-insensitively, since shells report `box` or `box.example.com`
+/// depending on configuration.
+pub fn is_local_host(host: &str, local: Option<&str>) -> bool {
+    let short = |h: &str| h.split('.').next().unwrap_or(h).to_ascii_lowercase();
+    match local {
+        Some(local) => short(host) == short(local),
+        None => false,
+    }
+}
+
+/// Context for a shell running on a remote host over SSH.
+///
+/// The cwd only exists on the remote machine, so git, project and
+/// environment probing are skipped; everything comes from what the remote
+/// shell integration reported.
+pub fn remote_context(host: &str, cwd: &str, shell: &str, history: Vec<String>) -> ShellContext {
+    ShellContext {
+        history,
+        cwd: cwd.to_string(),
+        shell: shell.to_string(),
+        remote_host: Some(host.to_string()),
+        ..ShellContext::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_host_matches_short_name() {
+        assert!(is_local_host("Laptop.local", Some("laptop")));
+        assert!(!is_local_host("devbox", Some("laptop")));
+        assert!(!is_local_host("devbox", None));
+    }
+
+    #[test]
+    fn remote_context_skips_local_probing() {
+        let ctx = remote_context("devbox", "/srv/app", "bash", vec!["make".to_string()]);
+        assert_eq!(ctx.remote_host.as_deref(), Some("devbox"));
+        assert!(ctx.git.is_none());
+        assert!(ctx.env_vars.is_empty());
+    }
+}
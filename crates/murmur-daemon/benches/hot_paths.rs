@@ -0,0 +1,210 @@
+//! Benchmarks for the daemon's per-keystroke hot paths.
+//!
+//! Run with `cargo bench -p murmur-daemon`. Every request that reaches the
+//! daemon hashes a cache key, looks it up, decodes and encodes JSON-RPC and,
+//! on a miss, predicts prefetch inputs; empty prompts also rank suggestions
+//! from the sequence model. None of these should be noticeable next to a
+//! provider round trip, so each has a budget, listed under "Benchmarks" in
+//! the README. A refactor that pushes a benchmark past its budget needs a
+//! reason.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use murmur_context::{GitInfo, ProjectType};
+use murmur_daemon::cache::CompletionCache;
+use murmur_daemon::fingerprint::Fingerprint;
+use murmur_daemon::prefetch::{predict_next_inputs, PrefixModel};
+use murmur_daemon::proactive;
+use murmur_daemon::sequence::SequenceModel;
+use murmur_protocol::{
+    CompletionItem, CompletionKind, CompletionMetadata, CompletionRequest, CompletionResponse,
+    HistoryEntry, JsonRpcRequest, JsonRpcResponse, RequestId,
+};
+
+/// A plausible week of commands in one project: a handful of workflows
+/// repeated across sessions.
+fn history(len: usize) -> Vec<HistoryEntry> {
+    const WORKFLOWS: &[&[&str]] = &[
+        &["git pull", "cargo build", "cargo test", "git status"],
+        &["git status", "git add -A", "git commit", "git push"],
+        &["cargo fmt", "cargo clippy", "cargo test -p core"],
+        &["docker compose up -d", "docker ps", "docker logs api"],
+        &["ls", "cd src", "vim main.rs", "cargo run"],
+    ];
+    let mut entries = Vec::with_capacity(len);
+    let mut timestamp = 1_700_000_000;
+    'fill: for session in 0.. {
+        for command in WORKFLOWS[session % WORKFLOWS.len()] {
+            if entries.len() == len {
+                break 'fill;
+            }
+            timestamp += 30;
+            entries.push(HistoryEntry {
+                command: command.to_string(),
+                cwd: "/home/dev/project".to_string(),
+                source: "zsh".to_string(),
+                exit_code: 0,
+                timestamp,
+                session_id: Some(format!("s{}", session % 7)),
+                host: None,
+                suggested_by: None,
+            });
+        }
+        // New session after a break
+        timestamp += 3_600;
+    }
+    entries
+}
+
+fn item(text: &str, score: f64) -> CompletionItem {
+    CompletionItem {
+        text: text.to_string(),
+        description: Some("Suggested command".to_string()),
+        kind: CompletionKind::Command,
+        score,
+        metadata: CompletionMetadata::default(),
+    }
+}
+
+fn response(items: usize) -> CompletionResponse {
+    CompletionResponse {
+        items: (0..items)
+            .map(|i| {
+                item(
+                    &format!("git checkout feature/branch-{i}"),
+                    1.0 - i as f64 / 20.0,
+                )
+            })
+            .collect(),
+        provider: "anthropic".to_string(),
+        latency_ms: 240,
+        cached: false,
+        notice: None,
+        groups: vec![],
+        refreshing: false,
+    }
+}
+
+fn bench_cache(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cache");
+    let fingerprint = Fingerprint {
+        branch: Some("main".to_string()),
+        dirty: true,
+        project: Some(ProjectType::Rust),
+    };
+    group.bench_function("key", |b| {
+        b.iter(|| {
+            CompletionCache::cache_key(
+                black_box("git checkout fea"),
+                black_box("/home/dev/project"),
+                black_box("zsh"),
+                black_box(None),
+                black_box(&fingerprint),
+            )
+        })
+    });
+
+    let mut cache = CompletionCache::new(1_000);
+    for i in 0..1_000u64 {
+        cache.put(i, "/home/dev/project", None, response(5));
+    }
+    group.bench_function("get_hit", |b| b.iter(|| cache.get(black_box(500))));
+
+    let mut i = 0u64;
+    group.bench_function("put", |b| {
+        b.iter(|| {
+            i += 1;
+            cache.put(black_box(i), "/home/dev/project", None, response(5));
+        })
+    });
+    group.finish();
+}
+
+fn bench_prefetch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("prefetch");
+    group.bench_function("predict_next_inputs", |b| {
+        b.iter(|| predict_next_inputs(black_box("git c")))
+    });
+    let mut model = PrefixModel::new();
+    for command in [
+        "git commit -m wip",
+        "git checkout main",
+        "cargo build --release",
+    ] {
+        for _ in 0..50 {
+            model.observe(command);
+        }
+    }
+    group.bench_function("learned", |b| b.iter(|| model.predict(black_box("git c"))));
+    group.finish();
+}
+
+fn bench_ranking(c: &mut Criterion) {
+    let mut group = c.benchmark_group("ranking");
+    let model = SequenceModel::train(&history(5_000));
+    group.bench_function("sequence_predict", |b| {
+        b.iter(|| model.predict(black_box(&["cargo build", "cargo test"]), 5))
+    });
+
+    let git = GitInfo {
+        branch: "main".to_string(),
+        dirty: true,
+        recent_commits: vec![],
+        repo_root: "/home/dev/project".to_string(),
+    };
+    let recent: Vec<HistoryEntry> = history(50).into_iter().rev().collect();
+    group.bench_function("proactive_merge", |b| {
+        b.iter(|| {
+            let predictions = model.predict(&["git add -A", "git commit"], 5);
+            proactive::suggestions(predictions, Some(&git), black_box(&recent))
+        })
+    });
+    group.finish();
+}
+
+fn bench_jsonrpc(c: &mut Criterion) {
+    let mut group = c.benchmark_group("jsonrpc");
+    let request = CompletionRequest {
+        input: "git checkout fea".to_string(),
+        cursor_pos: 16,
+        cwd: "/home/dev/project".to_string(),
+        history: history(20).into_iter().map(|e| e.command).collect(),
+        shell: Some("zsh".to_string()),
+        max_items: None,
+        min_score: None,
+        host: None,
+        provider: None,
+        model: None,
+        cancel_token: None,
+        session_id: None,
+    };
+    let line = serde_json::to_string(&JsonRpcRequest::new(
+        "complete",
+        Some(serde_json::to_value(&request).unwrap()),
+        RequestId::Number(1),
+    ))
+    .unwrap();
+    group.bench_function("decode_request", |b| {
+        b.iter(|| {
+            let request: JsonRpcRequest = serde_json::from_str(black_box(&line)).unwrap();
+            serde_json::from_value::<CompletionRequest>(request.params.unwrap()).unwrap()
+        })
+    });
+
+    let response = response(10);
+    group.bench_function("encode_response", |b| {
+        b.iter(|| {
+            let result = serde_json::to_value(black_box(&response)).unwrap();
+            serde_json::to_string(&JsonRpcResponse::success(result, RequestId::Number(1))).unwrap()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_cache,
+    bench_prefetch,
+    bench_ranking,
+    bench_jsonrpc
+);
+criterion_main!(benches);
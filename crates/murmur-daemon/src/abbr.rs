@@ -0,0 +1,365 @@
+//! Fish-style abbreviations for every shell (`[abbreviations]`).
+//!
+//! An abbreviation is a word that expands in place when followed by a
+//! space or Enter, so what runs (and lands in history) is the full
+//! command. Unlike an alias, the expansion can be seen and edited before
+//! it runs. Definitions come from the config; with `learn` on, the daemon
+//! also abbreviates the two-word commands run most often by their
+//! initials (`git checkout` becomes `gc`), skipping names that are already
+//! programs or builtins.
+//!
+//! Only a word in command position expands: the first word of the line
+//! or of a command after `;`, `&&`, `|` and the like. The shell widgets
+//! ask `abbr/expand` on space and Enter; fish loads the table into its own
+//! `abbr` instead. Learning reads thousands of history entries and looks
+//! names up on `PATH`, so it runs off the request path: a stale table is
+//! used until the new one is swapped in.
+
+use murmur_context::lexer;
+use murmur_protocol::{AbbrExpandResponse, Abbreviation, HistoryEntry};
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+use crate::config::AbbreviationsConfig;
+
+/// Most abbreviations learned from history.
+const MAX_LEARNED: usize = 30;
+
+/// How long learned abbreviations are used before history is read again.
+const RELEARN_INTERVAL: Duration = Duration::from_secs(600);
+
+/// Defined and learned abbreviations. Defined ones win a name clash.
+#[derive(Debug, Default)]
+pub struct Abbreviations {
+    defined: BTreeMap<String, String>,
+    learned: BTreeMap<String, String>,
+    learn: bool,
+    min_uses: usize,
+    learned_at: Option<Instant>,
+    /// A `Learner` is out and hasn't reported back.
+    learning: bool,
+}
+
+/// What learning needs from the table, to run without holding it.
+#[derive(Debug)]
+pub struct Learner {
+    defined: BTreeMap<String, String>,
+    min_uses: usize,
+}
+
+impl Learner {
+    /// Abbreviations for the two-word commands that succeeded at least
+    /// `min_uses` times in `entries`. `taken` says whether a name is
+    /// already a program or builtin.
+    pub fn learn(
+        &self,
+        entries: &[HistoryEntry],
+        taken: impl Fn(&str) -> bool,
+    ) -> BTreeMap<String, String> {
+        let mut uses: HashMap<String, usize> = HashMap::new();
+        for entry in entries.iter().filter(|e| e.exit_code == 0) {
+            for command in lexer::commands(&entry.command) {
+                if let Some(phrase) = phrase(command.without_assignments()) {
+                    *uses.entry(phrase).or_default() += 1;
+                }
+            }
+        }
+        let mut frequent: Vec<(String, usize)> = uses
+            .into_iter()
+            .filter(|&(_, n)| n >= self.min_uses.max(1))
+            .collect();
+        // Most used first, so it gets the name when initials collide
+        frequent.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        let mut learned = BTreeMap::new();
+        for (phrase, _) in frequent {
+            if learned.len() >= MAX_LEARNED {
+                break;
+            }
+            let name: String = phrase.split(' ').filter_map(|w| w.chars().next()).collect();
+            if self.defined.contains_key(&name)
+                || learned.contains_key(&name)
+                || self.defined.values().any(|e| *e == phrase)
+                || taken(&name)
+            {
+                continue;
+            }
+            learned.insert(name, phrase);
+        }
+        learned
+    }
+}
+
+impl Abbreviations {
+    pub fn from_config(config: &AbbreviationsConfig) -> Self {
+        let defined = config
+            .define
+            .iter()
+            .filter(|(name, expansion)| {
+                let valid = !name.is_empty()
+                    && !name.contains(char::is_whitespace)
+                    && !expansion.trim().is_empty();
+                if !valid {
+                    warn!(name = %name, "Ignoring invalid abbreviation");
+                }
+                valid
+            })
+            .map(|(name, expansion)| (name.clone(), expansion.trim().to_string()))
+            .collect();
+        Self {
+            defined,
+            learned: BTreeMap::new(),
+            learn: config.learn,
+            min_uses: config.min_uses,
+            learned_at: None,
+            learning: false,
+        }
+    }
+
+    /// Whether the learned abbreviations are stale at `now` and nobody is
+    /// relearning them yet.
+    pub fn needs_learning(&self, now: Instant) -> bool {
+        self.learn
+            && !self.learning
+            && self.learned_at.map_or(true, |at| {
+                now.saturating_duration_since(at) >= RELEARN_INTERVAL
+            })
+    }
+
+    /// A `Learner` to relearn with if the table needs it at `now`; pass
+    /// what it learns to `finish_learning`.
+    pub fn start_learning(&mut self, now: Instant) -> Option<Learner> {
+        if !self.needs_learning(now) {
+            return None;
+        }
+        self.learning = true;
+        Some(Learner {
+            defined: self.defined.clone(),
+            min_uses: self.min_uses,
+        })
+    }
+
+    /// Swap in what a `Learner` learned at `now`, or keep the old table if
+    /// learning failed. Relearning waits `RELEARN_INTERVAL` either way.
+    pub fn finish_learning(&mut self, learned: Option<BTreeMap<String, String>>, now: Instant) {
+        if !self.learning {
+            // Replaced by a new table since; its definitions may differ
+            return;
+        }
+        self.learning = false;
+        self.learned_at = Some(now);
+        if let Some(learned) = learned {
+            debug!(learned = learned.len(), "Learned abbreviations");
+            self.learned = learned;
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.defined
+            .get(name)
+            .or_else(|| self.learned.get(name))
+            .map(String::as_str)
+    }
+
+    /// Every abbreviation, sorted by name.
+    pub fn list(&self) -> Vec<Abbreviation> {
+        let defined = self.defined.iter().map(|(name, expansion)| Abbreviation {
+            name: name.clone(),
+            expansion: expansion.clone(),
+            learned: false,
+        });
+        let learned = self
+            .learned
+            .iter()
+            .filter(|(name, _)| !self.defined.contains_key(*name))
+            .map(|(name, expansion)| Abbreviation {
+                name: name.clone(),
+                expansion: expansion.clone(),
+                learned: true,
+            });
+        let mut all: Vec<_> = defined.chain(learned).collect();
+        all.sort_by(|a, b| a.name.cmp(&b.name));
+        all
+    }
+
+    /// Expand the word ending at `cursor_pos` (in characters) if it is an
+    /// abbreviation in command position.
+    pub fn expand(&self, input: &str, cursor_pos: usize) -> AbbrExpandResponse {
+        let cursor = input
+            .char_indices()
+            .nth(cursor_pos)
+            .map_or(input.len(), |(i, _)| i);
+        let (before, after) = input.split_at(cursor);
+        let unchanged = AbbrExpandResponse {
+            expanded: false,
+            input: input.to_string(),
+            cursor_pos: before.chars().count(),
+            abbreviation: None,
+        };
+        // The cursor must be at the end of the word, not inside it
+        if after.starts_with(|c: char| !c.is_whitespace()) {
+            return unchanged;
+        }
+        let start = lexer::segment_start(before);
+        let word = &before[start..];
+        if word.is_empty() || word.contains(char::is_whitespace) {
+            return unchanged;
+        }
+        let Some(expansion) = self.get(word) else {
+            return unchanged;
+        };
+        let head = &before[..start];
+        AbbrExpandResponse {
+            expanded: true,
+            input: format!("{head}{expansion}{after}"),
+            cursor_pos: head.chars().count() + expansion.chars().count(),
+            abbreviation: Some(word.to_string()),
+        }
+    }
+}
+
+/// The first two words of a command, if both are plain words: a program
+/// and a subcommand, not a flag, path or quoted argument.
+fn phrase(words: &[String]) -> Option<String> {
+    let plain = |w: &str| {
+        w.len() > 1
+            && w.starts_with(|c: char| c.is_ascii_lowercase())
+            && w.chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+    };
+    match words {
+        [program, subcommand, ..] if plain(program) && plain(subcommand) => {
+            Some(format!("{program} {subcommand}"))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, ManualClock};
+
+    fn abbreviations(define: &[(&str, &str)]) -> Abbreviations {
+        let config = AbbreviationsConfig {
+            learn: true,
+            min_uses: 3,
+            define: define
+                .iter()
+                .map(|(n, e)| (n.to_string(), e.to_string()))
+                .collect(),
+        };
+        Abbreviations::from_config(&config)
+    }
+
+    fn entry(command: &str, exit_code: i32) -> HistoryEntry {
+        HistoryEntry {
+            command: command.to_string(),
+            cwd: "/repo".to_string(),
+            source: "zsh".to_string(),
+            exit_code,
+            timestamp: 0,
+            session_id: None,
+            host: None,
+            suggested_by: None,
+        }
+    }
+
+    #[test]
+    fn only_words_in_command_position_expand() {
+        let abbrs = abbreviations(&[("gco", "git checkout"), ("k", "kubectl")]);
+        let expanded = abbrs.expand("gco", 3);
+        assert!(expanded.expanded);
+        assert_eq!(
+            (expanded.input.as_str(), expanded.cursor_pos),
+            ("git checkout", 12)
+        );
+        assert_eq!(expanded.abbreviation.as_deref(), Some("gco"));
+
+        let expanded = abbrs.expand("make && k get pods", 9);
+        assert_eq!(expanded.input, "make && kubectl get pods");
+        assert_eq!(expanded.cursor_pos, 15);
+
+        // An argument, a word being typed in the middle, an unknown word
+        assert!(!abbrs.expand("echo gco", 8).expanded);
+        assert!(!abbrs.expand("gcoo", 3).expanded);
+        assert!(!abbrs.expand("ls", 2).expanded);
+        let unchanged = abbrs.expand("échо k", 6);
+        assert_eq!(
+            (unchanged.input.as_str(), unchanged.cursor_pos),
+            ("échо k", 6)
+        );
+    }
+
+    #[test]
+    fn frequent_subcommands_are_learned_by_initials() {
+        let mut abbrs = abbreviations(&[("gs", "git status")]);
+        let mut entries = vec![];
+        for _ in 0..5 {
+            entries.push(entry("git checkout main", 0));
+            entries.push(entry("git status", 0));
+            entries.push(entry("docker compose up -d", 0));
+        }
+        for _ in 0..3 {
+            entries.push(entry("git commit -m wip", 0));
+            entries.push(entry("cargo test --workspace", 0));
+        }
+        // Failures, flags and rare commands don't count
+        for _ in 0..5 {
+            entries.push(entry("npm run build", 1));
+            entries.push(entry("ls -la", 0));
+        }
+        entries.push(entry("kubectl get pods", 0));
+
+        let clock = ManualClock::new(0);
+        let learner = abbrs.start_learning(clock.now()).unwrap();
+        // One learner at a time
+        assert!(abbrs.start_learning(clock.now()).is_none());
+        let learned = learner.learn(&entries, |name| name == "dc");
+        abbrs.finish_learning(Some(learned), clock.now());
+        assert!(!abbrs.needs_learning(clock.now()));
+        let learned: Vec<_> = abbrs
+            .list()
+            .into_iter()
+            .filter(|a| a.learned)
+            .map(|a| (a.name, a.expansion))
+            .collect();
+        assert_eq!(
+            learned,
+            [
+                // `git checkout` is used more, so it gets `gc`
+                ("ct".to_string(), "cargo test".to_string()),
+                ("gc".to_string(), "git checkout".to_string()),
+            ]
+        );
+        // The defined one is kept
+        assert_eq!(abbrs.get("gs"), Some("git status"));
+    }
+
+    #[test]
+    fn learned_abbreviations_go_stale() {
+        let clock = ManualClock::new(0);
+        let mut abbrs = abbreviations(&[]);
+        abbrs.start_learning(clock.now()).unwrap();
+        abbrs.finish_learning(None, clock.now());
+
+        clock.advance(RELEARN_INTERVAL - Duration::from_secs(1));
+        assert!(abbrs.start_learning(clock.now()).is_none());
+        clock.advance(Duration::from_secs(1));
+        assert!(abbrs.start_learning(clock.now()).is_some());
+    }
+
+    #[test]
+    fn a_replaced_table_ignores_the_old_learner() {
+        let clock = ManualClock::new(0);
+        let mut abbrs = abbreviations(&[]);
+        abbrs.start_learning(clock.now()).unwrap();
+        let learned = BTreeMap::from([("gc".to_string(), "git checkout".to_string())]);
+
+        abbrs = abbreviations(&[("gc", "git commit")]);
+        abbrs.finish_learning(Some(learned), clock.now());
+        assert_eq!(abbrs.get("gc"), Some("git commit"));
+        assert!(abbrs.needs_learning(clock.now()));
+    }
+}
@@ -0,0 +1,242 @@
+//! Natural-language questions about command history.
+//!
+//! `murmur history ask "what did I deploy on friday"` runs in two steps: a
+//! provider translates the question into a `HistoryFilter` (local times,
+//! keywords, cwd, ...), the daemon runs that filter against the history,
+//! and the same provider then summarizes the matches. The model never sees
+//! more history than the filter selected.
+
+use crate::assets;
+use crate::budget::{self, Admit};
+use crate::history::CommandHistory;
+use crate::localtime;
+use murmur_protocol::{HistoryAskRequest, HistoryAskResponse, HistoryEntry, HistoryFilter};
+use murmur_providers::Provider;
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::{debug, warn};
+
+/// Entries returned when the request doesn't set `limit`.
+pub const DEFAULT_ASK_LIMIT: usize = 50;
+
+/// Most entries shown to the provider when summarizing.
+const SUMMARY_ENTRIES: usize = 40;
+
+/// System prompt for translating a question into a filter.
+pub const ASK_SYSTEM_PROMPT: &str = "You translate a question about someone's shell \
+     command history into a search filter. Respond ONLY with a JSON object with these \
+     optional fields: \"since\" and \"until\" (local times as \"YYYY-MM-DD HH:MM\", until \
+     is exclusive), \"keywords\" (array; a command matches if it contains any of them, so \
+     include the commands likely meant, e.g. for deploys: deploy, kubectl apply, helm \
+     upgrade), \"cwd\" (directory; use \".\" for the current one), \"source\" (one of \
+     \"terminal\", \"claude-code\", \"codex\") and \"failed_only\" (true for questions \
+     about failures). Morning is 06:00-12:00, afternoon 12:00-18:00, evening \
+     18:00-24:00. Omit fields the question doesn't constrain.";
+
+/// System prompt for summarizing the matching entries.
+pub const SUMMARY_SYSTEM_PROMPT: &str = "You answer a question about someone's shell \
+     command history from the matching commands listed. Reply with one or two plain \
+     sentences, no markdown. Mention concrete commands and times where useful.";
+
+/// User prompt for interpreting `request` at time `now`.
+pub fn build_prompt(request: &HistoryAskRequest, now: u64) -> String {
+    let mut prompt = format!("Now: {}\n", localtime::format_local_with_weekday(now));
+    if let Some(ref cwd) = request.cwd {
+        prompt.push_str(&format!("Current directory: {cwd}\n"));
+    }
+    prompt.push_str(&format!("Question: {}", request.question.trim()));
+    prompt
+}
+
+/// Parse the model's filter. Times are local wall-clock strings (or unix
+/// seconds); a cwd of "." means the directory the question was asked from.
+pub fn parse_filter(text: &str, cwd: Option<&str>) -> Option<HistoryFilter> {
+    #[derive(Deserialize)]
+    struct RawFilter {
+        since: Option<Value>,
+        until: Option<Value>,
+        #[serde(default)]
+        keywords: Vec<String>,
+        cwd: Option<String>,
+        source: Option<String>,
+        #[serde(default)]
+        failed_only: bool,
+    }
+
+    let start = text.find('{')?;
+    let end = text.rfind('}')?;
+    let raw: RawFilter = match serde_json::from_str(text.get(start..=end)?) {
+        Ok(raw) => raw,
+        Err(e) => {
+            debug!(error = %e, "History filter is not valid JSON");
+            return None;
+        }
+    };
+
+    let time = |value: Option<Value>| match value? {
+        Value::Number(n) => n.as_u64(),
+        Value::String(s) => localtime::parse_local(&s),
+        _ => None,
+    };
+    Some(HistoryFilter {
+        since: time(raw.since),
+        until: time(raw.until),
+        keywords: raw
+            .keywords
+            .into_iter()
+            .map(|k| k.trim().to_string())
+            .filter(|k| !k.is_empty())
+            .collect(),
+        cwd: match raw.cwd.as_deref().map(str::trim) {
+            Some("." | "./") => cwd.map(String::from),
+            Some("") | None => None,
+            Some(dir) => Some(dir.to_string()),
+        },
+        source: raw.source.filter(|s| !s.trim().is_empty()),
+        failed_only: raw.failed_only,
+    })
+}
+
+/// Ask each provider in turn until one returns a usable filter. Returns the
+/// filter together with the provider, which is reused for the summary.
+pub async fn interpret(
+    chain: &[Arc<dyn Provider>],
+    budget: &dyn Admit,
+    request: &HistoryAskRequest,
+    now: u64,
+) -> Result<(HistoryFilter, Arc<dyn Provider>), String> {
+    let prompt = build_prompt(request, now);
+    let system = assets::prompt("history-ask", ASK_SYSTEM_PROMPT);
+    let mut attempted = false;
+    for provider in chain {
+        if !budget.admit(provider.as_ref()).await {
+            continue;
+        }
+        attempted = true;
+        match provider.generate(&system, &prompt).await {
+            Ok(text) => match parse_filter(&text, request.cwd.as_deref()) {
+                Some(filter) => return Ok((filter, provider.clone())),
+                None => debug!(provider = provider.name(), "No usable history filter"),
+            },
+            Err(e) => warn!(provider = provider.name(), error = %e, "History question failed"),
+        }
+    }
+
+    if !attempted && !chain.is_empty() {
+        return Err(budget::EXHAUSTED.to_string());
+    }
+    if chain.is_empty() {
+        Err(
+            "No provider supports history questions; configure anthropic, openai, gemini, openrouter or ollama"
+                .to_string(),
+        )
+    } else {
+        Err("All providers failed to interpret the question".to_string())
+    }
+}
+
+/// One or two sentences answering `question` from `entries`. Falls back to
+/// a plain count when the provider fails or the budget is spent.
+pub async fn summarize(
+    provider: &dyn Provider,
+    budget: &dyn Admit,
+    question: &str,
+    entries: &[HistoryEntry],
+) -> String {
+    if entries.is_empty() {
+        return "No matching commands found.".to_string();
+    }
+    if !budget.admit(provider).await {
+        return fallback_summary(entries);
+    }
+
+    let mut prompt = format!(
+        "Question: {}\nMatching commands (newest first):\n",
+        question.trim()
+    );
+    for entry in entries.iter().take(SUMMARY_ENTRIES) {
+        let status = if entry.exit_code == 0 {
+            String::new()
+        } else {
+            format!(" [exit {}]", entry.exit_code)
+        };
+        prompt.push_str(&format!(
+            "{}  {}{status}\n",
+            localtime::format_local(entry.timestamp),
+            entry.command
+        ));
+    }
+
+    let system = assets::prompt("history-answer", SUMMARY_SYSTEM_PROMPT);
+    match provider.generate(&system, &prompt).await {
+        Ok(text) if !text.trim().is_empty() => text.trim().to_string(),
+        Ok(_) => fallback_summary(entries),
+        Err(e) => {
+            warn!(provider = provider.name(), error = %e, "History summary failed");
+            fallback_summary(entries)
+        }
+    }
+}
+
+/// Answer `request` from `history`: interpret, search, summarize.
+pub async fn run(
+    chain: &[Arc<dyn Provider>],
+    budget: &dyn Admit,
+    history: &Mutex<CommandHistory>,
+    request: &HistoryAskRequest,
+    now: u64,
+) -> Result<HistoryAskResponse, String> {
+    let (filter, provider) = interpret(chain, budget, request, now).await?;
+    debug!(?filter, "Interpreted history question");
+    let limit = request.limit.unwrap_or(DEFAULT_ASK_LIMIT).max(1);
+    let entries = history
+        .lock()
+        .await
+        .search(&filter, limit)
+        .map_err(|e| format!("History search failed: {e}"))?;
+    let summary = summarize(provider.as_ref(), budget, &request.question, &entries).await;
+    Ok(HistoryAskResponse {
+        filter,
+        entries,
+        summary,
+        provider: provider.name().to_string(),
+    })
+}
+
+fn fallback_summary(entries: &[HistoryEntry]) -> String {
+    let newest = entries.first().map_or(0, |e| e.timestamp);
+    let oldest = entries.last().map_or(0, |e| e.timestamp);
+    format!(
+        "{} matching command{} between {} and {}.",
+        entries.len(),
+        if entries.len() == 1 { "" } else { "s" },
+        localtime::format_local(oldest),
+        localtime::format_local(newest)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_filter_with_local_times() {
+        let text = "```json\n{\"since\": \"2026-10-16 12:00\", \"until\": \"2026-10-16 18:00\", \
+                    \"keywords\": [\"deploy\", \" \"], \"cwd\": \".\", \"failed_only\": true}\n```";
+        let filter = parse_filter(text, Some("/work/app")).unwrap();
+        assert_eq!(filter.since, localtime::parse_local("2026-10-16 12:00"));
+        assert_eq!(filter.until.unwrap() - filter.since.unwrap(), 6 * 3600);
+        assert_eq!(filter.keywords, vec!["deploy"]);
+        assert_eq!(filter.cwd.as_deref(), Some("/work/app"));
+        assert!(filter.failed_only);
+        assert!(filter.source.is_none());
+    }
+
+    #[test]
+    fn rejects_non_json() {
+        assert!(parse_filter("I can't help with that", None).is_none());
+        assert_eq!(parse_filter("{}", None), Some(HistoryFilter::default()));
+    }
+}
@@ -0,0 +1,214 @@
+//! Files built into the binary, so a `murmur` installed anywhere (Homebrew,
+//! `cargo install`) needs nothing from the source tree: the shell
+//! integration scripts, the Claude Code, Codex and launcher integrations,
+//! and the system prompts for commit messages, queries and the rest.
+//!
+//! Assets have paths like `shell/zsh/murmur.zsh` or `prompts/commit.txt`.
+//! A file at the same path under `<config dir>/assets/` is used instead of
+//! the built-in one; `murmur assets export` writes built-in files out as a
+//! starting point for editing.
+
+use crate::config::Config;
+use crate::{ask, branch, commit, cron, diagnose, draft, expr, pattern, query, summary};
+use include_dir::{include_dir, Dir, File};
+use murmur_protocol::ExprTool;
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+
+static SHELL: Dir<'static> = include_dir!("$CARGO_MANIFEST_DIR/../../shell-integration");
+static INTEGRATIONS: Dir<'static> = include_dir!("$CARGO_MANIFEST_DIR/../../integrations");
+
+/// Embedded directories and the path prefix their files go under.
+const DIRS: [(&str, &Dir<'static>); 2] = [("shell", &SHELL), ("integrations", &INTEGRATIONS)];
+
+/// Where files overriding the built-in assets live.
+pub fn overrides_dir() -> PathBuf {
+    Config::config_dir().join("assets")
+}
+
+/// The system prompts, by name.
+fn prompts() -> Vec<(&'static str, Cow<'static, str>)> {
+    let mut prompts: Vec<(&str, Cow<str>)> = vec![
+        ("commit", commit::COMMIT_SYSTEM_PROMPT.into()),
+        ("branch", branch::BRANCH_SYSTEM_PROMPT.into()),
+        ("pr", draft::PR_SYSTEM_PROMPT.into()),
+        ("changelog", draft::CHANGELOG_SYSTEM_PROMPT.into()),
+        ("query", query::QUERY_SYSTEM_PROMPT.into()),
+        ("diagnose", diagnose::DIAGNOSE_SYSTEM_PROMPT.into()),
+        ("regex", pattern::REGEX_SYSTEM_PROMPT.into()),
+        ("cron", cron::CRON_SYSTEM_PROMPT.into()),
+        ("history-ask", ask::ASK_SYSTEM_PROMPT.into()),
+        ("history-answer", ask::SUMMARY_SYSTEM_PROMPT.into()),
+        ("session-summary", summary::SUMMARY_SYSTEM_PROMPT.into()),
+    ];
+    for (name, tool) in [
+        ("expr-jq", ExprTool::Jq),
+        ("expr-awk", ExprTool::Awk),
+        ("expr-sed", ExprTool::Sed),
+    ] {
+        prompts.push((name, expr::system_prompt(tool).into()));
+    }
+    prompts
+}
+
+fn files(dir: &'static Dir<'static>, out: &mut Vec<&'static File<'static>>) {
+    out.extend(dir.files());
+    for sub in dir.dirs() {
+        files(sub, out);
+    }
+}
+
+/// Every built-in asset's path, sorted.
+pub fn list() -> Vec<String> {
+    let mut paths = Vec::new();
+    for (prefix, dir) in DIRS {
+        let mut found = Vec::new();
+        files(dir, &mut found);
+        paths.extend(
+            found
+                .into_iter()
+                // Bytecode a local run left behind isn't an asset
+                .filter(|file| file.contents_utf8().is_some())
+                .map(|file| format!("{prefix}/{}", file.path().display())),
+        );
+    }
+    paths.extend(
+        prompts()
+            .into_iter()
+            .map(|(name, _)| format!("prompts/{name}.txt")),
+    );
+    paths.sort();
+    paths
+}
+
+/// The built-in asset at `path`.
+pub fn builtin(path: &str) -> Option<Cow<'static, str>> {
+    if let Some(name) = path
+        .strip_prefix("prompts/")
+        .and_then(|rest| rest.strip_suffix(".txt"))
+    {
+        return prompts()
+            .into_iter()
+            .find(|(prompt, _)| *prompt == name)
+            .map(|(_, text)| Cow::Owned(format!("{text}\n")));
+    }
+    let (prefix, rest) = path.split_once('/')?;
+    let (_, dir) = DIRS.iter().find(|(dir_prefix, _)| *dir_prefix == prefix)?;
+    dir.get_file(rest)?.contents_utf8().map(Cow::Borrowed)
+}
+
+/// The asset at `path`: its override if there is one, else the built-in.
+pub fn get(path: &str) -> Option<Cow<'static, str>> {
+    match std::fs::read_to_string(overrides_dir().join(path)) {
+        Ok(text) => Some(Cow::Owned(text)),
+        Err(_) => builtin(path),
+    }
+}
+
+/// Whether `path` is overridden.
+pub fn is_overridden(path: &str) -> bool {
+    overrides_dir().join(path).is_file()
+}
+
+/// System prompt `name`, or `prompts/<name>.txt` under the overrides
+/// directory when that file has text.
+pub fn prompt(name: &str, default: &str) -> String {
+    prompt_in(&overrides_dir(), name, default)
+}
+
+fn prompt_in(dir: &Path, name: &str, default: &str) -> String {
+    std::fs::read_to_string(dir.join(format!("prompts/{name}.txt")))
+        .ok()
+        .map(|text| text.trim().to_string())
+        .filter(|text| !text.is_empty())
+        .unwrap_or_else(|| default.to_string())
+}
+
+/// Write the built-in assets whose paths start with one of `only` (all of
+/// them when it's empty) under `dir`. Files already there are kept unless
+/// `force`. Returns the paths written.
+pub fn export(dir: &Path, only: &[String], force: bool) -> std::io::Result<Vec<String>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut written = Vec::new();
+    for path in list() {
+        if !only.is_empty() && !only.iter().any(|prefix| path.starts_with(prefix.as_str())) {
+            continue;
+        }
+        let target = dir.join(&path);
+        if target.exists() && !force {
+            continue;
+        }
+        let Some(text) = builtin(&path) else {
+            continue;
+        };
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&target, text.as_bytes())?;
+        // Hooks and launcher scripts are run directly
+        if path.ends_with(".sh") || path.ends_with(".py") {
+            std::fs::set_permissions(&target, std::fs::Permissions::from_mode(0o755))?;
+        }
+        written.push(path);
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scripts_integrations_and_prompts_are_built_in() {
+        let paths = list();
+        for path in [
+            "shell/zsh/murmur.zsh",
+            "shell/terminals/wezterm.lua",
+            "integrations/claude-code/hooks/murmur-learn.sh",
+            "prompts/commit.txt",
+            "prompts/expr-jq.txt",
+        ] {
+            assert!(paths.iter().any(|p| p == path), "{path} missing");
+            assert!(builtin(path).is_some_and(|text| !text.is_empty()));
+        }
+        assert!(!paths.iter().any(|p| p.contains("__pycache__")));
+        assert_eq!(
+            builtin("prompts/commit.txt").unwrap().trim(),
+            commit::COMMIT_SYSTEM_PROMPT
+        );
+        assert!(builtin("prompts/missing.txt").is_none());
+        assert!(builtin("elsewhere/murmur.zsh").is_none());
+    }
+
+    #[test]
+    fn exports_keep_edited_files_and_overrides_win() {
+        let dir = std::env::temp_dir().join(format!("murmur-assets-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let written = export(&dir, &["prompts/".to_string()], false).unwrap();
+        assert_eq!(written.len(), prompts().len());
+        assert!(!dir.join("shell").exists());
+
+        std::fs::write(dir.join("prompts/commit.txt"), "Write haiku.\n").unwrap();
+        assert!(export(&dir, &["prompts/commit".to_string()], false)
+            .unwrap()
+            .is_empty());
+        assert_eq!(prompt_in(&dir, "commit", "default"), "Write haiku.");
+        assert_eq!(prompt_in(&dir, "nothing", "default"), "default");
+
+        std::fs::write(dir.join("prompts/branch.txt"), "  \n").unwrap();
+        assert_eq!(prompt_in(&dir, "branch", "default"), "default");
+
+        export(&dir, &["integrations/launchers".to_string()], true).unwrap();
+        let mode = std::fs::metadata(dir.join("integrations/launchers/raycast-murmur-query.sh"))
+            .unwrap()
+            .permissions();
+        assert_eq!(
+            std::os::unix::fs::PermissionsExt::mode(&mode) & 0o111,
+            0o111
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
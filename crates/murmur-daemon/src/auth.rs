@@ -0,0 +1,172 @@
+//! API tokens for the network listeners (`listen_tcp`, `listen_ws`).
+//!
+//! The network listeners don't start without `[[daemon.tokens]]`. A TCP or
+//! WebSocket connection can only `initialize`, `ping` and negotiate
+//! compression until `initialize` presents one of the tokens. Each method
+//! then needs a scope the token has: `complete` for completions and the
+//! other suggestions, `history` for reading and recording history, `repo`
+//! for the methods that read a git repository, `admin` for the rest.
+//! Tokens are looked up again for every request, so one removed from the
+//! config stops working at the next reload, even on open connections.
+//!
+//! The Unix socket, which only its owner can open, allows everything.
+
+use crate::config::ApiToken;
+use murmur_protocol::{methods, Scope};
+
+/// What a connection has authenticated as.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Access {
+    /// A Unix socket connection.
+    Full,
+    /// A network connection that hasn't sent a token.
+    Anonymous,
+    /// A network connection that sent this token.
+    Token(String),
+}
+
+impl Access {
+    /// Whether the connection may call `method`, given the configured
+    /// tokens.
+    pub fn permits(&self, method: &str, tokens: &[ApiToken]) -> bool {
+        self.allows(required_scope(method), tokens)
+    }
+
+    /// Whether the connection has `scope` (`None` needs no scope).
+    pub fn allows(&self, scope: Option<Scope>, tokens: &[ApiToken]) -> bool {
+        let Some(needed) = scope else {
+            return true;
+        };
+        match self {
+            Access::Full => true,
+            Access::Anonymous => false,
+            Access::Token(token) => find(tokens, token)
+                .is_some_and(|found| found.scopes.iter().any(|scope| scope.grants(needed))),
+        }
+    }
+}
+
+/// The scope `method` needs, or `None` for the methods that set up a
+/// connection. Unknown methods need `admin`.
+pub fn required_scope(method: &str) -> Option<Scope> {
+    match method {
+        methods::INITIALIZE | methods::PING | methods::COMPRESSION_NEGOTIATE => None,
+        methods::COMPLETE
+        | methods::COMPLETE_CANDIDATES
+        | methods::COMPLETE_CANCEL
+        | methods::ABBR_EXPAND
+        | methods::ABBR_LIST
+        | methods::QUERY
+        | methods::EXPR_SUGGEST
+        | methods::REGEX_SUGGEST
+        | methods::CRON_SUGGEST
+        | methods::DIAGNOSE
+        | methods::HEALTH
+        | methods::SESSION_OPEN
+        | methods::EVENTS_SUBSCRIBE => Some(Scope::Complete),
+        // Runs over a corpus directory the client names
+        methods::VOICE_SELFTEST => Some(Scope::Admin),
+        method if method.starts_with("voice/") => Some(Scope::Complete),
+        methods::HISTORY_LIST | methods::HISTORY_ASK | methods::CONTEXT_UPDATE => {
+            Some(Scope::History)
+        }
+        // Run git in the client's `cwd` and send what it prints to providers
+        methods::COMMIT_SUGGEST | methods::SUMMARIZE_COMMITS | methods::BRANCH_SUGGEST => {
+            Some(Scope::Repo)
+        }
+        _ => Some(Scope::Admin),
+    }
+}
+
+/// The scope an `events/subscribe` client needs to be sent notification
+/// `method`: new history entries are history, the rest `complete`.
+pub fn notification_scope(method: &str) -> Option<Scope> {
+    match method {
+        methods::HISTORY_UPDATED => Some(Scope::History),
+        _ => Some(Scope::Complete),
+    }
+}
+
+/// The configured token equal to `presented`.
+pub fn find<'a>(tokens: &'a [ApiToken], presented: &str) -> Option<&'a ApiToken> {
+    tokens
+        .iter()
+        .find(|token| constant_time_eq(token.token.as_bytes(), presented.as_bytes()))
+}
+
+/// Compare without returning early, so timing doesn't reveal how much of
+/// a guess was right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(value: &str, scopes: &[Scope]) -> ApiToken {
+        ApiToken {
+            name: "test".to_string(),
+            token: value.to_string(),
+            scopes: scopes.to_vec(),
+        }
+    }
+
+    #[test]
+    fn methods_need_their_scope() {
+        let tokens = [
+            token("thin", &[Scope::Complete]),
+            token("root", &[Scope::Admin]),
+            token("repo", &[Scope::Repo]),
+        ];
+        let thin = Access::Token("thin".to_string());
+        assert!(thin.permits(methods::COMPLETE, &tokens));
+        assert!(thin.permits(methods::VOICE_PROCESS, &tokens));
+        assert!(!thin.permits(methods::HISTORY_LIST, &tokens));
+        assert!(!thin.permits(methods::VOICE_SELFTEST, &tokens));
+        assert!(!thin.permits(methods::CONTEXT_UPDATE, &tokens));
+        for method in [
+            methods::COMMIT_SUGGEST,
+            methods::SUMMARIZE_COMMITS,
+            methods::BRANCH_SUGGEST,
+        ] {
+            assert!(!thin.permits(method, &tokens), "{method}");
+        }
+        assert!(!thin.permits(methods::SHUTDOWN, &tokens));
+        assert!(!thin.permits("made/up", &tokens));
+
+        let root = Access::Token("root".to_string());
+        assert!(root.permits(methods::SHUTDOWN, &tokens));
+        assert!(root.permits(methods::VOICE_SELFTEST, &tokens));
+        assert!(root.permits(methods::HISTORY_ASK, &tokens));
+
+        let repo = Access::Token("repo".to_string());
+        assert!(repo.permits(methods::COMMIT_SUGGEST, &tokens));
+        assert!(repo.permits(methods::BRANCH_SUGGEST, &tokens));
+        assert!(!repo.permits(methods::HISTORY_LIST, &tokens));
+
+        assert!(!thin.allows(notification_scope(methods::HISTORY_UPDATED), &tokens));
+        assert!(thin.allows(notification_scope(methods::COMPLETION_READY), &tokens));
+
+        let stranger = Access::Token("nope".to_string());
+        assert!(!stranger.permits(methods::COMPLETE, &tokens));
+        assert!(stranger.permits(methods::INITIALIZE, &tokens));
+    }
+
+    #[test]
+    fn anonymous_connections_only_set_up() {
+        assert!(!Access::Anonymous.permits(methods::SHUTDOWN, &[]));
+        assert!(!Access::Anonymous.permits(methods::CONFIG_RELOAD, &[]));
+        assert!(Access::Anonymous.permits(methods::INITIALIZE, &[]));
+        let tokens = [token("thin", &[Scope::Complete])];
+        assert!(!Access::Anonymous.permits(methods::COMPLETE, &tokens));
+        assert!(Access::Anonymous.permits(methods::PING, &tokens));
+        assert!(Access::Full.permits(methods::SHUTDOWN, &tokens));
+        // A token removed by a reload stops working
+        let others = [token("other", &[Scope::Admin])];
+        assert!(!Access::Token("thin".to_string()).permits(methods::COMPLETE, &others));
+    }
+}
@@ -0,0 +1,391 @@
+//! Branch names from a description of the work (`murmur branch`).
+//!
+//! A provider boils the description down to a change type and a few
+//! words; the name itself is put together here from `[branch] pattern`,
+//! so it is kebab-case and within `max_length` whatever the model replies.
+//! Tickets (`PROJ-42`, `#42`, an issue URL) are found in the description
+//! or the clipboard text without asking the model. When no provider
+//! answers, the description's own words are used.
+
+use murmur_protocol::{BranchSuggestRequest, BranchSuggestResponse};
+use murmur_providers::Provider;
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+use crate::assets;
+use crate::budget::Admit;
+use crate::config::BranchConfig;
+
+/// Change types a branch may be named for.
+const TYPES: &[&str] = &[
+    "feat", "fix", "docs", "refactor", "perf", "test", "build", "ci", "chore",
+];
+
+/// Most words of a description used when no provider summarizes it.
+const MAX_FALLBACK_WORDS: usize = 6;
+
+/// Words left out when the description's own words are used.
+const STOP_WORDS: &[&str] = &[
+    "a", "an", "the", "to", "of", "for", "in", "on", "at", "and", "or", "with", "when", "is",
+];
+
+/// System prompt for summarizing a description into a branch name.
+pub const BRANCH_SYSTEM_PROMPT: &str = "You name git branches. Respond ONLY with one line \
+     `type: words`, where type is one of feat, fix, docs, refactor, perf, test, build, ci \
+     or chore, and words are two to five lowercase words saying what the work is, e.g. \
+     `fix: login timeout on slow networks`. No commentary.";
+
+/// The ticket `text` mentions: a `PROJ-42` key, `#42`, or the number of an
+/// issue, pull request or merge request URL.
+pub fn find_ticket(text: &str) -> Option<String> {
+    for word in text.split_whitespace() {
+        if word.contains("://") {
+            let segments: Vec<&str> = word.split(['/', '?', '#']).collect();
+            for (i, segment) in segments.iter().enumerate() {
+                if is_key(segment) {
+                    return Some(segment.to_string());
+                }
+                let numbered = matches!(*segment, "issues" | "pull" | "merge_requests");
+                if let Some(number) = segments.get(i + 1).filter(|n| numbered && is_number(n)) {
+                    return Some(number.to_string());
+                }
+            }
+            continue;
+        }
+        let word = word.trim_matches(|c: char| !c.is_alphanumeric() && c != '#');
+        if is_key(word) {
+            return Some(word.to_string());
+        }
+        if let Some(number) = word.strip_prefix('#').filter(|n| is_number(n)) {
+            return Some(number.to_string());
+        }
+    }
+    None
+}
+
+/// Whether `word` is a tracker key like `PROJ-42`.
+fn is_key(word: &str) -> bool {
+    let Some((project, number)) = word.split_once('-') else {
+        return false;
+    };
+    project.len() > 1
+        && project.starts_with(|c: char| c.is_ascii_uppercase())
+        && project
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+        && is_number(number)
+}
+
+fn is_number(word: &str) -> bool {
+    !word.is_empty() && word.chars().all(|c| c.is_ascii_digit())
+}
+
+/// A `type:` the description starts with, and the rest of it.
+fn split_type(description: &str) -> (Option<&'static str>, &str) {
+    let Some((head, rest)) = description.split_once(':') else {
+        return (None, description);
+    };
+    match TYPES.iter().find(|t| head.trim().eq_ignore_ascii_case(t)) {
+        Some(kind) => (Some(*kind), rest.trim()),
+        None => (None, description),
+    }
+}
+
+/// The change type a description's first word suggests.
+fn guess_type(description: &str) -> &'static str {
+    let first = description
+        .split_whitespace()
+        .next()
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    match first.as_str() {
+        "fix" | "bug" | "bugfix" | "hotfix" | "repair" | "crash" => "fix",
+        "doc" | "docs" | "document" | "readme" => "docs",
+        "refactor" | "cleanup" | "clean" | "simplify" | "rename" => "refactor",
+        "test" | "tests" => "test",
+        "bump" | "upgrade" => "chore",
+        other => TYPES
+            .iter()
+            .copied()
+            .find(|t| *t == other)
+            .unwrap_or("feat"),
+    }
+}
+
+/// Lowercase ASCII words of `text`, optionally without stop words.
+fn words(text: &str, skip_stop_words: bool) -> Vec<String> {
+    text.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_ascii_lowercase)
+        .filter(|w| !skip_stop_words || !STOP_WORDS.contains(&w.as_str()))
+        .collect()
+}
+
+/// The type and words in a model's `type: words` reply.
+pub fn parse_reply(text: &str) -> Option<(String, Vec<String>)> {
+    let line = text.lines().map(str::trim).find(|l| !l.is_empty())?;
+    let line = line.trim_matches(|c| c == '`' || c == '"');
+    let (kind, rest) = line.split_once(':')?;
+    // A scope, if the model added one, isn't part of the name
+    let kind = kind
+        .split('(')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase();
+    let kind = TYPES.iter().find(|t| **t == kind)?;
+    let words = words(rest, false);
+    (!words.is_empty()).then(|| (kind.to_string(), words))
+}
+
+/// `pattern` filled in, with words dropped from the end of the slug
+/// until the name fits in `max_length`.
+pub fn render(
+    pattern: &str,
+    prefix: &str,
+    ticket: Option<&str>,
+    words: &[String],
+    max_length: usize,
+) -> String {
+    let mut words = words.to_vec();
+    loop {
+        let name = tidy(
+            &pattern
+                .replace("{type}", prefix)
+                .replace("{ticket}", ticket.unwrap_or(""))
+                .replace("{slug}", &words.join("-")),
+        );
+        if name.len() <= max_length || words.len() <= 1 {
+            return cut(&name, max_length);
+        }
+        words.pop();
+    }
+}
+
+/// `name` without empty path segments or separators left over from an
+/// empty part, e.g. `fix/-timeout` becomes `fix/timeout`.
+fn tidy(name: &str) -> String {
+    let is_separator = |c: char| matches!(c, '-' | '_' | '.');
+    name.split('/')
+        .map(|segment| {
+            let mut tidied = String::new();
+            for c in segment.trim_matches(is_separator).chars() {
+                if !(is_separator(c) && tidied.ends_with(is_separator)) {
+                    tidied.push(c);
+                }
+            }
+            tidied
+        })
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// `name` cut to `max_length` bytes, without a trailing separator.
+fn cut(name: &str, max_length: usize) -> String {
+    if name.len() <= max_length {
+        return name.to_string();
+    }
+    let mut end = max_length;
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    name[..end]
+        .trim_end_matches(['-', '_', '.', '/'])
+        .to_string()
+}
+
+/// `name`, or with `-2`, `-3`, ... added if a branch has it already.
+fn unique(name: String, existing: &[String]) -> String {
+    if !existing.contains(&name) {
+        return name;
+    }
+    (2..)
+        .map(|n| format!("{name}-{n}"))
+        .find(|candidate| !existing.contains(candidate))
+        .unwrap_or(name)
+}
+
+/// Name a branch for `request`, asking each provider in turn to summarize
+/// the description. `existing` are the repository's branches.
+pub async fn run(
+    chain: &[Arc<dyn Provider>],
+    budget: &dyn Admit,
+    config: &BranchConfig,
+    request: &BranchSuggestRequest,
+    existing: &[String],
+) -> Result<BranchSuggestResponse, String> {
+    let start = std::time::Instant::now();
+    let ticket = find_ticket(&request.description)
+        .or_else(|| request.clipboard.as_deref().and_then(find_ticket));
+    let description = request
+        .description
+        .split_whitespace()
+        .filter(|word| find_ticket(word).is_none())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let (explicit, rest) = split_type(&description);
+
+    let mut summary = None;
+    if !rest.is_empty() {
+        let system = assets::prompt("branch", BRANCH_SYSTEM_PROMPT);
+        for provider in chain {
+            if !budget.admit(provider.as_ref()).await {
+                continue;
+            }
+            match provider.generate(&system, &description).await {
+                Ok(text) => match parse_reply(&text) {
+                    Some(reply) => {
+                        summary = Some((reply, provider.name().to_string()));
+                        break;
+                    }
+                    None => debug!(provider = provider.name(), "Provider wrote no branch name"),
+                },
+                Err(e) => warn!(provider = provider.name(), error = %e, "Branch naming failed"),
+            }
+        }
+    }
+    let (kind, words, provider) = match summary {
+        Some(((kind, words), provider)) => (kind, words, Some(provider)),
+        None => {
+            let kind = explicit.unwrap_or_else(|| guess_type(rest));
+            let mut words = words(rest, true);
+            // "fix login timeout" shouldn't become fix/fix-login-timeout
+            if words.first().is_some_and(|w| w == kind) {
+                words.remove(0);
+            }
+            words.truncate(MAX_FALLBACK_WORDS);
+            (kind.to_string(), words, None)
+        }
+    };
+    let kind = explicit.map_or(kind, String::from);
+    if words.is_empty() && ticket.is_none() {
+        return Err("Nothing to name the branch after; describe the work".to_string());
+    }
+
+    let prefix = config.prefixes.get(&kind).unwrap_or(&kind);
+    let name = render(
+        &config.pattern,
+        prefix,
+        ticket.as_deref(),
+        &words,
+        config.max_length,
+    );
+    Ok(BranchSuggestResponse {
+        name: unique(name, existing),
+        kind,
+        ticket,
+        provider,
+        latency_ms: start.elapsed().as_millis() as u64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::budget::Unmetered;
+
+    fn strings(words: &[&str]) -> Vec<String> {
+        words.iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn tickets_are_found() {
+        assert_eq!(find_ticket("PROJ-42 fix login").as_deref(), Some("PROJ-42"));
+        assert_eq!(find_ticket("fix login (#118)").as_deref(), Some("118"));
+        assert_eq!(
+            find_ticket("https://github.com/acme/api/issues/77").as_deref(),
+            Some("77")
+        );
+        assert_eq!(
+            find_ticket("https://acme.atlassian.net/browse/OPS-9?focused=1").as_deref(),
+            Some("OPS-9")
+        );
+        assert_eq!(find_ticket("fix utf-8 decoding in x-forwarded-for"), None);
+        assert_eq!(find_ticket("https://example.com/docs/page-2"), None);
+    }
+
+    #[test]
+    fn names_follow_the_pattern() {
+        let words = strings(&["login", "timeout", "on", "slow", "networks"]);
+        let pattern = "{type}/{ticket}-{slug}";
+        assert_eq!(
+            render(pattern, "fix", Some("PROJ-42"), &words, 60),
+            "fix/PROJ-42-login-timeout-on-slow-networks"
+        );
+        // Without a ticket its separator goes too
+        assert_eq!(
+            render(pattern, "fix", None, &words, 60),
+            "fix/login-timeout-on-slow-networks"
+        );
+        assert_eq!(
+            render(pattern, "fix", None, &words, 19),
+            "fix/login-timeout"
+        );
+        assert_eq!(
+            render(
+                "{ticket}_{slug}",
+                "feat",
+                None,
+                &strings(&["dark", "mode"]),
+                60
+            ),
+            "dark-mode"
+        );
+        let long = strings(&["supercalifragilisticexpialidocious"]);
+        assert_eq!(render(pattern, "feat", None, &long, 16), "feat/supercalifr");
+    }
+
+    #[test]
+    fn replies_are_parsed() {
+        assert_eq!(
+            parse_reply("`fix(auth): Login Timeout`\n"),
+            Some(("fix".to_string(), strings(&["login", "timeout"])))
+        );
+        assert_eq!(parse_reply("feature: dark mode"), None);
+        assert_eq!(parse_reply("feat: ..."), None);
+    }
+
+    #[tokio::test]
+    async fn descriptions_are_used_when_no_provider_answers() {
+        let config = BranchConfig::default();
+        let request = |description: &str, clipboard: Option<&str>| BranchSuggestRequest {
+            description: description.to_string(),
+            clipboard: clipboard.map(String::from),
+            cwd: None,
+        };
+
+        let response = run(
+            &[],
+            &Unmetered,
+            &config,
+            &request("Fix the login timeout", None),
+            &[],
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.name, "fix/login-timeout");
+        assert_eq!(response.provider, None);
+
+        let existing = strings(&["docs/OPS-9-api-tokens"]);
+        let response = run(
+            &[],
+            &Unmetered,
+            &config,
+            &request(
+                "docs: API tokens",
+                Some("https://acme.atlassian.net/browse/OPS-9"),
+            ),
+            &existing,
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.name, "docs/OPS-9-api-tokens-2");
+        assert_eq!(response.ticket.as_deref(), Some("OPS-9"));
+        assert_eq!(response.kind, "docs");
+
+        assert!(run(&[], &Unmetered, &config, &request("the", None), &[])
+            .await
+            .is_err());
+    }
+}
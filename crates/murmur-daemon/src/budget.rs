@@ -0,0 +1,128 @@
+//! Daily request budget for cloud providers.
+
+use async_trait::async_trait;
+use murmur_providers::Provider;
+
+const SECS_PER_DAY: u64 = 86_400;
+
+/// Error for a fallback chain the budget let no provider in to.
+pub const EXHAUSTED: &str =
+    "Daily cloud budget is spent; raise [budget] daily_requests or try again tomorrow";
+
+/// Asked right before each provider call in a fallback chain, so only the
+/// providers actually reached are charged.
+#[async_trait]
+pub trait Admit: Send + Sync {
+    /// Whether `provider` may be called now, counting the call if so.
+    async fn admit(&self, provider: &dyn Provider) -> bool;
+}
+
+/// Admits every call, for work done outside the daemon's budget.
+pub struct Unmetered;
+
+#[async_trait]
+impl Admit for Unmetered {
+    async fn admit(&self, _provider: &dyn Provider) -> bool {
+        true
+    }
+}
+
+/// Counts cloud provider requests per UTC day against an optional limit.
+#[derive(Debug)]
+pub struct RequestBudget {
+    daily_limit: Option<u32>,
+    used: u32,
+    day: u64,
+}
+
+impl RequestBudget {
+    pub fn new(daily_limit: Option<u32>) -> Self {
+        Self {
+            daily_limit,
+            used: 0,
+            day: 0,
+        }
+    }
+
+    /// Record one request at `now` (unix seconds). Returns `false` without
+    /// counting it when today's budget is already spent.
+    pub fn try_spend(&mut self, now: u64) -> bool {
+        self.roll_over(now);
+        match self.daily_limit {
+            Some(limit) if self.used >= limit => false,
+            _ => {
+                self.used = self.used.saturating_add(1);
+                true
+            }
+        }
+    }
+
+    /// Requests left today (`None` = unlimited).
+    pub fn remaining(&mut self, now: u64) -> Option<u32> {
+        self.roll_over(now);
+        self.daily_limit
+            .map(|limit| limit.saturating_sub(self.used))
+    }
+
+    /// Requests counted today.
+    pub fn used(&mut self, now: u64) -> u32 {
+        self.roll_over(now);
+        self.used
+    }
+
+    /// The daily limit (`None` = unlimited).
+    pub fn daily_limit(&self) -> Option<u32> {
+        self.daily_limit
+    }
+
+    /// Change the daily limit, keeping what was already used today.
+    pub fn set_daily_limit(&mut self, daily_limit: Option<u32>) {
+        self.daily_limit = daily_limit;
+    }
+
+    fn roll_over(&mut self, now: u64) {
+        let day = now / SECS_PER_DAY;
+        if day != self.day {
+            self.day = day;
+            self.used = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unlimited_budget_always_allows() {
+        let mut budget = RequestBudget::new(None);
+        for _ in 0..100 {
+            assert!(budget.try_spend(1_000));
+        }
+        assert_eq!(budget.remaining(1_000), None);
+    }
+
+    #[test]
+    fn limit_is_enforced_and_resets_daily() {
+        let mut budget = RequestBudget::new(Some(2));
+        assert!(budget.try_spend(10));
+        assert!(budget.try_spend(20));
+        assert!(!budget.try_spend(30));
+        assert_eq!(budget.remaining(30), Some(0));
+        assert_eq!(budget.used(30), 2);
+
+        // Next day
+        assert_eq!(budget.remaining(SECS_PER_DAY + 5), Some(2));
+        assert!(budget.try_spend(SECS_PER_DAY + 5));
+    }
+
+    #[test]
+    fn lowering_the_limit_keeps_todays_count() {
+        let mut budget = RequestBudget::new(Some(10));
+        assert!(budget.try_spend(10));
+        assert!(budget.try_spend(20));
+        budget.set_daily_limit(Some(2));
+        assert_eq!(budget.remaining(30), Some(0));
+        assert!(!budget.try_spend(30));
+    }
+}
@@ -1,20 +1,152 @@
 use lru::LruCache;
-use murmur_protocol::CompletionResponse;
+use murmur_protocol::{CompletionItem, CompletionKind, CompletionResponse};
 use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
 use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-/// LRU cache for completion responses.
+use crate::clock::{Clock, SystemClock};
+use crate::config::DaemonConfig;
+use crate::fingerprint::{self, Fingerprint};
+
+/// LRU cache for completion responses, bounded by entry count and
+/// optionally by (approximate) bytes.
 pub struct CompletionCache {
     inner: LruCache<u64, CacheEntry>,
+    clock: Arc<dyn Clock>,
+    ttl: CacheTtl,
+    max_bytes: Option<usize>,
+    bytes: usize,
+    /// Keys of the entries stored with `put_typed`, by scope and input, so
+    /// `get_prefix` looks up prefixes instead of scanning.
+    typed: HashMap<u64, HashMap<String, u64>>,
 }
 
 struct CacheEntry {
     response: CompletionResponse,
-    created_at: std::time::Instant,
+    /// Directory the completion was for, so it can be invalidated.
+    cwd: String,
+    created_at: Instant,
+    ttl: Duration,
+    /// Approximate heap and inline size, for `max_bytes`.
+    size: usize,
+    /// The scope (see `scope_key`) and input it answered, for prefix
+    /// lookups.
+    typed: Option<(u64, String)>,
+}
+
+impl CacheEntry {
+    fn new(
+        response: CompletionResponse,
+        cwd: &str,
+        typed: Option<(u64, String)>,
+        created_at: Instant,
+        ttl: Duration,
+    ) -> Self {
+        let items: usize = response
+            .items
+            .iter()
+            .map(|item| {
+                std::mem::size_of::<CompletionItem>()
+                    + item.text.len()
+                    + item.description.as_ref().map_or(0, String::len)
+            })
+            .sum();
+        let size = std::mem::size_of::<Self>()
+            + response.provider.len()
+            + cwd.len()
+            + typed.as_ref().map_or(0, |(_, input)| input.len())
+            + items;
+        Self {
+            response,
+            cwd: cwd.to_string(),
+            created_at,
+            ttl,
+            size,
+            typed,
+        }
+    }
+}
+
+/// TTL when none is configured.
+pub const CACHE_TTL: Duration = Duration::from_secs(300); // 5 minutes
+
+/// How long cached responses live: `default`, unless the provider that
+/// answered or else the request's route ("shell", "code" or "local") has
+/// its own TTL, and no longer than the TTL of any kind of item in the
+/// response. Paths go stale in seconds; a subcommand table's answers are
+/// good for hours.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheTtl {
+    pub default: Duration,
+    pub by_kind: HashMap<String, Duration>,
+    pub by_provider: HashMap<String, Duration>,
+    pub by_route: HashMap<String, Duration>,
+}
+
+impl Default for CacheTtl {
+    fn default() -> Self {
+        Self {
+            default: CACHE_TTL,
+            by_kind: HashMap::new(),
+            by_provider: HashMap::new(),
+            by_route: HashMap::new(),
+        }
+    }
+}
+
+impl CacheTtl {
+    /// The TTLs set in `[daemon]`.
+    pub fn from_config(config: &DaemonConfig) -> Self {
+        let secs = |map: &HashMap<String, u64>| {
+            map.iter()
+                .map(|(name, secs)| (name.clone(), Duration::from_secs(*secs)))
+                .collect()
+        };
+        Self {
+            default: Duration::from_secs(config.cache_ttl_secs),
+            by_kind: secs(&config.cache_ttl_by_kind),
+            by_provider: secs(&config.cache_ttl_by_provider),
+            by_route: secs(&config.cache_ttl_by_route),
+        }
+    }
+
+    /// How long `response`, answered on `route`, may be served from the
+    /// cache.
+    pub fn of(&self, response: &CompletionResponse, route: Option<&str>) -> Duration {
+        let base = self
+            .by_provider
+            .get(&response.provider)
+            .or_else(|| route.and_then(|route| self.by_route.get(route)))
+            .copied()
+            .unwrap_or(self.default);
+        // Each item lives as long as its kind allows; the shortest wins
+        response
+            .items
+            .iter()
+            .map(|item| {
+                self.by_kind
+                    .get(kind_name(&item.kind))
+                    .copied()
+                    .unwrap_or(base)
+            })
+            .min()
+            .unwrap_or(base)
+    }
 }
 
-const CACHE_TTL: std::time::Duration = std::time::Duration::from_secs(300); // 5 minutes
+/// The name `kind` has in config files and on the wire.
+pub fn kind_name(kind: &CompletionKind) -> &'static str {
+    match kind {
+        CompletionKind::Command => "command",
+        CompletionKind::Argument => "argument",
+        CompletionKind::Path => "path",
+        CompletionKind::FullCommand => "full_command",
+        CompletionKind::Code => "code",
+    }
+}
 
 impl CompletionCache {
     pub fn new(capacity: usize) -> Self {
@@ -22,39 +154,239 @@ impl CompletionCache {
             inner: LruCache::new(
                 NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::new(100).unwrap()),
             ),
+            clock: Arc::new(SystemClock),
+            ttl: CacheTtl::default(),
+            max_bytes: None,
+            bytes: 0,
+            typed: HashMap::new(),
         }
     }
 
-    /// Build a cache key from input + context.
-    pub fn cache_key(input: &str, cwd: &str, shell: &str) -> u64 {
+    /// Also evict the least recently used entries while the cache holds
+    /// more than `max_bytes`.
+    pub fn with_max_bytes(mut self, max_bytes: Option<usize>) -> Self {
+        self.set_max_bytes(max_bytes);
+        self
+    }
+
+    /// Change the byte bound, evicting entries if the cache is over it.
+    pub fn set_max_bytes(&mut self, max_bytes: Option<usize>) {
+        self.max_bytes = max_bytes;
+        self.shrink();
+    }
+
+    pub fn max_bytes(&self) -> Option<usize> {
+        self.max_bytes
+    }
+
+    /// Approximate bytes held by cached entries.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Expire entries by `ttl` instead of the default five minutes.
+    pub fn with_ttl(mut self, ttl: CacheTtl) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Change the TTLs of responses stored from now on.
+    pub fn set_ttl(&mut self, ttl: CacheTtl) {
+        self.ttl = ttl;
+    }
+
+    pub fn ttl(&self) -> &CacheTtl {
+        &self.ttl
+    }
+
+    /// Judge expiry by `clock` instead of the system clock.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Build a cache key from input + context. Remote hosts get their own
+    /// namespace so `/srv/app` on one machine never serves another, and
+    /// the directory's fingerprint keeps one branch's answers from another.
+    pub fn cache_key(
+        input: &str,
+        cwd: &str,
+        shell: &str,
+        host: Option<&str>,
+        fingerprint: &Fingerprint,
+    ) -> u64 {
         let mut hasher = DefaultHasher::new();
         input.hash(&mut hasher);
         cwd.hash(&mut hasher);
         shell.hash(&mut hasher);
+        host.hash(&mut hasher);
+        fingerprint.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// The key shared by every input typed in one place: directory, shell,
+    /// host and fingerprint, as for `cache_key`.
+    pub fn scope_key(cwd: &str, shell: &str, host: Option<&str>, fingerprint: &Fingerprint) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        cwd.hash(&mut hasher);
+        shell.hash(&mut hasher);
+        host.hash(&mut hasher);
+        fingerprint.hash(&mut hasher);
         hasher.finish()
     }
 
     /// Get a cached response, if it exists and hasn't expired.
     pub fn get(&mut self, key: u64) -> Option<CompletionResponse> {
+        let now = self.clock.now();
         if let Some(entry) = self.inner.get(&key) {
-            if entry.created_at.elapsed() < CACHE_TTL {
+            if now.saturating_duration_since(entry.created_at) < entry.ttl {
                 return Some(entry.response.clone());
             }
             // Expired — remove it
-            self.inner.pop(&key);
+            self.remove(key);
         }
         None
     }
 
-    /// Store a response in the cache.
-    pub fn put(&mut self, key: u64, response: CompletionResponse) {
-        self.inner.put(
-            key,
-            CacheEntry {
-                response,
-                created_at: std::time::Instant::now(),
-            },
-        );
+    /// The answer for the longest shorter input in `scope` that `input`
+    /// extends, keeping only the items that still start with `input`:
+    /// what `git com` got, for `git comm`. `None` if none are left.
+    pub fn get_prefix(&mut self, scope: u64, input: &str) -> Option<CompletionResponse> {
+        let now = self.clock.now();
+        let typed = self.typed.get(&scope)?;
+        let key = input
+            .char_indices()
+            .rev()
+            .filter(|(end, _)| *end > 0)
+            .filter_map(|(end, _)| typed.get(&input[..end]))
+            .copied()
+            .find(|key| {
+                self.inner.peek(key).is_some_and(|entry| {
+                    now.saturating_duration_since(entry.created_at) < entry.ttl
+                })
+            })?;
+        let mut response = self.inner.get(&key)?.response.clone();
+        response.items.retain(|item| item.text.starts_with(input));
+        (!response.items.is_empty()).then_some(response)
+    }
+
+    /// Store a response for a completion in `cwd`, answered on `route`.
+    /// A response bigger than `max_bytes` on its own isn't stored.
+    pub fn put(&mut self, key: u64, cwd: &str, route: Option<&str>, response: CompletionResponse) {
+        self.insert(key, cwd, None, route, response);
+    }
+
+    /// Store a response like `put`, noting it answered `input` in `scope`
+    /// so `get_prefix` can serve it while the input grows.
+    pub fn put_typed(
+        &mut self,
+        key: u64,
+        scope: u64,
+        input: &str,
+        cwd: &str,
+        route: Option<&str>,
+        response: CompletionResponse,
+    ) {
+        self.insert(key, cwd, Some((scope, input.to_string())), route, response);
+    }
+
+    fn insert(
+        &mut self,
+        key: u64,
+        cwd: &str,
+        typed: Option<(u64, String)>,
+        route: Option<&str>,
+        response: CompletionResponse,
+    ) {
+        let ttl = self.ttl.of(&response, route);
+        let entry = CacheEntry::new(response, cwd, typed, self.clock.now(), ttl);
+        if self.max_bytes.is_some_and(|max| entry.size > max) {
+            self.remove(key);
+            return;
+        }
+        self.bytes += entry.size;
+        let typed = entry.typed.clone();
+        // Returns the entry replaced under `key`, or the one evicted for room
+        if let Some((old_key, old)) = self.inner.push(key, entry) {
+            self.forget(old_key, &old);
+        }
+        if let Some((scope, input)) = typed {
+            self.typed.entry(scope).or_default().insert(input, key);
+        }
+        self.shrink();
+    }
+
+    fn remove(&mut self, key: u64) {
+        if let Some(entry) = self.inner.pop(&key) {
+            self.forget(key, &entry);
+        }
+    }
+
+    /// Account for `entry`, stored under `key`, having left the cache.
+    fn forget(&mut self, key: u64, entry: &CacheEntry) {
+        self.bytes -= entry.size;
+        let Some((scope, input)) = &entry.typed else {
+            return;
+        };
+        if let Some(inputs) = self.typed.get_mut(scope) {
+            if inputs.get(input) == Some(&key) {
+                inputs.remove(input);
+            }
+            if inputs.is_empty() {
+                self.typed.remove(scope);
+            }
+        }
+    }
+
+    /// Evict the least recently used entries until within `max_bytes`.
+    fn shrink(&mut self) {
+        let Some(max) = self.max_bytes else {
+            return;
+        };
+        while self.bytes > max {
+            match self.inner.pop_lru() {
+                Some((key, entry)) => self.forget(key, &entry),
+                None => break,
+            }
+        }
+    }
+
+    /// Drop completions for `dir` and the directories under it. Returns
+    /// how many were dropped.
+    pub fn invalidate_under(&mut self, dir: &str) -> usize {
+        let stale: Vec<u64> = self
+            .inner
+            .iter()
+            .filter(|(_, entry)| fingerprint::is_under(&entry.cwd, dir))
+            .map(|(key, _)| *key)
+            .collect();
+        for key in &stale {
+            self.remove(*key);
+        }
+        stale.len()
+    }
+
+    /// Drop completions for `dir` itself that suggest paths, since the
+    /// names in it changed. Returns how many were dropped.
+    pub fn invalidate_paths(&mut self, dir: &str) -> usize {
+        let dir = dir.trim_end_matches('/');
+        let stale: Vec<u64> = self
+            .inner
+            .iter()
+            .filter(|(_, entry)| {
+                entry.cwd.trim_end_matches('/') == dir
+                    && entry
+                        .response
+                        .items
+                        .iter()
+                        .any(|item| item.kind == CompletionKind::Path)
+            })
+            .map(|(key, _)| *key)
+            .collect();
+        for key in &stale {
+            self.remove(*key);
+        }
+        stale.len()
     }
 
     /// Number of entries in the cache.
@@ -70,13 +402,30 @@ impl CompletionCache {
     /// Clear all entries.
     pub fn clear(&mut self) {
         self.inner.clear();
+        self.bytes = 0;
+        self.typed.clear();
+    }
+
+    /// Change the capacity, evicting the least recently used entries if
+    /// it shrinks.
+    pub fn resize(&mut self, capacity: usize) {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::new(100).unwrap());
+        while self.inner.len() > capacity.get() {
+            if let Some((key, entry)) = self.inner.pop_lru() {
+                self.forget(key, &entry);
+            }
+        }
+        self.inner.resize(capacity);
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use murmur_protocol::{CompletionItem, CompletionKind};
+    use crate::clock::ManualClock;
+    use murmur_protocol::{CompletionItem, CompletionKind, CompletionMetadata};
+    use proptest::prelude::*;
+    use std::collections::HashMap;
 
     fn make_response() -> CompletionResponse {
         CompletionResponse {
@@ -85,20 +434,25 @@ mod tests {
                 description: Some("Commit changes".to_string()),
                 kind: CompletionKind::Command,
                 score: 1.0,
+                metadata: CompletionMetadata::default(),
             }],
             provider: "test".to_string(),
             latency_ms: 50,
             cached: false,
+            notice: None,
+            groups: vec![],
+            refreshing: false,
         }
     }
 
     #[test]
     fn cache_put_and_get() {
         let mut cache = CompletionCache::new(10);
-        let key = CompletionCache::cache_key("git c", "/home", "zsh");
+        let key =
+            CompletionCache::cache_key("git c", "/home", "zsh", None, &Fingerprint::default());
         let response = make_response();
 
-        cache.put(key, response.clone());
+        cache.put(key, "/home", None, response.clone());
         let cached = cache.get(key);
         assert!(cached.is_some());
         assert_eq!(cached.unwrap().items[0].text, "git commit");
@@ -107,14 +461,253 @@ mod tests {
     #[test]
     fn cache_miss() {
         let mut cache = CompletionCache::new(10);
-        let key = CompletionCache::cache_key("git c", "/home", "zsh");
+        let key =
+            CompletionCache::cache_key("git c", "/home", "zsh", None, &Fingerprint::default());
         assert!(cache.get(key).is_none());
     }
 
     #[test]
     fn different_inputs_different_keys() {
-        let key1 = CompletionCache::cache_key("git c", "/home", "zsh");
-        let key2 = CompletionCache::cache_key("git s", "/home", "zsh");
+        let key1 =
+            CompletionCache::cache_key("git c", "/home", "zsh", None, &Fingerprint::default());
+        let key2 =
+            CompletionCache::cache_key("git s", "/home", "zsh", None, &Fingerprint::default());
         assert_ne!(key1, key2);
     }
+
+    #[test]
+    fn remote_hosts_have_separate_keys() {
+        let local = CompletionCache::cache_key("ls", "/srv", "bash", None, &Fingerprint::default());
+        let remote = CompletionCache::cache_key(
+            "ls",
+            "/srv",
+            "bash",
+            Some("devbox"),
+            &Fingerprint::default(),
+        );
+        assert_ne!(local, remote);
+    }
+
+    #[test]
+    fn fingerprints_and_invalidation_separate_entries() {
+        let main = Fingerprint {
+            branch: Some("main".to_string()),
+            ..Fingerprint::default()
+        };
+        let dirty = Fingerprint {
+            dirty: true,
+            ..main.clone()
+        };
+        let on_main = CompletionCache::cache_key("git c", "/repo", "zsh", None, &main);
+        assert_ne!(
+            on_main,
+            CompletionCache::cache_key("git c", "/repo", "zsh", None, &dirty)
+        );
+
+        let mut cache = CompletionCache::new(10);
+        cache.put(on_main, "/repo", None, make_response());
+        cache.put(1, "/repo/src", None, make_response());
+        cache.put(2, "/repository", None, make_response());
+        assert_eq!(cache.invalidate_under("/repo"), 2);
+        assert!(cache.get(on_main).is_none());
+        assert!(cache.get(2).is_some());
+    }
+
+    #[test]
+    fn longer_inputs_are_served_narrowed() {
+        let scope = CompletionCache::scope_key("/repo", "zsh", None, &Fingerprint::default());
+        let mut response = make_response();
+        response.items.push(CompletionItem {
+            text: "git config".to_string(),
+            ..response.items[0].clone()
+        });
+        let mut cache = CompletionCache::new(10);
+        cache.put_typed(1, scope, "git c", "/repo", None, response.clone());
+        cache.put_typed(2, scope, "git co", "/repo", None, response);
+
+        let narrowed = cache.get_prefix(scope, "git comm").unwrap();
+        assert_eq!(narrowed.items.len(), 1);
+        assert_eq!(narrowed.items[0].text, "git commit");
+        // Only shorter inputs in the same scope, with an item left
+        assert!(cache.get_prefix(scope, "git co").is_some());
+        assert!(cache.get_prefix(scope, "git c").is_none());
+        assert!(cache.get_prefix(scope, "git push").is_none());
+        assert!(cache.get_prefix(scope + 1, "git comm").is_none());
+        assert!(cache.get_prefix(scope, "git commit --amend").is_none());
+    }
+
+    #[test]
+    fn evicted_inputs_leave_the_prefix_index() {
+        let scope = CompletionCache::scope_key("/repo", "zsh", None, &Fingerprint::default());
+        let mut cache = CompletionCache::new(1);
+        cache.put_typed(1, scope, "git c", "/repo", None, make_response());
+        cache.put_typed(2, scope, "ls -", "/repo", None, make_response());
+        assert!(cache.get_prefix(scope, "git co").is_none());
+        assert_eq!(cache.typed[&scope].len(), 1);
+
+        cache.clear();
+        assert!(cache.typed.is_empty());
+    }
+
+    #[test]
+    fn file_changes_drop_path_completions() {
+        let mut paths = make_response();
+        paths.items[0].kind = CompletionKind::Path;
+        let mut cache = CompletionCache::new(10);
+        cache.put(1, "/repo", None, paths.clone());
+        cache.put(2, "/repo", None, make_response());
+        cache.put(3, "/repo/src", None, paths);
+        assert_eq!(cache.invalidate_paths("/repo/"), 1);
+        assert!(cache.get(1).is_none());
+        assert!(cache.get(2).is_some());
+        assert!(cache.get(3).is_some());
+    }
+
+    #[test]
+    fn ttls_follow_provider_and_kind() {
+        let ttl = CacheTtl {
+            default: Duration::from_secs(300),
+            by_kind: HashMap::from([
+                ("path".to_string(), Duration::from_secs(10)),
+                ("argument".to_string(), Duration::from_secs(3600)),
+            ]),
+            by_provider: HashMap::from([("ollama".to_string(), Duration::from_secs(60))]),
+            by_route: HashMap::from([("code".to_string(), Duration::from_secs(30))]),
+        };
+        let mut response = make_response();
+        assert_eq!(ttl.of(&response, None), Duration::from_secs(300));
+        assert_eq!(ttl.of(&response, Some("code")), Duration::from_secs(30));
+        response.provider = "ollama".to_string();
+        assert_eq!(ttl.of(&response, None), Duration::from_secs(60));
+        response.items[0].kind = CompletionKind::Argument;
+        assert_eq!(ttl.of(&response, None), Duration::from_secs(3600));
+        let mut path = response.items[0].clone();
+        path.kind = CompletionKind::Path;
+        response.items.push(path);
+        assert_eq!(ttl.of(&response, None), Duration::from_secs(10));
+
+        let clock = Arc::new(ManualClock::new(0));
+        let mut cache = CompletionCache::new(4)
+            .with_clock(clock.clone())
+            .with_ttl(ttl);
+        cache.put(1, "/", None, response);
+        cache.put(2, "/", None, make_response());
+        clock.advance(Duration::from_secs(10));
+        assert!(cache.get(1).is_none());
+        assert!(cache.get(2).is_some());
+    }
+
+    #[test]
+    fn byte_bound_evicts_the_least_recently_used() {
+        let one = CacheEntry::new(make_response(), "/", None, Instant::now(), CACHE_TTL).size;
+        let mut cache = CompletionCache::new(10).with_max_bytes(Some(one * 2));
+        cache.put(1, "/", None, make_response());
+        cache.put(2, "/", None, make_response());
+        cache.put(2, "/", None, make_response());
+        assert_eq!(cache.bytes(), one * 2);
+        cache.get(1);
+        cache.put(3, "/", None, make_response());
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get(2).is_none());
+
+        cache.set_max_bytes(Some(one));
+        assert_eq!(cache.len(), 1);
+        cache.resize(1);
+        cache.invalidate_under("/");
+        assert_eq!(cache.bytes(), 0);
+
+        // Too big to keep at all
+        cache.set_max_bytes(Some(one - 1));
+        cache.put(4, "/", None, make_response());
+        assert!(cache.is_empty());
+    }
+
+    /// A response tagged with `id` so tests can tell which put it came from.
+    fn tagged(id: u64) -> CompletionResponse {
+        CompletionResponse {
+            latency_ms: id,
+            ..make_response()
+        }
+    }
+
+    /// `Put` or `Get` of a key, after advancing the clock by some seconds.
+    #[derive(Debug, Clone)]
+    enum Op {
+        Put(u64, u64),
+        Get(u64, u64),
+    }
+
+    fn ops(keys: u64) -> impl Strategy<Value = Vec<Op>> {
+        prop::collection::vec(
+            prop_oneof![
+                (0..keys, 0..120u64).prop_map(|(key, secs)| Op::Put(key, secs)),
+                (0..keys, 0..120u64).prop_map(|(key, secs)| Op::Get(key, secs)),
+            ],
+            0..200,
+        )
+    }
+
+    proptest! {
+        /// The cache never outgrows its capacity and never serves a stale or
+        /// overwritten response. With room for every key, nothing fresh is
+        /// ever missing either.
+        #[test]
+        fn bounded_and_never_stale(capacity in 1..16usize, keys in 1..24u64, ops in ops(24)) {
+            let clock = Arc::new(ManualClock::new(0));
+            let mut cache = CompletionCache::new(capacity).with_clock(clock.clone());
+            // key -> (id of the last put, when)
+            let mut puts: HashMap<u64, (u64, Instant)> = HashMap::new();
+
+            for (id, op) in ops.into_iter().enumerate() {
+                match op {
+                    Op::Put(key, secs) => {
+                        clock.advance(Duration::from_secs(secs));
+                        let key = key % keys;
+                        cache.put(key, "/", None, tagged(id as u64));
+                        puts.insert(key, (id as u64, clock.now()));
+                    }
+                    Op::Get(key, secs) => {
+                        clock.advance(Duration::from_secs(secs));
+                        let now = clock.now();
+                        let key = key % keys;
+                        let fresh = puts
+                            .get(&key)
+                            .filter(|(_, at)| now.duration_since(*at) < CACHE_TTL);
+                        match cache.get(key) {
+                            Some(response) => {
+                                let (id, _) = fresh.expect("served a stale or missing entry");
+                                prop_assert_eq!(response.latency_ms, *id);
+                            }
+                            None => prop_assert!(
+                                fresh.is_none() || keys as usize > capacity,
+                                "lost a fresh entry without eviction pressure"
+                            ),
+                        }
+                    }
+                }
+                prop_assert!(cache.len() <= capacity);
+            }
+        }
+
+        /// Once an entry has expired it stays expired, and it expires exactly
+        /// at the TTL.
+        #[test]
+        fn expiry_is_monotonic(mut ages in prop::collection::vec(0..600u64, 1..20)) {
+            ages.sort_unstable();
+            let clock = Arc::new(ManualClock::new(0));
+            let mut cache = CompletionCache::new(4).with_clock(clock.clone());
+            cache.put(7, "/", None, make_response());
+
+            let mut expired = false;
+            let mut elapsed = 0;
+            for age in ages {
+                clock.advance(Duration::from_secs(age - elapsed));
+                elapsed = age;
+                let hit = cache.get(7).is_some();
+                prop_assert!(!(expired && hit), "entry came back after expiring");
+                prop_assert_eq!(hit, Duration::from_secs(age) < CACHE_TTL);
+                expired |= !hit;
+            }
+        }
+    }
 }
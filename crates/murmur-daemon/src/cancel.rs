@@ -0,0 +1,118 @@
+//! Cancelling completions nobody is waiting for any more.
+//!
+//! A shell that keeps typing, or that gave up on a slow answer, has no use
+//! for the completion it asked for, but the providers would still be asked
+//! (and paid for). A request carrying a `cancel_token` can be aborted with
+//! `complete/cancel`, or by a newer request with the same token: the
+//! provider calls in flight are dropped and nothing is cached.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+/// Completions that can be cancelled, by token.
+#[derive(Debug, Default)]
+pub struct Cancellations {
+    running: Mutex<HashMap<String, Arc<Notify>>>,
+}
+
+impl Cancellations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `work` under `token`, cancelling whatever ran under it before.
+    /// Returns `None` if `work` was cancelled before it finished.
+    pub async fn run<T>(&self, token: &str, work: impl Future<Output = T>) -> Option<T> {
+        let notify = Arc::new(Notify::new());
+        let older = self
+            .running
+            .lock()
+            .unwrap()
+            .insert(token.to_string(), notify.clone());
+        if let Some(older) = older {
+            older.notify_one();
+        }
+        let _running = Running {
+            cancellations: self,
+            token,
+            notify: notify.clone(),
+        };
+        tokio::select! {
+            result = work => Some(result),
+            _ = notify.notified() => None,
+        }
+    }
+
+    /// Cancel the work running under `token`; false if there is none.
+    pub fn cancel(&self, token: &str) -> bool {
+        match self.running.lock().unwrap().remove(token) {
+            Some(notify) => {
+                // Stores a permit if `run` isn't waiting yet
+                notify.notify_one();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Unregisters a token when its work ends, unless a newer request took it.
+struct Running<'a> {
+    cancellations: &'a Cancellations,
+    token: &'a str,
+    notify: Arc<Notify>,
+}
+
+impl Drop for Running<'_> {
+    fn drop(&mut self) {
+        let mut running = self.cancellations.running.lock().unwrap();
+        if running
+            .get(self.token)
+            .is_some_and(|n| Arc::ptr_eq(n, &self.notify))
+        {
+            running.remove(self.token);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn cancelled_work_is_abandoned() {
+        let cancellations = Cancellations::new();
+        let slow = tokio::time::sleep(Duration::from_secs(10));
+        let (result, cancelled) = tokio::join!(cancellations.run("zsh-1", slow), async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            cancellations.cancel("zsh-1")
+        });
+        assert_eq!(result, None);
+        assert!(cancelled);
+        // Nothing is running under the token any more
+        assert!(!cancellations.cancel("zsh-1"));
+        assert_eq!(cancellations.run("zsh-1", async { 7 }).await, Some(7));
+    }
+
+    #[tokio::test]
+    async fn a_newer_request_cancels_the_older() {
+        let cancellations = Cancellations::new();
+        let (older, newer, elsewhere) = tokio::join!(
+            cancellations.run("zsh-1", tokio::time::sleep(Duration::from_secs(10))),
+            async {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                cancellations
+                    .run("zsh-1", tokio::time::sleep(Duration::from_millis(10)))
+                    .await
+            },
+            cancellations.run("zsh-2", tokio::time::sleep(Duration::from_millis(30))),
+        );
+        assert_eq!(older, None);
+        assert_eq!(newer, Some(()));
+        assert_eq!(elsewhere, Some(()));
+        assert!(cancellations.running.lock().unwrap().is_empty());
+    }
+}
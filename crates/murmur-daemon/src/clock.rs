@@ -0,0 +1,92 @@
+//! Time source for the daemon.
+//!
+//! Cache TTLs, history timestamps, daily budgets, diagnosis rate limits and
+//! confirmation timeouts all read the time through a shared `Clock`, so
+//! tests can swap in a `ManualClock` and move time forward instead of
+//! sleeping.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Where the daemon gets the current time.
+pub trait Clock: Send + Sync {
+    /// Monotonic time, for TTLs and timeouts.
+    fn now(&self) -> Instant;
+
+    /// Wall-clock time in unix seconds, for timestamps and daily budgets.
+    fn unix_now(&self) -> u64;
+}
+
+/// The real clock.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn unix_now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+    }
+}
+
+/// A clock that only moves when `advance` is called. Both readings move
+/// together.
+#[derive(Debug)]
+pub struct ManualClock {
+    start: Instant,
+    unix_start: u64,
+    elapsed_ms: AtomicU64,
+}
+
+impl ManualClock {
+    /// A clock reading `unix_start` seconds.
+    pub fn new(unix_start: u64) -> Self {
+        Self {
+            start: Instant::now(),
+            unix_start,
+            elapsed_ms: AtomicU64::new(0),
+        }
+    }
+
+    /// Move the clock forward.
+    pub fn advance(&self, by: Duration) {
+        self.elapsed_ms
+            .fetch_add(by.as_millis() as u64, Ordering::SeqCst);
+    }
+
+    fn elapsed(&self) -> Duration {
+        Duration::from_millis(self.elapsed_ms.load(Ordering::SeqCst))
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    fn unix_now(&self) -> u64 {
+        self.unix_start + self.elapsed().as_secs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_clock_moves_only_when_advanced() {
+        let clock = ManualClock::new(1_700_000_000);
+        let before = clock.now();
+        assert_eq!(clock.now(), before);
+        assert_eq!(clock.unix_now(), 1_700_000_000);
+
+        clock.advance(Duration::from_millis(1_500));
+        assert_eq!(clock.now() - before, Duration::from_millis(1_500));
+        assert_eq!(clock.unix_now(), 1_700_000_001);
+    }
+}
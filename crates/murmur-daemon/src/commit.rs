@@ -0,0 +1,323 @@
+//! Commit messages for staged changes (`murmur commit`).
+//!
+//! The staged diff goes through `Provider::generate` with a prompt asking
+//! for a Conventional Commits message, along with the diffstat and recent
+//! subjects so the message follows the repository's habits. Large diffs
+//! are cut down to fit: lockfiles are left out, each file gets a share of
+//! the budget, and files past it are only in the diffstat.
+
+use crate::assets;
+use crate::budget::{self, Admit};
+use murmur_context::StagedChanges;
+use murmur_protocol::CommitSuggestResponse;
+use murmur_providers::Provider;
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+/// Most diff bytes put in the prompt.
+pub const MAX_DIFF_BYTES: usize = 12_000;
+
+/// Fewest bytes a file's diff is cut to, so a change to many files still
+/// shows something of each one that fits.
+const MIN_FILE_BYTES: usize = 1_500;
+
+/// Generated files whose diffs say nothing about the change.
+const LOCKFILES: &[&str] = &[
+    "Cargo.lock",
+    "package-lock.json",
+    "yarn.lock",
+    "pnpm-lock.yaml",
+    "poetry.lock",
+    "Gemfile.lock",
+    "composer.lock",
+    "go.sum",
+    "flake.lock",
+];
+
+/// Conventional Commits types the subject may start with.
+const TYPES: &[&str] = &[
+    "feat", "fix", "docs", "style", "refactor", "perf", "test", "build", "ci", "chore", "revert",
+];
+
+/// System prompt for writing a commit message.
+pub const COMMIT_SYSTEM_PROMPT: &str = "You write git commit messages in the Conventional \
+     Commits format. Respond ONLY with the message: a subject line \
+     `type(scope): summary`, where type is one of feat, fix, docs, style, refactor, \
+     perf, test, build, ci, chore or revert, the scope is optional, and the summary \
+     is in the imperative mood, at most 72 characters, without a trailing period. \
+     If the change needs explaining, add a blank line and a short body wrapped at \
+     72 columns saying what changed and why. No markdown fences or commentary.";
+
+/// `diff` cut to at most `max_bytes`, and whether anything was cut.
+pub fn bound_diff(diff: &str, max_bytes: usize) -> (String, bool) {
+    let files = split_files(diff);
+    let share = (max_bytes / files.len().max(1)).max(MIN_FILE_BYTES);
+    let mut bounded = String::new();
+    let mut truncated = false;
+    for (i, file) in files.iter().enumerate() {
+        let header = file.lines().next().unwrap_or("");
+        if LOCKFILES
+            .iter()
+            .any(|name| header.ends_with(&format!("/{name}")))
+        {
+            bounded.push_str(&format!("{header}\n(lockfile changes omitted)\n"));
+            continue;
+        }
+        let room = max_bytes.saturating_sub(bounded.len());
+        if room < MIN_FILE_BYTES.min(file.len()) {
+            bounded.push_str(&format!(
+                "({} more files, see the list above)\n",
+                files.len() - i
+            ));
+            return (bounded, true);
+        }
+        let limit = share.min(room);
+        if file.len() <= limit {
+            bounded.push_str(file);
+            if !file.ends_with('\n') {
+                bounded.push('\n');
+            }
+            continue;
+        }
+        truncated = true;
+        // Whole lines only, whatever their endings, so the cut stays on a
+        // character boundary
+        let mut kept = 0;
+        for line in file.split_inclusive('\n') {
+            if kept + line.len() > limit {
+                break;
+            }
+            bounded.push_str(line);
+            kept += line.len();
+        }
+        let rest = file[kept..].lines().count();
+        bounded.push_str(&format!("... ({rest} more lines)\n"));
+    }
+    (bounded, truncated)
+}
+
+/// A patch split into one piece per file, each starting `diff --git`.
+fn split_files(diff: &str) -> Vec<&str> {
+    let mut starts: Vec<usize> = diff
+        .match_indices("diff --git ")
+        .map(|(i, _)| i)
+        .filter(|&i| i == 0 || diff.as_bytes()[i - 1] == b'\n')
+        .collect();
+    if starts.first() != Some(&0) {
+        starts.insert(0, 0);
+    }
+    starts
+        .iter()
+        .zip(starts.iter().skip(1).chain([&diff.len()]))
+        .map(|(&start, &end)| &diff[start..end])
+        .filter(|file| !file.is_empty())
+        .collect()
+}
+
+/// User prompt for `changes`, and whether the diff in it was cut.
+pub fn build_prompt(changes: &StagedChanges) -> (String, bool) {
+    let (diff, truncated) = bound_diff(&changes.diff, MAX_DIFF_BYTES);
+    let mut prompt = String::new();
+    if !changes.recent_subjects.is_empty() {
+        prompt.push_str("Recent commit subjects:\n");
+        for subject in &changes.recent_subjects {
+            prompt.push_str(&format!("{subject}\n"));
+        }
+        prompt.push('\n');
+    }
+    if let Some(ref message) = changes.amended_message {
+        prompt.push_str(&format!(
+            "This replaces the last commit, whose message was:\n{}\n\n",
+            message.trim()
+        ));
+    }
+    prompt.push_str(&format!(
+        "Files changed:\n{}\n\nDiff:\n{diff}",
+        changes.stat.trim_end()
+    ));
+    (prompt, truncated)
+}
+
+/// The message in a model's reply, without fences or quotes around it.
+pub fn parse_message(text: &str) -> Option<String> {
+    let lines: Vec<&str> = text
+        .trim()
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("```"))
+        .collect();
+    let message = lines.join("\n");
+    let message = message.trim().trim_matches(|c| c == '"' || c == '`').trim();
+    if message.is_empty() {
+        return None;
+    }
+    // One blank line between subject and body, none trailing
+    let mut parts = message.splitn(2, '\n');
+    let subject = parts.next().unwrap_or("").trim();
+    let body = parts.next().unwrap_or("").trim();
+    Some(match body.is_empty() {
+        true => subject.to_string(),
+        false => format!("{subject}\n\n{body}"),
+    })
+}
+
+/// Whether `message` starts with a `type(scope): summary` subject.
+pub fn is_conventional(message: &str) -> bool {
+    let subject = message.lines().next().unwrap_or("");
+    let Some((prefix, summary)) = subject.split_once(": ") else {
+        return false;
+    };
+    let kind = prefix.trim_end_matches('!');
+    let kind = match kind.split_once('(') {
+        Some((kind, scope)) if scope.ends_with(')') && scope.len() > 1 => kind,
+        Some(_) => return false,
+        None => kind,
+    };
+    TYPES.contains(&kind) && !summary.trim().is_empty()
+}
+
+/// Ask each provider in turn for a message. One that isn't in the
+/// Conventional Commits form is used only if no provider writes one that
+/// is.
+pub async fn run(
+    chain: &[Arc<dyn Provider>],
+    budget: &dyn Admit,
+    changes: &StagedChanges,
+) -> Result<CommitSuggestResponse, String> {
+    let start = std::time::Instant::now();
+    let (prompt, truncated) = build_prompt(changes);
+    let mut fallback = None;
+    let system = assets::prompt("commit", COMMIT_SYSTEM_PROMPT);
+    let mut attempted = false;
+    for provider in chain {
+        if !budget.admit(provider.as_ref()).await {
+            continue;
+        }
+        attempted = true;
+        match provider.generate(&system, &prompt).await {
+            Ok(text) => {
+                let Some(message) = parse_message(&text) else {
+                    debug!(
+                        provider = provider.name(),
+                        "Provider wrote no commit message"
+                    );
+                    continue;
+                };
+                let response = CommitSuggestResponse {
+                    message,
+                    provider: provider.name().to_string(),
+                    latency_ms: start.elapsed().as_millis() as u64,
+                    truncated,
+                };
+                if is_conventional(&response.message) {
+                    return Ok(response);
+                }
+                debug!(
+                    provider = provider.name(),
+                    "Commit message isn't conventional, trying next"
+                );
+                fallback.get_or_insert(response);
+            }
+            Err(e) => warn!(provider = provider.name(), error = %e, "Commit message failed"),
+        }
+    }
+    if let Some(response) = fallback {
+        return Ok(response);
+    }
+    if !attempted && !chain.is_empty() {
+        return Err(budget::EXHAUSTED.to_string());
+    }
+    if chain.is_empty() {
+        Err(
+            "No provider can write commit messages; configure anthropic, openai, gemini, openrouter or ollama"
+                .to_string(),
+        )
+    } else {
+        Err("All providers failed to write a commit message".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, lines: usize) -> String {
+        let mut diff = format!(
+            "diff --git a/{path} b/{path}\n--- a/{path}\n+++ b/{path}\n@@ -1,{lines} +1,{lines} @@\n"
+        );
+        for i in 0..lines {
+            diff.push_str(&format!("+line {i} of {path}\n"));
+        }
+        diff
+    }
+
+    #[test]
+    fn small_diffs_are_sent_whole() {
+        let diff = [file("src/main.rs", 10), file("README.md", 3)].concat();
+        assert_eq!(bound_diff(&diff, MAX_DIFF_BYTES), (diff.clone(), false));
+    }
+
+    #[test]
+    fn large_diffs_are_cut_per_file() {
+        let diff = [
+            file("Cargo.lock", 500),
+            file("src/big.rs", 2000),
+            file("src/small.rs", 5),
+        ]
+        .concat();
+        let (bounded, truncated) = bound_diff(&diff, 6_000);
+        assert!(truncated);
+        assert!(bounded.len() <= 6_000 + 200, "{}", bounded.len());
+        assert!(
+            bounded.contains("diff --git a/Cargo.lock b/Cargo.lock\n(lockfile changes omitted)")
+        );
+        assert!(!bounded.contains("of Cargo.lock"));
+        assert!(bounded.contains("+line 0 of src/big.rs\n"));
+        assert!(bounded.contains("more lines)\n"));
+        // The small file still fits after the big one's share
+        assert!(bounded.contains("+line 4 of src/small.rs\n"));
+
+        let many: String = (0..40)
+            .map(|i| file(&format!("src/f{i}.rs"), 100))
+            .collect();
+        let (bounded, truncated) = bound_diff(&many, 6_000);
+        assert!(truncated);
+        assert!(bounded.ends_with("more files, see the list above)\n"));
+    }
+
+    #[test]
+    fn crlf_and_multibyte_lines_are_cut_whole() {
+        let body: String = (0..400)
+            .map(|i| format!("+ligne {i} é ✓ 日本語\r\n"))
+            .collect();
+        let diff = format!("diff --git a/src/é.rs b/src/é.rs\r\n{body}");
+        let (bounded, truncated) = bound_diff(&diff, 4_000);
+        assert!(truncated);
+        assert!(bounded.contains("+ligne 0 é ✓ 日本語\r\n"));
+        let cut = bounded.rfind("... (").unwrap();
+        assert!(bounded[..cut].ends_with("日本語\r\n"));
+    }
+
+    #[test]
+    fn messages_are_cleaned_up() {
+        assert_eq!(
+            parse_message("```\nfeat(cli): add commit command\n\n\nWrites messages.\n```\n")
+                .as_deref(),
+            Some("feat(cli): add commit command\n\nWrites messages.")
+        );
+        assert_eq!(
+            parse_message("\"fix: handle empty diffs\"").as_deref(),
+            Some("fix: handle empty diffs")
+        );
+        assert_eq!(parse_message("```\n```"), None);
+    }
+
+    #[test]
+    fn conventional_subjects_are_recognized() {
+        assert!(is_conventional("feat: add abbreviations"));
+        assert!(is_conventional("fix(daemon)!: drop stale requests\n\nBody"));
+        assert!(!is_conventional("Add abbreviations"));
+        assert!(!is_conventional("feature: add abbreviations"));
+        assert!(!is_conventional("fix(: oops"));
+        assert!(!is_conventional("fix: "));
+    }
+}
@@ -0,0 +1,118 @@
+//! Optional compression for connections carrying large payloads.
+//!
+//! Shell context and base64 audio add up on a slow SSH tunnel. A client
+//! sends `compression/negotiate` with the algorithms it supports; the daemon
+//! answers (as a plain JSON line) with the first one it also supports, and
+//! from then on every message on that connection is a length-prefixed frame
+//! of compressed JSON. Clients that never ask keep plain JSON lines.
+
+use murmur_protocol::Compression;
+use std::io::{self, Read, Write};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::frame;
+
+/// Algorithms this daemon can use, preferred first.
+pub const SUPPORTED: [Compression; 2] = [Compression::Zstd, Compression::Gzip];
+
+/// zstd's default level: most of the gain for little CPU.
+const ZSTD_LEVEL: i32 = 3;
+
+/// The first of the client's `offered` algorithms that is supported.
+pub fn negotiate(offered: &[Compression]) -> Option<Compression> {
+    offered.iter().copied().find(|a| SUPPORTED.contains(a))
+}
+
+pub fn compress(algorithm: Compression, data: &[u8]) -> io::Result<Vec<u8>> {
+    match algorithm {
+        Compression::Gzip => {
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(data)?;
+            encoder.finish()
+        }
+        Compression::Zstd => zstd::encode_all(data, ZSTD_LEVEL),
+    }
+}
+
+/// Decompress `data`, failing if the result would exceed `limit` bytes so a
+/// small frame can't expand without bound.
+pub fn decompress(algorithm: Compression, data: &[u8], limit: usize) -> io::Result<Vec<u8>> {
+    let decoder: Box<dyn Read> = match algorithm {
+        Compression::Gzip => Box::new(flate2::read::GzDecoder::new(data)),
+        Compression::Zstd => Box::new(zstd::Decoder::new(data)?),
+    };
+    let mut out = Vec::new();
+    decoder.take(limit as u64 + 1).read_to_end(&mut out)?;
+    if out.len() > limit {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("message exceeds {limit} bytes"),
+        ));
+    }
+    Ok(out)
+}
+
+/// Write `json` as one compressed frame.
+pub async fn write_frame<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    algorithm: Compression,
+    json: &str,
+) -> io::Result<()> {
+    frame::write(writer, &compress(algorithm, json.as_bytes())?).await
+}
+
+/// Read one compressed frame of at most `limit` bytes, compressed or not.
+/// `None` when the peer closed the connection between frames.
+pub async fn read_frame<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    algorithm: Compression,
+    limit: usize,
+) -> io::Result<Option<String>> {
+    let Some(payload) = frame::read(reader, limit).await? else {
+        return Ok(None);
+    };
+    let json = decompress(algorithm, &payload, limit)?;
+    String::from_utf8(json)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiation_prefers_the_client_order() {
+        assert_eq!(
+            negotiate(&[Compression::Gzip, Compression::Zstd]),
+            Some(Compression::Gzip)
+        );
+        assert_eq!(negotiate(&[]), None);
+    }
+
+    #[tokio::test]
+    async fn frames_round_trip() {
+        let context = "git status\n".repeat(2000);
+        for algorithm in SUPPORTED {
+            let mut buf = Vec::new();
+            write_frame(&mut buf, algorithm, &context).await.unwrap();
+            assert!(buf.len() < context.len() / 10, "{algorithm:?}");
+
+            let mut reader = &buf[..];
+            let message = read_frame(&mut reader, algorithm, 1 << 20).await.unwrap();
+            assert_eq!(message.as_deref(), Some(context.as_str()));
+            assert!(read_frame(&mut reader, algorithm, 1 << 20)
+                .await
+                .unwrap()
+                .is_none());
+        }
+    }
+
+    #[test]
+    fn decompression_is_bounded() {
+        let bomb = compress(Compression::Zstd, &[b'a'; 100_000]).unwrap();
+        assert!(decompress(Compression::Zstd, &bomb, 1000).is_err());
+        assert!(decompress(Compression::Zstd, &bomb, 100_000).is_ok());
+    }
+}
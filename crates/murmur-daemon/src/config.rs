@@ -1,4 +1,6 @@
+use murmur_protocol::{RiskLevel, Scope, SensitiveCategory};
 use murmur_providers::ProviderConfig;
+use murmur_voice::SttStrategy;
 use serde::Deserialize;
 use std::collections::HashMap;
 use std::path::PathBuf;
@@ -14,6 +16,28 @@ pub struct Config {
     pub voice: VoiceConfig,
     #[serde(default)]
     pub context: ContextConfig,
+    #[serde(default)]
+    pub completion: CompletionConfig,
+    #[serde(default)]
+    pub routing: RoutingConfig,
+    #[serde(default)]
+    pub ranking: RankingConfig,
+    #[serde(default)]
+    pub budget: BudgetConfig,
+    #[serde(default)]
+    pub notifications: NotificationsConfig,
+    #[serde(default)]
+    pub tts: TtsConfig,
+    #[serde(default)]
+    pub diagnose: DiagnoseConfig,
+    #[serde(default)]
+    pub policy: PolicyConfig,
+    #[serde(default)]
+    pub hooks: HooksConfig,
+    #[serde(default)]
+    pub abbreviations: AbbreviationsConfig,
+    #[serde(default)]
+    pub branch: BranchConfig,
 }
 
 #[derive(Debug, Deserialize)]
@@ -22,8 +46,97 @@ pub struct DaemonConfig {
     pub socket_path: String,
     #[serde(default = "default_cache_size")]
     pub cache_size: usize,
+    /// How long cached completions are served.
+    #[serde(default = "default_cache_ttl")]
+    pub cache_ttl_secs: u64,
+    /// Per-kind overrides of `cache_ttl_secs` ("command", "argument",
+    /// "path", "full_command" or "code"). A response lives as long as its
+    /// shortest-lived item.
+    #[serde(default)]
+    pub cache_ttl_by_kind: HashMap<String, u64>,
+    /// Per-provider overrides of `cache_ttl_secs`, by provider name.
+    #[serde(default)]
+    pub cache_ttl_by_provider: HashMap<String, u64>,
+    /// Per-route overrides of `cache_ttl_secs` ("shell", "code" or
+    /// "local"); a provider's own TTL wins.
+    #[serde(default)]
+    pub cache_ttl_by_route: HashMap<String, u64>,
+    /// Evict cached completions once they take about this many bytes,
+    /// as well as past `cache_size` entries. Unset bounds entries only.
+    #[serde(default)]
+    pub cache_max_bytes: Option<usize>,
     #[serde(default = "default_log_level")]
     pub log_level: String,
+    /// SQLite database for persistent state (history). An empty string
+    /// keeps everything in memory.
+    #[serde(default = "default_state_path")]
+    pub state_path: Option<String>,
+    /// How often to run every provider's health check; 0 never does.
+    #[serde(default = "default_health_check_interval")]
+    pub health_check_interval_secs: u64,
+    /// Address (e.g. `127.0.0.1:9477`) to serve `/healthz` and `/readyz`
+    /// on over HTTP, for container and supervisor probes. Unset serves
+    /// neither.
+    #[serde(default)]
+    pub health_listen: Option<String>,
+    /// Also accept connections on this TCP address (e.g. `0.0.0.0:7878`),
+    /// for shells in containers, VMs or WSL; they set `MURMUR_REMOTE` to
+    /// it. Needs `tokens`.
+    #[serde(default)]
+    pub listen_tcp: Option<String>,
+    /// Also accept WebSocket connections on this address; each message is
+    /// one JSON-RPC request. Needs `tokens`. See `websocket`.
+    #[serde(default)]
+    pub listen_ws: Option<String>,
+    /// `Origin`s allowed to open WebSocket connections. Browsers always
+    /// send one, so web pages are refused unless listed here.
+    #[serde(default)]
+    pub ws_origins: Vec<String>,
+    /// API tokens for `listen_tcp` and `listen_ws`. Network clients must
+    /// send one in `initialize` and can use only the methods its scopes
+    /// allow. See `auth`.
+    #[serde(default)]
+    pub tokens: Vec<ApiToken>,
+}
+
+impl DaemonConfig {
+    /// Per-kind and per-route cache TTLs must name a completion kind or
+    /// route.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        const KINDS: &[&str] = &["command", "argument", "path", "full_command", "code"];
+        const ROUTES: &[&str] = &["shell", "code", "local"];
+        if let Some(route) = self
+            .cache_ttl_by_route
+            .keys()
+            .find(|route| !ROUTES.contains(&route.as_str()))
+        {
+            anyhow::bail!(
+                "daemon.cache_ttl_by_route: unknown route {route:?}, expected one of {}",
+                ROUTES.join(", ")
+            );
+        }
+        if let Some(kind) = self
+            .cache_ttl_by_kind
+            .keys()
+            .find(|kind| !KINDS.contains(&kind.as_str()))
+        {
+            anyhow::bail!(
+                "daemon.cache_ttl_by_kind: unknown kind {kind:?}, expected one of {}",
+                KINDS.join(", ")
+            );
+        }
+        Ok(())
+    }
+}
+
+/// An API token in `[[daemon.tokens]]`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApiToken {
+    /// Who the token was made for, shown in logs.
+    #[serde(default)]
+    pub name: String,
+    pub token: String,
+    pub scopes: Vec<Scope>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -38,12 +151,84 @@ pub struct VoiceConfig {
     pub language: String,
     #[serde(default = "default_confidence")]
     pub confidence_threshold: f64,
+    /// With several STT engines: "failover" (one at a time), "race" (all at
+    /// once, first confident result wins) or "best" (all, most confident).
+    #[serde(default)]
+    pub strategy: SttStrategy,
     #[serde(default = "default_capture_timeout")]
     pub capture_timeout_ms: u64,
     #[serde(default)]
     pub deepgram_api_key: Option<String>,
+    /// Gzip audio uploaded to Deepgram, for slow uplinks.
+    #[serde(default)]
+    pub deepgram_gzip: bool,
+    /// Local speaker diarization for `murmur transcribe --speakers`: run
+    /// with a WAV file's path appended, it must print RTTM.
+    #[serde(default)]
+    pub diarize_command: Option<String>,
     #[serde(default = "default_restructurer")]
     pub restructurer: String,
+    /// Check that restructured commands can run, asking the restructurer
+    /// once more when one can't, then falling back to the transcript.
+    #[serde(default = "default_true")]
+    pub validate_commands: bool,
+    /// Tell clients to run voice commands immediately.
+    #[serde(default)]
+    pub auto_execute: bool,
+    /// In auto-execute mode, require a spoken "yes" before destructive commands.
+    #[serde(default = "default_true")]
+    pub confirm_destructive: bool,
+    /// How long a confirmation prompt stays answerable.
+    #[serde(default = "default_confirm_timeout")]
+    pub confirm_timeout_secs: u64,
+    /// What auto-execute asks for before each kind of sensitive command.
+    #[serde(default)]
+    pub sensitive: SensitiveConfig,
+}
+
+impl VoiceConfig {
+    /// The policy for `category`; with several, the strictest applies.
+    pub fn policy(&self, category: SensitiveCategory) -> ConfirmPolicy {
+        match category {
+            SensitiveCategory::Destructive => {
+                self.sensitive
+                    .destructive
+                    .unwrap_or(match self.confirm_destructive {
+                        true => ConfirmPolicy::Confirm,
+                        false => ConfirmPolicy::Allow,
+                    })
+            }
+            SensitiveCategory::Exfiltration => self.sensitive.exfiltration,
+            SensitiveCategory::Privilege => self.sensitive.privilege,
+        }
+    }
+}
+
+/// Per-category policy for voice commands the safety checker marks
+/// sensitive, applied in auto-execute mode.
+#[derive(Debug, Deserialize)]
+pub struct SensitiveConfig {
+    /// Unset follows `confirm_destructive`.
+    #[serde(default)]
+    pub destructive: Option<ConfirmPolicy>,
+    #[serde(default = "default_word_policy")]
+    pub exfiltration: ConfirmPolicy,
+    #[serde(default = "default_word_policy")]
+    pub privilege: ConfirmPolicy,
+}
+
+/// What a voice command needs before it runs, least to most strict.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfirmPolicy {
+    /// Nothing; it runs.
+    Allow,
+    /// A spoken "yes".
+    Confirm,
+    /// A word picked from a wordlist, named in the prompt.
+    Word,
+    /// Never run from voice; the command is shown for review.
+    Block,
 }
 
 #[derive(Debug, Deserialize)]
@@ -54,16 +239,353 @@ pub struct ContextConfig {
     pub git_enabled: bool,
     #[serde(default = "default_true")]
     pub project_detection: bool,
+    /// Periodically condense each session's history into a short summary.
+    #[serde(default)]
+    pub summaries: bool,
+    #[serde(default = "default_summary_provider")]
+    pub summary_provider: String,
+    #[serde(default = "default_summary_interval")]
+    pub summary_interval_secs: u64,
+    #[serde(default = "default_summary_window")]
+    pub summary_window_secs: u64,
+    #[serde(default = "default_summary_min_commands")]
+    pub summary_min_commands: usize,
+    /// Watch the directories completions are asked for in, and drop their
+    /// cached completions and fingerprints when files there change.
+    #[serde(default = "default_true")]
+    pub watch_directories: bool,
+    /// Wait this long after a change for others before invalidating.
+    #[serde(default = "default_watch_debounce")]
+    pub watch_debounce_ms: u64,
+}
+
+/// Post-processing limits applied to every completion response.
+/// Clients can override both values per request.
+#[derive(Debug, Deserialize)]
+pub struct CompletionConfig {
+    #[serde(default = "default_max_items")]
+    pub max_items: usize,
+    #[serde(default)]
+    pub min_score: f64,
+    /// Hide suggestions riskier than this ("safe" or "caution"); unset
+    /// shows everything.
+    #[serde(default)]
+    pub max_risk: Option<RiskLevel>,
+    /// Hide suggestions whose program wasn't found on this machine.
+    #[serde(default)]
+    pub hide_unverified: bool,
+    /// Wait this long for typing to pause before asking a cloud provider;
+    /// a request overtaken meanwhile answers from local providers only.
+    /// 0 asks at once.
+    #[serde(default)]
+    pub settle_ms: u64,
+    /// Wait this long after a `complete` for another on the same
+    /// connection; a newer one whose input extends it answers instead.
+    /// 0 answers each at once.
+    #[serde(default)]
+    pub debounce_ms: u64,
+    /// Answer with whatever is ready once a completion has taken this
+    /// long: a local provider's suggestions, or nothing. 0 waits for the
+    /// provider chain however long it takes.
+    #[serde(default)]
+    pub latency_budget_ms: u64,
+    /// Per-route overrides of `latency_budget_ms`, keyed by route
+    /// ("shell", "code" or "local").
+    #[serde(default)]
+    pub latency_budget_by_route: HashMap<String, u64>,
+    /// When completions come from history, a table of common subcommands
+    /// and flags, and the file system instead of a model.
+    #[serde(default)]
+    pub heuristics: HeuristicsMode,
+}
+
+impl CompletionConfig {
+    /// `heuristics`, or always `Only` in a build with the
+    /// `heuristics-only` feature.
+    pub fn heuristics_mode(&self) -> HeuristicsMode {
+        if cfg!(feature = "heuristics-only") {
+            HeuristicsMode::Only
+        } else {
+            self.heuristics
+        }
+    }
+}
+
+/// When completions are made without a model.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HeuristicsMode {
+    /// When no provider is configured.
+    #[default]
+    Auto,
+    /// Always; no provider is started, so nothing leaves the machine.
+    Only,
+    /// Never; completions are empty without a provider.
+    Off,
+}
+
+/// Provider routing strategy.
+///
+/// With `tiered = true`, the fast provider answers immediately and the strong
+/// provider is queried in the background; its results replace the cache entry.
+#[derive(Debug, Deserialize)]
+pub struct RoutingConfig {
+    #[serde(default)]
+    pub tiered: bool,
+    #[serde(default = "default_fast_provider")]
+    pub fast_provider: String,
+    #[serde(default = "default_strong_provider")]
+    pub strong_provider: String,
+    /// How the provider chain is tried.
+    #[serde(default)]
+    pub strategy: RoutingStrategy,
+    /// With `strategy = "race"`, how long the first provider has before the
+    /// second is asked too.
+    #[serde(default = "default_hedge_delay_ms")]
+    pub hedge_delay_ms: u64,
+    /// Put the provider that has been answering completions fastest first
+    /// in the chain. Queries and diagnoses keep the fixed order.
+    #[serde(default)]
+    pub adaptive: bool,
+    /// Also ask this provider for every completion, in the background,
+    /// and log whose suggestion was run (see `shadow`).
+    #[serde(default)]
+    pub shadow_provider: Option<String>,
+    /// JSON Lines file the shadow evaluation is appended to.
+    #[serde(default = "default_shadow_log")]
+    pub shadow_log: String,
+}
+
+/// How a completion chain is tried.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RoutingStrategy {
+    /// One provider at a time, moving on when one fails.
+    #[default]
+    Failover,
+    /// Hedge a slow first provider with the second and take whichever
+    /// answers first.
+    Race,
+    /// Ask the first two providers at once and combine their suggestions.
+    Merge,
+}
+
+/// Weights of the signals completions are ranked by. Each signal is scored
+/// 0–1 per suggestion and the final score is their weighted average, so
+/// only the ratios matter. The defaults rank by provider score alone.
+///
+/// Reloaded while the daemon runs when the config file changes.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct RankingConfig {
+    /// The score the provider gave the suggestion.
+    #[serde(default = "default_provider_weight")]
+    pub provider: f64,
+    /// How often the command appears in recent history.
+    #[serde(default)]
+    pub history: f64,
+    /// How recently the command was last run.
+    #[serde(default)]
+    pub recency: f64,
+    /// Whether the command was run in the current directory before.
+    #[serde(default)]
+    pub directory: f64,
+    /// How often the suggestion was run after being shown.
+    #[serde(default)]
+    pub acceptance: f64,
+}
+
+impl RankingConfig {
+    /// Weights must be finite and non-negative, and not all zero.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let weights = [
+            ("provider", self.provider),
+            ("history", self.history),
+            ("recency", self.recency),
+            ("directory", self.directory),
+            ("acceptance", self.acceptance),
+        ];
+        if let Some((name, weight)) = weights.iter().find(|(_, w)| !w.is_finite() || *w < 0.0) {
+            anyhow::bail!("ranking.{name} must be a non-negative number, got {weight}");
+        }
+        if weights.iter().all(|(_, w)| *w == 0.0) {
+            anyhow::bail!("ranking weights can't all be zero");
+        }
+        Ok(())
+    }
+}
+
+/// Daily limit on requests sent to cloud providers. Once exhausted, only
+/// local providers are used until the next UTC day.
+#[derive(Debug, Default, Deserialize)]
+pub struct BudgetConfig {
+    /// Maximum cloud requests per day (unset = unlimited).
+    #[serde(default)]
+    pub daily_requests: Option<u32>,
+}
+
+/// Desktop notifications for results that arrive after the user moved on.
+#[derive(Debug, Deserialize)]
+pub struct NotificationsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Announce background strong-model refinements.
+    #[serde(default = "default_true")]
+    pub on_refinement: bool,
+    /// Announce finished voice processing jobs.
+    #[serde(default = "default_true")]
+    pub on_voice: bool,
+    /// Only notify for jobs that took at least this long.
+    #[serde(default = "default_notify_min_elapsed")]
+    pub min_elapsed_ms: u64,
+    /// Offer the result on the clipboard (Linux: "Copy" action; macOS: copied
+    /// immediately).
+    #[serde(default)]
+    pub copy_to_clipboard: bool,
+}
+
+/// Spoken feedback (accessibility mode): read suggestions and voice results
+/// aloud through `say`, `espeak-ng`, `espeak` or `spd-say`.
+#[derive(Debug, Deserialize)]
+pub struct TtsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// "auto" or a specific command (say, espeak-ng, espeak, spd-say).
+    #[serde(default = "default_tts_engine")]
+    pub engine: String,
+    /// Words per minute (engine default when unset).
+    #[serde(default)]
+    pub rate: Option<u32>,
+    /// Engine-specific voice name.
+    #[serde(default)]
+    pub voice: Option<String>,
+    /// Read the top completion suggestion.
+    #[serde(default = "default_true")]
+    pub speak_completions: bool,
+    /// Read the generated command in voice command mode.
+    #[serde(default = "default_true")]
+    pub speak_voice_command: bool,
+    /// Read the cleaned-up text in voice natural mode.
+    #[serde(default = "default_true")]
+    pub speak_voice_natural: bool,
+}
+
+/// Restricted mode. With any allowed prefix or a policy file set, every
+/// suggested command must start with an allowed prefix; others are dropped
+/// and logged.
+#[derive(Debug, Default, Deserialize)]
+pub struct PolicyConfig {
+    /// Permitted command prefixes, matched word by word (e.g. "git log").
+    #[serde(default)]
+    pub allowed_prefixes: Vec<String>,
+    /// File with more prefixes, one per line (`#` starts a comment).
+    #[serde(default)]
+    pub policy_file: Option<String>,
+}
+
+/// Failure diagnoses for the post-command shell hook. The hook itself is
+/// opt-in (`murmur setup <shell> --diagnose`); these limits bound its cost.
+#[derive(Debug, Deserialize)]
+pub struct DiagnoseConfig {
+    /// Most provider requests per hour; cached answers don't count.
+    #[serde(default = "default_diagnose_per_hour")]
+    pub max_per_hour: u32,
+    /// Minimum time between provider requests.
+    #[serde(default = "default_diagnose_interval")]
+    pub min_interval_secs: u64,
+    /// How long a diagnosis is reused for the same command and error.
+    #[serde(default = "default_diagnose_cache_ttl")]
+    pub cache_ttl_secs: u64,
+}
+
+/// Scripts that rewrite completion requests, responses and history
+/// records; see `hooks`.
+#[derive(Debug, Deserialize)]
+pub struct HooksConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Directory holding the `on_request.rhai`, `on_response.rhai` and
+    /// `on_history_record.rhai` scripts.
+    #[serde(default = "default_hooks_dir")]
+    pub dir: String,
+    /// Longest a hook may run before it's stopped and ignored.
+    #[serde(default = "default_hook_timeout")]
+    pub timeout_ms: u64,
+}
+
+/// Abbreviations expanded by the shell widgets; see `abbr`.
+#[derive(Debug, Deserialize)]
+pub struct AbbreviationsConfig {
+    /// Also abbreviate frequent two-word commands by their initials.
+    #[serde(default)]
+    pub learn: bool,
+    /// Successful runs a command needs before it's abbreviated.
+    #[serde(default = "default_abbr_min_uses")]
+    pub min_uses: usize,
+    /// Abbreviations by name, e.g. `gco = "git checkout"`.
+    #[serde(default)]
+    pub define: HashMap<String, String>,
+}
+
+/// Branch names from `murmur branch`; see `branch`.
+#[derive(Debug, Deserialize)]
+pub struct BranchConfig {
+    /// Template with `{type}`, `{ticket}` and `{slug}`. Separators next to
+    /// a part that is empty (no ticket found) are dropped.
+    #[serde(default = "default_branch_pattern")]
+    pub pattern: String,
+    /// Longest name; the slug loses words from the end to fit.
+    #[serde(default = "default_branch_max_length")]
+    pub max_length: usize,
+    /// What `{type}` becomes for each change type, e.g. `feat = "feature"`.
+    /// Types not listed are used as they are.
+    #[serde(default)]
+    pub prefixes: HashMap<String, String>,
 }
 
 fn default_socket_path() -> String {
-    "/tmp/murmur.sock".to_string()
+    Config::runtime_dir()
+        .join(format!("{}.sock", file_stem(Config::profile().as_deref())))
+        .display()
+        .to_string()
+}
+
+/// `murmur`, or `murmur-<profile>` for the socket and PID file of a profile.
+fn file_stem(profile: Option<&str>) -> String {
+    match profile {
+        Some(profile) => format!("murmur-{profile}"),
+        None => "murmur".to_string(),
+    }
+}
+
+/// `path` with a leading `~/` replaced by the home directory.
+pub fn expand_home(path: &str) -> PathBuf {
+    match path.strip_prefix("~/") {
+        Some(rest) => PathBuf::from(std::env::var_os("HOME").unwrap_or_default()).join(rest),
+        None => PathBuf::from(path),
+    }
+}
+
+/// Config directory under `home` for `profile`.
+fn config_dir_in(home: &str, profile: Option<&str>) -> PathBuf {
+    let dir = PathBuf::from(format!("{home}/.config/murmur"));
+    match profile {
+        Some(profile) => dir.join("profiles").join(profile),
+        None => dir,
+    }
 }
 
 fn default_cache_size() -> usize {
     1000
 }
 
+fn default_cache_ttl() -> u64 {
+    300
+}
+
+fn default_health_check_interval() -> u64 {
+    300
+}
+
 fn default_log_level() -> String {
     "info".to_string()
 }
@@ -96,6 +618,108 @@ fn default_restructurer() -> String {
     "claude-cli".to_string()
 }
 
+fn default_confirm_timeout() -> u64 {
+    30
+}
+
+fn default_word_policy() -> ConfirmPolicy {
+    ConfirmPolicy::Word
+}
+
+fn default_state_path() -> Option<String> {
+    Some(Config::config_dir().join("state.db").display().to_string())
+}
+
+fn default_summary_provider() -> String {
+    "ollama".to_string()
+}
+
+fn default_summary_interval() -> u64 {
+    300
+}
+
+fn default_summary_window() -> u64 {
+    3600
+}
+
+fn default_summary_min_commands() -> usize {
+    5
+}
+
+fn default_watch_debounce() -> u64 {
+    500
+}
+
+fn default_notify_min_elapsed() -> u64 {
+    3000
+}
+
+fn default_tts_engine() -> String {
+    "auto".to_string()
+}
+
+fn default_max_items() -> usize {
+    10
+}
+
+fn default_fast_provider() -> String {
+    "ollama".to_string()
+}
+
+fn default_strong_provider() -> String {
+    "anthropic".to_string()
+}
+
+fn default_provider_weight() -> f64 {
+    1.0
+}
+
+fn default_hedge_delay_ms() -> u64 {
+    150
+}
+
+fn default_hooks_dir() -> String {
+    match Config::profile() {
+        Some(_) => Config::config_dir().join("hooks").display().to_string(),
+        None => "~/.config/murmur/hooks".to_string(),
+    }
+}
+
+fn default_hook_timeout() -> u64 {
+    250
+}
+
+fn default_abbr_min_uses() -> usize {
+    20
+}
+
+fn default_branch_pattern() -> String {
+    "{type}/{ticket}-{slug}".to_string()
+}
+
+fn default_branch_max_length() -> usize {
+    60
+}
+
+fn default_shadow_log() -> String {
+    Config::config_dir()
+        .join("shadow.jsonl")
+        .display()
+        .to_string()
+}
+
+fn default_diagnose_per_hour() -> u32 {
+    20
+}
+
+fn default_diagnose_interval() -> u64 {
+    5
+}
+
+fn default_diagnose_cache_ttl() -> u64 {
+    86_400
+}
+
 fn default_true() -> bool {
     true
 }
@@ -105,7 +729,49 @@ impl Default for DaemonConfig {
         Self {
             socket_path: default_socket_path(),
             cache_size: default_cache_size(),
+            cache_ttl_secs: default_cache_ttl(),
+            cache_ttl_by_kind: HashMap::new(),
+            cache_ttl_by_provider: HashMap::new(),
+            cache_ttl_by_route: HashMap::new(),
+            cache_max_bytes: None,
             log_level: default_log_level(),
+            state_path: default_state_path(),
+            health_check_interval_secs: default_health_check_interval(),
+            health_listen: None,
+            listen_tcp: None,
+            listen_ws: None,
+            ws_origins: Vec::new(),
+            tokens: Vec::new(),
+        }
+    }
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: default_hooks_dir(),
+            timeout_ms: default_hook_timeout(),
+        }
+    }
+}
+
+impl Default for AbbreviationsConfig {
+    fn default() -> Self {
+        Self {
+            learn: false,
+            min_uses: default_abbr_min_uses(),
+            define: HashMap::new(),
+        }
+    }
+}
+
+impl Default for BranchConfig {
+    fn default() -> Self {
+        Self {
+            pattern: default_branch_pattern(),
+            max_length: default_branch_max_length(),
+            prefixes: HashMap::new(),
         }
     }
 }
@@ -118,9 +784,27 @@ impl Default for VoiceConfig {
             hotkey: default_hotkey(),
             language: default_language(),
             confidence_threshold: default_confidence(),
+            strategy: SttStrategy::default(),
             capture_timeout_ms: default_capture_timeout(),
             deepgram_api_key: None,
+            deepgram_gzip: false,
+            diarize_command: None,
             restructurer: default_restructurer(),
+            validate_commands: true,
+            auto_execute: false,
+            confirm_destructive: true,
+            confirm_timeout_secs: default_confirm_timeout(),
+            sensitive: SensitiveConfig::default(),
+        }
+    }
+}
+
+impl Default for SensitiveConfig {
+    fn default() -> Self {
+        Self {
+            destructive: None,
+            exfiltration: default_word_policy(),
+            privilege: default_word_policy(),
         }
     }
 }
@@ -131,18 +815,102 @@ impl Default for ContextConfig {
             history_lines: default_history_lines(),
             git_enabled: default_true(),
             project_detection: default_true(),
+            summaries: false,
+            summary_provider: default_summary_provider(),
+            summary_interval_secs: default_summary_interval(),
+            summary_window_secs: default_summary_window(),
+            summary_min_commands: default_summary_min_commands(),
+            watch_directories: default_true(),
+            watch_debounce_ms: default_watch_debounce(),
+        }
+    }
+}
+
+impl Default for CompletionConfig {
+    fn default() -> Self {
+        Self {
+            max_items: default_max_items(),
+            min_score: 0.0,
+            max_risk: None,
+            hide_unverified: false,
+            settle_ms: 0,
+            debounce_ms: 0,
+            latency_budget_ms: 0,
+            latency_budget_by_route: HashMap::new(),
+            heuristics: HeuristicsMode::default(),
+        }
+    }
+}
+
+impl Default for RoutingConfig {
+    fn default() -> Self {
+        Self {
+            tiered: false,
+            fast_provider: default_fast_provider(),
+            strong_provider: default_strong_provider(),
+            strategy: RoutingStrategy::default(),
+            hedge_delay_ms: default_hedge_delay_ms(),
+            adaptive: false,
+            shadow_provider: None,
+            shadow_log: default_shadow_log(),
+        }
+    }
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            on_refinement: true,
+            on_voice: true,
+            min_elapsed_ms: default_notify_min_elapsed(),
+            copy_to_clipboard: false,
+        }
+    }
+}
+
+impl Default for RankingConfig {
+    fn default() -> Self {
+        Self {
+            provider: default_provider_weight(),
+            history: 0.0,
+            recency: 0.0,
+            directory: 0.0,
+            acceptance: 0.0,
+        }
+    }
+}
+
+impl Default for DiagnoseConfig {
+    fn default() -> Self {
+        Self {
+            max_per_hour: default_diagnose_per_hour(),
+            min_interval_secs: default_diagnose_interval(),
+            cache_ttl_secs: default_diagnose_cache_ttl(),
+        }
+    }
+}
+
+impl Default for TtsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            engine: default_tts_engine(),
+            rate: None,
+            voice: None,
+            speak_completions: true,
+            speak_voice_command: true,
+            speak_voice_natural: true,
         }
     }
 }
 
 impl Config {
-    /// Load config from the default path (~/.config/murmur/config.toml).
+    /// Load config from the default path (`config.toml` in `config_dir`).
     pub fn load() -> anyhow::Result<Self> {
         let path = Self::config_path();
         if path.exists() {
-            let content = std::fs::read_to_string(&path)?;
-            let config: Config = toml::from_str(&content)?;
-            Ok(config)
+            Self::load_from(&path)
         } else {
             Ok(Config::default())
         }
@@ -152,16 +920,59 @@ impl Config {
     pub fn load_from(path: &std::path::Path) -> anyhow::Result<Self> {
         let content = std::fs::read_to_string(path)?;
         let config: Config = toml::from_str(&content)?;
+        config.ranking.validate()?;
+        config.daemon.validate()?;
         Ok(config)
     }
 
     pub fn config_path() -> PathBuf {
+        Self::config_dir().join("config.toml")
+    }
+
+    /// `~/.config/murmur`, or `~/.config/murmur/profiles/<name>` under a
+    /// profile: the config file, state database (history), hooks and
+    /// shadow log are kept apart per profile.
+    pub fn config_dir() -> PathBuf {
         let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
-        PathBuf::from(format!("{home}/.config/murmur/config.toml"))
+        config_dir_in(&home, Self::profile().as_deref())
+    }
+
+    /// The profile named by `MURMUR_PROFILE`, which `murmur --profile`
+    /// sets. Unset, empty, `default` and invalid names mean no profile.
+    pub fn profile() -> Option<String> {
+        std::env::var("MURMUR_PROFILE")
+            .ok()
+            .filter(|name| name != "default" && Self::valid_profile(name))
+    }
+
+    /// Whether `name` can name a profile: letters, digits, `-` and `_`.
+    pub fn valid_profile(name: &str) -> bool {
+        !name.is_empty()
+            && name.len() <= 64
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    }
+
+    /// Directory for the socket and PID file: `$XDG_RUNTIME_DIR/murmur`,
+    /// `~/Library/Application Support/murmur` on macOS, else
+    /// `/tmp/murmur-<uid>`, so users on one machine don't share a daemon.
+    pub fn runtime_dir() -> PathBuf {
+        if let Some(dir) = std::env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+            return PathBuf::from(dir).join("murmur");
+        }
+        if cfg!(target_os = "macos") {
+            if let Some(home) = std::env::var_os("HOME") {
+                return PathBuf::from(home).join("Library/Application Support/murmur");
+            }
+        }
+        // SAFETY: getuid has no preconditions and can't fail
+        let uid = unsafe { libc::getuid() };
+        PathBuf::from(format!("/tmp/murmur-{uid}"))
     }
 
     pub fn pid_path() -> PathBuf {
-        PathBuf::from("/tmp/murmur.pid")
+        Self::runtime_dir().join(format!("{}.pid", file_stem(Self::profile().as_deref())))
     }
 }
 
@@ -172,9 +983,157 @@ mod tests {
     #[test]
     fn default_config() {
         let config = Config::default();
-        assert_eq!(config.daemon.socket_path, "/tmp/murmur.sock");
+        assert_eq!(
+            PathBuf::from(&config.daemon.socket_path),
+            Config::runtime_dir().join(format!("{}.sock", file_stem(Config::profile().as_deref())))
+        );
         assert_eq!(config.daemon.cache_size, 1000);
         assert!(!config.voice.enabled);
+        assert_eq!(config.completion.max_items, 10);
+        assert_eq!(config.completion.min_score, 0.0);
+        assert!(!config.routing.tiered);
+    }
+
+    #[test]
+    fn profiles_get_their_own_files() {
+        assert_eq!(file_stem(None), "murmur");
+        assert_eq!(file_stem(Some("work")), "murmur-work");
+        assert_eq!(
+            config_dir_in("/home/me", None),
+            PathBuf::from("/home/me/.config/murmur")
+        );
+        assert_eq!(
+            config_dir_in("/home/me", Some("work")),
+            PathBuf::from("/home/me/.config/murmur/profiles/work")
+        );
+        assert!(Config::valid_profile("client_a-2"));
+        assert!(!Config::valid_profile(""));
+        assert!(!Config::valid_profile("../other"));
+        assert!(!Config::valid_profile("a b"));
+    }
+
+    #[test]
+    fn parse_tiered_routing() {
+        let toml_str = r#"
+[routing]
+tiered = true
+fast_provider = "codestral"
+"#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert!(config.routing.tiered);
+        assert_eq!(config.routing.fast_provider, "codestral");
+        assert_eq!(config.routing.strong_provider, "anthropic");
+        assert_eq!(config.routing.strategy, RoutingStrategy::Failover);
+    }
+
+    #[test]
+    fn parse_cache_ttls() {
+        let config: Config =
+            toml::from_str("[daemon]\ncache_ttl_secs = 60\n[daemon.cache_ttl_by_kind]\npath = 5\n")
+                .unwrap();
+        assert_eq!(config.daemon.cache_ttl_secs, 60);
+        assert_eq!(config.daemon.cache_ttl_by_kind["path"], 5);
+        assert!(config.daemon.validate().is_ok());
+        let config: Config = toml::from_str("[daemon.cache_ttl_by_kind]\nfiles = 5\n").unwrap();
+        assert!(config.daemon.validate().is_err());
+        let config: Config =
+            toml::from_str("[daemon.cache_ttl_by_route]\ncode = 30\nremote = 5\n").unwrap();
+        assert!(config.daemon.validate().is_err());
+    }
+
+    #[test]
+    fn parse_ranking_weights() {
+        let config: Config = toml::from_str("[ranking]\nhistory = 0.5\n").unwrap();
+        assert_eq!(config.ranking.provider, 1.0);
+        assert_eq!(config.ranking.history, 0.5);
+        assert!(config.ranking.validate().is_ok());
+        assert!(RankingConfig::default().validate().is_ok());
+
+        let config: Config = toml::from_str("[ranking]\nrecency = -1.0\n").unwrap();
+        assert!(config.ranking.validate().is_err());
+        let config: Config = toml::from_str("[ranking]\nprovider = 0.0\n").unwrap();
+        assert!(config.ranking.validate().is_err());
+    }
+
+    #[test]
+    fn parse_race_routing() {
+        let config: Config =
+            toml::from_str("[routing]\nstrategy = \"race\"\nhedge_delay_ms = 0\n").unwrap();
+        assert_eq!(config.routing.strategy, RoutingStrategy::Race);
+        assert_eq!(config.routing.hedge_delay_ms, 0);
+        assert!(toml::from_str::<Config>("[routing]\nstrategy = \"fastest\"\n").is_err());
+        let config: Config = toml::from_str("[routing]\nstrategy = \"merge\"\n").unwrap();
+        assert_eq!(config.routing.strategy, RoutingStrategy::Merge);
+    }
+
+    #[test]
+    fn parse_custom_provider() {
+        let config: Config = toml::from_str(
+            "[providers.custom_vllm]\nbase_url = \"http://localhost:8000/v1\"\nmodel = \"qwen\"\napi_key_header = \"x-api-key\"\n",
+        )
+        .unwrap();
+        let vllm = &config.providers["custom_vllm"];
+        assert_eq!(vllm.endpoint.as_deref(), Some("http://localhost:8000/v1"));
+        assert_eq!(vllm.api_key_header.as_deref(), Some("x-api-key"));
+    }
+
+    #[test]
+    fn parse_abbreviations() {
+        let config: Config = toml::from_str(
+            "[abbreviations]\nlearn = true\n\n[abbreviations.define]\ngco = \"git checkout\"\n",
+        )
+        .unwrap();
+        assert!(config.abbreviations.learn);
+        assert_eq!(config.abbreviations.min_uses, 20);
+        assert_eq!(config.abbreviations.define["gco"], "git checkout");
+    }
+
+    #[test]
+    fn parse_branch() {
+        let config: Config =
+            toml::from_str("[branch]\nmax_length = 40\n\n[branch.prefixes]\nfeat = \"feature\"\n")
+                .unwrap();
+        assert_eq!(config.branch.pattern, "{type}/{ticket}-{slug}");
+        assert_eq!(config.branch.max_length, 40);
+        assert_eq!(config.branch.prefixes["feat"], "feature");
+    }
+
+    #[test]
+    fn parse_budget() {
+        let config: Config = toml::from_str("[budget]\ndaily_requests = 50\n").unwrap();
+        assert_eq!(config.budget.daily_requests, Some(50));
+        assert_eq!(Config::default().budget.daily_requests, None);
+    }
+
+    #[test]
+    fn parse_notifications() {
+        let toml_str = r#"
+[notifications]
+enabled = true
+on_voice = false
+"#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert!(config.notifications.enabled);
+        assert!(config.notifications.on_refinement);
+        assert!(!config.notifications.on_voice);
+        assert_eq!(config.notifications.min_elapsed_ms, 3000);
+    }
+
+    #[test]
+    fn parse_tts() {
+        let toml_str = r#"
+[tts]
+enabled = true
+engine = "espeak-ng"
+rate = 200
+speak_completions = false
+"#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert!(config.tts.enabled);
+        assert_eq!(config.tts.engine, "espeak-ng");
+        assert_eq!(config.tts.rate, Some(200));
+        assert!(!config.tts.speak_completions);
+        assert!(config.tts.speak_voice_command);
     }
 
     #[test]
@@ -195,6 +1154,11 @@ engine = "apple"
 
 [context]
 history_lines = 100
+
+[completion]
+max_items = 1
+min_score = 0.8
+settle_ms = 200
 "#;
         let config: Config = toml::from_str(toml_str).unwrap();
         assert_eq!(config.daemon.socket_path, "/tmp/test.sock");
@@ -202,5 +1166,8 @@ history_lines = 100
         assert!(config.providers.contains_key("anthropic"));
         assert!(config.voice.enabled);
         assert_eq!(config.context.history_lines, 100);
+        assert_eq!(config.completion.max_items, 1);
+        assert_eq!(config.completion.min_score, 0.8);
+        assert_eq!(config.completion.settle_ms, 200);
     }
 }
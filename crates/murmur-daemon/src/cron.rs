@@ -0,0 +1,404 @@
+//! Crontab schedules from a description (`murmur cron`).
+//!
+//! A provider turns "every weekday at 6pm" into the five time fields, which
+//! are parsed here before anything is returned: Vixie cron syntax with
+//! lists, ranges, steps, month and weekday names, and the `@daily` style
+//! shorthands. The next few run times are worked out in local time from
+//! the parsed schedule, so a schedule that never fires (`0 0 30 2 *`) is
+//! caught too.
+
+use crate::assets;
+use crate::budget::{self, Admit};
+use crate::localtime::{civil_from_days, utc_offset};
+use murmur_protocol::CronSuggestResponse;
+use murmur_providers::Provider;
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+/// Run times returned with a schedule.
+pub const NEXT_RUNS: usize = 3;
+
+/// How far ahead to look for run times. Eight years covers schedules that
+/// only fire on February 29th.
+const SEARCH_DAYS: i64 = 8 * 366;
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// System prompt for writing a schedule.
+pub const CRON_SYSTEM_PROMPT: &str = "You write crontab schedules. Respond ONLY with the \
+     five time fields of a crontab line (minute, hour, day of month, month, day of \
+     week) in standard Vixie cron syntax, e.g. `0 18 * * 1-5`. No seconds or year \
+     field, no command, no markdown fences or commentary.";
+
+/// A parsed crontab schedule, each field a bit set of the values it
+/// allows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Day of month and day of week were both restricted, so a day matching
+    /// either runs (cron's rule).
+    either_day: bool,
+    /// `@reboot`: runs at startup, never on the clock.
+    reboot: bool,
+}
+
+impl Schedule {
+    /// Parse the time fields at the start of a crontab line; anything after
+    /// them (the command) is ignored. Returns the schedule and its fields
+    /// as written.
+    pub fn parse(line: &str) -> Result<(Schedule, String), String> {
+        let mut fields = line.split_whitespace();
+        let first = fields.next().ok_or("empty schedule")?;
+        if let Some(name) = first.strip_prefix('@') {
+            let expanded = match name {
+                "yearly" | "annually" => "0 0 1 1 *",
+                "monthly" => "0 0 1 * *",
+                "weekly" => "0 0 * * 0",
+                "daily" | "midnight" => "0 0 * * *",
+                "hourly" => "0 * * * *",
+                "reboot" => {
+                    let schedule = Schedule {
+                        reboot: true,
+                        ..Schedule::parse("* * * * *")?.0
+                    };
+                    return Ok((schedule, first.to_string()));
+                }
+                _ => return Err(format!("unknown shorthand {first}")),
+            };
+            return Ok((Schedule::parse(expanded)?.0, first.to_string()));
+        }
+        let fields: Vec<&str> = std::iter::once(first).chain(fields.take(4)).collect();
+        if fields.len() < 5 {
+            return Err(format!(
+                "expected 5 fields (minute hour day month weekday), got {}",
+                fields.len()
+            ));
+        }
+        let schedule = Schedule {
+            minutes: parse_field(fields[0], "minute", 0, 59, &[])?,
+            hours: parse_field(fields[1], "hour", 0, 23, &[])?,
+            days: parse_field(fields[2], "day of month", 1, 31, &[])?,
+            months: parse_field(fields[3], "month", 1, 12, &MONTHS)?,
+            // 7 is Sunday too
+            weekdays: {
+                let set = parse_field(fields[4], "day of week", 0, 7, &WEEKDAYS)?;
+                (set | set >> 7) & 0x7f
+            },
+            either_day: !fields[2].starts_with('*') && !fields[4].starts_with('*'),
+            reboot: false,
+        };
+        Ok((schedule, fields.join(" ")))
+    }
+
+    /// Whether the schedule runs on the day `days` after the epoch.
+    fn runs_on(&self, days: i64) -> bool {
+        let (_, month, day) = civil_from_days(days);
+        let weekday = (days + 4).rem_euclid(7);
+        if !has(self.months, month) {
+            return false;
+        }
+        let (by_day, by_weekday) = (has(self.days, day), has(self.weekdays, weekday));
+        match self.either_day {
+            true => by_day || by_weekday,
+            false => by_day && by_weekday,
+        }
+    }
+
+    /// The first `count` run times after `now`, as unix timestamps. Fewer
+    /// if the schedule runs less often within the search window, none for
+    /// `@reboot`.
+    pub fn next_runs(&self, now: u64, count: usize) -> Vec<u64> {
+        let mut runs = Vec::new();
+        if self.reboot {
+            return runs;
+        }
+        let start = now as i64 + utc_offset(now);
+        let today = start.div_euclid(86_400);
+        for days in today..today + SEARCH_DAYS {
+            if !self.runs_on(days) {
+                continue;
+            }
+            for hour in (0..24).filter(|&h| has(self.hours, h)) {
+                for minute in (0..60).filter(|&m| has(self.minutes, m)) {
+                    let local = days * 86_400 + hour * 3600 + minute * 60;
+                    if local <= start {
+                        continue;
+                    }
+                    // The offset in effect then, so DST is mostly right
+                    let utc = local - utc_offset(local.max(0) as u64);
+                    runs.push(utc.max(0) as u64);
+                    if runs.len() == count {
+                        return runs;
+                    }
+                }
+            }
+        }
+        runs
+    }
+}
+
+fn has(set: u64, value: i64) -> bool {
+    set >> value & 1 == 1
+}
+
+/// Bit set of the values one field allows: `*`, `5`, `1-5`, `*/15`,
+/// `10-40/10`, `mon-fri` and comma-separated lists of them.
+fn parse_field(field: &str, name: &str, min: i64, max: i64, names: &[&str]) -> Result<u64, String> {
+    let value = |text: &str| -> Result<i64, String> {
+        let lower = text.to_ascii_lowercase();
+        let value = match names.iter().position(|n| *n == lower) {
+            // Names start at the field's first value (jan = 1, sun = 0)
+            Some(i) => i as i64 + min,
+            None => text
+                .parse()
+                .map_err(|_| format!("{name} field: {text:?} is not a number"))?,
+        };
+        if !(min..=max).contains(&value) {
+            return Err(format!("{name} field: {value} is out of range {min}-{max}"));
+        }
+        Ok(value)
+    };
+    let mut set = 0;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => match step.parse::<i64>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => return Err(format!("{name} field: bad step in {item:?}")),
+            },
+            None => (item, 1),
+        };
+        let (low, high) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((low, high)) => (value(low)?, value(high)?),
+                // `5/15` means from 5 to the end
+                None if item.contains('/') => (value(range)?, max),
+                None => {
+                    let v = value(range)?;
+                    (v, v)
+                }
+            },
+        };
+        if low > high {
+            return Err(format!("{name} field: {item:?} runs backwards"));
+        }
+        for v in (low..=high).step_by(step as usize) {
+            set |= 1 << v;
+        }
+    }
+    Ok(set)
+}
+
+/// The schedule in a model's reply, without fences or backticks around it.
+pub fn parse_reply(text: &str) -> Option<&str> {
+    text.lines()
+        .map(|line| line.trim().trim_matches('`').trim())
+        .find(|line| !line.is_empty() && !line.starts_with("```"))
+}
+
+/// Ask each provider in turn for a schedule that parses and runs.
+pub async fn run(
+    chain: &[Arc<dyn Provider>],
+    budget: &dyn Admit,
+    description: &str,
+    command: Option<&str>,
+    now: u64,
+) -> Result<CronSuggestResponse, String> {
+    let start = std::time::Instant::now();
+    let prompt = format!("Schedule: {}", description.trim());
+    let mut invalid = None;
+    let system = assets::prompt("cron", CRON_SYSTEM_PROMPT);
+    let mut attempted = false;
+    for provider in chain {
+        if !budget.admit(provider.as_ref()).await {
+            continue;
+        }
+        attempted = true;
+        let text = match provider.generate(&system, &prompt).await {
+            Ok(text) => text,
+            Err(e) => {
+                warn!(provider = provider.name(), error = %e, "Cron schedule failed");
+                continue;
+            }
+        };
+        let Some(reply) = parse_reply(&text) else {
+            debug!(provider = provider.name(), "Provider wrote no schedule");
+            continue;
+        };
+        let (schedule, fields) = match Schedule::parse(reply) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                debug!(provider = provider.name(), %reply, error = %e, "Schedule doesn't parse");
+                invalid = Some(e);
+                continue;
+            }
+        };
+        let next_runs = schedule.next_runs(now, NEXT_RUNS);
+        if next_runs.is_empty() && !schedule.reboot {
+            debug!(provider = provider.name(), %fields, "Schedule never runs");
+            invalid = Some(format!("{fields} never runs"));
+            continue;
+        }
+        let line = match command.map(str::trim).filter(|c| !c.is_empty()) {
+            Some(command) => format!("{fields} {command}"),
+            None => fields.clone(),
+        };
+        return Ok(CronSuggestResponse {
+            schedule: fields,
+            line,
+            next_runs,
+            provider: provider.name().to_string(),
+            latency_ms: start.elapsed().as_millis() as u64,
+        });
+    }
+    if !attempted && !chain.is_empty() {
+        return Err(budget::EXHAUSTED.to_string());
+    }
+    match invalid {
+        Some(e) => Err(format!("No provider wrote a valid schedule: {e}")),
+        None if chain.is_empty() => Err(
+            "No provider can write schedules; configure anthropic, openai, gemini, openrouter or ollama"
+                .to_string(),
+        ),
+        None => Err("All providers failed to write a schedule".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn runs(line: &str, now: &str) -> Vec<String> {
+        let (schedule, _) = Schedule::parse(line).unwrap();
+        let now = crate::localtime::parse_local(now).unwrap();
+        schedule
+            .next_runs(now, NEXT_RUNS)
+            .into_iter()
+            .map(crate::localtime::format_local)
+            .collect()
+    }
+
+    #[test]
+    fn fields_are_parsed() {
+        let (schedule, fields) =
+            Schedule::parse("*/15 9-17 * JAN,jul mon-fri /usr/bin/backup").unwrap();
+        assert_eq!(fields, "*/15 9-17 * JAN,jul mon-fri");
+        assert_eq!(schedule.minutes, 1 | 1 << 15 | 1 << 30 | 1 << 45);
+        assert_eq!(schedule.months, 1 << 1 | 1 << 7);
+        assert_eq!(schedule.weekdays, 0b011_1110);
+        assert_eq!(Schedule::parse("0 0 * * 7").unwrap().0.weekdays, 1);
+        assert_eq!(
+            Schedule::parse("5/20 * * * *").unwrap().0.minutes,
+            1 << 5 | 1 << 25 | 1 << 45
+        );
+        assert_eq!(Schedule::parse("@daily").unwrap().1, "@daily");
+    }
+
+    #[test]
+    fn bad_fields_are_explained() {
+        let error = |line| Schedule::parse(line).unwrap_err();
+        assert_eq!(error("60 * * * *"), "minute field: 60 is out of range 0-59");
+        assert_eq!(
+            error("0 18 * *"),
+            "expected 5 fields (minute hour day month weekday), got 4"
+        );
+        assert_eq!(
+            error("0 18 * * fri-mon"),
+            "day of week field: \"fri-mon\" runs backwards"
+        );
+        assert_eq!(error("*/0 * * * *"), "minute field: bad step in \"*/0\"");
+        assert_eq!(error("0 six * * *"), "hour field: \"six\" is not a number");
+        assert_eq!(error("@sometimes"), "unknown shorthand @sometimes");
+    }
+
+    #[test]
+    fn next_runs_follow_the_schedule() {
+        // 2026-10-16 is a Friday
+        assert_eq!(
+            runs("0 18 * * 1-5", "2026-10-16 17:00"),
+            ["2026-10-16 18:00", "2026-10-19 18:00", "2026-10-20 18:00"]
+        );
+        assert_eq!(
+            runs("30 * * * *", "2026-10-16 17:30"),
+            ["2026-10-16 18:30", "2026-10-16 19:30", "2026-10-16 20:30"]
+        );
+        // Day of month or Friday, when both are given
+        assert_eq!(
+            runs("0 0 1 * fri", "2026-10-16 12:00"),
+            ["2026-10-23 00:00", "2026-10-30 00:00", "2026-11-01 00:00"]
+        );
+        // Only two leap days in the search window
+        assert_eq!(
+            runs("0 12 29 2 *", "2026-10-16 12:00"),
+            ["2028-02-29 12:00", "2032-02-29 12:00"]
+        );
+        assert!(runs("0 0 30 2 *", "2026-10-16 12:00").is_empty());
+        assert!(runs("@reboot", "2026-10-16 12:00").is_empty());
+    }
+
+    #[test]
+    fn replies_are_cleaned_up() {
+        assert_eq!(parse_reply("```\n0 18 * * 1-5\n```"), Some("0 18 * * 1-5"));
+        assert_eq!(parse_reply("`@hourly`"), Some("@hourly"));
+        assert_eq!(parse_reply("  \n"), None);
+    }
+
+    struct Fake;
+
+    #[async_trait::async_trait]
+    impl Provider for Fake {
+        fn name(&self) -> &str {
+            "fake"
+        }
+
+        async fn complete(
+            &self,
+            _request: &murmur_protocol::CompletionRequest,
+            _context: &murmur_context::ShellContext,
+        ) -> Result<Vec<murmur_protocol::CompletionItem>, murmur_providers::ProviderError> {
+            Ok(vec![])
+        }
+
+        async fn generate(
+            &self,
+            _system: &str,
+            _prompt: &str,
+        ) -> Result<String, murmur_providers::ProviderError> {
+            Ok("0 18 * * 1-5".to_string())
+        }
+
+        async fn health_check(&self) -> Result<(), murmur_providers::ProviderError> {
+            Ok(())
+        }
+    }
+
+    struct Spent;
+
+    #[async_trait::async_trait]
+    impl Admit for Spent {
+        async fn admit(&self, _provider: &dyn Provider) -> bool {
+            false
+        }
+    }
+
+    #[tokio::test]
+    async fn a_spent_budget_is_not_a_provider_failure() {
+        let chain: Vec<Arc<dyn Provider>> = vec![Arc::new(Fake)];
+        let err = run(&chain, &Spent, "weekdays at six", None, 0)
+            .await
+            .unwrap_err();
+        assert_eq!(err, budget::EXHAUSTED);
+
+        let response = run(&chain, &budget::Unmetered, "weekdays at six", None, 0)
+            .await
+            .unwrap();
+        assert_eq!(response.line, "0 18 * * 1-5");
+    }
+}
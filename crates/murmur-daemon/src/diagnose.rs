@@ -0,0 +1,368 @@
+//! One-line diagnoses for failed commands ("that failed because ...").
+//!
+//! The opt-in post-command shell hook reports every command that exits
+//! non-zero. Failures the user caused on purpose (Ctrl-C, Ctrl-Z, a closed
+//! pipe) are skipped, answers are cached per command and error signature so
+//! a repeated failure costs nothing, and provider requests are rate-limited.
+
+use lru::LruCache;
+use murmur_context::ProjectType;
+use murmur_protocol::{DiagnoseRequest, DiagnoseResponse, RiskLevel};
+use murmur_providers::Provider;
+use serde::Deserialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+use crate::assets;
+use crate::budget::{self, Admit};
+use crate::config::DiagnoseConfig;
+use crate::safety;
+
+/// System prompt for diagnosing a failed command.
+pub const DIAGNOSE_SYSTEM_PROMPT: &str = "A shell command just failed. Explain the most \
+     likely cause in one short sentence and suggest a command that fixes it. Respond ONLY \
+     with a JSON object: {\"diagnosis\": \"...\", \"fix\": \"...\"}. Use null for fix when \
+     no single command would help. Don't repeat the failed command as the fix.";
+
+/// Diagnoses kept in the cache.
+const CACHE_CAPACITY: usize = 256;
+
+/// Error output lines sent to the provider.
+const STDERR_TAIL_LINES: usize = 20;
+
+/// Error output lines that identify a failure for caching.
+const SIGNATURE_LINES: usize = 3;
+
+const SECS_PER_HOUR: u64 = 3_600;
+
+/// Why a failure with this exit code isn't worth diagnosing, if it isn't.
+/// 128+n is death by signal n; these are signals the user sent.
+pub fn skip_reason(exit_code: i32) -> Option<&'static str> {
+    match exit_code {
+        0 => Some("succeeded"),
+        130 | 131 => Some("interrupted"),
+        141 => Some("broken pipe"),
+        146 | 148 => Some("suspended"),
+        _ => None,
+    }
+}
+
+/// What identifies this failure: the last lines of error output with
+/// numbers masked (line numbers, PIDs, timings vary between runs), or just
+/// the exit code when no output was captured.
+pub fn error_signature(exit_code: i32, stderr: Option<&str>) -> String {
+    let lines: Vec<&str> = stderr
+        .unwrap_or_default()
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect();
+    let tail = &lines[lines.len().saturating_sub(SIGNATURE_LINES)..];
+    let mut signature = format!("exit {exit_code}");
+    for line in tail {
+        signature.push('\n');
+        let mut in_number = false;
+        for c in line.chars() {
+            if c.is_ascii_digit() {
+                if !in_number {
+                    signature.push('#');
+                }
+                in_number = true;
+            } else {
+                signature.push(c);
+                in_number = false;
+            }
+        }
+    }
+    signature
+}
+
+/// User prompt describing the failure.
+pub fn build_prompt(request: &DiagnoseRequest, project: Option<&ProjectType>) -> String {
+    let mut prompt = format!(
+        "Command: {}\nExit code: {}",
+        request.command, request.exit_code
+    );
+    if request.exit_code > 128 {
+        prompt.push_str(&format!(" (killed by signal {})", request.exit_code - 128));
+    }
+    prompt.push_str(&format!("\nDirectory: {}", request.cwd));
+    if let Some(ref shell) = request.shell {
+        prompt.push_str(&format!("\nShell: {shell}"));
+    }
+    if let Some(project) = project {
+        prompt.push_str(&format!("\nProject type: {project:?}"));
+    }
+    if let Some(stderr) = request.stderr.as_deref().filter(|s| !s.trim().is_empty()) {
+        let lines: Vec<&str> = stderr.lines().collect();
+        let tail = &lines[lines.len().saturating_sub(STDERR_TAIL_LINES)..];
+        prompt.push_str(&format!("\nError output:\n{}", tail.join("\n")));
+    } else {
+        prompt.push_str("\nError output was not captured.");
+    }
+    prompt
+}
+
+/// Parse the model's answer into a diagnosis and an optional fix. Fixes
+/// that would destroy data are dropped; the hook shows them next to the
+/// prompt, one paste away from running.
+pub fn parse_diagnosis(text: &str, failed_command: &str) -> Option<(String, Option<String>)> {
+    #[derive(Deserialize)]
+    struct RawDiagnosis {
+        diagnosis: String,
+        fix: Option<String>,
+    }
+
+    let start = text.find('{')?;
+    let end = text.rfind('}')?;
+    let raw: RawDiagnosis = match serde_json::from_str(text.get(start..=end)?) {
+        Ok(raw) => raw,
+        Err(e) => {
+            debug!(error = %e, "Diagnosis is not valid JSON");
+            return None;
+        }
+    };
+
+    let diagnosis = raw.diagnosis.lines().next()?.trim().to_string();
+    if diagnosis.is_empty() {
+        return None;
+    }
+    let fix = raw
+        .fix
+        .map(|f| f.trim().to_string())
+        .filter(|f| !f.is_empty() && f != failed_command.trim())
+        .filter(|f| safety::classify(f) != RiskLevel::Destructive);
+    Some((diagnosis, fix))
+}
+
+/// Ask each provider in `chain` in turn until one gives a usable diagnosis.
+pub async fn diagnose(
+    chain: &[Arc<dyn Provider>],
+    budget: &dyn Admit,
+    request: &DiagnoseRequest,
+    project: Option<&ProjectType>,
+) -> Result<DiagnoseResponse, String> {
+    let prompt = build_prompt(request, project);
+    let system = assets::prompt("diagnose", DIAGNOSE_SYSTEM_PROMPT);
+    let mut attempted = false;
+    for provider in chain {
+        if !budget.admit(provider.as_ref()).await {
+            continue;
+        }
+        attempted = true;
+        match provider.generate(&system, &prompt).await {
+            Ok(text) => match parse_diagnosis(&text, &request.command) {
+                Some((diagnosis, fix)) => {
+                    return Ok(DiagnoseResponse {
+                        diagnosis: Some(diagnosis),
+                        fix,
+                        provider: Some(provider.name().to_string()),
+                        ..Default::default()
+                    })
+                }
+                None => debug!(provider = provider.name(), "No usable diagnosis"),
+            },
+            Err(e) => warn!(provider = provider.name(), error = %e, "Diagnosis failed"),
+        }
+    }
+
+    if !attempted && !chain.is_empty() {
+        return Err(budget::EXHAUSTED.to_string());
+    }
+    if chain.is_empty() {
+        Err(
+            "No provider available for diagnoses; configure anthropic, openai, gemini, openrouter or ollama"
+                .to_string(),
+        )
+    } else {
+        Err("All providers failed to diagnose the command".to_string())
+    }
+}
+
+/// Outcome of looking up a failure before asking a provider.
+#[derive(Debug)]
+pub enum Lookup {
+    /// Diagnosed before; reuse the answer.
+    Cached(DiagnoseResponse),
+    /// Too many provider requests recently.
+    RateLimited,
+    /// Ask a provider (the request has been counted).
+    Diagnose,
+}
+
+/// Cache and rate limit for diagnoses.
+pub struct Diagnoses {
+    cache: LruCache<u64, (u64, DiagnoseResponse)>,
+    cache_ttl_secs: u64,
+    max_per_hour: u32,
+    min_interval_secs: u64,
+    /// Times of provider requests within the last hour, oldest first.
+    requests: VecDeque<u64>,
+}
+
+impl Diagnoses {
+    pub fn new(config: &DiagnoseConfig) -> Self {
+        Self {
+            cache: LruCache::new(NonZeroUsize::new(CACHE_CAPACITY).unwrap()),
+            cache_ttl_secs: config.cache_ttl_secs,
+            max_per_hour: config.max_per_hour,
+            min_interval_secs: config.min_interval_secs,
+            requests: VecDeque::new(),
+        }
+    }
+
+    /// Apply new limits, keeping cached diagnoses and the requests already
+    /// made this hour.
+    pub fn configure(&mut self, config: &DiagnoseConfig) {
+        self.cache_ttl_secs = config.cache_ttl_secs;
+        self.max_per_hour = config.max_per_hour;
+        self.min_interval_secs = config.min_interval_secs;
+    }
+
+    /// Cache key for a failure: the command (whitespace-normalized) and its
+    /// error signature.
+    pub fn key(command: &str, signature: &str) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        command
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .hash(&mut hasher);
+        signature.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Check the cache, then the rate limit, at `now` (unix seconds).
+    pub fn lookup(&mut self, key: u64, now: u64) -> Lookup {
+        if let Some((created, response)) = self.cache.get(&key) {
+            if now.saturating_sub(*created) < self.cache_ttl_secs {
+                return Lookup::Cached(DiagnoseResponse {
+                    cached: true,
+                    ..response.clone()
+                });
+            }
+            self.cache.pop(&key);
+        }
+
+        while self
+            .requests
+            .front()
+            .is_some_and(|&t| now.saturating_sub(t) >= SECS_PER_HOUR)
+        {
+            self.requests.pop_front();
+        }
+        let too_soon = self
+            .requests
+            .back()
+            .is_some_and(|&t| now.saturating_sub(t) < self.min_interval_secs);
+        if too_soon || self.requests.len() >= self.max_per_hour as usize {
+            return Lookup::RateLimited;
+        }
+        self.requests.push_back(now);
+        Lookup::Diagnose
+    }
+
+    /// Remember a diagnosis produced at `now`.
+    pub fn insert(&mut self, key: u64, response: DiagnoseResponse, now: u64) {
+        self.cache.put(key, (now, response));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(max_per_hour: u32) -> DiagnoseConfig {
+        DiagnoseConfig {
+            max_per_hour,
+            min_interval_secs: 5,
+            cache_ttl_secs: 600,
+        }
+    }
+
+    #[test]
+    fn signals_from_the_user_are_skipped() {
+        assert_eq!(skip_reason(130), Some("interrupted"));
+        assert_eq!(skip_reason(148), Some("suspended"));
+        assert_eq!(skip_reason(0), Some("succeeded"));
+        assert_eq!(skip_reason(1), None);
+        assert_eq!(skip_reason(137), None);
+    }
+
+    #[test]
+    fn signature_masks_numbers() {
+        let a = error_signature(
+            1,
+            Some(
+                "Compiling app\nerror: src/main.rs:12:5 expected `;`\n\nbuild failed after 3.2s\n",
+            ),
+        );
+        let b = error_signature(
+            1,
+            Some("Compiling app\nerror: src/main.rs:40:9 expected `;`\nbuild failed after 11.0s"),
+        );
+        assert_eq!(a, b);
+        assert!(a.ends_with("build failed after #.#s"));
+        assert_eq!(error_signature(127, None), "exit 127");
+        assert_ne!(error_signature(2, None), error_signature(1, None));
+    }
+
+    #[test]
+    fn parses_diagnosis_and_drops_unsafe_fixes() {
+        let text = r#"Sure: {"diagnosis": "The lockfile is out of date.", "fix": "npm install"}"#;
+        assert_eq!(
+            parse_diagnosis(text, "npm ci"),
+            Some((
+                "The lockfile is out of date.".to_string(),
+                Some("npm install".to_string())
+            ))
+        );
+
+        let text = r#"{"diagnosis": "Disk is full.", "fix": "rm -rf /"}"#;
+        assert_eq!(parse_diagnosis(text, "make").unwrap().1, None);
+        let text = r#"{"diagnosis": "Flaky network.", "fix": "make"}"#;
+        assert_eq!(parse_diagnosis(text, "make").unwrap().1, None);
+        assert!(parse_diagnosis("no idea", "make").is_none());
+    }
+
+    #[test]
+    fn repeated_failures_hit_the_cache() {
+        let mut diagnoses = Diagnoses::new(&config(10));
+        let key = Diagnoses::key("cargo  build", "exit 101");
+        assert_eq!(key, Diagnoses::key("cargo build", "exit 101"));
+        assert!(matches!(diagnoses.lookup(key, 1_000), Lookup::Diagnose));
+        diagnoses.insert(
+            key,
+            DiagnoseResponse {
+                diagnosis: Some("Missing dependency.".to_string()),
+                ..Default::default()
+            },
+            1_000,
+        );
+        match diagnoses.lookup(key, 1_001) {
+            Lookup::Cached(response) => assert!(response.cached),
+            other => panic!("expected a cached diagnosis, got {other:?}"),
+        }
+        // Expired entries are diagnosed again
+        assert!(matches!(diagnoses.lookup(key, 1_600), Lookup::Diagnose));
+    }
+
+    #[test]
+    fn provider_requests_are_rate_limited() {
+        let mut diagnoses = Diagnoses::new(&config(2));
+        assert!(matches!(diagnoses.lookup(1, 1_000), Lookup::Diagnose));
+        // Within the minimum interval
+        assert!(matches!(diagnoses.lookup(2, 1_002), Lookup::RateLimited));
+        assert!(matches!(diagnoses.lookup(2, 1_010), Lookup::Diagnose));
+        // Hourly limit reached
+        assert!(matches!(diagnoses.lookup(3, 1_100), Lookup::RateLimited));
+        assert!(matches!(
+            diagnoses.lookup(3, 1_000 + SECS_PER_HOUR),
+            Lookup::Diagnose
+        ));
+    }
+}
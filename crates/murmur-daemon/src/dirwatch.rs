@@ -0,0 +1,343 @@
+//! Invalidation when files in a working directory change.
+//!
+//! Completions are cached per directory, and so is what the directory
+//! looks like (its fingerprint). Both go stale when a file appears or a
+//! `package.json` is edited, long before their TTL runs out. The daemon
+//! watches the directories completions were recently asked for in (the
+//! directories themselves, not what's under them) and reports a change
+//! once things settle, so a build or `npm install` writing hundreds of
+//! files becomes one invalidation. Linux uses inotify; other platforms
+//! poll the directory and its project files.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+use backend::Backend;
+
+/// Directories watched at once; the least recently used goes first.
+const MAX_WATCHED: usize = 16;
+
+/// A change in a watched directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirChange {
+    pub dir: String,
+    /// Whether a project file (`Cargo.toml`, `package.json`, ...) changed,
+    /// so the project's commands may have too. Otherwise only the names
+    /// in the directory did.
+    pub project: bool,
+}
+
+/// Adds directories to the watching task, which stops when this is
+/// dropped.
+pub struct DirWatcher {
+    dirs: mpsc::UnboundedSender<String>,
+}
+
+impl DirWatcher {
+    /// Start watching. Changes arrive on the receiver `debounce` after the
+    /// first of a burst, one per directory.
+    pub fn start(
+        debounce: Duration,
+    ) -> std::io::Result<(Self, mpsc::UnboundedReceiver<DirChange>)> {
+        let backend = Backend::new()?;
+        let (dirs, added) = mpsc::unbounded_channel();
+        let (changed, changes) = mpsc::unbounded_channel();
+        tokio::spawn(run(backend, added, changed, debounce));
+        Ok((Self { dirs }, changes))
+    }
+
+    /// Watch `dir`, or mark it recently used if it's already watched.
+    pub fn watch(&self, dir: &str) {
+        let _ = self.dirs.send(dir.to_string());
+    }
+}
+
+async fn run(
+    mut backend: Backend,
+    mut added: mpsc::UnboundedReceiver<String>,
+    changed: mpsc::UnboundedSender<DirChange>,
+    debounce: Duration,
+) {
+    let mut watched: VecDeque<String> = VecDeque::new();
+    let mut pending: HashMap<String, bool> = HashMap::new();
+    let mut settled_at: Option<Instant> = None;
+    loop {
+        tokio::select! {
+            dir = added.recv() => {
+                let Some(dir) = dir else {
+                    return;
+                };
+                if let Some(i) = watched.iter().position(|d| *d == dir) {
+                    watched.remove(i);
+                } else if let Err(e) = backend.add(&dir) {
+                    debug!(dir = %dir, error = %e, "Can't watch directory");
+                    continue;
+                } else if watched.len() >= MAX_WATCHED {
+                    if let Some(oldest) = watched.pop_front() {
+                        backend.remove(&oldest);
+                    }
+                }
+                watched.push_back(dir);
+            }
+            changes = backend.next() => {
+                let changes = match changes {
+                    Ok(changes) => changes,
+                    Err(e) => {
+                        warn!(error = %e, "Stopped watching directories");
+                        return;
+                    }
+                };
+                for change in changes {
+                    *pending.entry(change.dir).or_default() |= change.project;
+                }
+                if !pending.is_empty() {
+                    settled_at.get_or_insert_with(|| Instant::now() + debounce);
+                }
+            }
+            _ = tokio::time::sleep_until(settled_at.unwrap_or_else(Instant::now)), if settled_at.is_some() => {
+                settled_at = None;
+                for (dir, project) in pending.drain() {
+                    if changed.send(DirChange { dir, project }).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod backend {
+    use super::DirChange;
+    use murmur_context::ProjectDetector;
+    use std::collections::HashMap;
+    use std::ffi::CString;
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use tokio::io::unix::AsyncFd;
+
+    /// Files created, removed, renamed in or out, or saved.
+    const EVENTS: u32 = libc::IN_CREATE
+        | libc::IN_DELETE
+        | libc::IN_MOVED_FROM
+        | libc::IN_MOVED_TO
+        | libc::IN_CLOSE_WRITE
+        | libc::IN_ONLYDIR;
+
+    /// `struct inotify_event` without its name: wd, mask, cookie, len.
+    const HEADER: usize = 16;
+
+    /// An inotify instance and the directories it watches, by descriptor.
+    pub struct Backend {
+        fd: AsyncFd<OwnedFd>,
+        dirs: HashMap<i32, String>,
+    }
+
+    impl Backend {
+        pub fn new() -> io::Result<Self> {
+            // SAFETY: inotify_init1 takes no pointers
+            let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // SAFETY: fd was just opened and nothing else owns it
+            let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+            Ok(Self {
+                fd: AsyncFd::new(fd)?,
+                dirs: HashMap::new(),
+            })
+        }
+
+        pub fn add(&mut self, dir: &str) -> io::Result<()> {
+            let path =
+                CString::new(dir).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            // SAFETY: path is NUL-terminated and outlives the call
+            let wd = unsafe { libc::inotify_add_watch(self.fd.as_raw_fd(), path.as_ptr(), EVENTS) };
+            if wd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            self.dirs.insert(wd, dir.to_string());
+            Ok(())
+        }
+
+        pub fn remove(&mut self, dir: &str) {
+            let Some(wd) = self
+                .dirs
+                .iter()
+                .find(|(_, watched)| *watched == dir)
+                .map(|(wd, _)| *wd)
+            else {
+                return;
+            };
+            self.dirs.remove(&wd);
+            // SAFETY: wd came from inotify_add_watch on this instance
+            unsafe { libc::inotify_rm_watch(self.fd.as_raw_fd(), wd) };
+        }
+
+        /// The next batch of changes, once there is one.
+        pub async fn next(&mut self) -> io::Result<Vec<DirChange>> {
+            let mut buf = [0u8; 4096];
+            loop {
+                let mut ready = self.fd.readable().await?;
+                let read = ready.try_io(|fd| {
+                    // SAFETY: buf is valid for writes of its whole length
+                    let n =
+                        unsafe { libc::read(fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) };
+                    match n {
+                        n if n < 0 => Err(io::Error::last_os_error()),
+                        n => Ok(n as usize),
+                    }
+                });
+                if let Ok(read) = read {
+                    return Ok(self.parse(&buf[..read?]));
+                }
+            }
+        }
+
+        fn parse(&self, mut events: &[u8]) -> Vec<DirChange> {
+            let mut changes = Vec::new();
+            while events.len() >= HEADER {
+                let field = |at: usize| {
+                    u32::from_ne_bytes([events[at], events[at + 1], events[at + 2], events[at + 3]])
+                };
+                let (wd, mask, len) = (field(0) as i32, field(4), field(12) as usize);
+                let name = events.get(HEADER..HEADER + len).unwrap_or_default();
+                let name =
+                    String::from_utf8_lossy(name.split(|b| *b == 0).next().unwrap_or_default());
+                if mask & libc::IN_Q_OVERFLOW != 0 {
+                    // Events were lost; anything may have changed
+                    changes.extend(self.dirs.values().map(|dir| DirChange {
+                        dir: dir.clone(),
+                        project: true,
+                    }));
+                } else if let Some(dir) = self.dirs.get(&wd) {
+                    changes.push(DirChange {
+                        dir: dir.clone(),
+                        project: ProjectDetector::is_marker(&name),
+                    });
+                }
+                events = events.get(HEADER + len..).unwrap_or_default();
+            }
+            changes
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod backend {
+    use super::DirChange;
+    use murmur_context::ProjectDetector;
+    use std::collections::HashMap;
+    use std::io;
+    use std::path::Path;
+    use std::time::{Duration, SystemTime};
+
+    /// How often watched directories are looked at.
+    const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+    /// When a directory and its project files last changed.
+    #[derive(PartialEq)]
+    struct Snapshot {
+        modified: Option<SystemTime>,
+        markers: Vec<(String, Option<SystemTime>)>,
+    }
+
+    impl Snapshot {
+        fn of(dir: &str) -> Self {
+            let modified = |path: &Path| path.metadata().and_then(|m| m.modified()).ok();
+            let mut markers: Vec<_> = std::fs::read_dir(dir)
+                .into_iter()
+                .flatten()
+                .flatten()
+                .filter_map(|entry| {
+                    let name = entry.file_name().to_string_lossy().into_owned();
+                    ProjectDetector::is_marker(&name).then(|| (name, modified(&entry.path())))
+                })
+                .collect();
+            markers.sort();
+            Self {
+                modified: modified(Path::new(dir)),
+                markers,
+            }
+        }
+    }
+
+    /// Watched directories as last seen.
+    pub struct Backend {
+        dirs: HashMap<String, Snapshot>,
+        ticker: tokio::time::Interval,
+    }
+
+    impl Backend {
+        pub fn new() -> io::Result<Self> {
+            Ok(Self {
+                dirs: HashMap::new(),
+                ticker: tokio::time::interval(POLL_INTERVAL),
+            })
+        }
+
+        pub fn add(&mut self, dir: &str) -> io::Result<()> {
+            if !Path::new(dir).is_dir() {
+                return Err(io::Error::new(io::ErrorKind::NotFound, "not a directory"));
+            }
+            self.dirs.insert(dir.to_string(), Snapshot::of(dir));
+            Ok(())
+        }
+
+        pub fn remove(&mut self, dir: &str) {
+            self.dirs.remove(dir);
+        }
+
+        /// The changes since the last look, at the next poll.
+        pub async fn next(&mut self) -> io::Result<Vec<DirChange>> {
+            self.ticker.tick().await;
+            let mut changes = Vec::new();
+            for (dir, seen) in &mut self.dirs {
+                let now = Snapshot::of(dir);
+                if now != *seen {
+                    changes.push(DirChange {
+                        dir: dir.clone(),
+                        project: now.markers != seen.markers,
+                    });
+                    *seen = now;
+                }
+            }
+            Ok(changes)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn next(changes: &mut mpsc::UnboundedReceiver<DirChange>) -> DirChange {
+        tokio::time::timeout(Duration::from_secs(5), changes.recv())
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn reports_settled_changes() {
+        let dir = std::env::temp_dir().join(format!("murmur-dirwatch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (watcher, mut changes) = DirWatcher::start(Duration::from_millis(50)).unwrap();
+        watcher.watch(dir.to_str().unwrap());
+
+        // Let the watch land (and the first poll pass, when polling)
+        tokio::time::sleep(Duration::from_millis(1200)).await;
+        std::fs::write(dir.join("a.txt"), "a").unwrap();
+        std::fs::write(dir.join("b.txt"), "b").unwrap();
+        let change = next(&mut changes).await;
+        assert_eq!(change.dir, dir.to_str().unwrap());
+        assert!(!change.project);
+
+        std::fs::write(dir.join("package.json"), "{}").unwrap();
+        assert!(next(&mut changes).await.project);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
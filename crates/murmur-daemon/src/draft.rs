@@ -0,0 +1,205 @@
+//! PR descriptions and changelog sections from commit history
+//! (`murmur pr-draft`).
+//!
+//! The commits in a range, with their messages and the diffstat between
+//! its ends, go through `Provider::generate` with a prompt for the kind of
+//! draft asked for. Only messages are sent, not the diff: a branch's
+//! commits already say what changed, and a release's diff would not fit.
+
+use crate::assets;
+use crate::budget::{self, Admit};
+use murmur_context::LogEntry;
+use murmur_protocol::{DraftKind, SummarizeCommitsResponse};
+use murmur_providers::Provider;
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+/// Most commits read from the range.
+pub const MAX_COMMITS: usize = 200;
+
+/// Most bytes of commit messages put in the prompt.
+const MAX_LOG_BYTES: usize = 16_000;
+
+/// Most bytes kept of one commit's body.
+const MAX_BODY_BYTES: usize = 600;
+
+/// System prompt for a pull request description.
+pub const PR_SYSTEM_PROMPT: &str = "You write pull request descriptions from a branch's \
+     commits. Respond ONLY with Markdown: a title line starting with `# `, a short \
+     paragraph saying what the change does and why, then a `## Changes` section \
+     with one bullet per notable change. Call out breaking changes and anything \
+     reviewers should check. Merge commits and fixups of earlier commits in the \
+     list are not changes of their own. No commentary outside the description.";
+
+/// System prompt for a changelog section.
+pub const CHANGELOG_SYSTEM_PROMPT: &str = "You write changelog sections from commits, in \
+     the Keep a Changelog style. Respond ONLY with Markdown: `### Added`, `### \
+     Changed`, `### Fixed` and `### Removed` headings (only those that apply, in \
+     that order), each with one short bullet per change a user would notice. \
+     Leave out refactors, tests, CI and other internal commits. No commentary \
+     outside the changelog.";
+
+/// User prompt for the commits in `range`, newest first, and whether it
+/// had to be cut.
+pub fn build_prompt(range: &str, commits: &[LogEntry], stat: &str) -> (String, bool) {
+    let mut truncated = commits.len() >= MAX_COMMITS;
+    let mut log = String::new();
+    for (i, commit) in commits.iter().enumerate() {
+        let mut entry = format!("- {} {}\n", commit.hash, commit.subject);
+        if !commit.body.is_empty() {
+            let body = cut(&commit.body, MAX_BODY_BYTES);
+            truncated |= body.len() < commit.body.len();
+            for line in body.lines() {
+                entry.push_str(&format!("  {line}\n"));
+            }
+        }
+        if log.len() + entry.len() > MAX_LOG_BYTES {
+            log.push_str(&format!("({} older commits left out)\n", commits.len() - i));
+            truncated = true;
+            break;
+        }
+        log.push_str(&entry);
+    }
+    let mut prompt = format!("Range: {range}\n\nCommits, newest first:\n{log}");
+    if !stat.trim().is_empty() {
+        prompt.push_str(&format!("\nFiles changed:\n{}\n", stat.trim_end()));
+    }
+    (prompt, truncated)
+}
+
+/// `text` cut to at most `max` bytes, on a character boundary.
+fn cut(text: &str, max: usize) -> &str {
+    if text.len() <= max {
+        return text;
+    }
+    let mut end = max;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+/// The draft in a model's reply, without a fence around it.
+pub fn parse_draft(text: &str) -> Option<String> {
+    let text = text.trim();
+    let text = match text.strip_prefix("```") {
+        // The fence's first line may name a language
+        Some(fenced) => fenced
+            .split_once('\n')
+            .map_or("", |(_, rest)| rest)
+            .trim_end()
+            .trim_end_matches("```"),
+        None => text,
+    };
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// Ask each provider in turn until one writes a draft.
+pub async fn run(
+    chain: &[Arc<dyn Provider>],
+    budget: &dyn Admit,
+    kind: DraftKind,
+    range: &str,
+    commits: &[LogEntry],
+    stat: &str,
+) -> Result<SummarizeCommitsResponse, String> {
+    let start = std::time::Instant::now();
+    let (prompt, truncated) = build_prompt(range, commits, stat);
+    let system = match kind {
+        DraftKind::Pr => assets::prompt("pr", PR_SYSTEM_PROMPT),
+        DraftKind::Changelog => assets::prompt("changelog", CHANGELOG_SYSTEM_PROMPT),
+    };
+    let mut attempted = false;
+    for provider in chain {
+        if !budget.admit(provider.as_ref()).await {
+            continue;
+        }
+        attempted = true;
+        match provider.generate(&system, &prompt).await {
+            Ok(text) => match parse_draft(&text) {
+                Some(text) => {
+                    return Ok(SummarizeCommitsResponse {
+                        text,
+                        commits: commits.len(),
+                        provider: provider.name().to_string(),
+                        latency_ms: start.elapsed().as_millis() as u64,
+                        truncated,
+                    })
+                }
+                None => debug!(provider = provider.name(), "Provider wrote no draft"),
+            },
+            Err(e) => warn!(provider = provider.name(), error = %e, "Drafting failed"),
+        }
+    }
+    if !attempted && !chain.is_empty() {
+        return Err(budget::EXHAUSTED.to_string());
+    }
+    if chain.is_empty() {
+        Err(
+            "No provider can write drafts; configure anthropic, openai, gemini, openrouter or ollama"
+                .to_string(),
+        )
+    } else {
+        Err("All providers failed to write a draft".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commit(hash: &str, subject: &str, body: &str) -> LogEntry {
+        LogEntry {
+            hash: hash.to_string(),
+            subject: subject.to_string(),
+            body: body.to_string(),
+        }
+    }
+
+    #[test]
+    fn prompts_list_commits_and_files() {
+        let commits = [
+            commit("b2", "fix: keep the socket on reload", ""),
+            commit(
+                "a1",
+                "feat: add abbreviations",
+                "Expands on space.\nLearned too.",
+            ),
+        ];
+        let (prompt, truncated) = build_prompt("main..topic", &commits, " src/abbr.rs | 40 +++\n");
+        assert!(!truncated);
+        assert_eq!(
+            prompt,
+            "Range: main..topic\n\nCommits, newest first:\n\
+             - b2 fix: keep the socket on reload\n\
+             - a1 feat: add abbreviations\n  Expands on space.\n  Learned too.\n\
+             \nFiles changed:\n src/abbr.rs | 40 +++\n"
+        );
+    }
+
+    #[test]
+    fn long_histories_are_cut() {
+        let body = "é".repeat(MAX_BODY_BYTES);
+        let commits: Vec<_> = (0..100)
+            .map(|i| commit(&format!("{i:07x}"), "chore: bump", &body))
+            .collect();
+        let (prompt, truncated) = build_prompt("v1.0.0", &commits, "");
+        assert!(truncated);
+        assert!(prompt.len() < MAX_LOG_BYTES + 100);
+        assert!(prompt.ends_with("older commits left out)\n"));
+    }
+
+    #[test]
+    fn fences_are_removed() {
+        assert_eq!(
+            parse_draft("```markdown\n# Add abbreviations\n\nBody\n```").as_deref(),
+            Some("# Add abbreviations\n\nBody")
+        );
+        assert_eq!(
+            parse_draft("### Added\n- Abbreviations\n").as_deref(),
+            Some("### Added\n- Abbreviations")
+        );
+        assert_eq!(parse_draft("```\n```"), None);
+    }
+}
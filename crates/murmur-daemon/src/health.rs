@@ -0,0 +1,96 @@
+//! Periodic provider health checks, for `status` and `murmur doctor`.
+//!
+//! Each active provider's `health_check` runs every
+//! `[daemon] health_check_interval_secs`, and the latest outcome is kept:
+//! when it ran, how long it took and the error if it failed. Checks only
+//! report; routing still tries every provider in the chain.
+
+use murmur_providers::ProviderError;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Longest a single check may take before it counts as failed.
+pub const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The latest health check of one provider.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProviderHealth {
+    pub ok: bool,
+    /// Unix time the check finished.
+    pub checked_at: u64,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Latest health check results by provider name.
+#[derive(Debug, Default)]
+pub struct HealthChecks {
+    results: BTreeMap<String, ProviderHealth>,
+}
+
+impl HealthChecks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a check of `provider` that took `elapsed`.
+    pub fn record(
+        &mut self,
+        provider: &str,
+        result: Result<(), ProviderError>,
+        elapsed: Duration,
+        now: u64,
+    ) {
+        let error = result.err().map(|e| e.to_string());
+        self.results.insert(
+            provider.to_string(),
+            ProviderHealth {
+                ok: error.is_none(),
+                checked_at: now,
+                latency_ms: elapsed.as_millis() as u64,
+                error,
+            },
+        );
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<String, ProviderHealth> {
+        self.results.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latest_result_wins() {
+        let mut checks = HealthChecks::new();
+        let overloaded = ProviderError::Api {
+            status: 529,
+            message: "overloaded".to_string(),
+        };
+        checks.record("ollama", Err(overloaded), Duration::from_millis(3), 100);
+        checks.record("anthropic", Err(ProviderError::Timeout), CHECK_TIMEOUT, 100);
+        let snapshot = checks.snapshot();
+        assert!(!snapshot["ollama"].ok);
+        assert_eq!(
+            snapshot["ollama"].error.as_deref(),
+            Some("API error: 529 — overloaded")
+        );
+        assert_eq!(snapshot["anthropic"].latency_ms, 10_000);
+
+        checks.record("ollama", Ok(()), Duration::from_millis(40), 400);
+        let snapshot = checks.snapshot();
+        assert_eq!(
+            snapshot["ollama"],
+            ProviderHealth {
+                ok: true,
+                checked_at: 400,
+                latency_ms: 40,
+                error: None,
+            }
+        );
+    }
+}
@@ -0,0 +1,171 @@
+//! Hedged provider requests for `[routing] strategy = "race"`.
+//!
+//! The first provider in the chain gets a head start of `hedge_delay_ms`.
+//! If it hasn't answered by then, the second is asked too and whichever
+//! succeeds first wins; the other request is dropped, which cancels it.
+//! A delay of zero fires both at once. Trading some extra requests for
+//! lower tail latency is the point, so the second provider is only asked
+//! when the first is slow.
+
+use murmur_context::ShellContext;
+use murmur_protocol::{CompletionItem, CompletionRequest};
+use murmur_providers::{Provider, ProviderError};
+use std::future::Future;
+use std::pin::pin;
+use std::time::Duration;
+use tracing::debug;
+
+/// A provider's answer, or why it didn't give one.
+pub type Attempt<'a> = (&'a dyn Provider, Result<Vec<CompletionItem>, ProviderError>);
+
+/// Race `first` against `second`, giving `first` a head start of `delay`.
+/// `admit` is awaited before `second` is asked; returning false (e.g. no
+/// budget left) leaves `first` to answer alone.
+///
+/// Returns the attempts that finished, in the order they did: a success is
+/// always last, and a loser that was cancelled is not included.
+pub async fn race<'a>(
+    first: &'a dyn Provider,
+    second: &'a dyn Provider,
+    delay: Duration,
+    admit: impl Future<Output = bool>,
+    params: &CompletionRequest,
+    context: &ShellContext,
+) -> Vec<Attempt<'a>> {
+    let mut first_call = pin!(first.complete(params, context));
+    // Answered (or failed) within its head start: no race needed
+    if let Ok(result) = tokio::time::timeout(delay, &mut first_call).await {
+        return vec![(first, result)];
+    }
+    if !admit.await {
+        return vec![(first, first_call.await)];
+    }
+
+    debug!(
+        slow = first.name(),
+        hedge = second.name(),
+        "Provider slow, racing the next one"
+    );
+    let mut second_call = pin!(second.complete(params, context));
+    tokio::select! {
+        result = &mut first_call => finish((first, result), second, second_call).await,
+        result = &mut second_call => finish((second, result), first, first_call).await,
+    }
+}
+
+/// The attempts once `winner` finished: alone if it succeeded (dropping
+/// the other call), else followed by the other provider's attempt.
+async fn finish<'a>(
+    winner: Attempt<'a>,
+    other: &'a dyn Provider,
+    other_call: impl Future<Output = Result<Vec<CompletionItem>, ProviderError>>,
+) -> Vec<Attempt<'a>> {
+    if winner.1.is_ok() {
+        debug!(
+            winner = winner.0.name(),
+            cancelled = other.name(),
+            "Race won"
+        );
+        return vec![winner];
+    }
+    vec![winner, (other, other_call.await)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+
+    struct Fake {
+        name: &'static str,
+        delay: Duration,
+        fails: bool,
+    }
+
+    #[async_trait]
+    impl Provider for Fake {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        async fn complete(
+            &self,
+            _request: &CompletionRequest,
+            _context: &ShellContext,
+        ) -> Result<Vec<CompletionItem>, ProviderError> {
+            tokio::time::sleep(self.delay).await;
+            if self.fails {
+                return Err(ProviderError::Timeout);
+            }
+            Ok(vec![])
+        }
+
+        async fn health_check(&self) -> Result<(), ProviderError> {
+            Ok(())
+        }
+    }
+
+    fn fake(name: &'static str, delay_ms: u64, fails: bool) -> Fake {
+        Fake {
+            name,
+            delay: Duration::from_millis(delay_ms),
+            fails,
+        }
+    }
+
+    async fn names(first: &Fake, second: &Fake, delay_ms: u64, admit: bool) -> Vec<String> {
+        let params: CompletionRequest = serde_json::from_value(
+            serde_json::json!({"input": "git st", "cursor_pos": 6, "cwd": "/tmp"}),
+        )
+        .unwrap();
+        let context = ShellContext::default();
+        race(
+            first,
+            second,
+            Duration::from_millis(delay_ms),
+            async { admit },
+            &params,
+            &context,
+        )
+        .await
+        .into_iter()
+        .map(|(provider, result)| format!("{}:{}", provider.name(), result.is_ok()))
+        .collect()
+    }
+
+    #[tokio::test]
+    async fn fast_first_provider_is_not_hedged() {
+        let second = fake("second", 0, false);
+        assert_eq!(
+            names(&fake("first", 0, false), &second, 200, true).await,
+            vec!["first:true"]
+        );
+        // Failing inside its head start leaves the rest to the chain
+        assert_eq!(
+            names(&fake("first", 0, true), &second, 200, true).await,
+            vec!["first:false"]
+        );
+    }
+
+    #[tokio::test]
+    async fn slow_first_provider_loses_the_race() {
+        let first = fake("first", 2_000, false);
+        let second = fake("second", 10, false);
+        assert_eq!(names(&first, &second, 20, true).await, vec!["second:true"]);
+        // Without budget for the second, the first answers alone
+        assert_eq!(
+            names(&fake("first", 50, false), &second, 20, false).await,
+            vec!["first:true"]
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_winner_waits_for_the_other() {
+        let first = fake("first", 100, false);
+        let second = fake("second", 10, true);
+        assert_eq!(
+            names(&first, &second, 20, true).await,
+            vec!["second:false", "first:true"]
+        );
+    }
+}
@@ -1,14 +1,22 @@
-use murmur_protocol::HistoryEntry;
-use std::collections::VecDeque;
-use std::time::{SystemTime, UNIX_EPOCH};
+use murmur_protocol::{ContextUpdateRequest, HistoryEntry, HistoryFilter};
+use std::collections::{HashSet, VecDeque};
+use std::sync::Arc;
+use tracing::warn;
+
+use crate::clock::{Clock, SystemClock};
+use crate::store::{Store, StoreError};
 
 /// Cross-tool command history store.
 ///
 /// Stores command executions from all sources (terminal, Claude Code, Codex, etc.)
-/// in a bounded ring buffer. Newest entries are at the front.
+/// in a bounded ring buffer. Newest entries are at the front. With a
+/// persistent `Store`, every entry is also written to disk and the buffer
+/// is refilled from it on startup.
 pub struct CommandHistory {
     entries: VecDeque<HistoryEntry>,
     max_entries: usize,
+    store: Option<Store>,
+    clock: Arc<dyn Clock>,
 }
 
 impl CommandHistory {
@@ -16,25 +24,87 @@ impl CommandHistory {
         Self {
             entries: VecDeque::with_capacity(max_entries),
             max_entries,
+            store: None,
+            clock: Arc::new(SystemClock),
         }
     }
 
+    /// History backed by `store`, preloaded with its newest entries.
+    pub fn with_store(max_entries: usize, store: Store) -> Result<Self, StoreError> {
+        let entries = store.recent_history(max_entries)?.into();
+        Ok(Self {
+            entries,
+            max_entries,
+            store: Some(store),
+            clock: Arc::new(SystemClock),
+        })
+    }
+
+    /// Timestamp new entries by `clock` instead of the system clock.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
     /// Record a new command execution.
     pub fn record(&mut self, command: String, cwd: String, source: String, exit_code: i32) {
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
+        self.record_in_session(command, cwd, source, exit_code, None, None);
+    }
 
-        let entry = HistoryEntry {
+    /// Record a new command execution tagged with the reporting session and,
+    /// for SSH sessions, the remote host it ran on.
+    pub fn record_in_session(
+        &mut self,
+        command: String,
+        cwd: String,
+        source: String,
+        exit_code: i32,
+        session_id: Option<String>,
+        host: Option<String>,
+    ) {
+        let timestamp = self.clock.unix_now();
+        self.record_entry(HistoryEntry {
             command,
             cwd,
             source,
             exit_code,
             timestamp,
-        };
+            session_id,
+            host,
+            suggested_by: None,
+        });
+    }
+
+    /// Record a command reported by `context/update`, which ran on `host`
+    /// (`None` for the local machine).
+    pub fn record_update(&mut self, update: ContextUpdateRequest, host: Option<String>) {
+        let timestamp = self.clock.unix_now();
+        self.record_entry(HistoryEntry {
+            command: update.command,
+            cwd: update.cwd,
+            source: update.source,
+            exit_code: update.exit_code,
+            timestamp,
+            session_id: update.session_id,
+            host,
+            suggested_by: update.suggested_by,
+        });
+    }
 
-        self.entries.push_front(entry);
+    /// Record an entry with its own timestamp. Entries stay newest first even
+    /// if the clock stepped back since the last one, so `since` and `search`
+    /// never miss an entry.
+    pub fn record_entry(&mut self, entry: HistoryEntry) {
+        if let Some(ref store) = self.store {
+            if let Err(e) = store.insert_history(&entry) {
+                warn!(error = %e, "Failed to persist history entry");
+            }
+        }
+        // Almost always the front; ties go before older entries
+        let pos = self
+            .entries
+            .partition_point(|e| e.timestamp > entry.timestamp);
+        self.entries.insert(pos, entry);
 
         // Trim to max size
         while self.entries.len() > self.max_entries {
@@ -42,10 +112,76 @@ impl CommandHistory {
         }
     }
 
+    /// Merge previously recorded entries (e.g. imported shell history),
+    /// skipping any already present with the same command and timestamp,
+    /// or for `undated` entries the same command and source. Returns the
+    /// number of entries added.
+    pub fn import(
+        &mut self,
+        entries: Vec<HistoryEntry>,
+        undated: bool,
+    ) -> Result<usize, StoreError> {
+        if let Some(ref mut store) = self.store {
+            let imported = store.import_history(&entries, undated)?;
+            self.entries = store.recent_history(self.max_entries)?.into();
+            return Ok(imported);
+        }
+
+        let mut imported = 0;
+        if undated {
+            let mut seen: HashSet<(String, String)> = self
+                .entries
+                .iter()
+                .map(|e| (e.command.clone(), e.source.clone()))
+                .collect();
+            // Newest first, so repeated commands keep their latest time
+            for entry in entries.into_iter().rev() {
+                if seen.insert((entry.command.clone(), entry.source.clone())) {
+                    self.entries.push_back(entry);
+                    imported += 1;
+                }
+            }
+        } else {
+            let mut seen: HashSet<(String, u64)> = self
+                .entries
+                .iter()
+                .map(|e| (e.command.clone(), e.timestamp))
+                .collect();
+            for entry in entries {
+                if seen.insert((entry.command.clone(), entry.timestamp)) {
+                    self.entries.push_back(entry);
+                    imported += 1;
+                }
+            }
+        }
+        // Keep newest-first order so `since` stays correct
+        self.entries
+            .make_contiguous()
+            .sort_by_key(|e| std::cmp::Reverse(e.timestamp));
+        self.entries.truncate(self.max_entries);
+        Ok(imported)
+    }
+
     /// List recent entries, optionally filtered by cwd.
     pub fn list(&self, cwd: Option<&str>, limit: usize) -> Vec<&HistoryEntry> {
+        self.list_on_host(None, cwd, limit)
+    }
+
+    /// List recent entries from one remote host (all hosts when `host` is
+    /// `None`), optionally filtered by cwd. A cwd on one host says nothing
+    /// about the same path elsewhere, so both filters apply together.
+    pub fn list_on_host(
+        &self,
+        host: Option<&str>,
+        cwd: Option<&str>,
+        limit: usize,
+    ) -> Vec<&HistoryEntry> {
         self.entries
             .iter()
+            .filter(|e| match host {
+                Some(h) => e.host.as_deref() == Some(h),
+                None => true,
+            })
             .filter(|e| match cwd {
                 Some(dir) => e.cwd == dir,
                 None => true,
@@ -54,6 +190,44 @@ impl CommandHistory {
             .collect()
     }
 
+    /// Entries matching `filter`, newest first. Searches the whole store
+    /// when there is one, not just the entries kept in memory.
+    pub fn search(
+        &self,
+        filter: &HistoryFilter,
+        limit: usize,
+    ) -> Result<Vec<HistoryEntry>, StoreError> {
+        if let Some(ref store) = self.store {
+            let mut entries = store.history_between(filter.since, filter.until)?;
+            entries.retain(|e| matches(filter, e));
+            entries.truncate(limit);
+            return Ok(entries);
+        }
+        Ok(self
+            .entries
+            .iter()
+            .filter(|e| matches(filter, e))
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+
+    /// The newest `limit` entries, newest first, from the store when there is
+    /// one (so more than the in-memory buffer is available).
+    pub fn recent(&self, limit: usize) -> Result<Vec<HistoryEntry>, StoreError> {
+        match self.store {
+            Some(ref store) => store.recent_history(limit),
+            None => Ok(self.entries.iter().take(limit).cloned().collect()),
+        }
+    }
+
+    /// Entries recorded at or after `timestamp`, newest first.
+    pub fn since(&self, timestamp: u64) -> impl Iterator<Item = &HistoryEntry> {
+        self.entries
+            .iter()
+            .take_while(move |e| e.timestamp >= timestamp)
+    }
+
     /// Number of entries stored.
     pub fn len(&self) -> usize {
         self.entries.len()
@@ -65,9 +239,42 @@ impl CommandHistory {
     }
 }
 
+fn matches(filter: &HistoryFilter, entry: &HistoryEntry) -> bool {
+    if filter.since.is_some_and(|since| entry.timestamp < since)
+        || filter.until.is_some_and(|until| entry.timestamp >= until)
+    {
+        return false;
+    }
+    if filter.failed_only && entry.exit_code == 0 {
+        return false;
+    }
+    if let Some(ref source) = filter.source {
+        if &entry.source != source {
+            return false;
+        }
+    }
+    if let Some(ref cwd) = filter.cwd {
+        let dir = cwd.trim_end_matches('/');
+        let inside = entry
+            .cwd
+            .strip_prefix(dir)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'));
+        if !inside {
+            return false;
+        }
+    }
+    let command = entry.command.to_lowercase();
+    filter.keywords.is_empty()
+        || filter
+            .keywords
+            .iter()
+            .any(|k| command.contains(&k.to_lowercase()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn record_and_list() {
@@ -129,6 +336,63 @@ mod tests {
         assert_eq!(entries[0].command, "cmd 4");
     }
 
+    #[test]
+    fn since_returns_only_recent_entries() {
+        let mut history = CommandHistory::new(100);
+        history.record_in_session(
+            "make".to_string(),
+            "/tmp".to_string(),
+            "terminal".to_string(),
+            0,
+            Some("s1".to_string()),
+            None,
+        );
+        assert_eq!(history.since(0).count(), 1);
+        assert_eq!(history.since(u64::MAX).count(), 0);
+        assert_eq!(history.list(None, 1)[0].session_id.as_deref(), Some("s1"));
+    }
+
+    #[test]
+    fn filter_by_host() {
+        let mut history = CommandHistory::new(100);
+        history.record(
+            "ls".to_string(),
+            "/srv".to_string(),
+            "terminal".to_string(),
+            0,
+        );
+        history.record_in_session(
+            "systemctl status app".to_string(),
+            "/srv".to_string(),
+            "terminal".to_string(),
+            0,
+            None,
+            Some("devbox".to_string()),
+        );
+
+        let remote = history.list_on_host(Some("devbox"), Some("/srv"), 10);
+        assert_eq!(remote.len(), 1);
+        assert_eq!(remote[0].command, "systemctl status app");
+        assert_eq!(history.list(Some("/srv"), 10).len(), 2);
+        assert!(history.list_on_host(Some("other"), None, 10).is_empty());
+    }
+
+    #[test]
+    fn persisted_entries_are_reloaded() {
+        let mut history =
+            CommandHistory::with_store(10, Store::open_in_memory(&SystemClock).unwrap()).unwrap();
+        history.record(
+            "make".to_string(),
+            "/tmp".to_string(),
+            "terminal".to_string(),
+            0,
+        );
+        let store = history.store.take().unwrap();
+        let reloaded = CommandHistory::with_store(10, store).unwrap();
+        assert_eq!(reloaded.len(), 1);
+        assert_eq!(reloaded.list(None, 1)[0].command, "make");
+    }
+
     #[test]
     fn respects_limit() {
         let mut history = CommandHistory::new(100);
@@ -143,4 +407,227 @@ mod tests {
         let entries = history.list(None, 3);
         assert_eq!(entries.len(), 3);
     }
+
+    #[test]
+    fn import_merges_by_timestamp_without_duplicates() {
+        let mut history = CommandHistory::new(100);
+        history.record(
+            "cargo test".to_string(),
+            "/tmp".to_string(),
+            "terminal".to_string(),
+            0,
+        );
+        let old = |command: &str, timestamp| HistoryEntry {
+            command: command.to_string(),
+            cwd: String::new(),
+            source: "bash-history".to_string(),
+            exit_code: 0,
+            timestamp,
+            session_id: None,
+            host: None,
+            suggested_by: None,
+        };
+        let batch = vec![old("ls", 10), old("ls", 10), old("make", 20)];
+        assert_eq!(history.import(batch.clone(), false).unwrap(), 2);
+        assert_eq!(history.import(batch, false).unwrap(), 0);
+
+        let entries = history.list(None, 10);
+        assert_eq!(entries[0].command, "cargo test");
+        assert_eq!(entries[1].command, "make");
+        assert_eq!(history.since(20).count(), 2);
+    }
+
+    #[test]
+    fn search_applies_every_filter() {
+        let mut history = CommandHistory::new(100);
+        for (command, cwd, exit_code) in [
+            ("kubectl apply -f prod.yaml", "/work/app", 0),
+            ("fly deploy", "/work/app/web", 1),
+            ("fly deploy", "/work/other", 0),
+            ("ls", "/work/app", 0),
+        ] {
+            history.record(
+                command.to_string(),
+                cwd.to_string(),
+                "terminal".to_string(),
+                exit_code,
+            );
+        }
+
+        let mut filter = HistoryFilter {
+            keywords: vec!["Deploy".to_string(), "kubectl apply".to_string()],
+            cwd: Some("/work/app".to_string()),
+            ..Default::default()
+        };
+        let found = history.search(&filter, 10).unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].command, "fly deploy");
+
+        filter.failed_only = true;
+        assert_eq!(history.search(&filter, 10).unwrap().len(), 1);
+        filter.since = Some(u64::MAX);
+        assert!(history.search(&filter, 10).unwrap().is_empty());
+    }
+
+    /// Entries drawn from a small vocabulary so filters and timestamps
+    /// collide often. Timestamps are unordered, as with a clock that steps
+    /// back or history merged from another machine.
+    fn entry() -> impl Strategy<Value = HistoryEntry> {
+        (
+            prop::sample::select(vec!["ls", "git status", "cargo test", "make deploy"]),
+            prop::sample::select(vec!["/work", "/work/app", "/work/app/web", "/other"]),
+            prop::sample::select(vec!["terminal", "claude-code"]),
+            0..2i32,
+            0..40u64,
+            prop::option::of(prop::sample::select(vec!["devbox", "prod"])),
+        )
+            .prop_map(
+                |(command, cwd, source, exit_code, timestamp, host)| HistoryEntry {
+                    command: command.to_string(),
+                    cwd: cwd.to_string(),
+                    source: source.to_string(),
+                    exit_code,
+                    timestamp,
+                    session_id: None,
+                    host: host.map(String::from),
+                    suggested_by: None,
+                },
+            )
+    }
+
+    fn filter() -> impl Strategy<Value = HistoryFilter> {
+        (
+            prop::option::of(0..40u64),
+            prop::option::of(0..40u64),
+            prop::collection::vec(prop::sample::select(vec!["GIT", "test", "deploy"]), 0..2),
+            prop::option::of(prop::sample::select(vec!["/work", "/work/app/", "/wor"])),
+            prop::option::of(prop::sample::select(vec!["terminal", "claude-code"])),
+            any::<bool>(),
+        )
+            .prop_map(
+                |(since, until, keywords, cwd, source, failed_only)| HistoryFilter {
+                    since,
+                    until,
+                    keywords: keywords.into_iter().map(String::from).collect(),
+                    cwd: cwd.map(String::from),
+                    source: source.map(String::from),
+                    failed_only,
+                },
+            )
+    }
+
+    /// `matches`, spelled out independently.
+    fn expected_match(filter: &HistoryFilter, entry: &HistoryEntry) -> bool {
+        let dir = filter.cwd.as_deref().map(|d| d.trim_end_matches('/'));
+        filter.since.map_or(true, |t| entry.timestamp >= t)
+            && filter.until.map_or(true, |t| entry.timestamp < t)
+            && (!filter.failed_only || entry.exit_code != 0)
+            && filter.source.as_ref().map_or(true, |s| &entry.source == s)
+            && dir.map_or(true, |d| {
+                entry.cwd == d || entry.cwd.starts_with(&format!("{d}/"))
+            })
+            && (filter.keywords.is_empty()
+                || filter
+                    .keywords
+                    .iter()
+                    .any(|k| entry.command.to_lowercase().contains(&k.to_lowercase())))
+    }
+
+    fn timestamps(history: &CommandHistory) -> Vec<u64> {
+        history
+            .list(None, usize::MAX)
+            .iter()
+            .map(|e| e.timestamp)
+            .collect()
+    }
+
+    /// The same entries of one history, in the same order.
+    fn same(a: &[&HistoryEntry], b: &[&HistoryEntry]) -> bool {
+        a.len() == b.len() && a.iter().zip(b).all(|(x, y)| std::ptr::eq(*x, *y))
+    }
+
+    proptest! {
+        /// Recording keeps the newest `max_entries` entries, newest first,
+        /// whatever order they arrive in.
+        #[test]
+        fn recording_keeps_the_newest_in_order(
+            max in 1..20usize,
+            entries in prop::collection::vec(entry(), 0..60),
+        ) {
+            let mut history = CommandHistory::new(max);
+            for entry in &entries {
+                history.record_entry(entry.clone());
+                prop_assert!(history.len() <= max);
+            }
+
+            let mut expected: Vec<u64> = entries.iter().map(|e| e.timestamp).collect();
+            expected.sort_unstable_by(|a, b| b.cmp(a));
+            expected.truncate(max);
+            prop_assert_eq!(timestamps(&history), expected);
+        }
+
+        /// Imports never duplicate a command and timestamp and leave the
+        /// history bounded and newest first.
+        #[test]
+        fn imports_stay_bounded_ordered_and_unique(
+            max in 1..30usize,
+            batches in prop::collection::vec(prop::collection::vec(entry(), 0..20), 0..4),
+        ) {
+            let mut history = CommandHistory::new(max);
+            for batch in batches {
+                history.import(batch, false).unwrap();
+                prop_assert!(history.len() <= max);
+                let ts = timestamps(&history);
+                prop_assert!(ts.windows(2).all(|w| w[0] >= w[1]));
+                let mut seen = HashSet::new();
+                for e in history.list(None, usize::MAX) {
+                    prop_assert!(seen.insert((&e.command, e.timestamp)));
+                }
+            }
+        }
+
+        /// `since`, `list_on_host` and `search` return exactly the matching
+        /// entries, newest first, up to the limit.
+        #[test]
+        fn queries_return_exactly_the_matches(
+            entries in prop::collection::vec(entry(), 0..40),
+            since in 0..45u64,
+            host in prop::option::of(prop::sample::select(vec!["devbox", "prod"])),
+            cwd in prop::option::of(prop::sample::select(vec!["/work", "/work/app"])),
+            filter in filter(),
+            limit in 0..50usize,
+        ) {
+            let mut history = CommandHistory::new(100);
+            for entry in entries {
+                history.record_entry(entry);
+            }
+            let all = history.list(None, usize::MAX);
+
+            let recent: Vec<_> = history.since(since).collect();
+            let expected: Vec<_> = all.iter().copied().filter(|e| e.timestamp >= since).collect();
+            prop_assert!(same(&recent, &expected));
+
+            let listed = history.list_on_host(host, cwd, limit);
+            let expected: Vec<_> = all
+                .iter()
+                .copied()
+                .filter(|e| host.map_or(true, |h| e.host.as_deref() == Some(h)))
+                .filter(|e| cwd.map_or(true, |d| e.cwd == d))
+                .take(limit)
+                .collect();
+            prop_assert!(same(&listed, &expected));
+
+            let found = history.search(&filter, limit).unwrap();
+            let expected: Vec<HistoryEntry> = all
+                .iter()
+                .filter(|e| expected_match(&filter, e))
+                .take(limit)
+                .map(|e| (*e).clone())
+                .collect();
+            prop_assert_eq!(found.len(), expected.len());
+            for (f, e) in found.iter().zip(&expected) {
+                prop_assert_eq!((&f.command, &f.cwd, f.timestamp), (&e.command, &e.cwd, e.timestamp));
+            }
+        }
+    }
 }
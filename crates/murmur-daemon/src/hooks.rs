@@ -0,0 +1,299 @@
+//! User scripts that rewrite what passes through the daemon.
+//!
+//! With `[hooks] enabled`, a [rhai](https://rhai.rs) script in the hooks
+//! directory named `on_request.rhai`, `on_response.rhai` or
+//! `on_history_record.rhai` is run at that point, so filtering and
+//! rewriting need no compiled plugin. The value is in scope as `request`,
+//! `response` (next to the `request` it answers) or `record`, and what the
+//! script evaluates to replaces it; evaluating to `()` leaves it as is.
+//! Scripts are read on each call, so edits apply without a restart.
+//!
+//! Scripts run in an embedded engine with no file, process or network
+//! access and no `import`, under size limits, and are stopped at
+//! `timeout_ms`. One that doesn't compile, fails, times out or returns
+//! something of the wrong shape is logged and the value passes through
+//! unchanged, so a broken script can't take completions down.
+
+use murmur_protocol::{CompletionRequest, CompletionResponse, ContextUpdateRequest};
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{Dynamic, Engine, Scope};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+use crate::config::{expand_home, HooksConfig};
+
+pub const ON_REQUEST: &str = "on_request";
+pub const ON_RESPONSE: &str = "on_response";
+pub const ON_HISTORY_RECORD: &str = "on_history_record";
+
+/// Extension hook scripts are looked up with.
+const SCRIPT_EXTENSION: &str = "rhai";
+
+/// Largest string, array or map a script may build, so one can't eat the
+/// daemon's memory before its time is up.
+const MAX_STRING_SIZE: usize = 1 << 20;
+const MAX_ARRAY_SIZE: usize = 10_000;
+const MAX_MAP_SIZE: usize = 10_000;
+const MAX_CALL_LEVELS: usize = 32;
+
+/// The hook scripts in one directory.
+pub struct Hooks {
+    dir: PathBuf,
+    timeout: Duration,
+}
+
+impl Hooks {
+    /// Hooks as configured, or `None` if they're disabled.
+    pub fn from_config(config: &HooksConfig) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+        Some(Self::new(
+            expand_home(&config.dir),
+            Duration::from_millis(config.timeout_ms),
+        ))
+    }
+
+    pub fn new(dir: PathBuf, timeout: Duration) -> Self {
+        Self { dir, timeout }
+    }
+
+    /// `on_request`: rewrite a completion request before it's answered.
+    pub async fn on_request(&self, request: CompletionRequest) -> CompletionRequest {
+        let Some(value) = dynamic(ON_REQUEST, &request) else {
+            return request;
+        };
+        let mut scope = Scope::new();
+        scope.push_dynamic("request", value);
+        match self.run(ON_REQUEST, scope).await {
+            Some(value) => parse(ON_REQUEST, value).unwrap_or(request),
+            None => request,
+        }
+    }
+
+    /// `on_response`: rewrite suggestions before they're sent. The script
+    /// sees `request` and `response` and evaluates to the response.
+    pub async fn on_response(
+        &self,
+        request: &CompletionRequest,
+        response: CompletionResponse,
+    ) -> CompletionResponse {
+        let (Some(request), Some(value)) = (
+            dynamic(ON_RESPONSE, request),
+            dynamic(ON_RESPONSE, &response),
+        ) else {
+            return response;
+        };
+        let mut scope = Scope::new();
+        scope.push_dynamic("request", request);
+        scope.push_dynamic("response", value);
+        match self.run(ON_RESPONSE, scope).await {
+            Some(value) => parse(ON_RESPONSE, value).unwrap_or(response),
+            None => response,
+        }
+    }
+
+    /// `on_history_record`: rewrite a command before it's recorded, or
+    /// evaluate to `false` to keep it out of history. `None` if it was
+    /// dropped.
+    pub async fn on_history_record(
+        &self,
+        record: ContextUpdateRequest,
+    ) -> Option<ContextUpdateRequest> {
+        let Some(value) = dynamic(ON_HISTORY_RECORD, &record) else {
+            return Some(record);
+        };
+        let mut scope = Scope::new();
+        scope.push_dynamic("record", value);
+        match self.run(ON_HISTORY_RECORD, scope).await {
+            Some(value) if value.as_bool() == Ok(false) => {
+                debug!("History record dropped by hook");
+                None
+            }
+            Some(value) => Some(parse(ON_HISTORY_RECORD, value).unwrap_or(record)),
+            None => Some(record),
+        }
+    }
+
+    /// The script for `hook`, if there is one.
+    fn script(&self, hook: &str) -> Option<PathBuf> {
+        let path = self.dir.join(hook).with_extension(SCRIPT_EXTENSION);
+        path.is_file().then_some(path)
+    }
+
+    /// Run `hook` with `scope`: what it evaluated to, or `None` if that
+    /// was `()`, there's no script or it failed.
+    async fn run(&self, hook: &'static str, scope: Scope<'static>) -> Option<Dynamic> {
+        let path = self.script(hook)?;
+        let source = match tokio::fs::read_to_string(&path).await {
+            Ok(source) => source,
+            Err(e) => {
+                warn!(hook, script = %path.display(), error = %e, "Failed to read hook");
+                return None;
+            }
+        };
+        let timeout = self.timeout;
+        // Evaluation is synchronous; keep it off the runtime's workers
+        let result = tokio::task::spawn_blocking(move || {
+            let mut scope = scope;
+            sandboxed(hook, Instant::now() + timeout)
+                .eval_with_scope::<Dynamic>(&mut scope, &source)
+        })
+        .await;
+        match result {
+            Ok(Ok(value)) if value.is_unit() => None,
+            Ok(Ok(value)) => Some(value),
+            Ok(Err(e)) if matches!(*e, rhai::EvalAltResult::ErrorTerminated(..)) => {
+                warn!(
+                    hook,
+                    timeout_ms = timeout.as_millis() as u64,
+                    "Hook timed out, ignoring it"
+                );
+                None
+            }
+            Ok(Err(e)) => {
+                warn!(hook, error = %e, "Hook failed, ignoring it");
+                None
+            }
+            Err(e) => {
+                warn!(hook, error = %e, "Hook panicked, ignoring it");
+                None
+            }
+        }
+    }
+}
+
+/// An engine that can only compute: no module loading, `print` and
+/// `debug` go to the log, sizes are capped and evaluation is stopped once
+/// `deadline` passes.
+fn sandboxed(hook: &'static str, deadline: Instant) -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .set_module_resolver(DummyModuleResolver::new())
+        .set_max_string_size(MAX_STRING_SIZE)
+        .set_max_array_size(MAX_ARRAY_SIZE)
+        .set_max_map_size(MAX_MAP_SIZE)
+        .set_max_call_levels(MAX_CALL_LEVELS)
+        .on_print(move |text| debug!(hook, "{text}"))
+        .on_debug(move |text, _, pos| debug!(hook, %pos, "{text}"))
+        .on_progress(move |_| (Instant::now() >= deadline).then_some(Dynamic::UNIT));
+    engine
+}
+
+/// `value` as a script sees it.
+fn dynamic(hook: &str, value: &impl Serialize) -> Option<Dynamic> {
+    match rhai::serde::to_dynamic(value) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            warn!(hook, error = %e, "Failed to pass a value to hook");
+            None
+        }
+    }
+}
+
+fn parse<T: DeserializeOwned>(hook: &str, value: Dynamic) -> Option<T> {
+    match rhai::serde::from_dynamic(&value) {
+        Ok(parsed) => Some(parsed),
+        Err(e) => {
+            warn!(hook, error = %e, "Hook result has the wrong shape, ignoring it");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hooks_in(name: &str, scripts: &[(&str, &str)]) -> (Hooks, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("murmur-hooks-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (file, body) in scripts {
+            std::fs::write(dir.join(file), body).unwrap();
+        }
+        (Hooks::new(dir.clone(), Duration::from_millis(500)), dir)
+    }
+
+    fn request(input: &str) -> CompletionRequest {
+        serde_json::from_value(serde_json::json!({
+            "input": input,
+            "cursor_pos": input.len(),
+            "cwd": "/repo",
+        }))
+        .unwrap()
+    }
+
+    fn record(command: &str) -> ContextUpdateRequest {
+        serde_json::from_value(serde_json::json!({
+            "source": "terminal",
+            "command": command,
+            "cwd": "/repo",
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn hooks_rewrite_what_they_return() {
+        let (hooks, dir) = hooks_in(
+            "rewrite",
+            &[
+                (
+                    "on_request.rhai",
+                    r#"request.input.replace("gti ", "git "); request"#,
+                ),
+                (
+                    "on_history_record.rhai",
+                    r#"if record.command.contains("TOKEN=") { false }"#,
+                ),
+            ],
+        );
+        let rewritten = hooks.on_request(request("gti st")).await;
+        assert_eq!(rewritten.input, "git st");
+        assert_eq!(rewritten.cursor_pos, 6);
+        // Evaluating to `()` keeps the record
+        let kept = hooks.on_history_record(record("make")).await.unwrap();
+        assert_eq!(kept.command, "make");
+        assert!(hooks
+            .on_history_record(record("TOKEN=abc deploy"))
+            .await
+            .is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn broken_hooks_are_ignored() {
+        let (hooks, dir) = hooks_in(
+            "broken",
+            &[
+                ("on_request.rhai", "loop {}"),
+                ("on_history_record.rhai", "record.command ="),
+                ("on_response.rhai", r#"import "std" as s; response"#),
+            ],
+        );
+        let started = Instant::now();
+        assert_eq!(hooks.on_request(request("gti st")).await.input, "gti st");
+        assert!(started.elapsed() < Duration::from_secs(2));
+        let kept = hooks.on_history_record(record("make")).await.unwrap();
+        assert_eq!(kept.command, "make");
+        let response: CompletionResponse = serde_json::from_value(serde_json::json!({
+            "items": [], "provider": "history", "latency_ms": 1, "cached": false,
+        }))
+        .unwrap();
+        let response = hooks.on_response(&request("ls"), response).await;
+        assert_eq!(response.provider, "history");
+
+        // Wrong shape
+        std::fs::write(dir.join("on_history_record.rhai"), "42").unwrap();
+        let kept = hooks.on_history_record(record("make")).await.unwrap();
+        assert_eq!(kept.command, "make");
+
+        // Only `.rhai` files are hooks
+        std::fs::remove_file(dir.join("on_request.rhai")).unwrap();
+        std::fs::write(dir.join("on_request.lua"), "return nil").unwrap();
+        assert_eq!(hooks.script(ON_REQUEST), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,342 @@
+//! One-time import of native shell history (zsh, bash, fish, Atuin).
+//!
+//! Parsers keep timestamps where the format records them. Plain history
+//! files have none, so their entries get consecutive timestamps ending at
+//! the file's modification time, which preserves their order. Those
+//! timestamps change with every read, so such entries are kept apart and
+//! imported by command instead (see `HistoryImportRequest::undated`).
+
+use murmur_protocol::HistoryEntry;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ImportError {
+    #[error("Unknown history source: {0}. Supported: zsh, bash, fish, atuin")]
+    UnknownSource(String),
+    #[error("Failed to read {path}: {source}")]
+    Read {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("Failed to read Atuin database: {0}")]
+    Atuin(#[from] rusqlite::Error),
+}
+
+/// A history source that can be imported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistorySource {
+    Zsh,
+    Bash,
+    Fish,
+    Atuin,
+}
+
+impl HistorySource {
+    pub const ALL: [HistorySource; 4] = [Self::Zsh, Self::Bash, Self::Fish, Self::Atuin];
+
+    pub fn from_name(name: &str) -> Result<Self, ImportError> {
+        match name {
+            "zsh" => Ok(Self::Zsh),
+            "bash" => Ok(Self::Bash),
+            "fish" => Ok(Self::Fish),
+            "atuin" => Ok(Self::Atuin),
+            other => Err(ImportError::UnknownSource(other.to_string())),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Zsh => "zsh",
+            Self::Bash => "bash",
+            Self::Fish => "fish",
+            Self::Atuin => "atuin",
+        }
+    }
+
+    /// Where the source keeps its history by default.
+    pub fn default_path(self) -> PathBuf {
+        let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
+        let data =
+            std::env::var("XDG_DATA_HOME").unwrap_or_else(|_| format!("{home}/.local/share"));
+        match self {
+            Self::Zsh => match std::env::var("HISTFILE") {
+                Ok(path) if path.contains("zsh") => PathBuf::from(path),
+                _ => PathBuf::from(format!("{home}/.zsh_history")),
+            },
+            Self::Bash => PathBuf::from(format!("{home}/.bash_history")),
+            Self::Fish => PathBuf::from(format!("{data}/fish/fish_history")),
+            Self::Atuin => PathBuf::from(format!("{data}/atuin/history.db")),
+        }
+    }
+
+    /// `source` recorded on imported entries, e.g. "zsh-history".
+    fn entry_source(self) -> String {
+        format!("{}-history", self.name())
+    }
+}
+
+/// History read from a file, each list oldest first.
+#[derive(Debug, Default)]
+pub struct ReadHistory {
+    /// Entries with the time the source recorded.
+    pub dated: Vec<HistoryEntry>,
+    /// Entries the source recorded no time for, timed by the file's
+    /// modification time.
+    pub undated: Vec<HistoryEntry>,
+}
+
+impl ReadHistory {
+    pub fn len(&self) -> usize {
+        self.dated.len() + self.undated.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Read and parse history from `path`.
+pub fn read_history(source: HistorySource, path: &Path) -> Result<ReadHistory, ImportError> {
+    let read_err = |e| ImportError::Read {
+        path: path.display().to_string(),
+        source: e,
+    };
+
+    if source == HistorySource::Atuin {
+        return Ok(ReadHistory {
+            dated: read_atuin(path)?,
+            undated: Vec::new(),
+        });
+    }
+
+    let bytes = std::fs::read(path).map_err(read_err)?;
+    let mtime = std::fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs());
+
+    let parsed = match source {
+        HistorySource::Zsh => parse_zsh(&unmetafy(&bytes)),
+        HistorySource::Bash => parse_bash(&String::from_utf8_lossy(&bytes)),
+        HistorySource::Fish => parse_fish(&String::from_utf8_lossy(&bytes)),
+        HistorySource::Atuin => unreachable!(),
+    };
+    Ok(into_entries(parsed, source, mtime))
+}
+
+/// A parsed command with its timestamp, if the format had one.
+type Parsed = (String, Option<u64>);
+
+fn into_entries(parsed: Vec<Parsed>, source: HistorySource, mtime: u64) -> ReadHistory {
+    let count = parsed.len() as u64;
+    let mut read = ReadHistory::default();
+    for (i, (command, timestamp)) in parsed.into_iter().enumerate() {
+        if command.trim().is_empty() {
+            continue;
+        }
+        let entry = HistoryEntry {
+            command: command.trim().to_string(),
+            cwd: String::new(),
+            source: source.entry_source(),
+            exit_code: 0,
+            timestamp: timestamp.unwrap_or_else(|| mtime.saturating_sub(count - i as u64)),
+            session_id: None,
+            host: None,
+            suggested_by: None,
+        };
+        match timestamp {
+            Some(_) => read.dated.push(entry),
+            None => read.undated.push(entry),
+        }
+    }
+    read
+}
+
+/// zsh stores non-ASCII bytes "metafied": 0x83 followed by the byte XOR 32.
+fn unmetafy(bytes: &[u8]) -> String {
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut iter = bytes.iter();
+    while let Some(&b) = iter.next() {
+        if b == 0x83 {
+            if let Some(&next) = iter.next() {
+                out.push(next ^ 32);
+            }
+        } else {
+            out.push(b);
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Plain lines, or `: <start>:<elapsed>;<command>` with EXTENDED_HISTORY.
+/// A trailing backslash continues the command on the next line.
+fn parse_zsh(text: &str) -> Vec<Parsed> {
+    let mut entries: Vec<Parsed> = Vec::new();
+    let mut continuing = false;
+    for line in text.lines() {
+        if continuing {
+            if let Some((command, _)) = entries.last_mut() {
+                command.pop(); // the backslash
+                command.push('\n');
+                command.push_str(line);
+            }
+        } else if let Some((timestamp, command)) = line
+            .strip_prefix(": ")
+            .and_then(|rest| rest.split_once(';'))
+            .and_then(|(meta, command)| {
+                let start = meta.split(':').next()?.trim().parse().ok()?;
+                Some((start, command))
+            })
+        {
+            entries.push((command.to_string(), Some(timestamp)));
+        } else {
+            entries.push((line.to_string(), None));
+        }
+        continuing = line.ends_with('\\');
+    }
+    entries
+}
+
+/// Plain lines, optionally preceded by `#<unix time>` (HISTTIMEFORMAT).
+fn parse_bash(text: &str) -> Vec<Parsed> {
+    let mut entries = Vec::new();
+    let mut timestamp = None;
+    for line in text.lines() {
+        if let Some(ts) = line.strip_prefix('#').and_then(|t| t.parse().ok()) {
+            timestamp = Some(ts);
+            continue;
+        }
+        entries.push((line.to_string(), timestamp.take()));
+    }
+    entries
+}
+
+/// fish's YAML-like format: `- cmd: <command>` followed by `  when: <time>`.
+fn parse_fish(text: &str) -> Vec<Parsed> {
+    let mut entries: Vec<Parsed> = Vec::new();
+    for line in text.lines() {
+        if let Some(command) = line.strip_prefix("- cmd: ") {
+            entries.push((unescape_fish(command), None));
+        } else if let Some(when) = line.trim_start().strip_prefix("when: ") {
+            if let Some((_, timestamp)) = entries.last_mut() {
+                *timestamp = when.trim().parse().ok();
+            }
+        }
+    }
+    entries
+}
+
+fn unescape_fish(command: &str) -> String {
+    let mut out = String::with_capacity(command.len());
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') => out.push('\n'),
+                Some('\\') => out.push('\\'),
+                Some(other) => {
+                    out.push('\\');
+                    out.push(other);
+                }
+                None => out.push('\\'),
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Atuin keeps cwd, exit code, session and host, so those are imported too.
+fn read_atuin(path: &Path) -> Result<Vec<HistoryEntry>, ImportError> {
+    if !path.exists() {
+        return Err(ImportError::Read {
+            path: path.display().to_string(),
+            source: std::io::Error::from(std::io::ErrorKind::NotFound),
+        });
+    }
+    let conn =
+        rusqlite::Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut stmt = conn.prepare(
+        "SELECT command, cwd, exit, timestamp, session, hostname FROM history
+         WHERE deleted_at IS NULL ORDER BY timestamp",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        // Atuin timestamps are nanoseconds; hostname is "host:user"
+        let nanos: i64 = row.get(3)?;
+        let hostname: Option<String> = row.get(5)?;
+        Ok(HistoryEntry {
+            command: row.get(0)?,
+            cwd: row.get(1)?,
+            source: HistorySource::Atuin.entry_source(),
+            exit_code: row.get(2)?,
+            timestamp: (nanos / 1_000_000_000).max(0) as u64,
+            session_id: row.get(4)?,
+            host: hostname.and_then(|h| h.split(':').next().map(String::from)),
+            suggested_by: None,
+        })
+    })?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zsh_extended_and_multiline() {
+        let parsed = parse_zsh(": 1700000000:0;git status\n: 1700000005:2;echo a \\\nb\nls\n");
+        assert_eq!(parsed[0], ("git status".to_string(), Some(1700000000)));
+        assert_eq!(parsed[1], ("echo a \nb".to_string(), Some(1700000005)));
+        assert_eq!(parsed[2], ("ls".to_string(), None));
+    }
+
+    #[test]
+    fn zsh_metafied_bytes_are_decoded() {
+        // "é" is 0xC3 0xA9; zsh metafies 0xA9 as 0x83 0x89
+        let bytes = [b'e', b'c', b'h', b'o', b' ', 0xC3, 0x83, 0x89];
+        assert_eq!(unmetafy(&bytes), "echo é");
+    }
+
+    #[test]
+    fn bash_timestamps_apply_to_next_line() {
+        let parsed = parse_bash("#1700000000\nmake\nls\n");
+        assert_eq!(parsed[0], ("make".to_string(), Some(1700000000)));
+        assert_eq!(parsed[1], ("ls".to_string(), None));
+    }
+
+    #[test]
+    fn fish_entries_with_escapes() {
+        let text = "- cmd: echo \\\\n\n  when: 1700000000\n- cmd: printf a\\nb\n  when: 1700000001\n  paths:\n    - a\n";
+        let parsed = parse_fish(text);
+        assert_eq!(parsed[0], ("echo \\n".to_string(), Some(1700000000)));
+        assert_eq!(parsed[1], ("printf a\nb".to_string(), Some(1700000001)));
+    }
+
+    #[test]
+    fn untimed_entries_keep_their_order() {
+        let parsed = vec![("ls".to_string(), None), ("pwd".to_string(), None)];
+        let read = into_entries(parsed, HistorySource::Bash, 1000);
+        assert!(read.dated.is_empty());
+        assert_eq!(read.undated[0].timestamp, 998);
+        assert_eq!(read.undated[1].timestamp, 999);
+        assert_eq!(read.undated[1].source, "bash-history");
+    }
+
+    #[test]
+    fn importing_a_file_again_adds_nothing() {
+        let mut history = crate::history::CommandHistory::new(100);
+        let text = "#1700000000\nmake\nls\ncd /tmp\nls\n";
+        let import = |history: &mut crate::history::CommandHistory, mtime| {
+            let read = into_entries(parse_bash(text), HistorySource::Bash, mtime);
+            history.import(read.dated, false).unwrap() + history.import(read.undated, true).unwrap()
+        };
+        assert_eq!(import(&mut history, 1_800_000_000), 3);
+        // The file was touched since, so the made-up times moved
+        assert_eq!(import(&mut history, 1_800_000_500), 0);
+        let latest = history.list(None, 1);
+        assert_eq!(latest[0].command, "ls");
+    }
+}
@@ -0,0 +1,166 @@
+//! Per-provider response latency, for `[routing] adaptive = true`.
+//!
+//! Providers answer in one piece, so the time to the first byte of a
+//! completion is the time to the whole answer. Each provider's latency and
+//! success rate are exponentially decayed averages: recent requests count
+//! most, so a provider that slows down (or recovers) is noticed within a
+//! few requests. Raced requests aren't sampled, since the loser's time is
+//! never known.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+/// Weight of the newest sample in each average.
+const DECAY: f64 = 0.3;
+
+/// Requests a provider must have answered before it can be preferred.
+const MIN_SAMPLES: u32 = 3;
+
+/// Success rate below which a provider isn't preferred however fast it is.
+const MIN_SUCCESS_RATE: f64 = 0.8;
+
+#[derive(Debug, Clone, Copy)]
+struct Estimate {
+    latency_ms: f64,
+    success_rate: f64,
+    samples: u32,
+}
+
+/// Latency estimates by provider name.
+#[derive(Debug, Default)]
+pub struct ProviderLatency {
+    estimates: HashMap<String, Estimate>,
+}
+
+impl ProviderLatency {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fold in one request. Only successful answers update the latency; a
+    /// failure that returns quickly says nothing about how fast answers are.
+    pub fn record(&mut self, provider: &str, elapsed: Duration, ok: bool) {
+        let ms = elapsed.as_secs_f64() * 1000.0;
+        let success = if ok { 1.0 } else { 0.0 };
+        match self.estimates.get_mut(provider) {
+            Some(estimate) => {
+                estimate.success_rate += DECAY * (success - estimate.success_rate);
+                if ok {
+                    estimate.latency_ms = if estimate.samples == 0 {
+                        ms
+                    } else {
+                        estimate.latency_ms + DECAY * (ms - estimate.latency_ms)
+                    };
+                    estimate.samples += 1;
+                }
+            }
+            None => {
+                self.estimates.insert(
+                    provider.to_string(),
+                    Estimate {
+                        latency_ms: if ok { ms } else { 0.0 },
+                        success_rate: success,
+                        samples: u32::from(ok),
+                    },
+                );
+            }
+        }
+    }
+
+    /// Move the fastest adequate provider in `chain` to the front, keeping
+    /// the others in order. Adequate means enough answered requests and a
+    /// good success rate; with none, `chain` is left alone.
+    pub fn prefer_fastest<T>(&self, chain: &mut Vec<T>, name: impl Fn(&T) -> &str) {
+        let fastest = chain
+            .iter()
+            .enumerate()
+            .filter_map(|(i, provider)| {
+                let estimate = self.estimates.get(name(provider))?;
+                (estimate.samples >= MIN_SAMPLES && estimate.success_rate >= MIN_SUCCESS_RATE)
+                    .then_some((i, estimate.latency_ms))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i);
+        if let Some(i) = fastest.filter(|&i| i > 0) {
+            let provider = chain.remove(i);
+            chain.insert(0, provider);
+        }
+    }
+
+    /// Current latency estimates in milliseconds, for `status`.
+    pub fn snapshot(&self) -> BTreeMap<String, u64> {
+        self.estimates
+            .iter()
+            .filter(|(_, e)| e.samples > 0)
+            .map(|(name, e)| (name.clone(), e.latency_ms.round() as u64))
+            .collect()
+    }
+}
+
+/// Run `work`, returning its output and how long it took.
+pub async fn timed<T>(work: impl Future<Output = T>) -> (T, Duration) {
+    let start = Instant::now();
+    let output = work.await;
+    (output, start.elapsed())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record_n(latency: &mut ProviderLatency, provider: &str, ms: u64, n: usize) {
+        for _ in 0..n {
+            latency.record(provider, Duration::from_millis(ms), true);
+        }
+    }
+
+    fn prefer(latency: &ProviderLatency, chain: &[&'static str]) -> Vec<&'static str> {
+        let mut chain = chain.to_vec();
+        latency.prefer_fastest(&mut chain, |name| name);
+        chain
+    }
+
+    #[test]
+    fn estimates_decay_towards_recent_samples() {
+        let mut latency = ProviderLatency::new();
+        latency.record("anthropic", Duration::from_millis(400), true);
+        assert_eq!(latency.snapshot()["anthropic"], 400);
+        latency.record("anthropic", Duration::from_millis(1400), true);
+        assert_eq!(latency.snapshot()["anthropic"], 700);
+        // Failures leave the latency alone
+        latency.record("anthropic", Duration::from_millis(5), false);
+        assert_eq!(latency.snapshot()["anthropic"], 700);
+    }
+
+    #[test]
+    fn fastest_adequate_provider_goes_first() {
+        let mut latency = ProviderLatency::new();
+        let chain = ["anthropic", "openai", "ollama"];
+        // Not enough samples yet
+        record_n(&mut latency, "ollama", 100, 2);
+        assert_eq!(prefer(&latency, &chain), chain);
+
+        record_n(&mut latency, "anthropic", 600, 3);
+        record_n(&mut latency, "openai", 300, 3);
+        assert_eq!(prefer(&latency, &chain), ["openai", "anthropic", "ollama"]);
+
+        record_n(&mut latency, "ollama", 100, 1);
+        assert_eq!(prefer(&latency, &chain), ["ollama", "anthropic", "openai"]);
+    }
+
+    #[test]
+    fn failing_providers_are_not_preferred() {
+        let mut latency = ProviderLatency::new();
+        record_n(&mut latency, "anthropic", 600, 3);
+        record_n(&mut latency, "openai", 100, 3);
+        for _ in 0..3 {
+            latency.record("openai", Duration::from_millis(100), false);
+        }
+        assert_eq!(
+            prefer(&latency, &["anthropic", "openai"]),
+            ["anthropic", "openai"]
+        );
+    }
+}
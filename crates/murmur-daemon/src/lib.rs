@@ -1,8 +1,53 @@
 //! Murmur Daemon — Core server that handles completion requests via Unix socket.
 
+pub mod abbr;
+pub mod ask;
+pub mod assets;
+pub mod auth;
+pub mod branch;
+pub mod budget;
 pub mod cache;
+pub mod cancel;
+pub mod clock;
+pub mod commit;
+pub mod compression;
 pub mod config;
+pub mod cron;
+pub mod debug;
+pub mod diagnose;
+pub mod dirwatch;
+pub mod draft;
+pub mod events;
+pub mod expr;
+pub mod fingerprint;
+pub mod frame;
 pub mod handler;
+pub mod health;
+pub mod hedge;
 pub mod history;
+pub mod hooks;
+pub mod import;
+pub mod latency;
+pub mod localtime;
+pub mod merge;
+pub mod notify;
+pub mod pattern;
+pub mod policy;
 pub mod prefetch;
+pub mod proactive;
+pub mod probe;
+pub mod query;
+pub mod ranking;
+pub mod repl;
+pub mod safety;
+pub mod sequence;
 pub mod server;
+pub mod session;
+pub mod settle;
+pub mod shadow;
+pub mod store;
+pub mod summary;
+pub mod template;
+pub mod upload;
+pub mod verify;
+pub mod websocket;
@@ -0,0 +1,143 @@
+//! Minimal local wall-clock conversions for unix timestamps.
+//!
+//! Prompts and CLI output talk about "friday afternoon", so timestamps are
+//! shown and parsed in the machine's local time. The UTC offset comes from
+//! `localtime_r`; dates use the proleptic Gregorian calendar.
+
+const WEEKDAYS: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+
+/// Local UTC offset in seconds at `timestamp`.
+pub fn utc_offset(timestamp: u64) -> i64 {
+    let time = timestamp as libc::time_t;
+    // SAFETY: localtime_r only writes to the tm we pass in
+    unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&time, &mut tm).is_null() {
+            0
+        } else {
+            tm.tm_gmtoff as i64
+        }
+    }
+}
+
+/// `timestamp` as local "YYYY-MM-DD HH:MM".
+pub fn format_local(timestamp: u64) -> String {
+    format_with_offset(timestamp, utc_offset(timestamp))
+}
+
+/// `timestamp` as local "YYYY-MM-DD HH:MM (Weekday)".
+pub fn format_local_with_weekday(timestamp: u64) -> String {
+    let offset = utc_offset(timestamp);
+    let local = timestamp as i64 + offset;
+    let weekday = WEEKDAYS[(local.div_euclid(86_400) + 4).rem_euclid(7) as usize];
+    format!("{} ({weekday})", format_with_offset(timestamp, offset))
+}
+
+/// Parse a local "YYYY-MM-DD", "YYYY-MM-DD HH:MM[:SS]" (or with `T`) into a
+/// unix timestamp. A bare integer is taken as a unix timestamp already.
+pub fn parse_local(text: &str) -> Option<u64> {
+    let text = text.trim();
+    if let Ok(timestamp) = text.parse::<u64>() {
+        return Some(timestamp);
+    }
+    let local = parse_civil(text)?;
+    // Use the offset in effect near that time, so DST is mostly right
+    let utc = local - utc_offset(local.max(0) as u64);
+    u64::try_from(utc).ok()
+}
+
+fn format_with_offset(timestamp: u64, offset: i64) -> String {
+    let local = timestamp as i64 + offset;
+    let (year, month, day) = civil_from_days(local.div_euclid(86_400));
+    let secs = local.rem_euclid(86_400);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}",
+        secs / 3600,
+        secs % 3600 / 60
+    )
+}
+
+/// Seconds since the epoch for a local wall-clock time, ignoring the offset.
+fn parse_civil(text: &str) -> Option<i64> {
+    let (date, time) = match text.split_once([' ', 'T']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (text, None),
+    };
+    let mut parts = date.split('-').map(|p| p.parse::<i64>().ok());
+    let (year, month, day) = (parts.next()??, parts.next()??, parts.next()??);
+    if parts.next().is_some() || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let secs = match time {
+        Some(time) => {
+            let mut parts = time.split(':').map(|p| p.parse::<i64>().ok());
+            let hour = parts.next()??;
+            let minute = parts.next().flatten().unwrap_or(0);
+            let second = parts.next().flatten().unwrap_or(0);
+            if !(0..=24).contains(&hour) || !(0..60).contains(&minute) {
+                return None;
+            }
+            hour * 3600 + minute * 60 + second
+        }
+        None => 0,
+    };
+    Some(days_from_civil(year, month, day) * 86_400 + secs)
+}
+
+// Howard Hinnant's days <-> civil date algorithms
+
+/// Days since the epoch for a (year, month, day) date.
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// The (year, month, day) date `days` after the epoch.
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn civil_dates_roundtrip() {
+        // 2024-02-29 12:30 UTC
+        assert_eq!(parse_civil("2024-02-29 12:30"), Some(1_709_209_800));
+        assert_eq!(format_with_offset(1_709_209_800, 0), "2024-02-29 12:30");
+        assert_eq!(format_with_offset(1_709_209_800, 3600), "2024-02-29 13:30");
+        assert_eq!(parse_civil("1970-01-01"), Some(0));
+        assert_eq!(parse_civil("2024-13-01"), None);
+    }
+
+    #[test]
+    fn local_parse_inverts_format() {
+        let timestamp = 1_760_000_000;
+        let shown = format_local(timestamp);
+        assert_eq!(parse_local(&shown), Some(timestamp - timestamp % 60));
+        assert_eq!(parse_local("1760000000"), Some(timestamp));
+    }
+}
@@ -0,0 +1,91 @@
+//! Combining completions from several providers for
+//! `[routing] strategy = "merge"`.
+//!
+//! A local model often has the exact history-based answer a cloud model
+//! misses, and the other way round. Merged lists are deduplicated by
+//! normalized text, keeping the best-scored copy, and sorted by score.
+
+use murmur_protocol::CompletionItem;
+use std::collections::HashMap;
+
+/// Merge `lists`, each already tagged with the provider that produced it.
+/// Duplicates keep the highest score and the first provider's metadata on
+/// ties; the result is ordered by score, highest first.
+pub fn merge(lists: Vec<Vec<CompletionItem>>) -> Vec<CompletionItem> {
+    let mut merged: Vec<CompletionItem> = Vec::new();
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for item in lists.into_iter().flatten() {
+        let key = normalize(&item.text);
+        if key.is_empty() {
+            continue;
+        }
+        match seen.get(&key) {
+            Some(&i) => {
+                if item.score > merged[i].score {
+                    merged[i] = item;
+                }
+            }
+            None => {
+                seen.insert(key, merged.len());
+                merged.push(item);
+            }
+        }
+    }
+    merged.sort_by(|a, b| b.score.total_cmp(&a.score));
+    merged
+}
+
+/// Text compared for duplicates: trimmed, with runs of whitespace collapsed.
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use murmur_protocol::{CompletionKind, CompletionMetadata};
+
+    fn item(text: &str, score: f64, provider: &str) -> CompletionItem {
+        CompletionItem {
+            text: text.to_string(),
+            description: None,
+            kind: CompletionKind::FullCommand,
+            score,
+            metadata: CompletionMetadata {
+                provider: Some(provider.to_string()),
+                ..CompletionMetadata::default()
+            },
+        }
+    }
+
+    #[test]
+    fn duplicates_keep_the_best_score() {
+        let local = vec![
+            item("git push origin main", 0.9, "ollama"),
+            item("git pull", 0.5, "ollama"),
+        ];
+        let cloud = vec![
+            item("git  push origin main ", 0.7, "anthropic"),
+            item("git pull --rebase", 0.8, "anthropic"),
+            item("git pull", 0.6, "anthropic"),
+        ];
+        let merged = merge(vec![local, cloud]);
+        let summary: Vec<(&str, &str)> = merged
+            .iter()
+            .map(|i| (i.text.as_str(), i.metadata.provider.as_deref().unwrap()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("git push origin main", "ollama"),
+                ("git pull --rebase", "anthropic"),
+                ("git pull", "anthropic"),
+            ]
+        );
+    }
+
+    #[test]
+    fn blank_suggestions_are_dropped() {
+        assert!(merge(vec![vec![item("  ", 1.0, "ollama")], vec![]]).is_empty());
+    }
+}
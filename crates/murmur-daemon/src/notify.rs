@@ -0,0 +1,199 @@
+//! Desktop notifications for slow background results.
+//!
+//! Uses the platform's own tooling instead of a native binding: `osascript`
+//! on macOS and `notify-send` elsewhere. On Linux the notification carries a
+//! "Copy" action; on macOS the result is copied to the clipboard up front
+//! when `copy_to_clipboard` is set, since plain AppleScript notifications
+//! can't have buttons.
+
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tracing::{debug, warn};
+
+use crate::config::NotificationsConfig;
+
+/// Longest result text shown in a notification body.
+const MAX_BODY_CHARS: usize = 200;
+
+/// What produced the result being announced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotifyKind {
+    Refinement,
+    Voice,
+}
+
+/// Sends desktop notifications according to `[notifications]`.
+#[derive(Debug, Clone)]
+pub struct Notifier {
+    enabled: bool,
+    on_refinement: bool,
+    on_voice: bool,
+    min_elapsed: Duration,
+    copy_to_clipboard: bool,
+}
+
+impl Notifier {
+    pub fn new(config: &NotificationsConfig) -> Self {
+        Self {
+            enabled: config.enabled,
+            on_refinement: config.on_refinement,
+            on_voice: config.on_voice,
+            min_elapsed: Duration::from_millis(config.min_elapsed_ms),
+            copy_to_clipboard: config.copy_to_clipboard,
+        }
+    }
+
+    /// Whether a result of `kind` that took `elapsed` should be announced.
+    /// Fast results are skipped: the user is still looking at the prompt.
+    pub fn should_notify(&self, kind: NotifyKind, elapsed: Duration) -> bool {
+        let kind_enabled = match kind {
+            NotifyKind::Refinement => self.on_refinement,
+            NotifyKind::Voice => self.on_voice,
+        };
+        self.enabled && kind_enabled && elapsed >= self.min_elapsed
+    }
+
+    /// Announce `result` in the background if the config allows it.
+    pub fn notify(&self, kind: NotifyKind, elapsed: Duration, title: &str, result: &str) {
+        if !self.should_notify(kind, elapsed) || result.trim().is_empty() {
+            return;
+        }
+        let title = title.to_string();
+        let result = result.to_string();
+        let copy = self.copy_to_clipboard;
+        tokio::spawn(async move {
+            if let Err(e) = send(&title, &result, copy).await {
+                warn!(error = %e, "Desktop notification failed");
+            }
+        });
+    }
+}
+
+async fn send(title: &str, result: &str, copy: bool) -> std::io::Result<()> {
+    let body = truncate(result, MAX_BODY_CHARS);
+
+    if cfg!(target_os = "macos") {
+        if copy {
+            copy_to_clipboard(result).await?;
+        }
+        let body = if copy {
+            format!("{body} (copied)")
+        } else {
+            body
+        };
+        Command::new("osascript")
+            .arg("-e")
+            .arg(applescript_notification(title, &body))
+            .status()
+            .await?;
+        return Ok(());
+    }
+
+    let mut cmd = Command::new("notify-send");
+    cmd.args(["--app-name=murmur", title, &body]);
+    if copy {
+        // Blocks until the notification is dismissed; prints the action key
+        cmd.args(["--action=copy=Copy", "--wait"]);
+    }
+    let output = cmd.stderr(Stdio::null()).output().await?;
+    if copy && String::from_utf8_lossy(&output.stdout).trim() == "copy" {
+        copy_to_clipboard(result).await?;
+        debug!("Notification result copied to clipboard");
+    }
+    Ok(())
+}
+
+/// Pipe `text` into the first available clipboard tool.
+async fn copy_to_clipboard(text: &str) -> std::io::Result<()> {
+    let candidates: &[(&str, &[&str])] = if cfg!(target_os = "macos") {
+        &[("pbcopy", &[])]
+    } else {
+        &[
+            ("wl-copy", &[]),
+            ("xclip", &["-selection", "clipboard"]),
+            ("xsel", &["--clipboard", "--input"]),
+        ]
+    };
+
+    let mut last_err = None;
+    for (program, args) in candidates {
+        match Command::new(program)
+            .args(*args)
+            .stdin(Stdio::piped())
+            .spawn()
+        {
+            Ok(mut child) => {
+                if let Some(mut stdin) = child.stdin.take() {
+                    stdin.write_all(text.as_bytes()).await?;
+                }
+                child.wait().await?;
+                return Ok(());
+            }
+            Err(e) => last_err = Some(e),
+        }
+    }
+    Err(last_err.unwrap_or_else(|| std::io::Error::other("no clipboard tool found")))
+}
+
+/// `display notification` script with both strings safely quoted.
+fn applescript_notification(title: &str, body: &str) -> String {
+    format!(
+        "display notification {} with title {}",
+        applescript_string(body),
+        applescript_string(title)
+    )
+}
+
+fn applescript_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    let text = text.trim();
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let cut: String = text.chars().take(max_chars.saturating_sub(1)).collect();
+    format!("{cut}…")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notifier() -> Notifier {
+        Notifier::new(&NotificationsConfig {
+            enabled: true,
+            on_refinement: true,
+            on_voice: false,
+            min_elapsed_ms: 2000,
+            copy_to_clipboard: false,
+        })
+    }
+
+    #[test]
+    fn only_slow_enabled_results_notify() {
+        let n = notifier();
+        assert!(n.should_notify(NotifyKind::Refinement, Duration::from_secs(3)));
+        assert!(!n.should_notify(NotifyKind::Refinement, Duration::from_millis(500)));
+        assert!(!n.should_notify(NotifyKind::Voice, Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn applescript_quotes_are_escaped() {
+        let script = applescript_notification("murmur", r#"echo "hi" \ there"#);
+        assert_eq!(
+            script,
+            r#"display notification "echo \"hi\" \\ there" with title "murmur""#
+        );
+    }
+
+    #[test]
+    fn long_bodies_are_truncated() {
+        let body = truncate(&"x".repeat(300), 10);
+        assert_eq!(body.chars().count(), 10);
+        assert!(body.ends_with('…'));
+    }
+}
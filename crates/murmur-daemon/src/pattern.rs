@@ -0,0 +1,207 @@
+//! Regular expressions from a description (`murmur regex`).
+//!
+//! A provider writes the expression from the description and the start of
+//! any sample lines. It is compiled here with the `regex` crate, so only an
+//! expression that works is returned, and run over the whole sample for
+//! match counts and previews. One that matches none of the sample is used
+//! only if no provider writes one that matches something.
+
+use crate::assets;
+use crate::budget::{self, Admit};
+use crate::debug::scrub;
+use crate::expr::bound_sample;
+use murmur_protocol::{RegexPreview, RegexSuggestResponse};
+use murmur_providers::Provider;
+use regex::{Regex, RegexBuilder};
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+/// Matching lines previewed in a response.
+pub const MAX_PREVIEWS: usize = 10;
+
+/// Compiled size limit, well above any expression written for a one-line
+/// description.
+const SIZE_LIMIT: usize = 1 << 20;
+
+/// System prompt for writing a regular expression.
+pub const REGEX_SYSTEM_PROMPT: &str = "You write regular expressions for Rust's regex \
+     crate, whose syntax is like RE2: no lookahead, lookbehind or backreferences. \
+     Respond ONLY with the expression on one line, without slashes, quotes, flags, \
+     markdown fences or commentary. Use (?i) for case-insensitive matching and \
+     named groups (?P<name>...) where parts are worth capturing.";
+
+/// User prompt for `description` and an optional sample.
+pub fn build_prompt(description: &str, sample: Option<&str>) -> String {
+    let mut prompt = format!("Match: {}\n", description.trim());
+    if let Some(sample) = sample.filter(|sample| !sample.trim().is_empty()) {
+        let (bounded, cut) = bound_sample(sample);
+        prompt.push_str("\nSample lines:\n");
+        prompt.push_str(&scrub(&bounded));
+        if cut {
+            prompt.push_str("... (more lines follow)\n");
+        }
+    }
+    prompt
+}
+
+/// The expression in a model's reply, without fences, backticks or
+/// slashes around it.
+pub fn parse_pattern(text: &str) -> Option<String> {
+    let line = text
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with("```"))?;
+    let pattern = line.trim_matches('`');
+    let pattern = match pattern.strip_prefix('/').and_then(|p| p.strip_suffix('/')) {
+        Some(inner) if !inner.is_empty() => inner,
+        _ => pattern,
+    };
+    (!pattern.is_empty()).then(|| pattern.to_string())
+}
+
+/// `pattern` compiled, or why it doesn't.
+pub fn compile(pattern: &str) -> Result<Regex, String> {
+    RegexBuilder::new(pattern)
+        .size_limit(SIZE_LIMIT)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+/// How many of `sample`'s lines `regex` matches, and previews of the first
+/// `MAX_PREVIEWS` of them.
+pub fn preview(regex: &Regex, sample: &str) -> (usize, Vec<RegexPreview>) {
+    let mut matched = 0;
+    let mut previews = Vec::new();
+    for (i, line) in sample.lines().enumerate() {
+        if !regex.is_match(line) {
+            continue;
+        }
+        matched += 1;
+        if previews.len() < MAX_PREVIEWS {
+            previews.push(RegexPreview {
+                line_number: i + 1,
+                line: line.to_string(),
+                spans: regex
+                    .find_iter(line)
+                    .map(|m| [m.start(), m.end()])
+                    .collect(),
+            });
+        }
+    }
+    (matched, previews)
+}
+
+/// Ask each provider in turn for an expression that compiles and, given a
+/// sample, matches some of it.
+pub async fn run(
+    chain: &[Arc<dyn Provider>],
+    budget: &dyn Admit,
+    description: &str,
+    sample: Option<&str>,
+) -> Result<RegexSuggestResponse, String> {
+    let start = std::time::Instant::now();
+    let prompt = build_prompt(description, sample);
+    let sample = sample.unwrap_or("");
+    let mut fallback = None;
+    let mut invalid = None;
+    let system = assets::prompt("regex", REGEX_SYSTEM_PROMPT);
+    let mut attempted = false;
+    for provider in chain {
+        if !budget.admit(provider.as_ref()).await {
+            continue;
+        }
+        attempted = true;
+        let text = match provider.generate(&system, &prompt).await {
+            Ok(text) => text,
+            Err(e) => {
+                warn!(provider = provider.name(), error = %e, "Regex failed");
+                continue;
+            }
+        };
+        let Some(pattern) = parse_pattern(&text) else {
+            debug!(provider = provider.name(), "Provider wrote no regex");
+            continue;
+        };
+        let regex = match compile(&pattern) {
+            Ok(regex) => regex,
+            Err(e) => {
+                debug!(provider = provider.name(), %pattern, error = %e, "Regex doesn't compile");
+                invalid = Some(e);
+                continue;
+            }
+        };
+        let (matched_lines, previews) = preview(&regex, sample);
+        let response = RegexSuggestResponse {
+            pattern,
+            provider: provider.name().to_string(),
+            latency_ms: start.elapsed().as_millis() as u64,
+            matched_lines,
+            sample_lines: sample.lines().count(),
+            previews,
+        };
+        if matched_lines > 0 || sample.trim().is_empty() {
+            return Ok(response);
+        }
+        debug!(
+            provider = provider.name(),
+            "Regex matches none of the sample, trying next"
+        );
+        fallback.get_or_insert(response);
+    }
+    if let Some(response) = fallback {
+        return Ok(response);
+    }
+    if !attempted && !chain.is_empty() {
+        return Err(budget::EXHAUSTED.to_string());
+    }
+    match invalid {
+        Some(e) => Err(format!("No provider wrote a valid regex: {e}")),
+        None if chain.is_empty() => Err(
+            "No provider can write regexes; configure anthropic, openai, gemini, openrouter or ollama"
+                .to_string(),
+        ),
+        None => Err("All providers failed to write a regex".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns_are_cleaned_up() {
+        assert_eq!(
+            parse_pattern("```regex\n\\d{4}-\\d{2}-\\d{2}\n```").as_deref(),
+            Some(r"\d{4}-\d{2}-\d{2}")
+        );
+        assert_eq!(
+            parse_pattern("`/ERROR|WARN/`").as_deref(),
+            Some("ERROR|WARN")
+        );
+        assert_eq!(parse_pattern("/").as_deref(), Some("/"));
+        assert_eq!(parse_pattern("```\n```"), None);
+    }
+
+    #[test]
+    fn invalid_patterns_are_reported() {
+        assert!(compile(r"(?<=a)b").is_err());
+        assert!(compile(r"(unclosed").unwrap_err().contains("unclosed"));
+    }
+
+    #[test]
+    fn previews_show_where_lines_match() {
+        let regex = compile(r"\d{4}-\d{2}-\d{2}").unwrap();
+        let sample = "2024-05-01 boot\nno date here\nfrom 2024-05-02 to 2024-05-03\n";
+        let (matched, previews) = preview(&regex, sample);
+        assert_eq!(matched, 2);
+        assert_eq!(previews[0].line_number, 1);
+        assert_eq!(previews[0].spans, vec![[0, 10]]);
+        assert_eq!(previews[1].line_number, 3);
+        assert_eq!(previews[1].spans, vec![[5, 15], [19, 29]]);
+
+        let many = "x\n".repeat(MAX_PREVIEWS * 2);
+        let (matched, previews) = preview(&compile("x").unwrap(), &many);
+        assert_eq!(matched, MAX_PREVIEWS * 2);
+        assert_eq!(previews.len(), MAX_PREVIEWS);
+    }
+}
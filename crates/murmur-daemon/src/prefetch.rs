@@ -3,9 +3,155 @@
 //! After a user requests completions for "git c", we can predict that
 //! "git co", "git ch", "git cl" are likely next inputs and pre-fetch those
 //! completions in the background, making the next request instant.
+//!
+//! Predictions come from what the user actually runs: `PrefixModel` counts,
+//! over the cross-tool history, which word follows the one or two words
+//! before it at the start of a command, and completes the word being typed
+//! from that. Two words of context are preferred; otherwise the model backs
+//! off to one. Until the history says anything about an input, the builtin
+//! table of common commands answers.
 
+use murmur_context::lexer;
+use murmur_protocol::HistoryEntry;
 use std::collections::HashMap;
 
+/// Observations needed before a continuation is pre-fetched.
+const MIN_SUPPORT: u32 = 2;
+
+/// Leading words of each command learned from; later ones are mostly
+/// arguments too specific to be worth pre-fetching.
+const MAX_WORDS: usize = 3;
+
+/// Most continuations predicted for one input.
+const MAX_PREDICTIONS: usize = 4;
+
+/// Upper bound on distinct contexts tracked, to bound memory.
+const MAX_CONTEXTS: usize = 20_000;
+
+/// Next-word counts learned from command history.
+#[derive(Default)]
+pub struct PrefixModel {
+    /// By the one or two words before it; "" for a command's first word.
+    next: HashMap<String, HashMap<String, u32>>,
+}
+
+impl PrefixModel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Train on `entries`.
+    pub fn train(entries: &[HistoryEntry]) -> Self {
+        let mut model = Self::new();
+        for entry in entries {
+            model.observe(&entry.command);
+        }
+        model
+    }
+
+    /// Count the leading words of each command in `command`.
+    pub fn observe(&mut self, command: &str) {
+        for segment in lexer::commands(command) {
+            let words = segment.without_assignments();
+            let words = &words[..words.len().min(MAX_WORDS)];
+            for (i, word) in words.iter().enumerate() {
+                let contexts = match i {
+                    0 => vec![String::new()],
+                    1 => vec![words[0].clone()],
+                    _ => vec![words[i - 1].clone(), words[i - 2..i].join(" ")],
+                };
+                for context in contexts {
+                    self.count(context, word);
+                }
+            }
+        }
+    }
+
+    fn count(&mut self, context: String, word: &str) {
+        if self.next.len() >= MAX_CONTEXTS && !self.next.contains_key(&context) {
+            return;
+        }
+        *self
+            .next
+            .entry(context)
+            .or_default()
+            .entry(word.to_string())
+            .or_insert(0) += 1;
+    }
+
+    /// Likely longer inputs, most frequent first: the word being typed
+    /// completed, and after a whole word the one that usually follows it.
+    pub fn predict(&self, input: &str) -> Vec<String> {
+        let input = input.trim_start();
+        if input.trim().is_empty() {
+            return Vec::new();
+        }
+        let mut words: Vec<&str> = input.split_whitespace().collect();
+        let mut candidates = match input.ends_with(char::is_whitespace) {
+            true => self.continuations(&words, ""),
+            false => {
+                let partial = words.pop().unwrap_or_default();
+                let mut candidates = self.continuations(&words, partial);
+                // "git" is also "git " about to be followed by a subcommand
+                words.push(partial);
+                candidates.extend(self.continuations(&words, ""));
+                candidates
+            }
+        };
+        candidates.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let typed = input.trim_end();
+        let mut predictions: Vec<String> = Vec::new();
+        for (prediction, _) in candidates {
+            if prediction.len() > typed.len()
+                && prediction.starts_with(typed)
+                && !predictions.contains(&prediction)
+            {
+                predictions.push(prediction);
+            }
+        }
+        predictions.truncate(MAX_PREDICTIONS);
+        predictions
+    }
+
+    /// Words after `words` that start with `partial`, as whole inputs with
+    /// their counts, from the longest context that has any.
+    fn continuations(&self, words: &[&str], partial: &str) -> Vec<(String, u32)> {
+        if words.len() >= MAX_WORDS {
+            return Vec::new();
+        }
+        let contexts = match words.len() {
+            0 => vec![String::new()],
+            1 => vec![words[0].to_string()],
+            n => vec![words[n - 2..].join(" "), words[n - 1].to_string()],
+        };
+        let line = words.join(" ");
+        contexts
+            .iter()
+            .filter_map(|context| self.next.get(context))
+            .map(|next| {
+                next.iter()
+                    .filter(|(word, &n)| n >= MIN_SUPPORT && word.starts_with(partial))
+                    .map(|(word, &n)| match line.as_str() {
+                        "" => (word.clone(), n),
+                        line => (format!("{line} {word}"), n),
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .find(|found| !found.is_empty())
+            .unwrap_or_default()
+    }
+
+    /// Number of distinct contexts learned.
+    pub fn len(&self) -> usize {
+        self.next.len()
+    }
+
+    /// Whether nothing has been learned yet.
+    pub fn is_empty(&self) -> bool {
+        self.next.is_empty()
+    }
+}
+
 /// Common command prefixes and their likely continuations.
 /// Each entry maps a prefix pattern to a list of likely next characters/words.
 struct PrefetchRules {
@@ -114,8 +260,18 @@ impl PrefetchRules {
     }
 }
 
-/// Determine which inputs to pre-fetch based on the current input.
-/// Returns a list of predicted next inputs that should be pre-fetched.
+/// Inputs to pre-fetch after `input`: what `model` learned the user types
+/// next, or the builtin table's guesses when it knows nothing about it.
+pub fn predict(model: &PrefixModel, input: &str) -> Vec<String> {
+    match model.predict(input) {
+        learned if learned.is_empty() => predict_next_inputs(input),
+        learned => learned,
+    }
+}
+
+/// Determine which inputs to pre-fetch based on the current input, from
+/// the builtin table. Returns a list of predicted next inputs that should
+/// be pre-fetched.
 pub fn predict_next_inputs(input: &str) -> Vec<String> {
     let rules = PrefetchRules::new();
     let input_trimmed = input.trim();
@@ -185,4 +341,34 @@ mod tests {
         let predictions = predict_next_inputs("npm r");
         assert!(predictions.contains(&"npm run".to_string()));
     }
+
+    fn trained(commands: &[&str]) -> PrefixModel {
+        let mut model = PrefixModel::new();
+        for command in commands {
+            model.observe(command);
+        }
+        model
+    }
+
+    #[test]
+    fn learns_what_the_user_runs() {
+        let model = trained(&[
+            "git commit -m one",
+            "git commit -m two",
+            "git cherry-pick abc",
+            "git cherry-pick def",
+            "git cherry-pick 012",
+            "jj log",
+            "jj log -r @",
+            "FOO=1 jj squash && jj squash",
+        ]);
+        assert_eq!(predict(&model, "git c"), ["git cherry-pick", "git commit"]);
+        assert_eq!(predict(&model, "git commit "), ["git commit -m"]);
+        assert_eq!(predict(&model, "jj"), ["jj log", "jj squash"]);
+        assert_eq!(predict(&model, "j"), ["jj"]);
+        // Seen once isn't enough; the builtin table takes over
+        assert_eq!(predict(&model, "git cl"), ["git clone"]);
+        assert!(predict(&model, "cargo t").contains(&"cargo test".to_string()));
+        assert!(predict(&model, "").is_empty());
+    }
 }
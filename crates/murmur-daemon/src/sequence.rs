@@ -0,0 +1,278 @@
+//! Command sequence mining for next-command prediction.
+//!
+//! Workflows repeat: `git add -A` is followed by `git commit`, `cargo build`
+//! by `cargo test`. A Markov model over the cross-tool history counts which
+//! command follows which, within a session, and predicts the likely next
+//! command from the last one or two. Second-order predictions (the last two
+//! commands) are preferred when they have enough support; otherwise the
+//! model backs off to the last command alone.
+
+use murmur_protocol::HistoryEntry;
+use std::collections::HashMap;
+
+/// Observations needed before a transition is suggested.
+const MIN_SUPPORT: u32 = 2;
+
+/// Commands further apart than this belong to different workflows.
+const SESSION_GAP_SECS: u64 = 30 * 60;
+
+/// Upper bound on distinct contexts tracked per order, to bound memory.
+const MAX_STATES: usize = 20_000;
+
+/// A predicted next command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prediction {
+    pub command: String,
+    /// Share of observed transitions from this context that led here.
+    pub probability: f64,
+    /// How often the transition was observed.
+    pub support: u32,
+}
+
+/// Last commands seen in one session, for extending the chain.
+struct SessionTail {
+    previous: Option<String>,
+    last: String,
+    timestamp: u64,
+}
+
+#[derive(Default)]
+pub struct SequenceModel {
+    first: HashMap<String, HashMap<String, u32>>,
+    second: HashMap<(String, String), HashMap<String, u32>>,
+    tails: HashMap<String, SessionTail>,
+}
+
+impl SequenceModel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Train on `entries`, oldest first.
+    pub fn train(entries: &[HistoryEntry]) -> Self {
+        let mut model = Self::new();
+        for entry in entries {
+            model.observe(entry);
+        }
+        model
+    }
+
+    /// Add one executed command, extending its session's chain.
+    pub fn observe(&mut self, entry: &HistoryEntry) {
+        let command = normalize(&entry.command);
+        if command.is_empty() {
+            return;
+        }
+        let key = session_key(entry);
+        let tail = match self.tails.remove(&key) {
+            Some(tail) if entry.timestamp.saturating_sub(tail.timestamp) <= SESSION_GAP_SECS => {
+                tail
+            }
+            _ => {
+                self.tails.insert(
+                    key,
+                    SessionTail {
+                        previous: None,
+                        last: command,
+                        timestamp: entry.timestamp,
+                    },
+                );
+                return;
+            }
+        };
+
+        // Re-running the same command isn't a workflow step
+        if tail.last == command {
+            self.tails.insert(
+                key,
+                SessionTail {
+                    timestamp: entry.timestamp,
+                    ..tail
+                },
+            );
+            return;
+        }
+
+        count(&mut self.first, tail.last.clone(), &command);
+        if let Some(previous) = tail.previous {
+            count(&mut self.second, (previous, tail.last.clone()), &command);
+        }
+        self.tails.insert(
+            key,
+            SessionTail {
+                previous: Some(tail.last),
+                last: command,
+                timestamp: entry.timestamp,
+            },
+        );
+    }
+
+    /// Likely next commands after `recent` (oldest first), best first.
+    pub fn predict(&self, recent: &[&str], limit: usize) -> Vec<Prediction> {
+        let recent: Vec<String> = recent
+            .iter()
+            .map(|c| normalize(c))
+            .filter(|c| !c.is_empty())
+            .collect();
+        let Some(last) = recent.last() else {
+            return Vec::new();
+        };
+
+        let mut predictions = Vec::new();
+        if let [.., previous, last] = recent.as_slice() {
+            if let Some(next) = self.second.get(&(previous.clone(), last.clone())) {
+                predictions = ranked(next);
+            }
+        }
+        if let Some(next) = self.first.get(last) {
+            for prediction in ranked(next) {
+                if !predictions.iter().any(|p| p.command == prediction.command) {
+                    predictions.push(prediction);
+                }
+            }
+        }
+        predictions.truncate(limit);
+        predictions
+    }
+
+    /// Number of distinct commands with observed successors.
+    pub fn len(&self) -> usize {
+        self.first.len()
+    }
+
+    /// Whether nothing has been learned yet.
+    pub fn is_empty(&self) -> bool {
+        self.first.is_empty()
+    }
+}
+
+fn count<K: std::hash::Hash + Eq>(
+    table: &mut HashMap<K, HashMap<String, u32>>,
+    context: K,
+    next: &str,
+) {
+    if table.len() >= MAX_STATES && !table.contains_key(&context) {
+        return;
+    }
+    *table
+        .entry(context)
+        .or_default()
+        .entry(next.to_string())
+        .or_insert(0) += 1;
+}
+
+/// Transitions with enough support, most frequent first.
+fn ranked(next: &HashMap<String, u32>) -> Vec<Prediction> {
+    let total: u32 = next.values().sum();
+    let mut predictions: Vec<Prediction> = next
+        .iter()
+        .filter(|(_, &n)| n >= MIN_SUPPORT)
+        .map(|(command, &n)| Prediction {
+            command: command.clone(),
+            probability: f64::from(n) / f64::from(total),
+            support: n,
+        })
+        .collect();
+    predictions.sort_by(|a, b| {
+        b.support
+            .cmp(&a.support)
+            .then_with(|| a.command.cmp(&b.command))
+    });
+    predictions
+}
+
+/// Entries in the same session (or, without one, the same source and host)
+/// form one chain.
+fn session_key(entry: &HistoryEntry) -> String {
+    match entry.session_id {
+        Some(ref id) => format!("session:{id}"),
+        None => format!(
+            "{}@{}",
+            entry.source,
+            entry.host.as_deref().unwrap_or_default()
+        ),
+    }
+}
+
+fn normalize(command: &str) -> String {
+    command.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(commands: &[&str]) -> Vec<HistoryEntry> {
+        commands
+            .iter()
+            .enumerate()
+            .map(|(i, command)| HistoryEntry {
+                command: command.to_string(),
+                cwd: "/repo".to_string(),
+                source: "terminal".to_string(),
+                exit_code: 0,
+                timestamp: 1_000 + i as u64 * 10,
+                session_id: None,
+                host: None,
+                suggested_by: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn predicts_frequent_successor() {
+        let model = SequenceModel::train(&entries(&[
+            "git add -A",
+            "git commit",
+            "git push",
+            "git add -A",
+            "git  commit",
+            "git push",
+            "git add -A",
+            "git status",
+        ]));
+        let next = model.predict(&["git add -A"], 3);
+        assert_eq!(next[0].command, "git commit");
+        assert_eq!(next[0].support, 2);
+        // Seen once only: not enough support
+        assert!(!next.iter().any(|p| p.command == "git status"));
+        assert_eq!(model.predict(&["git commit"], 3)[0].command, "git push");
+    }
+
+    #[test]
+    fn second_order_context_takes_precedence() {
+        let model = SequenceModel::train(&entries(&[
+            "cargo build",
+            "make",
+            "cargo test",
+            "cargo build",
+            "make",
+            "cargo test",
+            "npm ci",
+            "make",
+            "npm test",
+            "npm ci",
+            "make",
+            "npm test",
+            "npm ci",
+            "make",
+            "npm test",
+        ]));
+        assert_eq!(model.predict(&["make"], 1)[0].command, "npm test");
+        let next = model.predict(&["cargo build", "make"], 2);
+        assert_eq!(next[0].command, "cargo test");
+        assert_eq!(next[1].command, "npm test");
+    }
+
+    #[test]
+    fn gaps_and_sessions_break_chains() {
+        let mut history = entries(&["ls", "pwd", "ls", "pwd"]);
+        history[1].timestamp += SESSION_GAP_SECS;
+        history[2].timestamp += SESSION_GAP_SECS;
+        history[3].session_id = Some("other".to_string());
+        let model = SequenceModel::train(&history);
+        // Only pwd -> ls is in one chain
+        assert_eq!(model.len(), 1);
+        assert!(!model.first.contains_key("ls"));
+    }
+}
@@ -1,36 +1,128 @@
-use anyhow::Result;
-use murmur_protocol::{CompletionRequest, JsonRpcRequest, RequestId};
+use anyhow::{Context, Result};
+use murmur_protocol::{
+    CompletionRequest, Compression, CompressionRequest, CompressionResponse,
+    EventsSubscribeRequest, InitializeRequest, InitializeResponse, JsonRpcRequest, JsonRpcResponse,
+    RequestId, Scope, SessionOpenResponse,
+};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::net::UnixListener;
-use tokio::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, UnixListener, UnixStream};
+use tokio::sync::{broadcast, Mutex};
 use tracing::{debug, error, info, warn};
 
-use crate::cache::CompletionCache;
-use crate::config::Config;
+use crate::auth::{self, Access};
+use crate::cache::{CacheTtl, CompletionCache};
+use crate::clock::{Clock, SystemClock};
+use crate::compression;
+use crate::config::{expand_home, ApiToken, Config};
+use crate::dirwatch::DirWatcher;
+use crate::frame;
 use crate::handler::RequestHandler;
 use crate::history::CommandHistory;
-use crate::prefetch;
+use crate::probe;
+use crate::store::Store;
+use crate::upload;
+use crate::websocket;
+
+/// In-memory history entries (the store on disk keeps everything).
+const HISTORY_ENTRIES: usize = 1000;
+
+/// Longest request line accepted. Voice requests carry base64 audio, so
+/// this is generous; it only stops a client from growing the read buffer
+/// without bound.
+const MAX_REQUEST_BYTES: usize = 32 * 1024 * 1024;
+
+/// How often the config file is checked for changes.
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Set when systemd passed the socket in; it owns the file then, so
+/// shutting down leaves it in place for the next activation.
+static SOCKET_ACTIVATED: AtomicBool = AtomicBool::new(false);
+
+/// How a connection reached the daemon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Transport {
+    Unix,
+    Tcp,
+    WebSocket,
+}
+
+impl Transport {
+    /// Whether binary frames (compression, raw audio) can follow the JSON
+    /// lines. A WebSocket carries messages, not a byte stream.
+    fn carries_frames(self) -> bool {
+        self != Self::WebSocket
+    }
+}
 
 /// The main daemon server.
 pub struct Server {
     config: Arc<Config>,
     handler: Arc<RequestHandler>,
+    /// Config file to reload `[ranking]` from when it changes, and
+    /// everything else from on SIGHUP or `config/reload`.
+    config_file: Option<PathBuf>,
 }
 
 impl Server {
     pub fn new(config: Config) -> Self {
+        Self::with_clock(config, Arc::new(SystemClock))
+    }
+
+    /// A server whose caches, history, budgets and timeouts all read the
+    /// time from `clock`.
+    pub fn with_clock(config: Config, clock: Arc<dyn Clock>) -> Self {
         let config = Arc::new(config);
-        let cache = Arc::new(Mutex::new(CompletionCache::new(config.daemon.cache_size)));
-        let history = Arc::new(Mutex::new(CommandHistory::new(1000)));
-        let handler = Arc::new(RequestHandler::new(config.clone(), cache, history));
+        let cache = CompletionCache::new(config.daemon.cache_size)
+            .with_clock(clock.clone())
+            .with_ttl(CacheTtl::from_config(&config.daemon))
+            .with_max_bytes(config.daemon.cache_max_bytes);
+        let history = open_history(&config, clock.as_ref()).with_clock(clock.clone());
+        let handler = Arc::new(RequestHandler::new(
+            config.clone(),
+            Arc::new(Mutex::new(cache)),
+            Arc::new(Mutex::new(history)),
+            clock,
+        ));
 
-        Self { config, handler }
+        Self {
+            config,
+            handler,
+            config_file: None,
+        }
+    }
+
+    /// Reload the `[ranking]` weights and abbreviations whenever `path`
+    /// changes, and the rest of it on SIGHUP or `config/reload`.
+    pub fn watch_config(mut self, path: PathBuf) -> Self {
+        self.handler.set_config_file(path.clone());
+        self.config_file = Some(path);
+        self
     }
 
     /// Run the daemon server, listening on Unix socket.
     pub async fn run(&self) -> Result<()> {
         let socket_path = &self.config.daemon.socket_path;
+        let daemon = &self.config.daemon;
+        if (daemon.listen_tcp.is_some() || daemon.listen_ws.is_some()) && daemon.tokens.is_empty() {
+            anyhow::bail!(
+                "daemon.listen_tcp and daemon.listen_ws need a [[daemon.tokens]] entry; create one with `murmur token create`"
+            );
+        }
+        private_dir(&Config::runtime_dir())?;
+        if let Some(parent) = std::path::Path::new(socket_path).parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+
+        if let Some(listener) = activated_listener()? {
+            SOCKET_ACTIVATED.store(true, Ordering::Relaxed);
+            info!(socket = %socket_path, "Murmur daemon listening on the socket from systemd");
+            return self.serve(listener).await;
+        }
 
         // Clean up stale socket file (only if no live daemon is listening)
         if std::path::Path::new(socket_path).exists() {
@@ -49,20 +141,113 @@ impl Server {
             }
         }
 
-        let listener = UnixListener::bind(socket_path)?;
+        let listener = bind_private(socket_path)
+            .with_context(|| format!("Failed to listen on {socket_path}"))?;
         info!(socket = %socket_path, "Murmur daemon listening");
+        self.serve(listener).await
+    }
+
+    /// Start the other listeners and background jobs, then accept
+    /// connections on `listener` until shutdown.
+    async fn serve(&self, listener: UnixListener) -> Result<()> {
+        if let Some(addr) = &self.config.daemon.health_listen {
+            let probes = tokio::net::TcpListener::bind(addr)
+                .await
+                .with_context(|| format!("Failed to listen for health probes on {addr}"))?;
+            info!(addr = %addr, "Serving health probes");
+            tokio::spawn(probe::serve(probes, self.handler.clone()));
+        }
+        for (addr, transport) in [
+            (&self.config.daemon.listen_tcp, Transport::Tcp),
+            (&self.config.daemon.listen_ws, Transport::WebSocket),
+        ] {
+            let Some(addr) = addr else {
+                continue;
+            };
+            let listener = TcpListener::bind(addr)
+                .await
+                .with_context(|| format!("Failed to listen on {addr}"))?;
+            let local = listener.local_addr()?;
+            info!(addr = %local, transport = ?transport, "Murmur daemon listening");
+            tokio::spawn(serve_tcp(listener, self.handler.clone(), transport));
+        }
 
         // Write PID file
         let pid = std::process::id();
         std::fs::write(Config::pid_path(), pid.to_string())?;
         info!(pid = pid, "PID file written");
 
+        if self.config.context.summaries {
+            let handler = self.handler.clone();
+            let interval = self.config.context.summary_interval_secs.max(1);
+            tokio::spawn(async move {
+                let mut ticker = tokio::time::interval(std::time::Duration::from_secs(interval));
+                ticker.tick().await; // first tick fires immediately
+                loop {
+                    ticker.tick().await;
+                    handler.summarize_sessions().await;
+                }
+            });
+            info!(interval_secs = interval, "Session summaries enabled");
+        }
+
+        if self.config.context.watch_directories {
+            let debounce = Duration::from_millis(self.config.context.watch_debounce_ms);
+            match DirWatcher::start(debounce) {
+                Ok((watcher, mut changes)) => {
+                    self.handler.set_dir_watcher(watcher);
+                    let handler = self.handler.clone();
+                    tokio::spawn(async move {
+                        while let Some(change) = changes.recv().await {
+                            handler.directory_changed(change).await;
+                        }
+                    });
+                }
+                Err(e) => warn!(error = %e, "Can't watch directories for changes"),
+            }
+        }
+
+        let handler = self.handler.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(upload::IDLE_TIMEOUT / 5);
+            loop {
+                ticker.tick().await;
+                handler.expire_uploads().await;
+            }
+        });
+
+        let health_interval = self.config.daemon.health_check_interval_secs;
+        if health_interval > 0 {
+            let handler = self.handler.clone();
+            tokio::spawn(async move {
+                let mut ticker =
+                    tokio::time::interval(std::time::Duration::from_secs(health_interval));
+                // The first tick fires immediately, so results are ready soon
+                // after startup
+                loop {
+                    ticker.tick().await;
+                    handler.check_providers().await;
+                }
+            });
+        }
+
+        if let Some(path) = self.config_file.clone() {
+            tokio::spawn(watch_config(path, self.handler.clone()));
+        }
+        tokio::spawn(reload_on_hangup(self.handler.clone()));
+
         loop {
             match listener.accept().await {
                 Ok((stream, _addr)) => {
+                    if !same_user(&stream) {
+                        continue;
+                    }
                     let handler = self.handler.clone();
                     tokio::spawn(async move {
-                        if let Err(e) = handle_connection(stream, handler).await {
+                        let (reader, writer) = stream.into_split();
+                        if let Err(e) =
+                            handle_connection(reader, writer, handler, Transport::Unix).await
+                        {
                             error!(error = %e, "Connection handler error");
                         }
                     });
@@ -75,24 +260,349 @@ impl Server {
     }
 }
 
-async fn handle_connection(
-    stream: tokio::net::UnixStream,
+/// Create `dir`, private to this user, if it is missing. One that exists
+/// but belongs to someone else is refused: under /tmp, anyone could have
+/// created it first. One of ours that others can read or enter is made
+/// private again.
+fn private_dir(dir: &std::path::Path) -> Result<()> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+
+    std::fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+        .with_context(|| format!("Failed to create {}", dir.display()))?;
+    // SAFETY: geteuid has no preconditions and can't fail
+    let uid = unsafe { libc::geteuid() };
+    let metadata = std::fs::metadata(dir)?;
+    if metadata.uid() != uid {
+        anyhow::bail!(
+            "{} belongs to another user; remove it or set XDG_RUNTIME_DIR",
+            dir.display()
+        );
+    }
+    let mode = metadata.mode() & 0o777;
+    if mode & 0o077 != 0 {
+        warn!(dir = %dir.display(), mode = format!("{mode:o}"), "Runtime dir is open to other users, making it private");
+        std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))
+            .with_context(|| format!("Failed to restrict {}", dir.display()))?;
+    }
+    Ok(())
+}
+
+/// The listening socket systemd passes to a service started by its socket
+/// unit (`murmur service install`), if this process was started that way.
+fn activated_listener() -> Result<Option<UnixListener>> {
+    use std::os::unix::io::FromRawFd;
+
+    let for_us = std::env::var("LISTEN_PID")
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok())
+        == Some(std::process::id());
+    let fds = std::env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|n| n.parse::<i32>().ok())
+        .unwrap_or(0);
+    if !for_us || fds < 1 {
+        return Ok(None);
+    }
+    // Not for plugins and hooks started later
+    for var in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        std::env::remove_var(var);
+    }
+    // SAFETY: with LISTEN_PID naming this process, systemd passed fd 3 as
+    // the socket unit's listening socket, and nothing else owns it
+    let listener = unsafe { std::os::unix::net::UnixListener::from_raw_fd(3) };
+    listener.set_nonblocking(true)?;
+    Ok(Some(UnixListener::from_std(listener)?))
+}
+
+/// Listen on `path` with the socket readable and writable only by this
+/// user. The socket is bound under a temporary name and renamed into place
+/// once restricted, so clients never see it with looser permissions; the
+/// umask is process-wide and not ours to change while other threads create
+/// files.
+fn bind_private(path: &str) -> std::io::Result<UnixListener> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let staging = format!("{path}.{}", std::process::id());
+    let _ = std::fs::remove_file(&staging);
+    let listener = UnixListener::bind(&staging)?;
+    let placed = std::fs::set_permissions(&staging, std::fs::Permissions::from_mode(0o600))
+        .and_then(|()| std::fs::rename(&staging, path));
+    if let Err(e) = placed {
+        let _ = std::fs::remove_file(&staging);
+        return Err(e);
+    }
+    Ok(listener)
+}
+
+/// Whether the process at the other end of `stream` runs as this user
+/// (SO_PEERCRED on Linux, getpeereid elsewhere). The socket's permissions
+/// already keep others out on Linux, but some systems ignore permissions
+/// on sockets, and they can be loosened by hand.
+fn same_user(stream: &UnixStream) -> bool {
+    // SAFETY: geteuid has no preconditions and can't fail
+    let uid = unsafe { libc::geteuid() };
+    match stream.peer_cred() {
+        Ok(peer) if peer.uid() == uid => true,
+        Ok(peer) => {
+            warn!(
+                peer_uid = peer.uid(),
+                pid = ?peer.pid(),
+                "Rejected connection from another user"
+            );
+            false
+        }
+        Err(e) => {
+            warn!(error = %e, "Rejected connection without peer credentials");
+            false
+        }
+    }
+}
+
+/// Poll `path` and hand changed ranking weights and abbreviations to
+/// `handler`. A config that fails to parse or validate is logged and the
+/// old settings kept.
+async fn watch_config(path: PathBuf, handler: Arc<RequestHandler>) {
+    let modified = |path: &PathBuf| -> Option<SystemTime> {
+        std::fs::metadata(path).and_then(|m| m.modified()).ok()
+    };
+    let mut last = modified(&path);
+    let mut ticker = tokio::time::interval(CONFIG_POLL_INTERVAL);
+    loop {
+        ticker.tick().await;
+        let current = modified(&path);
+        if current == last {
+            continue;
+        }
+        last = current;
+        match Config::load_from(&path) {
+            Ok(config) => {
+                info!(path = %path.display(), ranking = ?config.ranking, "Config changed, ranking weights and abbreviations reloaded");
+                handler.set_abbreviations(&config.abbreviations).await;
+                handler.set_ranking(config.ranking).await;
+            }
+            Err(e) => {
+                warn!(path = %path.display(), error = %e, "Config changed but is invalid, keeping current settings");
+            }
+        }
+    }
+}
+
+/// Handle connections on a `listen_tcp` or `listen_ws` listener until the
+/// daemon exits.
+async fn serve_tcp(listener: TcpListener, handler: Arc<RequestHandler>, transport: Transport) {
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                error!(error = %e, "Failed to accept connection");
+                continue;
+            }
+        };
+        let handler = handler.clone();
+        tokio::spawn(async move {
+            debug!(peer = %addr, transport = ?transport, "Connection accepted");
+            let _ = stream.set_nodelay(true);
+            let result = match transport {
+                Transport::WebSocket => {
+                    let origins = handler.config().daemon.ws_origins.clone();
+                    match websocket::accept(stream, &origins, MAX_REQUEST_BYTES).await {
+                        Ok(stream) => {
+                            let (reader, writer) = tokio::io::split(stream);
+                            handle_connection(reader, writer, handler, transport).await
+                        }
+                        Err(e) => {
+                            debug!(peer = %addr, error = %e, "WebSocket handshake failed");
+                            return;
+                        }
+                    }
+                }
+                _ => {
+                    let (reader, writer) = stream.into_split();
+                    handle_connection(reader, writer, handler, transport).await
+                }
+            };
+            if let Err(e) = result {
+                error!(error = %e, "Connection handler error");
+            }
+        });
+    }
+}
+
+/// Reload the config on each SIGHUP, as `config/reload` does.
+async fn reload_on_hangup(handler: Arc<RequestHandler>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            warn!(error = %e, "Failed to listen for SIGHUP, config reloads need config/reload");
+            return;
+        }
+    };
+    while hangups.recv().await.is_some() {
+        info!("SIGHUP received, reloading config");
+        if let Err(e) = handler.reload_config().await {
+            warn!(error = %e, "Config reload failed, keeping current settings");
+        }
+    }
+}
+
+/// Read one message: a line, or a frame once compression is on. `None`
+/// when the client is gone or the stream is out of sync. A line longer
+/// than `MAX_REQUEST_BYTES` is returned cut short, for the caller to
+/// reject.
+async fn read_message<R: AsyncRead + Unpin>(
+    reader: &mut BufReader<R>,
+    codec: Option<Compression>,
+) -> Result<Option<String>> {
+    if let Some(algorithm) = codec {
+        return match compression::read_frame(reader, algorithm, MAX_REQUEST_BYTES).await {
+            Ok(message) => Ok(message),
+            Err(e) => {
+                // A bad frame leaves the stream out of sync
+                warn!(error = %e, "Invalid compressed frame, closing connection");
+                Ok(None)
+            }
+        };
+    }
+    let mut line = String::new();
+    let limit = MAX_REQUEST_BYTES as u64 + 1;
+    if reader.take(limit).read_line(&mut line).await? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line))
+}
+
+async fn handle_connection<R, W>(
+    reader: R,
+    mut writer: W,
     handler: Arc<RequestHandler>,
-) -> Result<()> {
-    let (reader, mut writer) = stream.into_split();
+    transport: Transport,
+) -> Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
     let mut reader = BufReader::new(reader);
-    let mut line = String::new();
+    let debounce = Duration::from_millis(handler.config().completion.debounce_ms);
+    // Set once the client negotiates compression
+    let mut codec: Option<Compression> = None;
+    // Set once `initialize` enables binary audio frames
+    let mut binary_audio = false;
+    // A message read ahead while debouncing a completion
+    let mut next: Option<String> = None;
+    // Network clients present a token in `initialize`
+    let mut access = match transport {
+        Transport::Unix => Access::Full,
+        _ => Access::Anonymous,
+    };
+    // Set once `session/open` succeeds on this connection
+    let mut session: Option<String> = None;
+
+    loop {
+        let line = match next.take() {
+            Some(line) => line,
+            None => match read_message(&mut reader, codec).await? {
+                Some(line) => line,
+                None => break,
+            },
+        };
+        if line.len() > MAX_REQUEST_BYTES {
+            // The rest of the line is still unread; the stream can't be resynced
+            warn!(
+                limit = MAX_REQUEST_BYTES,
+                "Request too large, closing connection"
+            );
+            let response = JsonRpcResponse::error(
+                murmur_protocol::INVALID_REQUEST,
+                format!("Request exceeds {MAX_REQUEST_BYTES} bytes"),
+                RequestId::Number(0),
+            );
+            write_message(&mut writer, codec, &serde_json::to_string(&response)?).await?;
+            return Ok(());
+        }
 
-    while reader.read_line(&mut line).await? > 0 {
         let trimmed = line.trim();
         if trimmed.is_empty() {
-            line.clear();
             continue;
         }
 
-        let response = match serde_json::from_str::<JsonRpcRequest>(trimmed) {
-            Ok(request) => {
+        let parsed = serde_json::from_str::<JsonRpcRequest>(trimmed).map(|mut request| {
+            if let Some(ref id) = session {
+                bind_session(&mut request, id);
+            }
+            request
+        });
+        let response = match parsed {
+            Ok(request) if !access.permits(&request.method, &handler.config().daemon.tokens) => {
+                let scope = auth::required_scope(&request.method).unwrap_or(Scope::Admin);
+                warn!(method = %request.method, ?access, "Request refused, token lacks the scope");
+                let response = JsonRpcResponse::error(
+                    murmur_protocol::UNAUTHORIZED,
+                    format!(
+                        "{} needs a token with the {} scope",
+                        request.method,
+                        scope.as_str()
+                    ),
+                    request.id.clone(),
+                );
+                if has_audio_frame(&request) {
+                    // The frame that follows can't be skipped reliably
+                    write_message(&mut writer, codec, &serde_json::to_string(&response)?).await?;
+                    return Ok(());
+                }
+                response
+            }
+            Ok(request) if request.method == murmur_protocol::methods::EVENTS_SUBSCRIBE => {
+                return stream_events(writer, codec, &handler, request, &access).await;
+            }
+            Ok(request) if request.method == murmur_protocol::methods::INITIALIZE => {
+                let (response, features) = initialize(
+                    request,
+                    transport,
+                    &mut access,
+                    &handler.config().daemon.tokens,
+                );
+                binary_audio = features.binary_audio;
+                write_message(&mut writer, codec, &serde_json::to_string(&response)?).await?;
+                continue;
+            }
+            Ok(request) if has_audio_frame(&request) => {
+                if !binary_audio {
+                    // The frame that follows can't be skipped reliably
+                    let response = JsonRpcResponse::error(
+                        murmur_protocol::INVALID_REQUEST,
+                        "audio_frame requires binary_audio from initialize",
+                        request.id,
+                    );
+                    write_message(&mut writer, codec, &serde_json::to_string(&response)?).await?;
+                    return Ok(());
+                }
+                match frame::read(&mut reader, MAX_REQUEST_BYTES).await {
+                    Ok(Some(audio)) => handler.handle_voice_frame(request, audio).await,
+                    Ok(None) => break,
+                    Err(e) => {
+                        warn!(error = %e, "Invalid audio frame, closing connection");
+                        return Ok(());
+                    }
+                }
+            }
+            Ok(request) if request.method == murmur_protocol::methods::COMPRESSION_NEGOTIATE => {
+                let (response, algorithm) = negotiate_compression(request, transport);
+                // The answer itself still uses the old framing
+                write_message(&mut writer, codec, &serde_json::to_string(&response)?).await?;
+                if algorithm.is_some() {
+                    debug!(algorithm = ?algorithm, "Connection compressed");
+                    codec = algorithm;
+                }
+                continue;
+            }
+            Ok(mut request) => {
                 let is_shutdown = request.method == murmur_protocol::methods::SHUTDOWN;
+                let is_session_open = request.method == murmur_protocol::methods::SESSION_OPEN;
                 let is_complete = request.method == murmur_protocol::methods::COMPLETE;
 
                 // Extract params for pre-fetching before handling consumes them
@@ -105,26 +615,71 @@ async fn handle_connection(
                     None
                 };
 
+                // Completions that a newer one on this connection extends
+                // within the debounce window are answered as cancelled
+                let mut prefetch_params = prefetch_params;
+                if let Some(params) = prefetch_params.as_mut().filter(|_| !debounce.is_zero()) {
+                    while next.is_none() && message_within(&mut reader, debounce).await {
+                        let Some(message) = read_message(&mut reader, codec).await? else {
+                            break;
+                        };
+                        match superseding(&message, params, session.as_deref()) {
+                            Some((newer, newer_params)) => {
+                                debug!(input = %params.input, "Debounced, a newer completion extends it");
+                                let response = JsonRpcResponse::error(
+                                    murmur_protocol::REQUEST_CANCELLED,
+                                    "Superseded by a newer completion",
+                                    request.id,
+                                );
+                                write_message(
+                                    &mut writer,
+                                    codec,
+                                    &serde_json::to_string(&response)?,
+                                )
+                                .await?;
+                                (request, *params) = (newer, newer_params);
+                            }
+                            None => next = Some(message),
+                        }
+                    }
+                }
+
                 let response = handler.handle(request).await;
 
+                if is_session_open {
+                    if let Some(opened) = response
+                        .result
+                        .clone()
+                        .and_then(|r| serde_json::from_value::<SessionOpenResponse>(r).ok())
+                    {
+                        session = Some(opened.session_id);
+                    }
+                }
+
                 if is_shutdown {
-                    let json = serde_json::to_string(&response)?;
-                    writer.write_all(json.as_bytes()).await?;
-                    writer.write_all(b"\n").await?;
-                    writer.flush().await?;
+                    write_message(&mut writer, codec, &serde_json::to_string(&response)?).await?;
 
                     // Clean up socket and PID files, then exit
                     info!("Shutting down");
-                    let socket_path = handler.socket_path();
-                    let _ = std::fs::remove_file(socket_path);
+                    if !SOCKET_ACTIVATED.load(Ordering::Relaxed) {
+                        let _ = std::fs::remove_file(handler.socket_path());
+                    }
                     let _ = std::fs::remove_file(Config::pid_path());
                     std::process::exit(0);
                 }
 
-                // Spawn speculative pre-fetch for predicted next inputs
+                // Spawn speculative pre-fetch for predicted next inputs, after
+                // refreshing an answer narrowed from a shorter input's
                 if let Some(params) = prefetch_params {
+                    let refresh = response
+                        .result
+                        .as_ref()
+                        .is_some_and(|result| result["refreshing"] == true);
                     let handler = handler.clone();
                     tokio::spawn(async move {
+                        if refresh {
+                            handler.refresh(&params).await;
+                        }
                         prefetch_completions(&handler, &params).await;
                     });
                 }
@@ -141,21 +696,265 @@ async fn handle_connection(
             }
         };
 
-        let json = serde_json::to_string(&response)?;
-        writer.write_all(json.as_bytes()).await?;
-        writer.write_all(b"\n").await?;
-        writer.flush().await?;
+        write_message(&mut writer, codec, &serde_json::to_string(&response)?).await?;
+    }
+
+    Ok(())
+}
+
+/// Whether more of the client's input arrives within `window`.
+async fn message_within<R: AsyncRead + Unpin>(reader: &mut BufReader<R>, window: Duration) -> bool {
+    matches!(
+        tokio::time::timeout(window, reader.fill_buf()).await,
+        Ok(Ok(buffered)) if !buffered.is_empty()
+    )
+}
 
-        line.clear();
+/// `message` parsed, if it's a `complete` for the same shell and place as
+/// `held` whose input extends `held`'s.
+fn superseding(
+    message: &str,
+    held: &CompletionRequest,
+    session: Option<&str>,
+) -> Option<(JsonRpcRequest, CompletionRequest)> {
+    let mut request: JsonRpcRequest = serde_json::from_str(message.trim()).ok()?;
+    if request.method != murmur_protocol::methods::COMPLETE {
+        return None;
+    }
+    if let Some(id) = session {
+        bind_session(&mut request, id);
     }
+    let params: CompletionRequest = serde_json::from_value(request.params.clone()?).ok()?;
+    let extends = params.input.starts_with(&held.input)
+        && (&params.cwd, &params.shell, &params.host) == (&held.cwd, &held.shell, &held.host)
+        && (&params.provider, &params.model) == (&held.provider, &held.model);
+    extends.then_some((request, params))
+}
 
+/// Give a completion or history request that doesn't name a session the
+/// connection's own.
+fn bind_session(request: &mut JsonRpcRequest, id: &str) {
+    use murmur_protocol::methods;
+    if ![
+        methods::COMPLETE,
+        methods::COMPLETE_CANDIDATES,
+        methods::CONTEXT_UPDATE,
+    ]
+    .contains(&request.method.as_str())
+    {
+        return;
+    }
+    if let Some(serde_json::Value::Object(params)) = request.params.as_mut() {
+        if params.get("session_id").map_or(true, |v| v.is_null()) {
+            params.insert("session_id".to_string(), id.into());
+        }
+    }
+}
+
+/// Answer `initialize`, returning the features enabled for the connection.
+fn initialize(
+    request: JsonRpcRequest,
+    transport: Transport,
+    access: &mut Access,
+    tokens: &[ApiToken],
+) -> (JsonRpcResponse, InitializeResponse) {
+    let params: InitializeRequest = match serde_json::from_value(request.params.unwrap_or_default())
+    {
+        Ok(params) => params,
+        Err(e) => {
+            let response = JsonRpcResponse::error(
+                murmur_protocol::INVALID_PARAMS,
+                format!("Invalid initialize params: {e}"),
+                request.id,
+            );
+            return (response, InitializeResponse::default());
+        }
+    };
+    let mut scopes = Vec::new();
+    if let (Some(presented), Access::Anonymous | Access::Token(_)) = (&params.token, &*access) {
+        match auth::find(tokens, presented) {
+            Some(token) => {
+                debug!(token = %token.name, "Client authenticated");
+                scopes = token.scopes.clone();
+                *access = Access::Token(presented.clone());
+            }
+            None => {
+                warn!(transport = ?transport, "Client sent an unknown token");
+                let response = JsonRpcResponse::error(
+                    murmur_protocol::UNAUTHORIZED,
+                    "Unknown token",
+                    request.id,
+                );
+                return (response, InitializeResponse::default());
+            }
+        }
+    }
+    if let Some(version) = params.protocol_version {
+        // A newer client checks `methods` rather than assuming
+        debug!(
+            client = params.client.as_deref().unwrap_or("unknown"),
+            version, "Client initialized"
+        );
+    }
+    let features = InitializeResponse {
+        binary_audio: params.binary_audio && transport.carries_frames(),
+        scopes,
+        protocol_version: murmur_protocol::PROTOCOL_VERSION,
+        daemon_version: env!("CARGO_PKG_VERSION").to_string(),
+        methods: murmur_protocol::methods::ALL
+            .iter()
+            .map(|m| m.to_string())
+            .collect(),
+        notifications: murmur_protocol::methods::NOTIFICATIONS
+            .iter()
+            .map(|m| m.to_string())
+            .collect(),
+    };
+    let response = JsonRpcResponse::success(serde_json::to_value(&features).unwrap(), request.id);
+    (response, features)
+}
+
+/// A `voice/process` request whose audio follows as a binary frame.
+fn has_audio_frame(request: &JsonRpcRequest) -> bool {
+    request.method == murmur_protocol::methods::VOICE_PROCESS
+        && request
+            .params
+            .as_ref()
+            .and_then(|p| p.get("audio_frame"))
+            .and_then(serde_json::Value::as_bool)
+            == Some(true)
+}
+
+/// Answer `compression/negotiate`, returning the algorithm picked. None is
+/// over a WebSocket, whose messages can't hold the frames.
+fn negotiate_compression(
+    request: JsonRpcRequest,
+    transport: Transport,
+) -> (JsonRpcResponse, Option<Compression>) {
+    let params: CompressionRequest =
+        match serde_json::from_value(request.params.unwrap_or_default()) {
+            Ok(params) => params,
+            Err(e) => {
+                let response = JsonRpcResponse::error(
+                    murmur_protocol::INVALID_PARAMS,
+                    format!("Invalid compression params: {e}"),
+                    request.id,
+                );
+                return (response, None);
+            }
+        };
+    let algorithm =
+        compression::negotiate(&params.algorithms).filter(|_| transport.carries_frames());
+    let response = CompressionResponse { algorithm };
+    (
+        JsonRpcResponse::success(serde_json::to_value(&response).unwrap(), request.id),
+        algorithm,
+    )
+}
+
+/// Answer `events/subscribe` with the current state, then push the daemon's
+/// notifications the client asked for until it disconnects. Network
+/// clients only get the ones their token's scopes cover.
+async fn stream_events<W: AsyncWrite + Unpin>(
+    mut writer: W,
+    codec: Option<Compression>,
+    handler: &RequestHandler,
+    request: JsonRpcRequest,
+    access: &Access,
+) -> Result<()> {
+    let params: EventsSubscribeRequest = match request.params {
+        Some(params) if !params.is_null() => match serde_json::from_value(params) {
+            Ok(params) => params,
+            Err(e) => {
+                let response = JsonRpcResponse::error(
+                    murmur_protocol::INVALID_PARAMS,
+                    format!("Invalid events/subscribe params: {e}"),
+                    request.id,
+                );
+                write_message(&mut writer, codec, &serde_json::to_string(&response)?).await?;
+                return Ok(());
+            }
+        },
+        _ => EventsSubscribeRequest::default(),
+    };
+    // Subscribe before taking the snapshot so no event falls in between
+    let mut events = handler.subscribe_events();
+    let state = handler.state().await;
+    let response = JsonRpcResponse::success(serde_json::to_value(&state)?, request.id);
+    write_message(&mut writer, codec, &serde_json::to_string(&response)?).await?;
+    debug!(methods = ?params.methods, "Event subscriber connected");
+
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                warn!(skipped, "Event subscriber lagging, events dropped");
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => return Ok(()),
+        };
+        let method = event.method();
+        if !params.methods.is_empty() && !params.methods.iter().any(|m| m == method) {
+            continue;
+        }
+        if let (Some(wanted), Some(session)) = (&params.session_id, event.session_id()) {
+            if wanted != session {
+                continue;
+            }
+        }
+        // Checked per notification, so a revoked token stops receiving
+        if !access.allows(
+            auth::notification_scope(method),
+            &handler.config().daemon.tokens,
+        ) {
+            continue;
+        }
+        let notification = event.to_jsonrpc();
+        if write_message(&mut writer, codec, &serde_json::to_string(&notification)?)
+            .await
+            .is_err()
+        {
+            debug!("Event subscriber disconnected");
+            return Ok(());
+        }
+    }
+}
+
+/// Write one message: a JSON line, or a compressed frame once negotiated.
+async fn write_message<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    codec: Option<Compression>,
+    json: &str,
+) -> Result<()> {
+    match codec {
+        Some(algorithm) => compression::write_frame(writer, algorithm, json).await?,
+        None => {
+            writer.write_all(json.as_bytes()).await?;
+            writer.write_all(b"\n").await?;
+            writer.flush().await?;
+        }
+    }
     Ok(())
 }
 
+/// Predicted next commands pre-fetched after an empty-prompt request.
+const SEQUENCE_PREFETCH: usize = 2;
+
 /// Speculatively pre-fetch completions for predicted next inputs.
 /// This runs in the background after a completion request is served.
 async fn prefetch_completions(handler: &RequestHandler, original: &CompletionRequest) {
-    let predictions = prefetch::predict_next_inputs(&original.input);
+    // On an empty prompt, warm the commands the user will probably run next
+    let predictions = if original.input.trim().is_empty() {
+        handler
+            .predict_next(original)
+            .await
+            .into_iter()
+            .take(SEQUENCE_PREFETCH)
+            .map(|p| p.command)
+            .collect()
+    } else {
+        handler.predict_inputs(&original.input).await
+    };
     if predictions.is_empty() {
         return;
     }
@@ -167,36 +966,87 @@ async fn prefetch_completions(handler: &RequestHandler, original: &CompletionReq
     );
 
     for predicted_input in predictions {
-        // Build a synthetic request for the predicted input
-        let request = JsonRpcRequest::new(
-            murmur_protocol::methods::COMPLETE,
-            Some(
-                serde_json::to_value(&CompletionRequest {
-                    input: predicted_input.clone(),
-                    cursor_pos: predicted_input.len(),
-                    cwd: original.cwd.clone(),
-                    history: original.history.clone(),
-                    shell: original.shell.clone(),
-                })
-                .unwrap(),
-            ),
-            RequestId::Number(0), // internal request, ID doesn't matter
-        );
+        let request = CompletionRequest {
+            input: predicted_input.clone(),
+            cursor_pos: predicted_input.len(),
+            cwd: original.cwd.clone(),
+            history: original.history.clone(),
+            shell: original.shell.clone(),
+            max_items: original.max_items,
+            min_score: original.min_score,
+            host: original.host.clone(),
+            provider: None,
+            model: None,
+            cancel_token: None,
+            session_id: None,
+        };
 
         // This will populate the cache for the predicted input
-        let _ = handler.handle(request).await;
+        let _ = handler.prefetch(&request).await;
         debug!(input = %predicted_input, "Pre-fetched completion");
     }
 }
 
-/// Initialize tracing subscriber.
+/// Configured state database path with `~/` expanded; `None` when
+/// persistence is disabled.
+pub fn state_path(config: &Config) -> Option<String> {
+    let path = config
+        .daemon
+        .state_path
+        .as_deref()
+        .filter(|p| !p.is_empty())?;
+    Some(expand_home(path).display().to_string())
+}
+
+/// History backed by the state database, or in-memory only when
+/// persistence is disabled or the database can't be used. A database that
+/// fails to open or migrate is left untouched.
+pub(crate) fn open_history(config: &Config, clock: &dyn Clock) -> CommandHistory {
+    let Some(path) = state_path(config) else {
+        return CommandHistory::new(HISTORY_ENTRIES);
+    };
+    match Store::open(std::path::Path::new(&path), clock)
+        .and_then(|store| CommandHistory::with_store(HISTORY_ENTRIES, store))
+    {
+        Ok(history) => {
+            info!(path = %path, entries = history.len(), "Loaded persistent history");
+            history
+        }
+        Err(e) => {
+            error!(path = %path, error = %e, "State database unavailable, history will not persist");
+            CommandHistory::new(HISTORY_ENTRIES)
+        }
+    }
+}
+
+/// Initialize tracing subscriber. Events are also kept, redacted, for
+/// `murmur debug bundle`.
 pub fn init_tracing(log_level: &str) {
+    use tracing_subscriber::prelude::*;
     use tracing_subscriber::EnvFilter;
 
     let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(log_level));
 
-    tracing_subscriber::fmt()
-        .with_env_filter(filter)
-        .with_target(false)
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer().with_target(false))
+        .with(crate::debug::LogCapture)
         .init();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn private_dir_restricts_an_open_dir() {
+        let dir = std::env::temp_dir().join(format!("murmur-private-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+        private_dir(&dir).unwrap();
+        let mode = std::fs::metadata(&dir).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,254 @@
+//! Per-shell sessions.
+//!
+//! Requests carry a `session_id`, either issued by `session/open` or one
+//! the shell picked (the plugins send `zsh-$$` and the like), so the
+//! daemon can tell one terminal's activity from another's. A session
+//! remembers the directories its shell has been in, what it ran and which
+//! of those runs were accepted suggestions. Ranking favors what this
+//! session accepted, and next-command predictions (and the prefetching
+//! built on them) follow its own last commands rather than the shared
+//! history file. Sessions live in memory: one ends when its shell exits,
+//! if the PID is known, or after a day without requests.
+
+use murmur_protocol::SessionOpenRequest;
+use std::collections::{HashMap, VecDeque};
+use std::hash::BuildHasher;
+
+/// Sessions kept at once; the least recently active go first.
+const MAX_SESSIONS: usize = 1024;
+
+/// A session with no requests for this long is dropped.
+const IDLE_SECS: u64 = 86_400;
+
+/// Directories and commands remembered per session.
+const MAX_REMEMBERED: usize = 20;
+
+/// One shell's state.
+#[derive(Debug, Default)]
+pub struct Session {
+    pub shell_pid: Option<u32>,
+    pub shell: Option<String>,
+    pub host: Option<String>,
+    /// Directories the shell has been in, newest last.
+    pub dirs: VecDeque<String>,
+    /// Commands it ran, newest last.
+    pub ran: VecDeque<String>,
+    /// Commands it ran unchanged from a suggestion, newest last.
+    pub accepted: VecDeque<String>,
+    last_seen: u64,
+}
+
+impl Session {
+    /// The current directory, if one was reported.
+    pub fn cwd(&self) -> Option<&str> {
+        self.dirs.back().map(String::as_str)
+    }
+
+    /// Note the shell is in `cwd`. Returns whether that's a change.
+    pub fn cd(&mut self, cwd: &str) -> bool {
+        if self.cwd() == Some(cwd) || cwd.is_empty() {
+            return false;
+        }
+        remember(&mut self.dirs, cwd);
+        true
+    }
+
+    /// Note the shell ran `command` in `cwd`, from a suggestion if
+    /// `accepted`.
+    pub fn ran(&mut self, command: &str, cwd: &str, accepted: bool) {
+        self.cd(cwd);
+        remember(&mut self.ran, command.trim());
+        if accepted {
+            remember(&mut self.accepted, command.trim());
+        }
+    }
+
+    /// The last `n` commands it ran, oldest first.
+    pub fn last_commands(&self, n: usize) -> Vec<String> {
+        self.ran
+            .iter()
+            .skip(self.ran.len().saturating_sub(n))
+            .cloned()
+            .collect()
+    }
+
+    /// Whether the shell accepted a suggestion of `command` recently.
+    pub fn has_accepted(&self, command: &str) -> bool {
+        self.accepted.iter().any(|c| c == command.trim())
+    }
+}
+
+fn remember(list: &mut VecDeque<String>, value: &str) {
+    if list.len() == MAX_REMEMBERED {
+        list.pop_front();
+    }
+    list.push_back(value.to_string());
+}
+
+/// Open sessions, by ID.
+#[derive(Default)]
+pub struct Sessions {
+    sessions: HashMap<String, Session>,
+}
+
+impl Sessions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start a session for `request` at `now`, or refresh the one it names.
+    /// Returns its ID.
+    pub fn open(&mut self, request: SessionOpenRequest, now: u64) -> String {
+        self.expire(now);
+        let id = request
+            .session_id
+            .filter(|id| !id.is_empty())
+            .unwrap_or_else(new_id);
+        let session = self.touch(&id, now);
+        session.shell_pid = request.shell_pid.or(session.shell_pid);
+        session.shell = request.shell.or(session.shell.take());
+        session.host = request.host.or(session.host.take());
+        if let Some(cwd) = request.cwd {
+            session.cd(&cwd);
+        }
+        id
+    }
+
+    /// Session `id` as of `now`, started if it's new (as with IDs a shell
+    /// picked without calling `session/open`).
+    pub fn touch(&mut self, id: &str, now: u64) -> &mut Session {
+        if !self.sessions.contains_key(id) && self.sessions.len() >= MAX_SESSIONS {
+            self.expire(now);
+            if self.sessions.len() >= MAX_SESSIONS {
+                let oldest = self
+                    .sessions
+                    .iter()
+                    .min_by_key(|(_, s)| s.last_seen)
+                    .map(|(id, _)| id.clone());
+                if let Some(oldest) = oldest {
+                    self.sessions.remove(&oldest);
+                }
+            }
+        }
+        let session = self.sessions.entry(id.to_string()).or_default();
+        session.last_seen = now;
+        session
+    }
+
+    /// Session `id`, if it's open.
+    pub fn get(&self, id: &str) -> Option<&Session> {
+        self.sessions.get(id)
+    }
+
+    /// Drop sessions idle since a day before `now`, and local ones whose
+    /// shell has exited.
+    pub fn expire(&mut self, now: u64) {
+        self.sessions.retain(|_, session| {
+            now.saturating_sub(session.last_seen) < IDLE_SECS
+                && !(session.host.is_none() && session.shell_pid.is_some_and(exited))
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+}
+
+/// A fresh random session ID.
+fn new_id() -> String {
+    let random = std::collections::hash_map::RandomState::new().hash_one(std::process::id());
+    format!("s-{random:016x}")
+}
+
+/// Whether no process `pid` exists any more.
+fn exited(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return true;
+    };
+    // Signal 0 checks the process exists without touching it
+    let failed = unsafe { libc::kill(pid, 0) } == -1;
+    failed && std::io::Error::last_os_error().raw_os_error() == Some(libc::ESRCH)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sessions_keep_their_own_commands() {
+        let mut sessions = Sessions::new();
+        let id = sessions.open(
+            SessionOpenRequest {
+                shell_pid: Some(std::process::id()),
+                cwd: Some("/repo".to_string()),
+                ..Default::default()
+            },
+            100,
+        );
+        assert!(id.starts_with("s-"));
+        assert_ne!(sessions.open(SessionOpenRequest::default(), 100), id);
+
+        let session = sessions.touch(&id, 110);
+        assert!(!session.cd("/repo"));
+        session.ran("cargo build", "/repo", false);
+        session.ran("cargo test ", "/repo/crates", true);
+        assert_eq!(session.cwd(), Some("/repo/crates"));
+        assert_eq!(session.last_commands(2), ["cargo build", "cargo test"]);
+        assert!(session.has_accepted("cargo test"));
+        assert!(!session.has_accepted("cargo build"));
+
+        // A shell's own ID starts a session too, and reopening keeps state
+        sessions.touch("zsh-42", 110).ran("ls", "/tmp", false);
+        let resumed = sessions.open(
+            SessionOpenRequest {
+                session_id: Some("zsh-42".to_string()),
+                ..Default::default()
+            },
+            120,
+        );
+        assert_eq!(resumed, "zsh-42");
+        assert_eq!(sessions.get("zsh-42").unwrap().last_commands(5), ["ls"]);
+        assert!(sessions.get("zsh-43").is_none());
+    }
+
+    #[test]
+    fn idle_and_exited_sessions_expire() {
+        let mut sessions = Sessions::new();
+        let alive = sessions.open(
+            SessionOpenRequest {
+                shell_pid: Some(std::process::id()),
+                ..Default::default()
+            },
+            0,
+        );
+        let gone = sessions.open(
+            SessionOpenRequest {
+                shell_pid: Some(i32::MAX as u32),
+                ..Default::default()
+            },
+            0,
+        );
+        sessions.touch("quiet", 0);
+        sessions.touch(&alive, IDLE_SECS);
+        sessions.expire(IDLE_SECS);
+        assert!(sessions.get(&alive).is_some());
+        assert!(sessions.get(&gone).is_none());
+        assert!(sessions.get("quiet").is_none());
+    }
+
+    #[test]
+    fn the_least_recently_active_make_room() {
+        let mut sessions = Sessions::new();
+        for i in 0..MAX_SESSIONS as u64 {
+            sessions.touch(&format!("s{i}"), 1000 + i);
+        }
+        sessions.touch("new", 5000);
+        assert_eq!(sessions.len(), MAX_SESSIONS);
+        assert!(sessions.get("s0").is_none());
+        assert!(sessions.get("s1").is_some());
+    }
+}
@@ -0,0 +1,77 @@
+//! Waiting for typing to settle before asking cloud providers.
+//!
+//! A fast typist sends a completion request per keystroke, and each one
+//! would otherwise cost a cloud API call whose answer is stale before it
+//! arrives. With `[completion] settle_ms` set, a request that would go to
+//! a cloud provider first waits that long; if a newer request arrives from
+//! the same shell meanwhile, the older one gives up on the cloud.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Shells tracked before the oldest are forgotten.
+const MAX_SHELLS: usize = 1024;
+
+/// The latest request number from each shell.
+#[derive(Debug, Default)]
+pub struct Settle {
+    latest: Mutex<HashMap<u64, u64>>,
+}
+
+impl Settle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Identifies a shell by where it is: requests carry no terminal ID,
+    /// and two prompts in the same directory rarely type at once.
+    pub fn key(cwd: &str, shell: &str, host: Option<&str>) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        (cwd, shell, host).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Wait `time`, then report whether this is still the latest request
+    /// from `key`'s shell.
+    pub async fn wait(&self, key: u64, time: Duration) -> bool {
+        let generation = {
+            let mut latest = self.latest.lock().unwrap();
+            if latest.len() >= MAX_SHELLS && !latest.contains_key(&key) {
+                latest.clear();
+            }
+            let generation = latest.entry(key).or_default();
+            *generation += 1;
+            *generation
+        };
+        tokio::time::sleep(time).await;
+        self.latest.lock().unwrap().get(&key) == Some(&generation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn newer_request_supersedes_older() {
+        let settle = Settle::new();
+        let key = Settle::key("/repo", "zsh", None);
+        let other = Settle::key("/other", "zsh", None);
+        let wait = Duration::from_millis(50);
+
+        let (first, second, elsewhere) = tokio::join!(
+            settle.wait(key, wait),
+            async {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                settle.wait(key, wait).await
+            },
+            settle.wait(other, wait),
+        );
+        assert!(!first);
+        assert!(second);
+        assert!(elsewhere);
+    }
+}
@@ -0,0 +1,312 @@
+//! Shadow-mode provider evaluation.
+//!
+//! With `[routing] shadow_provider` set, each completion the normal chain
+//! answers is also asked of the shadow provider in the background. Its
+//! suggestions are never shown. When a command next runs in that
+//! directory, a line is appended to `shadow_log` saying whether it was
+//! among the primary's suggestions, the shadow's, both or neither, so two
+//! providers can be compared on real use before switching.
+
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::io::Write;
+use std::path::Path;
+
+/// Trials waiting for a command longer than this are dropped.
+const TRIAL_TTL_SECS: u64 = 600;
+
+/// Most trials kept waiting at once.
+const MAX_PENDING: usize = 100;
+
+/// Which provider suggested the command that ran.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Accepted {
+    Primary,
+    Shadow,
+    Both,
+    Neither,
+}
+
+/// One line of the evaluation log.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Evaluation {
+    pub timestamp: u64,
+    pub cwd: String,
+    pub input: String,
+    pub command: String,
+    pub primary: String,
+    pub shadow: String,
+    pub accepted: Accepted,
+    /// Positions (from 1) of the command among each provider's suggestions.
+    pub primary_rank: Option<usize>,
+    pub shadow_rank: Option<usize>,
+    pub primary_ms: u64,
+    pub shadow_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shadow_error: Option<String>,
+}
+
+struct Trial {
+    id: u64,
+    started: u64,
+    cwd: String,
+    input: String,
+    primary: String,
+    primary_items: Vec<String>,
+    primary_ms: u64,
+    /// The shadow's suggestions (or error) and latency, once it answers.
+    shadow: Option<(Result<Vec<String>, String>, u64)>,
+}
+
+impl Trial {
+    fn shadow_items(&self) -> &[String] {
+        match self.shadow {
+            Some((Ok(ref items), _)) => items,
+            _ => &[],
+        }
+    }
+
+    fn suggested(&self, command: &str) -> bool {
+        self.primary_items.iter().any(|i| i == command)
+            || self.shadow_items().iter().any(|i| i == command)
+    }
+}
+
+/// Completions answered by both providers, waiting to see what runs.
+pub struct ShadowTrials {
+    shadow: String,
+    next_id: u64,
+    pending: VecDeque<Trial>,
+}
+
+impl ShadowTrials {
+    pub fn new(shadow: &str) -> Self {
+        Self {
+            shadow: shadow.to_string(),
+            next_id: 0,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Name of the shadow provider.
+    pub fn shadow(&self) -> &str {
+        &self.shadow
+    }
+
+    /// Note the suggestions `primary` gave for `input`; returns the trial's
+    /// ID for `finish`.
+    pub fn start(
+        &mut self,
+        now: u64,
+        cwd: &str,
+        input: &str,
+        primary: &str,
+        primary_items: Vec<String>,
+        primary_ms: u64,
+    ) -> u64 {
+        self.pending
+            .retain(|t| now.saturating_sub(t.started) < TRIAL_TTL_SECS);
+        if self.pending.len() >= MAX_PENDING {
+            self.pending.pop_front();
+        }
+        self.next_id += 1;
+        self.pending.push_back(Trial {
+            id: self.next_id,
+            started: now,
+            cwd: cwd.to_string(),
+            input: input.to_string(),
+            primary: primary.to_string(),
+            primary_items,
+            primary_ms,
+            shadow: None,
+        });
+        self.next_id
+    }
+
+    /// Record the shadow provider's answer to trial `id`.
+    pub fn finish(&mut self, id: u64, items: Result<Vec<String>, String>, latency_ms: u64) {
+        if let Some(trial) = self.pending.iter_mut().find(|t| t.id == id) {
+            trial.shadow = Some((items, latency_ms));
+        }
+    }
+
+    /// `command` ran in `cwd`: judge the trial it settles and forget the
+    /// directory's others. Of the answered trials there, the latest that
+    /// suggested the command is judged, or else the latest.
+    pub fn ran(&mut self, command: &str, cwd: &str, now: u64) -> Option<Evaluation> {
+        let command = command.trim();
+        let (trials, rest): (VecDeque<Trial>, VecDeque<Trial>) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(|t| t.cwd == cwd);
+        self.pending = rest;
+        let mut answered = trials.iter().rev().filter(|t| t.shadow.is_some());
+        let trial = answered
+            .clone()
+            .find(|t| t.suggested(command))
+            .or_else(|| answered.next())?;
+
+        let rank = |items: &[String]| items.iter().position(|i| i == command).map(|i| i + 1);
+        let primary_rank = rank(&trial.primary_items);
+        let shadow_rank = rank(trial.shadow_items());
+        let (shadow_error, shadow_ms) = match trial.shadow {
+            Some((Err(ref e), ms)) => (Some(e.clone()), ms),
+            Some((Ok(_), ms)) => (None, ms),
+            None => (None, 0),
+        };
+        Some(Evaluation {
+            timestamp: now,
+            cwd: cwd.to_string(),
+            input: trial.input.clone(),
+            command: command.to_string(),
+            primary: trial.primary.clone(),
+            shadow: self.shadow.clone(),
+            accepted: match (primary_rank, shadow_rank) {
+                (Some(_), Some(_)) => Accepted::Both,
+                (Some(_), None) => Accepted::Primary,
+                (None, Some(_)) => Accepted::Shadow,
+                (None, None) => Accepted::Neither,
+            },
+            primary_rank,
+            shadow_rank,
+            primary_ms: trial.primary_ms,
+            shadow_ms,
+            shadow_error,
+        })
+    }
+}
+
+/// Append `evaluation` to the JSON Lines log at `path`.
+pub fn append(path: &Path, evaluation: &Evaluation) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut line = serde_json::to_string(evaluation).map_err(std::io::Error::other)?;
+    line.push('\n');
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(line.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn the_trial_that_suggested_the_command_is_judged() {
+        let mut trials = ShadowTrials::new("codestral");
+        let first = trials.start(
+            100,
+            "/repo",
+            "git p",
+            "anthropic",
+            strings(&["git pull"]),
+            300,
+        );
+        trials.finish(first, Ok(strings(&["git push", "git pull"])), 120);
+        let second = trials.start(
+            101,
+            "/repo",
+            "git pu",
+            "anthropic",
+            strings(&["git pull"]),
+            280,
+        );
+        trials.finish(second, Ok(strings(&["git pull"])), 90);
+        let elsewhere = trials.start(101, "/other", "ls", "anthropic", strings(&["ls -la"]), 200);
+        trials.finish(elsewhere, Ok(vec![]), 80);
+
+        let evaluation = trials.ran("git push ", "/repo", 105).unwrap();
+        assert_eq!(evaluation.input, "git p");
+        assert_eq!(evaluation.command, "git push");
+        assert_eq!(evaluation.accepted, Accepted::Shadow);
+        assert_eq!(
+            (evaluation.primary_rank, evaluation.shadow_rank),
+            (None, Some(1))
+        );
+        assert_eq!((evaluation.primary_ms, evaluation.shadow_ms), (300, 120));
+
+        // The directory's other trials are settled too
+        assert_eq!(trials.ran("git push", "/repo", 106), None);
+        let evaluation = trials.ran("make", "/other", 106).unwrap();
+        assert_eq!(evaluation.accepted, Accepted::Neither);
+    }
+
+    #[test]
+    fn unanswered_and_stale_trials_are_not_judged() {
+        let mut trials = ShadowTrials::new("codestral");
+        trials.start(
+            100,
+            "/repo",
+            "git s",
+            "anthropic",
+            strings(&["git status"]),
+            300,
+        );
+        assert_eq!(trials.ran("git status", "/repo", 101), None);
+
+        let id = trials.start(
+            100,
+            "/repo",
+            "git s",
+            "anthropic",
+            strings(&["git status"]),
+            300,
+        );
+        trials.finish(id, Err("Timeout".to_string()), 5000);
+        trials.start(
+            100 + TRIAL_TTL_SECS,
+            "/other",
+            "ls",
+            "anthropic",
+            vec![],
+            10,
+        );
+        assert_eq!(
+            trials.ran("git status", "/repo", 101 + TRIAL_TTL_SECS),
+            None
+        );
+
+        let id = trials.start(
+            2000,
+            "/repo",
+            "git s",
+            "anthropic",
+            strings(&["git status"]),
+            300,
+        );
+        trials.finish(id, Err("Timeout".to_string()), 5000);
+        let evaluation = trials.ran("git status", "/repo", 2001).unwrap();
+        assert_eq!(evaluation.accepted, Accepted::Primary);
+        assert_eq!(evaluation.shadow_error.as_deref(), Some("Timeout"));
+    }
+
+    #[test]
+    fn evaluations_append_as_json_lines() {
+        let path = std::env::temp_dir()
+            .join(format!("murmur-shadow-{}", std::process::id()))
+            .join("shadow.jsonl");
+        let mut trials = ShadowTrials::new("codestral");
+        for _ in 0..2 {
+            let id = trials.start(100, "/repo", "ls", "ollama", strings(&["ls -la"]), 40);
+            trials.finish(id, Ok(strings(&["ls -la"])), 200);
+            append(&path, &trials.ran("ls -la", "/repo", 101).unwrap()).unwrap();
+        }
+        let log = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = log
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["accepted"], "both");
+        assert_eq!(lines[0]["shadow"], "codestral");
+        assert!(lines[0].get("shadow_error").is_none());
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,451 @@
+//! Versioned on-disk state (SQLite) with ordered migrations.
+//!
+//! Every schema change is a numbered `Migration` appended to `MIGRATIONS`;
+//! applied versions are recorded in the `schema_version` table. Before an
+//! existing database is migrated, a copy is written next to it, so an
+//! upgrade that goes wrong can always be rolled back by hand. A database
+//! written by a newer daemon is refused rather than modified.
+
+use murmur_protocol::HistoryEntry;
+use rusqlite::{params, Connection};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use tracing::info;
+
+use crate::clock::Clock;
+
+#[derive(Debug, Error)]
+pub enum StoreError {
+    #[error("SQLite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("State database is at schema version {found}, newer than supported ({supported}); refusing to touch it")]
+    TooNew { found: u32, supported: u32 },
+    #[error("Migration {version} ({description}) failed: {source}")]
+    Migration {
+        version: u32,
+        description: &'static str,
+        source: rusqlite::Error,
+    },
+}
+
+/// One schema change. Versions must be strictly increasing.
+#[derive(Debug, Clone, Copy)]
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub sql: &'static str,
+}
+
+/// All migrations, oldest first. Never edit a released entry; add a new one.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "cross-tool command history",
+        sql: "CREATE TABLE history (
+              id INTEGER PRIMARY KEY,
+              command TEXT NOT NULL,
+              cwd TEXT NOT NULL,
+              source TEXT NOT NULL,
+              exit_code INTEGER NOT NULL,
+              timestamp INTEGER NOT NULL,
+              session_id TEXT,
+              host TEXT
+          );
+          CREATE INDEX history_timestamp ON history (timestamp);",
+    },
+    Migration {
+        version: 2,
+        description: "index for deduplicating imported history",
+        sql: "CREATE INDEX history_command_timestamp ON history (command, timestamp);",
+    },
+    Migration {
+        version: 3,
+        description: "provider of the suggestion a command came from",
+        sql: "ALTER TABLE history ADD COLUMN suggested_by TEXT;",
+    },
+];
+
+/// Handle to the state database.
+pub struct Store {
+    conn: Connection,
+}
+
+impl Store {
+    /// Open (creating if needed) and migrate the database at `path`,
+    /// dating migrations and backups by `clock`.
+    pub fn open(path: &Path, clock: &dyn Clock) -> Result<Self, StoreError> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut conn = Connection::open(path)?;
+        migrate(&mut conn, MIGRATIONS, Some(path), clock.unix_now())?;
+        Ok(Self { conn })
+    }
+
+    /// In-memory database with the current schema (for tests).
+    pub fn open_in_memory(clock: &dyn Clock) -> Result<Self, StoreError> {
+        let mut conn = Connection::open_in_memory()?;
+        migrate(&mut conn, MIGRATIONS, None, clock.unix_now())?;
+        Ok(Self { conn })
+    }
+
+    /// Highest applied migration.
+    pub fn schema_version(&self) -> Result<u32, StoreError> {
+        current_version(&self.conn)
+    }
+
+    /// Append a history entry.
+    pub fn insert_history(&self, entry: &HistoryEntry) -> Result<(), StoreError> {
+        self.conn.execute(
+            "INSERT INTO history
+             (command, cwd, source, exit_code, timestamp, session_id, host, suggested_by)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                entry.command,
+                entry.cwd,
+                entry.source,
+                entry.exit_code,
+                entry.timestamp as i64,
+                entry.session_id,
+                entry.host,
+                entry.suggested_by,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Insert entries that aren't stored yet, in one transaction. An entry
+    /// counts as stored when the same command exists with the same
+    /// timestamp, or for `undated` entries (whose timestamps are made up)
+    /// from the same source, so re-running an import adds nothing. Returns
+    /// the number of entries inserted.
+    pub fn import_history(
+        &mut self,
+        entries: &[HistoryEntry],
+        undated: bool,
+    ) -> Result<usize, StoreError> {
+        let tx = self.conn.transaction()?;
+        let mut imported = 0;
+        {
+            let mut exists = tx.prepare(if undated {
+                "SELECT 1 FROM history WHERE command = ?1 AND source = ?2"
+            } else {
+                "SELECT 1 FROM history WHERE command = ?1 AND timestamp = ?2"
+            })?;
+            let mut insert = tx.prepare(
+                "INSERT INTO history
+                 (command, cwd, source, exit_code, timestamp, session_id, host, suggested_by)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            )?;
+            // Newest first when undated, so repeated commands keep their
+            // latest time
+            let ordered: Box<dyn Iterator<Item = &HistoryEntry>> = if undated {
+                Box::new(entries.iter().rev())
+            } else {
+                Box::new(entries.iter())
+            };
+            for entry in ordered {
+                let found = if undated {
+                    exists.exists(params![entry.command, entry.source])?
+                } else {
+                    exists.exists(params![entry.command, entry.timestamp as i64])?
+                };
+                if found {
+                    continue;
+                }
+                insert.execute(params![
+                    entry.command,
+                    entry.cwd,
+                    entry.source,
+                    entry.exit_code,
+                    entry.timestamp as i64,
+                    entry.session_id,
+                    entry.host,
+                    entry.suggested_by,
+                ])?;
+                imported += 1;
+            }
+        }
+        tx.commit()?;
+        Ok(imported)
+    }
+
+    /// The newest `limit` history entries, newest first.
+    pub fn recent_history(&self, limit: usize) -> Result<Vec<HistoryEntry>, StoreError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT command, cwd, source, exit_code, timestamp, session_id, host, suggested_by
+             FROM history ORDER BY timestamp DESC, id DESC LIMIT ?1",
+        )?;
+        let rows = stmt.query_map([limit as i64], history_row)?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Entries with `since <= timestamp < until`, newest first.
+    pub fn history_between(
+        &self,
+        since: Option<u64>,
+        until: Option<u64>,
+    ) -> Result<Vec<HistoryEntry>, StoreError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT command, cwd, source, exit_code, timestamp, session_id, host, suggested_by
+             FROM history WHERE timestamp >= ?1 AND timestamp < ?2
+             ORDER BY timestamp DESC, id DESC",
+        )?;
+        let since = since.unwrap_or(0) as i64;
+        let until = until.map_or(i64::MAX, |t| t as i64);
+        let rows = stmt.query_map(params![since, until], history_row)?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+}
+
+fn history_row(row: &rusqlite::Row) -> rusqlite::Result<HistoryEntry> {
+    Ok(HistoryEntry {
+        command: row.get(0)?,
+        cwd: row.get(1)?,
+        source: row.get(2)?,
+        exit_code: row.get(3)?,
+        timestamp: row.get::<_, i64>(4)? as u64,
+        session_id: row.get(5)?,
+        host: row.get(6)?,
+        suggested_by: row.get(7)?,
+    })
+}
+
+fn current_version(conn: &Connection) -> Result<u32, StoreError> {
+    let version: Option<u32> =
+        conn.query_row("SELECT MAX(version) FROM schema_version", [], |row| {
+            row.get(0)
+        })?;
+    Ok(version.unwrap_or(0))
+}
+
+/// Apply pending `migrations` in order, each in its own transaction.
+/// When `path` is given and the database already has a schema, it is backed
+/// up first. `now` (unix seconds) dates the migrations. Returns the number
+/// of migrations applied.
+pub fn migrate(
+    conn: &mut Connection,
+    migrations: &[Migration],
+    path: Option<&Path>,
+    now: u64,
+) -> Result<usize, StoreError> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+             version INTEGER PRIMARY KEY,
+             description TEXT NOT NULL,
+             applied_at INTEGER NOT NULL
+         );",
+    )?;
+
+    let current = current_version(conn)?;
+    let latest = migrations.last().map_or(0, |m| m.version);
+    if current > latest {
+        return Err(StoreError::TooNew {
+            found: current,
+            supported: latest,
+        });
+    }
+
+    let pending: Vec<&Migration> = migrations.iter().filter(|m| m.version > current).collect();
+    if pending.is_empty() {
+        return Ok(0);
+    }
+
+    if let (Some(path), true) = (path, current > 0) {
+        let mut backup = backup_path(path, current);
+        if backup.exists() {
+            // Keep the earlier backup from a previous (failed) attempt
+            backup = backup_path(path, current).with_extension(format!("{now}.bak"));
+        }
+        // VACUUM INTO writes a consistent snapshot even with WAL enabled
+        conn.execute("VACUUM INTO ?1", [backup.to_string_lossy()])?;
+        info!(backup = %backup.display(), "Backed up state before migrating");
+    }
+
+    let now = now as i64;
+    for migration in &pending {
+        let failed = |source| StoreError::Migration {
+            version: migration.version,
+            description: migration.description,
+            source,
+        };
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration.sql).map_err(failed)?;
+        tx.execute(
+            "INSERT INTO schema_version (version, description, applied_at) VALUES (?1, ?2, ?3)",
+            params![migration.version, migration.description, now],
+        )?;
+        tx.commit()?;
+        info!(
+            version = migration.version,
+            description = migration.description,
+            "Applied state migration"
+        );
+    }
+    Ok(pending.len())
+}
+
+/// `state.db` at version 3 is backed up to `state.db.v3.bak`.
+fn backup_path(path: &Path, version: u32) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".v{version}.bak"));
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::SystemClock;
+
+    const NEXT: Migration = Migration {
+        version: 4,
+        description: "feedback",
+        sql: "CREATE TABLE feedback (id INTEGER PRIMARY KEY, text TEXT);",
+    };
+
+    #[test]
+    fn migrations_are_strictly_ordered() {
+        assert!(MIGRATIONS.windows(2).all(|w| w[0].version < w[1].version));
+        assert_eq!(MIGRATIONS[0].version, 1);
+    }
+
+    #[test]
+    fn history_roundtrips_newest_first() {
+        let store = Store::open_in_memory(&SystemClock).unwrap();
+        assert_eq!(store.schema_version().unwrap(), MIGRATIONS.len() as u32);
+        for (i, command) in ["ls", "make"].iter().enumerate() {
+            store
+                .insert_history(&HistoryEntry {
+                    command: command.to_string(),
+                    cwd: "/tmp".to_string(),
+                    source: "terminal".to_string(),
+                    exit_code: 0,
+                    timestamp: i as u64,
+                    session_id: None,
+                    host: Some("devbox".to_string()),
+                    suggested_by: (i == 1).then(|| "ollama".to_string()),
+                })
+                .unwrap();
+        }
+        let entries = store.recent_history(10).unwrap();
+        assert_eq!(entries[0].command, "make");
+        assert_eq!(entries[1].host.as_deref(), Some("devbox"));
+        assert_eq!(entries[0].suggested_by.as_deref(), Some("ollama"));
+        assert_eq!(entries[1].suggested_by, None);
+        let between = store.history_between(Some(1), None).unwrap();
+        assert_eq!(between.len(), 1);
+        assert_eq!(
+            store.history_between(None, Some(1)).unwrap()[0].command,
+            "ls"
+        );
+    }
+
+    #[test]
+    fn import_skips_entries_already_stored() {
+        let mut store = Store::open_in_memory(&SystemClock).unwrap();
+        let entry = |command: &str, timestamp| HistoryEntry {
+            command: command.to_string(),
+            cwd: String::new(),
+            source: "zsh-history".to_string(),
+            exit_code: 0,
+            timestamp,
+            session_id: None,
+            host: None,
+            suggested_by: None,
+        };
+        let batch = [entry("ls", 1), entry("ls", 2), entry("make", 2)];
+        assert_eq!(store.import_history(&batch, false).unwrap(), 3);
+        assert_eq!(store.import_history(&batch, false).unwrap(), 0);
+        assert_eq!(store.import_history(&[entry("ls", 3)], false).unwrap(), 1);
+        assert_eq!(store.recent_history(10).unwrap().len(), 4);
+
+        // Made-up times don't matter, the command does
+        let undated = [entry("ls", 50), entry("vim", 51), entry("vim", 52)];
+        assert_eq!(store.import_history(&undated, true).unwrap(), 1);
+        let newest = &store.recent_history(1).unwrap()[0];
+        assert_eq!((newest.command.as_str(), newest.timestamp), ("vim", 52));
+        assert_eq!(store.import_history(&undated, true).unwrap(), 0);
+    }
+
+    #[test]
+    fn upgrade_applies_only_pending_and_backs_up() {
+        let dir = std::env::temp_dir().join(format!("murmur-store-{}", std::process::id()));
+        let path = dir.join("state.db");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut conn = Connection::open(&path).unwrap();
+        assert_eq!(
+            migrate(&mut conn, &MIGRATIONS[..2], Some(&path), 1_000).unwrap(),
+            2
+        );
+        assert!(!backup_path(&path, 0).exists());
+        conn.execute(
+            "INSERT INTO history (command, cwd, source, exit_code, timestamp)
+             VALUES ('ls', '/', 'zsh', 0, 1)",
+            [],
+        )
+        .unwrap();
+
+        let upgraded = [MIGRATIONS, &[NEXT]].concat();
+        assert_eq!(
+            migrate(&mut conn, &upgraded, Some(&path), 2_000).unwrap(),
+            2
+        );
+        assert!(backup_path(&path, 2).exists());
+        assert_eq!(current_version(&conn).unwrap(), 4);
+        let applied_at: i64 = conn
+            .query_row("SELECT MAX(applied_at) FROM schema_version", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(applied_at, 2_000);
+        assert_eq!(
+            migrate(&mut conn, &upgraded, Some(&path), 2_000).unwrap(),
+            0
+        );
+        // Rows from before the suggested_by column read as typed
+        let store = Store { conn };
+        assert_eq!(store.recent_history(1).unwrap()[0].suggested_by, None);
+        let mut conn = store.conn;
+
+        // An older daemon must not touch the newer schema
+        assert!(matches!(
+            migrate(&mut conn, MIGRATIONS, Some(&path), 3_000),
+            Err(StoreError::TooNew { found: 4, .. })
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn failed_migration_rolls_back() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let broken = [
+            Migration {
+                version: 1,
+                description: "ok",
+                sql: "CREATE TABLE a (x INTEGER);",
+            },
+            Migration {
+                version: 2,
+                description: "broken",
+                sql: "CREATE TABLE b (y INTEGER); NOT SQL;",
+            },
+        ];
+        assert!(matches!(
+            migrate(&mut conn, &broken, None, 1_000),
+            Err(StoreError::Migration { version: 2, .. })
+        ));
+        assert_eq!(current_version(&conn).unwrap(), 1);
+        let b_exists: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE name = 'b'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(b_exists, 0);
+    }
+}
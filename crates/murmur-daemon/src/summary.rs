@@ -0,0 +1,285 @@
+//! Rolling activity summaries for long-lived sessions.
+//!
+//! A background job periodically condenses each session's recent commands
+//! (plus its previous summary) into a few lines of prose using a cheap or
+//! local model. Completion prompts then carry the summary instead of a long
+//! raw history, keeping them short while retaining awareness of what the
+//! user has been doing for the last hour.
+
+use murmur_protocol::HistoryEntry;
+use std::collections::HashMap;
+
+use crate::history::CommandHistory;
+
+/// System prompt for the summarization model.
+pub const SUMMARY_SYSTEM_PROMPT: &str =
+    "You condense a developer's terminal activity into a short summary. \
+     Describe what they are working on and which tools, directories and \
+     commands matter, in at most three sentences. Mention failures that \
+     are still unresolved. Output ONLY the summary text.";
+
+/// The latest summary for one session.
+#[derive(Debug, Clone)]
+pub struct SessionSummary {
+    /// Summary text produced by the model.
+    pub text: String,
+    /// Working directory of the newest summarized command.
+    pub cwd: String,
+    /// When the summary was produced (unix seconds).
+    pub updated_at: u64,
+    /// Timestamp of the newest history entry folded into the summary.
+    pub last_entry_at: u64,
+}
+
+/// A session with enough new activity to be (re)summarized.
+#[derive(Debug)]
+pub struct PendingSummary {
+    pub session: String,
+    pub previous: Option<String>,
+    /// New entries, oldest first.
+    pub entries: Vec<HistoryEntry>,
+}
+
+/// Per-session summary store.
+#[derive(Default)]
+pub struct SessionSummaries {
+    sessions: HashMap<String, SessionSummary>,
+}
+
+impl SessionSummaries {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Collect sessions that have at least `min_new` commands newer than
+    /// their last summary, looking back at most `window_secs`.
+    pub fn pending(
+        &self,
+        history: &CommandHistory,
+        now: u64,
+        window_secs: u64,
+        min_new: usize,
+    ) -> Vec<PendingSummary> {
+        let mut grouped: HashMap<String, Vec<HistoryEntry>> = HashMap::new();
+        for entry in history.since(now.saturating_sub(window_secs)) {
+            let key = session_key(entry);
+            let already_summarized = self
+                .sessions
+                .get(&key)
+                .is_some_and(|s| entry.timestamp <= s.last_entry_at);
+            if !already_summarized {
+                grouped.entry(key).or_default().push(entry.clone());
+            }
+        }
+
+        let mut pending: Vec<PendingSummary> = grouped
+            .into_iter()
+            .filter(|(_, entries)| entries.len() >= min_new.max(1))
+            .map(|(session, mut entries)| {
+                entries.reverse();
+                PendingSummary {
+                    previous: self.sessions.get(&session).map(|s| s.text.clone()),
+                    session,
+                    entries,
+                }
+            })
+            .collect();
+        pending.sort_by(|a, b| a.session.cmp(&b.session));
+        pending
+    }
+
+    /// Store a freshly generated summary for a pending session.
+    pub fn update(&mut self, pending: &PendingSummary, text: String, now: u64) {
+        let Some(newest) = pending.entries.last() else {
+            return;
+        };
+        self.sessions.insert(
+            pending.session.clone(),
+            SessionSummary {
+                text,
+                cwd: newest.cwd.clone(),
+                updated_at: now,
+                last_entry_at: newest.timestamp,
+            },
+        );
+    }
+
+    /// The summary for a completion from `session`, if it has a fresh one;
+    /// other sessions' summaries, even from the same directory, aren't
+    /// its business. Summaries older than `window_secs` are ignored.
+    pub fn for_session(&self, session: &str, now: u64, window_secs: u64) -> Option<&str> {
+        self.sessions
+            .get(session)
+            .filter(|s| now.saturating_sub(s.updated_at) <= window_secs)
+            .map(|s| s.text.as_str())
+    }
+
+    /// Pick the most relevant summary for a completion in `cwd` from a
+    /// client without a session: the newest summary from that directory,
+    /// otherwise the newest one overall. Summaries older than
+    /// `window_secs` are ignored.
+    pub fn for_cwd(&self, cwd: &str, now: u64, window_secs: u64) -> Option<&str> {
+        let fresh = self
+            .sessions
+            .values()
+            .filter(|s| now.saturating_sub(s.updated_at) <= window_secs);
+        let (same_dir, other): (Vec<_>, Vec<_>) = fresh.partition(|s| s.cwd == cwd);
+        same_dir
+            .into_iter()
+            .max_by_key(|s| s.updated_at)
+            .or_else(|| other.into_iter().max_by_key(|s| s.updated_at))
+            .map(|s| s.text.as_str())
+    }
+
+    /// Number of sessions with a summary.
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// Whether no summaries exist yet.
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+}
+
+/// Entries without a session ID are grouped by their source tool.
+fn session_key(entry: &HistoryEntry) -> String {
+    entry
+        .session_id
+        .clone()
+        .filter(|id| !id.is_empty())
+        .unwrap_or_else(|| entry.source.clone())
+}
+
+/// Build the user prompt for summarizing a pending session.
+pub fn build_prompt(pending: &PendingSummary) -> String {
+    let mut prompt = String::new();
+    if let Some(ref previous) = pending.previous {
+        prompt.push_str(&format!("Previous summary:\n{previous}\n\n"));
+    }
+    prompt.push_str("New commands (oldest first):\n");
+    for entry in &pending.entries {
+        prompt.push_str(&format!("  [{}] {}", entry.cwd, entry.command));
+        if entry.exit_code != 0 {
+            prompt.push_str(&format!("  (exit {})", entry.exit_code));
+        }
+        prompt.push('\n');
+    }
+    prompt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history_with(commands: &[(&str, &str, Option<&str>)]) -> CommandHistory {
+        let mut history = CommandHistory::new(100);
+        for (command, cwd, session) in commands {
+            history.record_in_session(
+                command.to_string(),
+                cwd.to_string(),
+                "terminal".to_string(),
+                0,
+                session.map(String::from),
+                None,
+            );
+        }
+        history
+    }
+
+    fn now() -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    #[test]
+    fn pending_groups_by_session() {
+        let history = history_with(&[
+            ("cargo build", "/a", Some("s1")),
+            ("cargo test", "/a", Some("s1")),
+            ("ls", "/b", Some("s2")),
+        ]);
+        let summaries = SessionSummaries::new();
+        let pending = summaries.pending(&history, now(), 3600, 2);
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].session, "s1");
+        // Oldest first
+        assert_eq!(pending[0].entries[0].command, "cargo build");
+    }
+
+    #[test]
+    fn summarized_entries_are_not_pending_again() {
+        let history = history_with(&[("make", "/a", None), ("make test", "/a", None)]);
+        let mut summaries = SessionSummaries::new();
+        let t = now();
+        let pending = summaries.pending(&history, t, 3600, 1);
+        summaries.update(&pending[0], "Building with make".to_string(), t);
+        assert!(summaries.pending(&history, t, 3600, 1).is_empty());
+        assert_eq!(summaries.for_cwd("/a", t, 3600), Some("Building with make"));
+    }
+
+    #[test]
+    fn for_cwd_prefers_same_directory() {
+        let history = history_with(&[
+            ("npm test", "/web", Some("w")),
+            ("go test", "/api", Some("g")),
+        ]);
+        let mut summaries = SessionSummaries::new();
+        let t = now();
+        for p in summaries.pending(&history, t, 3600, 1) {
+            let text = format!("working in {}", p.entries[0].cwd);
+            summaries.update(&p, text, t);
+        }
+        assert_eq!(summaries.for_cwd("/web", t, 3600), Some("working in /web"));
+        assert_eq!(summaries.for_cwd("/api", t, 3600), Some("working in /api"));
+        assert!(summaries.for_cwd("/web", t + 7200, 3600).is_none());
+    }
+
+    #[test]
+    fn sessions_in_one_directory_keep_their_own_summary() {
+        let history = history_with(&[
+            ("cargo test", "/repo", Some("left")),
+            ("git rebase -i", "/repo", Some("right")),
+        ]);
+        let mut summaries = SessionSummaries::new();
+        let t = now();
+        for p in summaries.pending(&history, t, 3600, 1) {
+            let text = format!("running {}", p.entries[0].command);
+            summaries.update(&p, text, t);
+        }
+        assert_eq!(
+            summaries.for_session("left", t, 3600),
+            Some("running cargo test")
+        );
+        assert_eq!(
+            summaries.for_session("right", t, 3600),
+            Some("running git rebase -i")
+        );
+        // A session with nothing summarized yet gets no one else's
+        assert_eq!(summaries.for_session("new", t, 3600), None);
+        assert!(summaries.for_session("left", t + 7200, 3600).is_none());
+    }
+
+    #[test]
+    fn prompt_includes_previous_summary_and_failures() {
+        let pending = PendingSummary {
+            session: "s".to_string(),
+            previous: Some("Fixing CI".to_string()),
+            entries: vec![HistoryEntry {
+                command: "cargo test".to_string(),
+                cwd: "/repo".to_string(),
+                source: "terminal".to_string(),
+                exit_code: 101,
+                timestamp: 0,
+                session_id: None,
+                host: None,
+                suggested_by: None,
+            }],
+        };
+        let prompt = build_prompt(&pending);
+        assert!(prompt.contains("Previous summary:\nFixing CI"));
+        assert!(prompt.contains("[/repo] cargo test  (exit 101)"));
+    }
+}
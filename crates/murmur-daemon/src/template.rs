@@ -0,0 +1,214 @@
+//! Collapsing families of near-identical history commands.
+//!
+//! History often holds the same command with one argument changed:
+//! `ssh web1`, `ssh web2`, `ssh web3`. Offered one per line they crowd out
+//! everything else, so commands with the same shape (same words, one
+//! differing argument) become a single suggestion: the most likely value
+//! is pre-filled and the others are listed in `metadata.parameter` for UIs
+//! to cycle through.
+
+use murmur_protocol::{CompletionItem, CompletionOrigin, CompletionParameter};
+
+/// Alternates named in a collapsed suggestion's description.
+const DESCRIBED_ALTERNATES: usize = 3;
+
+struct Family {
+    /// Index of the family's best command in the output.
+    lead: usize,
+    words: Vec<(usize, usize)>,
+    /// Word that varies, once a second member has been found.
+    slot: Option<usize>,
+    alternates: Vec<String>,
+}
+
+/// Collapse history-mined commands in `items` (best first) that differ in
+/// a single argument into one suggestion per family. Other items are kept
+/// as they are, and the order is preserved.
+pub fn collapse(items: Vec<CompletionItem>) -> Vec<CompletionItem> {
+    let mut out: Vec<CompletionItem> = Vec::with_capacity(items.len());
+    let mut families: Vec<Family> = Vec::new();
+
+    for item in items {
+        if item.metadata.origin != Some(CompletionOrigin::History) {
+            out.push(item);
+            continue;
+        }
+        let words = word_spans(&item.text);
+        let member = families.iter_mut().find_map(|family| {
+            let lead = &out[family.lead].text;
+            let slot = differing_word(lead, &family.words, &item.text, &words)?;
+            (family.slot.is_none() || family.slot == Some(slot)).then_some((family, slot))
+        });
+        match member {
+            Some((family, slot)) => {
+                family.slot = Some(slot);
+                let (start, end) = words[slot];
+                family.alternates.push(item.text[start..end].to_string());
+            }
+            None => {
+                families.push(Family {
+                    lead: out.len(),
+                    words,
+                    slot: None,
+                    alternates: Vec::new(),
+                });
+                out.push(item);
+            }
+        }
+    }
+
+    for family in families {
+        let Some(slot) = family.slot else { continue };
+        let (start, end) = family.words[slot];
+        let lead = &mut out[family.lead];
+        let mut named = family
+            .alternates
+            .iter()
+            .take(DESCRIBED_ALTERNATES)
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(", ");
+        if family.alternates.len() > DESCRIBED_ALTERNATES {
+            named.push_str(", …");
+        }
+        lead.description = Some(match lead.description.take() {
+            Some(description) => format!("{description}; or {named}"),
+            None => format!("or {named}"),
+        });
+        lead.metadata.parameter = Some(CompletionParameter {
+            start,
+            end,
+            alternates: family.alternates,
+        });
+    }
+    out
+}
+
+/// Byte ranges of the whitespace-separated words in `text`.
+fn word_spans(text: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        match (c.is_whitespace(), start) {
+            (true, Some(s)) => {
+                spans.push((s, i));
+                start = None;
+            }
+            (false, None) => start = Some(i),
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        spans.push((s, text.len()));
+    }
+    spans
+}
+
+/// The one argument (never the program) in which two commands differ.
+fn differing_word(
+    a: &str,
+    a_words: &[(usize, usize)],
+    b: &str,
+    b_words: &[(usize, usize)],
+) -> Option<usize> {
+    if a_words.len() != b_words.len() || a_words.len() < 2 {
+        return None;
+    }
+    let mut differing = a_words
+        .iter()
+        .zip(b_words)
+        .enumerate()
+        .filter(|(_, (&(s1, e1), &(s2, e2)))| a[s1..e1] != b[s2..e2])
+        .map(|(i, _)| i);
+    match (differing.next(), differing.next()) {
+        (Some(i), None) if i > 0 => Some(i),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use murmur_protocol::{CompletionKind, CompletionMetadata};
+
+    fn item(text: &str, origin: CompletionOrigin, score: f64) -> CompletionItem {
+        CompletionItem {
+            text: text.to_string(),
+            description: None,
+            kind: CompletionKind::Command,
+            score,
+            metadata: CompletionMetadata {
+                origin: Some(origin),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn families_collapse_to_the_best_value() {
+        let items = vec![
+            item("ssh web2", CompletionOrigin::History, 0.5),
+            item("git push", CompletionOrigin::Context, 0.45),
+            item("ssh web1", CompletionOrigin::History, 0.3),
+            item("cargo test", CompletionOrigin::History, 0.25),
+            item("ssh web3", CompletionOrigin::History, 0.2),
+        ];
+        let collapsed = collapse(items);
+        let texts: Vec<&str> = collapsed.iter().map(|i| i.text.as_str()).collect();
+        assert_eq!(texts, ["ssh web2", "git push", "cargo test"]);
+        assert_eq!(collapsed[0].description.as_deref(), Some("or web1, web3"));
+        assert_eq!(
+            collapsed[0].metadata.parameter,
+            Some(CompletionParameter {
+                start: 4,
+                end: 8,
+                alternates: vec!["web1".to_string(), "web3".to_string()],
+            })
+        );
+        assert_eq!(collapsed[2].metadata.parameter, None);
+    }
+
+    #[test]
+    fn only_one_slot_per_family() {
+        let items = vec![
+            item("kubectl logs -n prod api", CompletionOrigin::History, 0.5),
+            item(
+                "kubectl logs -n prod worker",
+                CompletionOrigin::History,
+                0.4,
+            ),
+            // Differs from the lead in another word: its own suggestion
+            item("kubectl logs -n dev api", CompletionOrigin::History, 0.3),
+            // Differs in the program, which is never a parameter
+            item("kubectx logs -n prod api", CompletionOrigin::History, 0.2),
+        ];
+        let collapsed = collapse(items);
+        assert_eq!(collapsed.len(), 3);
+        let parameter = collapsed[0].metadata.parameter.as_ref().unwrap();
+        assert_eq!(&collapsed[0].text[parameter.start..parameter.end], "api");
+        assert_eq!(parameter.alternates, ["worker"]);
+    }
+
+    #[test]
+    fn long_families_are_summarised() {
+        let items = (1..=6)
+            .map(|n| item(&format!("ssh web{n}"), CompletionOrigin::History, 0.1))
+            .collect();
+        let collapsed = collapse(items);
+        assert_eq!(collapsed.len(), 1);
+        assert_eq!(
+            collapsed[0].description.as_deref(),
+            Some("or web2, web3, web4, …")
+        );
+        assert_eq!(
+            collapsed[0]
+                .metadata
+                .parameter
+                .as_ref()
+                .unwrap()
+                .alternates
+                .len(),
+            5
+        );
+    }
+}
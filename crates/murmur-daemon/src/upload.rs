@@ -0,0 +1,279 @@
+//! Chunked audio uploads.
+//!
+//! Long recordings are too big for a single `voice/process` line, so
+//! clients can send them in pieces: `voice/upload_begin` declares the size
+//! (and optionally a CRC-32), `voice/upload_chunk` appends in order, and
+//! `voice/upload_end` hands the assembled WAV to the voice pipeline.
+//! Uploads are held in memory, so sizes, chunk sizes and the number in
+//! flight are all capped, and abandoned uploads expire. IDs are random, so
+//! one client can't add to or take another's upload by guessing its ID.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// Largest recording accepted: about 30 minutes of 16 kHz mono audio.
+pub const MAX_UPLOAD_BYTES: usize = 64 * 1024 * 1024;
+
+/// Largest chunk accepted, before base64.
+pub const MAX_CHUNK_BYTES: usize = 1024 * 1024;
+
+/// Uploads in progress at once.
+const MAX_PENDING: usize = 4;
+
+/// An upload with no activity for this long is dropped.
+pub const IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum UploadError {
+    #[error("Upload of {0} bytes exceeds the {MAX_UPLOAD_BYTES}-byte limit")]
+    TooLarge(usize),
+
+    #[error("Too many uploads in progress")]
+    TooMany,
+
+    #[error("Unknown or expired upload {0}")]
+    Unknown(u64),
+
+    #[error("Chunk of {0} bytes exceeds the {MAX_CHUNK_BYTES}-byte limit")]
+    ChunkTooLarge(usize),
+
+    #[error("Chunk at offset {got}, expected {expected}")]
+    OutOfOrder { expected: usize, got: usize },
+
+    #[error("Chunk runs past the declared {0} bytes")]
+    Overflow(usize),
+
+    #[error("Checksum mismatch")]
+    Checksum,
+
+    #[error("Upload incomplete: {received} of {total} bytes")]
+    Incomplete { received: usize, total: usize },
+
+    #[error("Failed to pick an upload ID: {0}")]
+    NoId(String),
+}
+
+struct Upload {
+    data: Vec<u8>,
+    total: usize,
+    crc32: Option<u32>,
+    last_active: Instant,
+}
+
+/// Uploads in progress, by ID.
+#[derive(Default)]
+pub struct Uploads {
+    pending: HashMap<u64, Upload>,
+}
+
+impl Uploads {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start an upload of `total` bytes at `now`, returning its ID.
+    pub fn begin(
+        &mut self,
+        total: usize,
+        crc32: Option<u32>,
+        now: Instant,
+    ) -> Result<u64, UploadError> {
+        if total > MAX_UPLOAD_BYTES {
+            return Err(UploadError::TooLarge(total));
+        }
+        self.expire(now);
+        if self.pending.len() >= MAX_PENDING {
+            return Err(UploadError::TooMany);
+        }
+
+        let id = loop {
+            let id = random_id().map_err(|e| UploadError::NoId(e.to_string()))?;
+            if id != 0 && !self.pending.contains_key(&id) {
+                break id;
+            }
+        };
+        self.pending.insert(
+            id,
+            Upload {
+                data: Vec::new(),
+                total,
+                crc32,
+                last_active: now,
+            },
+        );
+        Ok(id)
+    }
+
+    /// Drop uploads idle for `IDLE_TIMEOUT` at `now`, returning how many.
+    pub fn expire(&mut self, now: Instant) -> usize {
+        let before = self.pending.len();
+        self.pending
+            .retain(|_, upload| now.duration_since(upload.last_active) < IDLE_TIMEOUT);
+        before - self.pending.len()
+    }
+
+    /// Append a chunk, returning the bytes received so far. A rejected
+    /// chunk leaves the upload as it was, so it can be sent again.
+    pub fn chunk(
+        &mut self,
+        id: u64,
+        offset: usize,
+        data: &[u8],
+        crc32: Option<u32>,
+        now: Instant,
+    ) -> Result<usize, UploadError> {
+        if data.len() > MAX_CHUNK_BYTES {
+            return Err(UploadError::ChunkTooLarge(data.len()));
+        }
+        self.expire(now);
+        let upload = self.pending.get_mut(&id).ok_or(UploadError::Unknown(id))?;
+        if offset != upload.data.len() {
+            return Err(UploadError::OutOfOrder {
+                expected: upload.data.len(),
+                got: offset,
+            });
+        }
+        if offset + data.len() > upload.total {
+            return Err(UploadError::Overflow(upload.total));
+        }
+        if crc32.is_some_and(|crc| crc != crc32fast::hash(data)) {
+            return Err(UploadError::Checksum);
+        }
+
+        upload.data.extend_from_slice(data);
+        upload.last_active = now;
+        Ok(upload.data.len())
+    }
+
+    /// Take the finished audio. An incomplete upload stays open for the
+    /// missing chunks; one that fails its checksum is dropped.
+    pub fn finish(&mut self, id: u64, now: Instant) -> Result<Vec<u8>, UploadError> {
+        self.expire(now);
+        let upload = self.pending.get(&id).ok_or(UploadError::Unknown(id))?;
+        if upload.data.len() < upload.total {
+            return Err(UploadError::Incomplete {
+                received: upload.data.len(),
+                total: upload.total,
+            });
+        }
+
+        let upload = self.pending.remove(&id).expect("checked above");
+        if upload
+            .crc32
+            .is_some_and(|crc| crc != crc32fast::hash(&upload.data))
+        {
+            return Err(UploadError::Checksum);
+        }
+        Ok(upload.data)
+    }
+}
+
+/// An ID nobody can predict.
+fn random_id() -> std::io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    std::io::Read::read_exact(&mut std::fs::File::open("/dev/urandom")?, &mut bytes)?;
+    Ok(u64::from_ne_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_assemble_in_order() {
+        let audio: Vec<u8> = (0..=255).cycle().take(2500).collect();
+        let now = Instant::now();
+        let mut uploads = Uploads::new();
+        let id = uploads
+            .begin(audio.len(), Some(crc32fast::hash(&audio)), now)
+            .unwrap();
+
+        let mut offset = 0;
+        for chunk in audio.chunks(1000) {
+            // A resent or skipped chunk is refused without losing progress
+            if offset > 0 {
+                assert!(uploads.chunk(id, 0, chunk, None, now).is_err());
+            }
+            let bad_crc = Some(crc32fast::hash(chunk) ^ 1);
+            assert_eq!(
+                uploads.chunk(id, offset, chunk, bad_crc, now),
+                Err(UploadError::Checksum)
+            );
+            offset = uploads
+                .chunk(id, offset, chunk, Some(crc32fast::hash(chunk)), now)
+                .unwrap();
+        }
+        assert_eq!(uploads.finish(id, now).unwrap(), audio);
+        assert_eq!(uploads.finish(id, now), Err(UploadError::Unknown(id)));
+    }
+
+    #[test]
+    fn sizes_are_enforced() {
+        let now = Instant::now();
+        let mut uploads = Uploads::new();
+        assert_eq!(
+            uploads.begin(MAX_UPLOAD_BYTES + 1, None, now),
+            Err(UploadError::TooLarge(MAX_UPLOAD_BYTES + 1))
+        );
+
+        let id = uploads.begin(10, None, now).unwrap();
+        assert_eq!(
+            uploads.chunk(id, 0, &[0; 11], None, now),
+            Err(UploadError::Overflow(10))
+        );
+        uploads.chunk(id, 0, &[0; 6], None, now).unwrap();
+        assert_eq!(
+            uploads.finish(id, now),
+            Err(UploadError::Incomplete {
+                received: 6,
+                total: 10
+            })
+        );
+        uploads.chunk(id, 6, &[0; 4], None, now).unwrap();
+        assert!(uploads.finish(id, now).is_ok());
+    }
+
+    #[test]
+    fn whole_file_checksum_is_verified() {
+        let now = Instant::now();
+        let mut uploads = Uploads::new();
+        let id = uploads
+            .begin(3, Some(crc32fast::hash(b"abc")), now)
+            .unwrap();
+        uploads.chunk(id, 0, b"abd", None, now).unwrap();
+        assert_eq!(uploads.finish(id, now), Err(UploadError::Checksum));
+    }
+
+    #[test]
+    fn abandoned_uploads_expire() {
+        let now = Instant::now();
+        let mut uploads = Uploads::new();
+        for _ in 0..MAX_PENDING {
+            uploads.begin(10, None, now).unwrap();
+        }
+        assert_eq!(uploads.begin(10, None, now), Err(UploadError::TooMany));
+
+        let later = now + IDLE_TIMEOUT;
+        let id = uploads.begin(10, None, later).unwrap();
+        assert_eq!(uploads.pending.len(), 1);
+        assert!(uploads.chunk(id, 0, b"x", None, later).is_ok());
+
+        // Without anyone calling, the daemon's timer drops it too
+        assert_eq!(uploads.expire(later + IDLE_TIMEOUT), 1);
+        assert_eq!(
+            uploads.chunk(id, 1, b"x", None, later + IDLE_TIMEOUT),
+            Err(UploadError::Unknown(id))
+        );
+    }
+
+    #[test]
+    fn ids_are_not_sequential() {
+        let now = Instant::now();
+        let mut uploads = Uploads::new();
+        let first = uploads.begin(10, None, now).unwrap();
+        let second = uploads.begin(10, None, now).unwrap();
+        assert_ne!(second, first + 1);
+        assert!(uploads.chunk(first + 1, 0, b"x", None, now).is_err());
+    }
+}
@@ -0,0 +1,184 @@
+//! Checks that a suggested command's program exists on this machine.
+//!
+//! Models sometimes suggest tools that aren't installed. Only the first
+//! program of the command line is checked: shell builtins and keywords
+//! pass, paths must point at an executable, and anything else must be on
+//! `PATH`. Aliases and functions defined in the user's shell are invisible
+//! to the daemon and come out unverified.
+//!
+//! [`ShellValidator`] applies the same check to voice commands, plus a
+//! syntax check by the user's shell.
+
+use async_trait::async_trait;
+use murmur_context::lexer;
+use murmur_voice::CommandValidator;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+
+/// Builtins and keywords common to zsh, bash and fish.
+const SHELL_BUILTINS: &[&str] = &[
+    ".", ":", "[", "[[", "alias", "bg", "builtin", "case", "cd", "command", "declare", "echo",
+    "eval", "exec", "exit", "export", "false", "fg", "for", "function", "history", "if", "jobs",
+    "kill", "let", "local", "popd", "printf", "pushd", "pwd", "read", "set", "source", "test",
+    "time", "trap", "true", "type", "ulimit", "umask", "unalias", "unset", "wait", "while",
+];
+
+/// Commands that run the next word as the actual program.
+const WRAPPERS: &[&str] = &[
+    "sudo", "doas", "env", "nohup", "nice", "time", "command", "exec",
+];
+
+/// Whether the program `command` runs can be found, resolving relative
+/// paths against `cwd`. `None` if there is no program to check.
+pub fn program_available(command: &str, cwd: &str) -> Option<bool> {
+    let program = program(command)?;
+    let program = program.as_str();
+    if SHELL_BUILTINS.contains(&program) {
+        return Some(true);
+    }
+    if program.contains('/') {
+        let path = match program.strip_prefix("~/") {
+            Some(rest) => PathBuf::from(std::env::var_os("HOME")?).join(rest),
+            None => Path::new(cwd).join(program),
+        };
+        return Some(is_executable(&path));
+    }
+    Some(on_path(program))
+}
+
+/// The program a command line starts with, past `VAR=value` assignments
+/// and wrappers like `sudo`. A wrapper given options is returned itself,
+/// since its options may take arguments.
+fn program(command: &str) -> Option<String> {
+    let first = lexer::commands(command).into_iter().next()?;
+    let mut words = first
+        .without_assignments()
+        .iter()
+        .map(String::as_str)
+        .peekable();
+    let mut program = words.next()?;
+    while WRAPPERS.contains(&program) {
+        match words.peek() {
+            Some(next) if !next.starts_with('-') => {
+                program = words.next()?;
+                // env takes assignments before the program
+                while lexer::is_assignment(program) {
+                    program = words.next()?;
+                }
+            }
+            _ => break,
+        }
+    }
+    Some(program.to_string())
+}
+
+/// Rejects restructured voice commands whose program isn't available or
+/// that the shell can't parse (`-n` reads without running). An alias the
+/// daemon can't see is rejected too, which costs a retry at worst.
+pub struct ShellValidator;
+
+#[async_trait]
+impl CommandValidator for ShellValidator {
+    async fn validate(&self, command: &str, cwd: &str, shell: Option<&str>) -> Result<(), String> {
+        if lexer::commands(command).is_empty() {
+            return Err("it's empty".to_string());
+        }
+        if program_available(command, cwd) == Some(false) {
+            let program = program(command).unwrap_or_default();
+            return Err(format!("`{program}` isn't a builtin or installed on PATH"));
+        }
+        syntax_error(command, shell).await.map_or(Ok(()), Err)
+    }
+}
+
+/// What `shell` (sh when unknown) says is wrong with `command`'s syntax.
+/// `None` if it parses, or the shell can't be run to check.
+async fn syntax_error(command: &str, shell: Option<&str>) -> Option<String> {
+    let shell = shell
+        .filter(|s| ["zsh", "bash", "fish"].contains(s))
+        .unwrap_or("sh");
+    let check = tokio::process::Command::new(shell)
+        .args(["-n", "-c", command])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .output();
+    let output = tokio::time::timeout(Duration::from_secs(2), check)
+        .await
+        .ok()?
+        .ok()?;
+    if output.status.success() {
+        return None;
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let problem = stderr.lines().find(|l| !l.trim().is_empty()).unwrap_or("");
+    Some(match problem.trim() {
+        "" => "it doesn't parse".to_string(),
+        problem => problem.to_string(),
+    })
+}
+
+fn on_path(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).any(|dir| is_executable(&dir.join(program))))
+        .unwrap_or(false)
+}
+
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_program_behind_wrappers() {
+        assert_eq!(
+            program("RUST_LOG=debug cargo run").as_deref(),
+            Some("cargo")
+        );
+        assert_eq!(program("sudo apt install jq").as_deref(), Some("apt"));
+        assert_eq!(program("sudo -u postgres psql").as_deref(), Some("sudo"));
+        assert_eq!(program("env FOO=1 make test").as_deref(), Some("make"));
+        assert_eq!(program("(cd src && ls)").as_deref(), Some("cd"));
+        assert_eq!(program("'my tool' --help").as_deref(), Some("my tool"));
+        assert_eq!(program("! grep -q x f").as_deref(), Some("grep"));
+        assert_eq!(program("  "), None);
+    }
+
+    #[test]
+    fn checks_builtins_paths_and_path() {
+        assert_eq!(program_available("cd /tmp", "/"), Some(true));
+        assert_eq!(program_available("sh -c true", "/"), Some(true));
+        assert_eq!(
+            program_available("./no-such-script.sh", "/tmp"),
+            Some(false)
+        );
+        assert_eq!(
+            program_available("definitely-not-installed-murmur-tool --help", "/"),
+            Some(false)
+        );
+        assert_eq!(program_available("", "/"), None);
+    }
+
+    #[tokio::test]
+    async fn validates_voice_commands() {
+        let validator = ShellValidator;
+        assert_eq!(validator.validate("ls -la", "/", Some("sh")).await, Ok(()));
+        let missing = validator
+            .validate("definitely-not-installed-murmur-tool", "/", None)
+            .await;
+        assert!(missing.unwrap_err().contains("isn't a builtin"));
+        assert!(validator
+            .validate("echo 'unbalanced", "/", None)
+            .await
+            .is_err());
+        assert!(validator.validate("  ", "/", None).await.is_err());
+    }
+}
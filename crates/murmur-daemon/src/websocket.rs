@@ -0,0 +1,160 @@
+//! WebSocket transport (`[daemon] listen_ws`).
+//!
+//! Clients that can't open a Unix socket or a raw TCP stream (browser
+//! extensions, editors with only a WebSocket client) connect here. Each
+//! text or binary message from the client is one JSON-RPC message, and
+//! each line the daemon writes goes back as a text message. `accept` does
+//! the handshake (tungstenite handles the protocol) and returns an
+//! in-memory stream carrying that line protocol, so the connection is
+//! handled like any other.
+//!
+//! Browsers send an `Origin` with every WebSocket handshake, and any page
+//! can aim one at a loopback address. Handshakes with an `Origin` are
+//! refused unless it is in `ws_origins`; other clients don't send one.
+
+use futures_util::{Sink, SinkExt, Stream, StreamExt};
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, DuplexStream};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::handshake::server::{
+    Callback, ErrorResponse, Request, Response,
+};
+use tokio_tungstenite::tungstenite::http::{header, StatusCode};
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tokio_tungstenite::tungstenite::{Error, Message};
+use tracing::debug;
+
+/// Longest a client may take to send its handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Bytes buffered between the socket and the connection handler.
+const BRIDGE_BUFFER: usize = 64 * 1024;
+
+/// Complete the handshake on `stream` and bridge it to a line stream.
+/// Messages over `max_message` bytes close the connection.
+pub async fn accept(
+    stream: TcpStream,
+    origins: &[String],
+    max_message: usize,
+) -> io::Result<DuplexStream> {
+    let config = WebSocketConfig {
+        max_message_size: Some(max_message),
+        max_frame_size: Some(max_message),
+        ..WebSocketConfig::default()
+    };
+    let handshake =
+        tokio_tungstenite::accept_hdr_async_with_config(stream, OriginCheck(origins), Some(config));
+    let socket = tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake)
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "handshake timed out"))?
+        .map_err(io::Error::other)?;
+
+    let (ours, theirs) = tokio::io::duplex(BRIDGE_BUFFER);
+    let (from_daemon, to_daemon) = tokio::io::split(ours);
+    let (outgoing, incoming) = socket.split();
+    tokio::spawn(forward_messages(incoming, to_daemon));
+    tokio::spawn(forward_lines(from_daemon, outgoing));
+    Ok(theirs)
+}
+
+/// Refuses handshakes from browser origins not in `ws_origins`.
+struct OriginCheck<'a>(&'a [String]);
+
+impl Callback for OriginCheck<'_> {
+    fn on_request(self, request: &Request, response: Response) -> Result<Response, ErrorResponse> {
+        let origin = request
+            .headers()
+            .get(header::ORIGIN)
+            .map(|origin| origin.to_str().unwrap_or_default());
+        if origin_allowed(origin, self.0) {
+            return Ok(response);
+        }
+        let mut refused = ErrorResponse::new(Some("origin not in ws_origins".to_string()));
+        *refused.status_mut() = StatusCode::FORBIDDEN;
+        Err(refused)
+    }
+}
+
+/// Whether a handshake with this `Origin` header may proceed.
+fn origin_allowed(origin: Option<&str>, origins: &[String]) -> bool {
+    origin.map_or(true, |origin| {
+        origins.iter().any(|allowed| allowed == origin)
+    })
+}
+
+/// Pass the client's messages to the daemon as lines. Shuts `to_daemon`
+/// when the client goes, so the handler sees the end of its input.
+async fn forward_messages<S, W>(mut incoming: S, mut to_daemon: W)
+where
+    S: Stream<Item = Result<Message, Error>> + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    while let Some(message) = incoming.next().await {
+        let mut message = match message {
+            Ok(Message::Text(text)) => text.into_bytes(),
+            Ok(Message::Binary(bytes)) => bytes,
+            Ok(Message::Close(_)) => break,
+            // Pings are answered by tungstenite
+            Ok(_) => continue,
+            Err(e) => {
+                debug!(error = %e, "Closing WebSocket connection");
+                break;
+            }
+        };
+        // JSON only has newlines between tokens, so a pretty-printed
+        // message can be flattened into one line
+        for byte in message.iter_mut().filter(|b| matches!(b, b'\n' | b'\r')) {
+            *byte = b' ';
+        }
+        message.push(b'\n');
+        if to_daemon.write_all(&message).await.is_err() {
+            break;
+        }
+    }
+    let _ = to_daemon.shutdown().await;
+}
+
+/// Send each line the daemon writes as a text message, and close once it
+/// is done with the connection.
+async fn forward_lines<R, S>(from_daemon: R, mut outgoing: S)
+where
+    R: AsyncRead + Unpin,
+    S: Sink<Message, Error = Error> + Unpin,
+{
+    let mut lines = BufReader::new(from_daemon).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if outgoing.send(Message::Text(line)).await.is_err() {
+            return;
+        }
+    }
+    let _ = outgoing.close().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn browsers_need_an_allowed_origin() {
+        assert!(origin_allowed(None, &[]));
+        assert!(!origin_allowed(Some("https://example.com"), &[]));
+        let allowed = ["https://example.com".to_string()];
+        assert!(origin_allowed(Some("https://example.com"), &allowed));
+        assert!(!origin_allowed(Some("https://example.com.evil"), &allowed));
+    }
+
+    #[tokio::test]
+    async fn messages_become_lines() {
+        let messages = vec![
+            Ok(Message::Text("{\n  \"id\": 1\n}".to_string())),
+            Ok(Message::Ping(vec![1])),
+            Ok(Message::Binary(b"{\"id\":2}".to_vec())),
+            Ok(Message::Close(None)),
+            Ok(Message::Text("{\"id\":3}".to_string())),
+        ];
+        let mut lines = Vec::new();
+        forward_messages(futures_util::stream::iter(messages), &mut lines).await;
+        assert_eq!(lines, b"{   \"id\": 1 }\n{\"id\":2}\n");
+    }
+}
@@ -1,6 +1,9 @@
-use murmur_daemon::config::{Config, DaemonConfig};
+use murmur_daemon::clock::ManualClock;
+use murmur_daemon::config::{Config, DaemonConfig, HeuristicsMode};
 use murmur_daemon::server::Server;
 use murmur_protocol::*;
+use murmur_providers::ProviderConfig;
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::net::UnixStream;
@@ -12,6 +15,14 @@ fn test_config(socket_path: &str) -> Config {
             socket_path: socket_path.to_string(),
             cache_size: 100,
             log_level: "warn".to_string(),
+            state_path: None,
+            health_check_interval_secs: 0,
+            health_listen: None,
+            listen_tcp: None,
+            listen_ws: None,
+            ws_origins: Vec::new(),
+            tokens: Vec::new(),
+            ..DaemonConfig::default()
         },
         ..Config::default()
     }
@@ -64,10 +75,346 @@ async fn test_status_request() {
     assert_eq!(result["status"], "running");
     assert!(result["cache_entries"].is_number());
 
+    // Only this user may connect
+    use std::os::unix::fs::PermissionsExt;
+    let mode = std::fs::metadata(&socket).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+
     // Clean up
     let _ = std::fs::remove_file(&socket);
 }
 
+#[tokio::test]
+async fn test_status_reports_restricted_mode() {
+    let socket = format!("/tmp/murmur-test-restricted-{}.sock", std::process::id());
+    let mut config = test_config(&socket);
+    config.policy.allowed_prefixes = vec!["git status".to_string()];
+
+    start_test_server(config).await;
+
+    let response = send_request(&socket, methods::STATUS, None).await;
+    let result = response.result.unwrap();
+    assert_eq!(result["restricted"], true);
+    assert_eq!(result["policy_violations"], 0);
+
+    let _ = std::fs::remove_file(&socket);
+}
+
+#[tokio::test]
+async fn test_status_reports_provider_health() {
+    let socket = format!("/tmp/murmur-test-health-{}.sock", std::process::id());
+    let mut config = test_config(&socket);
+    config.daemon.health_check_interval_secs = 60;
+    // Nothing listens on the discard port
+    config.providers.insert(
+        "openai".to_string(),
+        ProviderConfig {
+            api_key: Some("sk-test".to_string()),
+            api_key_header: None,
+            api: None,
+            command: None,
+            proxy: None,
+            model: None,
+            endpoint: Some("http://127.0.0.1:9/v1/chat/completions".to_string()),
+            enabled: true,
+            timeout_ms: 2000,
+            max_retries: 0,
+            backoff_ms: 200,
+            retry_on_429: true,
+        },
+    );
+    start_test_server(config).await;
+
+    // The first check runs at startup
+    let mut health = serde_json::Value::Null;
+    for _ in 0..50 {
+        let response = send_request(&socket, methods::STATUS, None).await;
+        health = response.result.unwrap()["provider_health"]["openai"].clone();
+        if !health.is_null() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(health["ok"], false);
+    assert!(health["error"]
+        .as_str()
+        .unwrap()
+        .contains("HTTP request failed"));
+    assert!(health["latency_ms"].is_number());
+
+    let _ = std::fs::remove_file(&socket);
+}
+
+#[tokio::test]
+async fn test_compressed_connection() {
+    use murmur_daemon::compression;
+
+    let socket = format!("/tmp/murmur-test-compress-{}.sock", std::process::id());
+    start_test_server(test_config(&socket)).await;
+
+    let stream = UnixStream::connect(&socket).await.unwrap();
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+
+    let negotiate = JsonRpcRequest::new(
+        methods::COMPRESSION_NEGOTIATE,
+        Some(serde_json::json!({"algorithms": ["brotli", "gzip"]})),
+        RequestId::Number(1),
+    );
+    let json = serde_json::to_string(&negotiate).unwrap();
+    writer.write_all(json.as_bytes()).await.unwrap();
+    writer.write_all(b"\n").await.unwrap();
+    let mut line = String::new();
+    reader.read_line(&mut line).await.unwrap();
+    let response: JsonRpcResponse = serde_json::from_str(&line).unwrap();
+    // Unknown algorithms are a parse error, not silently skipped
+    assert!(response.error.is_some());
+
+    let negotiate = JsonRpcRequest::new(
+        methods::COMPRESSION_NEGOTIATE,
+        Some(serde_json::json!({"algorithms": ["gzip", "zstd"]})),
+        RequestId::Number(2),
+    );
+    let json = serde_json::to_string(&negotiate).unwrap();
+    writer.write_all(json.as_bytes()).await.unwrap();
+    writer.write_all(b"\n").await.unwrap();
+    line.clear();
+    reader.read_line(&mut line).await.unwrap();
+    let response: JsonRpcResponse = serde_json::from_str(&line).unwrap();
+    assert_eq!(response.result.unwrap()["algorithm"], "gzip");
+
+    // Everything after that is compressed frames
+    let status = JsonRpcRequest::new(methods::STATUS, None, RequestId::Number(3));
+    let json = serde_json::to_string(&status).unwrap();
+    compression::write_frame(&mut writer, Compression::Gzip, &json)
+        .await
+        .unwrap();
+    let message = compression::read_frame(&mut reader, Compression::Gzip, 1 << 20)
+        .await
+        .unwrap()
+        .unwrap();
+    let response: JsonRpcResponse = serde_json::from_str(&message).unwrap();
+    assert_eq!(response.result.unwrap()["status"], "running");
+
+    let _ = std::fs::remove_file(&socket);
+}
+
+/// Write one JSON-RPC request line.
+async fn write_request(writer: &mut tokio::net::unix::OwnedWriteHalf, request: &JsonRpcRequest) {
+    let json = serde_json::to_string(request).unwrap();
+    writer.write_all(json.as_bytes()).await.unwrap();
+    writer.write_all(b"\n").await.unwrap();
+}
+
+/// Read one JSON-RPC response line.
+async fn read_response(
+    reader: &mut BufReader<tokio::net::unix::OwnedReadHalf>,
+) -> Option<JsonRpcResponse> {
+    let mut line = String::new();
+    reader.read_line(&mut line).await.unwrap();
+    serde_json::from_str(&line).ok()
+}
+
+#[tokio::test]
+async fn test_binary_audio_frames() {
+    use murmur_daemon::frame;
+
+    let socket = format!("/tmp/murmur-test-binary-audio-{}.sock", std::process::id());
+    start_test_server(test_config(&socket)).await;
+    let voice = |id| {
+        JsonRpcRequest::new(
+            methods::VOICE_PROCESS,
+            Some(serde_json::json!({"audio_frame": true, "mode": "command", "cwd": "/tmp"})),
+            RequestId::Number(id),
+        )
+    };
+
+    let stream = UnixStream::connect(&socket).await.unwrap();
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+
+    let initialize = JsonRpcRequest::new(
+        methods::INITIALIZE,
+        Some(serde_json::json!({"binary_audio": true})),
+        RequestId::Number(1),
+    );
+    write_request(&mut writer, &initialize).await;
+    let response = read_response(&mut reader).await.unwrap();
+    assert_eq!(response.result.unwrap()["binary_audio"], true);
+
+    // The frame is consumed (voice is disabled in the test config), so the
+    // connection stays usable
+    write_request(&mut writer, &voice(2)).await;
+    frame::write(&mut writer, &[0u8; 4096]).await.unwrap();
+    let response = read_response(&mut reader).await.unwrap();
+    assert!(response.error.unwrap().message.contains("disabled"));
+
+    write_request(
+        &mut writer,
+        &JsonRpcRequest::new(methods::STATUS, None, RequestId::Number(3)),
+    )
+    .await;
+    let response = read_response(&mut reader).await.unwrap();
+    assert_eq!(response.result.unwrap()["status"], "running");
+
+    // Without initialize, a frame can't be told apart from the next request
+    let stream = UnixStream::connect(&socket).await.unwrap();
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    write_request(&mut writer, &voice(1)).await;
+    let response = read_response(&mut reader).await.unwrap();
+    assert_eq!(response.error.unwrap().code, INVALID_REQUEST);
+    assert!(read_response(&mut reader).await.is_none());
+
+    let _ = std::fs::remove_file(&socket);
+}
+
+#[tokio::test]
+async fn test_initialize_reports_versions_and_methods() {
+    let socket = format!("/tmp/murmur-test-initialize-{}.sock", std::process::id());
+    start_test_server(test_config(&socket)).await;
+
+    let response = send_request(
+        &socket,
+        methods::INITIALIZE,
+        Some(serde_json::json!({"protocol_version": PROTOCOL_VERSION, "client": "test/1"})),
+    )
+    .await;
+    let features: InitializeResponse = serde_json::from_value(response.result.unwrap()).unwrap();
+    assert_eq!(features.protocol_version, PROTOCOL_VERSION);
+    assert_eq!(features.daemon_version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(features.supports(methods::CRON_SUGGEST), Some(true));
+    assert_eq!(features.supports(methods::VOICE_STOP), Some(false));
+    assert!(features
+        .notifications
+        .iter()
+        .any(|n| n == methods::HISTORY_UPDATED));
+
+    // Everything advertised is really handled
+    for method in &features.methods {
+        if [methods::SHUTDOWN, methods::COMPRESSION_NEGOTIATE].contains(&method.as_str()) {
+            continue;
+        }
+        let response = send_request(&socket, method, None).await;
+        if let Some(error) = response.error {
+            assert_ne!(error.code, METHOD_NOT_FOUND, "{method} isn't handled");
+        }
+    }
+
+    let _ = std::fs::remove_file(&socket);
+}
+
+#[tokio::test]
+async fn test_sessions_follow_their_connection() {
+    let socket = format!("/tmp/murmur-test-session-{}.sock", std::process::id());
+    start_test_server(test_config(&socket)).await;
+
+    let stream = UnixStream::connect(&socket).await.unwrap();
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    let open = JsonRpcRequest::new(
+        methods::SESSION_OPEN,
+        Some(serde_json::json!({"shell_pid": std::process::id(), "shell": "zsh", "cwd": "/tmp"})),
+        RequestId::Number(1),
+    );
+    write_request(&mut writer, &open).await;
+    let opened: SessionOpenResponse =
+        serde_json::from_value(read_response(&mut reader).await.unwrap().result.unwrap()).unwrap();
+
+    // Recorded without naming the session, on the connection that opened it
+    let update = JsonRpcRequest::new(
+        methods::CONTEXT_UPDATE,
+        Some(serde_json::json!({"source": "zsh", "command": "make", "cwd": "/tmp"})),
+        RequestId::Number(2),
+    );
+    write_request(&mut writer, &update).await;
+    assert!(read_response(&mut reader).await.unwrap().error.is_none());
+
+    let response = send_request(&socket, methods::HISTORY_LIST, None).await;
+    let entries = response.result.unwrap();
+    assert_eq!(entries[0]["session_id"], opened.session_id.as_str());
+
+    // Other connections name their own, and get one started for it
+    let update = serde_json::json!({
+        "source": "bash", "command": "ls", "cwd": "/", "session_id": "bash-1"
+    });
+    send_request(&socket, methods::CONTEXT_UPDATE, Some(update)).await;
+    let status = send_request(&socket, methods::STATUS, None).await;
+    assert_eq!(status.result.unwrap()["sessions"], 2);
+
+    let _ = std::fs::remove_file(&socket);
+}
+
+#[tokio::test]
+async fn test_chunked_voice_upload() {
+    use base64::Engine;
+
+    let socket = format!("/tmp/murmur-test-upload-{}.sock", std::process::id());
+    let mut config = test_config(&socket);
+    config.voice.enabled = true;
+    start_test_server(config).await;
+    let chunk = |upload_id: u64, offset: usize, data: &[u8]| {
+        serde_json::json!({
+            "upload_id": upload_id,
+            "offset": offset,
+            "data": base64::engine::general_purpose::STANDARD.encode(data),
+            "crc32": crc32fast::hash(data),
+        })
+    };
+
+    let response = send_request(
+        &socket,
+        methods::VOICE_UPLOAD_BEGIN,
+        Some(serde_json::json!({"total_bytes": 8, "crc32": 1234})),
+    )
+    .await;
+    let result = response.result.unwrap();
+    let upload_id = result["upload_id"].as_u64().unwrap();
+    assert_eq!(result["max_chunk_bytes"], 1024 * 1024);
+
+    let response = send_request(
+        &socket,
+        methods::VOICE_UPLOAD_CHUNK,
+        Some(chunk(upload_id, 4, b"abcd")),
+    )
+    .await;
+    assert!(response.error.unwrap().message.contains("expected 0"));
+
+    let response = send_request(
+        &socket,
+        methods::VOICE_UPLOAD_CHUNK,
+        Some(chunk(upload_id, 0, b"abcd")),
+    )
+    .await;
+    assert_eq!(response.result.unwrap()["received"], 4);
+
+    let end = serde_json::json!({"upload_id": upload_id, "mode": "command", "cwd": "/tmp"});
+    let response = send_request(&socket, methods::VOICE_UPLOAD_END, Some(end.clone())).await;
+    assert_eq!(response.error.unwrap().code, INVALID_PARAMS);
+
+    send_request(
+        &socket,
+        methods::VOICE_UPLOAD_CHUNK,
+        Some(chunk(upload_id, 4, b"efgh")),
+    )
+    .await;
+    // The whole-file checksum doesn't match, so the upload is dropped
+    let response = send_request(&socket, methods::VOICE_UPLOAD_END, Some(end.clone())).await;
+    assert!(response.error.unwrap().message.contains("Checksum"));
+    let response = send_request(&socket, methods::VOICE_UPLOAD_END, Some(end)).await;
+    assert!(response.error.unwrap().message.contains("Unknown"));
+
+    let response = send_request(
+        &socket,
+        methods::VOICE_UPLOAD_BEGIN,
+        Some(serde_json::json!({"total_bytes": 1usize << 40})),
+    )
+    .await;
+    assert!(response.error.unwrap().message.contains("exceeds"));
+
+    let _ = std::fs::remove_file(&socket);
+}
+
 #[tokio::test]
 async fn test_complete_request() {
     let socket = format!("/tmp/murmur-test-complete-{}.sock", std::process::id());
@@ -91,7 +438,7 @@ async fn test_complete_request() {
     );
 
     let result = response.result.unwrap();
-    // Without a configured provider, items should be empty but response valid
+    // Without a configured provider, the heuristics answer
     assert!(result["items"].is_array());
     assert!(result["latency_ms"].is_number());
     assert_eq!(result["cached"], false);
@@ -127,72 +474,203 @@ async fn test_complete_cache_hit() {
 }
 
 #[tokio::test]
-async fn test_invalid_method() {
-    let socket = format!("/tmp/murmur-test-method-{}.sock", std::process::id());
-    let config = test_config(&socket);
-
-    start_test_server(config).await;
+async fn test_checkout_invalidates_the_repository_cache() {
+    let socket = format!("/tmp/murmur-test-checkout-{}.sock", std::process::id());
+    let repo = std::env::temp_dir().join(format!("murmur-test-checkout-{}", std::process::id()));
+    std::fs::create_dir_all(repo.join(".git")).unwrap();
+    std::fs::write(repo.join(".git/HEAD"), "ref: refs/heads/main\n").unwrap();
+    let cwd = repo.to_str().unwrap();
+    start_test_server(test_config(&socket)).await;
 
-    let response = send_request(&socket, "nonexistent/method", None).await;
+    let params = serde_json::json!({
+        "input": "ls -",
+        "cursor_pos": 4,
+        "cwd": cwd,
+        "shell": "zsh"
+    });
+    send_request(&socket, methods::COMPLETE, Some(params.clone())).await;
+    let response = send_request(&socket, methods::COMPLETE, Some(params.clone())).await;
+    assert_eq!(response.result.unwrap()["cached"], true);
 
-    assert!(response.error.is_some());
-    assert_eq!(response.error.unwrap().code, METHOD_NOT_FOUND);
+    // A reported checkout drops the repository's entries at once, and the
+    // new branch keys them apart from the old one's
+    std::fs::write(repo.join(".git/HEAD"), "ref: refs/heads/fix\n").unwrap();
+    let update = serde_json::json!({
+        "source": "terminal",
+        "command": "git checkout fix",
+        "cwd": cwd,
+    });
+    send_request(&socket, methods::CONTEXT_UPDATE, Some(update)).await;
+    let response = send_request(&socket, methods::COMPLETE, Some(params)).await;
+    assert_eq!(response.result.unwrap()["cached"], false);
 
-    // Clean up
     let _ = std::fs::remove_file(&socket);
+    let _ = std::fs::remove_dir_all(&repo);
 }
 
 #[tokio::test]
-async fn test_complete_missing_params() {
-    let socket = format!("/tmp/murmur-test-params-{}.sock", std::process::id());
-    let config = test_config(&socket);
+async fn test_cache_and_rate_limit_follow_the_clock() {
+    let socket = format!("/tmp/murmur-test-clock-{}.sock", std::process::id());
+    let clock = Arc::new(ManualClock::new(1_700_000_000));
+    let server = Server::with_clock(test_config(&socket), clock.clone());
+    tokio::spawn(async move {
+        let _ = server.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
 
-    start_test_server(config).await;
+    let params =
+        serde_json::json!({"input": "ls -", "cursor_pos": 4, "cwd": "/tmp", "shell": "zsh"});
+    send_request(&socket, methods::COMPLETE, Some(params.clone())).await;
+    let response = send_request(&socket, methods::COMPLETE, Some(params.clone())).await;
+    assert_eq!(response.result.unwrap()["cached"], true);
 
-    let response = send_request(&socket, methods::COMPLETE, None).await;
+    // Past the five-minute TTL the cached response is gone
+    clock.advance(Duration::from_secs(301));
+    let response = send_request(&socket, methods::COMPLETE, Some(params)).await;
+    assert_eq!(response.result.unwrap()["cached"], false);
 
-    assert!(response.error.is_some());
-    assert_eq!(response.error.unwrap().code, INVALID_PARAMS);
+    // Diagnoses are spaced out; once the interval passes, a new one is tried
+    let params = serde_json::json!({"command": "make", "exit_code": 2, "cwd": "/tmp"});
+    send_request(&socket, methods::DIAGNOSE, Some(params.clone())).await;
+    let response = send_request(&socket, methods::DIAGNOSE, Some(params.clone())).await;
+    assert_eq!(response.result.unwrap()["skipped"], "rate limited");
+    clock.advance(Duration::from_secs(10));
+    let response = send_request(&socket, methods::DIAGNOSE, Some(params)).await;
+    assert_eq!(response.error.unwrap().code, INTERNAL_ERROR);
 
     // Clean up
     let _ = std::fs::remove_file(&socket);
 }
 
 #[tokio::test]
-async fn test_prefetch_populates_cache() {
-    let socket = format!("/tmp/murmur-test-prefetch-{}.sock", std::process::id());
-    let config = test_config(&socket);
+async fn test_complete_reports_degradation() {
+    let socket = format!("/tmp/murmur-test-degraded-{}.sock", std::process::id());
+    let mut config = test_config(&socket);
+    // Nothing listens on the discard port
+    config.providers.insert(
+        "openai".to_string(),
+        ProviderConfig {
+            api_key: Some("sk-test".to_string()),
+            api_key_header: None,
+            api: None,
+            command: None,
+            proxy: None,
+            model: None,
+            endpoint: Some("http://127.0.0.1:9/v1/chat/completions".to_string()),
+            enabled: true,
+            timeout_ms: 2000,
+            max_retries: 0,
+            backoff_ms: 200,
+            retry_on_429: true,
+        },
+    );
+    start_test_server(config).await;
+
+    let params =
+        serde_json::json!({"input": "git c", "cursor_pos": 5, "cwd": "/tmp", "shell": "zsh"});
+    let response = send_request(&socket, methods::COMPLETE, Some(params)).await;
+    let result: CompletionResponse = serde_json::from_value(response.result.unwrap()).unwrap();
+    assert_eq!(result.notice.unwrap().state, Degradation::Offline);
 
+    // Every later response carries the notice, cached or not
+    let params = serde_json::json!({"input": "", "cursor_pos": 0, "cwd": "/tmp", "shell": "zsh"});
+    let response = send_request(&socket, methods::COMPLETE, Some(params)).await;
+    assert_eq!(response.result.unwrap()["notice"]["state"], "offline");
+
+    // Clean up
+    let _ = std::fs::remove_file(&socket);
+
+    let socket = format!("/tmp/murmur-test-budget-{}.sock", std::process::id());
+    let mut config = test_config(&socket);
+    config.providers.insert(
+        "openai".to_string(),
+        ProviderConfig {
+            api_key: Some("sk-test".to_string()),
+            api_key_header: None,
+            api: None,
+            command: None,
+            proxy: None,
+            model: None,
+            endpoint: Some("http://127.0.0.1:9/v1/chat/completions".to_string()),
+            enabled: true,
+            timeout_ms: 2000,
+            max_retries: 0,
+            backoff_ms: 200,
+            retry_on_429: true,
+        },
+    );
+    config.budget.daily_requests = Some(0);
     start_test_server(config).await;
 
-    // Request completion for "git c" — should trigger pre-fetch for "git commit", etc.
+    let params =
+        serde_json::json!({"input": "ls -", "cursor_pos": 4, "cwd": "/tmp", "shell": "zsh"});
+    let response = send_request(&socket, methods::COMPLETE, Some(params)).await;
+    assert_eq!(
+        response.result.unwrap()["notice"]["state"],
+        "budget_exhausted"
+    );
+
+    // Clean up
+    let _ = std::fs::remove_file(&socket);
+}
+
+#[tokio::test]
+async fn test_complete_rejects_inactive_provider() {
+    let socket = format!("/tmp/murmur-test-pinned-{}.sock", std::process::id());
+    start_test_server(test_config(&socket)).await;
+
     let params = serde_json::json!({
         "input": "git c",
         "cursor_pos": 5,
         "cwd": "/tmp",
-        "shell": "zsh"
+        "provider": "anthropic",
+        "model": "claude-sonnet-4-5",
     });
+    let response = send_request(&socket, methods::COMPLETE, Some(params)).await;
+    let error = response.error.unwrap();
+    assert_eq!(error.code, INVALID_PARAMS);
+    assert!(error.message.contains("'anthropic' is not active"));
 
+    // A model alone would go to every provider in the chain
+    let params = serde_json::json!({
+        "input": "git c",
+        "cursor_pos": 5,
+        "cwd": "/tmp",
+        "model": "claude-sonnet-4-5",
+    });
     let response = send_request(&socket, methods::COMPLETE, Some(params)).await;
-    assert!(response.error.is_none());
-    assert_eq!(response.result.as_ref().unwrap()["cached"], false);
+    let error = response.error.unwrap();
+    assert_eq!(error.code, INVALID_PARAMS);
+    assert!(
+        error.message.contains("needs a provider"),
+        "{}",
+        error.message
+    );
 
-    // Wait for pre-fetch to complete
-    tokio::time::sleep(Duration::from_millis(500)).await;
+    let _ = std::fs::remove_file(&socket);
+}
 
-    // "git commit" should now be in cache (pre-fetched)
-    let params2 = serde_json::json!({
-        "input": "git commit",
-        "cursor_pos": 10,
+#[tokio::test]
+async fn test_complete_candidates_request() {
+    let socket = format!("/tmp/murmur-test-candidates-{}.sock", std::process::id());
+    let config = test_config(&socket);
+
+    start_test_server(config).await;
+
+    let params = serde_json::json!({
+        "input": "git s",
+        "cursor_pos": 5,
         "cwd": "/tmp",
-        "shell": "zsh"
+        "shell": "bash"
     });
-    let response2 = send_request(&socket, methods::COMPLETE, Some(params2)).await;
-    assert!(response2.error.is_none());
-    assert_eq!(
-        response2.result.as_ref().unwrap()["cached"],
-        true,
-        "Pre-fetched 'git commit' should be a cache hit"
+
+    let response = send_request(&socket, methods::COMPLETE_CANDIDATES, Some(params)).await;
+    assert!(response.error.is_none(), "{:?}", response.error);
+    let result = response.result.unwrap();
+    assert!(result["candidates"].is_array());
+    assert!(
+        result["items"].is_null(),
+        "candidates should not carry items"
     );
 
     // Clean up
@@ -200,47 +678,1515 @@ async fn test_prefetch_populates_cache() {
 }
 
 #[tokio::test]
-async fn test_multiple_requests_same_connection() {
-    let socket = format!("/tmp/murmur-test-multi-{}.sock", std::process::id());
+async fn test_events_subscribe_returns_state() {
+    let socket = format!("/tmp/murmur-test-events-{}.sock", std::process::id());
     let config = test_config(&socket);
 
     start_test_server(config).await;
 
-    // Open a single connection and send multiple requests
-    let stream = UnixStream::connect(&socket).await.unwrap();
-    let (reader, mut writer) = stream.into_split();
-    let mut reader = BufReader::new(reader);
+    let response = send_request(&socket, methods::EVENTS_SUBSCRIBE, None).await;
+    assert!(response.error.is_none(), "{:?}", response.error);
+    let state: DaemonState = serde_json::from_value(response.result.unwrap()).unwrap();
+    assert_eq!(state.provider, None);
+    assert_eq!(state.budget_remaining, None);
+    assert!(!state.voice_active);
 
-    // Request 1: status
-    let req1 = JsonRpcRequest::new(methods::STATUS, None, RequestId::Number(1));
-    let json1 = serde_json::to_string(&req1).unwrap();
-    writer.write_all(json1.as_bytes()).await.unwrap();
-    writer.write_all(b"\n").await.unwrap();
-    writer.flush().await.unwrap();
+    // Clean up
+    let _ = std::fs::remove_file(&socket);
+}
 
-    let mut line1 = String::new();
-    reader.read_line(&mut line1).await.unwrap();
-    let resp1: JsonRpcResponse = serde_json::from_str(&line1).unwrap();
-    assert!(resp1.error.is_none());
+#[tokio::test]
+async fn test_subscribers_get_the_notifications_they_ask_for() {
+    let socket = format!("/tmp/murmur-test-notify-{}.sock", std::process::id());
+    let config = test_config(&socket);
+
+    start_test_server(config).await;
+
+    let stream = UnixStream::connect(&socket).await.unwrap();
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let params = serde_json::json!({ "methods": [methods::HISTORY_UPDATED] });
+    let request = JsonRpcRequest::new(
+        methods::EVENTS_SUBSCRIBE,
+        Some(params),
+        RequestId::Number(1),
+    );
+    let json = serde_json::to_string(&request).unwrap();
+    writer
+        .write_all(format!("{json}\n").as_bytes())
+        .await
+        .unwrap();
+    let first = lines.next_line().await.unwrap().unwrap();
+    let response: JsonRpcResponse = serde_json::from_str(&first).unwrap();
+    assert!(response.result.is_some());
 
-    // Request 2: complete
     let params = serde_json::json!({
-        "input": "echo ",
-        "cursor_pos": 5,
+        "source": "zsh",
+        "command": "make test",
         "cwd": "/tmp",
-        "shell": "bash"
     });
-    let req2 = JsonRpcRequest::new(methods::COMPLETE, Some(params), RequestId::Number(2));
-    let json2 = serde_json::to_string(&req2).unwrap();
-    writer.write_all(json2.as_bytes()).await.unwrap();
-    writer.write_all(b"\n").await.unwrap();
-    writer.flush().await.unwrap();
+    send_request(&socket, methods::CONTEXT_UPDATE, Some(params)).await;
 
-    let mut line2 = String::new();
-    reader.read_line(&mut line2).await.unwrap();
-    let resp2: JsonRpcResponse = serde_json::from_str(&line2).unwrap();
-    assert!(resp2.error.is_none());
+    let line = tokio::time::timeout(Duration::from_secs(2), lines.next_line())
+        .await
+        .expect("no notification")
+        .unwrap()
+        .unwrap();
+    let notification: JsonRpcNotification = serde_json::from_str(&line).unwrap();
+    assert_eq!(notification.method, methods::HISTORY_UPDATED);
+    let entry: HistoryEntry = serde_json::from_value(notification.params.unwrap()).unwrap();
+    assert_eq!(entry.command, "make test");
 
     // Clean up
     let _ = std::fs::remove_file(&socket);
 }
+
+#[tokio::test]
+async fn test_voice_confirm_unknown_id() {
+    let socket = format!("/tmp/murmur-test-confirm-{}.sock", std::process::id());
+    let config = test_config(&socket);
+
+    start_test_server(config).await;
+
+    let params = serde_json::json!({"id": 42, "transcript": "yes"});
+    let response = send_request(&socket, methods::VOICE_CONFIRM, Some(params)).await;
+    let error = response.error.expect("unknown confirmation should fail");
+    assert_eq!(error.code, INVALID_PARAMS);
+    assert!(error.message.contains("42"));
+
+    // Clean up
+    let _ = std::fs::remove_file(&socket);
+}
+
+#[tokio::test]
+async fn test_query_without_providers() {
+    let socket = format!("/tmp/murmur-test-query-{}.sock", std::process::id());
+    let config = test_config(&socket);
+
+    start_test_server(config).await;
+
+    let params = serde_json::json!({"query": "show listening ports"});
+    let response = send_request(&socket, methods::QUERY, Some(params)).await;
+    let error = response.error.expect("query without providers should fail");
+    assert_eq!(error.code, INTERNAL_ERROR);
+
+    let response = send_request(&socket, methods::QUERY, None).await;
+    assert_eq!(response.error.unwrap().code, INVALID_PARAMS);
+
+    // Clean up
+    let _ = std::fs::remove_file(&socket);
+}
+
+#[tokio::test]
+async fn test_history_import_and_ask() {
+    let socket = format!("/tmp/murmur-test-history-{}.sock", std::process::id());
+    let config = test_config(&socket);
+
+    start_test_server(config).await;
+
+    let entry = serde_json::json!({
+        "command": "fly deploy",
+        "cwd": "",
+        "source": "zsh-history",
+        "exit_code": 0,
+        "timestamp": 1_700_000_000u64,
+    });
+    let params = serde_json::json!({"entries": [entry, entry]});
+    let response = send_request(&socket, methods::HISTORY_IMPORT, Some(params)).await;
+    assert_eq!(response.result.unwrap()["imported"], 1);
+
+    let response = send_request(&socket, methods::HISTORY_LIST, None).await;
+    let entries = response.result.unwrap();
+    assert_eq!(entries[0]["command"], "fly deploy");
+
+    // Interpreting the question needs a provider
+    let params = serde_json::json!({"question": "what did I deploy on friday"});
+    let response = send_request(&socket, methods::HISTORY_ASK, Some(params)).await;
+    assert_eq!(response.error.unwrap().code, INTERNAL_ERROR);
+
+    // Clean up
+    let _ = std::fs::remove_file(&socket);
+}
+
+#[tokio::test]
+async fn test_empty_input_predicts_next_command() {
+    let socket = format!("/tmp/murmur-test-sequence-{}.sock", std::process::id());
+    let config = test_config(&socket);
+
+    start_test_server(config).await;
+
+    for command in ["cargo build", "cargo test", "cargo build", "cargo test"] {
+        let params = serde_json::json!({
+            "source": "claude-code",
+            "command": command,
+            "cwd": "/tmp",
+            "session_id": "s1",
+        });
+        send_request(&socket, methods::CONTEXT_UPDATE, Some(params)).await;
+    }
+
+    let params = serde_json::json!({
+        "input": "",
+        "cursor_pos": 0,
+        "cwd": "/tmp",
+        "shell": "zsh",
+        "history": ["cargo build"],
+    });
+    let response = send_request(&socket, methods::COMPLETE, Some(params)).await;
+    let result = response.result.unwrap();
+    assert_eq!(result["provider"], "sequence");
+    assert_eq!(result["items"][0]["text"], "cargo test");
+
+    // Clean up
+    let _ = std::fs::remove_file(&socket);
+}
+
+#[tokio::test]
+async fn test_panes_get_their_own_predictions() {
+    let socket = format!("/tmp/murmur-test-panes-{}.sock", std::process::id());
+    start_test_server(test_config(&socket)).await;
+
+    // Two panes of one multiplexer, working in turns
+    let panes = [("pane-a", "/tmp", "cargo"), ("pane-b", "/", "npm")];
+    for round in ["build", "test", "build", "test", "build"] {
+        for (session, cwd, tool) in panes {
+            let command = match (tool, round) {
+                ("npm", "build") => "npm install".to_string(),
+                ("npm", _) => "npm start".to_string(),
+                (tool, round) => format!("{tool} {round}"),
+            };
+            let params = serde_json::json!({
+                "source": "zsh", "command": command, "cwd": cwd, "session_id": session,
+            });
+            send_request(&socket, methods::CONTEXT_UPDATE, Some(params)).await;
+        }
+    }
+
+    // Each pane's empty prompt follows its own last command, in its own
+    // directory even when the request leaves it out
+    for (session, expected) in [("pane-a", "cargo test"), ("pane-b", "npm start")] {
+        let params = serde_json::json!({
+            "input": "", "cursor_pos": 0, "cwd": "", "shell": "zsh", "session_id": session,
+        });
+        let response = send_request(&socket, methods::COMPLETE, Some(params)).await;
+        let result = response.result.unwrap();
+        assert_eq!(result["provider"], "sequence");
+        assert_eq!(result["items"][0]["text"], expected, "{session}");
+    }
+
+    let _ = std::fs::remove_file(&socket);
+}
+
+#[tokio::test]
+async fn test_diagnose_skips_and_rate_limits() {
+    let socket = format!("/tmp/murmur-test-diagnose-{}.sock", std::process::id());
+    let config = test_config(&socket);
+
+    start_test_server(config).await;
+
+    // Ctrl-C isn't a failure worth explaining
+    let params = serde_json::json!({"command": "sleep 100", "exit_code": 130, "cwd": "/tmp"});
+    let response = send_request(&socket, methods::DIAGNOSE, Some(params)).await;
+    assert_eq!(response.result.unwrap()["skipped"], "interrupted");
+
+    // No provider is configured, but the attempt still counts
+    let params = serde_json::json!({"command": "make", "exit_code": 2, "cwd": "/tmp"});
+    let response = send_request(&socket, methods::DIAGNOSE, Some(params.clone())).await;
+    assert_eq!(response.error.unwrap().code, INTERNAL_ERROR);
+    let response = send_request(&socket, methods::DIAGNOSE, Some(params)).await;
+    assert_eq!(response.result.unwrap()["skipped"], "rate limited");
+
+    // Clean up
+    let _ = std::fs::remove_file(&socket);
+}
+
+#[tokio::test]
+async fn test_invalid_method() {
+    let socket = format!("/tmp/murmur-test-method-{}.sock", std::process::id());
+    let config = test_config(&socket);
+
+    start_test_server(config).await;
+
+    let response = send_request(&socket, "nonexistent/method", None).await;
+
+    assert!(response.error.is_some());
+    assert_eq!(response.error.unwrap().code, METHOD_NOT_FOUND);
+
+    // Clean up
+    let _ = std::fs::remove_file(&socket);
+}
+
+#[tokio::test]
+async fn test_oversized_request_is_rejected() {
+    let socket = format!("/tmp/murmur-test-oversized-{}.sock", std::process::id());
+    start_test_server(test_config(&socket)).await;
+
+    let stream = UnixStream::connect(&socket).await.unwrap();
+    let (reader, mut writer) = stream.into_split();
+    // The server stops reading at the limit, so the tail of this write fails
+    tokio::spawn(async move {
+        let chunk = vec![b'x'; 1024 * 1024];
+        for _ in 0..40 {
+            if writer.write_all(&chunk).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut reader = BufReader::new(reader);
+    let mut line = String::new();
+    reader.read_line(&mut line).await.unwrap();
+    let response: JsonRpcResponse = serde_json::from_str(&line).unwrap();
+    assert_eq!(response.error.unwrap().code, INVALID_REQUEST);
+    // Then the connection is closed (reset, since unread data is discarded)
+    line.clear();
+    assert!(!matches!(reader.read_line(&mut line).await, Ok(n) if n > 0));
+
+    let _ = std::fs::remove_file(&socket);
+}
+
+#[tokio::test]
+async fn test_complete_missing_params() {
+    let socket = format!("/tmp/murmur-test-params-{}.sock", std::process::id());
+    let config = test_config(&socket);
+
+    start_test_server(config).await;
+
+    let response = send_request(&socket, methods::COMPLETE, None).await;
+
+    assert!(response.error.is_some());
+    assert_eq!(response.error.unwrap().code, INVALID_PARAMS);
+
+    // Clean up
+    let _ = std::fs::remove_file(&socket);
+}
+
+#[tokio::test]
+async fn test_prefetch_populates_cache() {
+    let socket = format!("/tmp/murmur-test-prefetch-{}.sock", std::process::id());
+    let config = test_config(&socket);
+
+    start_test_server(config).await;
+
+    // Request completion for "git c" — should trigger pre-fetch for "git commit", etc.
+    let params = serde_json::json!({
+        "input": "git c",
+        "cursor_pos": 5,
+        "cwd": "/tmp",
+        "shell": "zsh"
+    });
+
+    let response = send_request(&socket, methods::COMPLETE, Some(params)).await;
+    assert!(response.error.is_none());
+    assert_eq!(response.result.as_ref().unwrap()["cached"], false);
+
+    // Wait for pre-fetch to complete
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    // "git commit" should now be in cache (pre-fetched)
+    let params2 = serde_json::json!({
+        "input": "git commit",
+        "cursor_pos": 10,
+        "cwd": "/tmp",
+        "shell": "zsh"
+    });
+    let response2 = send_request(&socket, methods::COMPLETE, Some(params2)).await;
+    assert!(response2.error.is_none());
+    assert_eq!(
+        response2.result.as_ref().unwrap()["cached"],
+        true,
+        "Pre-fetched 'git commit' should be a cache hit"
+    );
+
+    // Clean up
+    let _ = std::fs::remove_file(&socket);
+}
+
+#[tokio::test]
+async fn test_multiple_requests_same_connection() {
+    let socket = format!("/tmp/murmur-test-multi-{}.sock", std::process::id());
+    let config = test_config(&socket);
+
+    start_test_server(config).await;
+
+    // Open a single connection and send multiple requests
+    let stream = UnixStream::connect(&socket).await.unwrap();
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+
+    // Request 1: status
+    let req1 = JsonRpcRequest::new(methods::STATUS, None, RequestId::Number(1));
+    let json1 = serde_json::to_string(&req1).unwrap();
+    writer.write_all(json1.as_bytes()).await.unwrap();
+    writer.write_all(b"\n").await.unwrap();
+    writer.flush().await.unwrap();
+
+    let mut line1 = String::new();
+    reader.read_line(&mut line1).await.unwrap();
+    let resp1: JsonRpcResponse = serde_json::from_str(&line1).unwrap();
+    assert!(resp1.error.is_none());
+
+    // Request 2: complete
+    let params = serde_json::json!({
+        "input": "echo ",
+        "cursor_pos": 5,
+        "cwd": "/tmp",
+        "shell": "bash"
+    });
+    let req2 = JsonRpcRequest::new(methods::COMPLETE, Some(params), RequestId::Number(2));
+    let json2 = serde_json::to_string(&req2).unwrap();
+    writer.write_all(json2.as_bytes()).await.unwrap();
+    writer.write_all(b"\n").await.unwrap();
+    writer.flush().await.unwrap();
+
+    let mut line2 = String::new();
+    reader.read_line(&mut line2).await.unwrap();
+    let resp2: JsonRpcResponse = serde_json::from_str(&line2).unwrap();
+    assert!(resp2.error.is_none());
+
+    // Clean up
+    let _ = std::fs::remove_file(&socket);
+}
+
+/// A plugin provider that answers every `complete` with `text`.
+fn plugin_answering(text: &str) -> ProviderConfig {
+    plugin_replying(&format!(
+        r#""result":[{{"text":"{text}","kind":"command","score":0.9}}]"#
+    ))
+}
+
+/// A plugin answering every request with `result`, a JSON-RPC `"result"`
+/// or `"error"` member.
+fn plugin_replying(result: &str) -> ProviderConfig {
+    let command = format!(
+        r#"while read -r line; do id=$(printf '%s' "$line" | sed -E 's/.*"id":([0-9]+)}}$/\1/'); printf '{{"jsonrpc":"2.0","id":%s,{result}}}\n' "$id"; done"#
+    );
+    serde_json::from_value(serde_json::json!({ "command": command })).unwrap()
+}
+
+#[tokio::test]
+async fn test_tiered_routing_answers_fast_and_escalates() {
+    let complete = serde_json::json!({
+        "input": "git st", "cursor_pos": 6, "cwd": "/tmp/tiered", "shell": "zsh",
+    });
+    let serve = |name: &str, fast: ProviderConfig| {
+        let socket = format!("/tmp/murmur-test-tiered-{name}-{}.sock", std::process::id());
+        let mut config = test_config(&socket);
+        config.completion.settle_ms = 0;
+        config.routing.tiered = true;
+        config.routing.fast_provider = "fast".to_string();
+        config.routing.strong_provider = "strong".to_string();
+        config.providers.insert("fast".to_string(), fast);
+        config
+            .providers
+            .insert("strong".to_string(), plugin_answering("git stash"));
+        (socket, config)
+    };
+
+    // The fast tier answers at once; the strong one's answer replaces it
+    // in the cache
+    let (socket, config) = serve("answers", plugin_answering("git status"));
+    start_test_server(config).await;
+    let response = send_request(&socket, methods::COMPLETE, Some(complete.clone())).await;
+    let result = response.result.unwrap();
+    assert_eq!(result["provider"], "fast");
+    assert_eq!(result["items"][0]["text"], "git status");
+    let mut tries = 0;
+    let refined = loop {
+        let response = send_request(&socket, methods::COMPLETE, Some(complete.clone())).await;
+        let result = response.result.unwrap();
+        tries += 1;
+        if result["provider"] == "strong" || tries == 50 {
+            break result;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    };
+    assert_eq!(refined["cached"], true);
+    assert_eq!(refined["items"][0]["text"], "git stash");
+
+    // Nothing or an error from the fast tier goes to the strong one at once
+    for (name, fast) in [
+        ("empty", plugin_replying(r#""result":[]"#)),
+        (
+            "fails",
+            plugin_replying(r#""error":{"code":-32000,"message":"overloaded"}"#),
+        ),
+    ] {
+        let (socket, config) = serve(name, fast);
+        start_test_server(config).await;
+        let response = send_request(&socket, methods::COMPLETE, Some(complete.clone())).await;
+        let result = response.result.unwrap();
+        assert_eq!(result["provider"], "strong", "{name}");
+        assert_eq!(result["items"][0]["text"], "git stash", "{name}");
+        let _ = std::fs::remove_file(&socket);
+    }
+}
+
+#[tokio::test]
+async fn test_shadow_provider_is_evaluated() {
+    let socket = format!("/tmp/murmur-test-shadow-{}.sock", std::process::id());
+    let log = format!("/tmp/murmur-test-shadow-{}.jsonl", std::process::id());
+    let _ = std::fs::remove_file(&log);
+    let mut config = test_config(&socket);
+    config.completion.settle_ms = 0;
+    config
+        .providers
+        .insert("alpha".to_string(), plugin_answering("git status"));
+    config
+        .providers
+        .insert("beta".to_string(), plugin_answering("git stash"));
+    config.routing.shadow_provider = Some("beta".to_string());
+    config.routing.shadow_log = log.clone();
+
+    start_test_server(config).await;
+
+    let params = serde_json::json!({
+        "input": "git st",
+        "cursor_pos": 6,
+        "cwd": "/tmp/shadow",
+        "shell": "zsh",
+    });
+    let response = send_request(&socket, methods::COMPLETE, Some(params)).await;
+    let result = response.result.unwrap();
+    assert_eq!(result["provider"], "alpha");
+    assert_eq!(result["items"][0]["text"], "git status");
+
+    // The shadow's suggestion is never shown, but running it counts for it
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let params = serde_json::json!({
+        "source": "zsh",
+        "command": "git stash",
+        "cwd": "/tmp/shadow",
+    });
+    send_request(&socket, methods::CONTEXT_UPDATE, Some(params)).await;
+
+    let evaluation: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&log).unwrap()).unwrap();
+    assert_eq!(evaluation["primary"], "alpha");
+    assert_eq!(evaluation["shadow"], "beta");
+    assert_eq!(evaluation["accepted"], "shadow");
+    assert_eq!(evaluation["input"], "git st");
+
+    // Clean up
+    let _ = std::fs::remove_file(&socket);
+    let _ = std::fs::remove_file(&log);
+}
+
+#[tokio::test]
+async fn test_longer_input_is_served_from_a_shorter_one() {
+    let socket = format!("/tmp/murmur-test-prefix-{}.sock", std::process::id());
+    let mut config = test_config(&socket);
+    config
+        .providers
+        .insert("plugin".to_string(), plugin_answering("git commit"));
+    start_test_server(config).await;
+
+    let complete = |input: &str| {
+        serde_json::json!({
+            "input": input, "cursor_pos": input.len(), "cwd": "/tmp/prefix", "shell": "zsh",
+        })
+    };
+    let response = send_request(&socket, methods::COMPLETE, Some(complete("git c"))).await;
+    assert_eq!(response.result.unwrap()["items"][0]["text"], "git commit");
+
+    let stream = UnixStream::connect(&socket).await.unwrap();
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let params = serde_json::json!({ "methods": [methods::COMPLETION_READY] });
+    let request = JsonRpcRequest::new(
+        methods::EVENTS_SUBSCRIBE,
+        Some(params),
+        RequestId::Number(1),
+    );
+    let json = serde_json::to_string(&request).unwrap();
+    writer
+        .write_all(format!("{json}\n").as_bytes())
+        .await
+        .unwrap();
+    lines.next_line().await.unwrap().unwrap();
+
+    // Served at once from "git c", then refreshed for "git com"
+    let response = send_request(&socket, methods::COMPLETE, Some(complete("git com"))).await;
+    let result = response.result.unwrap();
+    assert_eq!(result["cached"], true);
+    assert_eq!(result["refreshing"], true);
+    assert_eq!(result["items"][0]["text"], "git commit");
+
+    let line = tokio::time::timeout(Duration::from_secs(5), lines.next_line())
+        .await
+        .expect("no refresh")
+        .unwrap()
+        .unwrap();
+    let notification: JsonRpcNotification = serde_json::from_str(&line).unwrap();
+    assert_eq!(notification.params.unwrap()["input"], "git com");
+    let response = send_request(&socket, methods::COMPLETE, Some(complete("git com"))).await;
+    let result = response.result.unwrap();
+    assert_eq!(result["cached"], true);
+    assert!(result.get("refreshing").is_none());
+
+    let _ = std::fs::remove_file(&socket);
+}
+
+#[tokio::test]
+async fn test_non_ascii_input_is_served_from_a_shorter_one() {
+    let socket = format!("/tmp/murmur-test-prefix-utf8-{}.sock", std::process::id());
+    let mut config = test_config(&socket);
+    config.providers.insert(
+        "plugin".to_string(),
+        plugin_answering("cd ~/Téléchargements"),
+    );
+    start_test_server(config).await;
+
+    // The cursor counts characters, not bytes
+    let complete = |input: &str| {
+        serde_json::json!({
+            "input": input, "cursor_pos": input.chars().count(), "cwd": "/tmp/prefix-utf8",
+            "shell": "zsh",
+        })
+    };
+    let response = send_request(&socket, methods::COMPLETE, Some(complete("cd ~/Té"))).await;
+    assert_eq!(
+        response.result.unwrap()["items"][0]["text"],
+        "cd ~/Téléchargements"
+    );
+
+    let response = send_request(&socket, methods::COMPLETE, Some(complete("cd ~/Tél"))).await;
+    let result = response.result.unwrap();
+    assert_eq!(result["cached"], true);
+    assert_eq!(result["refreshing"], true);
+    assert_eq!(result["items"][0]["text"], "cd ~/Téléchargements");
+
+    let _ = std::fs::remove_file(&socket);
+}
+
+#[tokio::test]
+async fn test_latency_budget_answers_without_a_slow_provider() {
+    let socket = format!("/tmp/murmur-test-budget-{}.sock", std::process::id());
+    let mut config = test_config(&socket);
+    config.completion.settle_ms = 0;
+    config.completion.latency_budget_ms = 300;
+    let mut slow = plugin_answering("git status");
+    slow.command = slow.command.map(|c| format!("sleep 3; {c}"));
+    slow.timeout_ms = 10_000;
+    config.providers.insert("slow".to_string(), slow);
+
+    start_test_server(config).await;
+
+    let params = serde_json::json!({
+        "input": "git st",
+        "cursor_pos": 6,
+        "cwd": "/tmp/budget",
+        "shell": "zsh",
+    });
+    let started = std::time::Instant::now();
+    let response = send_request(&socket, methods::COMPLETE, Some(params.clone())).await;
+    assert!(started.elapsed() < Duration::from_millis(2000));
+    let result = response.result.unwrap();
+    assert_eq!(result["provider"], "none");
+    assert_eq!(result["items"].as_array().unwrap().len(), 0);
+}
+
+#[tokio::test]
+async fn test_heuristics_only_never_asks_a_provider() {
+    let socket = format!("/tmp/murmur-test-heuristics-{}.sock", std::process::id());
+    let mut config = test_config(&socket);
+    config.completion.heuristics = HeuristicsMode::Only;
+    config
+        .providers
+        .insert("plugin".to_string(), plugin_answering("git stash pop"));
+
+    start_test_server(config).await;
+
+    let params = serde_json::json!({
+        "input": "git sta",
+        "cursor_pos": 7,
+        "cwd": "/tmp/heuristics",
+        "shell": "zsh",
+    });
+    let response = send_request(&socket, methods::COMPLETE, Some(params)).await;
+    let result = response.result.unwrap();
+    assert_eq!(result["provider"], "heuristic");
+    let texts: Vec<&str> = result["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["text"].as_str().unwrap())
+        .collect();
+    assert!(texts.contains(&"git stash "), "{texts:?}");
+    assert!(texts.contains(&"git status "), "{texts:?}");
+    assert!(!texts.contains(&"git stash pop"), "{texts:?}");
+
+    // The plugin isn't even started
+    let response = send_request(&socket, methods::STATUS, None).await;
+    assert_eq!(
+        response.result.unwrap()["providers_active"],
+        serde_json::json!([])
+    );
+}
+
+#[tokio::test]
+async fn test_debug_bundle_traces_requests_without_their_params() {
+    let socket = format!("/tmp/murmur-test-debug-{}.sock", std::process::id());
+    let mut config = test_config(&socket);
+    config.completion.settle_ms = 0;
+    config
+        .providers
+        .insert("alpha".to_string(), plugin_answering("git status"));
+
+    start_test_server(config).await;
+
+    let params = serde_json::json!({
+        "input": "git st",
+        "cursor_pos": 6,
+        "cwd": "/tmp/debug",
+        "shell": "zsh",
+    });
+    send_request(&socket, methods::COMPLETE, Some(params)).await;
+    send_request(&socket, "no/such/method", None).await;
+
+    let params = serde_json::json!({ "traces": 2 });
+    let response = send_request(&socket, methods::DEBUG_BUNDLE, Some(params)).await;
+    let bundle: DebugBundle = serde_json::from_value(response.result.unwrap()).unwrap();
+    assert_eq!(bundle.status["status"], "running");
+    let methods: Vec<&str> = bundle.traces.iter().map(|t| t.method.as_str()).collect();
+    assert_eq!(methods, ["complete", "no/such/method"]);
+    assert_eq!(bundle.traces[0].provider.as_deref(), Some("alpha"));
+    assert_eq!(bundle.traces[0].items, Some(1));
+    assert_eq!(bundle.traces[1].error, Some(METHOD_NOT_FOUND));
+    assert!(!serde_json::to_string(&bundle.traces)
+        .unwrap()
+        .contains("git st"));
+}
+
+#[tokio::test]
+async fn test_identical_completions_in_flight_share_one_provider_call() {
+    let socket = format!("/tmp/murmur-test-coalesce-{}.sock", std::process::id());
+    let calls = format!("/tmp/murmur-test-coalesce-{}.calls", std::process::id());
+    let _ = std::fs::remove_file(&calls);
+    let mut config = test_config(&socket);
+    config.completion.settle_ms = 0;
+    let mut slow = plugin_answering("git status");
+    slow.command = slow.command.map(|c| {
+        c.replacen(
+            "do ",
+            &format!(r#"do case "$line" in *'"input":"git st"'*) echo call >> {calls};; esac; sleep 0.5; "#),
+            1,
+        )
+    });
+    config.providers.insert("slow".to_string(), slow);
+
+    start_test_server(config).await;
+
+    let params = serde_json::json!({
+        "input": "git st",
+        "cursor_pos": 6,
+        "cwd": "/tmp/coalesce",
+        "shell": "zsh",
+    });
+    let (first, second) = tokio::join!(
+        send_request(&socket, methods::COMPLETE, Some(params.clone())),
+        async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            send_request(&socket, methods::COMPLETE, Some(params.clone())).await
+        }
+    );
+    let (first, second) = (first.result.unwrap(), second.result.unwrap());
+    assert_eq!(first["items"][0]["text"], "git status");
+    assert_eq!(second["items"][0]["text"], "git status");
+    assert_eq!(second["cached"], true);
+    assert_eq!(std::fs::read_to_string(&calls).unwrap(), "call\n");
+    let _ = std::fs::remove_file(&calls);
+}
+
+#[tokio::test]
+async fn test_complete_cancel_aborts_a_slow_completion() {
+    let socket = format!("/tmp/murmur-test-cancel-{}.sock", std::process::id());
+    let mut config = test_config(&socket);
+    config.completion.settle_ms = 0;
+    let mut slow = plugin_answering("git status");
+    slow.command = slow.command.map(|c| c.replacen("do ", "do sleep 5; ", 1));
+    config.providers.insert("slow".to_string(), slow);
+
+    start_test_server(config).await;
+
+    let params = serde_json::json!({
+        "input": "git st",
+        "cursor_pos": 6,
+        "cwd": "/tmp/cancel",
+        "shell": "zsh",
+        "cancel_token": "zsh-4242",
+    });
+    let cancel = serde_json::json!({ "token": "zsh-4242" });
+    let started = std::time::Instant::now();
+    let (completion, cancelled) = tokio::join!(
+        send_request(&socket, methods::COMPLETE, Some(params)),
+        async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            send_request(&socket, methods::COMPLETE_CANCEL, Some(cancel.clone())).await
+        }
+    );
+    assert!(started.elapsed() < Duration::from_secs(3));
+    assert_eq!(completion.error.unwrap().code, REQUEST_CANCELLED);
+    assert_eq!(cancelled.result.unwrap()["cancelled"], true);
+
+    // Nothing is left running under the token
+    let again = send_request(&socket, methods::COMPLETE_CANCEL, Some(cancel)).await;
+    assert_eq!(again.result.unwrap()["cancelled"], false);
+}
+
+#[tokio::test]
+async fn test_health_reports_readiness() {
+    let socket = format!("/tmp/murmur-test-health-{}.sock", std::process::id());
+    start_test_server(test_config(&socket)).await;
+
+    let response = send_request(&socket, methods::HEALTH, None).await;
+    let health: HealthResponse = serde_json::from_value(response.result.unwrap()).unwrap();
+    assert!(health.live);
+    assert!(!health.ready);
+    assert_eq!(health.reasons, ["no provider is active"]);
+}
+
+#[tokio::test]
+async fn test_ping_names_the_daemon_process() {
+    let socket = format!("/tmp/murmur-test-ping-{}.sock", std::process::id());
+    start_test_server(test_config(&socket)).await;
+
+    let response = send_request(&socket, methods::PING, None).await;
+    let ping: PingResponse = serde_json::from_value(response.result.unwrap()).unwrap();
+    assert_eq!(ping.pid, std::process::id());
+    assert_eq!(ping.version, env!("CARGO_PKG_VERSION"));
+}
+
+#[tokio::test]
+async fn test_health_probes_over_http() {
+    let socket = format!("/tmp/murmur-test-probe-{}.sock", std::process::id());
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let mut config = test_config(&socket);
+    config.daemon.health_listen = Some(addr.to_string());
+    config
+        .providers
+        .insert("plugin".to_string(), plugin_answering("git status"));
+
+    start_test_server(config).await;
+
+    for path in ["/healthz", "/readyz"] {
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let request = format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        tokio::io::AsyncReadExt::read_to_string(&mut stream, &mut response)
+            .await
+            .unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+        let body = response.split("\r\n\r\n").nth(1).unwrap();
+        let health: HealthResponse = serde_json::from_str(body).unwrap();
+        assert!(health.ready);
+    }
+}
+
+#[tokio::test]
+async fn test_debounce_answers_only_the_latest_of_a_burst() {
+    let socket = format!("/tmp/murmur-test-debounce-{}.sock", std::process::id());
+    let calls = format!("/tmp/murmur-test-debounce-{}.calls", std::process::id());
+    let _ = std::fs::remove_file(&calls);
+    let mut config = test_config(&socket);
+    config.completion.debounce_ms = 200;
+    let mut counted = plugin_answering("git status");
+    counted.command = counted.command.map(|c| {
+        c.replacen(
+            "do ",
+            // Prefetches of predicted inputs aren't counted
+            &format!(
+                r#"do case "$line" in *'"input":"git s"'*|*'"input":"git st"'*|*'"input":"git sta"'*) echo call >> {calls};; esac; "#
+            ),
+            1,
+        )
+    });
+    config.providers.insert("counted".to_string(), counted);
+
+    start_test_server(config).await;
+
+    let stream = UnixStream::connect(&socket).await.unwrap();
+    let (reader, mut writer) = stream.into_split();
+    // Typed one keystroke at a time, then a request for elsewhere
+    for (id, input, cwd) in [
+        (1, "git s", "/tmp/debounce"),
+        (2, "git st", "/tmp/debounce"),
+        (3, "git sta", "/tmp/debounce"),
+        (4, "git s", "/tmp/elsewhere"),
+    ] {
+        let params = serde_json::json!({
+            "input": input,
+            "cursor_pos": input.len(),
+            "cwd": cwd,
+            "shell": "zsh",
+        });
+        let request = JsonRpcRequest::new(methods::COMPLETE, Some(params), RequestId::Number(id));
+        let json = serde_json::to_string(&request).unwrap() + "\n";
+        writer.write_all(json.as_bytes()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    let mut lines = BufReader::new(reader).lines();
+    let mut responses = Vec::new();
+    for _ in 0..4 {
+        let line = lines.next_line().await.unwrap().unwrap();
+        responses.push(serde_json::from_str::<JsonRpcResponse>(&line).unwrap());
+    }
+    let outcome: Vec<(RequestId, Option<i32>)> = responses
+        .iter()
+        .map(|r| (r.id.clone(), r.error.as_ref().map(|e| e.code)))
+        .collect();
+    assert_eq!(
+        outcome,
+        [
+            (RequestId::Number(1), Some(REQUEST_CANCELLED)),
+            (RequestId::Number(2), Some(REQUEST_CANCELLED)),
+            (RequestId::Number(3), None),
+            (RequestId::Number(4), None),
+        ]
+    );
+    assert_eq!(
+        responses[2].result.as_ref().unwrap()["items"][0]["text"],
+        "git status"
+    );
+    // One call for the burst, one for the other directory
+    assert_eq!(std::fs::read_to_string(&calls).unwrap(), "call\ncall\n");
+    let _ = std::fs::remove_file(&calls);
+}
+
+#[tokio::test]
+async fn test_hooks_filter_requests_and_history() {
+    let socket = format!("/tmp/murmur-test-hooks-{}.sock", std::process::id());
+    let dir = std::env::temp_dir().join(format!("murmur-test-hooks-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for (name, body) in [
+        // Fix a typo before the provider sees it
+        (
+            "on_request.rhai",
+            r#"request.input.replace("gti ", "git "); request"#,
+        ),
+        // Keep commands with credentials out of history
+        (
+            "on_history_record.rhai",
+            r#"if record.command.contains("TOKEN=") { false }"#,
+        ),
+    ] {
+        std::fs::write(dir.join(name), body).unwrap();
+    }
+    let mut config = test_config(&socket);
+    config.hooks.enabled = true;
+    config.hooks.dir = dir.display().to_string();
+    config.hooks.timeout_ms = 2000;
+    let mut echoing = plugin_answering("ECHO");
+    // Answer with the input the provider was given
+    echoing.command = echoing.command.map(|c| {
+        c.replacen(
+            "do ",
+            r#"do input=$(printf '%s' "$line" | sed -E 's/.*"input":"([^"]*)".*/\1/'); "#,
+            1,
+        )
+        .replace("ECHO", r#"'"$input"'"#)
+    });
+    config.providers.insert("echoing".to_string(), echoing);
+
+    start_test_server(config).await;
+
+    let params = serde_json::json!({
+        "input": "gti st",
+        "cursor_pos": 6,
+        "cwd": "/tmp/hooks",
+        "shell": "zsh",
+    });
+    let response = send_request(&socket, methods::COMPLETE, Some(params)).await;
+    assert_eq!(response.result.unwrap()["items"][0]["text"], "git st");
+
+    for command in ["make deploy", "TOKEN=abc make deploy"] {
+        let params = serde_json::json!({"source": "zsh", "command": command, "cwd": "/tmp/hooks"});
+        send_request(&socket, methods::CONTEXT_UPDATE, Some(params)).await;
+    }
+    let response = send_request(&socket, methods::HISTORY_LIST, None).await;
+    let entries = response.result.unwrap();
+    let commands: Vec<&str> = entries
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["command"].as_str().unwrap())
+        .collect();
+    assert_eq!(commands, ["make deploy"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_abbreviations_expand_defined_and_learned() {
+    let socket = format!("/tmp/murmur-test-abbr-{}.sock", std::process::id());
+    let mut config = test_config(&socket);
+    config.abbreviations.learn = true;
+    config.abbreviations.min_uses = 3;
+    config
+        .abbreviations
+        .define
+        .insert("gco".to_string(), "git checkout".to_string());
+    start_test_server(config).await;
+
+    for _ in 0..3 {
+        let params = serde_json::json!({
+            "source": "zsh",
+            "command": "zorkctl quest --fast",
+            "cwd": "/tmp/abbr",
+            "exit_code": 0,
+        });
+        send_request(&socket, methods::CONTEXT_UPDATE, Some(params)).await;
+    }
+
+    let expand = |input: &str| {
+        let cursor_pos = input.chars().count();
+        serde_json::json!({"input": input, "cursor_pos": cursor_pos})
+    };
+    let response = send_request(&socket, methods::ABBR_EXPAND, Some(expand("gco"))).await;
+    let expanded: AbbrExpandResponse = serde_json::from_value(response.result.unwrap()).unwrap();
+    assert!(expanded.expanded);
+    assert_eq!(expanded.input, "git checkout");
+
+    // Learned in the background after the first expansion
+    let mut tries = 0;
+    let expanded = loop {
+        let response =
+            send_request(&socket, methods::ABBR_EXPAND, Some(expand("cd /tmp; zq"))).await;
+        let expanded: AbbrExpandResponse =
+            serde_json::from_value(response.result.unwrap()).unwrap();
+        tries += 1;
+        if expanded.expanded || tries == 50 {
+            break expanded;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    };
+    assert_eq!(expanded.input, "cd /tmp; zorkctl quest");
+    assert_eq!(expanded.cursor_pos, 22);
+
+    // An argument isn't expanded
+    let response = send_request(&socket, methods::ABBR_EXPAND, Some(expand("echo gco"))).await;
+    let expanded: AbbrExpandResponse = serde_json::from_value(response.result.unwrap()).unwrap();
+    assert!(!expanded.expanded);
+    assert_eq!(expanded.input, "echo gco");
+
+    let response = send_request(&socket, methods::ABBR_LIST, None).await;
+    let list: AbbrListResponse = serde_json::from_value(response.result.unwrap()).unwrap();
+    let names: Vec<(&str, bool)> = list
+        .abbreviations
+        .iter()
+        .map(|a| (a.name.as_str(), a.learned))
+        .collect();
+    assert_eq!(names, [("gco", false), ("zq", true)]);
+}
+
+#[tokio::test]
+async fn test_commit_suggest_describes_staged_changes() {
+    let socket = format!("/tmp/murmur-test-commit-{}.sock", std::process::id());
+    let repo = std::env::temp_dir().join(format!("murmur-test-commit-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&repo);
+    std::fs::create_dir_all(&repo).unwrap();
+    let git = |args: &[&str]| {
+        let status = std::process::Command::new("git")
+            .args(args)
+            .current_dir(&repo)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {args:?}");
+    };
+    git(&["init", "-q"]);
+
+    let mut config = test_config(&socket);
+    let mut writer = plugin_answering("unused");
+    // Answer `generate` with a message, whatever the prompt
+    writer.command = writer.command.map(|c| {
+        c.replace(
+            r#""result":[{"text":"unused","kind":"command","score":0.9}]"#,
+            r#""result":" feat(greet): add hello script""#,
+        )
+    });
+    config.providers.insert("writer".to_string(), writer);
+    start_test_server(config).await;
+
+    let params = serde_json::json!({"cwd": repo.display().to_string()});
+    let response = send_request(&socket, methods::COMMIT_SUGGEST, Some(params.clone())).await;
+    assert_eq!(response.error.unwrap().message, "Nothing staged to commit");
+
+    std::fs::write(repo.join("hello.sh"), "echo hello\n").unwrap();
+    git(&["add", "hello.sh"]);
+    let response = send_request(&socket, methods::COMMIT_SUGGEST, Some(params)).await;
+    let suggestion: CommitSuggestResponse =
+        serde_json::from_value(response.result.expect("a message")).unwrap();
+    assert_eq!(suggestion.message, "feat(greet): add hello script");
+    assert_eq!(suggestion.provider, "writer");
+    assert!(!suggestion.truncated);
+
+    let params = serde_json::json!({"cwd": "/"});
+    let response = send_request(&socket, methods::COMMIT_SUGGEST, Some(params)).await;
+    assert_eq!(response.error.unwrap().message, "Not a git repository");
+    std::fs::remove_dir_all(&repo).unwrap();
+}
+
+#[tokio::test]
+async fn test_summarize_commits_drafts_from_a_range() {
+    let socket = format!("/tmp/murmur-test-draft-{}.sock", std::process::id());
+    let repo = std::env::temp_dir().join(format!("murmur-test-draft-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&repo);
+    std::fs::create_dir_all(&repo).unwrap();
+    let git = |args: &[&str]| {
+        let output = std::process::Command::new("git")
+            .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(&repo)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {args:?}");
+    };
+    git(&["init", "-q"]);
+    git(&["commit", "-q", "--allow-empty", "-m", "chore: start"]);
+    git(&["tag", "v1"]);
+    git(&["commit", "-q", "--allow-empty", "-m", "feat: add greeting"]);
+
+    let mut config = test_config(&socket);
+    let mut writer = plugin_answering("unused");
+    writer.command = writer.command.map(|c| {
+        c.replace(
+            r#""result":[{"text":"unused","kind":"command","score":0.9}]"#,
+            r####""result":"### Added - A greeting""####,
+        )
+    });
+    config.providers.insert("writer".to_string(), writer);
+    start_test_server(config).await;
+
+    let cwd = repo.display().to_string();
+    let params = serde_json::json!({"cwd": cwd, "range": "v1", "kind": "changelog"});
+    let response = send_request(&socket, methods::SUMMARIZE_COMMITS, Some(params)).await;
+    let draft: SummarizeCommitsResponse =
+        serde_json::from_value(response.result.expect("a draft")).unwrap();
+    assert_eq!(draft.text, "### Added - A greeting");
+    assert_eq!(draft.commits, 1);
+
+    for (range, error) in [
+        ("HEAD..HEAD", "No commits in HEAD..HEAD"),
+        ("--output=/tmp/x", "Invalid revision range: --output=/tmp/x"),
+    ] {
+        let params = serde_json::json!({"cwd": cwd, "range": range});
+        let response = send_request(&socket, methods::SUMMARIZE_COMMITS, Some(params)).await;
+        assert_eq!(response.error.unwrap().message, error);
+    }
+    std::fs::remove_dir_all(&repo).unwrap();
+}
+
+#[tokio::test]
+async fn test_config_reload_rebuilds_providers() {
+    let socket = format!("/tmp/murmur-test-reload-{}.sock", std::process::id());
+    let path = std::env::temp_dir().join(format!("murmur-test-reload-{}.toml", std::process::id()));
+    let daemon = format!(
+        "[daemon]\nsocket_path = \"{socket}\"\nstate_path = \"\"\nhealth_check_interval_secs = 0\n"
+    );
+    std::fs::write(&path, format!("{daemon}log_level = \"warn\"\n")).unwrap();
+    let server = Server::new(Config::load_from(&path).unwrap()).watch_config(path.clone());
+    tokio::spawn(async move {
+        let _ = server.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let status = send_request(&socket, methods::STATUS, None)
+        .await
+        .result
+        .unwrap();
+    assert_eq!(status["providers_active"], serde_json::json!([]));
+    assert_eq!(status["restricted"], false);
+    assert_eq!(status["budget_limit"], serde_json::Value::Null);
+
+    std::fs::write(
+        &path,
+        format!(
+            "{daemon}log_level = \"error\"\ncache_size = 5\n\n[context]\nwatch_directories = false\n\n[providers.alpha]\ncommand = \"true\"\n\n[policy]\nallowed_prefixes = [\"git status\"]\n\n[budget]\ndaily_requests = 3\n"
+        ),
+    )
+    .unwrap();
+    let response = send_request(&socket, methods::CONFIG_RELOAD, None).await;
+    assert!(response.error.is_none(), "{:?}", response.error);
+    let reload: ConfigReloadResponse = serde_json::from_value(response.result.unwrap()).unwrap();
+    assert_eq!(reload.providers, ["alpha"]);
+    assert_eq!(reload.cache_size, 5);
+    assert_eq!(
+        reload.restart_needed,
+        ["context.watch_directories", "daemon.log_level"]
+    );
+
+    // The same socket answers, with the new providers, policy and budget
+    let status = send_request(&socket, methods::STATUS, None)
+        .await
+        .result
+        .unwrap();
+    assert_eq!(status["providers_active"], serde_json::json!(["alpha"]));
+    assert_eq!(status["restricted"], true);
+    assert_eq!(status["budget_limit"], 3);
+
+    // A broken file changes nothing
+    std::fs::write(&path, "[daemon\n").unwrap();
+    let response = send_request(&socket, methods::CONFIG_RELOAD, None).await;
+    assert!(response
+        .error
+        .unwrap()
+        .message
+        .starts_with("Failed to load"));
+    let status = send_request(&socket, methods::STATUS, None).await;
+    assert_eq!(
+        status.result.unwrap()["providers_active"],
+        serde_json::json!(["alpha"])
+    );
+
+    let _ = std::fs::remove_file(&path);
+    let _ = std::fs::remove_file(&socket);
+}
+
+#[tokio::test]
+async fn test_branch_suggest_names_a_new_branch() {
+    let socket = format!("/tmp/murmur-test-branch-{}.sock", std::process::id());
+    let repo = std::env::temp_dir().join(format!("murmur-test-branch-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&repo);
+    std::fs::create_dir_all(&repo).unwrap();
+    let git = |args: &[&str]| {
+        let status = std::process::Command::new("git")
+            .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(&repo)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {args:?}");
+    };
+    git(&["init", "-q"]);
+    git(&["commit", "-q", "--allow-empty", "-m", "chore: start"]);
+    git(&["branch", "bugfix/PROJ-42-login-timeout"]);
+
+    let mut config = test_config(&socket);
+    config
+        .branch
+        .prefixes
+        .insert("fix".to_string(), "bugfix".to_string());
+    let mut namer = plugin_answering("unused");
+    namer.command = namer.command.map(|c| {
+        c.replace(
+            r#""result":[{"text":"unused","kind":"command","score":0.9}]"#,
+            r#""result":"fix: Login timeout""#,
+        )
+    });
+    config.providers.insert("namer".to_string(), namer);
+    start_test_server(config).await;
+
+    let params = serde_json::json!({
+        "description": "the login times out on slow networks",
+        "clipboard": "https://acme.atlassian.net/browse/PROJ-42",
+        "cwd": repo.display().to_string(),
+    });
+    let response = send_request(&socket, methods::BRANCH_SUGGEST, Some(params)).await;
+    let branch: BranchSuggestResponse =
+        serde_json::from_value(response.result.expect("a name")).unwrap();
+    assert_eq!(branch.name, "bugfix/PROJ-42-login-timeout-2");
+    assert_eq!(branch.kind, "fix");
+    assert_eq!(branch.ticket.as_deref(), Some("PROJ-42"));
+    assert_eq!(branch.provider.as_deref(), Some("namer"));
+
+    let params = serde_json::json!({"description": "  "});
+    let response = send_request(&socket, methods::BRANCH_SUGGEST, Some(params)).await;
+    assert_eq!(
+        response.error.unwrap().message,
+        "Nothing to name the branch after; describe the work"
+    );
+    std::fs::remove_dir_all(&repo).unwrap();
+}
+
+fn free_port() -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap().to_string()
+}
+
+fn admin_token() -> murmur_daemon::config::ApiToken {
+    murmur_daemon::config::ApiToken {
+        name: "admin".to_string(),
+        token: "mmr_admin".to_string(),
+        scopes: vec![Scope::Admin],
+    }
+}
+
+#[tokio::test]
+async fn test_network_listeners_need_a_token() {
+    let socket = format!("/tmp/murmur-test-tokenless-{}.sock", std::process::id());
+    let mut config = test_config(&socket);
+    config.daemon.listen_tcp = Some(free_port());
+    let error = Server::new(config).run().await.unwrap_err();
+    assert!(error.to_string().contains("daemon.tokens"), "{error}");
+    assert!(!std::path::Path::new(&socket).exists());
+}
+
+#[tokio::test]
+async fn test_tcp_and_websocket_listeners() {
+    let socket = format!("/tmp/murmur-test-listen-{}.sock", std::process::id());
+    let mut config = test_config(&socket);
+    let (tcp, ws) = (free_port(), free_port());
+    config.daemon.listen_tcp = Some(tcp.clone());
+    config.daemon.listen_ws = Some(ws.clone());
+    config.daemon.tokens = vec![admin_token()];
+    start_test_server(config).await;
+
+    let initialize = JsonRpcRequest::new(
+        methods::INITIALIZE,
+        Some(serde_json::json!({ "token": "mmr_admin" })),
+        RequestId::Number(1),
+    );
+    let initialize = serde_json::to_string(&initialize).unwrap();
+    let request = JsonRpcRequest::new(methods::STATUS, None, RequestId::Number(2));
+    let json = serde_json::to_string(&request).unwrap();
+
+    let stream = tokio::net::TcpStream::connect(&tcp).await.unwrap();
+    let (reader, mut writer) = stream.into_split();
+    writer
+        .write_all(format!("{initialize}\n{json}\n").as_bytes())
+        .await
+        .unwrap();
+    let mut reader = BufReader::new(reader);
+    let mut line = String::new();
+    reader.read_line(&mut line).await.unwrap();
+    line.clear();
+    reader.read_line(&mut line).await.unwrap();
+    let response: JsonRpcResponse = serde_json::from_str(&line).unwrap();
+    assert_eq!(response.result.unwrap()["status"], "running");
+
+    use futures_util::{SinkExt, StreamExt};
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+    use tokio_tungstenite::tungstenite::{Error, Message};
+
+    let url = format!("ws://{ws}/");
+    let stream = tokio::net::TcpStream::connect(&ws).await.unwrap();
+    let (mut client, _) = tokio_tungstenite::client_async(url.as_str(), stream)
+        .await
+        .unwrap();
+    for (message, expected) in [(&initialize, None), (&json, Some("running"))] {
+        client.send(Message::Text(message.clone())).await.unwrap();
+        let Some(Ok(Message::Text(reply))) = client.next().await else {
+            panic!("no text reply to {message}");
+        };
+        let response: JsonRpcResponse = serde_json::from_str(&reply).unwrap();
+        let result = response.result.unwrap();
+        if let Some(status) = expected {
+            assert_eq!(result["status"], status);
+        }
+    }
+
+    // Web pages not in ws_origins are turned away
+    let mut request = url.as_str().into_client_request().unwrap();
+    request
+        .headers_mut()
+        .insert("Origin", "https://example.com".parse().unwrap());
+    let stream = tokio::net::TcpStream::connect(&ws).await.unwrap();
+    match tokio_tungstenite::client_async(request, stream).await {
+        Err(Error::Http(response)) => assert_eq!(response.status(), 403),
+        other => panic!("page origin accepted: {:?}", other.map(|(_, r)| r.status())),
+    }
+
+    let _ = std::fs::remove_file(&socket);
+}
+
+#[tokio::test]
+async fn test_expr_suggest_writes_a_one_liner() {
+    let socket = format!("/tmp/murmur-test-expr-{}.sock", std::process::id());
+    let mut config = test_config(&socket);
+    let mut writer = plugin_answering("unused");
+    writer.command = writer.command.map(|c| {
+        c.replace(
+            r#""result":[{"text":"unused","kind":"command","score":0.9}]"#,
+            r#""result":".items[].name""#,
+        )
+    });
+    config.providers.insert("writer".to_string(), writer);
+    start_test_server(config).await;
+
+    let sample: String = (0..500)
+        .map(|i| format!("{{\"items\":[{{\"name\":\"item {i}\"}}]}}\n"))
+        .collect();
+    let params = serde_json::json!({
+        "tool": "jq",
+        "description": "the name of each item",
+        "sample": sample,
+    });
+    let response = send_request(&socket, methods::EXPR_SUGGEST, Some(params)).await;
+    let expr: ExprSuggestResponse =
+        serde_json::from_value(response.result.expect("a one-liner")).unwrap();
+    assert_eq!(expr.command, "jq '.items[].name'");
+    assert_eq!(expr.provider, "writer");
+    assert!(expr.sample_truncated);
+
+    let params = serde_json::json!({"tool": "perl", "description": "names"});
+    let response = send_request(&socket, methods::EXPR_SUGGEST, Some(params)).await;
+    assert!(response
+        .error
+        .unwrap()
+        .message
+        .starts_with("Invalid expr/suggest params"));
+    let _ = std::fs::remove_file(&socket);
+}
+
+#[tokio::test]
+async fn test_regex_suggest_prefers_a_pattern_that_matches() {
+    let socket = format!("/tmp/murmur-test-regex-{}.sock", std::process::id());
+    let mut config = test_config(&socket);
+    for (name, pattern) in [("dates", "[0-9]{4}-[0-9]{2}-[0-9]{2}"), ("misses", "ZZZ")] {
+        let mut provider = plugin_answering("unused");
+        provider.command = provider.command.map(|c| {
+            c.replace(
+                r#""result":[{"text":"unused","kind":"command","score":0.9}]"#,
+                &format!(r#""result":"{pattern}""#),
+            )
+        });
+        config.providers.insert(name.to_string(), provider);
+    }
+    start_test_server(config).await;
+
+    let params = serde_json::json!({
+        "description": "ISO dates in log lines",
+        "sample": "2024-05-01 boot ok\nno date\nfrom 2024-05-02 to 2024-05-03\n",
+    });
+    let response = send_request(&socket, methods::REGEX_SUGGEST, Some(params)).await;
+    let regex: RegexSuggestResponse =
+        serde_json::from_value(response.result.expect("a regex")).unwrap();
+    assert_eq!(regex.pattern, "[0-9]{4}-[0-9]{2}-[0-9]{2}");
+    assert_eq!(regex.provider, "dates");
+    assert_eq!((regex.matched_lines, regex.sample_lines), (2, 3));
+    assert_eq!(regex.previews[1].line_number, 3);
+    assert_eq!(regex.previews[1].spans, vec![[5, 15], [19, 29]]);
+    let _ = std::fs::remove_file(&socket);
+}
+
+#[tokio::test]
+async fn test_cron_suggest_checks_the_schedule() {
+    let socket = format!("/tmp/murmur-test-cron-{}.sock", std::process::id());
+    let mut config = test_config(&socket);
+    for (name, schedule) in [("weekdays", "0 18 * * 1-5"), ("never", "0 0 30 2 *")] {
+        let mut provider = plugin_answering("unused");
+        provider.command = provider.command.map(|c| {
+            c.replace(
+                r#""result":[{"text":"unused","kind":"command","score":0.9}]"#,
+                &format!(r#""result":"{schedule}""#),
+            )
+        });
+        config.providers.insert(name.to_string(), provider);
+    }
+    let now = 1_700_000_000;
+    let server = Server::with_clock(config, Arc::new(ManualClock::new(now)));
+    tokio::spawn(async move {
+        let _ = server.run().await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let params = serde_json::json!({
+        "description": "every weekday at 6pm",
+        "command": "~/bin/backup",
+    });
+    let response = send_request(&socket, methods::CRON_SUGGEST, Some(params)).await;
+    let cron: CronSuggestResponse =
+        serde_json::from_value(response.result.expect("a schedule")).unwrap();
+    assert_eq!(cron.schedule, "0 18 * * 1-5");
+    assert_eq!(cron.line, "0 18 * * 1-5 ~/bin/backup");
+    assert_eq!(cron.provider, "weekdays");
+    assert_eq!(cron.next_runs.len(), 3);
+    assert!(cron.next_runs[0] > now && cron.next_runs[2] < now + 5 * 86_400);
+    assert!(cron.next_runs.windows(2).all(|w| w[0] < w[1]));
+    let _ = std::fs::remove_file(&socket);
+}
+
+#[tokio::test]
+async fn test_network_clients_need_a_token_with_the_scope() {
+    use murmur_daemon::config::ApiToken;
+
+    let socket = format!("/tmp/murmur-test-tokens-{}.sock", std::process::id());
+    let mut config = test_config(&socket);
+    let tcp = free_port();
+    config.daemon.listen_tcp = Some(tcp.clone());
+    config.daemon.tokens = vec![ApiToken {
+        name: "thin".to_string(),
+        token: "mmr_thin".to_string(),
+        scopes: vec![Scope::Complete],
+    }];
+    start_test_server(config).await;
+
+    let stream = tokio::net::TcpStream::connect(&tcp).await.unwrap();
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    async fn call(
+        reader: &mut BufReader<tokio::net::tcp::OwnedReadHalf>,
+        writer: &mut tokio::net::tcp::OwnedWriteHalf,
+        method: &str,
+        params: Option<serde_json::Value>,
+    ) -> JsonRpcResponse {
+        let request = JsonRpcRequest::new(method, params, RequestId::Number(1));
+        let json = serde_json::to_string(&request).unwrap();
+        writer
+            .write_all(format!("{json}\n").as_bytes())
+            .await
+            .unwrap();
+        let mut line = String::new();
+        reader.read_line(&mut line).await.unwrap();
+        serde_json::from_str(&line).unwrap()
+    }
+
+    // Nothing but setting up the connection before a token
+    let response = call(&mut reader, &mut writer, methods::HEALTH, None).await;
+    assert_eq!(response.error.unwrap().code, UNAUTHORIZED);
+    let response = call(&mut reader, &mut writer, methods::SHUTDOWN, None).await;
+    assert_eq!(response.error.unwrap().code, UNAUTHORIZED);
+    let response = call(&mut reader, &mut writer, methods::PING, None).await;
+    assert!(response.result.is_some());
+
+    let wrong = serde_json::json!({ "token": "mmr_guess" });
+    let response = call(&mut reader, &mut writer, methods::INITIALIZE, Some(wrong)).await;
+    assert_eq!(response.error.unwrap().message, "Unknown token");
+
+    let right = serde_json::json!({ "token": "mmr_thin" });
+    let response = call(&mut reader, &mut writer, methods::INITIALIZE, Some(right)).await;
+    let features: InitializeResponse = serde_json::from_value(response.result.unwrap()).unwrap();
+    assert_eq!(features.scopes, [Scope::Complete]);
+
+    let response = call(&mut reader, &mut writer, methods::HEALTH, None).await;
+    assert!(response.result.is_some());
+    let response = call(&mut reader, &mut writer, methods::HISTORY_LIST, None).await;
+    let error = response.error.unwrap();
+    assert_eq!(error.code, UNAUTHORIZED);
+    assert!(error.message.contains("history scope"), "{}", error.message);
+    let response = call(&mut reader, &mut writer, methods::SHUTDOWN, None).await;
+    assert_eq!(response.error.unwrap().code, UNAUTHORIZED);
+    // Reading a repository or a corpus anywhere takes more than `complete`
+    let params = serde_json::json!({ "cwd": "/" });
+    let response = call(
+        &mut reader,
+        &mut writer,
+        methods::COMMIT_SUGGEST,
+        Some(params),
+    )
+    .await;
+    let error = response.error.unwrap();
+    assert_eq!(error.code, UNAUTHORIZED);
+    assert!(error.message.contains("repo scope"), "{}", error.message);
+    let params = serde_json::json!({ "corpus_dir": "/" });
+    let response = call(
+        &mut reader,
+        &mut writer,
+        methods::VOICE_SELFTEST,
+        Some(params),
+    )
+    .await;
+    assert_eq!(response.error.unwrap().code, UNAUTHORIZED);
+
+    // The Unix socket is unaffected
+    let response = send_request(&socket, methods::STATUS, None).await;
+    assert_eq!(response.result.unwrap()["status"], "running");
+
+    let _ = std::fs::remove_file(&socket);
+}
@@ -0,0 +1,40 @@
+use serde::{Deserialize, Serialize};
+
+/// Expand the abbreviation just typed, if there is one. Sent by the shell
+/// widget on space and Enter.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AbbrExpandRequest {
+    /// The line up to the cursor, or the whole line.
+    pub input: String,
+    /// Cursor position in characters; the word ending here is expanded.
+    pub cursor_pos: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AbbrExpandResponse {
+    /// Whether `input` changed.
+    pub expanded: bool,
+    /// The line with the abbreviation replaced (unchanged if it wasn't).
+    pub input: String,
+    /// Where the cursor goes, in characters.
+    pub cursor_pos: usize,
+    /// The abbreviation that was expanded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub abbreviation: Option<String>,
+}
+
+/// One abbreviation and what it expands to.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Abbreviation {
+    pub name: String,
+    pub expansion: String,
+    /// Learned from history rather than defined in the config.
+    #[serde(default)]
+    pub learned: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AbbrListResponse {
+    /// Sorted by name.
+    pub abbreviations: Vec<Abbreviation>,
+}
@@ -0,0 +1,94 @@
+use serde::{Deserialize, Serialize};
+
+/// Write a commit message for what is staged in a repository.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CommitSuggestRequest {
+    /// A directory inside the repository.
+    pub cwd: String,
+    /// Describe the commit `git commit --amend` would make: the staged
+    /// changes together with the last commit's.
+    #[serde(default)]
+    pub amend: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CommitSuggestResponse {
+    /// The message, in Conventional Commits form: a `type(scope): summary`
+    /// subject, optionally followed by a blank line and a body.
+    pub message: String,
+    /// Provider that wrote it.
+    pub provider: String,
+    pub latency_ms: u64,
+    /// The diff was too large to send whole, so the message was written
+    /// from part of it and the list of changed files.
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+/// What `summarize/commits` drafts.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DraftKind {
+    /// A pull request title and description.
+    #[default]
+    Pr,
+    /// A changelog section grouped into Added / Changed / Fixed / Removed.
+    Changelog,
+}
+
+/// Draft a PR description or changelog section from a range of commits.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SummarizeCommitsRequest {
+    /// A directory inside the repository.
+    pub cwd: String,
+    /// `base..head`, `base...head`, or a single ref meaning `ref..HEAD`.
+    pub range: String,
+    #[serde(default)]
+    pub kind: DraftKind,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SummarizeCommitsResponse {
+    /// The draft, in Markdown.
+    pub text: String,
+    /// Commits in the range.
+    pub commits: usize,
+    /// Provider that wrote it.
+    pub provider: String,
+    pub latency_ms: u64,
+    /// The range had too many commits or too long messages to send whole.
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+/// Name a branch for a piece of work.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BranchSuggestRequest {
+    /// What the work is, e.g. "PROJ-42 fix login timeout on slow networks".
+    pub description: String,
+    /// More text to find a ticket in if the description has none, such as
+    /// the clipboard holding an issue URL.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clipboard: Option<String>,
+    /// A directory inside the repository, so the name doesn't clash with
+    /// an existing branch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BranchSuggestResponse {
+    /// The branch name, following `[branch] pattern`.
+    pub name: String,
+    /// Change type the name's prefix came from (feat, fix, ...).
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Ticket found in the description or clipboard.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ticket: Option<String>,
+    /// Provider that summarized the description; none when its own words
+    /// were used because no provider answered.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    pub latency_ms: u64,
+}
@@ -1,3 +1,4 @@
+use crate::RiskLevel;
 use serde::{Deserialize, Serialize};
 
 /// Request for shell command completion.
@@ -15,6 +16,47 @@ pub struct CompletionRequest {
     /// Shell type (zsh, bash, fish).
     #[serde(default)]
     pub shell: Option<String>,
+    /// Maximum number of suggestions to return (overrides the daemon config).
+    #[serde(default)]
+    pub max_items: Option<usize>,
+    /// Minimum score a suggestion needs to be returned (overrides the daemon config).
+    #[serde(default)]
+    pub min_score: Option<f64>,
+    /// Hostname reported by shell integration running inside an SSH session.
+    /// Context, history and cached suggestions are kept separate per host.
+    #[serde(default)]
+    pub host: Option<String>,
+    /// Ask this provider (e.g. "ollama") only, bypassing routing and
+    /// failover. Must be an active provider.
+    #[serde(default)]
+    pub provider: Option<String>,
+    /// Model to use instead of the provider's configured one. Model names
+    /// are provider-specific, so this needs `provider`.
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Client-chosen name for this request, so `complete/cancel` can abort
+    /// it while providers are still answering. A newer request with the
+    /// same token cancels the older one, so a shell can use one per session.
+    #[serde(default)]
+    pub cancel_token: Option<String>,
+    /// Session from `session/open`, or an ID the shell picked like
+    /// `zsh-$$`, for per-session ranking and predictions.
+    #[serde(default)]
+    pub session_id: Option<String>,
+}
+
+/// Parameters of `complete/cancel`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CancelRequest {
+    /// The `cancel_token` of the completion to abort.
+    pub token: String,
+}
+
+/// Result of `complete/cancel`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CancelResponse {
+    /// Whether a completion with that token was still running.
+    pub cancelled: bool,
 }
 
 /// A single completion suggestion.
@@ -30,12 +72,101 @@ pub struct CompletionItem {
     /// Confidence score from 0.0 to 1.0.
     #[serde(default = "default_score")]
     pub score: f64,
+    /// Where the suggestion came from and how safe it is, for badges and
+    /// filters. Serialized inline; every field is optional.
+    #[serde(flatten, default)]
+    pub metadata: CompletionMetadata,
 }
 
 fn default_score() -> f64 {
     1.0
 }
 
+/// Provenance and safety details of a suggestion.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct CompletionMetadata {
+    /// Provider that generated the suggestion (absent for local sources).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    /// Whether it was generated now, replayed from cache, or mined from
+    /// history or project state.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<CompletionOrigin>,
+    /// Risk of running it, from the daemon's safety checker.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub risk: Option<RiskLevel>,
+    /// Whether the program it runs was found on the machine. Absent when
+    /// not checked, e.g. for shells on a remote host.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verified: Option<bool>,
+    /// Section the suggestion belongs to in a grouped list.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<CompletionGroup>,
+    /// The part of the text that varies across similar history commands,
+    /// for suggestions collapsed from a family like `ssh web1`/`ssh web2`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parameter: Option<CompletionParameter>,
+}
+
+/// A parameter slot in a suggestion: the pre-filled value's byte range in
+/// the text and the other values seen there, most likely first.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CompletionParameter {
+    pub start: usize,
+    pub end: usize,
+    pub alternates: Vec<String>,
+}
+
+/// Section of a suggestion list, for UIs that render suggestions under
+/// headings (tmux popups, TUIs, editors).
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CompletionGroup {
+    /// Commands mined from history.
+    History,
+    /// Generated by a provider.
+    Ai,
+    /// File and directory paths.
+    Paths,
+    /// Derived from project state.
+    Project,
+}
+
+impl CompletionGroup {
+    /// Heading to show above the group.
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::History => "From your history",
+            Self::Ai => "AI suggestions",
+            Self::Paths => "Paths",
+            Self::Project => "Project",
+        }
+    }
+}
+
+/// A group present in a response, in the order its first item appears.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct GroupHeader {
+    pub group: CompletionGroup,
+    pub label: String,
+    /// Items in the group.
+    pub count: usize,
+}
+
+/// Source of a suggestion.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CompletionOrigin {
+    /// Generated by a provider for this request.
+    Llm,
+    /// A provider's earlier answer served from the completion cache.
+    Cache,
+    /// Learned from command history (sequence predictions, re-runs).
+    History,
+    /// Derived from project state, such as uncommitted git changes.
+    Context,
+}
+
 /// Type of completion suggestion.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
@@ -63,6 +194,68 @@ pub struct CompletionResponse {
     pub latency_ms: u64,
     /// Whether this result came from cache.
     pub cached: bool,
+    /// Set while suggestions are worse than usual, so the shell can explain
+    /// why (once, not on every keystroke).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notice: Option<DegradationNotice>,
+    /// The groups `items` fall into; each item names its own in
+    /// `metadata.group`. Items stay ordered by relevance, not by group.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<GroupHeader>,
+    /// These items were cached for a shorter input and filtered to the
+    /// ones still matching; fresh completions for this input follow as a
+    /// `completion/ready` notification.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub refreshing: bool,
+}
+
+/// Why the daemon can't reach its usual providers.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Degradation {
+    /// Every cloud provider is failing.
+    CloudDown,
+    /// Today's cloud request budget is spent.
+    BudgetExhausted,
+    /// No cloud provider can be reached over the network.
+    Offline,
+}
+
+/// A degradation state and a one-line explanation for the user.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DegradationNotice {
+    pub state: Degradation,
+    pub message: String,
+}
+
+impl DegradationNotice {
+    pub fn new(state: Degradation) -> Self {
+        let message = match state {
+            Degradation::CloudDown => {
+                "cloud providers are failing, suggestions may be limited to local models"
+            }
+            Degradation::BudgetExhausted => {
+                "daily cloud budget used up, suggestions are limited to local models until tomorrow"
+            }
+            Degradation::Offline => "offline, suggestions are limited to local models",
+        };
+        Self {
+            state,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Plain-text completion candidates for synchronous shell integrations
+/// (e.g. bash's `COMPREPLY`), one single-line entry per suggestion.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CandidatesResponse {
+    /// Candidate command lines, ordered by relevance, without duplicates.
+    pub candidates: Vec<String>,
+    /// Which provider generated these candidates.
+    pub provider: String,
+    /// Whether this result came from cache.
+    pub cached: bool,
 }
 
 #[cfg(test)]
@@ -77,11 +270,33 @@ mod tests {
             cwd: "/home/user/project".to_string(),
             history: vec!["git status".to_string(), "git add .".to_string()],
             shell: Some("zsh".to_string()),
+            max_items: Some(1),
+            min_score: None,
+            host: Some("devbox".to_string()),
+            provider: Some("ollama".to_string()),
+            model: Some("qwen2.5-coder:7b".to_string()),
+            cancel_token: Some("zsh-4242".to_string()),
+            session_id: Some("zsh-4242".to_string()),
         };
         let json = serde_json::to_string(&req).unwrap();
         let roundtrip: CompletionRequest = serde_json::from_str(&json).unwrap();
         assert_eq!(roundtrip.input, "git c");
         assert_eq!(roundtrip.cursor_pos, 5);
+        assert_eq!(roundtrip.max_items, Some(1));
+        assert_eq!(roundtrip.provider.as_deref(), Some("ollama"));
+        assert_eq!(roundtrip.model.as_deref(), Some("qwen2.5-coder:7b"));
+        assert_eq!(roundtrip.host.as_deref(), Some("devbox"));
+        assert_eq!(roundtrip.cancel_token.as_deref(), Some("zsh-4242"));
+        assert_eq!(roundtrip.session_id.as_deref(), Some("zsh-4242"));
+    }
+
+    #[test]
+    fn completion_request_limits_default_to_none() {
+        let json = r#"{"input": "ls", "cursor_pos": 2, "cwd": "/tmp"}"#;
+        let req: CompletionRequest = serde_json::from_str(json).unwrap();
+        assert!(req.max_items.is_none());
+        assert!(req.min_score.is_none());
+        assert!(req.host.is_none());
     }
 
     #[test]
@@ -93,20 +308,55 @@ mod tests {
                     description: Some("Commit staged changes".to_string()),
                     kind: CompletionKind::FullCommand,
                     score: 0.95,
+                    metadata: CompletionMetadata {
+                        provider: Some("anthropic".to_string()),
+                        origin: Some(CompletionOrigin::Llm),
+                        risk: Some(RiskLevel::Caution),
+                        verified: Some(true),
+                        group: Some(CompletionGroup::Ai),
+                        parameter: None,
+                    },
                 },
                 CompletionItem {
                     text: "git checkout".to_string(),
                     description: Some("Switch branches".to_string()),
                     kind: CompletionKind::Command,
                     score: 0.8,
+                    metadata: CompletionMetadata::default(),
                 },
             ],
             provider: "anthropic".to_string(),
             latency_ms: 120,
             cached: false,
+            notice: None,
+            groups: vec![],
+            refreshing: false,
         };
         let json = serde_json::to_string_pretty(&resp).unwrap();
+        assert!(!json.contains("notice"));
+        assert!(!json.contains("groups"));
+        assert!(json.contains("\"group\": \"ai\""));
         assert!(json.contains("git commit"));
         assert!(json.contains("\"provider\": \"anthropic\""));
+
+        // Metadata is flattened into the item and omitted when unknown
+        let roundtrip: CompletionResponse = serde_json::from_str(&json).unwrap();
+        assert_eq!(roundtrip.items[0].metadata, resp.items[0].metadata);
+        let second = serde_json::to_value(&resp.items[1]).unwrap();
+        assert!(second.get("origin").is_none());
+        let first = serde_json::to_value(&resp.items[0]).unwrap();
+        assert_eq!(first["origin"], "llm");
+        assert_eq!(first["risk"], "caution");
+    }
+
+    #[test]
+    fn degradation_notice_roundtrips() {
+        let json = r#"{"items": [], "provider": "ollama", "latency_ms": 40, "cached": false,
+            "notice": {"state": "budget_exhausted", "message": "daily cloud budget used up"}}"#;
+        let resp: CompletionResponse = serde_json::from_str(json).unwrap();
+        assert_eq!(resp.notice.unwrap().state, Degradation::BudgetExhausted);
+
+        let notice = serde_json::to_value(DegradationNotice::new(Degradation::CloudDown)).unwrap();
+        assert_eq!(notice["state"], "cloud_down");
     }
 }
@@ -0,0 +1,44 @@
+use serde::{Deserialize, Serialize};
+
+/// Compression algorithm for a connection's messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+/// Parameters for `compression/negotiate`: the algorithms the client can
+/// use, most preferred first.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompressionRequest {
+    pub algorithms: Vec<Compression>,
+}
+
+/// Response to `compression/negotiate`. With an algorithm, every later
+/// message on the connection, both ways, is a frame: a 4-byte big-endian
+/// length, then that many bytes of compressed JSON. Without one, the
+/// connection stays on plain JSON lines.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompressionResponse {
+    #[serde(default)]
+    pub algorithm: Option<Compression>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn algorithms_are_lowercase() {
+        let request = CompressionRequest {
+            algorithms: vec![Compression::Zstd, Compression::Gzip],
+        };
+        assert_eq!(
+            serde_json::to_string(&request).unwrap(),
+            r#"{"algorithms":["zstd","gzip"]}"#
+        );
+        let response: CompressionResponse = serde_json::from_str("{}").unwrap();
+        assert!(response.algorithm.is_none());
+    }
+}
@@ -0,0 +1,16 @@
+use serde::{Deserialize, Serialize};
+
+/// What a `config/reload` (or SIGHUP) applied.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ConfigReloadResponse {
+    /// The config file that was read.
+    pub path: String,
+    /// Providers active after the reload.
+    pub providers: Vec<String>,
+    pub voice_enabled: bool,
+    pub cache_size: usize,
+    /// Changed settings that only take effect after a restart, such as
+    /// `daemon.socket_path`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub restart_needed: Vec<String>,
+}
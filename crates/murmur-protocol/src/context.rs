@@ -15,6 +15,14 @@ pub struct ContextUpdateRequest {
     /// Optional session ID for grouping commands.
     #[serde(default)]
     pub session_id: Option<String>,
+    /// Hostname of the machine the command ran on, when reported from an
+    /// SSH session. Absent for local shells.
+    #[serde(default)]
+    pub host: Option<String>,
+    /// Provider of the murmur suggestion the command was run from,
+    /// unchanged. Absent for typed commands.
+    #[serde(default)]
+    pub suggested_by: Option<String>,
 }
 
 /// Request to list cross-tool command history.
@@ -23,6 +31,9 @@ pub struct HistoryListRequest {
     /// Filter by working directory (optional).
     #[serde(default)]
     pub cwd: Option<String>,
+    /// Filter by remote host (optional).
+    #[serde(default)]
+    pub host: Option<String>,
     /// Maximum number of entries to return.
     #[serde(default = "default_limit")]
     pub limit: usize,
@@ -45,6 +56,86 @@ pub struct HistoryEntry {
     pub exit_code: i32,
     /// Unix timestamp (seconds since epoch).
     pub timestamp: u64,
+    /// Session the command belongs to, if the source reported one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// Remote host the command ran on; `None` for the local machine.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    /// Provider of the accepted suggestion the command came from; `None`
+    /// when it was typed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suggested_by: Option<String>,
+}
+
+/// Parameters for the `history/import` method: previously recorded commands
+/// (e.g. from a shell's history file) to merge into the daemon's history.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryImportRequest {
+    /// Entries to import, oldest first.
+    pub entries: Vec<HistoryEntry>,
+    /// The source recorded no times, so the timestamps were made up and
+    /// differ between imports: entries are matched on command and source
+    /// instead, and only the newest of repeated commands is kept.
+    #[serde(default)]
+    pub undated: bool,
+}
+
+/// Result of the `history/import` method.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryImportResponse {
+    /// Entries added; ones already in the history are skipped.
+    pub imported: usize,
+}
+
+/// Parameters for the `history/ask` method: a plain-language question about
+/// past commands, e.g. "what did I deploy on friday".
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryAskRequest {
+    pub question: String,
+    /// Directory the question is asked from, for "in this project".
+    #[serde(default)]
+    pub cwd: Option<String>,
+    /// Maximum number of entries to return.
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// Structured history filter a question was translated into. Every field
+/// is optional; an empty filter matches everything.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HistoryFilter {
+    /// Earliest timestamp (unix seconds, inclusive).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since: Option<u64>,
+    /// Latest timestamp (unix seconds, exclusive).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<u64>,
+    /// A command matches when it contains any of these (case-insensitive).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keywords: Vec<String>,
+    /// Only commands run in this directory or below.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    /// Only commands from this source (e.g. "terminal", "claude-code").
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Only commands that exited non-zero.
+    #[serde(default)]
+    pub failed_only: bool,
+}
+
+/// Result of the `history/ask` method.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryAskResponse {
+    /// How the question was interpreted.
+    pub filter: HistoryFilter,
+    /// Matching entries, newest first.
+    pub entries: Vec<HistoryEntry>,
+    /// One or two sentences answering the question.
+    pub summary: String,
+    /// Provider that interpreted the question.
+    pub provider: String,
 }
 
 #[cfg(test)]
@@ -59,11 +150,14 @@ mod tests {
             cwd: "/home/user/project".to_string(),
             exit_code: 0,
             session_id: Some("abc123".to_string()),
+            host: None,
+            suggested_by: Some("anthropic".to_string()),
         };
         let json = serde_json::to_string(&req).unwrap();
         let parsed: ContextUpdateRequest = serde_json::from_str(&json).unwrap();
         assert_eq!(parsed.source, "claude-code");
         assert_eq!(parsed.exit_code, 0);
+        assert!(parsed.host.is_none());
     }
 
     #[test]
@@ -0,0 +1,54 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Request for the daemon's part of a diagnostic bundle.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DebugBundleRequest {
+    /// How many of the most recent request traces to include.
+    #[serde(default = "default_traces")]
+    pub traces: usize,
+}
+
+fn default_traces() -> usize {
+    50
+}
+
+impl Default for DebugBundleRequest {
+    fn default() -> Self {
+        Self {
+            traces: default_traces(),
+        }
+    }
+}
+
+/// What the daemon contributes to `murmur debug bundle`. Nothing in it
+/// holds what the user typed or said.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DebugBundle {
+    /// The `status` response, including provider health.
+    pub status: Value,
+    /// Recent log lines, oldest first, with user text redacted.
+    pub logs: Vec<String>,
+    /// Recent requests, oldest first.
+    pub traces: Vec<RequestTrace>,
+}
+
+/// How one request went, without its params.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RequestTrace {
+    /// Unix time the request arrived.
+    pub timestamp: u64,
+    pub method: String,
+    pub latency_ms: u64,
+    /// The provider that answered, for completions and queries.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    /// Number of suggestions returned.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub items: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cached: Option<bool>,
+    /// Error code, if the request failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<i32>,
+}
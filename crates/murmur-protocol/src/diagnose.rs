@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+
+/// Parameters for the `diagnose` method: a command that just exited
+/// non-zero, reported by the shell's post-command hook.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiagnoseRequest {
+    pub command: String,
+    pub exit_code: i32,
+    pub cwd: String,
+    #[serde(default)]
+    pub shell: Option<String>,
+    /// Tail of the command's error output, when the caller captured it.
+    /// Shell hooks can't; wrappers and AI tools usually can.
+    #[serde(default)]
+    pub stderr: Option<String>,
+    /// Hostname the command ran on, for shells in SSH sessions.
+    #[serde(default)]
+    pub host: Option<String>,
+}
+
+/// Result of the `diagnose` method.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DiagnoseResponse {
+    /// One-line explanation of the failure. Absent when the failure was
+    /// not diagnosed (see `skipped`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diagnosis: Option<String>,
+    /// Command that likely fixes the problem.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fix: Option<String>,
+    /// Why no diagnosis was produced, e.g. "interrupted" or "rate limited".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skipped: Option<String>,
+    /// Whether the answer came from the diagnosis cache.
+    #[serde(default)]
+    pub cached: bool,
+    /// Provider that produced the diagnosis.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skipped_response_omits_diagnosis() {
+        let response = DiagnoseResponse {
+            skipped: Some("rate limited".to_string()),
+            ..Default::default()
+        };
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"skipped": "rate limited", "cached": false})
+        );
+        let req: DiagnoseRequest =
+            serde_json::from_str(r#"{"command": "make", "exit_code": 2, "cwd": "/src"}"#).unwrap();
+        assert!(req.stderr.is_none());
+    }
+}
@@ -0,0 +1,208 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{CompletionResponse, DegradationNotice, HistoryEntry, JsonRpcNotification, VoiceStage};
+
+/// Snapshot of daemon state for status bars and other ambient UIs.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct DaemonState {
+    /// Provider that served the most recent completion.
+    #[serde(default)]
+    pub provider: Option<String>,
+    /// Cloud requests left in today's budget (`None` = unlimited).
+    #[serde(default)]
+    pub budget_remaining: Option<u32>,
+    /// Whether a voice request is currently being captured or processed.
+    #[serde(default)]
+    pub voice_active: bool,
+}
+
+/// Event pushed to `events/subscribe` clients as an `event` notification.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DaemonEvent {
+    /// A provider produced completions.
+    ProviderUsed { provider: String },
+    /// The request budget changed.
+    BudgetUpdated { remaining: Option<u32> },
+    /// Voice capture/processing started or finished.
+    VoiceActivity {
+        active: bool,
+        /// Session the recording came from, if it named one.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        session_id: Option<String>,
+    },
+    /// Progress through a recording, sent every half second or so while it
+    /// is transcribed and restructured. `percent` is an estimate of the
+    /// whole job, and reaches 100 when the result is ready.
+    VoiceProgress {
+        stage: VoiceStage,
+        percent: u8,
+        elapsed_ms: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        session_id: Option<String>,
+    },
+}
+
+/// Parameters of `events/subscribe`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EventsSubscribeRequest {
+    /// Notification methods to receive, e.g. `["completion/ready"]`. Empty
+    /// means all of them.
+    #[serde(default)]
+    pub methods: Vec<String>,
+    /// Skip events tagged with other sessions, so a pane only hears about
+    /// its own recordings. Untagged events still arrive.
+    #[serde(default)]
+    pub session_id: Option<String>,
+}
+
+/// Notification pushed to `events/subscribe` clients. Serializes to the
+/// `method` and `params` of a JSON-RPC notification.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "method", content = "params")]
+pub enum DaemonNotification {
+    /// A status change, for status bars.
+    #[serde(rename = "event")]
+    Event(DaemonEvent),
+    /// Completions that arrived after the request was answered, such as
+    /// the strong tier's refinement of a fast answer.
+    #[serde(rename = "completion/ready")]
+    CompletionReady(CompletionReady),
+    /// Cloud providers stopped answering, or (`notice` null) recovered.
+    #[serde(rename = "provider/degraded")]
+    ProviderDegraded(ProviderDegraded),
+    /// A command was added to the history.
+    #[serde(rename = "history/updated")]
+    HistoryUpdated(HistoryEntry),
+}
+
+/// Params of `completion/ready`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompletionReady {
+    /// Input the completions are for.
+    pub input: String,
+    pub cwd: String,
+    /// Remote host the request came from; `None` for the local machine.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    pub response: CompletionResponse,
+}
+
+/// Params of `provider/degraded`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderDegraded {
+    pub notice: Option<DegradationNotice>,
+}
+
+impl DaemonNotification {
+    /// The notification's JSON-RPC method.
+    pub fn method(&self) -> &'static str {
+        match self {
+            DaemonNotification::Event(_) => crate::methods::EVENT,
+            DaemonNotification::CompletionReady(_) => crate::methods::COMPLETION_READY,
+            DaemonNotification::ProviderDegraded(_) => crate::methods::PROVIDER_DEGRADED,
+            DaemonNotification::HistoryUpdated(_) => crate::methods::HISTORY_UPDATED,
+        }
+    }
+
+    /// Session the notification concerns, if it's about one session.
+    pub fn session_id(&self) -> Option<&str> {
+        match self {
+            DaemonNotification::Event(DaemonEvent::VoiceActivity { session_id, .. })
+            | DaemonNotification::Event(DaemonEvent::VoiceProgress { session_id, .. }) => {
+                session_id.as_deref()
+            }
+            _ => None,
+        }
+    }
+
+    /// The notification as a JSON-RPC message.
+    pub fn to_jsonrpc(&self) -> JsonRpcNotification {
+        // Plain structs and enums, which always serialize
+        let mut value = serde_json::to_value(self).unwrap_or_default();
+        JsonRpcNotification::new(self.method(), Some(value["params"].take()))
+    }
+}
+
+impl From<DaemonEvent> for DaemonNotification {
+    fn from(event: DaemonEvent) -> Self {
+        DaemonNotification::Event(event)
+    }
+}
+
+impl DaemonState {
+    /// Fold an event into the snapshot.
+    pub fn apply(&mut self, event: &DaemonEvent) {
+        match event {
+            DaemonEvent::ProviderUsed { provider } => self.provider = Some(provider.clone()),
+            DaemonEvent::BudgetUpdated { remaining } => self.budget_remaining = *remaining,
+            DaemonEvent::VoiceActivity { active, .. } => self.voice_active = *active,
+            DaemonEvent::VoiceProgress { .. } => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_is_internally_tagged() {
+        let event = DaemonEvent::VoiceActivity {
+            active: true,
+            session_id: None,
+        };
+        let json = serde_json::to_string(&event).unwrap();
+        assert_eq!(json, r#"{"type":"voice_activity","active":true}"#);
+
+        let event = DaemonEvent::VoiceProgress {
+            stage: VoiceStage::Transcribing,
+            percent: 40,
+            elapsed_ms: 1500,
+            session_id: Some("zsh-4242".to_string()),
+        };
+        let json = serde_json::to_string(&event).unwrap();
+        assert_eq!(
+            json,
+            r#"{"type":"voice_progress","stage":"transcribing","percent":40,"elapsed_ms":1500,"session_id":"zsh-4242"}"#
+        );
+        assert_eq!(
+            DaemonNotification::from(event).session_id(),
+            Some("zsh-4242")
+        );
+    }
+
+    #[test]
+    fn notification_is_method_and_params() {
+        let notification = DaemonNotification::from(DaemonEvent::VoiceActivity {
+            active: true,
+            session_id: None,
+        });
+        let message = notification.to_jsonrpc();
+        assert_eq!(message.method, "event");
+        assert_eq!(message.params.unwrap()["type"], "voice_activity");
+
+        let notification = DaemonNotification::ProviderDegraded(ProviderDegraded { notice: None });
+        assert_eq!(
+            serde_json::to_string(&notification).unwrap(),
+            r#"{"method":"provider/degraded","params":{"notice":null}}"#
+        );
+        let back: DaemonNotification = serde_json::from_str(
+            r#"{"method":"history/updated","params":{"command":"ls","cwd":"/","source":"zsh","exit_code":0,"timestamp":1}}"#,
+        )
+        .unwrap();
+        assert_eq!(back.method(), crate::methods::HISTORY_UPDATED);
+    }
+
+    #[test]
+    fn state_applies_events() {
+        let mut state = DaemonState::default();
+        state.apply(&DaemonEvent::ProviderUsed {
+            provider: "ollama".to_string(),
+        });
+        state.apply(&DaemonEvent::BudgetUpdated { remaining: Some(3) });
+        assert_eq!(state.provider.as_deref(), Some("ollama"));
+        assert_eq!(state.budget_remaining, Some(3));
+        assert!(!state.voice_active);
+    }
+}
@@ -0,0 +1,128 @@
+use serde::{Deserialize, Serialize};
+
+/// Tool an `expr/suggest` one-liner is written for.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExprTool {
+    Jq,
+    Awk,
+    Sed,
+}
+
+impl ExprTool {
+    /// The command the one-liner runs.
+    pub fn command(self) -> &'static str {
+        match self {
+            ExprTool::Jq => "jq",
+            ExprTool::Awk => "awk",
+            ExprTool::Sed => "sed",
+        }
+    }
+}
+
+/// Write a jq, awk or sed one-liner for a transformation described in
+/// natural language.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ExprSuggestRequest {
+    pub tool: ExprTool,
+    /// What to do, e.g. "extract the name field from each item".
+    pub description: String,
+    /// The start of the data the one-liner will read, so it fits its
+    /// shape. The daemon cuts it down and strips credentials before
+    /// sending it to a provider.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ExprSuggestResponse {
+    /// The command line, e.g. `jq -r '.items[].name'`, reading standard
+    /// input.
+    pub command: String,
+    /// Provider that wrote it.
+    pub provider: String,
+    pub latency_ms: u64,
+    /// Only the start of the sample was sent.
+    #[serde(default)]
+    pub sample_truncated: bool,
+}
+
+/// Write a regular expression for a description and try it on sample
+/// lines.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RegexSuggestRequest {
+    /// What to match, e.g. "ISO dates in log lines".
+    pub description: String,
+    /// Lines to try the expression on. The daemon sends only the start of
+    /// it, without credentials, to the provider.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RegexSuggestResponse {
+    /// The expression, in the syntax of Rust's `regex` crate (like RE2: no
+    /// lookaround or backreferences). It compiled.
+    pub pattern: String,
+    /// Provider that wrote it.
+    pub provider: String,
+    pub latency_ms: u64,
+    /// Sample lines the expression matches.
+    #[serde(default)]
+    pub matched_lines: usize,
+    /// Lines in the sample.
+    #[serde(default)]
+    pub sample_lines: usize,
+    /// The first matching lines, with where the matches are.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub previews: Vec<RegexPreview>,
+}
+
+/// A sample line the expression matches.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RegexPreview {
+    /// 1-based.
+    pub line_number: usize,
+    pub line: String,
+    /// Byte ranges `[start, end)` of each match in `line`.
+    pub spans: Vec<[usize; 2]>,
+}
+
+/// Turn a description like "every weekday at 6pm" into a crontab line.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CronSuggestRequest {
+    pub description: String,
+    /// Command to put after the schedule.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CronSuggestResponse {
+    /// The five time fields, or a shorthand like `@daily`. It parsed.
+    pub schedule: String,
+    /// The schedule followed by the command, if one was given.
+    pub line: String,
+    /// The next run times as unix timestamps; empty for `@reboot`.
+    pub next_runs: Vec<u64>,
+    /// Provider that wrote it.
+    pub provider: String,
+    pub latency_ms: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tools_are_lowercase() {
+        let request: ExprSuggestRequest =
+            serde_json::from_str(r#"{"tool":"jq","description":"names"}"#).unwrap();
+        assert_eq!(request.tool, ExprTool::Jq);
+        assert_eq!(request.sample, None);
+        assert_eq!(
+            serde_json::to_value(ExprTool::Awk).unwrap(),
+            serde_json::json!("awk")
+        );
+    }
+}
@@ -0,0 +1,26 @@
+use serde::{Deserialize, Serialize};
+
+/// Answer to `ping`: the daemon is up. Cheap enough for the shell
+/// integration to send whenever a request goes unanswered.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PingResponse {
+    pub pid: u32,
+    pub version: String,
+}
+
+/// Liveness and readiness, for supervisors and container health probes.
+/// Answered from state the daemon already has, without calling providers.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HealthResponse {
+    /// The daemon is answering requests. Always true in a response; a
+    /// daemon that isn't live doesn't answer.
+    pub live: bool,
+    /// Completions can be served: a provider is active and not every
+    /// provider failed its last health check.
+    pub ready: bool,
+    /// Why the daemon isn't ready, empty when it is.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reasons: Vec<String>,
+    pub uptime_secs: u64,
+    pub version: String,
+}
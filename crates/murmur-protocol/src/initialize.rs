@@ -0,0 +1,147 @@
+use serde::{Deserialize, Serialize};
+
+/// Version of the wire protocol, raised when requests or responses change
+/// in ways a client may need to know about. Daemons from before version
+/// negotiation report 0.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Parameters for `initialize`, sent first on a connection to opt into
+/// optional protocol features and learn what the daemon supports.
+/// Connections that skip it get plain JSON lines for everything.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InitializeRequest {
+    /// The client can send `voice/process` audio as a binary frame.
+    #[serde(default)]
+    pub binary_audio: bool,
+    /// API token for a TCP or WebSocket listener that requires one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    /// The newest protocol version the client speaks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocol_version: Option<u32>,
+    /// Client name and version for the daemon's logs, like `murmur-mcp/0.4.0`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client: Option<String>,
+}
+
+/// Features the daemon enabled for this connection.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InitializeResponse {
+    /// `voice/process` requests may set `audio_frame` and follow the JSON
+    /// line with a 4-byte big-endian length and the raw WAV bytes.
+    #[serde(default)]
+    pub binary_audio: bool,
+    /// What the token sent grants, when one was.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scopes: Vec<Scope>,
+    /// The daemon's `PROTOCOL_VERSION`.
+    #[serde(default)]
+    pub protocol_version: u32,
+    /// The daemon's release, like `0.4.0`.
+    #[serde(default)]
+    pub daemon_version: String,
+    /// Every request method the daemon handles.
+    #[serde(default)]
+    pub methods: Vec<String>,
+    /// Every notification `events/subscribe` can deliver.
+    #[serde(default)]
+    pub notifications: Vec<String>,
+}
+
+impl InitializeResponse {
+    /// Whether the daemon handles `method`. `None` when it predates version
+    /// negotiation and doesn't say; try the method and handle
+    /// `METHOD_NOT_FOUND` then.
+    pub fn supports(&self, method: &str) -> Option<bool> {
+        (self.protocol_version > 0).then(|| self.methods.iter().any(|m| m == method))
+    }
+}
+
+/// What an API token lets a network client do.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// Completions and the other suggestion methods, voice included.
+    Complete,
+    /// Reading, asking about and recording command history.
+    History,
+    /// Methods that read the git repository at a client-given `cwd` and
+    /// send its diff or log to providers: commit messages, PR drafts and
+    /// branch names.
+    Repo,
+    /// Everything, including status, reloads and shutdown.
+    Admin,
+}
+
+impl Scope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::Complete => "complete",
+            Scope::History => "history",
+            Scope::Repo => "repo",
+            Scope::Admin => "admin",
+        }
+    }
+
+    /// Whether a token with this scope may use methods needing `needed`.
+    pub fn grants(self, needed: Scope) -> bool {
+        self == Scope::Admin || self == needed
+    }
+}
+
+impl std::str::FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "complete" => Ok(Scope::Complete),
+            "history" => Ok(Scope::History),
+            "repo" => Ok(Scope::Repo),
+            "admin" => Ok(Scope::Admin),
+            other => Err(format!(
+                "Unknown scope: {other}. Supported: complete, history, repo, admin"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn features_default_off() {
+        let request: InitializeRequest = serde_json::from_str("{}").unwrap();
+        assert!(!request.binary_audio);
+        let response: InitializeResponse =
+            serde_json::from_str(r#"{"binary_audio": true}"#).unwrap();
+        assert!(response.binary_audio);
+        assert!(request.token.is_none());
+    }
+
+    #[test]
+    fn old_daemons_dont_say_what_they_support() {
+        let old: InitializeResponse = serde_json::from_str(r#"{"binary_audio": false}"#).unwrap();
+        assert_eq!(old.protocol_version, 0);
+        assert_eq!(old.supports(crate::methods::COMPLETE), None);
+
+        let new = InitializeResponse {
+            protocol_version: PROTOCOL_VERSION,
+            methods: vec![crate::methods::COMPLETE.to_string()],
+            ..Default::default()
+        };
+        assert_eq!(new.supports(crate::methods::COMPLETE), Some(true));
+        assert_eq!(new.supports(crate::methods::CRON_SUGGEST), Some(false));
+    }
+
+    #[test]
+    fn admin_grants_every_scope() {
+        assert!(Scope::Admin.grants(Scope::History));
+        assert!(Scope::Complete.grants(Scope::Complete));
+        assert!(!Scope::Complete.grants(Scope::History));
+        assert!(!Scope::Complete.grants(Scope::Repo));
+        assert_eq!("history".parse::<Scope>(), Ok(Scope::History));
+        assert_eq!("repo".parse::<Scope>(), Ok(Scope::Repo));
+        assert!("root".parse::<Scope>().is_err());
+    }
+}
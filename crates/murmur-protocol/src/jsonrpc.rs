@@ -22,6 +22,16 @@ pub struct JsonRpcResponse {
     pub id: RequestId,
 }
 
+/// JSON-RPC 2.0 notification (no `id`, no response expected).
+/// Sent by the daemon to clients subscribed via `events/subscribe`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonRpcNotification {
+    pub jsonrpc: String,
+    pub method: String,
+    #[serde(default)]
+    pub params: Option<Value>,
+}
+
 /// JSON-RPC 2.0 error object.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JsonRpcError {
@@ -46,17 +56,120 @@ pub const METHOD_NOT_FOUND: i32 = -32601;
 pub const INVALID_PARAMS: i32 = -32602;
 pub const INTERNAL_ERROR: i32 = -32603;
 
+// Server-defined error codes
+/// The command isn't allowed by the daemon's command policy.
+pub const COMMAND_NOT_PERMITTED: i32 = -32001;
+/// The request was aborted by `complete/cancel` or a newer request.
+pub const REQUEST_CANCELLED: i32 = -32002;
+/// A network client's token is missing, unknown or lacks the scope the
+/// method needs.
+pub const UNAUTHORIZED: i32 = -32003;
+
 /// Known RPC method names.
 pub mod methods {
+    /// Optional first request on a connection; see `InitializeRequest`.
+    pub const INITIALIZE: &str = "initialize";
     pub const COMPLETE: &str = "complete";
+    pub const COMPLETE_CANDIDATES: &str = "complete/candidates";
+    /// Abort a completion by its `cancel_token`.
+    pub const COMPLETE_CANCEL: &str = "complete/cancel";
     pub const VOICE_START: &str = "voice/start";
     pub const VOICE_STOP: &str = "voice/stop";
     pub const VOICE_PROCESS: &str = "voice/process";
     pub const VOICE_STATUS: &str = "voice/status";
+    pub const VOICE_CONFIRM: &str = "voice/confirm";
+    pub const VOICE_SELFTEST: &str = "voice/selftest";
+    pub const VOICE_UPLOAD_BEGIN: &str = "voice/upload_begin";
+    pub const VOICE_UPLOAD_CHUNK: &str = "voice/upload_chunk";
+    pub const VOICE_UPLOAD_END: &str = "voice/upload_end";
     pub const STATUS: &str = "status";
+    /// Whether the daemon answers at all; cheaper than `health`.
+    pub const PING: &str = "ping";
+    /// Liveness and readiness; cheaper than `status`.
+    pub const HEALTH: &str = "health";
+    /// Expand the abbreviation before the cursor.
+    pub const ABBR_EXPAND: &str = "abbr/expand";
+    pub const ABBR_LIST: &str = "abbr/list";
     pub const CONTEXT_UPDATE: &str = "context/update";
     pub const HISTORY_LIST: &str = "history/list";
+    pub const HISTORY_IMPORT: &str = "history/import";
+    pub const HISTORY_ASK: &str = "history/ask";
+    pub const QUERY: &str = "query";
+    /// A commit message for the staged changes.
+    pub const COMMIT_SUGGEST: &str = "commit/suggest";
+    /// A PR description or changelog section for a range of commits.
+    pub const SUMMARIZE_COMMITS: &str = "summarize/commits";
+    /// A branch name for a description of the work.
+    pub const BRANCH_SUGGEST: &str = "branch/suggest";
+    /// A jq, awk or sed one-liner for a described transformation.
+    pub const EXPR_SUGGEST: &str = "expr/suggest";
+    /// A regular expression for a description, tried on sample lines.
+    pub const REGEX_SUGGEST: &str = "regex/suggest";
+    /// A crontab line for a described schedule, with its next run times.
+    pub const CRON_SUGGEST: &str = "cron/suggest";
+    pub const DIAGNOSE: &str = "diagnose";
     pub const SHUTDOWN: &str = "shutdown";
+    /// Re-read the config file without restarting; same as SIGHUP.
+    pub const CONFIG_RELOAD: &str = "config/reload";
+    pub const EVENTS_SUBSCRIBE: &str = "events/subscribe";
+    /// Recent logs, request traces and status, for `murmur debug bundle`.
+    pub const DEBUG_BUNDLE: &str = "debug/bundle";
+    /// Switch the connection to compressed frames.
+    pub const COMPRESSION_NEGOTIATE: &str = "compression/negotiate";
+    /// Start (or resume) a shell session the daemon keeps state for.
+    pub const SESSION_OPEN: &str = "session/open";
+    /// Notification method used for pushed daemon events.
+    pub const EVENT: &str = "event";
+    /// Notification: completions that arrived after the request was answered.
+    pub const COMPLETION_READY: &str = "completion/ready";
+    /// Notification: cloud providers degraded or recovered.
+    pub const PROVIDER_DEGRADED: &str = "provider/degraded";
+    /// Notification: a command was added to the history.
+    pub const HISTORY_UPDATED: &str = "history/updated";
+
+    /// Every request method the daemon handles, as `initialize` reports
+    /// them. `voice/stop` is reserved and not handled.
+    pub const ALL: &[&str] = &[
+        INITIALIZE,
+        COMPLETE,
+        COMPLETE_CANDIDATES,
+        COMPLETE_CANCEL,
+        VOICE_START,
+        VOICE_PROCESS,
+        VOICE_STATUS,
+        VOICE_CONFIRM,
+        VOICE_SELFTEST,
+        VOICE_UPLOAD_BEGIN,
+        VOICE_UPLOAD_CHUNK,
+        VOICE_UPLOAD_END,
+        STATUS,
+        PING,
+        HEALTH,
+        ABBR_EXPAND,
+        ABBR_LIST,
+        CONTEXT_UPDATE,
+        HISTORY_LIST,
+        HISTORY_IMPORT,
+        HISTORY_ASK,
+        QUERY,
+        COMMIT_SUGGEST,
+        SUMMARIZE_COMMITS,
+        BRANCH_SUGGEST,
+        EXPR_SUGGEST,
+        REGEX_SUGGEST,
+        CRON_SUGGEST,
+        DIAGNOSE,
+        SHUTDOWN,
+        CONFIG_RELOAD,
+        EVENTS_SUBSCRIBE,
+        DEBUG_BUNDLE,
+        COMPRESSION_NEGOTIATE,
+        SESSION_OPEN,
+    ];
+
+    /// Every notification method `events/subscribe` delivers.
+    pub const NOTIFICATIONS: &[&str] =
+        &[EVENT, COMPLETION_READY, PROVIDER_DEGRADED, HISTORY_UPDATED];
 }
 
 impl JsonRpcRequest {
@@ -70,6 +183,16 @@ impl JsonRpcRequest {
     }
 }
 
+impl JsonRpcNotification {
+    pub fn new(method: impl Into<String>, params: Option<Value>) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            method: method.into(),
+            params,
+        }
+    }
+}
+
 impl JsonRpcResponse {
     pub fn success(result: Value, id: RequestId) -> Self {
         Self {
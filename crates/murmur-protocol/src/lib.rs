@@ -3,14 +3,40 @@
 //! This crate contains no I/O or async code. It defines the message types
 //! used for communication between the shell plugin and the daemon.
 
+mod abbr;
+mod commit;
 mod completion;
+mod compression;
+mod config;
 mod context;
+mod debug;
+mod diagnose;
 mod error;
+mod events;
+mod expr;
+mod health;
+mod initialize;
 mod jsonrpc;
+mod query;
+mod safety;
+mod session;
 mod voice;
 
+pub use abbr::*;
+pub use commit::*;
 pub use completion::*;
+pub use compression::*;
+pub use config::*;
 pub use context::*;
+pub use debug::*;
+pub use diagnose::*;
 pub use error::*;
+pub use events::*;
+pub use expr::*;
+pub use health::*;
+pub use initialize::*;
 pub use jsonrpc::*;
+pub use query::*;
+pub use safety::*;
+pub use session::*;
 pub use voice::*;
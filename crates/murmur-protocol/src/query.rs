@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+
+/// One-shot natural-language request for a shell command, used by launcher
+/// integrations (Raycast, Alfred) rather than the inline shell widget.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryRequest {
+    /// What the user wants to do, e.g. "find files larger than 100MB".
+    pub query: String,
+    /// Working directory for context (launchers usually pass `$HOME`).
+    #[serde(default)]
+    pub cwd: Option<String>,
+    /// Shell the command should target.
+    #[serde(default)]
+    pub shell: Option<String>,
+    /// Maximum number of alternatives to return.
+    #[serde(default)]
+    pub max_items: Option<usize>,
+}
+
+/// A suggested command for a query.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryItem {
+    /// The shell command.
+    pub command: String,
+    /// Brief explanation of what it does.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// Response to a `query` request. Items are ordered best first.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryResponse {
+    pub items: Vec<QueryItem>,
+    /// Provider that answered.
+    pub provider: String,
+    /// Time taken in milliseconds.
+    pub latency_ms: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_request_defaults() {
+        let req: QueryRequest = serde_json::from_str(r#"{"query": "list ports"}"#).unwrap();
+        assert_eq!(req.query, "list ports");
+        assert!(req.cwd.is_none());
+        assert!(req.max_items.is_none());
+    }
+}
@@ -0,0 +1,46 @@
+use serde::{Deserialize, Serialize};
+
+/// How dangerous a shell command is to run without review.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum RiskLevel {
+    /// Read-only or easily undone.
+    #[default]
+    Safe,
+    /// Changes state or needs elevated privileges.
+    Caution,
+    /// Deletes data, rewrites history or tears down infrastructure.
+    Destructive,
+}
+
+/// Why a command needs more than a glance before it runs unattended.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum SensitiveCategory {
+    /// Deletes data, rewrites history or tears down infrastructure.
+    Destructive,
+    /// Sends local data off the machine (uploads, posts, piping into
+    /// `curl` or `nc`).
+    Exfiltration,
+    /// Runs with or grants elevated privileges (`sudo`, setuid bits).
+    Privilege,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn risk_levels_are_ordered() {
+        assert!(RiskLevel::Safe < RiskLevel::Caution);
+        assert!(RiskLevel::Caution < RiskLevel::Destructive);
+        assert_eq!(
+            serde_json::to_string(&RiskLevel::Destructive).unwrap(),
+            "\"destructive\""
+        );
+        assert_eq!(
+            serde_json::to_string(&SensitiveCategory::Exfiltration).unwrap(),
+            "\"exfiltration\""
+        );
+    }
+}
@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+
+/// Parameters of `session/open`: the shell a client speaks for, so the
+/// daemon can keep state across its requests. Every field is optional.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SessionOpenRequest {
+    /// Keep using this ID (like the `zsh-$$` the history hooks send)
+    /// instead of being issued a new one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// PID of the shell; the session ends when it exits.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shell_pid: Option<u32>,
+    /// Shell type (zsh, bash, fish).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shell: Option<String>,
+    /// The shell's working directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    /// Hostname, for shells in SSH sessions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+}
+
+/// Result of `session/open`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionOpenResponse {
+    /// Pass as `session_id` in `complete` and `context/update`. Requests on
+    /// the connection that opened the session get it without asking.
+    pub session_id: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn open_needs_no_params() {
+        let request: SessionOpenRequest = serde_json::from_str("{}").unwrap();
+        assert!(request.session_id.is_none());
+        assert_eq!(
+            serde_json::to_string(&SessionOpenRequest {
+                shell_pid: Some(42),
+                ..Default::default()
+            })
+            .unwrap(),
+            r#"{"shell_pid":42}"#
+        );
+    }
+}
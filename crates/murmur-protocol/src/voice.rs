@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::{RiskLevel, SensitiveCategory};
+
 /// Voice input mode.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
@@ -16,25 +18,43 @@ pub struct VoiceStartRequest {
     /// Which mode to use for restructuring.
     pub mode: VoiceMode,
     /// Current working directory (for context).
+    #[serde(default)]
     pub cwd: String,
     /// Shell type.
     #[serde(default)]
     pub shell: Option<String>,
+    /// Session from `session/open` (or a shell-picked ID). Its directory
+    /// stands in for an empty `cwd`, and progress events and confirmations
+    /// are tagged with it so other panes ignore them.
+    #[serde(default)]
+    pub session_id: Option<String>,
 }
 
 /// Request to process audio data through STT + restructuring.
-/// Audio should be base64-encoded WAV (16kHz mono 16-bit PCM).
+/// Audio should be WAV (16kHz mono 16-bit PCM), base64-encoded in
+/// `audio_data` or sent raw in a binary frame (`audio_frame`).
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VoiceProcessRequest {
-    /// Base64-encoded WAV audio data.
+    /// Base64-encoded WAV audio data. Empty when `audio_frame` is set.
+    #[serde(default)]
     pub audio_data: String,
+    /// The audio follows this request on the socket as a binary frame
+    /// instead. Needs `binary_audio` from `initialize`.
+    #[serde(default)]
+    pub audio_frame: bool,
     /// Which mode to use for restructuring.
     pub mode: VoiceMode,
     /// Current working directory (for context).
+    #[serde(default)]
     pub cwd: String,
     /// Shell type.
     #[serde(default)]
     pub shell: Option<String>,
+    /// Session from `session/open` (or a shell-picked ID). Its directory
+    /// stands in for an empty `cwd`, and progress events and confirmations
+    /// are tagged with it so other panes ignore them.
+    #[serde(default)]
+    pub session_id: Option<String>,
 }
 
 /// Response after voice capture and processing.
@@ -52,6 +72,146 @@ pub struct VoiceResult {
     pub engine: String,
     /// Total processing time in milliseconds.
     pub latency_ms: u64,
+    /// Risk of running `output` (command mode only).
+    #[serde(default)]
+    pub risk: RiskLevel,
+    /// Sensitive categories `output` falls in (command mode only).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sensitive: Vec<SensitiveCategory>,
+    /// Whether the client should run `output` right away (voice auto-execute).
+    #[serde(default)]
+    pub execute: bool,
+    /// Set when the command must be confirmed via `voice/confirm` first.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confirmation: Option<ConfirmationPrompt>,
+    /// What every engine heard, under the `best` STT strategy; for
+    /// debugging why one transcript won.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stt_candidates: Vec<SttCandidate>,
+}
+
+/// One STT engine's transcript of a recording.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SttCandidate {
+    pub engine: String,
+    #[serde(default)]
+    pub transcript: String,
+    #[serde(default)]
+    pub confidence: f64,
+    /// How closely the other engines' transcripts match this one (0.0 to
+    /// 1.0), by word-level edit distance.
+    #[serde(default)]
+    pub agreement: f64,
+    /// Why the engine gave no transcript.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A pending spoken confirmation for a risky voice command.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfirmationPrompt {
+    /// Pass back in `voice/confirm`.
+    pub id: u64,
+    /// The question asked (and spoken, if TTS is enabled).
+    pub prompt: String,
+    /// Word the reply must contain instead of a "yes", for commands whose
+    /// policy asks for one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub challenge: Option<String>,
+    /// Seconds until the confirmation expires.
+    pub expires_in_secs: u64,
+}
+
+/// Answer to a confirmation prompt: recorded audio or an already
+/// transcribed reply.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VoiceConfirmRequest {
+    /// Confirmation ID from `ConfirmationPrompt`.
+    pub id: u64,
+    /// Base64-encoded WAV audio of the reply.
+    #[serde(default)]
+    pub audio_data: Option<String>,
+    /// Text reply, used instead of audio when present.
+    #[serde(default)]
+    pub transcript: Option<String>,
+    /// Session the prompt was asked in; a prompt from another session's
+    /// recording can't be answered.
+    #[serde(default)]
+    pub session_id: Option<String>,
+}
+
+/// Outcome of `voice/confirm`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VoiceConfirmResult {
+    /// The command awaiting confirmation.
+    pub command: String,
+    /// What the user said.
+    pub transcript: String,
+    /// True only for a clear "yes", or the challenge word when one was
+    /// asked for; anything else cancels.
+    pub confirmed: bool,
+}
+
+/// Start a chunked audio upload, for recordings too long to send in one
+/// `voice/process` line. Chunks follow via `voice/upload_chunk`, then
+/// `voice/upload_end` processes the audio like `voice/process`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VoiceUploadBeginRequest {
+    /// Size of the whole WAV file.
+    pub total_bytes: usize,
+    /// CRC-32 of the whole file, checked by `voice/upload_end`.
+    #[serde(default)]
+    pub crc32: Option<u32>,
+}
+
+/// Response to `voice/upload_begin`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VoiceUploadBeginResponse {
+    pub upload_id: u64,
+    /// Largest chunk accepted, before base64.
+    pub max_chunk_bytes: usize,
+}
+
+/// One piece of an upload. Chunks must arrive in order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VoiceUploadChunkRequest {
+    pub upload_id: u64,
+    /// Position of this chunk in the file; must equal the bytes received so
+    /// far, so a rejected chunk can simply be sent again.
+    pub offset: usize,
+    /// Base64-encoded bytes.
+    pub data: String,
+    /// CRC-32 of this chunk's bytes.
+    #[serde(default)]
+    pub crc32: Option<u32>,
+}
+
+/// Response to `voice/upload_chunk`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VoiceUploadChunkResponse {
+    /// Bytes received so far.
+    pub received: usize,
+}
+
+/// Finish an upload and process the audio. Returns a `VoiceResult`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VoiceUploadEndRequest {
+    pub upload_id: u64,
+    pub mode: VoiceMode,
+    #[serde(default)]
+    pub cwd: String,
+    #[serde(default)]
+    pub shell: Option<String>,
+    #[serde(default)]
+    pub session_id: Option<String>,
+}
+
+/// What the daemon is doing with a recording, for progress events.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum VoiceStage {
+    Transcribing,
+    Restructuring,
 }
 
 /// Status of the voice engine.
@@ -65,6 +225,69 @@ pub struct VoiceStatus {
     pub active_engine: Option<String>,
 }
 
+/// Parameters for `voice/selftest`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VoiceSelftestRequest {
+    /// Directory of recorded utterances named `<id>.wav`. Utterances
+    /// without a recording are synthesized with the local TTS command.
+    #[serde(default)]
+    pub corpus_dir: Option<String>,
+    /// Only test the STT engine with this name.
+    #[serde(default)]
+    pub engine: Option<String>,
+}
+
+/// One corpus utterance run through one STT engine and the restructurer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SelftestCase {
+    /// Corpus utterance ID.
+    pub utterance: String,
+    /// STT engine tested.
+    pub engine: String,
+    /// Where the audio came from: "recorded" or "synthesized".
+    pub audio: String,
+    /// What the engine heard.
+    #[serde(default)]
+    pub transcript: String,
+    /// Word error rate of the transcript against the expected text.
+    #[serde(default)]
+    pub word_error_rate: f64,
+    /// Whether the word error rate is within the utterance's tolerance.
+    #[serde(default)]
+    pub transcript_ok: bool,
+    /// Restructured output, when a restructurer is configured.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    /// Whether the output matches one of the expected outputs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_ok: Option<bool>,
+    /// Why the case could not run to completion.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// STT plus restructuring time in milliseconds.
+    #[serde(default)]
+    pub latency_ms: u64,
+}
+
+impl SelftestCase {
+    /// Transcript within tolerance, and output too when it was checked.
+    pub fn passed(&self) -> bool {
+        self.error.is_none() && self.transcript_ok && self.output_ok != Some(false)
+    }
+}
+
+/// Result of `voice/selftest`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VoiceSelftestReport {
+    pub cases: Vec<SelftestCase>,
+    /// Utterances that could not be tested, with the reason.
+    #[serde(default)]
+    pub skipped: Vec<String>,
+    /// Restructurer backend tested, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restructurer: Option<String>,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -88,9 +311,25 @@ mod tests {
             confidence: 0.92,
             engine: "whisper".to_string(),
             latency_ms: 450,
+            risk: RiskLevel::Safe,
+            sensitive: vec![],
+            execute: false,
+            confirmation: None,
+            stt_candidates: Vec::new(),
         };
         let json = serde_json::to_string(&result).unwrap();
         let roundtrip: VoiceResult = serde_json::from_str(&json).unwrap();
         assert_eq!(roundtrip.output, "docker ps -a");
+        assert!(!json.contains("confirmation"));
+        assert!(!json.contains("sensitive"));
+    }
+
+    #[test]
+    fn voice_result_without_safety_fields_parses() {
+        let json = r#"{"transcript":"ls","output":"ls","mode":"command","confidence":1.0,"engine":"apple","latency_ms":5}"#;
+        let result: VoiceResult = serde_json::from_str(json).unwrap();
+        assert_eq!(result.risk, RiskLevel::Safe);
+        assert!(!result.execute);
+        assert!(result.confirmation.is_none());
     }
 }
@@ -1,14 +1,18 @@
 use async_trait::async_trait;
 use murmur_context::ShellContext;
-use murmur_protocol::{CompletionItem, CompletionKind, CompletionRequest};
+use murmur_protocol::{CompletionItem, CompletionRequest};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use tracing::{debug, warn};
+use tracing::debug;
 
-use crate::{Provider, ProviderConfig, ProviderError};
+use crate::chat;
+use crate::retry::RetryPolicy;
+use crate::{
+    http, Capabilities, CostClass, Exchange, LatencyClass, Provider, ProviderConfig, ProviderError,
+};
 
 const DEFAULT_MODEL: &str = "claude-haiku-4-5-20251001";
-const DEFAULT_ENDPOINT: &str = "https://api.anthropic.com/v1/messages";
+pub(crate) const DEFAULT_ENDPOINT: &str = "https://api.anthropic.com/v1/messages";
 
 pub struct AnthropicProvider {
     client: Client,
@@ -16,6 +20,7 @@ pub struct AnthropicProvider {
     model: String,
     endpoint: String,
     timeout: std::time::Duration,
+    retry: RetryPolicy,
 }
 
 #[derive(Serialize)]
@@ -23,7 +28,35 @@ struct AnthropicRequest {
     model: String,
     max_tokens: u32,
     messages: Vec<Message>,
-    system: String,
+    system: Vec<SystemBlock>,
+}
+
+/// A block of the system prompt. Blocks marked with `cache_control` end a
+/// cached prefix: later requests starting with the same blocks read them
+/// from Anthropic's prompt cache instead of processing them again.
+#[derive(Serialize)]
+struct SystemBlock {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache_control: Option<CacheControl>,
+}
+
+impl SystemBlock {
+    fn cached(text: String) -> Self {
+        Self {
+            kind: "text",
+            text,
+            cache_control: Some(CacheControl { kind: "ephemeral" }),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct CacheControl {
+    #[serde(rename = "type")]
+    kind: &'static str,
 }
 
 #[derive(Serialize)]
@@ -35,6 +68,18 @@ struct Message {
 #[derive(Deserialize)]
 struct AnthropicResponse {
     content: Vec<ContentBlock>,
+    #[serde(default)]
+    usage: Option<Usage>,
+}
+
+#[derive(Deserialize)]
+struct Usage {
+    #[serde(default)]
+    input_tokens: u32,
+    #[serde(default)]
+    cache_creation_input_tokens: u32,
+    #[serde(default)]
+    cache_read_input_tokens: u32,
 }
 
 #[derive(Deserialize)]
@@ -50,7 +95,7 @@ impl AnthropicProvider {
             .ok_or_else(|| ProviderError::NotConfigured("anthropic: api_key required".into()))?;
 
         Ok(Self {
-            client: Client::new(),
+            client: http::client(config)?,
             api_key,
             model: config
                 .model
@@ -61,136 +106,21 @@ impl AnthropicProvider {
                 .clone()
                 .unwrap_or_else(|| DEFAULT_ENDPOINT.to_string()),
             timeout: std::time::Duration::from_millis(config.timeout_ms),
+            retry: RetryPolicy::from_config(config),
         })
     }
 
-    fn build_system_prompt(&self, context: &ShellContext) -> String {
-        let mut prompt = String::from(
-            "You are a shell command autocomplete engine. Given the user's partial command \
-             and context, suggest the most likely completions.\n\n\
-             Rules:\n\
-             - Respond ONLY with a JSON array of objects\n\
-             - Each object has \"text\" (the full command) and \"description\" (brief explanation)\n\
-             - Order by relevance. Maximum 5 suggestions\n\
-             - Prefer commands the user has used before (from history)\n\
-             - Be context-aware: use git branch names, project type, and CWD\n\
-             - For git commands: suggest real branch names and recent commit hashes when relevant\n\
-             - Never suggest dangerous commands (rm -rf /, :(){:|:&};:) unless clearly intended\n\n\
-             Examples:\n\
-             Input: `git c` → [{\"text\":\"git commit -m \\\"\\\"\",\"description\":\"Commit staged changes\"},{\"text\":\"git checkout\",\"description\":\"Switch branches\"}]\n\
-             Input: `cargo t` → [{\"text\":\"cargo test\",\"description\":\"Run tests\"},{\"text\":\"cargo test --release\",\"description\":\"Run tests in release mode\"}]\n\
-             Input: `docker` → [{\"text\":\"docker ps\",\"description\":\"List running containers\"},{\"text\":\"docker compose up\",\"description\":\"Start services\"}]",
-        );
-
-        // Git context with actionable details
-        if let Some(ref git) = context.git {
-            prompt.push_str(&format!(
-                "\n\nGit context:\n  Branch: {}\n  Dirty: {}",
-                git.branch, git.dirty
-            ));
-            if !git.recent_commits.is_empty() {
-                prompt.push_str("\n  Recent commits:");
-                for commit in &git.recent_commits {
-                    prompt.push_str(&format!("\n    {commit}"));
-                }
-            }
-        }
-
-        if let Some(ref project) = context.project {
-            prompt.push_str(&format!("\nProject type: {project:?}"));
-        }
-
-        // Only include useful env vars
-        let useful_vars: Vec<String> = context
-            .env_vars
-            .iter()
-            .filter(|(k, _)| {
-                matches!(
-                    k.as_str(),
-                    "EDITOR" | "VIRTUAL_ENV" | "NODE_ENV" | "GOPATH" | "RUSTUP_HOME"
-                )
-            })
-            .map(|(k, v)| format!("{k}={v}"))
-            .collect();
-        if !useful_vars.is_empty() {
-            prompt.push_str(&format!("\nEnvironment: {}", useful_vars.join(", ")));
-        }
-
-        prompt
-    }
-
-    fn build_user_prompt(&self, request: &CompletionRequest, context: &ShellContext) -> String {
-        let shell = request.shell.as_deref().unwrap_or("unknown");
-        let mut prompt = format!(
-            "Shell: {shell}\nCWD: {}\nPartial command: `{}`",
-            request.cwd, request.input,
-        );
-
-        if !context.history.is_empty() {
-            let recent: Vec<&String> = context.history.iter().rev().take(15).collect();
-            prompt.push_str("\n\nRecent history (most recent first):\n");
-            for cmd in &recent {
-                prompt.push_str(&format!("  {cmd}\n"));
-            }
-        }
-
-        prompt
-    }
-
-    fn parse_completions(&self, text: &str) -> Vec<CompletionItem> {
-        // Try to parse the response as JSON array
-        #[derive(Deserialize)]
-        struct Suggestion {
-            text: String,
-            description: Option<String>,
-        }
-
-        // Extract JSON from the response (might be wrapped in markdown code blocks)
-        let json_str = text
-            .trim()
-            .trim_start_matches("```json")
-            .trim_start_matches("```")
-            .trim_end_matches("```")
-            .trim();
-
-        match serde_json::from_str::<Vec<Suggestion>>(json_str) {
-            Ok(suggestions) => suggestions
-                .into_iter()
-                .enumerate()
-                .map(|(i, s)| CompletionItem {
-                    text: s.text,
-                    description: s.description,
-                    kind: CompletionKind::FullCommand,
-                    score: 1.0 - (i as f64 * 0.1),
-                })
-                .collect(),
-            Err(e) => {
-                warn!(error = %e, "Failed to parse completion response as JSON");
-                vec![]
-            }
-        }
-    }
-}
-
-#[async_trait]
-impl Provider for AnthropicProvider {
-    fn name(&self) -> &str {
-        "anthropic"
-    }
-
-    async fn complete(
+    /// Send a single-turn Messages API request and return the first text block.
+    async fn send_message(
         &self,
-        request: &CompletionRequest,
-        context: &ShellContext,
-    ) -> Result<Vec<CompletionItem>, ProviderError> {
-        let system = self.build_system_prompt(context);
-        let user = self.build_user_prompt(request, context);
-
-        debug!(model = %self.model, input = %request.input, "Requesting completion from Anthropic");
-
+        model: &str,
+        system: Vec<SystemBlock>,
+        user: String,
+        max_tokens: u32,
+    ) -> Result<String, ProviderError> {
         let body = AnthropicRequest {
-            model: self.model.clone(),
-            max_tokens: 512,
+            model: model.to_string(),
+            max_tokens,
             messages: vec![Message {
                 role: "user".to_string(),
                 content: user,
@@ -198,16 +128,15 @@ impl Provider for AnthropicProvider {
             system,
         };
 
-        let response = self
+        let request = self
             .client
             .post(&self.endpoint)
             .header("x-api-key", &self.api_key)
             .header("anthropic-version", "2023-06-01")
             .header("content-type", "application/json")
             .timeout(self.timeout)
-            .json(&body)
-            .send()
-            .await?;
+            .json(&body);
+        let response = self.retry.send(request).await?;
 
         let status = response.status();
         if !status.is_success() {
@@ -219,59 +148,152 @@ impl Provider for AnthropicProvider {
         }
 
         let api_response: AnthropicResponse = response.json().await?;
-        let text = api_response
+        if let Some(usage) = api_response.usage {
+            debug!(
+                input_tokens = usage.input_tokens,
+                cache_write_tokens = usage.cache_creation_input_tokens,
+                cache_read_tokens = usage.cache_read_input_tokens,
+                "Anthropic token usage"
+            );
+        }
+        Ok(api_response
             .content
             .first()
-            .map(|b| b.text.as_str())
-            .unwrap_or("[]");
+            .map(|b| b.text.clone())
+            .unwrap_or_default())
+    }
+}
+
+#[async_trait]
+impl Provider for AnthropicProvider {
+    fn name(&self) -> &str {
+        "anthropic"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            streaming: true,
+            fim: false,
+            json_mode: false,
+            max_context: 200_000,
+            latency: LatencyClass::Moderate,
+            cost: CostClass::Low,
+        }
+    }
+
+    async fn complete(
+        &self,
+        request: &CompletionRequest,
+        context: &ShellContext,
+    ) -> Result<Vec<CompletionItem>, ProviderError> {
+        Ok(self.exchange(request, context).await?.items)
+    }
+
+    async fn exchange(
+        &self,
+        request: &CompletionRequest,
+        context: &ShellContext,
+    ) -> Result<Exchange, ProviderError> {
+        let system = completion_system(context);
+        let user = chat::user_prompt(request, context);
+
+        let model = request.model.as_deref().unwrap_or(&self.model);
+        debug!(model = %model, input = %request.input, "Requesting completion from Anthropic");
+
+        let prompt = vec![
+            ("system", system.iter().map(|b| b.text.as_str()).collect()),
+            ("user", user.clone()),
+        ];
+        let text = self.send_message(model, system, user, 512).await?;
+        let items = if text.trim().is_empty() {
+            vec![]
+        } else {
+            chat::parse_completions(&text)
+        };
+        Ok(Exchange {
+            prompt,
+            raw: text,
+            items,
+        })
+    }
 
-        Ok(self.parse_completions(text))
+    async fn generate(&self, system: &str, prompt: &str) -> Result<String, ProviderError> {
+        debug!(model = %self.model, "Requesting generation from Anthropic");
+        let text = self
+            .send_message(
+                &self.model,
+                vec![SystemBlock::cached(system.to_string())],
+                prompt.to_string(),
+                1024,
+            )
+            .await?;
+        Ok(text.trim().to_string())
     }
 
     async fn health_check(&self) -> Result<(), ProviderError> {
-        // Simple connectivity check — just hit the API with minimal request
         debug!("Anthropic health check");
-        Ok(())
+        // Listing models checks the key without spending tokens
+        let url = self.endpoint.replace("/messages", "/models");
+        let response = self
+            .client
+            .get(&url)
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", "2023-06-01")
+            .timeout(std::time::Duration::from_secs(5))
+            .send()
+            .await?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(ProviderError::Api {
+                status: response.status().as_u16(),
+                message: "Anthropic health check failed".to_string(),
+            })
+        }
+    }
+}
+
+/// The completion system prompt as two cached blocks: the instructions,
+/// shared by every request, then the shell's context, shared by requests
+/// from the same directory. The partial command and history go in the
+/// user message, after both cache breakpoints.
+fn completion_system(context: &ShellContext) -> Vec<SystemBlock> {
+    let mut system = vec![SystemBlock::cached(chat::INSTRUCTIONS.to_string())];
+    let context = chat::context_block(context);
+    if !context.trim().is_empty() {
+        system.push(SystemBlock::cached(context.trim_start().to_string()));
     }
+    system
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use murmur_context::GitInfo;
 
     #[test]
-    fn parse_json_completions() {
-        let provider = AnthropicProvider {
-            client: Client::new(),
-            api_key: "test".to_string(),
-            model: "test".to_string(),
-            endpoint: "test".to_string(),
-            timeout: std::time::Duration::from_secs(5),
-        };
-
-        let text = r#"[
-            {"text": "git commit -m \"fix: resolve issue\"", "description": "Commit staged changes"},
-            {"text": "git checkout -b feature/new", "description": "Create and switch to new branch"}
-        ]"#;
-
-        let completions = provider.parse_completions(text);
-        assert_eq!(completions.len(), 2);
-        assert!(completions[0].text.contains("git commit"));
-        assert!(completions[0].score > completions[1].score);
-    }
-
-    #[test]
-    fn parse_markdown_wrapped_json() {
-        let provider = AnthropicProvider {
-            client: Client::new(),
-            api_key: "test".to_string(),
-            model: "test".to_string(),
-            endpoint: "test".to_string(),
-            timeout: std::time::Duration::from_secs(5),
+    fn system_prompt_is_cached_in_two_blocks() {
+        let bare = serde_json::to_value(completion_system(&ShellContext::default())).unwrap();
+        assert_eq!(bare.as_array().unwrap().len(), 1);
+        assert_eq!(bare[0]["cache_control"]["type"], "ephemeral");
+
+        let context = ShellContext {
+            git: Some(GitInfo {
+                branch: "main".to_string(),
+                dirty: false,
+                recent_commits: vec![],
+                repo_root: "/repo".to_string(),
+            }),
+            ..ShellContext::default()
         };
-
-        let text = "```json\n[{\"text\": \"ls -la\", \"description\": \"List all files\"}]\n```";
-        let completions = provider.parse_completions(text);
-        assert_eq!(completions.len(), 1);
+        let system = serde_json::to_value(completion_system(&context)).unwrap();
+        assert_eq!(system[0]["text"], chat::INSTRUCTIONS);
+        assert!(system[1]["text"]
+            .as_str()
+            .unwrap()
+            .starts_with("Git context:\n  Branch: main"));
+        assert_eq!(system[1]["type"], "text");
+        assert_eq!(system[1]["cache_control"]["type"], "ephemeral");
     }
 }
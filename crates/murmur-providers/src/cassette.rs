@@ -0,0 +1,370 @@
+//! Recorded HTTP exchanges ("cassettes") for testing providers against
+//! real API responses without network access or keys.
+//!
+//! A `CassetteServer` listens on localhost and is used as the provider's
+//! endpoint. It replays a cassette's interactions in order, checking that
+//! each request has the recorded method and path. With
+//! `MURMUR_RECORD_CASSETTES=1` it forwards requests to the real API
+//! instead and saves what happened, so a cassette is re-recorded by
+//! running its test once with real keys.
+//!
+//! Cassettes are sanitized as they are written: request headers (which
+//! carry the keys) aren't saved, key-like query parameters are redacted,
+//! and only the response's status, content type and body are kept.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+/// Set to `1` to record cassettes from the real APIs.
+pub const RECORD_ENV: &str = "MURMUR_RECORD_CASSETTES";
+
+/// Query parameters whose values are replaced before saving.
+const SECRET_PARAMS: &[&str] = &["key", "api_key", "token", "access_token"];
+
+/// A recorded sequence of HTTP exchanges.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Cassette {
+    pub interactions: Vec<Interaction>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub method: String,
+    /// Path and sanitized query string.
+    pub path: String,
+    /// JSON request bodies, for reference; not used for matching.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    pub body: String,
+}
+
+impl Cassette {
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        let json = std::fs::read_to_string(path)?;
+        serde_json::from_str(&json).map_err(std::io::Error::other)
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(std::io::Error::other)?;
+        std::fs::write(path, json + "\n")
+    }
+}
+
+enum Mode {
+    Replay {
+        next: usize,
+    },
+    Record {
+        upstream: String,
+        client: reqwest::Client,
+    },
+}
+
+struct State {
+    path: PathBuf,
+    cassette: Cassette,
+    mode: Mode,
+}
+
+/// A local HTTP server replaying (or recording) one cassette.
+pub struct CassetteServer {
+    url: String,
+    state: Arc<Mutex<State>>,
+    task: JoinHandle<()>,
+}
+
+impl CassetteServer {
+    /// Serve the cassette at `path`. When recording, requests go to
+    /// `upstream` (scheme and host, e.g. `https://api.anthropic.com`).
+    pub async fn start(path: impl Into<PathBuf>, upstream: &str) -> std::io::Result<Self> {
+        let path = path.into();
+        let recording = std::env::var(RECORD_ENV).is_ok_and(|v| v == "1");
+        let (cassette, mode) = if recording {
+            let client = reqwest::Client::new();
+            let upstream = upstream.trim_end_matches('/').to_string();
+            (Cassette::default(), Mode::Record { upstream, client })
+        } else {
+            (Cassette::load(&path)?, Mode::Replay { next: 0 })
+        };
+        let state = Arc::new(Mutex::new(State {
+            path,
+            cassette,
+            mode,
+        }));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}", listener.local_addr()?);
+        let task = tokio::spawn({
+            let state = state.clone();
+            async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    tokio::spawn(serve(stream, state.clone()));
+                }
+            }
+        });
+        Ok(Self { url, state, task })
+    }
+
+    /// Base URL to use as the provider's endpoint.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Interactions not yet replayed; zero when recording.
+    pub fn remaining(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        match state.mode {
+            Mode::Replay { next } => state.cassette.interactions.len() - next,
+            Mode::Record { .. } => 0,
+        }
+    }
+}
+
+impl Drop for CassetteServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// A request as read off the socket.
+struct Incoming {
+    method: String,
+    target: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+async fn serve(stream: TcpStream, state: Arc<Mutex<State>>) {
+    let mut stream = BufReader::new(stream);
+    let Ok(Some(request)) = read_request(&mut stream).await else {
+        return;
+    };
+    let response = respond(&state, request).await;
+    let reason = if response.status < 400 { "OK" } else { "Error" };
+    let mut head = format!(
+        "HTTP/1.1 {} {reason}\r\ncontent-length: {}\r\nconnection: close\r\n",
+        response.status,
+        response.body.len()
+    );
+    if let Some(ref content_type) = response.content_type {
+        head.push_str(&format!("content-type: {content_type}\r\n"));
+    }
+    head.push_str("\r\n");
+    let stream = stream.get_mut();
+    let _ = stream.write_all(head.as_bytes()).await;
+    let _ = stream.write_all(response.body.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+async fn read_request(stream: &mut BufReader<TcpStream>) -> std::io::Result<Option<Incoming>> {
+    let mut line = String::new();
+    if stream.read_line(&mut line).await? == 0 {
+        return Ok(None);
+    }
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Ok(None);
+    };
+    let (method, target) = (method.to_string(), target.to_string());
+
+    let mut headers = Vec::new();
+    let mut length = 0;
+    loop {
+        line.clear();
+        stream.read_line(&mut line).await?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            let (name, value) = (name.trim().to_ascii_lowercase(), value.trim().to_string());
+            if name == "content-length" {
+                length = value.parse().unwrap_or(0);
+            }
+            headers.push((name, value));
+        }
+    }
+    let mut body = vec![0; length];
+    stream.read_exact(&mut body).await?;
+    Ok(Some(Incoming {
+        method,
+        target,
+        headers,
+        body,
+    }))
+}
+
+async fn respond(state: &Arc<Mutex<State>>, request: Incoming) -> RecordedResponse {
+    let path = sanitize_target(&request.target);
+    let upstream = {
+        let mut state = state.lock().unwrap();
+        let State { cassette, mode, .. } = &mut *state;
+        match mode {
+            Mode::Replay { next } => {
+                let Some(interaction) = cassette.interactions.get(*next) else {
+                    return mismatch(format!(
+                        "unexpected {} {path}: cassette has no more interactions",
+                        request.method
+                    ));
+                };
+                let recorded = &interaction.request;
+                if recorded.method != request.method || recorded.path != path {
+                    return mismatch(format!(
+                        "unexpected {} {path}: cassette expects {} {}",
+                        request.method, recorded.method, recorded.path
+                    ));
+                }
+                *next += 1;
+                return interaction.response.clone();
+            }
+            Mode::Record { upstream, client } => (upstream.clone(), client.clone()),
+        }
+    };
+
+    let (upstream, client) = upstream;
+    let response = match forward(&client, &upstream, &request).await {
+        Ok(response) => response,
+        Err(e) => return mismatch(format!("recording failed: {e}")),
+    };
+    let body = serde_json::from_slice(&request.body).ok();
+    let mut state = state.lock().unwrap();
+    state.cassette.interactions.push(Interaction {
+        request: RecordedRequest {
+            method: request.method,
+            path,
+            body,
+        },
+        response: response.clone(),
+    });
+    if let Err(e) = state.cassette.save(&state.path) {
+        warn!(path = %state.path.display(), error = %e, "Failed to save cassette");
+    }
+    response
+}
+
+async fn forward(
+    client: &reqwest::Client,
+    upstream: &str,
+    request: &Incoming,
+) -> Result<RecordedResponse, reqwest::Error> {
+    let method =
+        reqwest::Method::from_bytes(request.method.as_bytes()).unwrap_or(reqwest::Method::POST);
+    let mut outgoing = client.request(method, format!("{upstream}{}", request.target));
+    for (name, value) in &request.headers {
+        if !matches!(name.as_str(), "host" | "content-length" | "connection") {
+            outgoing = outgoing.header(name, value);
+        }
+    }
+    let response = outgoing.body(request.body.clone()).send().await?;
+    let status = response.status().as_u16();
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(String::from);
+    Ok(RecordedResponse {
+        status,
+        content_type,
+        body: response.text().await?,
+    })
+}
+
+/// A response the provider under test will fail on, explaining why.
+fn mismatch(message: String) -> RecordedResponse {
+    RecordedResponse {
+        status: 599,
+        content_type: Some("text/plain".to_string()),
+        body: format!("cassette: {message}"),
+    }
+}
+
+/// `target` with the values of key-like query parameters redacted.
+fn sanitize_target(target: &str) -> String {
+    let Some((path, query)) = target.split_once('?') else {
+        return target.to_string();
+    };
+    let query: Vec<String> = query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((name, _)) if SECRET_PARAMS.contains(&name) => format!("{name}=REDACTED"),
+            _ => pair.to_string(),
+        })
+        .collect();
+    format!("{path}?{}", query.join("&"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_redacted_from_paths() {
+        assert_eq!(
+            sanitize_target("/v1beta/models/gemini:generateContent?key=AIza123&alt=json"),
+            "/v1beta/models/gemini:generateContent?key=REDACTED&alt=json"
+        );
+        assert_eq!(sanitize_target("/v1/messages"), "/v1/messages");
+    }
+
+    #[tokio::test]
+    async fn interactions_replay_in_order() {
+        let path =
+            std::env::temp_dir().join(format!("murmur-cassette-{}.json", std::process::id()));
+        let response = |body: &str| RecordedResponse {
+            status: 200,
+            content_type: Some("application/json".to_string()),
+            body: body.to_string(),
+        };
+        Cassette {
+            interactions: vec![Interaction {
+                request: RecordedRequest {
+                    method: "POST".to_string(),
+                    path: "/api/generate".to_string(),
+                    body: None,
+                },
+                response: response(r#"{"response":"[]"}"#),
+            }],
+        }
+        .save(&path)
+        .unwrap();
+
+        let server = CassetteServer::start(&path, "http://localhost:11434")
+            .await
+            .unwrap();
+        let client = reqwest::Client::new();
+        let wrong = client
+            .get(format!("{}/api/tags", server.url()))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(wrong.status().as_u16(), 599);
+        let right = client
+            .post(format!("{}/api/generate", server.url()))
+            .body("{}")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(right.text().await.unwrap(), r#"{"response":"[]"}"#);
+        assert_eq!(server.remaining(), 0);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
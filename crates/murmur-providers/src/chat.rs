@@ -0,0 +1,243 @@
+//! Prompts and response parsing shared by chat-style providers
+//! (Anthropic Messages, OpenAI Chat Completions).
+
+use murmur_context::ShellContext;
+use murmur_protocol::{CompletionItem, CompletionKind, CompletionMetadata, CompletionRequest};
+use serde::Deserialize;
+use tracing::warn;
+
+/// History lines kept next to a session summary, which covers the rest.
+const SUMMARIZED_HISTORY: usize = 3;
+
+/// How many of the latest history lines a prompt that would otherwise
+/// show `lines` carries.
+pub(crate) fn history_lines(context: &ShellContext, lines: usize) -> usize {
+    match context.session_summary {
+        Some(_) => lines.min(SUMMARIZED_HISTORY),
+        None => lines,
+    }
+}
+
+/// System prompt: rules, examples and the shell's context.
+pub(crate) fn system_prompt(context: &ShellContext) -> String {
+    let mut prompt = String::from(INSTRUCTIONS);
+    prompt.push_str(&context_block(context));
+    prompt
+}
+
+/// Rules and examples, the same for every request. Kept first in the
+/// system prompt so providers that cache prompt prefixes can reuse it.
+pub(crate) const INSTRUCTIONS: &str = "You are a shell command autocomplete engine. Given the user's partial command \
+    and context, suggest the most likely completions.\n\n\
+    Rules:\n\
+    - Respond ONLY with a JSON array of objects\n\
+    - Each object has \"text\" (the full command) and \"description\" (brief explanation)\n\
+    - Order by relevance. Maximum 5 suggestions\n\
+    - Prefer commands the user has used before (from history)\n\
+    - Be context-aware: use git branch names, project type, and CWD\n\
+    - For git commands: suggest real branch names and recent commit hashes when relevant\n\
+    - Never suggest dangerous commands (rm -rf /, :(){:|:&};:) unless clearly intended\n\n\
+    Examples:\n\
+    Input: `git c` → [{\"text\":\"git commit -m \\\"\\\"\",\"description\":\"Commit staged changes\"},{\"text\":\"git checkout\",\"description\":\"Switch branches\"}]\n\
+    Input: `cargo t` → [{\"text\":\"cargo test\",\"description\":\"Run tests\"},{\"text\":\"cargo test --release\",\"description\":\"Run tests in release mode\"}]\n\
+    Input: `docker` → [{\"text\":\"docker ps\",\"description\":\"List running containers\"},{\"text\":\"docker compose up\",\"description\":\"Start services\"}]";
+
+/// The shell's context: git state, project type, environment. The same
+/// for every request from one directory until something there changes.
+pub(crate) fn context_block(context: &ShellContext) -> String {
+    let mut prompt = String::new();
+
+    // Git context with actionable details
+    if let Some(ref git) = context.git {
+        prompt.push_str(&format!(
+            "\n\nGit context:\n  Branch: {}\n  Dirty: {}",
+            git.branch, git.dirty
+        ));
+        if !git.recent_commits.is_empty() {
+            prompt.push_str("\n  Recent commits:");
+            for commit in &git.recent_commits {
+                prompt.push_str(&format!("\n    {commit}"));
+            }
+        }
+    }
+
+    if let Some(ref project) = context.project {
+        prompt.push_str(&format!("\nProject type: {project:?}"));
+    }
+
+    // Only include useful env vars
+    let useful_vars: Vec<String> = context
+        .env_vars
+        .iter()
+        .filter(|(k, _)| {
+            matches!(
+                k.as_str(),
+                "EDITOR" | "VIRTUAL_ENV" | "NODE_ENV" | "GOPATH" | "RUSTUP_HOME"
+            )
+        })
+        .map(|(k, v)| format!("{k}={v}"))
+        .collect();
+    if !useful_vars.is_empty() {
+        prompt.push_str(&format!("\nEnvironment: {}", useful_vars.join(", ")));
+    }
+
+    if let Some(ref container) = context.container {
+        prompt.push_str(&format!("\nRunning inside a {}", container.describe()));
+    }
+
+    if let Some(ref dev_env) = context.dev_env {
+        prompt.push_str(&format!("\nDev environment: {}", dev_env.describe()));
+    }
+
+    prompt
+}
+
+/// User prompt: the partial command, recent activity and history.
+pub(crate) fn user_prompt(request: &CompletionRequest, context: &ShellContext) -> String {
+    let shell = request.shell.as_deref().unwrap_or("unknown");
+    let mut prompt = format!(
+        "Shell: {shell}\nCWD: {}\nPartial command: `{}`",
+        request.cwd, request.input,
+    );
+
+    if let Some(ref host) = context.remote_host {
+        prompt.push_str(&format!("\nRemote host (SSH session): {host}"));
+    }
+
+    if let Some(ref prefix) = context.line_prefix {
+        prompt.push_str(&format!(
+            "\nEarlier on the same line: `{}` (complete only the partial command)",
+            prefix.trim()
+        ));
+    }
+
+    if let Some(ref summary) = context.session_summary {
+        prompt.push_str(&format!("\n\nRecent activity summary:\n{summary}"));
+    }
+
+    if !context.history.is_empty() {
+        let recent: Vec<&String> = context
+            .history
+            .iter()
+            .rev()
+            .take(history_lines(context, 15))
+            .collect();
+        prompt.push_str("\n\nRecent history (most recent first):\n");
+        for cmd in &recent {
+            prompt.push_str(&format!("  {cmd}\n"));
+        }
+    }
+
+    prompt
+}
+
+/// Parse the model's JSON array of suggestions, best first.
+pub fn parse_completions(text: &str) -> Vec<CompletionItem> {
+    // Try to parse the response as JSON array
+    #[derive(Deserialize)]
+    struct Suggestion {
+        text: String,
+        description: Option<String>,
+    }
+
+    // Extract JSON from the response (might be wrapped in markdown code blocks)
+    let json_str = text
+        .trim()
+        .trim_start_matches("```json")
+        .trim_start_matches("```")
+        .trim_end_matches("```")
+        .trim();
+
+    match serde_json::from_str::<Vec<Suggestion>>(json_str) {
+        Ok(suggestions) => suggestions
+            .into_iter()
+            .enumerate()
+            .map(|(i, s)| CompletionItem {
+                text: s.text,
+                description: s.description,
+                kind: CompletionKind::FullCommand,
+                score: 1.0 - (i as f64 * 0.1),
+                metadata: CompletionMetadata::default(),
+            })
+            .collect(),
+        Err(e) => {
+            warn!(error = %e, "Failed to parse completion response as JSON");
+            vec![]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_json_completions() {
+        let text = r#"[
+            {"text": "git commit -m \"fix: resolve issue\"", "description": "Commit staged changes"},
+            {"text": "git checkout -b feature/new", "description": "Create and switch to new branch"}
+        ]"#;
+
+        let completions = parse_completions(text);
+        assert_eq!(completions.len(), 2);
+        assert!(completions[0].text.contains("git commit"));
+        assert!(completions[0].score > completions[1].score);
+    }
+
+    #[test]
+    fn a_summary_replaces_older_history() {
+        let request = CompletionRequest {
+            input: "cargo t".to_string(),
+            cursor_pos: 7,
+            cwd: "/repo".to_string(),
+            history: vec![],
+            shell: Some("zsh".to_string()),
+            max_items: None,
+            min_score: None,
+            host: None,
+            provider: None,
+            model: None,
+            cancel_token: None,
+            session_id: None,
+        };
+        let mut context = ShellContext {
+            history: [
+                "cd ~/src/murmur",
+                "git pull --rebase",
+                "cargo build",
+                "vim crates/murmur-daemon/src/cache.rs",
+                "cargo test -p murmur-daemon cache",
+                "cargo test -p murmur-daemon cache -- --nocapture",
+                "git diff",
+                "git add -A",
+                "git commit -m 'Index typed inputs'",
+                "cargo clippy --workspace --all-targets",
+                "vim crates/murmur-daemon/src/handler.rs",
+                "cargo build",
+                "cargo test --workspace",
+                "git status",
+                "git push",
+            ]
+            .map(String::from)
+            .to_vec(),
+            ..ShellContext::default()
+        };
+        let without = user_prompt(&request, &context);
+        context.session_summary = Some(
+            "Working on the completion cache in the murmur repo: editing cache.rs \
+             and handler.rs, running the daemon's tests and clippy, then \
+             committing and pushing. No failures outstanding."
+                .to_string(),
+        );
+        let with = user_prompt(&request, &context);
+        assert!(with.len() <= without.len(), "{with}");
+        assert!(with.contains("git push") && !with.contains("git pull"));
+    }
+
+    #[test]
+    fn parse_markdown_wrapped_json() {
+        let text = "```json\n[{\"text\": \"ls -la\", \"description\": \"List all files\"}]\n```";
+        let completions = parse_completions(text);
+        assert_eq!(completions.len(), 1);
+    }
+}
@@ -1,14 +1,17 @@
 use async_trait::async_trait;
 use murmur_context::ShellContext;
-use murmur_protocol::{CompletionItem, CompletionKind, CompletionRequest};
+use murmur_protocol::{CompletionItem, CompletionKind, CompletionMetadata, CompletionRequest};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use tracing::debug;
 
-use crate::{Provider, ProviderConfig, ProviderError};
+use crate::retry::RetryPolicy;
+use crate::{
+    http, Capabilities, CostClass, Exchange, LatencyClass, Provider, ProviderConfig, ProviderError,
+};
 
 const DEFAULT_MODEL: &str = "codestral-latest";
-const DEFAULT_ENDPOINT: &str = "https://codestral.mistral.ai/v1/fim/completions";
+pub(crate) const DEFAULT_ENDPOINT: &str = "https://codestral.mistral.ai/v1/fim/completions";
 
 pub struct CodestralProvider {
     client: Client,
@@ -16,6 +19,7 @@ pub struct CodestralProvider {
     model: String,
     endpoint: String,
     timeout: std::time::Duration,
+    retry: RetryPolicy,
 }
 
 #[derive(Serialize)]
@@ -52,7 +56,7 @@ impl CodestralProvider {
             .ok_or_else(|| ProviderError::NotConfigured("codestral: api_key required".into()))?;
 
         Ok(Self {
-            client: Client::new(),
+            client: http::client(config)?,
             api_key,
             model: config
                 .model
@@ -63,89 +67,118 @@ impl CodestralProvider {
                 .clone()
                 .unwrap_or_else(|| DEFAULT_ENDPOINT.to_string()),
             timeout: std::time::Duration::from_millis(config.timeout_ms),
+            retry: RetryPolicy::from_config(config),
         })
     }
+}
 
-    /// Build FIM prompt from command context.
-    /// For shell commands, the "prefix" is what the user typed and the "suffix" is empty.
-    /// For code-like inputs, we can provide surrounding context.
-    fn build_prompt(
-        &self,
-        request: &CompletionRequest,
-        context: &ShellContext,
-    ) -> (String, String) {
-        let shell = request.shell.as_deref().unwrap_or("bash");
-        let mut prefix = String::new();
+/// Build FIM prompt from command context. Also used by Ollama's FIM mode.
+/// For shell commands, the "prefix" is what the user typed and the "suffix" is empty.
+/// For code-like inputs, we can provide surrounding context.
+pub(crate) fn fim_prompt(request: &CompletionRequest, context: &ShellContext) -> (String, String) {
+    let shell = request.shell.as_deref().unwrap_or("bash");
+    let mut prefix = String::new();
+
+    // Add context as comments
+    prefix.push_str(&format!("# Shell: {shell}\n"));
+    prefix.push_str(&format!("# CWD: {}\n", request.cwd));
+    if let Some(ref host) = context.remote_host {
+        prefix.push_str(&format!("# Remote host (SSH): {host}\n"));
+    }
 
-        // Add context as comments
-        prefix.push_str(&format!("# Shell: {shell}\n"));
-        prefix.push_str(&format!("# CWD: {}\n", request.cwd));
+    if let Some(ref git) = context.git {
+        prefix.push_str(&format!("# Git branch: {}\n", git.branch));
+    }
 
-        if let Some(ref git) = context.git {
-            prefix.push_str(&format!("# Git branch: {}\n", git.branch));
-        }
+    if let Some(ref project) = context.project {
+        prefix.push_str(&format!("# Project: {project:?}\n"));
+    }
+
+    if let Some(ref container) = context.container {
+        prefix.push_str(&format!("# Container: {}\n", container.describe()));
+    }
+
+    if let Some(ref dev_env) = context.dev_env {
+        prefix.push_str(&format!("# Dev environment: {}\n", dev_env.describe()));
+    }
 
-        if let Some(ref project) = context.project {
-            prefix.push_str(&format!("# Project: {project:?}\n"));
+    if let Some(ref summary) = context.session_summary {
+        for line in summary.lines() {
+            prefix.push_str(&format!("# {line}\n"));
         }
+    }
 
-        // Add recent history as context
-        if !context.history.is_empty() {
-            prefix.push_str("# Recent commands:\n");
-            for cmd in context.history.iter().rev().take(5) {
-                prefix.push_str(&format!("# $ {cmd}\n"));
-            }
+    // Add recent history as context
+    if !context.history.is_empty() {
+        prefix.push_str("# Recent commands:\n");
+        for cmd in context
+            .history
+            .iter()
+            .rev()
+            .take(crate::chat::history_lines(context, 5))
+        {
+            prefix.push_str(&format!("# $ {cmd}\n"));
         }
+    }
 
-        prefix.push_str("$ ");
-        prefix.push_str(&request.input);
+    prefix.push_str("$ ");
+    prefix.push_str(&request.input);
 
-        // Suffix is empty — we want completions after the cursor
-        let suffix = String::from("\n");
+    // Suffix is empty — we want completions after the cursor
+    let suffix = String::from("\n");
 
-        (prefix, suffix)
-    }
+    (prefix, suffix)
+}
 
-    fn parse_fim_completions(&self, text: &str, input: &str) -> Vec<CompletionItem> {
-        // FIM returns the completion text (what comes after the cursor)
-        let completion = text.trim();
-        if completion.is_empty() {
-            return vec![];
-        }
+/// Turn a FIM continuation of `input` into suggestions: the first line
+/// completes the input, later lines are follow-up commands.
+pub fn parse_fim_completions(text: &str, input: &str) -> Vec<CompletionItem> {
+    fim_items(text, input, "Codestral")
+}
 
-        // Split by newlines — each line could be a separate command suggestion
-        let mut items = Vec::new();
-        let full_command = format!("{}{}", input, completion.lines().next().unwrap_or(""));
+/// `parse_fim_completions` for any FIM provider, named `source` in the
+/// descriptions.
+pub(crate) fn fim_items(text: &str, input: &str, source: &str) -> Vec<CompletionItem> {
+    // FIM returns the completion text (what comes after the cursor)
+    let completion = text.trim();
+    if completion.is_empty() {
+        return vec![];
+    }
 
-        if !full_command.trim().is_empty() && full_command != input {
+    // Split by newlines — each line could be a separate command suggestion
+    let mut items = Vec::new();
+    let full_command = format!("{}{}", input, completion.lines().next().unwrap_or(""));
+
+    if !full_command.trim().is_empty() && full_command != input {
+        items.push(CompletionItem {
+            text: full_command.trim().to_string(),
+            description: Some(format!("Code completion ({source})")),
+            kind: CompletionKind::Code,
+            score: 1.0,
+            metadata: CompletionMetadata::default(),
+        });
+    }
+
+    // If there are multiple lines, add them as additional suggestions
+    for (i, line) in completion.lines().skip(1).take(4).enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        // Strip the `$ ` prefix if present
+        let cmd = line.strip_prefix("$ ").unwrap_or(line);
+        if !cmd.is_empty() {
             items.push(CompletionItem {
-                text: full_command.trim().to_string(),
-                description: Some("Code completion (Codestral)".to_string()),
+                text: cmd.to_string(),
+                description: Some(format!("Follow-up command ({source})")),
                 kind: CompletionKind::Code,
-                score: 1.0,
+                score: 0.8 - (i as f64 * 0.1),
+                metadata: CompletionMetadata::default(),
             });
         }
-
-        // If there are multiple lines, add them as additional suggestions
-        for (i, line) in completion.lines().skip(1).take(4).enumerate() {
-            let line = line.trim();
-            if line.is_empty() || line.starts_with('#') {
-                continue;
-            }
-            // Strip the `$ ` prefix if present
-            let cmd = line.strip_prefix("$ ").unwrap_or(line);
-            if !cmd.is_empty() {
-                items.push(CompletionItem {
-                    text: cmd.to_string(),
-                    description: Some("Follow-up command (Codestral)".to_string()),
-                    kind: CompletionKind::Code,
-                    score: 0.8 - (i as f64 * 0.1),
-                });
-            }
-        }
-
-        items
     }
+
+    items
 }
 
 #[async_trait]
@@ -154,33 +187,52 @@ impl Provider for CodestralProvider {
         "codestral"
     }
 
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            streaming: true,
+            fim: true,
+            json_mode: false,
+            max_context: 256_000,
+            latency: LatencyClass::Moderate,
+            cost: CostClass::Low,
+        }
+    }
+
     async fn complete(
         &self,
         request: &CompletionRequest,
         context: &ShellContext,
     ) -> Result<Vec<CompletionItem>, ProviderError> {
-        let (prompt, suffix) = self.build_prompt(request, context);
+        Ok(self.exchange(request, context).await?.items)
+    }
 
-        debug!(model = %self.model, input = %request.input, "Requesting FIM completion from Codestral");
+    async fn exchange(
+        &self,
+        request: &CompletionRequest,
+        context: &ShellContext,
+    ) -> Result<Exchange, ProviderError> {
+        let (prompt, suffix) = fim_prompt(request, context);
+
+        let model = request.model.as_deref().unwrap_or(&self.model);
+        debug!(model = %model, input = %request.input, "Requesting FIM completion from Codestral");
 
         let body = FimRequest {
-            model: self.model.clone(),
-            prompt,
-            suffix,
+            model: model.to_string(),
+            prompt: prompt.clone(),
+            suffix: suffix.clone(),
             stop: Some(vec!["\n\n".to_string(), "$ ".to_string()]),
             max_tokens: 256,
             temperature: 0.2,
         };
 
-        let response = self
+        let http_request = self
             .client
             .post(&self.endpoint)
             .header("Authorization", format!("Bearer {}", self.api_key))
             .header("content-type", "application/json")
             .timeout(self.timeout)
-            .json(&body)
-            .send()
-            .await?;
+            .json(&body);
+        let response = self.retry.send(http_request).await?;
 
         let status = response.status();
         if !status.is_success() {
@@ -198,7 +250,11 @@ impl Provider for CodestralProvider {
             .map(|c| c.message.content.as_str())
             .unwrap_or("");
 
-        Ok(self.parse_fim_completions(text, &request.input))
+        Ok(Exchange {
+            prompt: vec![("prefix", prompt), ("suffix", suffix)],
+            raw: text.to_string(),
+            items: parse_fim_completions(text, &request.input),
+        })
     }
 
     async fn health_check(&self) -> Result<(), ProviderError> {
@@ -238,20 +294,9 @@ impl Provider for CodestralProvider {
 mod tests {
     use super::*;
 
-    fn make_provider() -> CodestralProvider {
-        CodestralProvider {
-            client: Client::new(),
-            api_key: "test".to_string(),
-            model: "test".to_string(),
-            endpoint: "test".to_string(),
-            timeout: std::time::Duration::from_secs(5),
-        }
-    }
-
     #[test]
     fn parse_single_completion() {
-        let provider = make_provider();
-        let items = provider.parse_fim_completions("ommit -m \"fix: resolve issue\"", "git c");
+        let items = parse_fim_completions("ommit -m \"fix: resolve issue\"", "git c");
         assert_eq!(items.len(), 1);
         assert_eq!(items[0].text, "git commit -m \"fix: resolve issue\"");
         assert_eq!(items[0].kind, CompletionKind::Code);
@@ -259,9 +304,8 @@ mod tests {
 
     #[test]
     fn parse_multi_line_completion() {
-        let provider = make_provider();
         let text = "ommit -m \"fix bug\"\n$ git push origin main\n$ git log --oneline -5";
-        let items = provider.parse_fim_completions(text, "git c");
+        let items = parse_fim_completions(text, "git c");
         assert_eq!(items.len(), 3);
         assert_eq!(items[0].text, "git commit -m \"fix bug\"");
         assert_eq!(items[1].text, "git push origin main");
@@ -270,8 +314,7 @@ mod tests {
 
     #[test]
     fn parse_empty_completion() {
-        let provider = make_provider();
-        let items = provider.parse_fim_completions("", "git");
+        let items = parse_fim_completions("", "git");
         assert!(items.is_empty());
     }
 }
@@ -0,0 +1,290 @@
+//! Providers implemented by a separate program, in any language.
+//!
+//! A `[providers.<name>]` section with a `command` starts that command
+//! (through `sh -c`) on first use and talks JSON-RPC 2.0 with it, one
+//! message per line on its stdin and stdout:
+//!
+//! - `complete`, with `{"request": ..., "context": ...}` (a completion
+//!   request and the shell context), answers an array of completion items
+//! - `generate`, with `{"system": ..., "prompt": ...}`, answers a string;
+//!   plugins that can't answer an error
+//! - `health_check` answers anything but an error
+//!
+//! The process is kept running and asked one request at a time. It is
+//! restarted if it exits, closes its stdout or doesn't answer within
+//! `timeout_ms`. Lines on its stdout that aren't a response to the pending
+//! request are ignored, so stray debug output doesn't break the exchange;
+//! stderr goes to the daemon's.
+
+use async_trait::async_trait;
+use murmur_context::ShellContext;
+use murmur_protocol::{
+    CompletionItem, CompletionRequest, JsonRpcRequest, JsonRpcResponse, RequestId,
+};
+use serde_json::{json, Value};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::Mutex;
+use tracing::{debug, warn};
+
+use crate::{Exchange, Provider, ProviderConfig, ProviderError};
+
+pub struct ExternalProvider {
+    name: String,
+    command: String,
+    timeout: Duration,
+    process: Mutex<Option<Plugin>>,
+    next_id: AtomicI64,
+}
+
+/// A running plugin process.
+struct Plugin {
+    /// Held so the process is killed when the plugin is dropped.
+    _child: Child,
+    stdin: ChildStdin,
+    stdout: Lines<BufReader<ChildStdout>>,
+}
+
+impl ExternalProvider {
+    pub fn new(name: &str, config: &ProviderConfig) -> Result<Self, ProviderError> {
+        let command = config
+            .command
+            .clone()
+            .filter(|c| !c.trim().is_empty())
+            .ok_or_else(|| ProviderError::NotConfigured(format!("{name}: command required")))?;
+        Ok(Self {
+            name: name.to_string(),
+            command,
+            timeout: Duration::from_millis(config.timeout_ms),
+            process: Mutex::new(None),
+            next_id: AtomicI64::new(1),
+        })
+    }
+
+    fn spawn(&self) -> Result<Plugin, ProviderError> {
+        debug!(provider = %self.name, command = %self.command, "Starting plugin");
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| ProviderError::Plugin(format!("{}: failed to start: {e}", self.name)))?;
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            return Err(ProviderError::Plugin(format!("{}: no stdio", self.name)));
+        };
+        Ok(Plugin {
+            _child: child,
+            stdin,
+            stdout: BufReader::new(stdout).lines(),
+        })
+    }
+
+    /// Send `method` and wait for its result, restarting the process on
+    /// the next call if this one leaves it in an unknown state.
+    async fn call(&self, method: &str, params: Value) -> Result<Value, ProviderError> {
+        let mut process = self.process.lock().await;
+        let plugin = match process.take() {
+            Some(plugin) => plugin,
+            None => self.spawn()?,
+        };
+        match tokio::time::timeout(self.timeout, self.request(plugin, method, params)).await {
+            Ok((result, plugin)) => {
+                *process = plugin;
+                result
+            }
+            Err(_) => {
+                warn!(provider = %self.name, method, "Plugin didn't answer in time, restarting it");
+                Err(ProviderError::Timeout)
+            }
+        }
+    }
+
+    /// One request and its response. Gives the process back unless it
+    /// failed.
+    async fn request(
+        &self,
+        mut plugin: Plugin,
+        method: &str,
+        params: Value,
+    ) -> (Result<Value, ProviderError>, Option<Plugin>) {
+        let id = RequestId::Number(self.next_id.fetch_add(1, Ordering::Relaxed));
+        let request = JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            method: method.to_string(),
+            params: Some(params),
+            id: id.clone(),
+        };
+        let mut line = match serde_json::to_string(&request) {
+            Ok(line) => line,
+            Err(e) => return (Err(e.into()), Some(plugin)),
+        };
+        line.push('\n');
+
+        let broken = |e: String| {
+            warn!(provider = %self.name, error = %e, "Plugin failed, restarting it");
+            (
+                Err(ProviderError::Plugin(format!("{}: {e}", self.name))),
+                None,
+            )
+        };
+        if let Err(e) = plugin.stdin.write_all(line.as_bytes()).await {
+            return broken(e.to_string());
+        }
+        if let Err(e) = plugin.stdin.flush().await {
+            return broken(e.to_string());
+        }
+
+        loop {
+            let line = match plugin.stdout.next_line().await {
+                Ok(Some(line)) => line,
+                Ok(None) => return broken("exited".to_string()),
+                Err(e) => return broken(e.to_string()),
+            };
+            let Ok(response) = serde_json::from_str::<JsonRpcResponse>(&line) else {
+                debug!(provider = %self.name, line = %line, "Ignoring plugin output");
+                continue;
+            };
+            if response.id != id {
+                continue;
+            }
+            let result = match response.error {
+                Some(error) => Err(ProviderError::Plugin(format!(
+                    "{}: {}",
+                    self.name, error.message
+                ))),
+                None => Ok(response.result.unwrap_or(Value::Null)),
+            };
+            return (result, Some(plugin));
+        }
+    }
+}
+
+#[async_trait]
+impl Provider for ExternalProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn complete(
+        &self,
+        request: &CompletionRequest,
+        context: &ShellContext,
+    ) -> Result<Vec<CompletionItem>, ProviderError> {
+        Ok(self.exchange(request, context).await?.items)
+    }
+
+    async fn exchange(
+        &self,
+        request: &CompletionRequest,
+        context: &ShellContext,
+    ) -> Result<Exchange, ProviderError> {
+        debug!(provider = %self.name, input = %request.input, "Requesting completion from plugin");
+        let params = json!({ "request": request, "context": context });
+        let prompt = vec![("params", serde_json::to_string_pretty(&params)?)];
+        let result = self.call("complete", params).await?;
+        Ok(Exchange {
+            prompt,
+            raw: result.to_string(),
+            items: serde_json::from_value(result)?,
+        })
+    }
+
+    async fn generate(&self, system: &str, prompt: &str) -> Result<String, ProviderError> {
+        debug!(provider = %self.name, "Requesting generation from plugin");
+        let result = self
+            .call("generate", json!({ "system": system, "prompt": prompt }))
+            .await?;
+        match result {
+            Value::String(text) => Ok(text.trim().to_string()),
+            other => Err(ProviderError::Plugin(format!(
+                "{}: generate answered {other} instead of a string",
+                self.name
+            ))),
+        }
+    }
+
+    async fn health_check(&self) -> Result<(), ProviderError> {
+        self.call("health_check", Value::Null).await.map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Answers every request with `result`, echoing its ID.
+    fn answering(result: &str) -> String {
+        format!(
+            r#"while read -r line; do id=$(printf '%s' "$line" | sed -E 's/.*"id":([0-9]+)}}$/\1/'); echo "not json"; printf '{{"jsonrpc":"2.0","id":%s,{result}}}\n' "$id"; done"#
+        )
+    }
+
+    fn provider(command: &str, timeout_ms: u64) -> ExternalProvider {
+        let config = ProviderConfig {
+            api_key: None,
+            api_key_header: None,
+            api: None,
+            command: Some(command.to_string()),
+            proxy: None,
+            model: None,
+            endpoint: None,
+            enabled: true,
+            timeout_ms,
+            max_retries: 0,
+            backoff_ms: 200,
+            retry_on_429: true,
+        };
+        ExternalProvider::new("myplugin", &config).unwrap()
+    }
+
+    fn request() -> CompletionRequest {
+        serde_json::from_value(json!({ "input": "git st", "cursor_pos": 6, "cwd": "/repo" }))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn completions_come_from_the_plugin() {
+        let plugin = provider(
+            &answering(r#""result":[{"text":"git status","kind":"command","score":0.9}]"#),
+            5000,
+        );
+        for _ in 0..2 {
+            let items = plugin
+                .complete(&request(), &ShellContext::default())
+                .await
+                .unwrap();
+            assert_eq!(items.len(), 1);
+            assert_eq!(items[0].text, "git status");
+        }
+        plugin.health_check().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn errors_and_exits_are_reported() {
+        let plugin = provider(
+            &answering(r#""error":{"code":-32601,"message":"no such method"}"#),
+            5000,
+        );
+        let error = plugin.generate("system", "prompt").await.unwrap_err();
+        assert_eq!(error.to_string(), "Plugin error: myplugin: no such method");
+
+        // Exits after one line; each call starts it again
+        let plugin = provider("read -r line", 5000);
+        for _ in 0..2 {
+            let error = plugin.health_check().await.unwrap_err();
+            assert_eq!(error.to_string(), "Plugin error: myplugin: exited");
+        }
+
+        let plugin = provider("sleep 5", 100);
+        assert!(matches!(
+            plugin.health_check().await,
+            Err(ProviderError::Timeout)
+        ));
+    }
+}
@@ -0,0 +1,309 @@
+use async_trait::async_trait;
+use murmur_context::ShellContext;
+use murmur_protocol::{CompletionItem, CompletionRequest};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::chat;
+use crate::retry::RetryPolicy;
+use crate::{
+    http, Capabilities, CostClass, Exchange, LatencyClass, Provider, ProviderConfig, ProviderError,
+};
+
+const DEFAULT_MODEL: &str = "gemini-2.0-flash";
+pub(crate) const DEFAULT_ENDPOINT: &str = "https://generativelanguage.googleapis.com/v1beta";
+
+/// Google Gemini via the Generative Language API (AI Studio keys, including
+/// the free tier).
+pub struct GeminiProvider {
+    client: Client,
+    api_key: String,
+    model: String,
+    endpoint: String,
+    timeout: std::time::Duration,
+    retry: RetryPolicy,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerateRequest {
+    system_instruction: Content,
+    contents: Vec<Content>,
+    generation_config: GenerationConfig,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Content {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    role: Option<String>,
+    #[serde(default)]
+    parts: Vec<Part>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Part {
+    #[serde(default)]
+    text: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerationConfig {
+    max_output_tokens: u32,
+}
+
+#[derive(Deserialize)]
+struct GenerateResponse {
+    #[serde(default)]
+    candidates: Vec<Candidate>,
+}
+
+#[derive(Deserialize)]
+struct Candidate {
+    /// Missing when the candidate was blocked by safety filters.
+    #[serde(default)]
+    content: Option<Content>,
+}
+
+impl Content {
+    fn text(role: Option<&str>, text: String) -> Self {
+        Self {
+            role: role.map(String::from),
+            parts: vec![Part { text }],
+        }
+    }
+}
+
+impl GeminiProvider {
+    pub fn new(config: &ProviderConfig) -> Result<Self, ProviderError> {
+        let api_key = config
+            .api_key
+            .clone()
+            .ok_or_else(|| ProviderError::NotConfigured("gemini: api_key required".into()))?;
+
+        Ok(Self {
+            client: http::client(config)?,
+            api_key,
+            model: config
+                .model
+                .clone()
+                .unwrap_or_else(|| DEFAULT_MODEL.to_string()),
+            endpoint: config
+                .endpoint
+                .clone()
+                .unwrap_or_else(|| DEFAULT_ENDPOINT.to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            timeout: std::time::Duration::from_millis(config.timeout_ms),
+            retry: RetryPolicy::from_config(config),
+        })
+    }
+
+    /// Model URL; `model` may be given with or without the `models/` prefix.
+    fn model_url(&self, model: &str) -> String {
+        let model = model.trim_start_matches("models/");
+        format!("{}/models/{model}", self.endpoint)
+    }
+
+    /// Send a system + user request and return the first candidate's text.
+    async fn send_generate(
+        &self,
+        model: &str,
+        system: String,
+        user: String,
+        max_tokens: u32,
+    ) -> Result<String, ProviderError> {
+        let body = GenerateRequest {
+            system_instruction: Content::text(None, system),
+            contents: vec![Content::text(Some("user"), user)],
+            generation_config: GenerationConfig {
+                max_output_tokens: max_tokens,
+            },
+        };
+
+        let request = self
+            .client
+            .post(format!("{}:generateContent", self.model_url(model)))
+            .header("x-goog-api-key", &self.api_key)
+            .timeout(self.timeout)
+            .json(&body);
+        let response = self.retry.send(request).await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let message = response.text().await.unwrap_or_default();
+            return Err(ProviderError::Api {
+                status: status.as_u16(),
+                message,
+            });
+        }
+
+        let api_response: GenerateResponse = response.json().await?;
+        Ok(first_candidate_text(api_response))
+    }
+}
+
+/// Concatenated text parts of the first candidate, or empty if none.
+fn first_candidate_text(response: GenerateResponse) -> String {
+    response
+        .candidates
+        .into_iter()
+        .next()
+        .and_then(|c| c.content)
+        .map(|content| {
+            content
+                .parts
+                .into_iter()
+                .map(|p| p.text)
+                .collect::<String>()
+        })
+        .unwrap_or_default()
+}
+
+#[async_trait]
+impl Provider for GeminiProvider {
+    fn name(&self) -> &str {
+        "gemini"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            streaming: true,
+            fim: false,
+            json_mode: true,
+            max_context: 1_048_576,
+            latency: LatencyClass::Moderate,
+            cost: CostClass::Low,
+        }
+    }
+
+    async fn complete(
+        &self,
+        request: &CompletionRequest,
+        context: &ShellContext,
+    ) -> Result<Vec<CompletionItem>, ProviderError> {
+        Ok(self.exchange(request, context).await?.items)
+    }
+
+    async fn exchange(
+        &self,
+        request: &CompletionRequest,
+        context: &ShellContext,
+    ) -> Result<Exchange, ProviderError> {
+        let system = chat::system_prompt(context);
+        let user = chat::user_prompt(request, context);
+
+        let model = request.model.as_deref().unwrap_or(&self.model);
+        debug!(model = %model, input = %request.input, "Requesting completion from Gemini");
+
+        let prompt = vec![("system", system.clone()), ("user", user.clone())];
+        let text = self.send_generate(model, system, user, 512).await?;
+        let items = if text.trim().is_empty() {
+            vec![]
+        } else {
+            chat::parse_completions(&text)
+        };
+        Ok(Exchange {
+            prompt,
+            raw: text,
+            items,
+        })
+    }
+
+    async fn generate(&self, system: &str, prompt: &str) -> Result<String, ProviderError> {
+        debug!(model = %self.model, "Requesting generation from Gemini");
+        let text = self
+            .send_generate(&self.model, system.to_string(), prompt.to_string(), 1024)
+            .await?;
+        Ok(text.trim().to_string())
+    }
+
+    async fn health_check(&self) -> Result<(), ProviderError> {
+        debug!("Gemini health check");
+        // Fetching the model's metadata checks the key and model name
+        // without spending any quota
+        let response = self
+            .client
+            .get(self.model_url(&self.model))
+            .header("x-goog-api-key", &self.api_key)
+            .timeout(std::time::Duration::from_secs(5))
+            .send()
+            .await?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(ProviderError::Api {
+                status: response.status().as_u16(),
+                message: "Gemini health check failed".to_string(),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(api_key: Option<&str>, model: Option<&str>) -> ProviderConfig {
+        ProviderConfig {
+            api_key: api_key.map(String::from),
+            api_key_header: None,
+            api: None,
+            command: None,
+            proxy: None,
+            model: model.map(String::from),
+            endpoint: None,
+            enabled: true,
+            timeout_ms: 5000,
+            max_retries: 0,
+            backoff_ms: 200,
+            retry_on_429: true,
+        }
+    }
+
+    #[test]
+    fn key_required_and_model_prefix_optional() {
+        assert!(GeminiProvider::new(&config(None, None)).is_err());
+        let provider = GeminiProvider::new(&config(Some("key"), None)).unwrap();
+        assert_eq!(
+            provider.model_url(&provider.model),
+            "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.0-flash"
+        );
+        let provider =
+            GeminiProvider::new(&config(Some("key"), Some("models/gemini-1.5-pro"))).unwrap();
+        assert!(provider
+            .model_url(&provider.model)
+            .ends_with("/models/gemini-1.5-pro"));
+    }
+
+    #[test]
+    fn request_uses_api_field_names() {
+        let body = GenerateRequest {
+            system_instruction: Content::text(None, "system".to_string()),
+            contents: vec![Content::text(Some("user"), "hi".to_string())],
+            generation_config: GenerationConfig {
+                max_output_tokens: 64,
+            },
+        };
+        let json = serde_json::to_value(&body).unwrap();
+        assert_eq!(json["systemInstruction"]["parts"][0]["text"], "system");
+        assert!(json["systemInstruction"].get("role").is_none());
+        assert_eq!(json["contents"][0]["role"], "user");
+        assert_eq!(json["generationConfig"]["maxOutputTokens"], 64);
+    }
+
+    #[test]
+    fn response_joins_parts_and_tolerates_blocked_candidates() {
+        let json = r#"{"candidates": [{"content": {"role": "model", "parts": [{"text": "[{\"text\": "}, {"text": "\"ls\"}]"}]}, "finishReason": "STOP"}]}"#;
+        let response: GenerateResponse = serde_json::from_str(json).unwrap();
+        assert_eq!(first_candidate_text(response), r#"[{"text": "ls"}]"#);
+
+        let blocked = r#"{"candidates": [{"finishReason": "SAFETY"}], "promptFeedback": {}}"#;
+        let response: GenerateResponse = serde_json::from_str(blocked).unwrap();
+        assert_eq!(first_candidate_text(response), "");
+        let response: GenerateResponse = serde_json::from_str("{}").unwrap();
+        assert_eq!(first_candidate_text(response), "");
+    }
+}
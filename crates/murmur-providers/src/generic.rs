@@ -0,0 +1,276 @@
+use async_trait::async_trait;
+use murmur_context::ShellContext;
+use murmur_protocol::{CompletionItem, CompletionRequest};
+use reqwest::{Client, RequestBuilder};
+use tracing::debug;
+
+use crate::chat;
+use crate::openai::{ChatRequest, ChatResponse};
+use crate::retry::RetryPolicy;
+use crate::{
+    http, Capabilities, CostClass, Exchange, LatencyClass, Provider, ProviderConfig, ProviderError,
+};
+
+/// Config sections named `custom_<anything>` become generic providers.
+pub const CUSTOM_PREFIX: &str = "custom_";
+
+/// Any server speaking the OpenAI Chat Completions API, configured entirely
+/// from its `[providers.custom_*]` section: vLLM, LM Studio, llama.cpp's
+/// server, Together, Fireworks, Groq and the like.
+///
+/// `base_url` is the API root (e.g. `http://localhost:1234/v1`); the key is
+/// sent as a bearer token unless `api_key_header` names another header.
+pub struct GenericOpenAiProvider {
+    name: String,
+    client: Client,
+    api_key: Option<String>,
+    api_key_header: Option<String>,
+    model: String,
+    endpoint: String,
+    timeout: std::time::Duration,
+    retry: RetryPolicy,
+}
+
+impl GenericOpenAiProvider {
+    pub fn new(name: &str, config: &ProviderConfig) -> Result<Self, ProviderError> {
+        let base_url = config
+            .endpoint
+            .as_deref()
+            .ok_or_else(|| ProviderError::NotConfigured(format!("{name}: base_url required")))?;
+        let model = config
+            .model
+            .clone()
+            .ok_or_else(|| ProviderError::NotConfigured(format!("{name}: model required")))?;
+
+        let base_url = base_url.trim_end_matches('/');
+        let endpoint = if base_url.ends_with("/chat/completions") {
+            base_url.to_string()
+        } else {
+            format!("{base_url}/chat/completions")
+        };
+
+        Ok(Self {
+            name: name.to_string(),
+            client: http::client(config)?,
+            api_key: config.api_key.clone(),
+            api_key_header: config
+                .api_key_header
+                .clone()
+                .filter(|h| !h.eq_ignore_ascii_case("authorization")),
+            model,
+            endpoint,
+            timeout: std::time::Duration::from_millis(config.timeout_ms),
+            retry: RetryPolicy::from_config(config),
+        })
+    }
+
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        match (&self.api_key, &self.api_key_header) {
+            (Some(key), Some(header)) => request.header(header.as_str(), key),
+            (Some(key), None) => request.bearer_auth(key),
+            (None, _) => request,
+        }
+    }
+
+    /// Send a system + user chat request and return the first choice's text.
+    async fn send_chat(
+        &self,
+        model: &str,
+        system: String,
+        user: String,
+        max_tokens: u32,
+    ) -> Result<String, ProviderError> {
+        let body = ChatRequest::new(model.to_string(), system, user, max_tokens);
+
+        let request = self
+            .authorize(self.client.post(&self.endpoint))
+            .timeout(self.timeout)
+            .json(&body);
+        let response = self.retry.send(request).await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let message = response.text().await.unwrap_or_default();
+            return Err(ProviderError::Api {
+                status: status.as_u16(),
+                message,
+            });
+        }
+
+        let api_response: ChatResponse = response.json().await?;
+        Ok(api_response.into_text())
+    }
+}
+
+#[async_trait]
+impl Provider for GenericOpenAiProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn complete(
+        &self,
+        request: &CompletionRequest,
+        context: &ShellContext,
+    ) -> Result<Vec<CompletionItem>, ProviderError> {
+        Ok(self.exchange(request, context).await?.items)
+    }
+
+    async fn exchange(
+        &self,
+        request: &CompletionRequest,
+        context: &ShellContext,
+    ) -> Result<Exchange, ProviderError> {
+        let system = chat::system_prompt(context);
+        let user = chat::user_prompt(request, context);
+
+        let model = request.model.as_deref().unwrap_or(&self.model);
+        debug!(provider = %self.name, model = %model, input = %request.input, "Requesting completion");
+
+        let prompt = vec![("system", system.clone()), ("user", user.clone())];
+        let text = self.send_chat(model, system, user, 512).await?;
+        let items = if text.trim().is_empty() {
+            vec![]
+        } else {
+            chat::parse_completions(&text)
+        };
+        Ok(Exchange {
+            prompt,
+            raw: text,
+            items,
+        })
+    }
+
+    async fn generate(&self, system: &str, prompt: &str) -> Result<String, ProviderError> {
+        debug!(provider = %self.name, model = %self.model, "Requesting generation");
+        let text = self
+            .send_chat(&self.model, system.to_string(), prompt.to_string(), 1024)
+            .await?;
+        Ok(text.trim().to_string())
+    }
+
+    /// Servers on this machine (vLLM, LM Studio) don't use the cloud budget.
+    fn is_local(&self) -> bool {
+        let host = self
+            .endpoint
+            .split("://")
+            .nth(1)
+            .and_then(|rest| rest.split('/').next())
+            .unwrap_or_default();
+        host.starts_with("localhost")
+            || host.starts_with("127.")
+            || host.starts_with("[::1]")
+            || host.starts_with("0.0.0.0")
+    }
+
+    /// What every OpenAI-compatible server offers; the model behind it is
+    /// unknown.
+    fn capabilities(&self) -> Capabilities {
+        let (latency, cost) = if self.is_local() {
+            (LatencyClass::Fast, CostClass::Free)
+        } else {
+            (LatencyClass::Moderate, CostClass::Medium)
+        };
+        Capabilities {
+            streaming: true,
+            latency,
+            cost,
+            ..Capabilities::default()
+        }
+    }
+
+    async fn health_check(&self) -> Result<(), ProviderError> {
+        debug!(provider = %self.name, "Health check");
+        // The models listing is part of every compatible server and free
+        let url = self.endpoint.replace("/chat/completions", "/models");
+        let response = self
+            .authorize(self.client.get(&url))
+            .timeout(std::time::Duration::from_secs(5))
+            .send()
+            .await?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(ProviderError::Api {
+                status: response.status().as_u16(),
+                message: format!("{} health check failed", self.name),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(base_url: Option<&str>, model: Option<&str>) -> ProviderConfig {
+        ProviderConfig {
+            api_key: None,
+            api_key_header: None,
+            api: None,
+            command: None,
+            proxy: None,
+            model: model.map(String::from),
+            endpoint: base_url.map(String::from),
+            enabled: true,
+            timeout_ms: 5000,
+            max_retries: 0,
+            backoff_ms: 200,
+            retry_on_429: true,
+        }
+    }
+
+    #[test]
+    fn base_url_and_model_required() {
+        assert!(GenericOpenAiProvider::new("custom_x", &config(None, Some("m"))).is_err());
+        assert!(
+            GenericOpenAiProvider::new("custom_x", &config(Some("http://h/v1"), None)).is_err()
+        );
+    }
+
+    #[test]
+    fn endpoint_is_built_from_base_url() {
+        let provider = GenericOpenAiProvider::new(
+            "custom_lmstudio",
+            &config(Some("http://localhost:1234/v1/"), Some("qwen2.5-coder-7b")),
+        )
+        .unwrap();
+        assert_eq!(provider.name(), "custom_lmstudio");
+        assert_eq!(
+            provider.endpoint,
+            "http://localhost:1234/v1/chat/completions"
+        );
+        assert!(provider.is_local());
+        assert_eq!(provider.capabilities().cost, CostClass::Free);
+        assert_eq!(provider.capabilities().latency, LatencyClass::Fast);
+
+        let provider = GenericOpenAiProvider::new(
+            "custom_together",
+            &config(
+                Some("https://api.together.xyz/v1/chat/completions"),
+                Some("meta-llama/Llama-3.3-70B-Instruct-Turbo"),
+            ),
+        )
+        .unwrap();
+        assert_eq!(
+            provider.endpoint,
+            "https://api.together.xyz/v1/chat/completions"
+        );
+        assert!(!provider.is_local());
+        assert_eq!(provider.capabilities().cost, CostClass::Medium);
+        assert!(provider.capabilities().streaming);
+    }
+
+    #[test]
+    fn authorization_header_means_bearer() {
+        let mut cfg = config(Some("http://h/v1"), Some("m"));
+        cfg.api_key_header = Some("Authorization".to_string());
+        let provider = GenericOpenAiProvider::new("custom_x", &cfg).unwrap();
+        assert!(provider.api_key_header.is_none());
+
+        cfg.api_key_header = Some("x-api-key".to_string());
+        let provider = GenericOpenAiProvider::new("custom_x", &cfg).unwrap();
+        assert_eq!(provider.api_key_header.as_deref(), Some("x-api-key"));
+    }
+}
@@ -0,0 +1,524 @@
+//! Completions without a model: shell history, a table of common
+//! subcommands and flags, and the file system.
+//!
+//! - History lines that extend the input, ranked by how often and how
+//!   recently they were run
+//! - Subcommands and flags of well-known tools from a static spec
+//! - Files and directories for the word under the cursor
+//!
+//! Spec and path suggestions that were run before rank above those that
+//! weren't. Nothing leaves the machine, so the daemon can use this when no
+//! provider is configured or when it's built without model support.
+
+use async_trait::async_trait;
+use murmur_context::ShellContext;
+use murmur_protocol::{
+    CompletionItem, CompletionKind, CompletionMetadata, CompletionOrigin, CompletionRequest,
+};
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::{Capabilities, CostClass, LatencyClass, Provider, ProviderError};
+
+/// Most suggestions of each source.
+const MAX_PER_SOURCE: usize = 10;
+
+/// Directory entries read when completing a path.
+const MAX_DIR_ENTRIES: usize = 2_000;
+
+/// Subcommands and flags of a program, each with a short description.
+struct Spec {
+    command: &'static str,
+    subcommands: &'static [(&'static str, &'static str)],
+    flags: &'static [(&'static str, &'static str)],
+}
+
+const SPECS: &[Spec] = &[
+    Spec {
+        command: "git",
+        subcommands: &[
+            ("add", "Stage changes"),
+            ("branch", "List, create or delete branches"),
+            ("checkout", "Switch branches or restore files"),
+            ("cherry-pick", "Apply the changes of existing commits"),
+            ("clone", "Clone a repository"),
+            ("commit", "Record staged changes"),
+            ("diff", "Show changes"),
+            ("fetch", "Download objects and refs"),
+            ("init", "Create an empty repository"),
+            ("log", "Show commit logs"),
+            ("merge", "Join histories together"),
+            ("pull", "Fetch and integrate"),
+            ("push", "Update remote refs"),
+            ("rebase", "Reapply commits on another base"),
+            ("remote", "Manage remotes"),
+            ("reset", "Reset HEAD to a state"),
+            ("restore", "Restore working tree files"),
+            ("revert", "Revert commits"),
+            ("show", "Show objects"),
+            ("stash", "Stash changes away"),
+            ("status", "Show the working tree status"),
+            ("switch", "Switch branches"),
+            ("tag", "Manage tags"),
+        ],
+        flags: &[
+            ("--all", "All refs or changes"),
+            ("--amend", "Replace the last commit"),
+            (
+                "--force-with-lease",
+                "Force only if the remote is as expected",
+            ),
+            ("--oneline", "One line per commit"),
+            ("--staged", "Staged changes"),
+            ("--hard", "Discard working tree changes"),
+            ("-m", "Message"),
+            ("-b", "Create a branch"),
+        ],
+    },
+    Spec {
+        command: "cargo",
+        subcommands: &[
+            ("add", "Add a dependency"),
+            ("bench", "Run benchmarks"),
+            ("build", "Compile the package"),
+            ("check", "Check for errors without building"),
+            ("clippy", "Run lints"),
+            ("doc", "Build documentation"),
+            ("fmt", "Format the code"),
+            ("install", "Install a binary"),
+            ("new", "Create a package"),
+            ("publish", "Upload to the registry"),
+            ("run", "Build and run a binary"),
+            ("test", "Run tests"),
+            ("update", "Update the lock file"),
+        ],
+        flags: &[
+            ("--release", "Optimized build"),
+            ("--workspace", "Every package in the workspace"),
+            ("--all-targets", "Every target"),
+            ("--all-features", "Every feature"),
+            ("--features", "Features to enable"),
+            ("-p", "Package"),
+        ],
+    },
+    Spec {
+        command: "docker",
+        subcommands: &[
+            ("build", "Build an image"),
+            ("compose", "Run multi-container applications"),
+            ("exec", "Run a command in a container"),
+            ("images", "List images"),
+            ("logs", "Show container logs"),
+            ("ps", "List containers"),
+            ("pull", "Download an image"),
+            ("push", "Upload an image"),
+            ("rm", "Remove containers"),
+            ("rmi", "Remove images"),
+            ("run", "Run a container"),
+            ("stop", "Stop containers"),
+        ],
+        flags: &[
+            ("-d", "Run in the background"),
+            ("-it", "Interactive with a terminal"),
+            ("--rm", "Remove the container on exit"),
+            ("-p", "Publish a port"),
+            ("-v", "Mount a volume"),
+            ("-f", "File or follow"),
+        ],
+    },
+    Spec {
+        command: "npm",
+        subcommands: &[
+            ("audit", "Check dependencies for vulnerabilities"),
+            ("ci", "Clean install from the lock file"),
+            ("init", "Create a package.json"),
+            ("install", "Install dependencies"),
+            ("publish", "Publish the package"),
+            ("run", "Run a script"),
+            ("start", "Run the start script"),
+            ("test", "Run the test script"),
+            ("update", "Update dependencies"),
+        ],
+        flags: &[
+            ("--save-dev", "Add as a dev dependency"),
+            ("-g", "Install globally"),
+        ],
+    },
+    Spec {
+        command: "kubectl",
+        subcommands: &[
+            ("apply", "Apply a configuration"),
+            ("delete", "Delete resources"),
+            ("describe", "Show resource details"),
+            ("exec", "Run a command in a container"),
+            ("get", "List resources"),
+            ("logs", "Print container logs"),
+            ("port-forward", "Forward local ports to a pod"),
+            ("rollout", "Manage rollouts"),
+        ],
+        flags: &[
+            ("-n", "Namespace"),
+            ("-f", "File"),
+            ("-o", "Output format"),
+            ("--all-namespaces", "Every namespace"),
+        ],
+    },
+    Spec {
+        command: "ls",
+        subcommands: &[],
+        flags: &[
+            ("-l", "Long listing"),
+            ("-a", "Include hidden entries"),
+            ("-la", "Long listing with hidden entries"),
+            ("-h", "Human-readable sizes"),
+            ("-t", "Sort by modification time"),
+            ("-R", "Recurse into directories"),
+        ],
+    },
+    Spec {
+        command: "grep",
+        subcommands: &[],
+        flags: &[
+            ("-r", "Search directories recursively"),
+            ("-i", "Ignore case"),
+            ("-n", "Show line numbers"),
+            ("-v", "Invert the match"),
+            ("-E", "Extended regular expressions"),
+            ("-l", "Only file names"),
+        ],
+    },
+];
+
+/// Programs whose arguments are directories only.
+const DIRECTORY_COMMANDS: &[&str] = &["cd", "pushd", "rmdir"];
+
+/// Completions from history, the spec table and the file system.
+#[derive(Debug, Default)]
+pub struct HeuristicProvider;
+
+impl HeuristicProvider {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[async_trait]
+impl Provider for HeuristicProvider {
+    fn name(&self) -> &str {
+        "heuristic"
+    }
+
+    async fn complete(
+        &self,
+        request: &CompletionRequest,
+        context: &ShellContext,
+    ) -> Result<Vec<CompletionItem>, ProviderError> {
+        let input: String = request.input.chars().take(request.cursor_pos).collect();
+        Ok(suggest(&input, &context.history, &context.cwd))
+    }
+
+    fn is_local(&self) -> bool {
+        true
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            streaming: false,
+            fim: false,
+            json_mode: false,
+            max_context: 0,
+            latency: LatencyClass::Instant,
+            cost: CostClass::Free,
+        }
+    }
+
+    async fn health_check(&self) -> Result<(), ProviderError> {
+        Ok(())
+    }
+}
+
+/// Suggestions for `input`, best first.
+pub fn suggest(input: &str, history: &[String], cwd: &str) -> Vec<CompletionItem> {
+    if input.trim().is_empty() {
+        return vec![];
+    }
+    let mut items = from_history(input, history);
+    let mut rest = from_spec(input);
+    rest.extend(from_paths(input, cwd));
+    for item in &mut rest {
+        item.score += 0.2 * usage(&item.text, history);
+    }
+    items.extend(rest);
+    items.sort_by(|a, b| b.score.total_cmp(&a.score));
+    let mut seen = std::collections::HashSet::new();
+    items.retain(|item| seen.insert(item.text.trim_end().to_string()));
+    items
+}
+
+/// Earlier commands that extend `input`, most used first, with more
+/// recent ones ahead on a tie.
+fn from_history(input: &str, history: &[String]) -> Vec<CompletionItem> {
+    let mut counts: HashMap<&str, (usize, usize)> = HashMap::new();
+    for (i, line) in history.iter().enumerate() {
+        let line = line.trim();
+        if line.len() > input.len() && line.starts_with(input) {
+            let entry = counts.entry(line).or_default();
+            entry.0 += 1;
+            entry.1 = i;
+        }
+    }
+    let Some(most) = counts.values().map(|(count, _)| *count).max() else {
+        return vec![];
+    };
+    let len = history.len().max(1) as f64;
+    let mut ranked: Vec<_> = counts.into_iter().collect();
+    ranked.sort_by_key(|&(_, (count, last))| std::cmp::Reverse((count, last)));
+    ranked
+        .into_iter()
+        .take(MAX_PER_SOURCE)
+        .map(|(line, (count, last))| {
+            let score = 0.5 + 0.35 * count as f64 / most as f64 + 0.1 * (last + 1) as f64 / len;
+            item(
+                line,
+                None,
+                CompletionKind::FullCommand,
+                score,
+                CompletionOrigin::History,
+            )
+        })
+        .collect()
+}
+
+/// Subcommands and flags from the spec table for the word being typed.
+fn from_spec(input: &str) -> Vec<CompletionItem> {
+    let (head, word) = split_word(input);
+    let words: Vec<&str> = head.split_whitespace().collect();
+    let Some(first) = words.first() else {
+        // Still typing the program's name
+        return SPECS
+            .iter()
+            .filter(|spec| spec.command.len() > word.len() && spec.command.starts_with(word))
+            .map(|spec| {
+                let text = format!("{head}{} ", spec.command);
+                item(
+                    &text,
+                    None,
+                    CompletionKind::Command,
+                    0.3,
+                    CompletionOrigin::Context,
+                )
+            })
+            .collect();
+    };
+    let Some(spec) = SPECS.iter().find(|spec| spec.command == *first) else {
+        return vec![];
+    };
+    let candidates = if word.starts_with('-') {
+        spec.flags
+    } else if words.len() == 1 {
+        spec.subcommands
+    } else {
+        return vec![];
+    };
+    candidates
+        .iter()
+        .filter(|(name, _)| name.len() > word.len() && name.starts_with(word))
+        .take(MAX_PER_SOURCE)
+        .map(|(name, description)| {
+            let text = format!("{head}{name} ");
+            item(
+                &text,
+                Some(description),
+                CompletionKind::Argument,
+                0.4,
+                CompletionOrigin::Context,
+            )
+        })
+        .collect()
+}
+
+/// Files and directories that start with the word being typed, in
+/// argument position. Hidden ones only once the word starts with a dot.
+fn from_paths(input: &str, cwd: &str) -> Vec<CompletionItem> {
+    let (head, word) = split_word(input);
+    let Some(program) = head.split_whitespace().next() else {
+        return vec![];
+    };
+    if word.starts_with('-') || word.contains(['$', '*', '?', '"', '\'']) {
+        return vec![];
+    }
+    let dirs_only = DIRECTORY_COMMANDS.contains(&program);
+    let (dir_part, name_part) = match word.rfind('/') {
+        Some(i) => (&word[..=i], &word[i + 1..]),
+        None => ("", word),
+    };
+    let dir = match dir_part.strip_prefix("~/") {
+        Some(rest) => match std::env::var_os("HOME") {
+            Some(home) => Path::new(&home).join(rest),
+            None => return vec![],
+        },
+        None => Path::new(cwd).join(dir_part),
+    };
+    let Ok(entries) = std::fs::read_dir(&dir) else {
+        return vec![];
+    };
+    let mut names: Vec<(String, bool)> = entries
+        .take(MAX_DIR_ENTRIES)
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let is_dir = entry.path().is_dir();
+            let wanted = name.starts_with(name_part)
+                && (name_part.starts_with('.') || !name.starts_with('.'))
+                && (is_dir || !dirs_only)
+                && !name.contains(char::is_whitespace);
+            wanted.then_some((name, is_dir))
+        })
+        .collect();
+    names.sort();
+    names
+        .into_iter()
+        .take(MAX_PER_SOURCE)
+        .map(|(name, is_dir)| {
+            let text = if is_dir {
+                format!("{head}{dir_part}{name}/")
+            } else {
+                format!("{head}{dir_part}{name} ")
+            };
+            item(
+                &text,
+                None,
+                CompletionKind::Path,
+                0.35,
+                CompletionOrigin::Context,
+            )
+        })
+        .collect()
+}
+
+/// The line before the word under the cursor, and that word.
+fn split_word(input: &str) -> (&str, &str) {
+    let start = input.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+    input.split_at(start)
+}
+
+/// How much of the history starts with `text`, relative to the most
+/// common such prefix: 0.0 for never run, up to 1.0.
+fn usage(text: &str, history: &[String]) -> f64 {
+    let text = text.trim_end();
+    if history.is_empty() || text.is_empty() {
+        return 0.0;
+    }
+    let runs = history
+        .iter()
+        .filter(|line| {
+            line.trim()
+                .strip_prefix(text)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+        })
+        .count();
+    (runs as f64 / history.len() as f64 * 10.0).min(1.0)
+}
+
+fn item(
+    text: &str,
+    description: Option<&str>,
+    kind: CompletionKind,
+    score: f64,
+    origin: CompletionOrigin,
+) -> CompletionItem {
+    CompletionItem {
+        text: text.to_string(),
+        description: description.map(str::to_string),
+        kind,
+        score: score.min(1.0),
+        metadata: CompletionMetadata {
+            origin: Some(origin),
+            ..CompletionMetadata::default()
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    fn texts(items: &[CompletionItem]) -> Vec<&str> {
+        items.iter().map(|item| item.text.as_str()).collect()
+    }
+
+    #[test]
+    fn history_ranks_by_frequency_then_recency() {
+        let history = history(&[
+            "git push origin main",
+            "git pull",
+            "git push origin main",
+            "git push --tags",
+            "ls",
+        ]);
+        let items = from_history("git pu", &history);
+        assert_eq!(
+            texts(&items),
+            ["git push origin main", "git push --tags", "git pull"]
+        );
+        assert!(items[0].score > items[1].score);
+        assert_eq!(items[0].metadata.origin, Some(CompletionOrigin::History));
+        assert!(from_history("git push origin main", &history).is_empty());
+    }
+
+    #[test]
+    fn spec_completes_subcommands_and_flags() {
+        assert_eq!(
+            texts(&from_spec("git ch")),
+            ["git checkout ", "git cherry-pick "]
+        );
+        assert_eq!(
+            texts(&from_spec("cargo test --w")),
+            ["cargo test --workspace "]
+        );
+        assert!(from_spec("git commit fi").is_empty());
+        assert!(from_spec("unknown-tool s").is_empty());
+        assert_eq!(texts(&from_spec("kub")), ["kubectl "]);
+    }
+
+    #[test]
+    fn paths_complete_in_argument_position() {
+        let dir = std::env::temp_dir().join(format!("murmur-heuristic-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::write(dir.join("Cargo.toml"), "").unwrap();
+        std::fs::write(dir.join(".hidden"), "").unwrap();
+        std::fs::write(dir.join("src/main.rs"), "").unwrap();
+        let cwd = dir.to_str().unwrap();
+
+        assert_eq!(texts(&from_paths("cat C", cwd)), ["cat Cargo.toml "]);
+        assert_eq!(texts(&from_paths("cd ", cwd)), ["cd src/"]);
+        assert_eq!(texts(&from_paths("vim src/m", cwd)), ["vim src/main.rs "]);
+        assert_eq!(texts(&from_paths("cat .h", cwd)), ["cat .hidden "]);
+        assert!(from_paths("Car", cwd).is_empty());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn commands_run_before_rank_first() {
+        let history = history(&[
+            "cargo clippy --fix",
+            "cargo clippy --fix",
+            "cargo build",
+            "ls",
+        ]);
+        let items = suggest("cargo c", &history, "/nonexistent");
+        assert_eq!(items[0].text, "cargo clippy --fix");
+        let spec: Vec<_> = items
+            .iter()
+            .filter(|item| item.kind == CompletionKind::Argument)
+            .collect();
+        assert_eq!(spec[0].text, "cargo clippy ");
+        assert!(spec[0].score > spec[1].score);
+        assert!(suggest("  ", &history, "/").is_empty());
+    }
+}
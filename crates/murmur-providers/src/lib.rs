@@ -1,15 +1,42 @@
 //! Murmur Providers — LLM provider abstraction with failover support.
 
 mod anthropic;
+mod chat;
 mod codestral;
+mod external;
+mod gemini;
+mod generic;
+mod heuristic;
+mod http;
 mod ollama;
+mod openai;
+mod openrouter;
+mod retry;
 mod router;
 
 pub use anthropic::AnthropicProvider;
 pub use codestral::CodestralProvider;
+pub use external::ExternalProvider;
+pub use gemini::GeminiProvider;
+pub use generic::{GenericOpenAiProvider, CUSTOM_PREFIX};
+pub use heuristic::HeuristicProvider;
+pub use http::check_reachable;
 pub use ollama::OllamaProvider;
+pub use openai::OpenAiProvider;
+pub use openrouter::OpenRouterProvider;
 pub use router::{ProviderRouter, RouteDecision};
 
+/// Recorded HTTP exchanges for testing providers without network access.
+#[cfg(any(test, feature = "cassettes"))]
+pub mod cassette;
+
+/// Response parsers exposed to the fuzz targets in `fuzz/`.
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing {
+    pub use crate::chat::parse_completions;
+    pub use crate::codestral::parse_fim_completions;
+}
+
 use async_trait::async_trait;
 use murmur_context::ShellContext;
 use murmur_protocol::{CompletionItem, CompletionRequest};
@@ -34,6 +61,17 @@ pub enum ProviderError {
 
     #[error("Timeout")]
     Timeout,
+
+    #[error("Plugin error: {0}")]
+    Plugin(String),
+}
+
+impl ProviderError {
+    /// The provider couldn't be reached at all (no connection, DNS failure),
+    /// as opposed to answering with an error.
+    pub fn is_unreachable(&self) -> bool {
+        matches!(self, ProviderError::Http(e) if e.is_connect())
+    }
 }
 
 /// Configuration for a provider.
@@ -41,11 +79,42 @@ pub enum ProviderError {
 pub struct ProviderConfig {
     pub api_key: Option<String>,
     pub model: Option<String>,
+    #[serde(alias = "base_url")]
     pub endpoint: Option<String>,
+    /// Header carrying `api_key` instead of `Authorization: Bearer`
+    /// (generic OpenAI-compatible providers only).
+    #[serde(default)]
+    pub api_key_header: Option<String>,
+    /// API completions go through (Ollama only): "generate" (the default),
+    /// "chat" for `/api/chat`, or "fim" for fill-in-the-middle models such
+    /// as codellama, starcoder2 and qwen2.5-coder.
+    #[serde(default)]
+    pub api: Option<String>,
+    /// Program implementing the provider over JSON-RPC on stdin/stdout,
+    /// run with `sh -c`. Makes any section that isn't a built-in provider
+    /// a plugin.
+    #[serde(default)]
+    pub command: Option<String>,
+    /// Proxy for this provider only: `http://`, `https://`, `socks5://` or
+    /// `socks5h://` (resolve names through the proxy), with credentials as
+    /// `user:password@`. Unset uses `HTTPS_PROXY`/`ALL_PROXY` if present.
+    #[serde(default)]
+    pub proxy: Option<String>,
     #[serde(default = "default_true")]
     pub enabled: bool,
     #[serde(default = "default_timeout")]
     pub timeout_ms: u64,
+    /// Times to resend a request that failed with a 5xx (or 429) before
+    /// moving on to the next provider in the chain.
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    /// Wait before the first retry; doubled for each one after.
+    #[serde(default = "default_backoff")]
+    pub backoff_ms: u64,
+    /// Whether rate limiting (429) is retried too. Turn off to fall through
+    /// to the next provider at once.
+    #[serde(default = "default_true")]
+    pub retry_on_429: bool,
 }
 
 fn default_true() -> bool {
@@ -56,6 +125,82 @@ fn default_timeout() -> u64 {
     5000
 }
 
+fn default_max_retries() -> u32 {
+    1
+}
+
+fn default_backoff() -> u64 {
+    200
+}
+
+/// One completion request as sent and answered, for inspecting prompts in
+/// `murmur repl`.
+#[derive(Debug, Clone, Default)]
+pub struct Exchange {
+    /// The prompt as sent, in labelled parts ("system", "user", or a
+    /// fill-in-the-middle "prefix" and "suffix").
+    pub prompt: Vec<(&'static str, String)>,
+    /// The model's reply before parsing.
+    pub raw: String,
+    pub items: Vec<CompletionItem>,
+}
+
+/// How quickly a provider typically answers a completion, fastest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LatencyClass {
+    /// Answered in-process, without a model.
+    Instant,
+    /// A model on this machine.
+    Fast,
+    /// A hosted API.
+    Moderate,
+    /// Large or reasoning models.
+    Slow,
+}
+
+/// What a request to a provider typically costs, cheapest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CostClass {
+    Free,
+    Low,
+    Medium,
+    High,
+}
+
+/// What a provider's API supports and how it usually behaves, for routing
+/// and budgeting without going by its name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct Capabilities {
+    /// The API can stream its reply token by token.
+    pub streaming: bool,
+    /// Completions are filled in between a prefix and a suffix rather than
+    /// asked for in a chat; such models are trained on code.
+    pub fim: bool,
+    /// The API can be told to reply with JSON only.
+    pub json_mode: bool,
+    /// Tokens of prompt and reply the default model takes (0 for providers
+    /// that take no prompt).
+    pub max_context: u32,
+    pub latency: LatencyClass,
+    pub cost: CostClass,
+}
+
+/// A hosted chat API without streaming or JSON mode.
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self {
+            streaming: false,
+            fim: false,
+            json_mode: false,
+            max_context: 8_192,
+            latency: LatencyClass::Moderate,
+            cost: CostClass::Medium,
+        }
+    }
+}
+
 /// Trait that all LLM providers must implement.
 #[async_trait]
 pub trait Provider: Send + Sync {
@@ -69,6 +214,54 @@ pub trait Provider: Send + Sync {
         context: &ShellContext,
     ) -> Result<Vec<CompletionItem>, ProviderError>;
 
+    /// Like `complete`, but also returns the prompt and the unparsed reply.
+    /// The default only fills in the items.
+    async fn exchange(
+        &self,
+        request: &CompletionRequest,
+        context: &ShellContext,
+    ) -> Result<Exchange, ProviderError> {
+        let items = self.complete(request, context).await?;
+        Ok(Exchange {
+            items,
+            ..Exchange::default()
+        })
+    }
+
+    /// Generate free-form text from a system prompt and a user prompt.
+    ///
+    /// Used for auxiliary jobs (summaries, explanations) rather than inline
+    /// completions. Providers that only support completion-style APIs keep
+    /// the default, which reports the capability as missing.
+    async fn generate(&self, system: &str, prompt: &str) -> Result<String, ProviderError> {
+        let _ = (system, prompt);
+        Err(ProviderError::NotConfigured(format!(
+            "{}: free-form generation not supported",
+            self.name()
+        )))
+    }
+
+    /// Whether the provider runs on this machine. Local providers don't
+    /// count against the daemon's cloud request budget.
+    fn is_local(&self) -> bool {
+        false
+    }
+
+    /// What the provider supports. The default describes a chat API
+    /// without streaming or JSON mode, hosted unless `is_local` says
+    /// otherwise.
+    fn capabilities(&self) -> Capabilities {
+        if self.is_local() {
+            Capabilities {
+                latency: LatencyClass::Fast,
+                cost: CostClass::Free,
+                ..Capabilities::default()
+            }
+        } else {
+            Capabilities::default()
+        }
+    }
+
     /// Check if the provider is healthy/reachable.
     async fn health_check(&self) -> Result<(), ProviderError>;
 }
@@ -1,38 +1,104 @@
 use async_trait::async_trait;
 use murmur_context::ShellContext;
-use murmur_protocol::{CompletionItem, CompletionKind, CompletionRequest};
+use murmur_protocol::{CompletionItem, CompletionRequest};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use tracing::{debug, warn};
+use tracing::debug;
 
-use crate::{Provider, ProviderConfig, ProviderError};
+use crate::chat;
+use crate::codestral;
+use crate::retry::RetryPolicy;
+use crate::{
+    http, Capabilities, CostClass, Exchange, LatencyClass, Provider, ProviderConfig, ProviderError,
+};
 
 const DEFAULT_MODEL: &str = "codellama:7b";
-const DEFAULT_ENDPOINT: &str = "http://localhost:11434";
+pub(crate) const DEFAULT_ENDPOINT: &str = "http://localhost:11434";
+
+/// Tokens a fill-in-the-middle completion may run to.
+const FIM_MAX_TOKENS: u32 = 128;
 
 pub struct OllamaProvider {
     client: Client,
     model: String,
     endpoint: String,
     timeout: std::time::Duration,
+    retry: RetryPolicy,
+    api: Api,
+}
+
+/// Which Ollama API completions go through (`api` in the config).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Api {
+    /// `/api/generate` with a prose prompt.
+    Generate,
+    /// `/api/chat` with the same prompts as the cloud chat providers.
+    Chat,
+    /// `/api/generate` with a prefix and suffix, for models trained on
+    /// fill-in-the-middle.
+    Fim,
+}
+
+impl Api {
+    fn parse(name: Option<&str>) -> Result<Self, ProviderError> {
+        match name.unwrap_or("generate") {
+            "generate" => Ok(Self::Generate),
+            "chat" => Ok(Self::Chat),
+            "fim" => Ok(Self::Fim),
+            other => Err(ProviderError::NotConfigured(format!(
+                "ollama: unknown api '{other}' (generate, chat or fim)"
+            ))),
+        }
+    }
 }
 
 #[derive(Serialize)]
 struct OllamaRequest {
     model: String,
     prompt: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suffix: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    options: Option<OllamaOptions>,
     stream: bool,
 }
 
+#[derive(Serialize)]
+struct OllamaOptions {
+    num_predict: u32,
+    temperature: f32,
+    stop: Vec<String>,
+}
+
 #[derive(Deserialize)]
 struct OllamaResponse {
     response: String,
 }
 
+#[derive(Serialize)]
+struct OllamaChatRequest {
+    model: String,
+    messages: Vec<OllamaMessage>,
+    stream: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct OllamaMessage {
+    role: String,
+    content: String,
+}
+
+#[derive(Deserialize)]
+struct OllamaChatResponse {
+    message: OllamaMessage,
+}
+
 impl OllamaProvider {
     pub fn new(config: &ProviderConfig) -> Result<Self, ProviderError> {
         Ok(Self {
-            client: Client::new(),
+            client: http::client(config)?,
             model: config
                 .model
                 .clone()
@@ -42,9 +108,98 @@ impl OllamaProvider {
                 .clone()
                 .unwrap_or_else(|| DEFAULT_ENDPOINT.to_string()),
             timeout: std::time::Duration::from_millis(config.timeout_ms),
+            retry: RetryPolicy::from_config(config),
+            api: Api::parse(config.api.as_deref())?,
         })
     }
 
+    /// Call `/api/generate` (non-streaming) with an optional system prompt.
+    async fn send_generate(
+        &self,
+        model: &str,
+        prompt: String,
+        system: Option<String>,
+    ) -> Result<OllamaResponse, ProviderError> {
+        let body = OllamaRequest {
+            model: model.to_string(),
+            prompt,
+            system,
+            suffix: None,
+            options: None,
+            stream: false,
+        };
+        self.post("/api/generate", &body).await
+    }
+
+    /// Call `/api/generate` with a fill-in-the-middle prefix and suffix;
+    /// Ollama wraps them in the model's FIM template.
+    async fn send_fim(
+        &self,
+        model: &str,
+        prefix: String,
+        suffix: String,
+    ) -> Result<OllamaResponse, ProviderError> {
+        let body = OllamaRequest {
+            model: model.to_string(),
+            prompt: prefix,
+            system: None,
+            suffix: Some(suffix),
+            options: Some(OllamaOptions {
+                num_predict: FIM_MAX_TOKENS,
+                temperature: 0.2,
+                stop: vec!["\n\n".to_string(), "$ ".to_string()],
+            }),
+            stream: false,
+        };
+        self.post("/api/generate", &body).await
+    }
+
+    /// Call `/api/chat` (non-streaming) with a system and a user message.
+    async fn send_chat(
+        &self,
+        model: &str,
+        system: String,
+        user: String,
+    ) -> Result<String, ProviderError> {
+        let body = OllamaChatRequest {
+            model: model.to_string(),
+            messages: vec![
+                OllamaMessage {
+                    role: "system".to_string(),
+                    content: system,
+                },
+                OllamaMessage {
+                    role: "user".to_string(),
+                    content: user,
+                },
+            ],
+            stream: false,
+        };
+        let response: OllamaChatResponse = self.post("/api/chat", &body).await?;
+        Ok(response.message.content)
+    }
+
+    async fn post<T: serde::de::DeserializeOwned>(
+        &self,
+        path: &str,
+        body: &impl Serialize,
+    ) -> Result<T, ProviderError> {
+        let url = format!("{}{path}", self.endpoint);
+        let request = self.client.post(&url).timeout(self.timeout).json(body);
+        let response = self.retry.send(request).await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let message = response.text().await.unwrap_or_default();
+            return Err(ProviderError::Api {
+                status: status.as_u16(),
+                message,
+            });
+        }
+
+        Ok(response.json().await?)
+    }
+
     fn build_prompt(&self, request: &CompletionRequest, context: &ShellContext) -> String {
         let mut prompt = format!(
             "You are a shell command autocomplete engine.\n\
@@ -56,6 +211,10 @@ impl OllamaProvider {
             request.input,
         );
 
+        if let Some(ref host) = context.remote_host {
+            prompt.push_str(&format!("Remote host (SSH): {host}\n"));
+        }
+
         if let Some(ref git) = context.git {
             prompt.push_str(&format!(
                 "Git branch: {}, dirty: {}\n",
@@ -67,14 +226,31 @@ impl OllamaProvider {
             prompt.push_str(&format!("Project: {project:?}\n"));
         }
 
+        if let Some(ref container) = context.container {
+            prompt.push_str(&format!("Container: {}\n", container.describe()));
+        }
+
+        if let Some(ref dev_env) = context.dev_env {
+            prompt.push_str(&format!("Dev environment: {}\n", dev_env.describe()));
+        }
+
         prompt.push_str(
             "\nSuggest up to 5 completions as a JSON array of objects with \"text\" and \"description\" fields.\n\
              Respond ONLY with the JSON array, no other text.",
         );
 
+        if let Some(ref summary) = context.session_summary {
+            prompt.push_str(&format!("\nRecent activity: {summary}\n"));
+        }
+
         if !context.history.is_empty() {
             prompt.push_str("\nRecent history:\n");
-            for cmd in context.history.iter().rev().take(5) {
+            for cmd in context
+                .history
+                .iter()
+                .rev()
+                .take(chat::history_lines(context, 5))
+            {
                 prompt.push_str(&format!("  {cmd}\n"));
             }
         }
@@ -89,74 +265,84 @@ impl Provider for OllamaProvider {
         "ollama"
     }
 
+    fn is_local(&self) -> bool {
+        true
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            streaming: true,
+            fim: self.api == Api::Fim,
+            json_mode: true,
+            // Ollama's default `num_ctx`, whatever the model could take
+            max_context: 4_096,
+            latency: LatencyClass::Fast,
+            cost: CostClass::Free,
+        }
+    }
+
     async fn complete(
         &self,
         request: &CompletionRequest,
         context: &ShellContext,
     ) -> Result<Vec<CompletionItem>, ProviderError> {
-        let prompt = self.build_prompt(request, context);
-
-        debug!(model = %self.model, input = %request.input, "Requesting completion from Ollama");
-
-        let url = format!("{}/api/generate", self.endpoint);
-        let body = OllamaRequest {
-            model: self.model.clone(),
-            prompt,
-            stream: false,
-        };
-
-        let response = self
-            .client
-            .post(&url)
-            .timeout(self.timeout)
-            .json(&body)
-            .send()
-            .await?;
-
-        let status = response.status();
-        if !status.is_success() {
-            let message = response.text().await.unwrap_or_default();
-            return Err(ProviderError::Api {
-                status: status.as_u16(),
-                message,
-            });
-        }
-
-        let api_response: OllamaResponse = response.json().await?;
+        Ok(self.exchange(request, context).await?.items)
+    }
 
-        // Parse response same way as Anthropic
-        #[derive(Deserialize)]
-        struct Suggestion {
-            text: String,
-            description: Option<String>,
-        }
+    async fn exchange(
+        &self,
+        request: &CompletionRequest,
+        context: &ShellContext,
+    ) -> Result<Exchange, ProviderError> {
+        let model = request.model.as_deref().unwrap_or(&self.model);
+        debug!(model = %model, api = ?self.api, input = %request.input, "Requesting completion from Ollama");
 
-        let json_str = api_response
-            .response
-            .trim()
-            .trim_start_matches("```json")
-            .trim_start_matches("```")
-            .trim_end_matches("```")
-            .trim();
-
-        match serde_json::from_str::<Vec<Suggestion>>(json_str) {
-            Ok(suggestions) => Ok(suggestions
-                .into_iter()
-                .enumerate()
-                .map(|(i, s)| CompletionItem {
-                    text: s.text,
-                    description: s.description,
-                    kind: CompletionKind::FullCommand,
-                    score: 1.0 - (i as f64 * 0.1),
+        match self.api {
+            Api::Generate => {
+                let prompt = self.build_prompt(request, context);
+                let api_response = self.send_generate(model, prompt.clone(), None).await?;
+                Ok(Exchange {
+                    items: chat::parse_completions(&api_response.response),
+                    prompt: vec![("prompt", prompt)],
+                    raw: api_response.response,
+                })
+            }
+            Api::Chat => {
+                let system = chat::system_prompt(context);
+                let user = chat::user_prompt(request, context);
+                let prompt = vec![("system", system.clone()), ("user", user.clone())];
+                let text = self.send_chat(model, system, user).await?;
+                let items = if text.trim().is_empty() {
+                    vec![]
+                } else {
+                    chat::parse_completions(&text)
+                };
+                Ok(Exchange {
+                    prompt,
+                    raw: text,
+                    items,
+                })
+            }
+            Api::Fim => {
+                let (prefix, suffix) = codestral::fim_prompt(request, context);
+                let api_response = self.send_fim(model, prefix.clone(), suffix.clone()).await?;
+                Ok(Exchange {
+                    items: codestral::fim_items(&api_response.response, &request.input, "Ollama"),
+                    prompt: vec![("prefix", prefix), ("suffix", suffix)],
+                    raw: api_response.response,
                 })
-                .collect()),
-            Err(e) => {
-                warn!(error = %e, "Failed to parse Ollama response");
-                Ok(vec![])
             }
         }
     }
 
+    async fn generate(&self, system: &str, prompt: &str) -> Result<String, ProviderError> {
+        debug!(model = %self.model, "Requesting generation from Ollama");
+        let api_response = self
+            .send_generate(&self.model, prompt.to_string(), Some(system.to_string()))
+            .await?;
+        Ok(api_response.response.trim().to_string())
+    }
+
     async fn health_check(&self) -> Result<(), ProviderError> {
         let url = format!("{}/api/tags", self.endpoint);
         self.client
@@ -167,3 +353,82 @@ impl Provider for OllamaProvider {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(api: Option<&str>) -> ProviderConfig {
+        ProviderConfig {
+            api_key: None,
+            api_key_header: None,
+            api: api.map(String::from),
+            command: None,
+            proxy: None,
+            model: None,
+            endpoint: None,
+            enabled: true,
+            timeout_ms: 5000,
+            max_retries: 0,
+            backoff_ms: 200,
+            retry_on_429: true,
+        }
+    }
+
+    #[test]
+    fn api_is_chosen_in_config() {
+        assert_eq!(
+            OllamaProvider::new(&config(None)).unwrap().api,
+            Api::Generate
+        );
+        assert_eq!(
+            OllamaProvider::new(&config(Some("chat"))).unwrap().api,
+            Api::Chat
+        );
+        assert_eq!(
+            OllamaProvider::new(&config(Some("fim"))).unwrap().api,
+            Api::Fim
+        );
+        assert!(OllamaProvider::new(&config(Some("completions"))).is_err());
+    }
+
+    #[test]
+    fn fim_models_report_it() {
+        let provider = OllamaProvider::new(&config(Some("fim"))).unwrap();
+        assert!(provider.capabilities().fim);
+        assert_eq!(provider.capabilities().cost, CostClass::Free);
+        assert!(
+            !OllamaProvider::new(&config(None))
+                .unwrap()
+                .capabilities()
+                .fim
+        );
+    }
+
+    #[test]
+    fn fim_request_carries_the_suffix() {
+        let body = OllamaRequest {
+            model: "qwen2.5-coder:1.5b".to_string(),
+            prompt: "# Shell: zsh\n$ git ch".to_string(),
+            system: None,
+            suffix: Some("\n".to_string()),
+            options: Some(OllamaOptions {
+                num_predict: FIM_MAX_TOKENS,
+                temperature: 0.2,
+                stop: vec!["\n\n".to_string()],
+            }),
+            stream: false,
+        };
+        let json = serde_json::to_value(&body).unwrap();
+        assert_eq!(json["suffix"], "\n");
+        assert_eq!(json["options"]["num_predict"], 128);
+        assert!(json.get("system").is_none());
+
+        let items = codestral::fim_items("eckout main", "git ch", "Ollama");
+        assert_eq!(items[0].text, "git checkout main");
+        assert_eq!(
+            items[0].description.as_deref(),
+            Some("Code completion (Ollama)")
+        );
+    }
+}
@@ -0,0 +1,297 @@
+use async_trait::async_trait;
+use murmur_context::ShellContext;
+use murmur_protocol::{CompletionItem, CompletionRequest};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::chat;
+use crate::retry::RetryPolicy;
+use crate::{
+    http, Capabilities, CostClass, Exchange, LatencyClass, Provider, ProviderConfig, ProviderError,
+};
+
+const DEFAULT_MODEL: &str = "gpt-4o-mini";
+pub(crate) const DEFAULT_ENDPOINT: &str = "https://api.openai.com/v1/chat/completions";
+
+/// OpenAI Chat Completions, or any service speaking the same API: Azure
+/// OpenAI deployments and OpenAI-compatible proxies (LiteLLM, vLLM, ...)
+/// via `endpoint`.
+pub struct OpenAiProvider {
+    client: Client,
+    api_key: Option<String>,
+    model: String,
+    endpoint: String,
+    timeout: std::time::Duration,
+    retry: RetryPolicy,
+}
+
+/// Chat Completions request body, shared with OpenAI-compatible providers.
+#[derive(Serialize)]
+pub(crate) struct ChatRequest {
+    model: String,
+    max_tokens: u32,
+    messages: Vec<Message>,
+}
+
+#[derive(Serialize)]
+struct Message {
+    role: &'static str,
+    content: String,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ChatResponse {
+    choices: Vec<Choice>,
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    message: ChoiceMessage,
+}
+
+#[derive(Deserialize)]
+struct ChoiceMessage {
+    #[serde(default)]
+    content: Option<String>,
+}
+
+impl ChatRequest {
+    /// A system + user conversation.
+    pub(crate) fn new(model: String, system: String, user: String, max_tokens: u32) -> Self {
+        Self {
+            model,
+            max_tokens,
+            messages: vec![
+                Message {
+                    role: "system",
+                    content: system,
+                },
+                Message {
+                    role: "user",
+                    content: user,
+                },
+            ],
+        }
+    }
+}
+
+impl ChatResponse {
+    /// Text of the first choice, or empty if there is none.
+    pub(crate) fn into_text(self) -> String {
+        self.choices
+            .into_iter()
+            .next()
+            .and_then(|c| c.message.content)
+            .unwrap_or_default()
+    }
+}
+
+impl OpenAiProvider {
+    pub fn new(config: &ProviderConfig) -> Result<Self, ProviderError> {
+        // Self-hosted compatible endpoints often run without a key
+        if config.api_key.is_none() && config.endpoint.is_none() {
+            return Err(ProviderError::NotConfigured(
+                "openai: api_key required".into(),
+            ));
+        }
+
+        Ok(Self {
+            client: http::client(config)?,
+            api_key: config.api_key.clone(),
+            model: config
+                .model
+                .clone()
+                .unwrap_or_else(|| DEFAULT_MODEL.to_string()),
+            endpoint: config
+                .endpoint
+                .clone()
+                .unwrap_or_else(|| DEFAULT_ENDPOINT.to_string()),
+            timeout: std::time::Duration::from_millis(config.timeout_ms),
+            retry: RetryPolicy::from_config(config),
+        })
+    }
+
+    /// Azure authenticates with an `api-key` header instead of a bearer token.
+    fn is_azure(&self) -> bool {
+        self.endpoint.contains(".openai.azure.com")
+    }
+
+    /// Send a system + user chat request and return the first choice's text.
+    async fn send_chat(
+        &self,
+        model: &str,
+        system: String,
+        user: String,
+        max_tokens: u32,
+    ) -> Result<String, ProviderError> {
+        let body = ChatRequest::new(model.to_string(), system, user, max_tokens);
+
+        let mut request = self
+            .client
+            .post(&self.endpoint)
+            .timeout(self.timeout)
+            .json(&body);
+        if let Some(ref key) = self.api_key {
+            request = if self.is_azure() {
+                request.header("api-key", key)
+            } else {
+                request.bearer_auth(key)
+            };
+        }
+        let response = self.retry.send(request).await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let message = response.text().await.unwrap_or_default();
+            return Err(ProviderError::Api {
+                status: status.as_u16(),
+                message,
+            });
+        }
+
+        let api_response: ChatResponse = response.json().await?;
+        Ok(api_response.into_text())
+    }
+}
+
+#[async_trait]
+impl Provider for OpenAiProvider {
+    fn name(&self) -> &str {
+        "openai"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            streaming: true,
+            fim: false,
+            json_mode: true,
+            max_context: 128_000,
+            latency: LatencyClass::Moderate,
+            cost: CostClass::Low,
+        }
+    }
+
+    async fn complete(
+        &self,
+        request: &CompletionRequest,
+        context: &ShellContext,
+    ) -> Result<Vec<CompletionItem>, ProviderError> {
+        Ok(self.exchange(request, context).await?.items)
+    }
+
+    async fn exchange(
+        &self,
+        request: &CompletionRequest,
+        context: &ShellContext,
+    ) -> Result<Exchange, ProviderError> {
+        let system = chat::system_prompt(context);
+        let user = chat::user_prompt(request, context);
+
+        let model = request.model.as_deref().unwrap_or(&self.model);
+        debug!(model = %model, input = %request.input, "Requesting completion from OpenAI");
+
+        let prompt = vec![("system", system.clone()), ("user", user.clone())];
+        let text = self.send_chat(model, system, user, 512).await?;
+        let items = if text.trim().is_empty() {
+            vec![]
+        } else {
+            chat::parse_completions(&text)
+        };
+        Ok(Exchange {
+            prompt,
+            raw: text,
+            items,
+        })
+    }
+
+    async fn generate(&self, system: &str, prompt: &str) -> Result<String, ProviderError> {
+        debug!(model = %self.model, "Requesting generation from OpenAI");
+        let text = self
+            .send_chat(&self.model, system.to_string(), prompt.to_string(), 1024)
+            .await?;
+        Ok(text.trim().to_string())
+    }
+
+    async fn health_check(&self) -> Result<(), ProviderError> {
+        debug!("OpenAI health check");
+        // Azure deployments have no models listing under their endpoint
+        if self.is_azure() {
+            return Ok(());
+        }
+        let url = self.endpoint.replace("/chat/completions", "/models");
+        let mut request = self
+            .client
+            .get(&url)
+            .timeout(std::time::Duration::from_secs(5));
+        if let Some(ref key) = self.api_key {
+            request = request.bearer_auth(key);
+        }
+        let response = request.send().await?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(ProviderError::Api {
+                status: response.status().as_u16(),
+                message: "OpenAI health check failed".to_string(),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(api_key: Option<&str>, endpoint: Option<&str>) -> ProviderConfig {
+        ProviderConfig {
+            api_key: api_key.map(String::from),
+            api_key_header: None,
+            api: None,
+            command: None,
+            proxy: None,
+            model: None,
+            endpoint: endpoint.map(String::from),
+            enabled: true,
+            timeout_ms: 5000,
+            max_retries: 0,
+            backoff_ms: 200,
+            retry_on_429: true,
+        }
+    }
+
+    #[test]
+    fn key_required_for_the_default_endpoint() {
+        assert!(OpenAiProvider::new(&config(None, None)).is_err());
+        let provider = OpenAiProvider::new(&config(Some("sk-test"), None)).unwrap();
+        assert_eq!(provider.model, DEFAULT_MODEL);
+        assert!(!provider.is_azure());
+
+        // Local OpenAI-compatible servers usually need no key
+        let provider = OpenAiProvider::new(&config(
+            None,
+            Some("http://localhost:4000/v1/chat/completions"),
+        ))
+        .unwrap();
+        assert!(provider.api_key.is_none());
+    }
+
+    #[test]
+    fn detects_azure_endpoints() {
+        let provider = OpenAiProvider::new(&config(
+            Some("key"),
+            Some("https://acme.openai.azure.com/openai/deployments/mini/chat/completions?api-version=2024-10-21"),
+        ))
+        .unwrap();
+        assert!(provider.is_azure());
+    }
+
+    #[test]
+    fn chat_response_takes_first_choice() {
+        let json =
+            r#"{"choices": [{"index": 0, "message": {"role": "assistant", "content": "[]"}}]}"#;
+        let response: ChatResponse = serde_json::from_str(json).unwrap();
+        assert_eq!(response.choices[0].message.content.as_deref(), Some("[]"));
+    }
+}
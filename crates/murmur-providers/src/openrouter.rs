@@ -0,0 +1,283 @@
+use async_trait::async_trait;
+use murmur_context::ShellContext;
+use murmur_protocol::{CompletionItem, CompletionRequest};
+use reqwest::Client;
+use serde::Serialize;
+use tracing::debug;
+
+use crate::chat;
+use crate::openai::{ChatRequest, ChatResponse};
+use crate::retry::RetryPolicy;
+use crate::{
+    http, Capabilities, CostClass, Exchange, LatencyClass, Provider, ProviderConfig, ProviderError,
+};
+
+/// Lets OpenRouter pick a model for each request.
+const DEFAULT_MODEL: &str = "openrouter/auto";
+pub(crate) const DEFAULT_ENDPOINT: &str = "https://openrouter.ai/api/v1/chat/completions";
+
+/// OpenRouter: one key for models from many vendors, addressed by
+/// `vendor/model` slugs. `model` may list several slugs separated by commas;
+/// OpenRouter tries them in order when one is down or rate-limited.
+pub struct OpenRouterProvider {
+    client: Client,
+    api_key: String,
+    models: Vec<String>,
+    endpoint: String,
+    timeout: std::time::Duration,
+    retry: RetryPolicy,
+}
+
+/// A Chat Completions request plus OpenRouter's fallback list.
+#[derive(Serialize)]
+struct RouterRequest {
+    #[serde(flatten)]
+    chat: ChatRequest,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    models: Vec<String>,
+}
+
+/// Split a configured `model` into slugs, checking each is `vendor/model`.
+fn parse_models(model: Option<&str>) -> Result<Vec<String>, ProviderError> {
+    let models: Vec<String> = model
+        .unwrap_or(DEFAULT_MODEL)
+        .split(',')
+        .map(str::trim)
+        .filter(|m| !m.is_empty())
+        .map(String::from)
+        .collect();
+    if models.is_empty() {
+        return Ok(vec![DEFAULT_MODEL.to_string()]);
+    }
+    if let Some(bad) = models.iter().find(|m| {
+        let mut parts = m.splitn(2, '/');
+        parts.next().map_or(true, str::is_empty) || parts.next().map_or(true, str::is_empty)
+    }) {
+        return Err(ProviderError::NotConfigured(format!(
+            "openrouter: model must be a vendor/model slug like openai/gpt-4o-mini, got '{bad}'"
+        )));
+    }
+    Ok(models)
+}
+
+impl OpenRouterProvider {
+    pub fn new(config: &ProviderConfig) -> Result<Self, ProviderError> {
+        let api_key = config
+            .api_key
+            .clone()
+            .ok_or_else(|| ProviderError::NotConfigured("openrouter: api_key required".into()))?;
+
+        Ok(Self {
+            client: http::client(config)?,
+            api_key,
+            models: parse_models(config.model.as_deref())?,
+            endpoint: config
+                .endpoint
+                .clone()
+                .unwrap_or_else(|| DEFAULT_ENDPOINT.to_string()),
+            timeout: std::time::Duration::from_millis(config.timeout_ms),
+            retry: RetryPolicy::from_config(config),
+        })
+    }
+
+    /// A request for `model`, or the configured models with fallbacks.
+    fn request(
+        &self,
+        model: Option<&str>,
+        system: String,
+        user: String,
+        max_tokens: u32,
+    ) -> RouterRequest {
+        let primary = model.unwrap_or(&self.models[0]).to_string();
+        RouterRequest {
+            chat: ChatRequest::new(primary, system, user, max_tokens),
+            // Only worth sending when there is something to fall back to
+            models: if model.is_none() && self.models.len() > 1 {
+                self.models.clone()
+            } else {
+                vec![]
+            },
+        }
+    }
+
+    /// Send a system + user chat request and return the first choice's text.
+    async fn send_chat(
+        &self,
+        model: Option<&str>,
+        system: String,
+        user: String,
+        max_tokens: u32,
+    ) -> Result<String, ProviderError> {
+        let request = self
+            .client
+            .post(&self.endpoint)
+            .bearer_auth(&self.api_key)
+            // Attribution shown on OpenRouter's dashboards
+            .header("X-Title", "Murmur")
+            .timeout(self.timeout)
+            .json(&self.request(model, system, user, max_tokens));
+        let response = self.retry.send(request).await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let message = response.text().await.unwrap_or_default();
+            return Err(ProviderError::Api {
+                status: status.as_u16(),
+                message,
+            });
+        }
+
+        let api_response: ChatResponse = response.json().await?;
+        Ok(api_response.into_text())
+    }
+}
+
+#[async_trait]
+impl Provider for OpenRouterProvider {
+    fn name(&self) -> &str {
+        "openrouter"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            streaming: true,
+            fim: false,
+            json_mode: false,
+            max_context: 128_000,
+            latency: LatencyClass::Moderate,
+            cost: CostClass::Medium,
+        }
+    }
+
+    async fn complete(
+        &self,
+        request: &CompletionRequest,
+        context: &ShellContext,
+    ) -> Result<Vec<CompletionItem>, ProviderError> {
+        Ok(self.exchange(request, context).await?.items)
+    }
+
+    async fn exchange(
+        &self,
+        request: &CompletionRequest,
+        context: &ShellContext,
+    ) -> Result<Exchange, ProviderError> {
+        let system = chat::system_prompt(context);
+        let user = chat::user_prompt(request, context);
+
+        let model = request.model.as_deref();
+        debug!(model = %model.unwrap_or(&self.models[0]), input = %request.input, "Requesting completion from OpenRouter");
+
+        let prompt = vec![("system", system.clone()), ("user", user.clone())];
+        let text = self.send_chat(model, system, user, 512).await?;
+        let items = if text.trim().is_empty() {
+            vec![]
+        } else {
+            chat::parse_completions(&text)
+        };
+        Ok(Exchange {
+            prompt,
+            raw: text,
+            items,
+        })
+    }
+
+    async fn generate(&self, system: &str, prompt: &str) -> Result<String, ProviderError> {
+        debug!(model = %self.models[0], "Requesting generation from OpenRouter");
+        let text = self
+            .send_chat(None, system.to_string(), prompt.to_string(), 1024)
+            .await?;
+        Ok(text.trim().to_string())
+    }
+
+    async fn health_check(&self) -> Result<(), ProviderError> {
+        debug!("OpenRouter health check");
+        // Key info costs nothing and fails for a bad key
+        let url = self.endpoint.replace("/chat/completions", "/auth/key");
+        let response = self
+            .client
+            .get(&url)
+            .bearer_auth(&self.api_key)
+            .timeout(std::time::Duration::from_secs(5))
+            .send()
+            .await?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(ProviderError::Api {
+                status: response.status().as_u16(),
+                message: "OpenRouter health check failed".to_string(),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(model: Option<&str>) -> ProviderConfig {
+        ProviderConfig {
+            api_key: Some("sk-or-test".to_string()),
+            api_key_header: None,
+            api: None,
+            command: None,
+            proxy: None,
+            model: model.map(String::from),
+            endpoint: None,
+            enabled: true,
+            timeout_ms: 5000,
+            max_retries: 0,
+            backoff_ms: 200,
+            retry_on_429: true,
+        }
+    }
+
+    #[test]
+    fn models_must_be_slugs() {
+        assert_eq!(parse_models(None).unwrap(), vec![DEFAULT_MODEL]);
+        assert_eq!(
+            parse_models(Some("anthropic/claude-3.5-haiku, openai/gpt-4o-mini")).unwrap(),
+            vec!["anthropic/claude-3.5-haiku", "openai/gpt-4o-mini"]
+        );
+        assert!(parse_models(Some("gpt-4o-mini")).is_err());
+        assert!(parse_models(Some("openai/")).is_err());
+        assert!(parse_models(Some("meta-llama/llama-3.1-8b-instruct:free")).is_ok());
+    }
+
+    #[test]
+    fn fallbacks_are_sent_as_models() {
+        let provider = OpenRouterProvider::new(&config(Some("openai/gpt-4o-mini"))).unwrap();
+        let json = serde_json::to_value(provider.request(None, "s".into(), "u".into(), 8)).unwrap();
+        assert_eq!(json["model"], "openai/gpt-4o-mini");
+        assert!(json.get("models").is_none());
+
+        let provider = OpenRouterProvider::new(&config(Some(
+            "openai/gpt-4o-mini,google/gemini-2.0-flash-001",
+        )))
+        .unwrap();
+        let json = serde_json::to_value(provider.request(None, "s".into(), "u".into(), 8)).unwrap();
+        assert_eq!(json["model"], "openai/gpt-4o-mini");
+        assert_eq!(json["models"][1], "google/gemini-2.0-flash-001");
+        assert_eq!(json["messages"][0]["role"], "system");
+
+        // A per-request model replaces the fallbacks
+        let json = serde_json::to_value(provider.request(
+            Some("anthropic/claude-3.5-haiku"),
+            "s".into(),
+            "u".into(),
+            8,
+        ))
+        .unwrap();
+        assert_eq!(json["model"], "anthropic/claude-3.5-haiku");
+        assert!(json.get("models").is_none());
+    }
+
+    #[test]
+    fn key_required() {
+        let mut config = config(None);
+        config.api_key = None;
+        assert!(OpenRouterProvider::new(&config).is_err());
+    }
+}
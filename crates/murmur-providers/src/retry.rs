@@ -0,0 +1,149 @@
+//! Retrying transient provider errors before the chain moves on.
+
+use reqwest::{RequestBuilder, Response, StatusCode};
+use std::time::Duration;
+use tracing::debug;
+
+use crate::{ProviderConfig, ProviderError};
+
+/// When to resend a request that got a 429 or 5xx: up to `max_retries`
+/// more times, waiting `backoff`, then twice that, and so on.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RetryPolicy {
+    max_retries: u32,
+    backoff: Duration,
+    retry_on_429: bool,
+}
+
+impl RetryPolicy {
+    pub(crate) fn from_config(config: &ProviderConfig) -> Self {
+        Self {
+            max_retries: config.max_retries,
+            backoff: Duration::from_millis(config.backoff_ms),
+            retry_on_429: config.retry_on_429,
+        }
+    }
+
+    fn retryable(&self, status: StatusCode) -> bool {
+        status.is_server_error() || (status == StatusCode::TOO_MANY_REQUESTS && self.retry_on_429)
+    }
+
+    fn delay(&self, attempt: u32) -> Duration {
+        self.backoff.saturating_mul(2u32.saturating_pow(attempt))
+    }
+
+    /// Send `request`, retrying while the response is retryable. Returns
+    /// the last response, successful or not, for the caller to check.
+    /// Connection errors are not retried: the provider is down, and the
+    /// next one in the chain is the better bet.
+    pub(crate) async fn send(&self, request: RequestBuilder) -> Result<Response, ProviderError> {
+        let mut attempt = 0;
+        loop {
+            // Streaming bodies can't be cloned, so they get a single try
+            let retry = match request.try_clone() {
+                Some(retry) if attempt < self.max_retries => retry,
+                _ => return Ok(request.send().await?),
+            };
+            let response = retry.send().await?;
+            if !self.retryable(response.status()) {
+                return Ok(response);
+            }
+            let delay = self.delay(attempt);
+            debug!(
+                url = %response.url(),
+                status = response.status().as_u16(),
+                attempt = attempt + 1,
+                delay_ms = delay.as_millis() as u64,
+                "Retrying provider request"
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn policy(max_retries: u32, retry_on_429: bool) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            backoff: Duration::from_millis(1),
+            retry_on_429,
+        }
+    }
+
+    /// Serve one response per connection with the given statuses, in order.
+    /// Returns the URL and a handle resolving to the number of requests.
+    async fn serve(statuses: Vec<u16>) -> (String, tokio::task::JoinHandle<usize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            let mut served = 0;
+            for status in statuses {
+                let Ok((mut stream, _)) = listener.accept().await else {
+                    break;
+                };
+                let mut buf = [0u8; 4096];
+                let _ = stream.read(&mut buf).await;
+                let response = format!(
+                    "HTTP/1.1 {status} X\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+                served += 1;
+            }
+            served
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn backoff_doubles() {
+        let policy = RetryPolicy {
+            max_retries: 3,
+            backoff: Duration::from_millis(200),
+            retry_on_429: true,
+        };
+        assert_eq!(policy.delay(0), Duration::from_millis(200));
+        assert_eq!(policy.delay(2), Duration::from_millis(800));
+        assert!(policy.retryable(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(policy.retryable(StatusCode::TOO_MANY_REQUESTS));
+        assert!(!policy.retryable(StatusCode::UNAUTHORIZED));
+    }
+
+    #[tokio::test]
+    async fn retries_transient_errors_until_success() {
+        let (url, server) = serve(vec![503, 429, 200]).await;
+        let response = policy(2, true)
+            .send(reqwest::Client::new().get(&url))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(server.await.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_retries() {
+        let (url, server) = serve(vec![500, 502, 200]).await;
+        let response = policy(1, true)
+            .send(reqwest::Client::new().get(&url))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn rate_limits_can_fall_through() {
+        let (url, server) = serve(vec![429, 200]).await;
+        let response = policy(2, false)
+            .send(reqwest::Client::new().get(&url))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        server.abort();
+    }
+}
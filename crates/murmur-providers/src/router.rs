@@ -1,4 +1,4 @@
-use murmur_context::ShellContext;
+use murmur_context::{lexer, ShellContext};
 use murmur_protocol::CompletionRequest;
 
 /// Decision about which provider to route a request to.
@@ -35,36 +35,47 @@ impl ProviderRouter {
     }
 
     fn is_code_context(request: &CompletionRequest, context: &ShellContext) -> bool {
-        let input = &request.input;
+        let commands = lexer::commands(&request.input);
+        // A lone `vim` may still become `vimdiff`; `vim ` won't
+        let typing_args = request.input.ends_with(char::is_whitespace);
 
         // Check if the input is running/editing code
-        let code_commands = [
-            "vim ",
-            "nvim ",
-            "nano ",
-            "code ",
-            "emacs ",
-            "python ",
-            "python3 ",
-            "node ",
-            "ruby ",
-            "perl ",
-            "cargo run",
-            "go run",
-            "npx ",
-            "tsx ",
-            "bun run",
+        let code_commands: [&[&str]; 15] = [
+            &["vim"],
+            &["nvim"],
+            &["nano"],
+            &["code"],
+            &["emacs"],
+            &["python"],
+            &["python3"],
+            &["node"],
+            &["ruby"],
+            &["perl"],
+            &["cargo", "run"],
+            &["go", "run"],
+            &["npx"],
+            &["tsx"],
+            &["bun", "run"],
         ];
-        for cmd in &code_commands {
-            if input.starts_with(cmd) {
-                return true;
-            }
+        let runs_code = commands.iter().any(|command| {
+            let words = command.without_assignments();
+            code_commands.iter().any(|cmd| {
+                starts_with(words, cmd) && (cmd.len() > 1 || words.len() > 1 || typing_args)
+            })
+        });
+        if runs_code {
+            return true;
         }
 
         // Code-related cat/less (viewing source files)
-        if (input.starts_with("cat ") || input.starts_with("less ") || input.starts_with("bat "))
-            && Self::has_code_extension(input)
-        {
+        let views_code = commands.iter().any(|command| {
+            let words = command.without_assignments();
+            matches!(
+                words.first().map(String::as_str),
+                Some("cat" | "less" | "bat")
+            ) && words[1..].iter().any(|w| Self::has_code_extension(w))
+        });
+        if views_code {
             return true;
         }
 
@@ -77,31 +88,36 @@ impl ProviderRouter {
                     | murmur_context::ProjectType::Python
                     | murmur_context::ProjectType::Go
             )
-        ) && Self::looks_like_code_input(input)
+        ) && Self::looks_like_code_input(&commands)
     }
 
-    fn has_code_extension(input: &str) -> bool {
+    fn has_code_extension(word: &str) -> bool {
         let extensions = [
             ".rs", ".py", ".js", ".ts", ".tsx", ".jsx", ".go", ".rb", ".java", ".c", ".cpp", ".h",
         ];
-        extensions.iter().any(|ext| input.contains(ext))
+        extensions.iter().any(|ext| word.ends_with(ext))
     }
 
-    fn looks_like_code_input(input: &str) -> bool {
-        // Heuristic: contains code-like patterns
-        input.contains("fn ")
-            || input.contains("def ")
-            || input.contains("function ")
-            || input.contains("class ")
-            || input.contains("import ")
-            || input.contains("const ")
-            || input.contains("let ")
-            || input.contains("pub ")
-            || input.contains("async ")
-            || input.contains("struct ")
+    fn looks_like_code_input(commands: &[lexer::Command]) -> bool {
+        // Heuristic: a line starting with a declaration keyword is code
+        // pasted at the prompt; the same word quoted in an argument isn't
+        let keywords = [
+            "fn", "def", "function", "class", "import", "const", "let", "pub", "async", "struct",
+        ];
+        commands.iter().any(|command| {
+            command
+                .words
+                .first()
+                .is_some_and(|w| keywords.contains(&w.as_str()))
+        })
     }
 }
 
+/// Whether `words` begin with exactly the words of `prefix`.
+fn starts_with(words: &[String], prefix: &[&str]) -> bool {
+    words.len() >= prefix.len() && words.iter().zip(prefix).all(|(w, p)| w == p)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -114,6 +130,13 @@ mod tests {
             cwd: "/home/user".to_string(),
             history: vec![],
             shell: Some("zsh".to_string()),
+            max_items: None,
+            min_score: None,
+            host: None,
+            provider: None,
+            model: None,
+            cancel_token: None,
+            session_id: None,
         };
         let context = ShellContext::default();
         assert_eq!(
@@ -130,6 +153,13 @@ mod tests {
             cwd: "/home/user/project".to_string(),
             history: vec![],
             shell: Some("zsh".to_string()),
+            max_items: None,
+            min_score: None,
+            host: None,
+            provider: None,
+            model: None,
+            cancel_token: None,
+            session_id: None,
         };
         let context = ShellContext::default();
         assert_eq!(
@@ -146,6 +176,13 @@ mod tests {
             cwd: "/home/user".to_string(),
             history: vec![],
             shell: Some("zsh".to_string()),
+            max_items: None,
+            min_score: None,
+            host: None,
+            provider: None,
+            model: None,
+            cancel_token: None,
+            session_id: None,
         };
         let context = ShellContext::default();
         assert_eq!(
@@ -162,6 +199,13 @@ mod tests {
             cwd: "/home/user".to_string(),
             history: vec![],
             shell: Some("zsh".to_string()),
+            max_items: None,
+            min_score: None,
+            host: None,
+            provider: None,
+            model: None,
+            cancel_token: None,
+            session_id: None,
         };
         let context = ShellContext::default();
         assert_eq!(
@@ -178,6 +222,13 @@ mod tests {
             cwd: "/home/user".to_string(),
             history: vec![],
             shell: Some("bash".to_string()),
+            max_items: None,
+            min_score: None,
+            host: None,
+            provider: None,
+            model: None,
+            cancel_token: None,
+            session_id: None,
         };
         let context = ShellContext::default();
         assert_eq!(
@@ -185,4 +236,42 @@ mod tests {
             RouteDecision::Shell
         );
     }
+
+    #[test]
+    fn quoted_words_do_not_route() {
+        let mut request = CompletionRequest {
+            input: "git commit -m \"let cat src/main.rs go\"".to_string(),
+            cursor_pos: 37,
+            cwd: "/home/user".to_string(),
+            history: vec![],
+            shell: Some("zsh".to_string()),
+            max_items: None,
+            min_score: None,
+            host: None,
+            provider: None,
+            model: None,
+            cancel_token: None,
+            session_id: None,
+        };
+        let context = ShellContext {
+            project: Some(murmur_context::ProjectType::Rust),
+            ..ShellContext::default()
+        };
+        assert_eq!(
+            ProviderRouter::route(&request, &context),
+            RouteDecision::Shell
+        );
+
+        // Compound commands route on any part
+        request.input = "cd src && RUST_LOG=debug cargo run".to_string();
+        assert_eq!(
+            ProviderRouter::route(&request, &context),
+            RouteDecision::Code
+        );
+        request.input = "vim".to_string();
+        assert_eq!(
+            ProviderRouter::route(&request, &context),
+            RouteDecision::Shell
+        );
+    }
 }
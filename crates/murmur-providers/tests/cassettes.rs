@@ -0,0 +1,156 @@
+//! Provider requests and response parsing against recorded API responses.
+//!
+//! Re-record a cassette by running its test with `MURMUR_RECORD_CASSETTES=1`
+//! and the provider's key in the environment (`ANTHROPIC_API_KEY`,
+//! `CODESTRAL_API_KEY`, or a local Ollama with the model pulled).
+
+use murmur_context::ShellContext;
+use murmur_protocol::{CompletionKind, CompletionRequest};
+use murmur_providers::cassette::CassetteServer;
+use murmur_providers::{
+    AnthropicProvider, CodestralProvider, OllamaProvider, Provider, ProviderConfig, ProviderError,
+};
+use serde_json::json;
+
+async fn cassette(name: &str, upstream: &str) -> CassetteServer {
+    let path = format!("{}/tests/cassettes/{name}.json", env!("CARGO_MANIFEST_DIR"));
+    CassetteServer::start(path, upstream).await.unwrap()
+}
+
+fn config(endpoint: String, key_var: &str, extra: serde_json::Value) -> ProviderConfig {
+    let mut config = json!({
+        "endpoint": endpoint,
+        "api_key": std::env::var(key_var).unwrap_or_else(|_| "test-key".to_string()),
+        "max_retries": 0,
+        "timeout_ms": 30_000,
+    });
+    config
+        .as_object_mut()
+        .unwrap()
+        .extend(extra.as_object().cloned().unwrap_or_default());
+    serde_json::from_value(config).unwrap()
+}
+
+fn request(input: &str) -> CompletionRequest {
+    serde_json::from_value(json!({
+        "input": input,
+        "cursor_pos": input.len(),
+        "cwd": "/home/dev/murmur",
+        "shell": "zsh",
+    }))
+    .unwrap()
+}
+
+fn context() -> ShellContext {
+    ShellContext {
+        cwd: "/home/dev/murmur".to_string(),
+        shell: "zsh".to_string(),
+        history: vec!["cargo test".to_string(), "git commit -m wip".to_string()],
+        ..Default::default()
+    }
+}
+
+fn texts(items: &[murmur_protocol::CompletionItem]) -> Vec<&str> {
+    items.iter().map(|i| i.text.as_str()).collect()
+}
+
+#[tokio::test]
+async fn anthropic_completion() {
+    let server = cassette("anthropic_complete", "https://api.anthropic.com").await;
+    let endpoint = format!("{}/v1/messages", server.url());
+    let provider =
+        AnthropicProvider::new(&config(endpoint, "ANTHROPIC_API_KEY", json!({}))).unwrap();
+    let items = provider
+        .complete(&request("git pu"), &context())
+        .await
+        .unwrap();
+    assert_eq!(
+        texts(&items),
+        ["git push origin main", "git push --force-with-lease"]
+    );
+    assert_eq!(items[0].description.as_deref(), Some("Push main to origin"));
+    assert_eq!(server.remaining(), 0);
+}
+
+#[tokio::test]
+async fn anthropic_overloaded() {
+    let server = cassette("anthropic_overloaded", "https://api.anthropic.com").await;
+    let endpoint = format!("{}/v1/messages", server.url());
+    let provider =
+        AnthropicProvider::new(&config(endpoint, "ANTHROPIC_API_KEY", json!({}))).unwrap();
+    let error = provider
+        .complete(&request("git pu"), &context())
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(error, ProviderError::Api { status: 529, ref message } if message.contains("overloaded_error")),
+        "{error}"
+    );
+}
+
+#[tokio::test]
+async fn codestral_fim_completion() {
+    let server = cassette("codestral_fim", "https://codestral.mistral.ai").await;
+    let endpoint = format!("{}/v1/fim/completions", server.url());
+    let provider =
+        CodestralProvider::new(&config(endpoint, "CODESTRAL_API_KEY", json!({}))).unwrap();
+    let items = provider
+        .complete(&request("git p"), &context())
+        .await
+        .unwrap();
+    assert_eq!(texts(&items), ["git push origin main"]);
+    assert_eq!(items[0].kind, CompletionKind::Code);
+}
+
+#[tokio::test]
+async fn ollama_generate_completion() {
+    let server = cassette("ollama_generate", "http://localhost:11434").await;
+    let provider = OllamaProvider::new(&config(
+        server.url().to_string(),
+        "OLLAMA_API_KEY",
+        json!({ "model": "codellama:7b" }),
+    ))
+    .unwrap();
+    // The model wrapped its JSON in a markdown fence
+    let items = provider
+        .complete(&request("git pu"), &context())
+        .await
+        .unwrap();
+    assert_eq!(
+        texts(&items),
+        ["git push origin main", "git push --force-with-lease"]
+    );
+}
+
+#[tokio::test]
+async fn ollama_chat_completion() {
+    let server = cassette("ollama_chat", "http://localhost:11434").await;
+    let provider = OllamaProvider::new(&config(
+        server.url().to_string(),
+        "OLLAMA_API_KEY",
+        json!({ "model": "qwen2.5-coder:7b", "api": "chat" }),
+    ))
+    .unwrap();
+    let items = provider
+        .complete(&request("git pu"), &context())
+        .await
+        .unwrap();
+    assert_eq!(items.len(), 2);
+    assert_eq!(server.remaining(), 0);
+}
+
+#[tokio::test]
+async fn ollama_fim_completion() {
+    let server = cassette("ollama_fim", "http://localhost:11434").await;
+    let provider = OllamaProvider::new(&config(
+        server.url().to_string(),
+        "OLLAMA_API_KEY",
+        json!({ "model": "qwen2.5-coder:1.5b", "api": "fim" }),
+    ))
+    .unwrap();
+    let items = provider
+        .complete(&request("git p"), &context())
+        .await
+        .unwrap();
+    assert_eq!(texts(&items), ["git push origin main"]);
+}
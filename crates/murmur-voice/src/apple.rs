@@ -2,7 +2,7 @@ use async_trait::async_trait;
 use std::sync::atomic::{AtomicU64, Ordering};
 use tracing::{debug, warn};
 
-use crate::{encode_wav, SttEngine, SttResult, VoiceError};
+use crate::{prepare_wav, SttEngine, SttResult, VoiceError};
 
 static TEMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
 
@@ -86,19 +86,7 @@ impl SttEngine for AppleEngine {
         let tmp_path = tmp_dir.join(format!("murmur-stt-{}-{}.wav", std::process::id(), counter));
         let tmp_path_str = tmp_path.to_string_lossy().to_string();
 
-        // If the data doesn't start with RIFF header, encode it as WAV
-        let wav_data = if audio_data.len() >= 4 && &audio_data[..4] == b"RIFF" {
-            audio_data.to_vec()
-        } else {
-            // Assume raw 16-bit PCM samples at 16kHz
-            let samples: Vec<i16> = audio_data
-                .chunks_exact(2)
-                .map(|chunk| i16::from_le_bytes([chunk[0], chunk[1]]))
-                .collect();
-            encode_wav(&samples, 16000)?
-        };
-
-        std::fs::write(&tmp_path, &wav_data)
+        std::fs::write(&tmp_path, prepare_wav(audio_data)?)
             .map_err(|e| VoiceError::SttError(format!("Failed to write temp audio file: {e}")))?;
 
         // Spawn the helper
@@ -144,6 +132,7 @@ impl SttEngine for AppleEngine {
         Ok(SttResult {
             transcript,
             confidence,
+            words: Vec::new(),
         })
     }
 }
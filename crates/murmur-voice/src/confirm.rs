@@ -0,0 +1,149 @@
+//! Tiny yes/no grammar for spoken confirmations.
+//!
+//! Deliberately strict: the reply must contain an affirmative phrase and no
+//! negative one. Anything ambiguous counts as "no", since the command being
+//! confirmed is one we already consider risky.
+//!
+//! The riskiest commands can ask for a word from `CHALLENGE_WORDS` instead:
+//! a stray "yeah" or a misheard "yes" is easy to get, a named word isn't.
+
+/// Phrases accepted as "yes". Multi-word phrases match as a word sequence.
+const AFFIRMATIVE: &[&str] = &[
+    "yes",
+    "yeah",
+    "yep",
+    "yup",
+    "sure",
+    "confirm",
+    "confirmed",
+    "affirmative",
+    "correct",
+    "go ahead",
+    "do it",
+    "run it",
+    "ok",
+    "okay",
+];
+
+/// Phrases that cancel, even next to an affirmative one ("yes, no wait").
+const NEGATIVE: &[&str] = &[
+    "no",
+    "nope",
+    "nah",
+    "cancel",
+    "stop",
+    "abort",
+    "negative",
+    "don't",
+    "dont",
+    "do not",
+    "wait",
+    "never mind",
+    "nevermind",
+];
+
+/// Words a challenge is picked from: common, two syllables or more, and
+/// unlike each other and any yes or no when spoken.
+pub const CHALLENGE_WORDS: &[&str] = &[
+    "anchor", "bamboo", "cactus", "dolphin", "ember", "falcon", "glacier", "hammock", "igloo",
+    "jasmine", "kettle", "lantern", "meadow", "nectar", "orchid", "pepper", "quartz", "rocket",
+    "saddle", "tundra", "umbrella", "velvet", "walnut", "zebra",
+];
+
+/// The challenge word for `seed`.
+pub fn challenge_word(seed: u64) -> &'static str {
+    CHALLENGE_WORDS[(seed % CHALLENGE_WORDS.len() as u64) as usize]
+}
+
+/// Whether a transcribed reply says `word` and doesn't also cancel.
+pub fn says_challenge(transcript: &str, word: &str) -> bool {
+    parse_confirmation(transcript) != Some(false)
+        && words(transcript)
+            .iter()
+            .any(|w| w.eq_ignore_ascii_case(word))
+}
+
+/// Lowercase words of a transcript, without surrounding punctuation.
+fn words(transcript: &str) -> Vec<String> {
+    transcript
+        .split_whitespace()
+        .map(|w| {
+            w.trim_matches(|c: char| !c.is_alphanumeric() && c != '\'')
+                .to_lowercase()
+        })
+        .filter(|w| !w.is_empty())
+        .collect()
+}
+
+/// Interpret a transcribed reply. `Some(true)` for a clear yes,
+/// `Some(false)` for a no, `None` when neither was recognized.
+pub fn parse_confirmation(transcript: &str) -> Option<bool> {
+    let words = words(transcript);
+
+    let contains = |phrase: &str| {
+        let parts: Vec<&str> = phrase.split(' ').collect();
+        words
+            .windows(parts.len())
+            .any(|window| window.iter().zip(&parts).all(|(w, p)| w == p))
+    };
+
+    if NEGATIVE.iter().any(|p| contains(p)) {
+        Some(false)
+    } else if AFFIRMATIVE.iter().any(|p| contains(p)) {
+        Some(true)
+    } else {
+        None
+    }
+}
+
+/// Question asked before running `command`.
+pub fn confirmation_prompt(command: &str) -> String {
+    format!("Run {command}? Say yes to confirm.")
+}
+
+/// Question asked before running `command` when a challenge word is needed.
+pub fn challenge_prompt(command: &str, word: &str) -> String {
+    format!("Run {command}? Say the word {word} to confirm.")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_yes() {
+        assert_eq!(parse_confirmation("Yes."), Some(true));
+        assert_eq!(parse_confirmation("yeah go ahead"), Some(true));
+        assert_eq!(parse_confirmation("OK, run it"), Some(true));
+    }
+
+    #[test]
+    fn negatives_win() {
+        assert_eq!(parse_confirmation("no"), Some(false));
+        assert_eq!(parse_confirmation("yes, no wait"), Some(false));
+        assert_eq!(parse_confirmation("don't do it"), Some(false));
+        assert_eq!(parse_confirmation("Cancel!"), Some(false));
+    }
+
+    #[test]
+    fn unrelated_speech_is_unrecognized() {
+        assert_eq!(parse_confirmation("what did you say"), None);
+        assert_eq!(parse_confirmation(""), None);
+        // "yesterday" must not match "yes"
+        assert_eq!(parse_confirmation("yesterday"), None);
+    }
+
+    #[test]
+    fn challenges_need_the_word() {
+        assert!(says_challenge("Falcon.", "falcon"));
+        assert!(says_challenge("okay falcon", "falcon"));
+        assert!(!says_challenge("yes", "falcon"));
+        assert!(!says_challenge("falcons", "falcon"));
+        assert!(!says_challenge("falcon, no wait", "falcon"));
+        assert_eq!(challenge_word(1), CHALLENGE_WORDS[1]);
+        assert_eq!(
+            challenge_word(CHALLENGE_WORDS.len() as u64),
+            CHALLENGE_WORDS[0]
+        );
+    }
+}
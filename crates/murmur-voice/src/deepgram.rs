@@ -6,16 +6,20 @@
 use async_trait::async_trait;
 use reqwest::Client;
 use serde::Deserialize;
+use std::io::Write;
 use tracing::debug;
 
-use crate::{SttEngine, SttResult, VoiceError};
+use crate::diarize::group_by_speaker;
+use crate::{SttEngine, SttResult, SttSegments, VoiceError, Word};
 
 const DEEPGRAM_API_URL: &str = "https://api.deepgram.com/v1/listen";
 
 pub struct DeepgramEngine {
     client: Client,
+    endpoint: String,
     api_key: String,
     language: String,
+    gzip: bool,
 }
 
 #[derive(Deserialize)]
@@ -37,16 +41,64 @@ struct DeepgramChannel {
 struct DeepgramAlternative {
     transcript: String,
     confidence: f64,
+    #[serde(default)]
+    words: Vec<DeepgramWord>,
+}
+
+#[derive(Deserialize)]
+struct DeepgramWord {
+    word: String,
+    start: f64,
+    end: f64,
+    /// With `punctuate`, the word as it appears in the transcript.
+    punctuated_word: Option<String>,
+    /// With `diarize`, who spoke it, numbered from 0.
+    speaker: Option<u32>,
+}
+
+impl DeepgramWord {
+    fn to_word(&self) -> Word {
+        Word {
+            text: self
+                .punctuated_word
+                .clone()
+                .unwrap_or_else(|| self.word.clone()),
+            start: self.start,
+            end: self.end,
+        }
+    }
 }
 
 impl DeepgramEngine {
     pub fn new(api_key: String, language: String) -> Self {
         Self {
             client: Client::new(),
+            endpoint: DEEPGRAM_API_URL.to_string(),
             api_key,
             language,
+            gzip: false,
         }
     }
+
+    /// Send audio to `endpoint` instead of Deepgram's hosted API, e.g. a
+    /// self-hosted deployment.
+    pub fn with_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.endpoint = endpoint.into();
+        self
+    }
+
+    /// Gzip uploads (`Content-Encoding: gzip`). Speech WAV only shrinks
+    /// modestly, but on a slow uplink every second counts.
+    pub fn with_gzip(mut self, gzip: bool) -> Self {
+        self.gzip = gzip;
+        self
+    }
+}
+
+fn gzip(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(data)?;
+    encoder.finish()
 }
 
 #[async_trait]
@@ -60,24 +112,74 @@ impl SttEngine for DeepgramEngine {
     }
 
     async fn transcribe(&self, audio_data: &[u8]) -> Result<SttResult, VoiceError> {
+        let alternative = self.listen(audio_data, false).await?;
+        Ok(SttResult {
+            words: alternative
+                .words
+                .iter()
+                .map(DeepgramWord::to_word)
+                .collect(),
+            transcript: alternative.transcript,
+            confidence: alternative.confidence,
+        })
+    }
+
+    async fn transcribe_speakers(&self, audio_data: &[u8]) -> Result<SttSegments, VoiceError> {
+        let alternative = self.listen(audio_data, true).await?;
+        let words = alternative
+            .words
+            .iter()
+            .map(|word| (word.to_word(), word.speaker.unwrap_or(0)));
+        Ok(SttSegments {
+            segments: group_by_speaker(words),
+            confidence: alternative.confidence,
+        })
+    }
+}
+
+impl DeepgramEngine {
+    /// Send audio to Deepgram and return the best transcription. With
+    /// `diarize`, words are labeled by speaker; the audio is then a whole
+    /// recording, so the timeout grows with its length.
+    async fn listen(
+        &self,
+        audio_data: &[u8],
+        diarize: bool,
+    ) -> Result<DeepgramAlternative, VoiceError> {
         debug!(
             audio_bytes = audio_data.len(),
             language = %self.language,
+            diarize,
             "Sending audio to Deepgram"
         );
 
-        let url = format!(
+        let mut url = format!(
             "{}?model=nova-2&language={}&punctuate=true&smart_format=true",
-            DEEPGRAM_API_URL, self.language
+            self.endpoint, self.language
         );
+        let mut timeout = std::time::Duration::from_secs(30);
+        if diarize {
+            url.push_str("&diarize=true");
+            // A second more for each minute of 16kHz mono audio
+            timeout += std::time::Duration::from_secs(audio_data.len() as u64 / 1_920_000);
+        }
 
-        let response = self
+        let mut request = self
             .client
             .post(&url)
             .header("Authorization", format!("Token {}", self.api_key))
             .header("Content-Type", "audio/wav")
-            .timeout(std::time::Duration::from_secs(30))
-            .body(audio_data.to_vec())
+            .timeout(timeout);
+        request = if self.gzip {
+            let body = gzip(audio_data)
+                .map_err(|e| VoiceError::SttError(format!("Failed to compress audio: {e}")))?;
+            debug!(compressed_bytes = body.len(), "Audio compressed");
+            request.header("Content-Encoding", "gzip").body(body)
+        } else {
+            request.body(audio_data.to_vec())
+        };
+
+        let response = request
             .send()
             .await
             .map_err(|e| VoiceError::SttError(format!("Deepgram request failed: {e}")))?;
@@ -98,8 +200,9 @@ impl SttEngine for DeepgramEngine {
         let alternative = result
             .results
             .channels
-            .first()
-            .and_then(|c| c.alternatives.first())
+            .into_iter()
+            .next()
+            .and_then(|c| c.alternatives.into_iter().next())
             .ok_or_else(|| VoiceError::SttError("No transcription results".to_string()))?;
 
         debug!(
@@ -107,11 +210,7 @@ impl SttEngine for DeepgramEngine {
             confidence = alternative.confidence,
             "Deepgram transcription complete"
         );
-
-        Ok(SttResult {
-            transcript: alternative.transcript.clone(),
-            confidence: alternative.confidence,
-        })
+        Ok(alternative)
     }
 }
 
@@ -131,6 +230,21 @@ mod tests {
         assert!(!engine.is_available());
     }
 
+    #[test]
+    fn gzip_round_trips() {
+        let audio = vec![0u8; 32_000];
+        let compressed = gzip(&audio).unwrap();
+        assert!(compressed.len() < audio.len());
+
+        let mut decoded = Vec::new();
+        std::io::Read::read_to_end(
+            &mut flate2::read::GzDecoder::new(&compressed[..]),
+            &mut decoded,
+        )
+        .unwrap();
+        assert_eq!(decoded, audio);
+    }
+
     #[test]
     fn available_with_key() {
         let engine = DeepgramEngine::new("dg-test-key".to_string(), "en".to_string());
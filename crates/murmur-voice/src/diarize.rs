@@ -0,0 +1,299 @@
+//! Speaker diarization: telling apart who spoke when in a recording.
+//!
+//! Deepgram does this itself (`diarize=true`). For local use, a
+//! `CommandDiarizer` runs a program (typically a pyannote.audio script)
+//! that writes speaker turns as RTTM, the format pyannote's
+//! `write_rttm` produces, and the turns label the segments any STT engine
+//! transcribed. Only `murmur transcribe` asks for speakers; live voice
+//! input is always one speaker.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::process::Command;
+use tracing::debug;
+
+use crate::{Segment, SpeakerSegment, VoiceError, Word};
+
+/// When one speaker talked, in seconds from the start of the recording.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Turn {
+    /// Speakers are numbered from 0 in order of first appearance.
+    pub speaker: u32,
+    pub start: f64,
+    pub end: f64,
+}
+
+/// Runs a local diarization program on each recording.
+pub struct CommandDiarizer {
+    command: String,
+    timeout: Duration,
+}
+
+impl CommandDiarizer {
+    /// `command` is run with `sh -c` and the recording's path appended as
+    /// its last argument, and must print RTTM to stdout.
+    pub fn new(command: String) -> Self {
+        Self {
+            command,
+            timeout: Duration::from_secs(600),
+        }
+    }
+
+    /// Speaker turns in a WAV recording, in order.
+    pub async fn diarize(&self, audio: &[u8]) -> Result<Vec<Turn>, VoiceError> {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "murmur-diarize-{}-{}.wav",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::write(&path, audio).map_err(|e| {
+            VoiceError::SttError(format!("Failed to write {}: {e}", path.display()))
+        })?;
+        debug!(command = %self.command, path = %path.display(), "Running diarizer");
+        let output = tokio::time::timeout(
+            self.timeout,
+            Command::new("sh")
+                .arg("-c")
+                .arg(format!("{} \"$1\"", self.command))
+                .arg("sh")
+                .arg(&path)
+                .kill_on_drop(true)
+                .output(),
+        )
+        .await;
+        let _ = std::fs::remove_file(&path);
+
+        let output = output
+            .map_err(|_| {
+                VoiceError::SttError(format!(
+                    "Diarizer timed out after {}s",
+                    self.timeout.as_secs()
+                ))
+            })?
+            .map_err(|e| VoiceError::SttError(format!("Failed to run diarizer: {e}")))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(VoiceError::SttError(format!(
+                "Diarizer failed ({}): {}",
+                output.status,
+                stderr.trim()
+            )));
+        }
+        let turns = parse_rttm(&String::from_utf8_lossy(&output.stdout));
+        debug!(turns = turns.len(), "Diarization complete");
+        Ok(turns)
+    }
+}
+
+/// Turns from RTTM `SPEAKER` lines (`SPEAKER <file> <channel> <start>
+/// <duration> <NA> <NA> <label> ...`), sorted by start, with labels
+/// numbered in order of first appearance. Other lines are skipped.
+pub fn parse_rttm(rttm: &str) -> Vec<Turn> {
+    let mut turns: Vec<(f64, f64, &str)> = rttm
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.first() != Some(&"SPEAKER") || fields.len() < 8 {
+                return None;
+            }
+            let start: f64 = fields[3].parse().ok()?;
+            let duration: f64 = fields[4].parse().ok()?;
+            Some((start, start + duration, fields[7]))
+        })
+        .collect();
+    turns.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut speakers = HashMap::new();
+    turns
+        .into_iter()
+        .map(|(start, end, label)| {
+            let next = speakers.len() as u32;
+            Turn {
+                speaker: *speakers.entry(label).or_insert(next),
+                start,
+                end,
+            }
+        })
+        .collect()
+}
+
+/// The speaker who talked most between `start` and `end`, or else the one
+/// whose turn is nearest.
+fn speaker_at(turns: &[Turn], start: f64, end: f64) -> Option<u32> {
+    let overlap = |t: &Turn| t.end.min(end) - t.start.max(start);
+    let distance = |t: &Turn| (t.start - end).max(start - t.end).max(0.0);
+    let mut talked: HashMap<u32, f64> = HashMap::new();
+    for turn in turns.iter().filter(|t| overlap(t) > 0.0) {
+        *talked.entry(turn.speaker).or_default() += overlap(turn);
+    }
+    match talked.into_iter().max_by(|a, b| a.1.total_cmp(&b.1)) {
+        Some((speaker, _)) => Some(speaker),
+        None => turns
+            .iter()
+            .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+            .map(|t| t.speaker),
+    }
+}
+
+/// Label transcribed segments with the speakers in `turns`. Segments with
+/// word timings are split where the speaker changes; others go to whoever
+/// talked most during them.
+pub fn label_speakers(segments: Vec<Segment>, turns: &[Turn]) -> Vec<Segment> {
+    let mut labeled = Vec::new();
+    for segment in segments {
+        if segment.words.is_empty() {
+            let speaker = speaker_at(turns, segment.start, segment.end);
+            labeled.push(Segment { speaker, ..segment });
+            continue;
+        }
+        let words = segment
+            .words
+            .iter()
+            .map(|w| (w.clone(), speaker_at(turns, w.start, w.end).unwrap_or(0)));
+        for part in group_by_speaker(words) {
+            labeled.push(Segment {
+                start: part.start,
+                end: part.end,
+                text: part.text,
+                confidence: segment.confidence,
+                engine: segment.engine.clone(),
+                words: part.words,
+                speaker: Some(part.speaker),
+            });
+        }
+    }
+    labeled
+}
+
+/// Runs of consecutive words by the same speaker.
+pub(crate) fn group_by_speaker(
+    words: impl IntoIterator<Item = (Word, u32)>,
+) -> Vec<SpeakerSegment> {
+    let mut segments: Vec<SpeakerSegment> = Vec::new();
+    for (word, speaker) in words {
+        match segments.last_mut() {
+            Some(segment) if segment.speaker == speaker => {
+                segment.text.push(' ');
+                segment.text.push_str(&word.text);
+                segment.end = word.end;
+                segment.words.push(word);
+            }
+            _ => segments.push(SpeakerSegment {
+                speaker,
+                start: word.start,
+                end: word.end,
+                text: word.text.clone(),
+                words: vec![word],
+            }),
+        }
+    }
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RTTM: &str = "\
+SPEAKER meeting 1 4.25 3.00 <NA> <NA> SPEAKER_00 <NA> <NA>
+SPEAKER meeting 1 0.50 3.50 <NA> <NA> SPEAKER_01 <NA> <NA>
+SPEAKER meeting 1 7.30 2.00 <NA> <NA> SPEAKER_01 <NA> <NA>
+";
+
+    fn word(text: &str, start: f64, end: f64) -> Word {
+        Word {
+            text: text.to_string(),
+            start,
+            end,
+        }
+    }
+
+    fn segment(start: f64, end: f64, text: &str, words: Vec<Word>) -> Segment {
+        Segment {
+            start,
+            end,
+            text: text.to_string(),
+            confidence: 0.9,
+            engine: "deepgram".to_string(),
+            words,
+            speaker: None,
+        }
+    }
+
+    #[test]
+    fn rttm_turns_are_numbered_by_first_appearance() {
+        let turns = parse_rttm(&format!("# comment\n{RTTM}"));
+        assert_eq!(
+            turns,
+            [
+                Turn {
+                    speaker: 0,
+                    start: 0.5,
+                    end: 4.0
+                },
+                Turn {
+                    speaker: 1,
+                    start: 4.25,
+                    end: 7.25
+                },
+                Turn {
+                    speaker: 0,
+                    start: 7.3,
+                    end: 9.3
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn segments_split_where_the_speaker_changes() {
+        let turns = parse_rttm(RTTM);
+        let words = vec![
+            word("Ready?", 0.6, 1.0),
+            word("Yes,", 4.3, 4.6),
+            word("ship", 4.7, 5.0),
+            word("it.", 5.0, 5.2),
+            word("Done.", 7.4, 7.9),
+        ];
+        let labeled = label_speakers(
+            vec![
+                segment(0.0, 8.0, "Ready? Yes, ship it. Done.", words),
+                // No word timings: whoever talked most, or the nearest
+                segment(8.0, 9.0, "Thanks.", vec![]),
+                segment(12.0, 13.0, "Bye.", vec![]),
+            ],
+            &turns,
+        );
+        let summary: Vec<(Option<u32>, &str, f64, f64)> = labeled
+            .iter()
+            .map(|s| (s.speaker, s.text.as_str(), s.start, s.end))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (Some(0), "Ready?", 0.6, 1.0),
+                (Some(1), "Yes, ship it.", 4.3, 5.2),
+                (Some(0), "Done.", 7.4, 7.9),
+                (Some(0), "Thanks.", 8.0, 9.0),
+                (Some(0), "Bye.", 12.0, 13.0),
+            ]
+        );
+        assert_eq!(labeled[1].words.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn the_command_gets_the_recording() {
+        let diarizer = CommandDiarizer::new(format!(
+            "test -s \"$1\" && printf '{}' && true",
+            RTTM.replace('\n', "\\n")
+        ));
+        let turns = diarizer.diarize(b"RIFF....WAVE").await.unwrap();
+        assert_eq!(turns.len(), 3);
+
+        let failing = CommandDiarizer::new("echo 'no model' >&2; exit 3; true".to_string());
+        let error = failing.diarize(b"RIFF").await.unwrap_err();
+        assert!(error.to_string().contains("no model"), "{error}");
+    }
+}
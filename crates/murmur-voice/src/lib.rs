@@ -5,20 +5,48 @@
 //! - Apple Speech framework integration (macOS, via Swift helper)
 //! - Deepgram cloud STT integration
 //! - Voice restructuring pipeline (transcript → LLM → command/prose)
+//! - Text-to-speech output for spoken feedback
+//! - Yes/no and challenge-word matching for spoken confirmations
+//! - A self-test that runs a corpus of utterances through each engine
+//! - Progress estimates while a recording is processed
+//! - Transcription of whole recordings as timed segments
+//! - Speaker diarization of recordings (Deepgram or a local command)
+//! - Failover, race and best-of strategies across STT engines
 //! - Audio utilities for WAV encoding
 
 mod apple;
 mod claude_cli;
+mod confirm;
 mod deepgram;
+mod diarize;
+mod progress;
 mod restructure;
+mod selftest;
+mod strategy;
+mod transcript;
+mod tts;
+mod validate;
 
 pub use apple::AppleEngine;
 pub use claude_cli::ClaudeCliRestructurer;
+pub use confirm::{
+    challenge_prompt, challenge_word, confirmation_prompt, parse_confirmation, says_challenge,
+    CHALLENGE_WORDS,
+};
 pub use deepgram::DeepgramEngine;
+pub use diarize::{label_speakers, parse_rttm, CommandDiarizer, Turn};
+pub use progress::{ignore_progress, ProgressFn};
 pub use restructure::VoiceRestructurer;
+pub use selftest::{corpus, word_error_rate, Utterance};
+pub use strategy::SttStrategy;
+pub use transcript::{speaker_label, subtitles, Segment, SubtitleFormat};
+pub use tts::{Speaker, TtsBackend};
+pub use validate::CommandValidator;
 
 use async_trait::async_trait;
-use murmur_protocol::{VoiceMode, VoiceResult, VoiceStatus};
+use murmur_protocol::{RiskLevel, SttCandidate, VoiceMode, VoiceResult, VoiceStage, VoiceStatus};
+use serde::Serialize;
+use std::sync::Arc;
 use std::time::Instant;
 use thiserror::Error;
 use tracing::{debug, info, warn};
@@ -51,6 +79,37 @@ pub struct SttResult {
     pub transcript: String,
     /// Confidence score (0.0 to 1.0).
     pub confidence: f64,
+    /// When each word was spoken, if the engine reports it.
+    pub words: Vec<Word>,
+}
+
+/// A transcribed word and when it was spoken, in seconds from the start
+/// of the audio.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Word {
+    pub text: String,
+    pub start: f64,
+    pub end: f64,
+}
+
+/// Result from transcribing audio with several speakers.
+#[derive(Debug, Clone)]
+pub struct SttSegments {
+    /// Stretches of speech by one speaker, in order.
+    pub segments: Vec<SpeakerSegment>,
+    /// Confidence score (0.0 to 1.0).
+    pub confidence: f64,
+}
+
+/// A stretch of speech by one speaker.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpeakerSegment {
+    /// Speakers are numbered from 0 in order of first appearance.
+    pub speaker: u32,
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+    pub words: Vec<Word>,
 }
 
 /// Trait for speech-to-text engines.
@@ -64,6 +123,15 @@ pub trait SttEngine: Send + Sync {
 
     /// Transcribe audio data (WAV format, 16kHz mono 16-bit).
     async fn transcribe(&self, audio_data: &[u8]) -> Result<SttResult, VoiceError>;
+
+    /// Transcribe a whole recording (WAV, any length) labeled by speaker.
+    /// Engines that can't tell speakers apart return `NotAvailable`.
+    async fn transcribe_speakers(&self, _audio_data: &[u8]) -> Result<SttSegments, VoiceError> {
+        Err(VoiceError::NotAvailable(format!(
+            "{} can't tell speakers apart",
+            self.name()
+        )))
+    }
 }
 
 /// Configuration for the voice engine.
@@ -73,8 +141,15 @@ pub struct VoiceConfig {
     pub engine: String,
     pub language: String,
     pub confidence_threshold: f64,
+    /// How several available STT engines are used.
+    pub strategy: SttStrategy,
     pub capture_timeout_ms: u64,
     pub deepgram_api_key: Option<String>,
+    /// Gzip audio uploaded to Deepgram.
+    pub deepgram_gzip: bool,
+    /// Local diarization program for `transcribe_speakers`; it gets a WAV
+    /// path and prints RTTM.
+    pub diarize_command: Option<String>,
 }
 
 impl Default for VoiceConfig {
@@ -84,8 +159,11 @@ impl Default for VoiceConfig {
             engine: "deepgram".to_string(),
             language: "en".to_string(),
             confidence_threshold: 0.5,
+            strategy: SttStrategy::Failover,
             capture_timeout_ms: 30000,
             deepgram_api_key: None,
+            deepgram_gzip: false,
+            diarize_command: None,
         }
     }
 }
@@ -98,22 +176,35 @@ pub enum Restructurer {
     ClaudeCli(ClaudeCliRestructurer),
 }
 
+impl Restructurer {
+    /// Backend name as written in the `[voice] restructurer` config.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Api(_) => "api",
+            Self::ClaudeCli(_) => "claude-cli",
+        }
+    }
+}
+
 /// The main voice engine that coordinates capture, STT, and restructuring.
 pub struct VoiceEngine {
     config: VoiceConfig,
-    engines: Vec<Box<dyn SttEngine>>,
+    engines: Vec<Arc<dyn SttEngine>>,
     restructurer: Option<Restructurer>,
+    validator: Option<Arc<dyn CommandValidator>>,
+    diarizer: Option<CommandDiarizer>,
 }
 
 impl VoiceEngine {
     pub fn new(config: VoiceConfig) -> Self {
-        let mut engines: Vec<Box<dyn SttEngine>> = Vec::new();
+        let mut engines: Vec<Arc<dyn SttEngine>> = Vec::new();
 
         // Initialize Deepgram if API key is available
         if let Some(ref key) = config.deepgram_api_key {
-            let engine = DeepgramEngine::new(key.clone(), config.language.clone());
+            let engine = DeepgramEngine::new(key.clone(), config.language.clone())
+                .with_gzip(config.deepgram_gzip);
             info!("Deepgram STT engine initialized");
-            engines.push(Box::new(engine));
+            engines.push(Arc::new(engine));
         }
 
         // Apple Speech engine (macOS only, via swift helper)
@@ -122,16 +213,19 @@ impl VoiceEngine {
             let apple = AppleEngine::new(config.language.clone());
             if apple.is_available() {
                 info!("Apple Speech STT engine initialized");
-                engines.push(Box::new(apple));
+                engines.push(Arc::new(apple));
             } else {
                 debug!("Apple STT helper not found — run swift-helpers/build.sh to enable");
             }
         }
 
+        let diarizer = config.diarize_command.clone().map(CommandDiarizer::new);
         Self {
             config,
             engines,
             restructurer: None,
+            validator: None,
+            diarizer,
         }
     }
 
@@ -140,6 +234,17 @@ impl VoiceEngine {
         self.restructurer = Some(restructurer);
     }
 
+    /// Check Command-mode restructurer output with `validator`, retrying
+    /// once when it's rejected.
+    pub fn set_validator(&mut self, validator: Arc<dyn CommandValidator>) {
+        self.validator = Some(validator);
+    }
+
+    /// Name of the configured restructurer backend, if any.
+    pub fn restructurer(&self) -> Option<&'static str> {
+        self.restructurer.as_ref().map(Restructurer::name)
+    }
+
     /// Get current voice engine status.
     pub fn status(&self) -> VoiceStatus {
         let available = self.detect_engines();
@@ -162,6 +267,20 @@ impl VoiceEngine {
         mode: VoiceMode,
         cwd: &str,
         shell: Option<&str>,
+    ) -> Result<VoiceResult, VoiceError> {
+        self.process_audio_with_progress(audio_data, mode, cwd, shell, &ignore_progress)
+            .await
+    }
+
+    /// `process_audio`, calling `report` with estimated progress about
+    /// twice a second until the result is ready.
+    pub async fn process_audio_with_progress(
+        &self,
+        audio_data: &[u8],
+        mode: VoiceMode,
+        cwd: &str,
+        shell: Option<&str>,
+        report: ProgressFn<'_>,
     ) -> Result<VoiceResult, VoiceError> {
         if !self.config.enabled {
             return Err(VoiceError::NotAvailable(
@@ -170,71 +289,230 @@ impl VoiceEngine {
         }
 
         let start = Instant::now();
+        let tracker = progress::Tracker::new(report);
+        let audio_secs = progress::audio_secs(audio_data);
 
-        // Try each STT engine in order
-        let stt_result = self.run_stt(audio_data).await?;
+        let (stt_result, engine, stt_candidates) = tracker
+            .track(
+                VoiceStage::Transcribing,
+                audio_secs,
+                self.run_stt(audio_data),
+            )
+            .await?;
 
         info!(
-            engine = stt_result.1,
-            transcript = %stt_result.0.transcript,
-            confidence = stt_result.0.confidence,
+            engine = %engine,
+            transcript = %stt_result.transcript,
+            confidence = stt_result.confidence,
             "STT completed"
         );
 
         // Check confidence threshold
-        if stt_result.0.confidence < self.config.confidence_threshold {
+        if stt_result.confidence < self.config.confidence_threshold {
             return Err(VoiceError::LowConfidence(
-                stt_result.0.confidence,
+                stt_result.confidence,
                 self.config.confidence_threshold,
             ));
         }
 
-        // Restructure the transcript
-        let output = match &self.restructurer {
+        let output = tracker
+            .track(
+                VoiceStage::Restructuring,
+                audio_secs,
+                self.restructure(&stt_result.transcript, &mode, cwd, shell),
+            )
+            .await?;
+
+        Ok(VoiceResult {
+            transcript: stt_result.transcript,
+            output,
+            mode,
+            confidence: stt_result.confidence,
+            engine,
+            latency_ms: start.elapsed().as_millis() as u64,
+            risk: RiskLevel::Safe,
+            sensitive: vec![],
+            execute: false,
+            confirmation: None,
+            stt_candidates,
+        })
+    }
+
+    /// Turn a transcript into a command or prose with the configured
+    /// restructurer, or return it unchanged when there is none. Commands
+    /// go past the validator, if one is set.
+    pub async fn restructure(
+        &self,
+        transcript: &str,
+        mode: &VoiceMode,
+        cwd: &str,
+        shell: Option<&str>,
+    ) -> Result<String, VoiceError> {
+        match (&self.validator, mode) {
+            (Some(validator), VoiceMode::Command) if self.restructurer.is_some() => {
+                validate::checked(
+                    transcript,
+                    cwd,
+                    shell,
+                    validator.as_ref(),
+                    |prompt| async move { self.restructure_once(&prompt, mode, cwd, shell).await },
+                )
+                .await
+            }
+            _ => self.restructure_once(transcript, mode, cwd, shell).await,
+        }
+    }
+
+    async fn restructure_once(
+        &self,
+        transcript: &str,
+        mode: &VoiceMode,
+        cwd: &str,
+        shell: Option<&str>,
+    ) -> Result<String, VoiceError> {
+        match &self.restructurer {
             Some(Restructurer::Api(restructurer)) => {
                 debug!(mode = ?mode, "Restructuring transcript via API");
-                restructurer
-                    .restructure(&stt_result.0.transcript, &mode, cwd, shell)
-                    .await?
+                restructurer.restructure(transcript, mode, cwd, shell).await
             }
             Some(Restructurer::ClaudeCli(restructurer)) => {
                 debug!(mode = ?mode, "Restructuring transcript via claude CLI");
-                restructurer
-                    .restructure(&stt_result.0.transcript, &mode, cwd, shell)
-                    .await?
+                restructurer.restructure(transcript, mode, cwd, shell).await
             }
-            None => {
-                // No restructurer — return raw transcript
-                stt_result.0.transcript.clone()
+            None => Ok(transcript.to_string()),
+        }
+    }
+
+    /// Transcribe audio without restructuring (e.g. a spoken yes/no reply).
+    /// Returns the result and the name of the engine that produced it.
+    pub async fn transcribe(&self, audio_data: &[u8]) -> Result<(SttResult, String), VoiceError> {
+        if !self.config.enabled {
+            return Err(VoiceError::NotAvailable(
+                "Voice input is disabled in config".to_string(),
+            ));
+        }
+        let (result, engine, _) = self.run_stt(audio_data).await?;
+        Ok((result, engine))
+    }
+
+    /// Transcribe a recording of any length (16-bit PCM WAV) as segments
+    /// cut at pauses, skipping silence. Unlike live input this works
+    /// whether or not voice input is enabled, and low-confidence segments
+    /// are kept: the caller has the confidence of each.
+    pub async fn transcribe_recording(
+        &self,
+        audio_data: &[u8],
+    ) -> Result<Vec<Segment>, VoiceError> {
+        let mut segments = Vec::new();
+        for chunk in transcript::split(audio_data)? {
+            let Some(wav) = chunk.wav else { continue };
+            let (result, engine, _) = self.run_stt(&wav).await?;
+            let text = result.transcript.trim();
+            if text.is_empty() {
+                continue;
             }
-        };
+            debug!(start = chunk.start, end = chunk.end, engine = %engine, "Transcribed segment");
+            let words = result
+                .words
+                .iter()
+                .map(|word| Word {
+                    text: word.text.clone(),
+                    start: chunk.start + word.start,
+                    end: chunk.start + word.end,
+                })
+                .collect();
+            segments.push(Segment {
+                start: chunk.start,
+                end: chunk.end,
+                text: text.to_string(),
+                confidence: result.confidence,
+                engine,
+                words,
+                speaker: None,
+            });
+        }
+        Ok(segments)
+    }
 
-        Ok(VoiceResult {
-            transcript: stt_result.0.transcript,
-            output,
-            mode,
-            confidence: stt_result.0.confidence,
-            engine: stt_result.1,
-            latency_ms: start.elapsed().as_millis() as u64,
-        })
+    /// `transcribe_recording` with each segment labeled by speaker. With a
+    /// `diarize_command`, it labels what the STT engines transcribed;
+    /// otherwise the whole recording goes in one request to the first
+    /// engine that can tell speakers apart, so labels hold throughout.
+    pub async fn transcribe_speakers(&self, audio_data: &[u8]) -> Result<Vec<Segment>, VoiceError> {
+        if let Some(ref diarizer) = self.diarizer {
+            let segments = self.transcribe_recording(audio_data).await?;
+            let turns = diarizer.diarize(audio_data).await?;
+            return Ok(label_speakers(segments, &turns));
+        }
+
+        let wav = transcript::mono_wav(audio_data)?;
+        for engine in self.engines.iter().filter(|e| e.is_available()) {
+            match engine.transcribe_speakers(&wav).await {
+                Ok(result) => {
+                    info!(
+                        engine = engine.name(),
+                        segments = result.segments.len(),
+                        "Diarized transcription completed"
+                    );
+                    return Ok(result
+                        .segments
+                        .into_iter()
+                        .map(|s| Segment {
+                            start: s.start,
+                            end: s.end,
+                            text: s.text,
+                            confidence: result.confidence,
+                            engine: engine.name().to_string(),
+                            words: s.words,
+                            speaker: Some(s.speaker),
+                        })
+                        .collect());
+                }
+                Err(VoiceError::NotAvailable(_)) => {}
+                Err(e) => {
+                    warn!(engine = engine.name(), error = %e, "Diarization failed, trying next");
+                }
+            }
+        }
+        Err(VoiceError::NotAvailable(
+            "No STT engine can tell speakers apart. Set deepgram_api_key or diarize_command in [voice] config.".to_string(),
+        ))
     }
 
-    /// Run STT across available engines with failover.
-    async fn run_stt(&self, audio_data: &[u8]) -> Result<(SttResult, String), VoiceError> {
+    /// Run STT across available engines with the configured strategy.
+    /// Returns the result, the engine that produced it, and under `best`
+    /// what every engine heard.
+    async fn run_stt(
+        &self,
+        audio_data: &[u8],
+    ) -> Result<(SttResult, String, Vec<SttCandidate>), VoiceError> {
         if self.engines.is_empty() {
             return Err(VoiceError::NotAvailable(
                 "No STT engines configured. Set deepgram_api_key in [voice] config.".to_string(),
             ));
         }
 
+        let audio_data = prepare_wav(audio_data)?;
+        match self.config.strategy {
+            SttStrategy::Failover => {}
+            SttStrategy::Race => {
+                let threshold = self.config.confidence_threshold;
+                let (result, engine) =
+                    strategy::race(&self.engines, audio_data.into(), threshold).await?;
+                return Ok((result, engine, Vec::new()));
+            }
+            SttStrategy::Best => {
+                return strategy::consensus(&self.engines, audio_data.into()).await;
+            }
+        }
         for engine in &self.engines {
             if !engine.is_available() {
                 continue;
             }
 
-            match engine.transcribe(audio_data).await {
+            match engine.transcribe(&audio_data).await {
                 Ok(result) => {
-                    return Ok((result, engine.name().to_string()));
+                    return Ok((result, engine.name().to_string(), Vec::new()));
                 }
                 Err(e) => {
                     warn!(engine = engine.name(), error = %e, "STT engine failed, trying next");
@@ -266,6 +544,61 @@ impl VoiceEngine {
     }
 }
 
+/// Longest recording accepted for transcription.
+const MAX_AUDIO_SECS: u32 = 120;
+
+/// Check audio received from a client before it reaches an STT engine.
+///
+/// WAV input must be 16-bit integer PCM, mono or stereo, at 8–48kHz and at
+/// most `MAX_AUDIO_SECS` long, and is returned unchanged. Anything without a
+/// RIFF header is taken as raw 16kHz 16-bit little-endian PCM and wrapped in
+/// a WAV header (a trailing odd byte is dropped).
+pub fn prepare_wav(audio_data: &[u8]) -> Result<Vec<u8>, VoiceError> {
+    if !audio_data.starts_with(b"RIFF") {
+        let samples: Vec<i16> = audio_data
+            .chunks_exact(2)
+            .map(|chunk| i16::from_le_bytes([chunk[0], chunk[1]]))
+            .collect();
+        if samples.is_empty() {
+            return Err(VoiceError::CaptureError("Audio is empty".to_string()));
+        }
+        if samples.len() > (MAX_AUDIO_SECS * 16_000) as usize {
+            return Err(VoiceError::CaptureError(format!(
+                "Audio is longer than {MAX_AUDIO_SECS}s"
+            )));
+        }
+        return encode_wav(&samples, 16_000);
+    }
+
+    let reader = hound::WavReader::new(std::io::Cursor::new(audio_data))
+        .map_err(|e| VoiceError::CaptureError(format!("Invalid WAV audio: {e}")))?;
+    let spec = reader.spec();
+    if spec.sample_format != hound::SampleFormat::Int || spec.bits_per_sample != 16 {
+        return Err(VoiceError::CaptureError(format!(
+            "Unsupported WAV format: {}-bit {:?}; expected 16-bit PCM",
+            spec.bits_per_sample, spec.sample_format
+        )));
+    }
+    if !(1..=2).contains(&spec.channels) || !(8_000..=48_000).contains(&spec.sample_rate) {
+        return Err(VoiceError::CaptureError(format!(
+            "Unsupported WAV format: {} channels at {}Hz",
+            spec.channels, spec.sample_rate
+        )));
+    }
+    if reader.duration() / spec.sample_rate > MAX_AUDIO_SECS {
+        return Err(VoiceError::CaptureError(format!(
+            "Audio is longer than {MAX_AUDIO_SECS}s"
+        )));
+    }
+    // The header's length is only a claim; the samples must actually be there
+    if reader.into_samples::<i16>().any(|s| s.is_err()) {
+        return Err(VoiceError::CaptureError(
+            "WAV audio is truncated".to_string(),
+        ));
+    }
+    Ok(audio_data.to_vec())
+}
+
 /// Encode raw PCM audio samples as WAV bytes.
 ///
 /// Useful for converting captured audio to the WAV format expected by STT engines.
@@ -342,6 +675,26 @@ mod tests {
         assert!(wav.len() > 44); // WAV header is 44 bytes
     }
 
+    #[test]
+    fn prepare_wav_checks_headers_and_wraps_raw_pcm() {
+        let wav = encode_wav(&[0, 1, -1, 300], 16_000).unwrap();
+        assert_eq!(prepare_wav(&wav).unwrap(), wav);
+
+        // Raw PCM gets a header; a dangling byte is dropped
+        let wrapped = prepare_wav(&[1, 0, 2, 0, 9]).unwrap();
+        assert!(wrapped.starts_with(b"RIFF"));
+        assert_eq!(hound::WavReader::new(&wrapped[..]).unwrap().len(), 2);
+        assert!(prepare_wav(&[7]).is_err());
+
+        // A header claiming more data than was sent
+        let truncated = &wav[..wav.len() - 4];
+        assert!(prepare_wav(truncated).is_err());
+        assert!(prepare_wav(b"RIFF\xff\xff\xff\xffWAVE").is_err());
+
+        let wav = encode_wav(&[0; 4], 96_000).unwrap();
+        assert!(prepare_wav(&wav).is_err());
+    }
+
     #[tokio::test]
     async fn process_audio_fails_when_disabled() {
         let config = VoiceConfig::default(); // enabled = false
@@ -0,0 +1,142 @@
+//! Progress estimates while a recording is processed.
+//!
+//! STT engines and restructurers don't report progress, so it is estimated
+//! from how long each stage usually takes: transcription about as long as
+//! the audio itself (local Whisper on a laptop CPU), restructuring a few
+//! seconds. The estimate creeps towards the end of its stage and only
+//! reaches it when the stage actually finishes.
+
+use murmur_protocol::VoiceStage;
+use std::future::Future;
+use std::pin::pin;
+use std::time::{Duration, Instant};
+
+/// How often progress is reported during a stage.
+const INTERVAL: Duration = Duration::from_millis(500);
+
+/// Share of the job, in percent, that transcription covers; restructuring
+/// is the rest.
+const TRANSCRIBE_SHARE: u8 = 80;
+
+/// Typical restructuring time.
+const RESTRUCTURE_EXPECTED: Duration = Duration::from_secs(3);
+
+/// Called with the stage, the estimated percent of the whole job done and
+/// the time since processing started.
+pub type ProgressFn<'a> = &'a (dyn Fn(VoiceStage, u8, Duration) + Send + Sync);
+
+/// A progress callback that ignores everything.
+pub fn ignore_progress(_stage: VoiceStage, _percent: u8, _elapsed: Duration) {}
+
+/// Tracks one recording's progress through its stages.
+pub(crate) struct Tracker<'a> {
+    report: ProgressFn<'a>,
+    start: Instant,
+}
+
+impl<'a> Tracker<'a> {
+    pub(crate) fn new(report: ProgressFn<'a>) -> Self {
+        Self {
+            report,
+            start: Instant::now(),
+        }
+    }
+
+    /// Run `stage`'s work, reporting estimated progress until it finishes.
+    /// `audio_secs` sets the expected transcription time.
+    pub(crate) async fn track<T>(
+        &self,
+        stage: VoiceStage,
+        audio_secs: f64,
+        work: impl Future<Output = T>,
+    ) -> T {
+        let (from, to, expected) = match stage {
+            VoiceStage::Transcribing => (
+                0,
+                TRANSCRIBE_SHARE,
+                Duration::from_secs_f64(audio_secs.max(1.0)),
+            ),
+            VoiceStage::Restructuring => (TRANSCRIBE_SHARE, 100, RESTRUCTURE_EXPECTED),
+        };
+        let stage_start = Instant::now();
+        let mut work = pin!(work);
+        let mut ticker = tokio::time::interval(INTERVAL);
+        loop {
+            tokio::select! {
+                output = &mut work => {
+                    (self.report)(stage, to, self.start.elapsed());
+                    return output;
+                }
+                _ = ticker.tick() => {
+                    let percent = estimate(from, to, stage_start.elapsed(), expected);
+                    (self.report)(stage, percent, self.start.elapsed());
+                }
+            }
+        }
+    }
+}
+
+/// Percent done `elapsed` into a stage spanning `from`..`to` that should
+/// take `expected`. Stops short of `to` when the stage overruns.
+fn estimate(from: u8, to: u8, elapsed: Duration, expected: Duration) -> u8 {
+    let fraction = (elapsed.as_secs_f64() / expected.as_secs_f64()).min(0.95);
+    from + (f64::from(to - from) * fraction) as u8
+}
+
+/// Length of a recording in seconds: from the WAV header, or as raw 16kHz
+/// 16-bit PCM like `prepare_wav` takes it. 0 if the header can't be read.
+pub(crate) fn audio_secs(audio: &[u8]) -> f64 {
+    if !audio.starts_with(b"RIFF") {
+        return audio.len() as f64 / 32_000.0;
+    }
+    hound::WavReader::new(std::io::Cursor::new(audio))
+        .map(|reader| f64::from(reader.duration()) / f64::from(reader.spec().sample_rate))
+        .unwrap_or(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn estimate_stays_inside_its_stage() {
+        let expected = Duration::from_secs(10);
+        assert_eq!(estimate(0, 80, Duration::ZERO, expected), 0);
+        assert_eq!(estimate(0, 80, Duration::from_secs(5), expected), 40);
+        assert_eq!(estimate(0, 80, Duration::from_secs(60), expected), 76);
+        assert_eq!(estimate(80, 100, Duration::from_secs(60), expected), 99);
+    }
+
+    #[test]
+    fn audio_secs_reads_the_header() {
+        let wav = crate::encode_wav(&vec![0; 32_000], 16_000).unwrap();
+        assert_eq!(audio_secs(&wav), 2.0);
+        assert_eq!(audio_secs(&[0; 16_000]), 0.5);
+        assert_eq!(audio_secs(b"RIFF but not audio"), 0.0);
+    }
+
+    #[tokio::test]
+    async fn stage_end_is_reported_when_work_finishes() {
+        let reports = Mutex::new(Vec::new());
+        let report = |stage, percent, _elapsed| reports.lock().unwrap().push((stage, percent));
+        let tracker = Tracker::new(&report);
+
+        let output = tracker
+            .track(VoiceStage::Transcribing, 10.0, async {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                "done"
+            })
+            .await;
+        assert_eq!(output, "done");
+        tracker
+            .track(VoiceStage::Restructuring, 10.0, async {})
+            .await;
+
+        let reports = reports.into_inner().unwrap();
+        // The interval's first tick fires at once
+        assert_eq!(reports[0], (VoiceStage::Transcribing, 0));
+        assert_eq!(reports[1], (VoiceStage::Transcribing, 80));
+        assert_eq!(reports.last(), Some(&(VoiceStage::Restructuring, 100)));
+    }
+}
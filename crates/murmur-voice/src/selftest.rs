@@ -0,0 +1,364 @@
+//! Golden-path self-test for the voice pipeline.
+//!
+//! A small corpus of utterances ships with the crate
+//! (`corpus/utterances.json`). Each one is run through every configured STT
+//! engine and then the restructurer, and the results are compared against
+//! expected outputs with a tolerance: a word error rate for transcripts and
+//! prose, a list of accepted spellings for commands.
+//!
+//! Audio comes from a recording (`<id>.wav` in a corpus directory) when
+//! there is one, and is otherwise synthesized with the local TTS command,
+//! so the test runs on any machine with `say` or espeak.
+
+use murmur_protocol::{SelftestCase, VoiceMode, VoiceSelftestReport};
+use serde::Deserialize;
+use std::path::Path;
+use std::time::Instant;
+use tracing::{debug, info};
+
+use crate::{prepare_wav, Speaker, VoiceEngine, VoiceError};
+
+const CORPUS: &str = include_str!("../corpus/utterances.json");
+
+/// Word error rate tolerated when an utterance doesn't set its own.
+const DEFAULT_MAX_WER: f64 = 0.2;
+
+/// A spoken test phrase and what should come out of it.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Utterance {
+    /// Stable ID; recordings are looked up as `<id>.wav`.
+    pub id: String,
+    /// What is said.
+    pub text: String,
+    /// An equally correct transcript, e.g. with numbers as digits.
+    #[serde(default)]
+    pub transcript: Option<String>,
+    /// Restructuring mode to test.
+    pub mode: VoiceMode,
+    /// Accepted restructured outputs.
+    pub expect: Vec<String>,
+    /// Highest acceptable word error rate, for the transcript and for
+    /// natural-mode output.
+    #[serde(default = "default_max_wer")]
+    pub max_wer: f64,
+}
+
+fn default_max_wer() -> f64 {
+    DEFAULT_MAX_WER
+}
+
+/// The bundled utterance corpus.
+pub fn corpus() -> Vec<Utterance> {
+    serde_json::from_str(CORPUS).expect("bundled voice corpus is valid JSON")
+}
+
+impl Utterance {
+    /// Word error rate of `transcript` against the closest expected text.
+    pub fn transcript_error(&self, transcript: &str) -> f64 {
+        std::iter::once(&self.text)
+            .chain(self.transcript.as_ref())
+            .map(|expected| word_error_rate(expected, transcript))
+            .fold(f64::INFINITY, f64::min)
+    }
+
+    /// Commands must match an expected command up to whitespace; prose may
+    /// differ by up to `max_wer`, ignoring case and punctuation.
+    pub fn output_matches(&self, output: &str) -> bool {
+        match self.mode {
+            VoiceMode::Command => {
+                let output = normalize_command(output);
+                self.expect.iter().any(|e| normalize_command(e) == output)
+            }
+            VoiceMode::Natural => self
+                .expect
+                .iter()
+                .any(|e| word_error_rate(e, output) <= self.max_wer),
+        }
+    }
+}
+
+/// Word-level edit distance between `expected` and `actual`, divided by the
+/// number of expected words. Case and punctuation are ignored.
+pub fn word_error_rate(expected: &str, actual: &str) -> f64 {
+    let expected = words(expected);
+    let actual = words(actual);
+    if expected.is_empty() {
+        return if actual.is_empty() { 0.0 } else { 1.0 };
+    }
+    word_distance(&expected, &actual) as f64 / expected.len() as f64
+}
+
+/// Word-level Levenshtein distance.
+pub(crate) fn word_distance(expected: &[String], actual: &[String]) -> usize {
+    // Single row
+    let mut row: Vec<usize> = (0..=actual.len()).collect();
+    for (i, e) in expected.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, a) in actual.iter().enumerate() {
+            let substitution = diagonal + usize::from(e != a);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[actual.len()]
+}
+
+/// Lowercase words with punctuation dropped.
+pub(crate) fn words(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Restructurers sometimes wrap commands in backticks or end them with `;`.
+fn normalize_command(command: &str) -> String {
+    command
+        .trim()
+        .trim_matches('`')
+        .trim_end_matches(';')
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Audio for `utterance` and where it came from: the recording in
+/// `corpus_dir`, or speech synthesized by `speaker`.
+pub async fn load_audio(
+    utterance: &Utterance,
+    corpus_dir: Option<&Path>,
+    speaker: Option<&Speaker>,
+) -> Result<(Vec<u8>, &'static str), VoiceError> {
+    if let Some(path) = corpus_dir
+        .map(|dir| dir.join(format!("{}.wav", utterance.id)))
+        .filter(|path| path.is_file())
+    {
+        let audio = std::fs::read(&path).map_err(|e| {
+            VoiceError::CaptureError(format!("Failed to read {}: {e}", path.display()))
+        })?;
+        return Ok((audio, "recorded"));
+    }
+
+    let speaker = speaker.ok_or_else(|| {
+        VoiceError::NotAvailable("no recording and no TTS command to synthesize one".to_string())
+    })?;
+    let path = std::env::temp_dir().join(format!(
+        "murmur-selftest-{}-{}.wav",
+        std::process::id(),
+        utterance.id
+    ));
+    let result = speaker.synthesize(&utterance.text, &path).await;
+    let audio = result.and_then(|()| {
+        std::fs::read(&path)
+            .map_err(|e| VoiceError::CaptureError(format!("Failed to read synthesized audio: {e}")))
+    });
+    let _ = std::fs::remove_file(&path);
+    Ok((audio?, "synthesized"))
+}
+
+impl VoiceEngine {
+    /// Run the bundled corpus through each available STT engine (or only
+    /// `engine`) and the restructurer. Works whether or not voice input is
+    /// enabled, so a setup can be checked before turning it on.
+    pub async fn selftest(
+        &self,
+        corpus_dir: Option<&Path>,
+        engine: Option<&str>,
+        speaker: Option<&Speaker>,
+    ) -> Result<VoiceSelftestReport, VoiceError> {
+        let engines: Vec<_> = self
+            .engines
+            .iter()
+            .filter(|e| e.is_available())
+            .filter(|e| engine.map_or(true, |name| e.name() == name))
+            .collect();
+        if engines.is_empty() {
+            return Err(VoiceError::NotAvailable(match engine {
+                Some(name) => format!("STT engine '{name}' is not configured"),
+                None => {
+                    "No STT engines configured. Set deepgram_api_key in [voice] config.".to_string()
+                }
+            }));
+        }
+
+        let cwd = std::env::temp_dir().to_string_lossy().into_owned();
+        let mut report = VoiceSelftestReport {
+            restructurer: self.restructurer.as_ref().map(|r| r.name().to_string()),
+            ..Default::default()
+        };
+
+        for utterance in corpus() {
+            let loaded = load_audio(&utterance, corpus_dir, speaker)
+                .await
+                .and_then(|(audio, source)| Ok((prepare_wav(&audio)?, source)));
+            let (audio, source) = match loaded {
+                Ok(loaded) => loaded,
+                Err(e) => {
+                    debug!(utterance = %utterance.id, error = %e, "No audio for utterance");
+                    report.skipped.push(format!("{}: {e}", utterance.id));
+                    continue;
+                }
+            };
+
+            for stt in &engines {
+                let start = Instant::now();
+                let mut case = SelftestCase {
+                    utterance: utterance.id.clone(),
+                    engine: stt.name().to_string(),
+                    audio: source.to_string(),
+                    transcript: String::new(),
+                    word_error_rate: 1.0,
+                    transcript_ok: false,
+                    output: None,
+                    output_ok: None,
+                    error: None,
+                    latency_ms: 0,
+                };
+
+                match stt.transcribe(&audio).await {
+                    Ok(result) => {
+                        case.word_error_rate = utterance.transcript_error(&result.transcript);
+                        case.transcript_ok = case.word_error_rate <= utterance.max_wer;
+                        case.transcript = result.transcript;
+                        if self.restructurer.is_some() {
+                            match self
+                                .restructure(&case.transcript, &utterance.mode, &cwd, None)
+                                .await
+                            {
+                                Ok(output) => {
+                                    case.output_ok = Some(utterance.output_matches(&output));
+                                    case.output = Some(output);
+                                }
+                                Err(e) => case.error = Some(e.to_string()),
+                            }
+                        }
+                    }
+                    Err(e) => case.error = Some(e.to_string()),
+                }
+
+                case.latency_ms = start.elapsed().as_millis() as u64;
+                info!(
+                    utterance = %case.utterance,
+                    engine = %case.engine,
+                    wer = case.word_error_rate,
+                    passed = case.passed(),
+                    "Voice selftest case"
+                );
+                report.cases.push(case);
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{encode_wav, SttEngine, SttResult, VoiceConfig};
+    use async_trait::async_trait;
+
+    /// Hears the same sentence whatever it is given.
+    struct FixedEngine(&'static str);
+
+    #[async_trait]
+    impl SttEngine for FixedEngine {
+        fn name(&self) -> &str {
+            "fixed"
+        }
+
+        fn is_available(&self) -> bool {
+            true
+        }
+
+        async fn transcribe(&self, _audio_data: &[u8]) -> Result<SttResult, VoiceError> {
+            Ok(SttResult {
+                transcript: self.0.to_string(),
+                confidence: 1.0,
+                words: Vec::new(),
+            })
+        }
+    }
+
+    #[test]
+    fn bundled_corpus_is_well_formed() {
+        let corpus = corpus();
+        assert!(corpus.len() >= 5);
+        let mut ids = std::collections::HashSet::new();
+        for utterance in &corpus {
+            assert!(ids.insert(&utterance.id), "duplicate id {}", utterance.id);
+            assert!(
+                !utterance.expect.is_empty(),
+                "{} expects nothing",
+                utterance.id
+            );
+        }
+    }
+
+    #[test]
+    fn word_error_rate_counts_edits_per_expected_word() {
+        assert_eq!(
+            word_error_rate("show the git status", "Show the git status."),
+            0.0
+        );
+        assert_eq!(
+            word_error_rate("show the git status", "show git status"),
+            0.25
+        );
+        assert_eq!(
+            word_error_rate("show the git status", "show the get status"),
+            0.25
+        );
+        assert_eq!(word_error_rate("", ""), 0.0);
+        assert_eq!(word_error_rate("", "noise"), 1.0);
+        assert!(word_error_rate("list docker containers", "") >= 1.0);
+    }
+
+    #[test]
+    fn outputs_are_compared_by_mode() {
+        let corpus = corpus();
+        let status = corpus.iter().find(|u| u.id == "git-status").unwrap();
+        assert!(status.output_matches("`git  status`"));
+        assert!(!status.output_matches("git log"));
+
+        let large = corpus.iter().find(|u| u.id == "large-files").unwrap();
+        assert_eq!(
+            large.transcript_error("find files larger than 100 megabytes"),
+            0.0
+        );
+
+        let prose = corpus.iter().find(|u| u.id == "commit-message").unwrap();
+        assert!(prose.output_matches("fix the crash when the config file is empty"));
+        assert!(!prose.output_matches("Refactor the config loader."));
+    }
+
+    #[tokio::test]
+    async fn runs_recorded_utterances_through_each_engine() {
+        let dir = std::env::temp_dir().join(format!("murmur-corpus-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("git-status.wav"),
+            encode_wav(&[0; 160], 16_000).unwrap(),
+        )
+        .unwrap();
+
+        let mut engine = VoiceEngine::new(VoiceConfig::default());
+        engine.engines = vec![std::sync::Arc::new(FixedEngine("show the git status"))];
+        let report = engine.selftest(Some(&dir), None, None).await.unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // Only the recorded utterance runs without a TTS command
+        assert_eq!(report.cases.len(), 1);
+        let case = &report.cases[0];
+        assert_eq!(case.audio, "recorded");
+        assert!(case.transcript_ok);
+        // No restructurer: the transcript is the output, and isn't checked
+        assert!(case.output.is_none());
+        assert!(case.passed());
+        assert_eq!(report.skipped.len(), corpus().len() - 1);
+
+        assert!(engine.selftest(None, Some("deepgram"), None).await.is_err());
+    }
+}
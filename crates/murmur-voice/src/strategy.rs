@@ -0,0 +1,274 @@
+//! How `VoiceEngine` uses several STT engines for one recording.
+//!
+//! `failover` asks them one at a time in order. `race` sends the audio to
+//! all of them at once and takes the first result confident enough to use,
+//! cancelling the rest. `best` waits for all of them and weighs each
+//! result's confidence against how closely the other engines agree with
+//! it, so one engine confidently mishearing noisy audio is outvoted. Both
+//! pay for every engine that answers.
+
+use crate::selftest::{word_distance, words};
+use crate::{SttEngine, SttResult, VoiceError};
+use murmur_protocol::SttCandidate;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::task::JoinSet;
+use tracing::{debug, warn};
+
+/// `[voice] strategy`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SttStrategy {
+    /// The first engine that answers, in order.
+    #[default]
+    Failover,
+    /// All engines at once; the first result at or above the threshold.
+    Race,
+    /// All engines at once; the result scoring best on confidence and
+    /// agreement with the others.
+    Best,
+}
+
+type Transcriptions = JoinSet<(String, Result<SttResult, VoiceError>)>;
+
+/// Start transcribing `audio` on every available engine.
+fn spawn_all(engines: &[Arc<dyn SttEngine>], audio: Arc<[u8]>) -> Transcriptions {
+    let mut tasks = JoinSet::new();
+    for engine in engines.iter().filter(|e| e.is_available()) {
+        let engine = engine.clone();
+        let audio = audio.clone();
+        tasks.spawn(async move {
+            let result = engine.transcribe(&audio).await;
+            (engine.name().to_string(), result)
+        });
+    }
+    tasks
+}
+
+/// Transcribe `audio` on every available engine concurrently. Return the
+/// first result with at least `enough` confidence and drop the other
+/// requests; if none gets there, the most confident result.
+pub(crate) async fn race(
+    engines: &[Arc<dyn SttEngine>],
+    audio: Arc<[u8]>,
+    enough: f64,
+) -> Result<(SttResult, String), VoiceError> {
+    let mut tasks = spawn_all(engines, audio);
+    let mut best: Option<(SttResult, String)> = None;
+    while let Some(joined) = tasks.join_next().await {
+        let Ok((name, result)) = joined else {
+            continue;
+        };
+        match result {
+            Ok(result) if result.confidence >= enough => {
+                // Dropping the set aborts the engines still transcribing
+                debug!(engine = %name, pending = tasks.len(), "STT race won");
+                return Ok((result, name));
+            }
+            Ok(result) => {
+                if best
+                    .as_ref()
+                    .map_or(true, |(kept, _)| result.confidence > kept.confidence)
+                {
+                    best = Some((result, name));
+                }
+            }
+            Err(e) => warn!(engine = %name, error = %e, "STT engine failed"),
+        }
+    }
+    best.ok_or_else(|| VoiceError::SttError("All STT engines failed".to_string()))
+}
+
+/// Transcribe `audio` on every available engine and wait for all of them.
+/// Each result scores the mean of its confidence and its agreement, the
+/// average word-level similarity of its transcript to the others'; the
+/// highest score wins. Also returns what every engine heard, in engine
+/// order.
+pub(crate) async fn consensus(
+    engines: &[Arc<dyn SttEngine>],
+    audio: Arc<[u8]>,
+) -> Result<(SttResult, String, Vec<SttCandidate>), VoiceError> {
+    let mut tasks = spawn_all(engines, audio);
+    let mut heard = Vec::new();
+    let mut failed = Vec::new();
+    while let Some(joined) = tasks.join_next().await {
+        let Ok((name, result)) = joined else {
+            continue;
+        };
+        match result {
+            Ok(result) => heard.push((name, result)),
+            Err(e) => {
+                warn!(engine = %name, error = %e, "STT engine failed");
+                failed.push(SttCandidate {
+                    engine: name,
+                    transcript: String::new(),
+                    confidence: 0.0,
+                    agreement: 0.0,
+                    error: Some(e.to_string()),
+                });
+            }
+        }
+    }
+
+    let said: Vec<Vec<String>> = heard.iter().map(|(_, r)| words(&r.transcript)).collect();
+    let agreement: Vec<f64> = (0..said.len())
+        .map(|i| {
+            let others: Vec<f64> = (0..said.len())
+                .filter(|&j| j != i)
+                .map(|j| similarity(&said[i], &said[j]))
+                .collect();
+            if others.is_empty() {
+                1.0
+            } else {
+                others.iter().sum::<f64>() / others.len() as f64
+            }
+        })
+        .collect();
+    let score = |i: usize| (heard[i].1.confidence + agreement[i]) / 2.0;
+    let winner = (0..heard.len()).max_by(|&a, &b| score(a).total_cmp(&score(b)));
+
+    let mut candidates: Vec<SttCandidate> = heard
+        .iter()
+        .zip(&agreement)
+        .map(|((name, result), &agreement)| SttCandidate {
+            engine: name.clone(),
+            transcript: result.transcript.clone(),
+            confidence: result.confidence,
+            agreement,
+            error: None,
+        })
+        .chain(failed)
+        .collect();
+    let order = |name: &str| engines.iter().position(|e| e.name() == name);
+    candidates.sort_by_key(|c| order(&c.engine));
+
+    let winner =
+        winner.ok_or_else(|| VoiceError::SttError("All STT engines failed".to_string()))?;
+    let (name, result) = heard.swap_remove(winner);
+    debug!(engine = %name, engines = candidates.len(), "STT consensus picked");
+    Ok((result, name, candidates))
+}
+
+/// 1 minus the word-level edit distance over the longer transcript's
+/// length: 1 for the same words, 0 for nothing in common.
+fn similarity(a: &[String], b: &[String]) -> f64 {
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+    1.0 - word_distance(a, b) as f64 / longest as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use std::time::Duration;
+
+    /// Answers after `delay` with `confidence`, or fails without one.
+    struct TimedEngine {
+        name: &'static str,
+        delay: Duration,
+        confidence: Option<f64>,
+        transcript: Option<&'static str>,
+    }
+
+    #[async_trait]
+    impl SttEngine for TimedEngine {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn is_available(&self) -> bool {
+            true
+        }
+
+        async fn transcribe(&self, _audio_data: &[u8]) -> Result<SttResult, VoiceError> {
+            tokio::time::sleep(self.delay).await;
+            let confidence = self
+                .confidence
+                .ok_or_else(|| VoiceError::SttError("down".to_string()))?;
+            Ok(SttResult {
+                transcript: self.transcript.unwrap_or(self.name).to_string(),
+                confidence,
+                words: Vec::new(),
+            })
+        }
+    }
+
+    fn engine(name: &'static str, ms: u64, confidence: Option<f64>) -> Arc<dyn SttEngine> {
+        Arc::new(TimedEngine {
+            name,
+            delay: Duration::from_millis(ms),
+            confidence,
+            transcript: None,
+        })
+    }
+
+    fn hearing(
+        name: &'static str,
+        transcript: &'static str,
+        confidence: f64,
+    ) -> Arc<dyn SttEngine> {
+        Arc::new(TimedEngine {
+            name,
+            delay: Duration::ZERO,
+            confidence: Some(confidence),
+            transcript: Some(transcript),
+        })
+    }
+
+    #[tokio::test]
+    async fn race_takes_the_first_confident_result() {
+        let engines = [
+            engine("slow", 300, Some(0.99)),
+            engine("unsure", 10, Some(0.3)),
+            engine("fast", 50, Some(0.8)),
+            engine("broken", 5, None),
+        ];
+        let audio: Arc<[u8]> = Arc::from(&b"wav"[..]);
+        let started = std::time::Instant::now();
+        let (result, name) = race(&engines, audio.clone(), 0.5).await.unwrap();
+        assert_eq!(name, "fast");
+        assert_eq!(result.transcript, "fast");
+        assert!(started.elapsed() < Duration::from_millis(300));
+
+        // Nothing confident enough: the best of what came back
+        let (_, name) = race(&engines, audio, 0.999).await.unwrap();
+        assert_eq!(name, "slow");
+    }
+
+    #[tokio::test]
+    async fn consensus_outvotes_a_confident_mishearing() {
+        let engines = [
+            hearing("sure", "get status please", 0.85),
+            hearing("a", "git status please", 0.75),
+            hearing("b", "git status", 0.7),
+            engine("broken", 0, None),
+        ];
+        let (result, name, candidates) = consensus(&engines, Arc::from(&b"wav"[..])).await.unwrap();
+        assert_eq!(name, "a");
+        assert_eq!(result.transcript, "git status please");
+
+        let names: Vec<&str> = candidates.iter().map(|c| c.engine.as_str()).collect();
+        assert_eq!(names, ["sure", "a", "b", "broken"]);
+        assert!(candidates[1].agreement > candidates[0].agreement);
+        assert!(candidates[3].error.is_some());
+
+        // Alone, an engine agrees with itself
+        let (_, name, candidates) = consensus(&engines[..1], Arc::from(&b"wav"[..]))
+            .await
+            .unwrap();
+        assert_eq!(name, "sure");
+        assert_eq!(candidates[0].agreement, 1.0);
+    }
+
+    #[tokio::test]
+    async fn all_engines_failing_is_an_error() {
+        let engines = [engine("a", 0, None), engine("b", 0, None)];
+        let audio: Arc<[u8]> = Arc::from(&b"wav"[..]);
+        let result = race(&engines, audio.clone(), 0.5).await;
+        assert!(matches!(result, Err(VoiceError::SttError(_))));
+        assert!(consensus(&engines, audio).await.is_err());
+    }
+}
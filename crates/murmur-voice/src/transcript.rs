@@ -0,0 +1,410 @@
+//! Transcribing recordings of any length (meetings, voice memos) as timed
+//! segments.
+//!
+//! Live dictation sends one short utterance at a time. A file can run for
+//! an hour, so it is cut into segments of `MIN_SEGMENT_SECS` to
+//! `MAX_SEGMENT_SECS` at the quietest point in each window, which keeps
+//! words whole and gives subtitle-sized cues. Segments that are silent
+//! throughout are never sent to an STT engine.
+
+use serde::Serialize;
+
+use crate::{encode_wav, VoiceError, Word};
+
+/// Shortest segment, unless the recording itself is shorter.
+const MIN_SEGMENT_SECS: f64 = 4.0;
+
+/// Longest segment.
+const MAX_SEGMENT_SECS: f64 = 15.0;
+
+/// Loudness is measured over frames this long.
+const FRAME_SECS: f64 = 0.05;
+
+/// RMS level (16-bit scale) below which a frame counts as silence,
+/// about -50 dBFS.
+const SILENCE_RMS: f64 = 100.0;
+
+/// A transcribed stretch of a recording.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Segment {
+    /// Offset of the segment from the start of the recording, in seconds.
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+    pub confidence: f64,
+    /// STT engine that transcribed it.
+    pub engine: String,
+    /// Word timings, from the start of the recording; empty if the engine
+    /// doesn't report them.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub words: Vec<Word>,
+    /// Who was speaking, numbered from 0, when transcribed with speakers.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speaker: Option<u32>,
+}
+
+/// How a speaker is named in transcripts and subtitles.
+pub fn speaker_label(speaker: u32) -> String {
+    format!("Speaker {}", speaker + 1)
+}
+
+/// A stretch of audio to transcribe.
+pub(crate) struct Chunk {
+    pub start: f64,
+    pub end: f64,
+    /// Mono WAV audio, or `None` if the chunk is silent.
+    pub wav: Option<Vec<u8>>,
+}
+
+/// Decode a 16-bit PCM WAV recording, mixing stereo down to mono.
+/// Returns the samples and sample rate.
+fn decode(audio: &[u8]) -> Result<(Vec<i16>, u32), VoiceError> {
+    let reader = hound::WavReader::new(std::io::Cursor::new(audio)).map_err(|e| {
+        VoiceError::CaptureError(format!(
+            "Not a WAV file ({e}); convert it first, e.g. ffmpeg -i in.m4a -ar 16000 -ac 1 out.wav"
+        ))
+    })?;
+    let spec = reader.spec();
+    if spec.sample_format != hound::SampleFormat::Int || spec.bits_per_sample != 16 {
+        return Err(VoiceError::CaptureError(format!(
+            "Unsupported WAV format: {}-bit {:?}; expected 16-bit PCM",
+            spec.bits_per_sample, spec.sample_format
+        )));
+    }
+    if !(8_000..=48_000).contains(&spec.sample_rate) {
+        return Err(VoiceError::CaptureError(format!(
+            "Unsupported sample rate: {}Hz",
+            spec.sample_rate
+        )));
+    }
+    let samples = reader
+        .into_samples::<i16>()
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| VoiceError::CaptureError(format!("Invalid WAV audio: {e}")))?;
+    let channels = spec.channels.max(1) as usize;
+    let mono: Vec<i16> = samples
+        .chunks(channels)
+        .map(|frame| (frame.iter().map(|&s| s as i32).sum::<i32>() / frame.len() as i32) as i16)
+        .collect();
+    Ok((mono, spec.sample_rate))
+}
+
+/// A whole recording as mono WAV, for engines that take it in one piece.
+pub(crate) fn mono_wav(audio: &[u8]) -> Result<Vec<u8>, VoiceError> {
+    let (mono, sample_rate) = decode(audio)?;
+    encode_wav(&mono, sample_rate)
+}
+
+/// Decode a 16-bit PCM WAV recording (stereo is mixed down) and cut it
+/// into chunks at pauses.
+pub(crate) fn split(audio: &[u8]) -> Result<Vec<Chunk>, VoiceError> {
+    let (mono, sample_rate) = decode(audio)?;
+    let rate = sample_rate as usize;
+    let frame_len = ((rate as f64 * FRAME_SECS) as usize).max(1);
+    let levels: Vec<f64> = mono.chunks(frame_len).map(rms).collect();
+    let min_frames = (MIN_SEGMENT_SECS / FRAME_SECS) as usize;
+    let max_frames = (MAX_SEGMENT_SECS / FRAME_SECS) as usize;
+
+    let mut chunks = Vec::new();
+    let mut first = 0;
+    while first < levels.len() {
+        let last = if levels.len() - first <= max_frames {
+            levels.len()
+        } else {
+            // Cut in the middle of the quietest frame, the earliest on ties
+            let window = first + min_frames..first + max_frames;
+            let quietest = window
+                .clone()
+                .min_by(|&a, &b| levels[a].total_cmp(&levels[b]))
+                .unwrap_or(window.end);
+            quietest + 1
+        };
+        let (start, end) = (first * frame_len, (last * frame_len).min(mono.len()));
+        let silent = levels[first..last].iter().all(|&l| l < SILENCE_RMS);
+        chunks.push(Chunk {
+            start: start as f64 / rate as f64,
+            end: end as f64 / rate as f64,
+            wav: if silent {
+                None
+            } else {
+                Some(encode_wav(&mono[start..end], sample_rate)?)
+            },
+        });
+        first = last;
+    }
+    Ok(chunks)
+}
+
+fn rms(samples: &[i16]) -> f64 {
+    let sum: f64 = samples.iter().map(|&s| (s as f64).powi(2)).sum();
+    (sum / samples.len() as f64).sqrt()
+}
+
+/// Longest caption line; a cue has at most two.
+const MAX_LINE_CHARS: usize = 42;
+
+/// Longest a cue stays on screen.
+const MAX_CUE_SECS: f64 = 6.0;
+
+/// A pause between words this long starts a new cue.
+const CUE_PAUSE_SECS: f64 = 0.8;
+
+/// Subtitle file formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubtitleFormat {
+    /// SubRip (`.srt`).
+    Srt,
+    /// WebVTT (`.vtt`), for browsers and most video players.
+    Vtt,
+}
+
+/// Render segments as subtitles. Segments with word timings are split into
+/// cues of up to two lines that start and end with the speech; others are
+/// one cue each. Speakers are named at the start of their cues, with a
+/// voice tag in WebVTT.
+pub fn subtitles(segments: &[Segment], format: SubtitleFormat) -> String {
+    let mut out = match format {
+        SubtitleFormat::Srt => String::new(),
+        SubtitleFormat::Vtt => "WEBVTT\n\n".to_string(),
+    };
+    for (i, cue) in segments.iter().flat_map(cues).enumerate() {
+        if format == SubtitleFormat::Srt {
+            out.push_str(&format!("{}\n", i + 1));
+        }
+        let speaker = match (cue.speaker.map(speaker_label), format) {
+            (Some(label), SubtitleFormat::Srt) => format!("{label}: "),
+            (Some(label), SubtitleFormat::Vtt) => format!("<v {label}>"),
+            (None, _) => String::new(),
+        };
+        out.push_str(&format!(
+            "{} --> {}\n{speaker}{}\n\n",
+            timestamp(cue.start, format),
+            timestamp(cue.end, format),
+            wrap(&cue.text)
+        ));
+    }
+    out
+}
+
+struct Cue {
+    start: f64,
+    end: f64,
+    text: String,
+    speaker: Option<u32>,
+}
+
+fn cues(segment: &Segment) -> Vec<Cue> {
+    if segment.words.is_empty() {
+        return vec![Cue {
+            start: segment.start,
+            end: segment.end,
+            text: segment.text.clone(),
+            speaker: segment.speaker,
+        }];
+    }
+    let mut cues: Vec<Cue> = Vec::new();
+    let mut open = false;
+    for word in &segment.words {
+        if let Some(cue) = cues.last_mut().filter(|_| open) {
+            let fits = cue.text.len() + 1 + word.text.len() <= 2 * MAX_LINE_CHARS;
+            if fits && word.end - cue.start <= MAX_CUE_SECS && word.start - cue.end < CUE_PAUSE_SECS
+            {
+                cue.text.push(' ');
+                cue.text.push_str(&word.text);
+                cue.end = word.end;
+                open = !word.text.ends_with(['.', '?', '!']);
+                continue;
+            }
+        }
+        cues.push(Cue {
+            start: word.start,
+            end: word.end,
+            text: word.text.clone(),
+            speaker: segment.speaker,
+        });
+        open = !word.text.ends_with(['.', '?', '!']);
+    }
+    cues
+}
+
+/// Break a cue longer than one line at the space nearest its middle.
+fn wrap(text: &str) -> String {
+    if text.len() <= MAX_LINE_CHARS {
+        return text.to_string();
+    }
+    let middle = text.len() / 2;
+    match text
+        .match_indices(' ')
+        .map(|(i, _)| i)
+        .min_by_key(|&i| i.abs_diff(middle))
+    {
+        Some(i) => format!("{}\n{}", &text[..i], &text[i + 1..]),
+        None => text.to_string(),
+    }
+}
+
+/// `HH:MM:SS,mmm` (SRT) or `HH:MM:SS.mmm` (VTT)
+fn timestamp(secs: f64, format: SubtitleFormat) -> String {
+    let ms = (secs * 1000.0).round() as u64;
+    let separator = match format {
+        SubtitleFormat::Srt => ',',
+        SubtitleFormat::Vtt => '.',
+    };
+    format!(
+        "{:02}:{:02}:{:02}{separator}{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        ms % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// WAV audio alternating tone (`true`) and silence, one second each.
+    fn recording(seconds: &[bool], channels: u16) -> Vec<u8> {
+        let mut buffer = std::io::Cursor::new(Vec::new());
+        let spec = hound::WavSpec {
+            channels,
+            sample_rate: 16_000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::new(&mut buffer, spec).unwrap();
+        for &tone in seconds {
+            for i in 0..16_000 {
+                let sample = if tone {
+                    ((i as f64 * 0.1).sin() * 8000.0) as i16
+                } else {
+                    0
+                };
+                for _ in 0..channels {
+                    writer.write_sample(sample).unwrap();
+                }
+            }
+        }
+        writer.finalize().unwrap();
+        buffer.into_inner()
+    }
+
+    #[test]
+    fn long_recordings_are_cut_at_pauses() {
+        // 7s speech, 1s pause, 12s speech
+        let mut seconds = vec![true; 7];
+        seconds.push(false);
+        seconds.extend([true; 12]);
+        let chunks = split(&recording(&seconds, 2)).unwrap();
+        assert_eq!(chunks.len(), 2);
+        assert!((7.0..8.0).contains(&chunks[0].end), "{}", chunks[0].end);
+        assert_eq!(chunks[1].start, chunks[0].end);
+        assert_eq!(chunks[1].end, 20.0);
+        assert!(chunks.iter().all(|c| c.wav.is_some()));
+
+        // Mixed down to mono
+        let wav = chunks[0].wav.as_ref().unwrap();
+        let spec = hound::WavReader::new(std::io::Cursor::new(wav))
+            .unwrap()
+            .spec();
+        assert_eq!(spec.channels, 1);
+    }
+
+    #[test]
+    fn silence_is_not_transcribed() {
+        let mut seconds = vec![false; 16];
+        seconds.extend([true; 3]);
+        let chunks = split(&recording(&seconds, 1)).unwrap();
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks[0].wav.is_none());
+        assert!(chunks[1].wav.is_some());
+
+        assert!(split(b"ID3\x04not a wav").is_err());
+    }
+
+    fn word(text: &str, start: f64, end: f64) -> Word {
+        Word {
+            text: text.to_string(),
+            start,
+            end,
+        }
+    }
+
+    fn segment(start: f64, end: f64, text: &str, words: Vec<Word>) -> Segment {
+        Segment {
+            start,
+            end,
+            text: text.to_string(),
+            confidence: 0.9,
+            engine: "deepgram".to_string(),
+            words,
+            speaker: None,
+        }
+    }
+
+    #[test]
+    fn segments_render_as_subtitles() {
+        let segments = [
+            segment(0.0, 7.45, "Let's start with the roadmap.", vec![]),
+            segment(3725.5, 3730.0, "Thanks, everyone.", vec![]),
+        ];
+        assert_eq!(
+            subtitles(&segments, SubtitleFormat::Srt),
+            "1\n00:00:00,000 --> 00:00:07,450\nLet's start with the roadmap.\n\n\
+             2\n01:02:05,500 --> 01:02:10,000\nThanks, everyone.\n\n"
+        );
+        assert_eq!(
+            subtitles(&segments[1..], SubtitleFormat::Vtt),
+            "WEBVTT\n\n01:02:05.500 --> 01:02:10.000\nThanks, everyone.\n\n"
+        );
+    }
+
+    #[test]
+    fn word_timings_make_shorter_cues() {
+        let words = vec![
+            word("So", 10.2, 10.4),
+            word("the", 10.4, 10.5),
+            word("deploy", 10.5, 10.9),
+            word("failed.", 10.9, 11.3),
+            word("We", 11.4, 11.5),
+            word("rolled", 11.5, 11.8),
+            word("back,", 11.8, 12.1),
+            // A long pause
+            word("then", 13.5, 13.7),
+            word("we", 13.7, 13.8),
+            word("redeployed", 13.8, 14.4),
+            word("the", 14.4, 14.5),
+            word("previous", 14.5, 14.9),
+            word("release", 14.9, 15.3),
+            word("to", 15.3, 15.4),
+            word("every", 15.4, 15.6),
+            word("region.", 15.6, 16.1),
+        ];
+        let text = words.iter().map(|w| w.text.as_str()).collect::<Vec<_>>();
+        let segments = [segment(10.0, 17.0, &text.join(" "), words)];
+        assert_eq!(
+            subtitles(&segments, SubtitleFormat::Vtt),
+            "WEBVTT\n\n\
+             00:00:10.200 --> 00:00:11.300\nSo the deploy failed.\n\n\
+             00:00:11.400 --> 00:00:12.100\nWe rolled back,\n\n\
+             00:00:13.500 --> 00:00:16.100\nthen we redeployed the previous\nrelease to every region.\n\n"
+        );
+    }
+
+    #[test]
+    fn subtitles_name_the_speakers() {
+        let mut segments = [
+            segment(0.0, 2.0, "Ready to ship?", vec![]),
+            segment(2.5, 3.0, "Yes.", vec![]),
+        ];
+        segments[0].speaker = Some(0);
+        segments[1].speaker = Some(1);
+        assert_eq!(
+            subtitles(&segments, SubtitleFormat::Srt),
+            "1\n00:00:00,000 --> 00:00:02,000\nSpeaker 1: Ready to ship?\n\n\
+             2\n00:00:02,500 --> 00:00:03,000\nSpeaker 2: Yes.\n\n"
+        );
+        assert!(subtitles(&segments, SubtitleFormat::Vtt)
+            .ends_with("00:00:02.500 --> 00:00:03.000\n<v Speaker 2>Yes.\n\n"));
+    }
+}
@@ -0,0 +1,269 @@
+//! Text-to-speech output via the platform's speech command.
+//!
+//! Supports macOS `say`, `espeak-ng`/`espeak` and speech-dispatcher's
+//! `spd-say`. A new utterance interrupts the previous one so stale
+//! suggestions are never read out after the user has moved on.
+
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::Mutex;
+use tokio::process::{Child, Command};
+use tracing::debug;
+
+use crate::VoiceError;
+
+/// A speech command-line tool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TtsBackend {
+    /// macOS `say`.
+    Say,
+    /// `espeak-ng`.
+    EspeakNg,
+    /// Classic `espeak`.
+    Espeak,
+    /// speech-dispatcher's `spd-say`.
+    SpdSay,
+}
+
+/// Backends tried by `auto`, in order of preference.
+const AUTO_ORDER: [TtsBackend; 4] = [
+    TtsBackend::Say,
+    TtsBackend::EspeakNg,
+    TtsBackend::Espeak,
+    TtsBackend::SpdSay,
+];
+
+impl TtsBackend {
+    /// Executable name.
+    pub fn program(self) -> &'static str {
+        match self {
+            Self::Say => "say",
+            Self::EspeakNg => "espeak-ng",
+            Self::Espeak => "espeak",
+            Self::SpdSay => "spd-say",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        AUTO_ORDER.into_iter().find(|b| b.program() == name)
+    }
+
+    /// Resolve a configured engine name. `auto` picks the first backend for
+    /// which `exists` returns true.
+    pub fn resolve(engine: &str, exists: impl Fn(&str) -> bool) -> Result<Self, VoiceError> {
+        if engine == "auto" {
+            return AUTO_ORDER
+                .into_iter()
+                .find(|b| exists(b.program()))
+                .ok_or_else(|| {
+                    VoiceError::NotAvailable(
+                        "No TTS command found (tried say, espeak-ng, espeak, spd-say)".to_string(),
+                    )
+                });
+        }
+        let backend = Self::from_name(engine).ok_or_else(|| {
+            VoiceError::NotAvailable(format!(
+                "Unknown TTS engine: {engine}. Use auto, say, espeak-ng, espeak or spd-say"
+            ))
+        })?;
+        if !exists(backend.program()) {
+            return Err(VoiceError::NotAvailable(format!(
+                "TTS command '{}' not found on PATH",
+                backend.program()
+            )));
+        }
+        Ok(backend)
+    }
+}
+
+/// Speaks text through a `TtsBackend`.
+pub struct Speaker {
+    backend: TtsBackend,
+    /// Words per minute (backend default when unset).
+    rate: Option<u32>,
+    /// Backend-specific voice name.
+    voice: Option<String>,
+    current: Mutex<Option<Child>>,
+}
+
+impl Speaker {
+    /// Create a speaker for `engine` ("auto" or a backend program name).
+    pub fn new(engine: &str, rate: Option<u32>, voice: Option<String>) -> Result<Self, VoiceError> {
+        let backend = TtsBackend::resolve(engine, on_path)?;
+        debug!(backend = backend.program(), "TTS backend selected");
+        Ok(Self {
+            backend,
+            rate,
+            voice,
+            current: Mutex::new(None),
+        })
+    }
+
+    pub fn backend(&self) -> TtsBackend {
+        self.backend
+    }
+
+    /// Start speaking `text` without waiting for it to finish, interrupting
+    /// any utterance still in progress.
+    pub fn speak(&self, text: &str) -> Result<(), VoiceError> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Ok(());
+        }
+
+        let child = Command::new(self.backend.program())
+            .args(self.args(text))
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| VoiceError::NotAvailable(format!("Failed to run TTS: {e}")))?;
+
+        let mut current = self.current.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(mut previous) = current.replace(child) {
+            let _ = previous.start_kill();
+        }
+        Ok(())
+    }
+
+    /// Speak `text` and wait until the utterance has finished.
+    pub async fn speak_and_wait(&self, text: &str) -> Result<(), VoiceError> {
+        let status = Command::new(self.backend.program())
+            .args(self.args(text.trim()))
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .await
+            .map_err(|e| VoiceError::NotAvailable(format!("Failed to run TTS: {e}")))?;
+        if !status.success() {
+            return Err(VoiceError::NotAvailable(format!(
+                "{} exited with {status}",
+                self.backend.program()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Render `text` to a WAV file at `path` instead of the speakers.
+    /// `say` writes 16kHz mono 16-bit PCM; espeak writes its native 22kHz.
+    pub async fn synthesize(&self, text: &str, path: &Path) -> Result<(), VoiceError> {
+        let path = path.to_string_lossy().into_owned();
+        let mut args = match self.backend {
+            TtsBackend::Say => vec![
+                "--file-format=WAVE".to_string(),
+                "--data-format=LEI16@16000".to_string(),
+                "-o".to_string(),
+                path,
+            ],
+            TtsBackend::EspeakNg | TtsBackend::Espeak => vec!["-w".to_string(), path],
+            TtsBackend::SpdSay => {
+                return Err(VoiceError::NotAvailable(
+                    "spd-say cannot write audio files".to_string(),
+                ))
+            }
+        };
+        args.extend(self.args(text.trim()));
+
+        let status = Command::new(self.backend.program())
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .await
+            .map_err(|e| VoiceError::NotAvailable(format!("Failed to run TTS: {e}")))?;
+        if !status.success() {
+            return Err(VoiceError::NotAvailable(format!(
+                "{} exited with {status}",
+                self.backend.program()
+            )));
+        }
+        Ok(())
+    }
+
+    fn args(&self, text: &str) -> Vec<String> {
+        let mut args = Vec::new();
+        match self.backend {
+            TtsBackend::Say => {
+                if let Some(rate) = self.rate {
+                    args.extend(["-r".to_string(), rate.to_string()]);
+                }
+                if let Some(ref voice) = self.voice {
+                    args.extend(["-v".to_string(), voice.clone()]);
+                }
+            }
+            TtsBackend::EspeakNg | TtsBackend::Espeak => {
+                if let Some(rate) = self.rate {
+                    args.extend(["-s".to_string(), rate.to_string()]);
+                }
+                if let Some(ref voice) = self.voice {
+                    args.extend(["-v".to_string(), voice.clone()]);
+                }
+            }
+            TtsBackend::SpdSay => {
+                // spd-say takes a relative rate (-100..100), not words per minute
+                if let Some(ref voice) = self.voice {
+                    args.extend(["-y".to_string(), voice.clone()]);
+                }
+            }
+        }
+        // Keep text that starts with a dash from being parsed as an option
+        args.push("--".to_string());
+        args.push(text.to_string());
+        args
+    }
+}
+
+fn on_path(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).any(|dir| is_executable(&dir.join(program))))
+        .unwrap_or(false)
+}
+
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn speaker(backend: TtsBackend, rate: Option<u32>) -> Speaker {
+        Speaker {
+            backend,
+            rate,
+            voice: None,
+            current: Mutex::new(None),
+        }
+    }
+
+    #[test]
+    fn auto_prefers_first_available_backend() {
+        let backend = TtsBackend::resolve("auto", |p| p == "espeak" || p == "spd-say").unwrap();
+        assert_eq!(backend, TtsBackend::Espeak);
+        assert!(TtsBackend::resolve("auto", |_| false).is_err());
+    }
+
+    #[test]
+    fn explicit_engine_must_exist() {
+        assert_eq!(
+            TtsBackend::resolve("say", |_| true).unwrap(),
+            TtsBackend::Say
+        );
+        assert!(TtsBackend::resolve("say", |_| false).is_err());
+        assert!(TtsBackend::resolve("festival", |_| true).is_err());
+    }
+
+    #[test]
+    fn args_include_rate_and_end_of_options() {
+        let args = speaker(TtsBackend::EspeakNg, Some(180)).args("-rf /tmp");
+        assert_eq!(args, vec!["-s", "180", "--", "-rf /tmp"]);
+        let args = speaker(TtsBackend::Say, None).args("hello");
+        assert_eq!(args, vec!["--", "hello"]);
+    }
+}
@@ -0,0 +1,109 @@
+//! Checking Command-mode restructurer output before it's used.
+//!
+//! Restructurers sometimes answer with a command that can't run: a
+//! program that isn't installed, or unbalanced quotes. With a
+//! `CommandValidator` set, a rejected command is restructured once more
+//! with the problem explained; if the retry is rejected too, the
+//! transcript is used as it was heard.
+
+use crate::VoiceError;
+use async_trait::async_trait;
+use std::future::Future;
+use tracing::warn;
+
+/// Decides whether a restructured command can run.
+#[async_trait]
+pub trait CommandValidator: Send + Sync {
+    /// What's wrong with `command` in `cwd` under `shell`, if anything.
+    async fn validate(&self, command: &str, cwd: &str, shell: Option<&str>) -> Result<(), String>;
+}
+
+/// Restructure `transcript` with `restructure`, retrying once with the
+/// validator's complaint and falling back to the transcript.
+pub(crate) async fn checked<F, Fut>(
+    transcript: &str,
+    cwd: &str,
+    shell: Option<&str>,
+    validator: &dyn CommandValidator,
+    restructure: F,
+) -> Result<String, VoiceError>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<String, VoiceError>>,
+{
+    let command = restructure(transcript.to_string()).await?;
+    // A restructurer that gave up returns the transcript; retrying won't help
+    if command.trim() == transcript.trim() {
+        return Ok(command);
+    }
+    let Err(problem) = validator.validate(&command, cwd, shell).await else {
+        return Ok(command);
+    };
+    warn!(command = %command, problem = %problem, "Restructured command rejected, retrying");
+
+    let retry = restructure(with_feedback(transcript, &command, &problem)).await?;
+    match validator.validate(&retry, cwd, shell).await {
+        Ok(()) => Ok(retry),
+        Err(problem) => {
+            warn!(command = %retry, problem = %problem, "Retry rejected too, using the transcript");
+            Ok(transcript.to_string())
+        }
+    }
+}
+
+/// The transcript with the rejected command and why, for the retry.
+fn with_feedback(transcript: &str, rejected: &str, problem: &str) -> String {
+    format!(
+        "{transcript}\n\n(Your previous answer `{rejected}` can't run: {problem}. \
+         Answer with a command that can.)"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Rejects commands that start with `gti`.
+    struct NoTypos;
+
+    #[async_trait]
+    impl CommandValidator for NoTypos {
+        async fn validate(&self, command: &str, _: &str, _: Option<&str>) -> Result<(), String> {
+            match command.starts_with("gti") {
+                true => Err("`gti` isn't installed".to_string()),
+                false => Ok(()),
+            }
+        }
+    }
+
+    /// Answers with `answers` in turn, keeping what it was asked.
+    async fn run(answers: &[&str]) -> (String, Vec<String>) {
+        let answers = Mutex::new(answers.iter().map(|a| a.to_string()).collect::<Vec<_>>());
+        let asked = Mutex::new(Vec::new());
+        let output = checked("git status", "/", Some("zsh"), &NoTypos, |prompt| {
+            asked.lock().unwrap().push(prompt);
+            let answer = answers.lock().unwrap().remove(0);
+            async move { Ok(answer) }
+        })
+        .await
+        .unwrap();
+        (output, asked.into_inner().unwrap())
+    }
+
+    #[tokio::test]
+    async fn rejected_commands_get_one_retry() {
+        let (output, asked) = run(&["git status -s"]).await;
+        assert_eq!(output, "git status -s");
+        assert_eq!(asked.len(), 1);
+
+        let (output, asked) = run(&["gti status", "git status"]).await;
+        assert_eq!(output, "git status");
+        assert!(asked[1].contains("`gti status` can't run: `gti` isn't installed"));
+
+        // Rejected twice: what was said
+        let (output, asked) = run(&["gti status", "gti st"]).await;
+        assert_eq!(output, "git status");
+        assert_eq!(asked.len(), 2);
+    }
+}
@@ -0,0 +1,67 @@
+//! Deepgram transcription against recorded API responses.
+//!
+//! Re-record with `MURMUR_RECORD_CASSETTES=1` and `DEEPGRAM_API_KEY` set.
+
+use murmur_providers::cassette::CassetteServer;
+use murmur_voice::{DeepgramEngine, SttEngine};
+
+async fn deepgram(cassette: &str) -> (CassetteServer, DeepgramEngine) {
+    let path = format!(
+        "{}/tests/cassettes/{cassette}.json",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    let server = CassetteServer::start(path, "https://api.deepgram.com")
+        .await
+        .unwrap();
+    let key = std::env::var("DEEPGRAM_API_KEY").unwrap_or_else(|_| "test-key".to_string());
+    let engine = DeepgramEngine::new(key, "en".to_string())
+        .with_endpoint(format!("{}/v1/listen", server.url()));
+    (server, engine)
+}
+
+/// A short silent WAV; the recorded transcripts don't depend on it.
+fn audio() -> Vec<u8> {
+    let mut audio = Vec::new();
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: 16_000,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::new(std::io::Cursor::new(&mut audio), spec).unwrap();
+    for _ in 0..1600 {
+        writer.write_sample(0i16).unwrap();
+    }
+    writer.finalize().unwrap();
+    audio
+}
+
+#[tokio::test]
+async fn deepgram_transcription() {
+    let (server, engine) = deepgram("deepgram_listen").await;
+    let result = engine.transcribe(&audio()).await.unwrap();
+    assert_eq!(result.transcript, "Show me the git log for the last week.");
+    assert!(result.confidence > 0.99);
+    assert_eq!(result.words.len(), 9);
+    assert_eq!(result.words[0].text, "Show");
+    assert_eq!(result.words[8].text, "week.");
+    assert_eq!((result.words[8].start, result.words[8].end), (1.4, 1.6));
+    assert_eq!(server.remaining(), 0);
+}
+
+#[tokio::test]
+async fn deepgram_diarization() {
+    let (server, engine) = deepgram("deepgram_diarize").await;
+    let result = engine.transcribe_speakers(&audio()).await.unwrap();
+    let turns: Vec<(u32, &str)> = result
+        .segments
+        .iter()
+        .map(|s| (s.speaker, s.text.as_str()))
+        .collect();
+    assert_eq!(turns, [(0, "Ready to ship?"), (1, "Yes, tag it.")]);
+    assert_eq!(
+        (result.segments[1].start, result.segments[1].end),
+        (1.5, 2.4)
+    );
+    assert_eq!(server.remaining(), 0);
+}
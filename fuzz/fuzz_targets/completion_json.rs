@@ -0,0 +1,17 @@
+//! Model output for chat providers (Anthropic, OpenAI, Gemini, OpenRouter,
+//! Ollama): a JSON array of suggestions, possibly wrapped in a markdown
+//! fence.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use murmur_providers::fuzzing::parse_completions;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(text) = std::str::from_utf8(data) else {
+        return;
+    };
+    for item in parse_completions(text) {
+        assert!(item.score <= 1.0);
+    }
+});
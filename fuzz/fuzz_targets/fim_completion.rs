@@ -0,0 +1,17 @@
+//! Codestral fill-in-the-middle output. The input is split at the first
+//! NUL byte into what the user typed and what the model returned.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use murmur_providers::fuzzing::parse_fim_completions;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(data) = std::str::from_utf8(data) else {
+        return;
+    };
+    let (input, text) = data.split_once('\0').unwrap_or(("", data));
+    for item in parse_fim_completions(text, input) {
+        assert!(!item.text.is_empty());
+    }
+});
@@ -0,0 +1,34 @@
+//! A request line as read from the daemon socket.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use murmur_protocol::{
+    CompletionRequest, ContextUpdateRequest, DiagnoseRequest, HistoryAskRequest,
+    HistoryImportRequest, JsonRpcRequest, QueryRequest, VoiceConfirmRequest, VoiceProcessRequest,
+};
+use serde_json::Value;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(request) = serde_json::from_slice::<JsonRpcRequest>(data) else {
+        return;
+    };
+
+    // Anything accepted must survive the round trip through the client
+    let json = serde_json::to_string(&request).unwrap();
+    let again: JsonRpcRequest = serde_json::from_str(&json).unwrap();
+    assert_eq!(again.method, request.method);
+
+    // Handlers parse params into their request type; any of them may be sent
+    if let Some(params) = request.params {
+        let _ = serde_json::from_value::<CompletionRequest>(params.clone());
+        let _ = serde_json::from_value::<VoiceProcessRequest>(params.clone());
+        let _ = serde_json::from_value::<VoiceConfirmRequest>(params.clone());
+        let _ = serde_json::from_value::<ContextUpdateRequest>(params.clone());
+        let _ = serde_json::from_value::<HistoryImportRequest>(params.clone());
+        let _ = serde_json::from_value::<HistoryAskRequest>(params.clone());
+        let _ = serde_json::from_value::<QueryRequest>(params.clone());
+        let _ = serde_json::from_value::<DiagnoseRequest>(params.clone());
+        let _ = serde_json::from_value::<Value>(params);
+    }
+});
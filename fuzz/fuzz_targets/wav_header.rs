@@ -0,0 +1,14 @@
+//! Audio sent to `voice/process` and `voice/confirm`, after base64
+//! decoding: a WAV file or raw PCM.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use murmur_voice::prepare_wav;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(wav) = prepare_wav(data) {
+        // What goes to the STT engines must itself be acceptable
+        assert!(prepare_wav(&wav).is_ok());
+    }
+});
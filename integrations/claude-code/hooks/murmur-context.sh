@@ -18,7 +18,7 @@
 #   }
 # }
 
-SOCKET="/tmp/murmur.sock"
+SOCKET="${MURMUR_SOCKET:-$(murmur socket-path 2>/dev/null)}"
 TIMEOUT=3
 
 # Check if daemon is running
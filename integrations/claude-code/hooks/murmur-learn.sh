@@ -38,7 +38,7 @@ if [ -z "$COMMAND" ]; then
     exit 0
 fi
 
-SOCKET="/tmp/murmur.sock"
+SOCKET="${MURMUR_SOCKET:-$(murmur socket-path 2>/dev/null)}"
 
 # Skip if daemon isn't running
 if [ ! -S "$SOCKET" ]; then
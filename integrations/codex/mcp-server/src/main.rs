@@ -8,10 +8,17 @@
 //! - murmur_status: Get daemon status and active providers
 //! - murmur_record_command: Record a command execution into cross-tool history
 //! - murmur_get_history: Get cross-tool command history
+//! - murmur_pr_draft: Draft a PR description or changelog from commits
+//!
+//! `tools/list` leaves out tools whose daemon method the running daemon
+//! doesn't handle, going by what its `initialize` reports.
 
 use anyhow::Result;
-use murmur_protocol::{JsonRpcRequest, JsonRpcResponse, RequestId};
+use murmur_protocol::{
+    methods, InitializeRequest, InitializeResponse, JsonRpcRequest, JsonRpcResponse, RequestId,
+};
 use serde_json::Value;
+use std::sync::OnceLock;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tracing::debug;
 
@@ -19,6 +26,15 @@ const PROTOCOL_VERSION: &str = "2025-11-25";
 const SERVER_NAME: &str = "murmur-mcp";
 const SERVER_VERSION: &str = env!("CARGO_PKG_VERSION");
 
+/// The daemon method each tool calls.
+const TOOL_METHODS: [(&str, &str); 5] = [
+    ("murmur_complete", methods::COMPLETE),
+    ("murmur_status", methods::STATUS),
+    ("murmur_record_command", methods::CONTEXT_UPDATE),
+    ("murmur_get_history", methods::HISTORY_LIST),
+    ("murmur_pr_draft", methods::SUMMARIZE_COMMITS),
+];
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // Log to stderr only (stdout is for MCP protocol)
@@ -88,7 +104,7 @@ async fn handle_message(raw: &str) -> Option<Value> {
             None
         }
         "tools/list" => {
-            let tools = serde_json::json!({
+            let mut tools = serde_json::json!({
                 "tools": [
                     {
                         "name": "murmur_complete",
@@ -107,6 +123,14 @@ async fn handle_message(raw: &str) -> Option<Value> {
                                 "shell": {
                                     "type": "string",
                                     "description": "Shell type: zsh, bash, or fish"
+                                },
+                                "provider": {
+                                    "type": "string",
+                                    "description": "Ask only this provider (e.g., 'anthropic', 'ollama') instead of the daemon's routing"
+                                },
+                                "model": {
+                                    "type": "string",
+                                    "description": "Model to use instead of the provider's configured one; needs provider"
                                 }
                             },
                             "required": ["input", "cwd"]
@@ -162,9 +186,44 @@ async fn handle_message(raw: &str) -> Option<Value> {
                                 }
                             }
                         }
+                    },
+                    {
+                        "name": "murmur_pr_draft",
+                        "description": "Draft a pull request description, or a changelog section, from the commits in a git range. Uses the commit messages and the diffstat, not the full diff.",
+                        "inputSchema": {
+                            "type": "object",
+                            "properties": {
+                                "range": {
+                                    "type": "string",
+                                    "description": "Commits to describe: 'base..head', 'base...head', or a base ref meaning base..HEAD (e.g., 'main', 'v1.2.0..HEAD')"
+                                },
+                                "cwd": {
+                                    "type": "string",
+                                    "description": "A directory inside the repository"
+                                },
+                                "kind": {
+                                    "type": "string",
+                                    "enum": ["pr", "changelog"],
+                                    "description": "What to write (default: pr)"
+                                }
+                            },
+                            "required": ["range", "cwd"]
+                        }
                     }
                 ]
             });
+            if let (Some(features), Some(list)) =
+                (daemon_features().await, tools["tools"].as_array_mut())
+            {
+                list.retain(|tool| {
+                    let name = tool["name"].as_str().unwrap_or_default();
+                    TOOL_METHODS
+                        .iter()
+                        .find(|(tool, _)| *tool == name)
+                        .and_then(|(_, method)| features.supports(method))
+                        != Some(false)
+                });
+            }
             Some(make_result(tools, id))
         }
         "tools/call" => {
@@ -207,12 +266,16 @@ async fn handle_tool_call(tool_name: &str, arguments: Value) -> Value {
                 .unwrap_or("");
             let cwd = arguments.get("cwd").and_then(|v| v.as_str()).unwrap_or(".");
             let shell = arguments.get("shell").and_then(|v| v.as_str());
+            let provider = arguments.get("provider").and_then(|v| v.as_str());
+            let model = arguments.get("model").and_then(|v| v.as_str());
 
             let params = serde_json::json!({
                 "input": input,
                 "cursor_pos": input.len(),
                 "cwd": cwd,
                 "shell": shell,
+                "provider": provider,
+                "model": model,
             });
 
             match send_to_daemon("complete", Some(params)).await {
@@ -248,15 +311,63 @@ async fn handle_tool_call(tool_name: &str, arguments: Value) -> Value {
                 Err(e) => tool_error(&format!("Failed to connect to Murmur daemon: {e}")),
             }
         }
+        "murmur_pr_draft" => {
+            let params = serde_json::json!({
+                "range": arguments.get("range").and_then(|v| v.as_str()).unwrap_or(""),
+                "cwd": arguments.get("cwd").and_then(|v| v.as_str()).unwrap_or("."),
+                "kind": arguments.get("kind").and_then(|v| v.as_str()).unwrap_or("pr"),
+            });
+
+            match send_to_daemon("summarize/commits", Some(params)).await {
+                Ok(response) => tool_result(response, false),
+                Err(e) => tool_error(&format!("Failed to draft from commits: {e}")),
+            }
+        }
         _ => tool_error(&format!("Unknown tool: {tool_name}")),
     }
 }
 
+/// The daemon socket: `MURMUR_SOCKET`, else what `murmur socket-path`
+/// reports from the user's config. Looked up once.
+fn socket_path() -> Result<&'static str> {
+    static PATH: OnceLock<Option<String>> = OnceLock::new();
+    let path = PATH.get_or_init(|| {
+        if let Some(path) = std::env::var("MURMUR_SOCKET")
+            .ok()
+            .filter(|p| !p.is_empty())
+        {
+            return Some(path);
+        }
+        let output = std::process::Command::new("murmur")
+            .arg("socket-path")
+            .stdin(std::process::Stdio::null())
+            .output()
+            .ok()
+            .filter(|output| output.status.success())?;
+        let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        (!path.is_empty()).then_some(path)
+    });
+    path.as_deref()
+        .ok_or_else(|| anyhow::anyhow!("Can't find the daemon socket; set MURMUR_SOCKET"))
+}
+
+/// What the daemon says it handles. `None` when it isn't running or
+/// predates `initialize`, and then every tool is offered.
+async fn daemon_features() -> Option<InitializeResponse> {
+    let params = InitializeRequest {
+        protocol_version: Some(murmur_protocol::PROTOCOL_VERSION),
+        client: Some(format!("{SERVER_NAME}/{SERVER_VERSION}")),
+        ..Default::default()
+    };
+    let result = send_to_daemon(methods::INITIALIZE, serde_json::to_value(params).ok())
+        .await
+        .ok()?;
+    serde_json::from_value(result).ok()
+}
+
 /// Send a JSON-RPC request to the Murmur daemon via Unix socket.
 async fn send_to_daemon(method: &str, params: Option<Value>) -> Result<Value> {
-    let socket_path = "/tmp/murmur.sock";
-
-    let stream = tokio::net::UnixStream::connect(socket_path).await?;
+    let stream = tokio::net::UnixStream::connect(socket_path()?).await?;
     let (reader, mut writer) = stream.into_split();
 
     let request = JsonRpcRequest::new(method, params, RequestId::Number(1));
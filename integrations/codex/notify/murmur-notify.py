@@ -16,14 +16,28 @@ import json
 import os
 import re
 import socket
+import subprocess
 import sys
 
 
+def socket_path() -> str:
+    """The daemon socket: $MURMUR_SOCKET, else what `murmur socket-path` reports."""
+    if os.environ.get("MURMUR_SOCKET"):
+        return os.environ["MURMUR_SOCKET"]
+    try:
+        result = subprocess.run(
+            ["murmur", "socket-path"], capture_output=True, text=True, timeout=2
+        )
+    except (OSError, subprocess.TimeoutExpired):
+        return ""
+    return result.stdout.strip() if result.returncode == 0 else ""
+
+
 def send_to_murmur(command: str, cwd: str, exit_code: int = 0) -> None:
     """Send a context/update request to the Murmur daemon."""
-    sock_path = os.environ.get("MURMUR_SOCKET", "/tmp/murmur.sock")
+    sock_path = socket_path()
 
-    if not os.path.exists(sock_path):
+    if not sock_path or not os.path.exists(sock_path):
         return
 
     request = json.dumps({
@@ -0,0 +1,24 @@
+#!/bin/bash
+# alfred-murmur-query.sh — Alfred script filter
+#
+# Lists commands for a plain-language request; the selected command is
+# passed on as {query} (connect a "Copy to Clipboard" output to paste it
+# into a terminal).
+#
+# Install: create a workflow with a Script Filter (keyword e.g. "mm",
+# "Argument Required", language /bin/bash, "with input as argv") and use:
+#
+#   /path/to/murmur/integrations/launchers/alfred-murmur-query.sh "$1"
+#
+# Leave "Alfred filters results" off and set a queue delay so it only runs
+# once you stop typing.
+
+export PATH="$HOME/.cargo/bin:/opt/homebrew/bin:/usr/local/bin:$PATH"
+
+if ! output=$(murmur query --alfred --max-items 5 "$1" 2>&1); then
+    # Show the error as a non-actionable item
+    message=$(printf '%s' "$output" | python3 -c "import sys,json; print(json.dumps(sys.stdin.read().strip()))")
+    printf '{"items":[{"title":"murmur failed","subtitle":%s,"valid":false}]}\n' "$message"
+    exit 0
+fi
+echo "$output"
@@ -0,0 +1,28 @@
+#!/bin/bash
+# raycast-murmur-query.sh — Raycast script command
+#
+# Turns a plain-language request into a shell command and copies it to the
+# clipboard, ready to paste into any terminal.
+#
+# Install: copy into your Raycast script commands directory and make it
+# executable. Requires `murmur` on PATH (the daemon is optional: without it
+# the configured providers are called directly).
+#
+# Required parameters:
+# @raycast.schemaVersion 1
+# @raycast.title Murmur Command
+# @raycast.mode compact
+# @raycast.packageName Murmur
+# @raycast.argument1 { "type": "text", "placeholder": "what do you want to do?" }
+#
+# Optional parameters:
+# @raycast.icon 💬
+# @raycast.description Get a shell command from a description
+
+export PATH="$HOME/.cargo/bin:/opt/homebrew/bin:/usr/local/bin:$PATH"
+
+command=$(murmur query "$1" 2>&1) || { echo "murmur: $command"; exit 1; }
+[[ -z "$command" ]] && { echo "No command found"; exit 1; }
+
+printf '%s' "$command" | pbcopy
+echo "Copied: $command"
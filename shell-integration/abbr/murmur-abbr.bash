@@ -0,0 +1,66 @@
+# Murmur abbreviations (bash)
+# Appended by `murmur setup bash --abbr`. See murmur-abbr.zsh: a word in
+# command position that is a murmur abbreviation expands in place on space
+# or Enter. Ctrl-Space inserts a space without expanding.
+#
+# Set MURMUR_ABBR=0 to pause it in the current shell.
+
+MURMUR_ABBR="${MURMUR_ABBR:-1}"
+
+# Known names, space-separated with a space at each end
+_MURMUR_ABBR_NAMES=" "
+_MURMUR_ABBR_LOADED=""
+
+# Learned abbreviations change, so the names are reloaded every ten minutes
+_murmur_abbr_load() {
+    local exit_code=$?
+    if [[ -z "$_MURMUR_ABBR_LOADED" ]] || (( SECONDS - _MURMUR_ABBR_LOADED >= 600 )); then
+        if command -v murmur &>/dev/null && _murmur_is_running; then
+            _MURMUR_ABBR_LOADED=$SECONDS
+            _MURMUR_ABBR_NAMES=" $(murmur abbr list --names 2>/dev/null | tr '\n' ' ')"
+        fi
+    fi
+    return $exit_code
+}
+
+# Replace the word before the cursor if the daemon expands it
+_murmur_abbr_expand() {
+    [[ "$MURMUR_ABBR" == 1 ]] || return 0
+    local left="${READLINE_LINE:0:READLINE_POINT}"
+    local word="${left##*[[:space:];|&(\`]}"
+    [[ -n "$word" && "$_MURMUR_ABBR_NAMES" == *" $word "* ]] || return 0
+
+    local escaped response expanded
+    escaped=$(printf '%s' "$left" | python3 -c "import sys,json; print(json.dumps(sys.stdin.read())[1:-1])" 2>/dev/null)
+    response=$(_murmur_request "abbr/expand" "{\"input\":\"$escaped\",\"cursor_pos\":${#left}}")
+    expanded=$(printf '%s' "$response" | python3 -c "
+import sys, json
+try:
+    result = json.load(sys.stdin)['result']
+    if result['expanded']:
+        sys.stdout.write(result['input'])
+except Exception:
+    pass
+" 2>/dev/null)
+    [[ -n "$expanded" ]] || return 0
+    READLINE_LINE="$expanded${READLINE_LINE:READLINE_POINT}"
+    READLINE_POINT=${#expanded}
+}
+
+_murmur_abbr_insert_space() {
+    READLINE_LINE="${READLINE_LINE:0:READLINE_POINT} ${READLINE_LINE:READLINE_POINT}"
+    (( READLINE_POINT++ ))
+}
+
+_murmur_abbr_space() {
+    _murmur_abbr_expand
+    _murmur_abbr_insert_space
+}
+
+bind -x '" ": _murmur_abbr_space'
+# Enter expands through a spare key sequence, then accepts the line
+bind -x '"\C-x\C-]": _murmur_abbr_expand'
+bind '"\C-m": "\C-x\C-]\C-j"'
+bind -x '"\C-@": _murmur_abbr_insert_space'
+
+PROMPT_COMMAND="_murmur_abbr_load${PROMPT_COMMAND:+;$PROMPT_COMMAND}"
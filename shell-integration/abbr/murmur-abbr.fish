@@ -0,0 +1,25 @@
+# Murmur abbreviations (fish)
+# Appended by `murmur setup fish --abbr`. Fish expands abbreviations itself,
+# so murmur's ([abbreviations] in config.toml, or learned from history) are
+# loaded into `abbr` when the shell starts and every ten minutes after.
+#
+# Set MURMUR_ABBR=0 to stop reloading them in the current shell.
+
+set -q MURMUR_ABBR; or set -g MURMUR_ABBR 1
+set -g _murmur_abbr_loaded
+set -g _murmur_abbr_names
+
+function _murmur_abbr_load --on-event fish_prompt
+    test "$MURMUR_ABBR" = 1; or return
+    if test -n "$_murmur_abbr_loaded"; and test (math (date +%s) - $_murmur_abbr_loaded) -lt 600
+        return
+    end
+    command -q murmur; and _murmur_is_running; or return
+    set -g _murmur_abbr_loaded (date +%s)
+    # Drop the ones loaded last time, in case they were unlearned
+    for name in $_murmur_abbr_names
+        abbr --erase -- $name 2>/dev/null
+    end
+    set -g _murmur_abbr_names (murmur abbr list --names 2>/dev/null)
+    murmur abbr list --fish 2>/dev/null | source
+end
@@ -0,0 +1,210 @@
+# Murmur Bash Integration (keybind mode)
+# Add to ~/.bashrc: eval "$(murmur setup bash --mode keybind)"
+#
+# For bash versions that can't render asynchronous ghost text. Pressing the
+# bound key synchronously asks the daemon for candidates and offers them as a
+# menu; a single candidate replaces the line directly.
+
+MURMUR_SOCKET="${MURMUR_SOCKET:-$(command murmur socket-path 2>/dev/null)}"
+MURMUR_TIMEOUT="${MURMUR_TIMEOUT:-5}"
+
+# Remote daemon (host:port, e.g. via `murmur tunnel`). When set, requests go
+# over TCP instead of the local socket. MURMUR_REMOTE_SSH=user@host lets the
+# plugin start the SSH tunnel automatically when the port isn't reachable.
+MURMUR_REMOTE="${MURMUR_REMOTE:-}"
+MURMUR_REMOTE_SSH="${MURMUR_REMOTE_SSH:-}"
+# API token for a daemon's own TCP listener (`murmur token create`)
+MURMUR_TOKEN="${MURMUR_TOKEN:-}"
+
+# Hostname reported to the daemon so an SSH session gets its own context,
+# history and cache instead of the daemon host's. Set automatically when the
+# shell runs inside an SSH session.
+if [[ -z "${MURMUR_HOST:-}" && -n "${SSH_CONNECTION:-}${SSH_TTY:-}" ]]; then
+    MURMUR_HOST="$(hostname 2>/dev/null)"
+fi
+MURMUR_HOST="${MURMUR_HOST:-}"
+MURMUR_MAX_ITEMS="${MURMUR_MAX_ITEMS:-}"
+MURMUR_MIN_SCORE="${MURMUR_MIN_SCORE:-}"
+MURMUR_KEY="${MURMUR_KEY:-\e\t}"
+
+# Readline settings that make the candidate menu behave like menu-complete
+bind 'set show-all-if-ambiguous on'
+bind 'set completion-ignore-case on'
+bind 'set menu-complete-display-prefix on'
+
+_murmur_is_running() {
+    [[ -n "$MURMUR_REMOTE" ]] || [[ -S "$MURMUR_SOCKET" ]]
+}
+
+# Restart a daemon that crashed (`murmur ensure-running`); succeeds only if
+# it had to be started, so an unanswered request is retried just then
+_murmur_revive() {
+    [[ -z "$MURMUR_REMOTE" ]] && command -v murmur &>/dev/null || return 1
+    [[ -n "$(command murmur ensure-running 2>/dev/null)" ]]
+}
+
+# Portable timeout wrapper (macOS may not have GNU timeout)
+_murmur_timeout() {
+    if command -v timeout &>/dev/null; then
+        timeout "$@"
+    elif command -v gtimeout &>/dev/null; then
+        gtimeout "$@"
+    else
+        shift
+        "$@"
+    fi
+}
+
+_murmur_request() {
+    local request="$1"
+
+    if [[ -n "$MURMUR_REMOTE" ]]; then
+        # A listener that requires a token gets it in `initialize` first
+        if [[ -n "$MURMUR_TOKEN" ]]; then
+            request="{\"jsonrpc\":\"2.0\",\"method\":\"initialize\",\"params\":{\"token\":\"$MURMUR_TOKEN\"},\"id\":0}"$'\n'"$request"
+        fi
+        local response
+        response=$(_murmur_send "$request" | tail -n 1)
+        if [[ -z "$response" && -n "$MURMUR_REMOTE_SSH" ]] && command -v murmur &>/dev/null; then
+            # Remote unreachable — bring up the SSH tunnel and retry once
+            murmur tunnel "$MURMUR_REMOTE_SSH" --ensure &>/dev/null && response=$(_murmur_send "$request" | tail -n 1)
+        fi
+        printf '%s\n' "$response"
+    else
+        local response
+        response=$(_murmur_send "$request")
+        # No answer — restart a crashed daemon and retry once
+        [[ -z "$response" ]] && _murmur_revive && response=$(_murmur_send "$request")
+        printf '%s\n' "$response"
+    fi
+}
+
+# Deliver one request line over the local socket or MURMUR_REMOTE
+_murmur_send() {
+    local request="$1"
+    if command -v socat &>/dev/null; then
+        if [[ -n "$MURMUR_REMOTE" ]]; then
+            echo "$request" | _murmur_timeout "$MURMUR_TIMEOUT" socat - TCP:"$MURMUR_REMOTE" 2>/dev/null
+        else
+            echo "$request" | _murmur_timeout "$MURMUR_TIMEOUT" socat - UNIX-CONNECT:"$MURMUR_SOCKET" 2>/dev/null
+        fi
+    elif command -v nc &>/dev/null; then
+        if [[ -n "$MURMUR_REMOTE" ]]; then
+            echo "$request" | _murmur_timeout "$MURMUR_TIMEOUT" nc "${MURMUR_REMOTE%:*}" "${MURMUR_REMOTE##*:}" 2>/dev/null
+        else
+            echo "$request" | _murmur_timeout "$MURMUR_TIMEOUT" nc -U "$MURMUR_SOCKET" 2>/dev/null
+        fi
+    else
+        # Python3 fallback — pass request via env var to avoid injection
+        MURMUR_REQ="$request" MURMUR_SOCK="$MURMUR_SOCKET" MURMUR_TMO="$MURMUR_TIMEOUT" MURMUR_REMOTE="$MURMUR_REMOTE" python3 -c "
+import socket, os
+timeout = float(os.environ.get('MURMUR_TMO', '5'))
+remote = os.environ.get('MURMUR_REMOTE')
+try:
+    if remote:
+        host, _, port = remote.rpartition(':')
+        sock = socket.create_connection((host, int(port)), timeout=timeout)
+    else:
+        sock = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+        sock.settimeout(timeout)
+        sock.connect(os.environ['MURMUR_SOCK'])
+    sock.sendall((os.environ['MURMUR_REQ'] + '\n').encode())
+    data = b''
+    # One response line per request line sent
+    while data.count(b'\n') <= os.environ['MURMUR_REQ'].count('\n'):
+        chunk = sock.recv(4096)
+        if not chunk:
+            break
+        data += chunk
+    print(data.decode().strip())
+    sock.close()
+except:
+    pass
+" 2>/dev/null
+    fi
+}
+
+# The suggestion last inserted and who made it, for `--record`
+_MURMUR_SUGGESTED=""
+_MURMUR_SUGGESTED_BY=""
+_MURMUR_CANDIDATES_BY=""
+
+# Fill COMPREPLY with daemon candidates for the current line, and
+# _MURMUR_CANDIDATES_BY with the provider that made them.
+_murmur_candidates() {
+    COMPREPLY=()
+    _murmur_is_running || return
+
+    local escaped_input escaped_cwd
+    escaped_input=$(printf '%s' "$READLINE_LINE" | python3 -c "import sys,json; print(json.dumps(sys.stdin.read())[1:-1])" 2>/dev/null)
+    escaped_cwd=$(printf '%s' "$PWD" | python3 -c "import sys,json; print(json.dumps(sys.stdin.read())[1:-1])" 2>/dev/null)
+
+    local params
+    params="{\"input\":\"$escaped_input\",\"cursor_pos\":$READLINE_POINT,\"cwd\":\"$escaped_cwd\",\"shell\":\"bash\""
+    # The session the history hooks use, for per-shell state
+    params+=",\"session_id\":\"bash-$$\""
+    [[ -n "$MURMUR_MAX_ITEMS" ]] && params+=",\"max_items\":$MURMUR_MAX_ITEMS"
+    [[ -n "$MURMUR_MIN_SCORE" ]] && params+=",\"min_score\":$MURMUR_MIN_SCORE"
+    if [[ -n "$MURMUR_HOST" ]]; then
+        # The daemon can't read shell history on another machine
+        local history_json
+        history_json=$(fc -ln -20 2>/dev/null | python3 -c "import sys,json; print(json.dumps([l.strip() for l in sys.stdin if l.strip()]))" 2>/dev/null)
+        params+=",\"host\":\"$MURMUR_HOST\""
+        [[ -n "$history_json" ]] && params+=",\"history\":$history_json"
+    elif [[ -z "${READLINE_LINE// /}" ]]; then
+        # The last commands tell the daemon where you are in a workflow
+        local history_json
+        history_json=$(fc -ln -2 2>/dev/null | python3 -c "import sys,json; print(json.dumps([l.strip() for l in sys.stdin if l.strip()]))" 2>/dev/null)
+        [[ -n "$history_json" ]] && params+=",\"history\":$history_json"
+    fi
+    params+="}"
+
+    local response
+    response=$(_murmur_request "{\"jsonrpc\":\"2.0\",\"method\":\"complete/candidates\",\"params\":$params,\"id\":$RANDOM}")
+    [[ -z "$response" ]] && return
+
+    # Candidates are already single-line, so one per output line is safe;
+    # the provider comes first
+    mapfile -t COMPREPLY < <(printf '%s' "$response" | python3 -c "
+import sys, json
+try:
+    result = json.load(sys.stdin).get('result', {})
+    print(result.get('provider') or 'murmur')
+    for c in result.get('candidates', []):
+        print(c)
+except Exception:
+    pass
+" 2>/dev/null)
+    _MURMUR_CANDIDATES_BY="${COMPREPLY[0]}"
+    COMPREPLY=("${COMPREPLY[@]:1}")
+}
+
+_murmur_keybind() {
+    # An empty line asks for what to run next instead of a completion
+    if ! _murmur_is_running && ! _murmur_revive; then
+        echo "[murmur] daemon not running — start with: murmur start" >&2
+        return
+    fi
+
+    _murmur_candidates
+    local count=${#COMPREPLY[@]}
+    (( count == 0 )) && return
+
+    local choice="${COMPREPLY[0]}"
+    if (( count > 1 )); then
+        local PS3="murmur> " candidate
+        select candidate in "${COMPREPLY[@]}"; do
+            [[ -n "$candidate" ]] && choice="$candidate"
+            break
+        done </dev/tty >&2
+        # Empty selection (Ctrl-D) keeps the original line
+        [[ -z "$candidate" ]] && return
+    fi
+
+    READLINE_LINE="$choice"
+    READLINE_POINT=${#READLINE_LINE}
+    _MURMUR_SUGGESTED="$choice"
+    _MURMUR_SUGGESTED_BY="$_MURMUR_CANDIDATES_BY"
+}
+
+bind -x "\"$MURMUR_KEY\": _murmur_keybind"
@@ -1,11 +1,36 @@
 # Murmur Bash Integration
 # Add to ~/.bashrc: eval "$(murmur setup bash)"
 
-MURMUR_SOCKET="${MURMUR_SOCKET:-/tmp/murmur.sock}"
+MURMUR_SOCKET="${MURMUR_SOCKET:-$(command murmur socket-path 2>/dev/null)}"
 MURMUR_TIMEOUT="${MURMUR_TIMEOUT:-5}"
 
+# Remote daemon (host:port, e.g. via `murmur tunnel`). When set, requests go
+# over TCP instead of the local socket. MURMUR_REMOTE_SSH=user@host lets the
+# plugin start the SSH tunnel automatically when the port isn't reachable.
+MURMUR_REMOTE="${MURMUR_REMOTE:-}"
+MURMUR_REMOTE_SSH="${MURMUR_REMOTE_SSH:-}"
+# API token for a daemon's own TCP listener (`murmur token create`)
+MURMUR_TOKEN="${MURMUR_TOKEN:-}"
+
+# Hostname reported to the daemon so an SSH session gets its own context,
+# history and cache instead of the daemon host's. Set automatically when the
+# shell runs inside an SSH session.
+if [[ -z "${MURMUR_HOST:-}" && -n "${SSH_CONNECTION:-}${SSH_TTY:-}" ]]; then
+    MURMUR_HOST="$(hostname 2>/dev/null)"
+fi
+MURMUR_HOST="${MURMUR_HOST:-}"
+MURMUR_MAX_ITEMS="${MURMUR_MAX_ITEMS:-}"
+MURMUR_MIN_SCORE="${MURMUR_MIN_SCORE:-}"
+
 _murmur_is_running() {
-    [[ -S "$MURMUR_SOCKET" ]]
+    [[ -n "$MURMUR_REMOTE" ]] || [[ -S "$MURMUR_SOCKET" ]]
+}
+
+# Restart a daemon that crashed (`murmur ensure-running`); succeeds only if
+# it had to be started, so an unanswered request is retried just then
+_murmur_revive() {
+    [[ -z "$MURMUR_REMOTE" ]] && command -v murmur &>/dev/null || return 1
+    [[ -n "$(command murmur ensure-running 2>/dev/null)" ]]
 }
 
 # Portable timeout wrapper (macOS may not have GNU timeout)
@@ -32,18 +57,56 @@ _murmur_request() {
         request="{\"jsonrpc\":\"2.0\",\"method\":\"$method\",\"params\":null,\"id\":$id}"
     fi
 
+    if [[ -n "$MURMUR_REMOTE" ]]; then
+        # A listener that requires a token gets it in `initialize` first
+        if [[ -n "$MURMUR_TOKEN" ]]; then
+            request="{\"jsonrpc\":\"2.0\",\"method\":\"initialize\",\"params\":{\"token\":\"$MURMUR_TOKEN\"},\"id\":0}"$'\n'"$request"
+        fi
+        local response
+        response=$(_murmur_send "$request" | tail -n 1)
+        if [[ -z "$response" && -n "$MURMUR_REMOTE_SSH" ]] && command -v murmur &>/dev/null; then
+            # Remote unreachable — bring up the SSH tunnel and retry once
+            murmur tunnel "$MURMUR_REMOTE_SSH" --ensure &>/dev/null && response=$(_murmur_send "$request" | tail -n 1)
+        fi
+        printf '%s\n' "$response"
+    else
+        local response
+        response=$(_murmur_send "$request")
+        # No answer — restart a crashed daemon and retry once
+        [[ -z "$response" ]] && _murmur_revive && response=$(_murmur_send "$request")
+        printf '%s\n' "$response"
+    fi
+}
+
+# Deliver one request line over the local socket or MURMUR_REMOTE
+_murmur_send() {
+    local request="$1"
     if command -v socat &>/dev/null; then
-        echo "$request" | _murmur_timeout "$MURMUR_TIMEOUT" socat - UNIX-CONNECT:"$MURMUR_SOCKET" 2>/dev/null
+        if [[ -n "$MURMUR_REMOTE" ]]; then
+            echo "$request" | _murmur_timeout "$MURMUR_TIMEOUT" socat - TCP:"$MURMUR_REMOTE" 2>/dev/null
+        else
+            echo "$request" | _murmur_timeout "$MURMUR_TIMEOUT" socat - UNIX-CONNECT:"$MURMUR_SOCKET" 2>/dev/null
+        fi
     elif command -v nc &>/dev/null; then
-        echo "$request" | _murmur_timeout "$MURMUR_TIMEOUT" nc -U "$MURMUR_SOCKET" 2>/dev/null
+        if [[ -n "$MURMUR_REMOTE" ]]; then
+            echo "$request" | _murmur_timeout "$MURMUR_TIMEOUT" nc "${MURMUR_REMOTE%:*}" "${MURMUR_REMOTE##*:}" 2>/dev/null
+        else
+            echo "$request" | _murmur_timeout "$MURMUR_TIMEOUT" nc -U "$MURMUR_SOCKET" 2>/dev/null
+        fi
     else
         # Python3 fallback — pass request via env var to avoid injection
-        MURMUR_REQ="$request" MURMUR_SOCK="$MURMUR_SOCKET" MURMUR_TMO="$MURMUR_TIMEOUT" python3 -c "
+        MURMUR_REQ="$request" MURMUR_SOCK="$MURMUR_SOCKET" MURMUR_TMO="$MURMUR_TIMEOUT" MURMUR_REMOTE="$MURMUR_REMOTE" python3 -c "
 import socket, os
-sock = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
-sock.settimeout(float(os.environ.get('MURMUR_TMO', '5')))
+timeout = float(os.environ.get('MURMUR_TMO', '5'))
+remote = os.environ.get('MURMUR_REMOTE')
 try:
-    sock.connect(os.environ['MURMUR_SOCK'])
+    if remote:
+        host, _, port = remote.rpartition(':')
+        sock = socket.create_connection((host, int(port)), timeout=timeout)
+    else:
+        sock = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+        sock.settimeout(timeout)
+        sock.connect(os.environ['MURMUR_SOCK'])
     sock.sendall((os.environ['MURMUR_REQ'] + '\n').encode())
     data = b''
     while True:
@@ -51,19 +114,26 @@ try:
         if not chunk:
             break
         data += chunk
-        if b'\n' in data:
+        # One response line per request line sent
+        if data.count(b'\n') > os.environ['MURMUR_REQ'].count('\n'):
             break
     print(data.decode().strip())
+    sock.close()
 except:
     pass
-finally:
-    sock.close()
 " 2>/dev/null
     fi
 }
 
+# Degradation state whose notice was last shown, so it appears once
+_MURMUR_NOTICE_STATE=""
+
+# The suggestion last inserted and who made it, for `--record`
+_MURMUR_SUGGESTED=""
+_MURMUR_SUGGESTED_BY=""
+
 _murmur_trigger() {
-    if ! _murmur_is_running; then
+    if ! _murmur_is_running && ! _murmur_revive; then
         echo ""
         echo "[murmur] daemon not running — start with: murmur start"
         return
@@ -73,10 +143,7 @@ _murmur_trigger() {
     local cursor="$READLINE_POINT"
     local cwd="$PWD"
 
-    # Skip empty input
-    if [[ -z "${input// /}" ]]; then
-        return
-    fi
+    # An empty prompt asks for what to run next instead of a completion
 
     # Escape for JSON (using python3 for correct handling of all special chars)
     local escaped_input escaped_cwd
@@ -84,22 +151,49 @@ _murmur_trigger() {
     escaped_cwd=$(printf '%s' "$cwd" | python3 -c "import sys,json; print(json.dumps(sys.stdin.read())[1:-1])" 2>/dev/null)
 
     local params
-    params="{\"input\":\"$escaped_input\",\"cursor_pos\":$cursor,\"cwd\":\"$escaped_cwd\",\"shell\":\"bash\"}"
+    params="{\"input\":\"$escaped_input\",\"cursor_pos\":$cursor,\"cwd\":\"$escaped_cwd\",\"shell\":\"bash\""
+    # Naming requests after the shell lets the daemon drop an unanswered one,
+    # and keep per-shell state under the session the history hooks use
+    params+=",\"cancel_token\":\"bash-$$\",\"session_id\":\"bash-$$\""
+    [[ -n "$MURMUR_MAX_ITEMS" ]] && params+=",\"max_items\":$MURMUR_MAX_ITEMS"
+    [[ -n "$MURMUR_MIN_SCORE" ]] && params+=",\"min_score\":$MURMUR_MIN_SCORE"
+    if [[ -n "$MURMUR_HOST" ]]; then
+        # The daemon can't read shell history on another machine
+        local history_json
+        history_json=$(fc -ln -20 2>/dev/null | python3 -c "import sys,json; print(json.dumps([l.strip() for l in sys.stdin if l.strip()]))" 2>/dev/null)
+        params+=",\"host\":\"$MURMUR_HOST\""
+        [[ -n "$history_json" ]] && params+=",\"history\":$history_json"
+    elif [[ -z "${input// /}" ]]; then
+        # The last commands tell the daemon where you are in a workflow;
+        # bash only writes its history file on exit
+        local history_json
+        history_json=$(fc -ln -2 2>/dev/null | python3 -c "import sys,json; print(json.dumps([l.strip() for l in sys.stdin if l.strip()]))" 2>/dev/null)
+        [[ -n "$history_json" ]] && params+=",\"history\":$history_json"
+    fi
+    params+="}"
 
     local response
     response=$(_murmur_request "complete" "$params")
 
     if [[ -z "$response" ]]; then
+        # Timed out: stop the daemon asking providers for an answer nobody sees
+        ( _murmur_request "complete/cancel" "{\"token\":\"bash-$$\"}" &>/dev/null & )
         return
     fi
 
-    # Parse completions from JSON response
+    # Parse completions from JSON response. The first line names the top
+    # suggestion's provider, the second is the degradation notice
+    # ("state<TAB>message"), empty when all is well.
     local completions
     completions=$(echo "$response" | python3 -c "
 import sys, json
 try:
     data = json.load(sys.stdin)
     if 'result' in data and 'items' in data['result']:
+        top = (data['result']['items'] or [{}])[0]
+        print(top.get('provider') or top.get('origin') or 'murmur')
+        notice = data['result'].get('notice')
+        print(f\"{notice['state']}\t{notice['message']}\" if notice else '')
         for item in data['result']['items']:
             desc = item.get('description', '')
             if desc:
@@ -110,6 +204,24 @@ except:
     pass
 " 2>/dev/null)
 
+    local suggested_by="${completions%%$'\n'*}"
+    completions="${completions#*$'\n'}"
+    local notice="${completions%%$'\n'*}"
+    if [[ "$completions" == *$'\n'* ]]; then
+        completions="${completions#*$'\n'}"
+    else
+        completions=""
+    fi
+
+    # Explain once why suggestions got worse; forget it when things recover
+    if [[ "${notice%%$'\t'*}" != "$_MURMUR_NOTICE_STATE" ]]; then
+        _MURMUR_NOTICE_STATE="${notice%%$'\t'*}"
+        if [[ -n "$notice" ]]; then
+            echo ""
+            echo "[murmur] ${notice#*$'\t'}"
+        fi
+    fi
+
     if [[ -z "$completions" ]]; then
         return
     fi
@@ -125,14 +237,18 @@ except:
         READLINE_LINE="$text"
         READLINE_POINT=${#READLINE_LINE}
     elif (( ${#items[@]} > 1 )); then
-        # Multiple completions — display them
-        echo ""
-        printf '%s\n' "${items[@]}"
+        # Multiple completions — use the terminal layer if loaded, else list them
+        if ! declare -F _murmur_render >/dev/null || ! _murmur_render "$completions"; then
+            echo ""
+            printf '%s\n' "${items[@]}"
+        fi
         # Insert the first one
         local text="${items[0]%%	*}"
         READLINE_LINE="$text"
         READLINE_POINT=${#READLINE_LINE}
     fi
+    _MURMUR_SUGGESTED="$READLINE_LINE"
+    _MURMUR_SUGGESTED_BY="$suggested_by"
 }
 
 # Bind to Option+Tab (Alt+Tab) — dedicated AI completion key
@@ -0,0 +1,59 @@
+# Murmur post-command diagnosis hook (bash)
+# Appended by `murmur setup bash --diagnose`. When a command exits non-zero,
+# the daemon is asked in the background why it failed. Bash can't redraw
+# the prompt from a background job, so the one-line answer is printed above
+# the first prompt after it arrives. Ctrl-C and Ctrl-Z are ignored; the
+# daemon caches answers per command and error and rate-limits provider
+# requests ([diagnose] in config.toml).
+#
+# Set MURMUR_DIAGNOSE=0 to pause it in the current shell.
+
+MURMUR_DIAGNOSE="${MURMUR_DIAGNOSE:-1}"
+MURMUR_DIAGNOSE_TIMEOUT="${MURMUR_DIAGNOSE_TIMEOUT:-20}"
+
+_MURMUR_DIAGNOSE_HISTNUM=""
+
+# Answers wait in a directory only this user can open, so nobody else can
+# plant one or point its path somewhere else
+if _MURMUR_DIAGNOSE_DIR=$(mktemp -d "${TMPDIR:-/tmp}/murmur-diagnose.XXXXXX"); then
+    _MURMUR_DIAGNOSE_FILE="$_MURMUR_DIAGNOSE_DIR/answer"
+    # Keep any EXIT trap already set
+    eval "_murmur_diagnose_trap=($(trap -p EXIT))"
+    trap "rm -rf '$_MURMUR_DIAGNOSE_DIR'${_murmur_diagnose_trap[2]:+; ${_murmur_diagnose_trap[2]}}" EXIT
+    unset _murmur_diagnose_trap
+else
+    MURMUR_DIAGNOSE=0
+fi
+
+_murmur_diagnose_prompt() {
+    local exit_code=$?
+    if [[ -n "$_MURMUR_DIAGNOSE_FILE" && -s "$_MURMUR_DIAGNOSE_FILE" ]]; then
+        cat "$_MURMUR_DIAGNOSE_FILE"
+        rm -f "$_MURMUR_DIAGNOSE_FILE"
+    fi
+
+    # The history number tells a new command from an empty line, which
+    # keeps the last exit code
+    local entry
+    entry=$(HISTTIMEFORMAT= history 1)
+    [[ "$entry" =~ ^\ *([0-9]+)\*?\ +(.*)$ ]] || return $exit_code
+    local histnum="${BASH_REMATCH[1]}" cmd="${BASH_REMATCH[2]}"
+    if [[ -z "$_MURMUR_DIAGNOSE_HISTNUM" || "$histnum" == "$_MURMUR_DIAGNOSE_HISTNUM" ]]; then
+        _MURMUR_DIAGNOSE_HISTNUM="$histnum"
+        return $exit_code
+    fi
+    _MURMUR_DIAGNOSE_HISTNUM="$histnum"
+
+    if [[ "$MURMUR_DIAGNOSE" == 1 && -n "$_MURMUR_DIAGNOSE_FILE" ]] && (( exit_code != 0 )) \
+        && command -v murmur &>/dev/null && _murmur_is_running; then
+        # Subshell keeps the job out of the job table; write-then-rename so
+        # a half-written answer is never shown
+        ( _murmur_timeout "$MURMUR_DIAGNOSE_TIMEOUT" murmur diagnose --exit-code "$exit_code" \
+            --shell bash --host "$MURMUR_HOST" -- "$cmd" >"$_MURMUR_DIAGNOSE_FILE.tmp" 2>/dev/null \
+            && mv -f "$_MURMUR_DIAGNOSE_FILE.tmp" "$_MURMUR_DIAGNOSE_FILE" & )
+    fi
+    return $exit_code
+}
+
+# First, so it sees the exit code of the command rather than of other hooks
+PROMPT_COMMAND="_murmur_diagnose_prompt${PROMPT_COMMAND:+;$PROMPT_COMMAND}"
@@ -0,0 +1,47 @@
+# Murmur post-command diagnosis hook (fish)
+# Appended by `murmur setup fish --diagnose`. See murmur-diagnose.bash; the
+# answer is printed above the first prompt after it arrives.
+#
+# Set MURMUR_DIAGNOSE=0 to pause it in the current shell.
+
+set -q MURMUR_DIAGNOSE; or set -g MURMUR_DIAGNOSE 1
+set -q MURMUR_DIAGNOSE_TIMEOUT; or set -g MURMUR_DIAGNOSE_TIMEOUT 20
+
+# Answers wait in a directory only this user can open, so nobody else can
+# plant one or point its path somewhere else
+set -l _murmur_tmp /tmp
+set -q TMPDIR; and set _murmur_tmp $TMPDIR
+if set -g _murmur_diagnose_dir (mktemp -d $_murmur_tmp/murmur-diagnose.XXXXXX)
+    set -g _murmur_diagnose_file $_murmur_diagnose_dir/answer
+else
+    set -g MURMUR_DIAGNOSE 0
+end
+
+function _murmur_diagnose_cleanup --on-event fish_exit
+    set -q _murmur_diagnose_dir; and rm -rf $_murmur_diagnose_dir
+end
+
+function _murmur_diagnose_postexec --on-event fish_postexec
+    set -l exit_code $status
+    test "$MURMUR_DIAGNOSE" = 1; and set -q _murmur_diagnose_file; and test $exit_code -ne 0; or return
+    string length -q -- (string trim -- "$argv[1]"); or return
+    command -q murmur; and _murmur_is_running; or return
+
+    # Functions can't run in the background; hand the job to sh
+    set -l timeout_cmd
+    if command -q timeout
+        set timeout_cmd timeout $MURMUR_DIAGNOSE_TIMEOUT
+    else if command -q gtimeout
+        set timeout_cmd gtimeout $MURMUR_DIAGNOSE_TIMEOUT
+    end
+    sh -c '"$@" >"$0.tmp" 2>/dev/null && mv -f "$0.tmp" "$0"' $_murmur_diagnose_file \
+        $timeout_cmd murmur diagnose --exit-code $exit_code --shell fish --host "$MURMUR_HOST" -- $argv[1] &
+    disown
+end
+
+function _murmur_diagnose_show --on-event fish_prompt
+    if set -q _murmur_diagnose_file; and test -s $_murmur_diagnose_file
+        cat $_murmur_diagnose_file
+        rm -f $_murmur_diagnose_file
+    end
+end
@@ -1,11 +1,38 @@
 # Murmur Fish Integration
 # Add to ~/.config/fish/config.fish: murmur setup fish | source
 
-set -g MURMUR_SOCKET /tmp/murmur.sock
+set -q MURMUR_SOCKET; or set -g MURMUR_SOCKET (command murmur socket-path 2>/dev/null)
 set -g MURMUR_TIMEOUT 5
+# Remote daemon (host:port, e.g. via `murmur tunnel`); MURMUR_REMOTE_SSH=user@host
+# starts the SSH tunnel automatically when the port isn't reachable.
+set -q MURMUR_REMOTE; or set -g MURMUR_REMOTE ""
+set -q MURMUR_REMOTE_SSH; or set -g MURMUR_REMOTE_SSH ""
+# API token for a daemon's own TCP listener (`murmur token create`)
+set -q MURMUR_TOKEN; or set -g MURMUR_TOKEN ""
+
+# Hostname reported to the daemon so an SSH session gets its own context,
+# history and cache. Set automatically inside SSH sessions.
+if not set -q MURMUR_HOST
+    if set -q SSH_CONNECTION; or set -q SSH_TTY
+        set -g MURMUR_HOST (hostname 2>/dev/null)
+    else
+        set -g MURMUR_HOST ""
+    end
+end
+# Optional per-client limits (unset = use daemon [completion] config)
+set -q MURMUR_MAX_ITEMS; or set -g MURMUR_MAX_ITEMS ""
+set -q MURMUR_MIN_SCORE; or set -g MURMUR_MIN_SCORE ""
 
 function _murmur_is_running
-    test -S $MURMUR_SOCKET
+    test -n "$MURMUR_REMOTE"; or test -S $MURMUR_SOCKET
+end
+
+# Restart a daemon that crashed (`murmur ensure-running`); succeeds only if
+# it had to be started, so an unanswered request is retried just then
+function _murmur_revive
+    test -z "$MURMUR_REMOTE"; and command -q murmur; or return 1
+    set -l started (command murmur ensure-running 2>/dev/null)
+    test -n "$started"
 end
 
 # Portable timeout wrapper (macOS may not have GNU timeout)
@@ -32,21 +59,62 @@ function _murmur_request
         set request "{\"jsonrpc\":\"2.0\",\"method\":\"$method\",\"params\":null,\"id\":$id}"
     end
 
+    if test -n "$MURMUR_REMOTE"
+        # A listener that requires a token gets it in `initialize` first
+        if test -n "$MURMUR_TOKEN"
+            set -l init "{\"jsonrpc\":\"2.0\",\"method\":\"initialize\",\"params\":{\"token\":\"$MURMUR_TOKEN\"},\"id\":0}"
+            set request (string join \n $init $request | string collect)
+        end
+        set -l response (_murmur_send $request | tail -n 1)
+        if test -z "$response"; and test -n "$MURMUR_REMOTE_SSH"; and command -q murmur
+            # Remote unreachable — bring up the SSH tunnel and retry once
+            murmur tunnel $MURMUR_REMOTE_SSH --ensure &>/dev/null; and set response (_murmur_send $request | tail -n 1)
+        end
+        printf '%s\n' $response
+    else
+        set -l response (_murmur_send $request)
+        # No answer — restart a crashed daemon and retry once
+        if test -z "$response"; and _murmur_revive
+            set response (_murmur_send $request)
+        end
+        printf '%s\n' $response
+    end
+end
+
+# Deliver one request line over the local socket or MURMUR_REMOTE
+function _murmur_send
+    set -l request $argv[1]
     if command -v socat &>/dev/null
-        echo $request | _murmur_timeout $MURMUR_TIMEOUT socat - UNIX-CONNECT:$MURMUR_SOCKET 2>/dev/null
+        if test -n "$MURMUR_REMOTE"
+            echo $request | _murmur_timeout $MURMUR_TIMEOUT socat - TCP:$MURMUR_REMOTE 2>/dev/null
+        else
+            echo $request | _murmur_timeout $MURMUR_TIMEOUT socat - UNIX-CONNECT:$MURMUR_SOCKET 2>/dev/null
+        end
     else if command -v nc &>/dev/null
-        echo $request | _murmur_timeout $MURMUR_TIMEOUT nc -U $MURMUR_SOCKET 2>/dev/null
+        if test -n "$MURMUR_REMOTE"
+            set -l parts (string split -r -m1 : $MURMUR_REMOTE)
+            echo $request | _murmur_timeout $MURMUR_TIMEOUT nc $parts[1] $parts[2] 2>/dev/null
+        else
+            echo $request | _murmur_timeout $MURMUR_TIMEOUT nc -U $MURMUR_SOCKET 2>/dev/null
+        end
     else
         # Python3 fallback — pass request via env var to avoid injection
         set -x MURMUR_REQ $request
         set -x MURMUR_SOCK $MURMUR_SOCKET
         set -x MURMUR_TMO $MURMUR_TIMEOUT
+        set -lx MURMUR_REMOTE $MURMUR_REMOTE
         python3 -c "
 import socket, os
-sock = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
-sock.settimeout(float(os.environ.get('MURMUR_TMO', '5')))
+timeout = float(os.environ.get('MURMUR_TMO', '5'))
+remote = os.environ.get('MURMUR_REMOTE')
 try:
-    sock.connect(os.environ['MURMUR_SOCK'])
+    if remote:
+        host, _, port = remote.rpartition(':')
+        sock = socket.create_connection((host, int(port)), timeout=timeout)
+    else:
+        sock = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+        sock.settimeout(timeout)
+        sock.connect(os.environ['MURMUR_SOCK'])
     sock.sendall((os.environ['MURMUR_REQ'] + '\n').encode())
     data = b''
     while True:
@@ -54,13 +122,13 @@ try:
         if not chunk:
             break
         data += chunk
-        if b'\\n' in data:
+        # One response line per request line sent
+        if data.count(b'\\n') > os.environ['MURMUR_REQ'].count('\\n'):
             break
     print(data.decode().strip())
+    sock.close()
 except:
     pass
-finally:
-    sock.close()
 " 2>/dev/null
         set -e MURMUR_REQ
         set -e MURMUR_SOCK
@@ -69,7 +137,7 @@ finally:
 end
 
 function _murmur_trigger
-    if not _murmur_is_running
+    if not _murmur_is_running; and not _murmur_revive
         echo "[murmur] daemon not running — start with: murmur start"
         commandline -f repaint
         return
@@ -79,39 +147,74 @@ function _murmur_trigger
     set -l cursor (commandline -C)
     set -l cwd (pwd)
 
-    # Skip empty input
-    if test -z (string trim "$input")
-        return
-    end
+    # An empty prompt asks for what to run next instead of a completion
 
     # Escape for JSON (using python3 for correct handling of all special chars)
     set -l escaped_input (printf '%s' $input | python3 -c "import sys,json; print(json.dumps(sys.stdin.read())[1:-1])" 2>/dev/null)
     set -l escaped_cwd (printf '%s' $cwd | python3 -c "import sys,json; print(json.dumps(sys.stdin.read())[1:-1])" 2>/dev/null)
 
-    set -l params "{\"input\":\"$escaped_input\",\"cursor_pos\":$cursor,\"cwd\":\"$escaped_cwd\",\"shell\":\"fish\"}"
+    set -l params "{\"input\":\"$escaped_input\",\"cursor_pos\":$cursor,\"cwd\":\"$escaped_cwd\",\"shell\":\"fish\""
+    # Naming requests after the shell lets the daemon drop an unanswered one,
+    # and keep per-shell state under the session the history hooks use
+    set params "$params,\"cancel_token\":\"fish-$fish_pid\",\"session_id\":\"fish-$fish_pid\""
+    test -n "$MURMUR_MAX_ITEMS"; and set params "$params,\"max_items\":$MURMUR_MAX_ITEMS"
+    test -n "$MURMUR_MIN_SCORE"; and set params "$params,\"min_score\":$MURMUR_MIN_SCORE"
+    if test -n "$MURMUR_HOST"
+        # The daemon can't read shell history on another machine; fish lists newest first
+        set -l history_json (history --max 20 2>/dev/null | python3 -c "import sys,json; print(json.dumps([l.strip() for l in reversed(sys.stdin.readlines()) if l.strip()]))" 2>/dev/null)
+        set params "$params,\"host\":\"$MURMUR_HOST\""
+        test -n "$history_json"; and set params "$params,\"history\":$history_json"
+    else if test -z (string trim -- "$input")
+        # The last commands tell the daemon where you are in a workflow
+        set -l history_json (history --max 2 2>/dev/null | python3 -c "import sys,json; print(json.dumps([l.strip() for l in reversed(sys.stdin.readlines()) if l.strip()]))" 2>/dev/null)
+        test -n "$history_json"; and set params "$params,\"history\":$history_json"
+    end
+    set params "$params}"
 
     set -l response (_murmur_request "complete" $params)
 
     if test -z "$response"
+        # Timed out: stop the daemon asking providers for an answer nobody sees
+        _murmur_request "complete/cancel" "{\"token\":\"fish-$fish_pid\"}" &>/dev/null
         return
     end
 
-    # Parse completions and insert the first one
-    set -l completion (echo $response | python3 -c "
+    # Parse the degradation notice ("state<TAB>message", empty when all is
+    # well), the first completion and its provider
+    set -l lines (echo $response | python3 -c "
 import sys, json
 try:
     data = json.load(sys.stdin)
     if 'result' in data and 'items' in data['result']:
+        notice = data['result'].get('notice')
+        print(f\"{notice['state']}\t{notice['message']}\" if notice else '')
         items = data['result']['items']
         if items:
             print(items[0]['text'])
+            print(items[0].get('provider') or items[0].get('origin') or 'murmur')
 except:
     pass
 " 2>/dev/null)
+    set -l notice $lines[1]
+    set -l completion $lines[2]
+    set -l suggested_by $lines[3]
+
+    # Explain once why suggestions got worse; forget it when things recover
+    set -l state (string split -m 1 \t -- "$notice")[1]
+    if test "$state" != "$_murmur_notice_state"
+        set -g _murmur_notice_state $state
+        if test -n "$notice"
+            echo
+            echo "[murmur] "(string split -m 1 \t -- "$notice")[2]
+            commandline -f repaint
+        end
+    end
 
     if test -n "$completion"
         commandline -r -- $completion
         commandline -C (string length "$completion")
+        set -g _murmur_suggested $completion
+        set -g _murmur_suggested_by $suggested_by
     end
 end
 
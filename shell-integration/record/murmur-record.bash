@@ -0,0 +1,41 @@
+# Murmur command recording hook (bash)
+# Appended by `murmur setup bash --record`. Each command is added to
+# murmur's history when it finishes, with its exit code and shell session.
+# A command run unchanged from a suggestion murmur inserted is marked with
+# the provider that made it, which tells AI-assisted commands from typed
+# ones and counts as the suggestion being accepted when ranking.
+#
+# Set MURMUR_RECORD=0 to pause it in the current shell.
+
+MURMUR_RECORD="${MURMUR_RECORD:-1}"
+
+_MURMUR_RECORD_HISTNUM=""
+
+_murmur_record_prompt() {
+    local exit_code=$?
+
+    # The history number tells a new command from an empty line
+    local entry
+    entry=$(HISTTIMEFORMAT= history 1)
+    [[ "$entry" =~ ^\ *([0-9]+)\*?\ +(.*)$ ]] || return $exit_code
+    local histnum="${BASH_REMATCH[1]}" cmd="${BASH_REMATCH[2]}"
+    if [[ -z "$_MURMUR_RECORD_HISTNUM" || "$histnum" == "$_MURMUR_RECORD_HISTNUM" ]]; then
+        _MURMUR_RECORD_HISTNUM="$histnum"
+        return $exit_code
+    fi
+    _MURMUR_RECORD_HISTNUM="$histnum"
+
+    local suggested_by=""
+    [[ "$cmd" == "$_MURMUR_SUGGESTED" ]] && suggested_by="$_MURMUR_SUGGESTED_BY"
+    _MURMUR_SUGGESTED="" _MURMUR_SUGGESTED_BY=""
+
+    if [[ "$MURMUR_RECORD" == 1 ]] && command -v murmur &>/dev/null && _murmur_is_running; then
+        # Subshell keeps the job out of the job table
+        ( murmur history record --exit-code "$exit_code" --shell bash --session "bash-$$" \
+            --host "$MURMUR_HOST" --suggested-by "$suggested_by" -- "$cmd" &>/dev/null & )
+    fi
+    return $exit_code
+}
+
+# First, so it sees the exit code of the command rather than of other hooks
+PROMPT_COMMAND="_murmur_record_prompt${PROMPT_COMMAND:+;$PROMPT_COMMAND}"
@@ -0,0 +1,21 @@
+# Murmur command recording hook (fish)
+# Appended by `murmur setup fish --record`. See murmur-record.zsh.
+#
+# Set MURMUR_RECORD=0 to pause it in the current shell.
+
+set -q MURMUR_RECORD; or set -g MURMUR_RECORD 1
+
+function _murmur_record_postexec --on-event fish_postexec
+    set -l exit_code $status
+    string length -q -- (string trim -- "$argv[1]"); or return
+    set -l suggested_by
+    test "$argv[1]" = "$_murmur_suggested"; and set suggested_by $_murmur_suggested_by
+    set -g _murmur_suggested
+    set -g _murmur_suggested_by
+    test "$MURMUR_RECORD" = 1; or return
+    command -q murmur; and _murmur_is_running; or return
+
+    murmur history record --exit-code $exit_code --shell fish --session fish-$fish_pid \
+        --host "$MURMUR_HOST" --suggested-by "$suggested_by" -- $argv[1] &>/dev/null &
+    disown
+end
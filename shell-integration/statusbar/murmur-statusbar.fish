@@ -0,0 +1,10 @@
+# Murmur status bar component (fish)
+# Appended by `murmur setup fish --statusbar`. See murmur-statusbar.sh for
+# terminal configuration.
+
+if status is-interactive; and not set -q MURMUR_STATUSBAR_STARTED; and command -q murmur
+    set -gx MURMUR_STATUSBAR_STARTED 1
+    set -q MURMUR_STATUSBAR_FORMAT; or set -l MURMUR_STATUSBAR_FORMAT auto
+    murmur statusbar --format $MURMUR_STATUSBAR_FORMAT >/dev/tty 2>/dev/null &
+    disown
+end
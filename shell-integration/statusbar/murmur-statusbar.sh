@@ -0,0 +1,16 @@
+# Murmur status bar component
+# Appended by `murmur setup <shell> --statusbar`. Starts one background
+# `murmur statusbar` per interactive shell that keeps the terminal's status
+# area updated with the active provider, remaining budget and voice activity.
+#
+# iTerm2: add an "Interpolated String" status bar component with the value
+#   \(user.murmur_status)
+# Ghostty and others: the status is shown in the window title.
+# Override the output with MURMUR_STATUSBAR_FORMAT=iterm2|title|plain.
+
+if [ -z "$MURMUR_STATUSBAR_STARTED" ] && [ -t 1 ] && command -v murmur >/dev/null 2>&1; then
+    export MURMUR_STATUSBAR_STARTED=1
+    # Subshell detaches the job from the shell's job table; the process exits
+    # on its own once the terminal goes away.
+    ( murmur statusbar --format "${MURMUR_STATUSBAR_FORMAT:-auto}" >/dev/tty 2>/dev/null & )
+fi
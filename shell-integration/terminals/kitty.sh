@@ -0,0 +1,23 @@
+# Murmur terminal layer: Kitty
+# Appended by `murmur setup` when running inside Kitty. Shows suggestions with
+# descriptions in an overlay window via Kitty's remote control protocol
+# (requires `allow_remote_control yes` in kitty.conf).
+
+# Render tab-separated "text<TAB>description" lines. Returns non-zero when
+# remote control is unavailable so the shell falls back to its plain list.
+_murmur_render() {
+    [ -n "$KITTY_WINDOW_ID" ] || return 1
+    command -v kitty >/dev/null 2>&1 || return 1
+
+    local file
+    file=$(mktemp "${TMPDIR:-/tmp}/murmur-suggest.XXXXXX") || return 1
+    printf '%s\n' "$1" | awk -F '\t' '{
+        printf "  \033[1m%d)\033[0m %s", NR, $1
+        if ($2 != "") printf "\n     \033[2m%s\033[0m", $2
+        printf "\n"
+    } END { printf "\n\033[2mpress any key to close\033[0m" }' >"$file"
+
+    kitty @ launch --type=overlay --title "murmur suggestions" --no-response \
+        sh -c 'cat "$1"; rm -f "$1"; stty -echo -icanon; dd bs=1 count=1 >/dev/null 2>&1' \
+        sh "$file" >/dev/null 2>&1 || { rm -f "$file"; return 1; }
+}
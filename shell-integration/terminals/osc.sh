@@ -0,0 +1,16 @@
+# Murmur terminal layer: generic OSC/ANSI terminals
+# Appended by `murmur setup` for terminals without a richer API (Warp, iTerm2,
+# Ghostty, Terminal.app, ...). Prints a styled list above the prompt and sets
+# the window title to the top suggestion.
+
+_murmur_render() {
+    [ -w /dev/tty ] || return 1
+    # zsh widgets must invalidate the display before writing to the terminal
+    [ -n "$ZSH_VERSION" ] && zle -I
+    printf '%s\n' "$1" | awk -F '\t' '{
+        printf "  \033[1m%d)\033[0m %s", NR, $1
+        if ($2 != "") printf "  \033[2m%s\033[0m", $2
+        printf "\n"
+        if (NR == 1) top = $1
+    } END { printf "\033]2;murmur: %s\007", top }' >/dev/tty
+}
@@ -0,0 +1,53 @@
+-- Murmur suggestion picker for WezTerm
+-- Print with `murmur setup wezterm` and add to ~/.wezterm.lua:
+--
+--   local murmur = require 'murmur'   -- or paste this file inline
+--   murmur.apply(config)
+--
+-- Shells emit the `murmur_suggestions` user variable (see `murmur setup zsh`);
+-- this shows the suggestions in an InputSelector and types the chosen one.
+
+local wezterm = require 'wezterm'
+local act = wezterm.action
+
+local M = {}
+
+function M.apply(_config)
+  wezterm.on('user-var-changed', function(window, pane, name, value)
+    if name ~= 'murmur_suggestions' or value == '' then
+      return
+    end
+
+    local choices = {}
+    for line in value:gmatch('[^\n]+') do
+      local text, desc = line:match('^([^\t]*)\t?(.*)$')
+      if text and text ~= '' then
+        local label = text
+        if desc and desc ~= '' then
+          label = text .. '  — ' .. desc
+        end
+        table.insert(choices, { id = text, label = label })
+      end
+    end
+    if #choices == 0 then
+      return
+    end
+
+    window:perform_action(
+      act.InputSelector {
+        title = 'murmur',
+        choices = choices,
+        fuzzy = true,
+        action = wezterm.action_callback(function(_, inner_pane, id)
+          if id then
+            -- Ctrl-E, Ctrl-U clears the line in both zsh and bash emacs mode
+            inner_pane:send_text('\x05\x15' .. id)
+          end
+        end),
+      },
+      pane
+    )
+  end)
+end
+
+return M
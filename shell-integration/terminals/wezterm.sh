@@ -0,0 +1,16 @@
+# Murmur terminal layer: WezTerm
+# Appended by `murmur setup` when running inside WezTerm. Publishes suggestions
+# as a user variable; the Lua handler from `murmur setup wezterm` turns them
+# into a searchable picker that replaces the command line with the selection.
+
+_murmur_render() {
+    command -v base64 >/dev/null 2>&1 || return 1
+    local encoded
+    encoded=$(printf '%s' "$1" | base64 | tr -d '\n')
+    # OSC 1337 SetUserVar; wrap for tmux passthrough when needed
+    if [ -n "$TMUX" ]; then
+        printf '\033Ptmux;\033\033]1337;SetUserVar=%s=%s\007\033\\' murmur_suggestions "$encoded" >/dev/tty
+    else
+        printf '\033]1337;SetUserVar=%s=%s\007' murmur_suggestions "$encoded" >/dev/tty
+    fi
+}